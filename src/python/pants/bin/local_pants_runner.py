@@ -17,7 +17,7 @@ from pants.build_graph.build_configuration import BuildConfiguration
 from pants.engine.environment import CompleteEnvironment
 from pants.engine.internals import native_engine
 from pants.engine.internals.native_engine import PySessionCancellationLatch
-from pants.engine.internals.scheduler import ExecutionError
+from pants.engine.internals.scheduler import ExecutionError, SchedulerSession
 from pants.engine.internals.session import SessionValues
 from pants.engine.streaming_workunit_handler import (
     StreamingWorkunitHandler,
@@ -36,10 +36,22 @@ from pants.option.arg_splitter import HelpRequest
 from pants.option.options import Options
 from pants.option.options_bootstrapper import OptionsBootstrapper
 from pants.util.contextutil import maybe_profiled
+from pants.util.strutil import pluralize
 
 logger = logging.getLogger(__name__)
 
 
+def invalidate_declared_paths(scheduler_session: SchedulerSession, options: Options) -> None:
+    """Invalidates the paths declared via `--invalidate-paths`.
+
+    If the filesystem is not being watched (see `--watch-filesystem`), this is the only way that
+    changes to files are observed by a Scheduler which is reused between runs.
+    """
+    invalidate_paths = options.for_global_scope().invalidate_paths
+    if invalidate_paths:
+        scheduler_session.invalidate_files(invalidate_paths)
+
+
 @dataclass
 class LocalPantsRunner:
     """Handles a single pants invocation running in the process-local context.
@@ -90,6 +102,12 @@ class LocalPantsRunner:
                 }
             ),
             cancellation_latch=cancellation_latch,
+            notification_threshold=(
+                global_options.notifications_threshold if global_options.notifications else None
+            ),
+            notification_command=tuple(global_options.notifications_command),
+            dry_run=global_options.process_execution_dry_run,
+            priority=global_options.session_priority,
         )
 
     @classmethod
@@ -137,6 +155,8 @@ class LocalPantsRunner:
             for scope in options.scope_to_flags.keys():
                 options.for_scope(scope)
 
+        invalidate_declared_paths(graph_session.scheduler_session, options)
+
         # Verify configs.
         global_bootstrap_options = options_bootstrapper.bootstrap_options.for_global_scope()
         if global_bootstrap_options.verify_config:
@@ -190,6 +210,20 @@ class LocalPantsRunner:
             poll_delay=(0.1 if poll else None),
         )
 
+    def _print_dry_run_report(self) -> None:
+        planned_processes = self.graph_session.scheduler_session.planned_processes()
+        lines = [
+            f"Dry run: {pluralize(len(planned_processes), 'process')} would have been executed "
+            f"(with {sum(p.input_bytes for p in planned_processes)} bytes of inputs in total)."
+        ]
+        for planned_process in planned_processes:
+            lines.append(
+                f"  {planned_process.description}: action "
+                f"{planned_process.action_digest.fingerprint}, "
+                f"{planned_process.input_bytes} bytes of inputs"
+            )
+        print("\n".join(lines), file=sys.stderr)
+
     def _finish_run(self, code: ExitCode) -> None:
         """Cleans up the run tracker."""
 
@@ -249,12 +283,20 @@ class LocalPantsRunner:
                 callbacks=self._get_workunits_callbacks(),
                 report_interval_seconds=global_options.streaming_workunits_report_interval,
                 pantsd=global_options.pantsd,
+                max_queued_workunits=global_options.streaming_workunits_max_queued,
+                overflow_policy=global_options.streaming_workunits_overflow_policy,
             )
             with streaming_reporter:
                 engine_result = PANTS_FAILED_EXIT_CODE
                 try:
                     engine_result = self._run_inner()
+                    if global_options.process_execution_dry_run:
+                        self._print_dry_run_report()
                 finally:
+                    if global_options.record_run_graph:
+                        self.graph_session.scheduler_session.write_run_graph(
+                            str(self.run_tracker.run_logs_file.with_name("run_graph.json"))
+                        )
                     metrics = self.graph_session.scheduler_session.metrics()
                     self.run_tracker.set_pantsd_scheduler_metrics(metrics)
                     self.run_tracker.end_run(engine_result)
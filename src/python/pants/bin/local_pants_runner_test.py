@@ -0,0 +1,46 @@
+# Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+# Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+from __future__ import annotations
+
+from pathlib import Path
+
+from pants.bin.local_pants_runner import invalidate_declared_paths
+from pants.engine.fs import DigestContents, PathGlobs
+from pants.option.options import Options
+from pants.testutil.rule_runner import QueryRule, RuleRunner
+
+
+def test_invalidate_declared_paths_without_watcher() -> None:
+    rule_runner = RuleRunner(
+        rules=[QueryRule(DigestContents, [PathGlobs])],
+        bootstrap_args=["--no-watch-filesystem"],
+    )
+    path = Path(rule_runner.build_root, "a.txt")
+
+    def read_file() -> str:
+        digest_contents = rule_runner.request(DigestContents, [PathGlobs(["a.txt"])])
+        assert len(digest_contents) == 1
+        return digest_contents[0].content.decode()
+
+    def options() -> Options:
+        return rule_runner.options_bootstrapper.full_options(rule_runner.build_config)
+
+    # NB: Files are written directly (rather than via the RuleRunner), which would invalidate them.
+    path.write_text("one")
+    assert read_file() == "one"
+
+    # Without a watcher, a change is not observed, even in a new session. NB: Nothing runs in the
+    # background to observe it, so there is no need to wait before checking...
+    path.write_text("two")
+    rule_runner.new_session("second")
+    assert read_file() == "one"
+
+    # ...until the path is invalidated, which `--invalidate-paths` does for each run.
+    rule_runner.set_options(["--invalidate-paths=['b.txt']"])
+    invalidate_declared_paths(rule_runner.scheduler, options())
+    assert read_file() == "one"
+
+    rule_runner.set_options(["--invalidate-paths=['a.txt']"])
+    invalidate_declared_paths(rule_runner.scheduler, options())
+    assert read_file() == "two"
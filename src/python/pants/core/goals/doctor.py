@@ -0,0 +1,72 @@
+# Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+# Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+from __future__ import annotations
+
+import json
+from typing import cast
+
+from pants.engine.console import Console
+from pants.engine.doctor import DoctorCheckStatus, EngineDoctor
+from pants.engine.goal import Goal, GoalSubsystem
+from pants.engine.rules import collect_rules, goal_rule
+
+
+class DoctorSubsystem(GoalSubsystem):
+    name = "doctor"
+    help = (
+        "Check the environment that Pants is running in for common problems: the writability and "
+        "free space of the local store, the filesystem watcher, resource limits, remote endpoints "
+        "and clock skew."
+    )
+
+    @classmethod
+    def register_options(cls, register):
+        super().register_options(register)
+        register(
+            "--json",
+            type=bool,
+            default=False,
+            help=(
+                "Output the report as JSON, for example to attach it to a bug report, rather than "
+                "as a human readable summary."
+            ),
+        )
+
+    @property
+    def json(self) -> bool:
+        return cast(bool, self.options.json)
+
+
+class Doctor(Goal):
+    subsystem_cls = DoctorSubsystem
+
+
+@goal_rule
+def doctor(
+    doctor_subsystem: DoctorSubsystem, console: Console, engine_doctor: EngineDoctor
+) -> Doctor:
+    checks = engine_doctor.run_checks()
+    if doctor_subsystem.json:
+        report = [
+            {"name": check.name, "status": check.status.value, "detail": check.detail}
+            for check in checks
+        ]
+        console.print_stdout(json.dumps(report, indent=2))
+    else:
+        for check in checks:
+            if check.status == DoctorCheckStatus.OK:
+                sigil = console.green("✓")
+            elif check.status == DoctorCheckStatus.WARNING:
+                sigil = console.yellow("!")
+            elif check.status == DoctorCheckStatus.FAILED:
+                sigil = console.red("𐄂")
+            else:
+                sigil = console.yellow("-")
+            console.print_stdout(f"{sigil} {check.name}: {check.detail}")
+    failed = any(check.status == DoctorCheckStatus.FAILED for check in checks)
+    return Doctor(exit_code=1 if failed else 0)
+
+
+def rules():
+    return collect_rules()
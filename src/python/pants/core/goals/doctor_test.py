@@ -0,0 +1,34 @@
+# Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+# Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+import json
+
+import pytest
+
+from pants.core.goals import doctor
+from pants.core.goals.doctor import Doctor
+from pants.testutil.rule_runner import RuleRunner
+
+
+@pytest.fixture
+def rule_runner() -> RuleRunner:
+    return RuleRunner(rules=doctor.rules())
+
+
+def test_json_report(rule_runner: RuleRunner) -> None:
+    result = rule_runner.run_goal_rule(Doctor, args=["--doctor-json"])
+    assert result.exit_code == 0
+    statuses = {check["name"]: check["status"] for check in json.loads(result.stdout)}
+    assert statuses["store_writable"] == "ok"
+    assert statuses["store_free_space"] in ("ok", "warning")
+    assert statuses["open_files_limit"] in ("ok", "warning")
+    # No remote is configured, so there is nothing to probe or to compare the clock to.
+    assert statuses["remote"] == "skipped"
+    assert statuses["clock_skew"] == "skipped"
+
+
+def test_summary(rule_runner: RuleRunner) -> None:
+    result = rule_runner.run_goal_rule(Doctor)
+    assert result.exit_code == 0
+    assert "store_writable: " in result.stdout
+    assert "remote: No remote endpoints are in use." in result.stdout
@@ -22,7 +22,16 @@ from pants.engine.console import Console
 from pants.engine.desktop import OpenFiles, OpenFilesRequest
 from pants.engine.engine_aware import EngineAwareReturnType
 from pants.engine.environment import CompleteEnvironment
-from pants.engine.fs import EMPTY_FILE_DIGEST, Digest, FileDigest, MergeDigests, Snapshot, Workspace
+from pants.engine.fs import (
+    EMPTY_FILE_DIGEST,
+    Digest,
+    FileDigest,
+    MergeDigests,
+    MergeTestReports,
+    Snapshot,
+    TestReportFormat,
+    Workspace,
+)
 from pants.engine.goal import Goal, GoalSubsystem
 from pants.engine.process import FallibleProcessResult, InteractiveProcess, InteractiveRunner
 from pants.engine.rules import Get, MultiGet, _uncacheable_rule, collect_rules, goal_rule, rule
@@ -331,6 +340,26 @@ class TestSubsystem(GoalSubsystem):
                 "`ENV_VAR` to copy the value of a variable in Pants's own environment."
             ),
         )
+        register(
+            "--junit-xml-report",
+            type=str,
+            default=None,
+            advanced=True,
+            help=(
+                "If set, merge the JUnit XML results of all test runners which produce them into "
+                "a single JUnit XML report at this path, relative to the build root."
+            ),
+        )
+        register(
+            "--json-report",
+            type=str,
+            default=None,
+            advanced=True,
+            help=(
+                "If set, merge the JUnit XML results of all test runners which produce them into "
+                "a single JSON report at this path, relative to the build root."
+            ),
+        )
 
     @property
     def extra_env_vars(self) -> List[str]:
@@ -356,6 +385,21 @@ class TestSubsystem(GoalSubsystem):
     def open_coverage(self) -> bool:
         return cast(bool, self.options.open_coverage)
 
+    @property
+    def junit_xml_report(self) -> Optional[str]:
+        return cast(Optional[str], self.options.junit_xml_report)
+
+    @property
+    def json_report(self) -> Optional[str]:
+        return cast(Optional[str], self.options.json_report)
+
+    def test_reports(self) -> List[Tuple[TestReportFormat, str]]:
+        reports = [
+            (TestReportFormat.JUNIT_XML, self.junit_xml_report),
+            (TestReportFormat.JSON, self.json_report),
+        ]
+        return [(report_format, path) for report_format, path in reports if path]
+
 
 class Test(Goal):
     subsystem_cls = TestSubsystem
@@ -437,6 +481,21 @@ async def run_tests(
     )
     workspace.write_digest(merged_xml_results)
 
+    test_reports = test_subsystem.test_reports()
+    if test_reports:
+        # NB: The reports are merged per-result (rather than from `merged_xml_results`), because
+        # test runners commonly write their results at identical paths.
+        xml_results_digests = [
+            result.xml_results.digest for result in results if result.xml_results
+        ]
+        report_digests = await MultiGet(
+            Get(Digest, MergeTestReports(xml_results_digests, report_format, path))
+            for report_format, path in test_reports
+        )
+        for report_digest, (_, path) in zip(report_digests, test_reports):
+            workspace.write_digest(report_digest)
+            console.print_stderr(f"\nWrote test report to {path}")
+
     if test_subsystem.use_coverage:
         # NB: We must pre-sort the data for itertools.groupby() to work properly, using the same
         # key function for both. However, you can't sort by `types`, so we call `str()` on it.
@@ -0,0 +1,48 @@
+# Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+# Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+from __future__ import annotations
+
+from dataclasses import dataclass
+from enum import Enum
+from typing import TYPE_CHECKING
+
+from pants.engine.rules import side_effecting
+
+if TYPE_CHECKING:
+    from pants.engine.internals.scheduler import SchedulerSession
+
+
+class DoctorCheckStatus(Enum):
+    OK = "ok"
+    # The check passed, but found something which is likely to cause problems.
+    WARNING = "warning"
+    FAILED = "failed"
+    # The check does not apply to this configuration (for example: no remote is in use).
+    SKIPPED = "skipped"
+
+
+@dataclass(frozen=True)
+class DoctorCheck:
+    """The outcome of one of the engine's checks of the environment that it is running in."""
+
+    name: str
+    status: DoctorCheckStatus
+    detail: str
+
+
+@side_effecting
+@dataclass(frozen=True)
+class EngineDoctor:
+    """A handle for running the engine's checks of its environment.
+
+    The checks cover the writability and free space of the local store, the filesystem watcher,
+    resource limits, the reachability of (and authentication to) the remote endpoints in use, and
+    clock skew relative to the remote. They run every time that they are requested, since the
+    environment may change between runs.
+    """
+
+    _scheduler: "SchedulerSession"
+
+    def run_checks(self) -> tuple[DoctorCheck, ...]:
+        return self._scheduler.engine_doctor()
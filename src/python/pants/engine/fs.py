@@ -1,6 +1,7 @@
 # Copyright 2020 Pants project contributors (see CONTRIBUTORS.md).
 # Licensed under the Apache License, Version 2.0 (see LICENSE).
 
+import mmap
 from dataclasses import dataclass
 from enum import Enum
 from typing import TYPE_CHECKING, Iterable, Optional, Tuple, Union
@@ -195,6 +196,144 @@ class DigestSubset:
     globs: PathGlobs
 
 
+class DepfileFormat(Enum):
+    # A Makefile-style depfile, as emitted by e.g. `gcc -MD`.
+    MAKE = "make"
+    # A binary `.ninja_deps` log.
+    NINJA_DEPS = "ninja_deps"
+
+
+@dataclass(frozen=True)
+class ParseDepfiles:
+    """A request to parse all of the depfiles in a digest into the unique paths that they list as
+    dependencies.
+
+    Example:
+
+        paths = await Get(Paths, ParseDepfiles(depfiles_digest, DepfileFormat.MAKE))
+    """
+
+    digest: Digest
+    format: DepfileFormat = DepfileFormat.MAKE
+
+
+class CoverageDataFormat(Enum):
+    # An LCOV tracefile, as emitted by e.g. `geninfo`, `c8` or `grcov`.
+    LCOV = "lcov"
+    # A coverage.py SQLite data file, as emitted by `coverage run --parallel-mode`.
+    COVERAGE_PY = "coverage_py"
+
+
+@dataclass(unsafe_hash=True)
+class MergeCoverageData:
+    """A request to merge all of the coverage data files in several digests into a single data file
+    at `output_path`.
+
+    Hit counts for the same source file are combined across all of the data files, which is
+    equivalent to (but much faster than) using e.g. `coverage combine` or `lcov --add-tracefile`.
+
+    Example:
+
+        merged_digest = await Get(
+            Digest, MergeCoverageData(shard_digests, CoverageDataFormat.LCOV, "coverage.lcov")
+        )
+    """
+
+    digests: Tuple[Digest, ...]
+    format: CoverageDataFormat
+    output_path: str
+
+    def __init__(
+        self, digests: Iterable[Digest], format: CoverageDataFormat, output_path: str
+    ) -> None:
+        self.digests = tuple(digests)
+        self.format = format
+        self.output_path = output_path
+
+
+class TestReportFormat(Enum):
+    # A single JUnit XML report, with one `<testsuites>` root element.
+    JUNIT_XML = "junit_xml"
+    # A JSON report with the totals and test case outcomes of each suite.
+    JSON = "json"
+
+
+@dataclass(unsafe_hash=True)
+class MergeTestReports:
+    """A request to merge all of the JUnit XML reports in several digests into a single report at
+    `output_path`.
+
+    The totals of the merged report are recomputed from the test cases of each suite, so that
+    individual test runners do not need to implement merging.
+
+    Example:
+
+        report_digest = await Get(
+            Digest, MergeTestReports(xml_digests, TestReportFormat.JSON, "report.json")
+        )
+    """
+
+    # Prevent this class from being detected by pytest as a test class.
+    __test__ = False
+
+    digests: Tuple[Digest, ...]
+    format: TestReportFormat
+    output_path: str
+
+    def __init__(
+        self, digests: Iterable[Digest], format: TestReportFormat, output_path: str
+    ) -> None:
+        self.digests = tuple(digests)
+        self.format = format
+        self.output_path = output_path
+
+
+@dataclass(frozen=True)
+class TextReplacement:
+    """A replacement of all of the occurrences of `pattern` in the content of a file.
+
+    By default, `pattern` is a regex (using the syntax of the Rust `regex` crate), and
+    `replacement` may refer to its capture groups as `$1` or `${name}`. If `literal` is True, both
+    are used verbatim, which is convenient for rewriting e.g. a path prefix.
+    """
+
+    pattern: str
+    replacement: str
+    literal: bool = False
+
+
+@dataclass(unsafe_hash=True)
+class RewriteDigest:
+    """A request to apply text replacements to the files in a digest which match `globs`, which is
+    equivalent to (but much cheaper than) running `sed` in a `Process`.
+
+    The replacements are applied to each matching file in order, and the other files in the digest
+    are unchanged.
+
+    Example:
+
+        rewritten_digest = await Get(
+            Digest,
+            RewriteDigest(
+                coverage_digest,
+                PathGlobs(["**/*.lcov"]),
+                [TextReplacement(f"{sandbox_root}/", "", literal=True)],
+            ),
+        )
+    """
+
+    digest: Digest
+    globs: PathGlobs
+    replacements: Tuple[TextReplacement, ...]
+
+    def __init__(
+        self, digest: Digest, globs: PathGlobs, replacements: Iterable[TextReplacement]
+    ) -> None:
+        self.digest = digest
+        self.globs = globs
+        self.replacements = tuple(replacements)
+
+
 @dataclass(unsafe_hash=True)
 class MergeDigests:
     digests: Tuple[Digest, ...]
@@ -248,10 +387,50 @@ class DownloadFile:
     To compute the `expected_digest`, manually download the file, then run `shasum -a 256` to
     compute the fingerprint and `wc -c` to compute the expected length of the downloaded file in
     bytes.
+
+    If no `expected_digest` is given, the file is trusted on first use: the digest of its first
+    download is recorded, and later downloads of the same URL must match it unless the URL is
+    re-pinned with `--download-repin`.
     """
 
     url: str
-    expected_digest: FileDigest
+    expected_digest: Optional[FileDigest] = None
+
+
+@dataclass(frozen=True)
+class BlobFile:
+    """A read-only temporary file containing the content of a `FileDigest`.
+
+    Request a `BlobFile` for a `FileDigest` to process a large file without copying its content
+    into Python (as requesting `DigestContents` would): the file is materialized from the store on
+    request, and is deleted when the current run ends. Do not retain the `path` beyond the @rule
+    which requested it.
+    """
+
+    path: str
+    digest: FileDigest
+
+    def memoryview(self) -> memoryview:
+        """Returns a read-only, memory-mapped view of the content of the file."""
+        if self.digest.serialized_bytes_length == 0:
+            # Empty files cannot be memory-mapped.
+            return memoryview(b"")
+        with open(self.path, "rb") as f:
+            return memoryview(mmap.mmap(f.fileno(), 0, access=mmap.ACCESS_READ))
+
+
+@dataclass(frozen=True)
+class DigestTreeSize:
+    """The transitive size of a `Digest`: the number and total size of the files below it, and the
+    number of directories (including its root).
+
+    Files and directories which occur at multiple paths are counted once per path, as they would be
+    when the digest is materialized. See `SchedulerSession.tree_size`.
+    """
+
+    file_count: int
+    file_bytes: int
+    directory_count: int
 
 
 @side_effecting
@@ -264,8 +443,15 @@ class Workspace:
     def write_digest(self, digest: Digest, *, path_prefix: Optional[str] = None) -> None:
         """Write a digest to disk, relative to the build root.
 
-        You should not use this in a `for` loop due to slow performance. Instead, call `await
-        Get(Digest, MergeDigests)` beforehand.
+        Files which already exist on disk with the same content and executability are not
+        re-written, so repeatedly writing the same digest is cheap.
+
+        The write is transactional: changed files and new directories are staged, and then moved
+        into place together, so that if the write fails or is interrupted while staging, none of
+        the digest is written.
+
+        You should not use this in a `for` loop due to slow performance (and because each call is
+        a separate transaction). Instead, call `await Get(Digest, MergeDigests)` beforehand.
         """
         self._scheduler.write_digest(digest, path_prefix=path_prefix)
 
@@ -295,9 +481,14 @@ def rules():
         QueryRule(Digest, (AddPrefix,)),
         QueryRule(Digest, (RemovePrefix,)),
         QueryRule(Digest, (DownloadFile,)),
+        QueryRule(BlobFile, (FileDigest,)),
         QueryRule(Digest, (MergeDigests,)),
         QueryRule(Digest, (DigestSubset,)),
         QueryRule(DigestContents, (Digest,)),
         QueryRule(Snapshot, (Digest,)),
         QueryRule(Paths, (PathGlobs,)),
+        QueryRule(Paths, (ParseDepfiles,)),
+        QueryRule(Digest, (MergeCoverageData,)),
+        QueryRule(Digest, (MergeTestReports,)),
+        QueryRule(Digest, (RewriteDigest,)),
     )
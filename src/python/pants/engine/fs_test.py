@@ -2,6 +2,7 @@
 # Licensed under the Apache License, Version 2.0 (see LICENSE).
 
 import hashlib
+import json
 import os
 import pkgutil
 import shutil
@@ -19,9 +20,13 @@ import pytest
 from pants.engine.console import Console
 from pants.engine.fs import (
     EMPTY_DIGEST,
+    EMPTY_FILE_DIGEST,
     EMPTY_SNAPSHOT,
     AddPrefix,
+    BlobFile,
+    CoverageDataFormat,
     CreateDigest,
+    DepfileFormat,
     Digest,
     DigestContents,
     DigestSubset,
@@ -30,11 +35,18 @@ from pants.engine.fs import (
     FileContent,
     FileDigest,
     GlobMatchErrorBehavior,
+    MergeCoverageData,
     MergeDigests,
+    MergeTestReports,
+    ParseDepfiles,
     PathGlobs,
     PathGlobsAndRoot,
+    Paths,
     RemovePrefix,
+    RewriteDigest,
     Snapshot,
+    TestReportFormat,
+    TextReplacement,
     Workspace,
 )
 from pants.engine.goal import Goal, GoalSubsystem
@@ -50,10 +62,15 @@ from pants.util.dirutil import relative_symlink, safe_file_dump
 def rule_runner() -> RuleRunner:
     return RuleRunner(
         rules=[
+            QueryRule(BlobFile, [FileDigest]),
             QueryRule(DigestContents, [PathGlobs]),
             QueryRule(Snapshot, [CreateDigest]),
             QueryRule(Snapshot, [DigestSubset]),
             QueryRule(Snapshot, [PathGlobs]),
+            QueryRule(Paths, [ParseDepfiles]),
+            QueryRule(Digest, [MergeCoverageData]),
+            QueryRule(Digest, [MergeTestReports]),
+            QueryRule(Digest, [RewriteDigest]),
         ],
         isolated_local_store=True,
     )
@@ -453,6 +470,20 @@ def test_create_empty_directory(rule_runner: RuleRunner) -> None:
     assert res.digest != EMPTY_DIGEST
 
 
+def test_snapshot_of_file_contents(rule_runner: RuleRunner) -> None:
+    file_contents = [
+        FileContent(f"generated/{i % 10}/file_{i}.txt", f"content {i}".encode())
+        for i in range(1000)
+    ]
+    file_contents.append(FileContent("bin/run.sh", b"#!/bin/sh", is_executable=True))
+    directories = [Directory("empty")]
+
+    snapshot = rule_runner.scheduler.snapshot_of_file_contents(file_contents, directories)
+    assert len(snapshot.files) == 1001
+    assert "empty" in snapshot.dirs
+    assert snapshot == rule_runner.request(Snapshot, [CreateDigest([*file_contents, *directories])])
+
+
 # -----------------------------------------------------------------------------------------------
 # `MergeDigests`
 # -----------------------------------------------------------------------------------------------
@@ -598,6 +629,178 @@ def test_digest_subset_nonexistent_filename_globs(rule_runner: RuleRunner) -> No
     #     )
 
 
+# -----------------------------------------------------------------------------------------------
+# `ParseDepfiles` -> `Paths`
+# -----------------------------------------------------------------------------------------------
+
+
+def test_parse_depfiles(rule_runner: RuleRunner) -> None:
+    depfiles_digest = rule_runner.request(
+        Digest,
+        [
+            CreateDigest(
+                [
+                    FileContent("a.d", b"a.o: a.c \\\n  include/common.h\n"),
+                    FileContent("b.d", b"b.o: b.c include/common.h\ninclude/common.h:\n"),
+                ]
+            )
+        ],
+    )
+    paths = rule_runner.request(Paths, [ParseDepfiles(depfiles_digest, DepfileFormat.MAKE)])
+    assert paths.files == ("a.c", "include/common.h", "b.c")
+    assert paths.dirs == ()
+
+
+def test_parse_depfiles_malformed(rule_runner: RuleRunner) -> None:
+    depfiles_digest = rule_runner.request(
+        Digest, [CreateDigest([FileContent("a.d", b"a.o a.c\n")])]
+    )
+    with pytest.raises(ExecutionError) as exc:
+        rule_runner.request(Paths, [ParseDepfiles(depfiles_digest)])
+    assert "Failed to parse a.d" in str(exc.value)
+
+
+# -----------------------------------------------------------------------------------------------
+# `MergeCoverageData` -> `Digest`
+# -----------------------------------------------------------------------------------------------
+
+
+def test_merge_coverage_data_lcov(rule_runner: RuleRunner) -> None:
+    shard_digests = [
+        rule_runner.request(Digest, [CreateDigest([FileContent("coverage.lcov", content)])])
+        for content in (
+            b"SF:src/a.py\nDA:1,1\nDA:2,0\nend_of_record\n",
+            b"SF:src/a.py\nDA:2,3\nend_of_record\nSF:src/b.py\nDA:1,0\nend_of_record\n",
+        )
+    ]
+    merged_digest = rule_runner.request(
+        Digest,
+        [MergeCoverageData(shard_digests, CoverageDataFormat.LCOV, "merged/coverage.lcov")],
+    )
+    [merged] = rule_runner.request(DigestContents, [merged_digest])
+    assert merged.path == "merged/coverage.lcov"
+    assert merged.content == (
+        b"SF:src/a.py\nDA:1,1\nDA:2,3\nLF:2\nLH:2\nend_of_record\n"
+        b"SF:src/b.py\nDA:1,0\nLF:1\nLH:0\nend_of_record\n"
+    )
+
+
+def test_merge_coverage_data_malformed(rule_runner: RuleRunner) -> None:
+    shard_digest = rule_runner.request(
+        Digest, [CreateDigest([FileContent("coverage.lcov", b"DA:1,1\n")])]
+    )
+    with pytest.raises(ExecutionError) as exc:
+        rule_runner.request(
+            Digest,
+            [MergeCoverageData([shard_digest], CoverageDataFormat.LCOV, "coverage.lcov")],
+        )
+    assert "is not within a source file record" in str(exc.value)
+
+
+# -----------------------------------------------------------------------------------------------
+# `MergeTestReports` -> `Digest`
+# -----------------------------------------------------------------------------------------------
+
+
+def test_merge_test_reports(rule_runner: RuleRunner) -> None:
+    result_digests = [
+        rule_runner.request(Digest, [CreateDigest([FileContent("results.xml", content)])])
+        for content in (
+            b'<testsuite name="a"><testcase name="t1" time="1.0"/></testsuite>',
+            b'<testsuite name="b"><testcase name="t2" time="0.5"><failure/></testcase></testsuite>',
+        )
+    ]
+    xml_digest = rule_runner.request(
+        Digest, [MergeTestReports(result_digests, TestReportFormat.JUNIT_XML, "report.xml")]
+    )
+    [xml_report] = rule_runner.request(DigestContents, [xml_digest])
+    assert xml_report.path == "report.xml"
+    assert xml_report.content == (
+        b'<?xml version="1.0" encoding="UTF-8"?>\n'
+        b'<testsuites tests="2" failures="1" errors="0" skipped="0" time="1.500">\n'
+        b'<testsuite name="a"><testcase name="t1" time="1.0"/></testsuite>\n'
+        b'<testsuite name="b"><testcase name="t2" time="0.5"><failure/></testcase></testsuite>\n'
+        b"</testsuites>\n"
+    )
+
+    json_digest = rule_runner.request(
+        Digest, [MergeTestReports(result_digests, TestReportFormat.JSON, "report.json")]
+    )
+    [json_report] = rule_runner.request(DigestContents, [json_digest])
+    assert json_report.path == "report.json"
+    report = json.loads(json_report.content)
+    assert (report["tests"], report["failures"]) == (2, 1)
+    assert [suite["name"] for suite in report["testsuites"]] == ["a", "b"]
+
+
+# -----------------------------------------------------------------------------------------------
+# `RewriteDigest` -> `Digest`
+# -----------------------------------------------------------------------------------------------
+
+
+def test_rewrite_digest(rule_runner: RuleRunner) -> None:
+    original_digest = rule_runner.request(
+        Digest,
+        [
+            CreateDigest(
+                [
+                    FileContent("cov/a.lcov", b"SF:/tmp/sandbox/src/a.py\nSF:/tmp/sandbox/b.py\n"),
+                    FileContent("cov/b.lcov", b"SF:src/c.py\n"),
+                    FileContent("bin/run", b"#!/tmp/sandbox/python\n", is_executable=True),
+                    Directory("empty"),
+                ]
+            )
+        ],
+    )
+    rewritten_digest = rule_runner.request(
+        Digest,
+        [
+            RewriteDigest(
+                original_digest,
+                PathGlobs(["cov/*.lcov"]),
+                [
+                    TextReplacement("/tmp/sandbox/", "", literal=True),
+                    TextReplacement(r"(?m)^SF:src/(\w+)\.py$", "SF:${1}.py"),
+                ],
+            )
+        ],
+    )
+    expected_digest = rule_runner.request(
+        Digest,
+        [
+            CreateDigest(
+                [
+                    FileContent("cov/a.lcov", b"SF:a.py\nSF:b.py\n"),
+                    FileContent("cov/b.lcov", b"SF:c.py\n"),
+                    FileContent("bin/run", b"#!/tmp/sandbox/python\n", is_executable=True),
+                    Directory("empty"),
+                ]
+            )
+        ],
+    )
+    assert rewritten_digest == expected_digest
+
+    # When nothing matches, the digest is unchanged.
+    unchanged_digest = rule_runner.request(
+        Digest,
+        [
+            RewriteDigest(
+                original_digest,
+                PathGlobs(["cov/*.lcov"]),
+                [TextReplacement("/nonexistent/", "", literal=True)],
+            )
+        ],
+    )
+    assert unchanged_digest == original_digest
+
+    with pytest.raises(ExecutionError) as exc:
+        rule_runner.request(
+            Digest,
+            [RewriteDigest(original_digest, PathGlobs(["**"]), [TextReplacement("(", "")])],
+        )
+    assert "Invalid pattern `(`" in str(exc.value)
+
+
 # -----------------------------------------------------------------------------------------------
 # `Digest` -> `Snapshot`
 # -----------------------------------------------------------------------------------------------
@@ -731,6 +934,29 @@ def test_remove_prefix(rule_runner: RuleRunner) -> None:
         ) in str(exc.value)
 
 
+# -----------------------------------------------------------------------------------------------
+# `BlobFile`
+# -----------------------------------------------------------------------------------------------
+
+
+def test_blob_file(rule_runner: RuleRunner) -> None:
+    prime_store_with_roland_digest(rule_runner)
+    file_digest = FileDigest("693d8db7b05e99c6b7a7c0616456039d89c555029026936248085193559a0b5d", 16)
+    blob_file = rule_runner.request(BlobFile, [file_digest])
+    assert blob_file.digest == file_digest
+    assert Path(blob_file.path).read_text() == "European Burmese"
+    assert bytes(blob_file.memoryview()) == b"European Burmese"
+
+    empty_blob_file = rule_runner.request(BlobFile, [EMPTY_FILE_DIGEST])
+    assert bytes(empty_blob_file.memoryview()) == b""
+
+
+def test_blob_file_missing(rule_runner: RuleRunner) -> None:
+    with pytest.raises(ExecutionError) as exc:
+        rule_runner.request(BlobFile, [FileDigest("0" * 64, 16)])
+    assert "was not present in the store" in str(exc.value)
+
+
 # -----------------------------------------------------------------------------------------------
 # `DownloadFile`
 # -----------------------------------------------------------------------------------------------
@@ -813,6 +1039,39 @@ def test_download_caches(downloads_rule_runner: RuleRunner) -> None:
     )
 
 
+def test_download_trust_on_first_use() -> None:
+    with temporary_dir() as temp_dir:
+        store_dir = Path(temp_dir, "lmdb_store")
+        path = Path(temp_dir, "file.txt")
+        url = f"file://{path}"
+
+        def download(*bootstrap_args: str) -> Snapshot:
+            # Discard any previously downloaded content, but not the recorded checksums.
+            for child in store_dir.glob("*"):
+                if child.is_dir():
+                    shutil.rmtree(child)
+            rule_runner = RuleRunner(
+                rules=[QueryRule(Snapshot, [DownloadFile])],
+                bootstrap_args=[f"--local-store-dir={store_dir}", *bootstrap_args],
+            )
+            return rule_runner.request(Snapshot, [DownloadFile(url)])
+
+        path.write_bytes(StubHandler.response_text)
+        assert download().digest == DOWNLOADS_EXPECTED_DIRECTORY_DIGEST
+
+        path.write_bytes(b"Hello, server!")
+        with pytest.raises(ExecutionError) as exc:
+            download()
+        assert "wrong digest" in str(exc.value).lower()
+        assert f"--download-repin={url}" in str(exc.value)
+
+        snapshot = download(f"--download-repin={url}")
+        assert snapshot.files == ("file.txt",)
+        assert snapshot.digest != DOWNLOADS_EXPECTED_DIRECTORY_DIGEST
+        # The new digest is now the pinned one.
+        assert download().digest == snapshot.digest
+
+
 def test_download_https() -> None:
     # This also tests that the custom certs functionality works.
     with temporary_dir() as temp_dir:
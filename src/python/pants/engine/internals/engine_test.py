@@ -71,6 +71,26 @@ def nested_raise(x: B) -> A:  # type: ignore[return]
     fn_raises(x)
 
 
+class Slow:
+    pass
+
+
+class Impatient:
+    pass
+
+
+@rule
+def slow(x: C) -> Slow:
+    time.sleep(2)
+    return Slow()
+
+
+@rule(timeout=0.1)
+async def impatient(x: D) -> Impatient:
+    await Get(Slow, C())
+    return Impatient()
+
+
 @dataclass(frozen=True)
 class Fib:
     val: int
@@ -241,6 +261,17 @@ class EngineTest(unittest.TestCase, SchedulerTestBase):
             remove_locations_from_traceback(str(cm.exception)),
         )
 
+    def test_rule_timeout(self) -> None:
+        rules = [slow, impatient, QueryRule(Impatient, (D,))]
+        scheduler = self.scheduler(rules, include_trace_on_error=False)
+        start = time.time()
+        with self.assertRaises(ExecutionError) as cm:
+            scheduler.product_request(Impatient, subjects=[D()])
+        assert (
+            "Rule `pants.engine.internals.engine_test.impatient` timed out after 0.1 seconds."
+        ) in str(cm.exception)
+        assert time.time() - start < 2
+
     def test_nonexistent_root(self) -> None:
         rules = [QueryRule(A, [B])]
         # No rules are available to compute A.
@@ -630,6 +661,7 @@ class StreamingWorkunitTests(unittest.TestCase, SchedulerTestBase):
         assert histograms_info["version"] == 0
         assert "histograms" in histograms_info
         assert "test_observation" in histograms_info["histograms"]
+        assert histograms_info["percentiles"]["test_observation"][50.0] == 128
         assert (
             histograms_info["histograms"]["test_observation"]
             == b"\x1c\x84\x93\x14\x00\x00\x00\x1fx\x9c\x93i\x99,\xcc\xc0\xc0\xc0\xcc\x00\x010\x9a\x11J3\xd9\x7f\x800\xfe32\x01\x00E\x0c\x03\x81"
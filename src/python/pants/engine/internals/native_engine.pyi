@@ -11,7 +11,7 @@ from typing_extensions import Protocol
 from pants.engine.fs import PathGlobs
 from pants.engine.internals.scheduler import Workunit, _PathGlobsAndRootCollection
 from pants.engine.internals.session import SessionValues
-from pants.engine.process import InteractiveProcess, InteractiveProcessResult
+from pants.engine.process import InteractiveProcess, InteractiveProcessResult, Process
 
 # TODO: black and flake8 disagree about the content of this file:
 #   see https://github.com/psf/black/issues/1548
@@ -36,12 +36,22 @@ def capture_snapshots(
     session: PySession,
     path_globs_and_root_tuple_wrapper: _PathGlobsAndRootCollection,
 ) -> tuple[PySnapshot, ...]: ...
+def snapshot_of_file_contents(
+    scheduler: PyScheduler,
+    session: PySession,
+    file_contents: list[tuple[str, bytes, bool]],
+    empty_directories: list[str],
+) -> PySnapshot: ...
 def ensure_remote_has_recursive(scheduler: PyScheduler, digests: list[PyDigest]) -> None: ...
+def expand_digest(scheduler: PyScheduler, displayed: str) -> PyDigest: ...
+def tree_size(scheduler: PyScheduler, digest: PyDigest) -> dict[str, int]: ...
 
 # TODO: Should this be a proper FileDigest? Maybe create PyFileDigest.
 def single_file_digests_to_bytes(
     scheduler: PyScheduler, digests: list[PyDigest]
 ) -> list[bytes]: ...
+def print_action(scheduler: PyScheduler, process: Process) -> dict[str, Any]: ...
+def export_repro_bundle(scheduler: PyScheduler, process: Process) -> PyDigest: ...
 def run_local_interactive_process(
     scheduler: PyScheduler, session: PySession, request: InteractiveProcess
 ) -> InteractiveProcessResult: ...
@@ -52,14 +62,18 @@ def default_cache_path() -> str: ...
 def write_log(msg: str, level: int, target: str) -> None: ...
 def flush_log() -> None: ...
 def set_per_run_log_path(path: str | None) -> None: ...
+def set_display_full_digests(full: bool) -> None: ...
 def maybe_set_panic_handler() -> None: ...
 def stdio_initialize(
     level: int,
     show_rust_3rdparty_logs: bool,
     use_color: bool,
     show_target: bool,
+    log_by_workunit: bool,
     log_levels_by_target: dict[str, int],
     message_regex_filters: tuple[str, ...],
+    redact_env_vars: tuple[str, ...],
+    redact_patterns: tuple[str, ...],
     log_file: str,
 ) -> tuple[RawIOBase, TextIO, TextIO]: ...
 def stdio_thread_get_destination() -> PyStdioDestination: ...
@@ -78,6 +92,7 @@ def tasks_task_begin(
     name: str,
     desc: str,
     level: int,
+    timeout_seconds: float | None,
 ) -> None: ...
 def tasks_task_end(tasks: PyTasks) -> None: ...
 def tasks_add_get(tasks: PyTasks, output: type, input: type) -> None: ...
@@ -104,25 +119,54 @@ def scheduler_create(
     ca_certs_path: str | None,
     ignore_patterns: Sequence[str],
     use_gitignore: bool,
+    watch_filesystem: bool,
     remoting_options: PyRemotingOptions,
     local_store_options: PyLocalStoreOptions,
     exec_strategy_opts: PyExecutionStrategyOptions,
+    fake_processes: PyFakeProcesses | None,
 ) -> PyScheduler: ...
 def scheduler_execute(
     scheduler: PyScheduler, session: PySession, execution_request: PyExecutionRequest
 ) -> tuple: ...
+def scheduler_warm_up(
+    scheduler: PyScheduler, session: PySession, execution_request: PyExecutionRequest
+) -> None: ...
+def scheduler_warm_up_remote_endpoints(scheduler: PyScheduler, only_unhealthy: bool) -> None: ...
+def scheduler_remote_endpoint_health(
+    scheduler: PyScheduler,
+) -> tuple[tuple[str, str, str, int | None, str | None], ...]: ...
+def scheduler_shutdown(scheduler: PyScheduler, timeout_millis: int) -> tuple[str, ...]: ...
+def engine_doctor(scheduler: PyScheduler) -> tuple[tuple[str, str, str], ...]: ...
 def scheduler_metrics(scheduler: PyScheduler, session: PySession) -> dict[str, int]: ...
+def scheduler_live_node_counts(
+    scheduler: PyScheduler, session: PySession
+) -> list[tuple[str, str, int]]: ...
 def session_new_run_id(session: PySession) -> None: ...
+def session_get_invocation_id(session: PySession) -> str: ...
 def session_poll_workunits(
     scheduler: PyScheduler, session: PySession, max_log_verbosity_level: int
 ) -> tuple[tuple[Workunit, ...], tuple[Workunit, ...]]: ...
+def session_set_streaming_workunits_queue_bounds(
+    scheduler: PyScheduler, session: PySession, max_queued: int | None, overflow_policy: str
+) -> None: ...
+def session_record_streaming_workunit_callback_time(
+    scheduler: PyScheduler, session: PySession, micros: int
+) -> None: ...
 def session_get_observation_histograms(scheduler: PyScheduler, session: PySession) -> dict: ...
 def session_record_test_observation(
     scheduler: PyScheduler, session: PySession, value: int
 ) -> None: ...
+def session_get_planned_processes(
+    scheduler: PyScheduler, session: PySession
+) -> tuple[tuple[str, Digest, Digest, int], ...]: ...
 def session_isolated_shallow_clone(session: PySession) -> PySession: ...
+def session_write_run_graph(scheduler: PyScheduler, session: PySession, path: str) -> None: ...
+def explain_run_graph_divergence(before_path: str, after_path: str) -> str: ...
 def all_counter_names() -> list[str]: ...
 def graph_len(scheduler: PyScheduler) -> int: ...
+def graph_per_session_nodes(
+    scheduler: PyScheduler, session: PySession
+) -> list[tuple[str, str]]: ...
 def graph_visualize(scheduler: PyScheduler, session: PySession, path: str) -> None: ...
 def graph_invalidate(scheduler: PyScheduler, paths: Sequence[str]) -> int: ...
 def graph_invalidate_all_paths(scheduler: PyScheduler) -> int: ...
@@ -135,7 +179,9 @@ def rule_graph_visualize(scheduler: PyScheduler, path: str) -> None: ...
 def rule_subgraph_visualize(
     scheduler: PyScheduler, param_types: Sequence[type], product_type: type, path: str
 ) -> None: ...
-def garbage_collect_store(scheduler: PyScheduler, target_size_bytes: int) -> None: ...
+def garbage_collect_store(
+    scheduler: PyScheduler, session: PySession, target_size_bytes: int
+) -> None: ...
 def lease_files_in_graph(scheduler: PyScheduler, session: PySession) -> None: ...
 
 class PyDigest:
@@ -198,6 +244,10 @@ class PySession:
         build_id: str,
         session_values: SessionValues,
         cancellation_latch: PySessionCancellationLatch,
+        notification_threshold_millis: int | None,
+        notification_command: list[str],
+        dry_run: bool,
+        priority: str,
     ) -> None: ...
 
 class PySessionCancellationLatch:
@@ -218,6 +268,21 @@ class PyStubCAS:
     def builder(cls) -> PyStubCASBuilder: ...
     def address(self) -> str: ...
 
+class PyFakeProcesses:
+    def __init__(self) -> None: ...
+    def respond(
+        self,
+        argv_prefix: Sequence[str],
+        description_regex: str | None,
+        exit_code: int,
+        stdout: bytes,
+        stderr: bytes,
+        output_digest: PyDigest | None,
+        latency_millis: int,
+        error: str | None,
+    ) -> None: ...
+    def calls(self) -> list[tuple[list[str], str]]: ...
+
 class PyStdioDestination:
     pass
 
@@ -1,18 +1,40 @@
 # Copyright 2018 Pants project contributors (see CONTRIBUTORS.md).
 # Licensed under the Apache License, Version 2.0 (see LICENSE).
 
+from dataclasses import dataclass
+from typing import Tuple
+
 import pytest
 
-from pants.engine.rules import SubsystemRule
+from pants.engine.rules import SubsystemRule, rule
 from pants.option.scope import GLOBAL_SCOPE, Scope, ScopedOptions
 from pants.python.python_repos import PythonRepos
+from pants.python.python_setup import PythonSetup
 from pants.testutil.rule_runner import QueryRule, RuleRunner
 from pants.util.logging import LogLevel
 
 
+@dataclass(frozen=True)
+class PythonIndexes:
+    indexes: Tuple[str, ...]
+
+
+@rule
+def python_indexes(python_repos: PythonRepos) -> PythonIndexes:
+    return PythonIndexes(tuple(python_repos.indexes))
+
+
 @pytest.fixture
 def rule_runner() -> RuleRunner:
-    return RuleRunner(rules=[SubsystemRule(PythonRepos), QueryRule(ScopedOptions, (Scope,))])
+    return RuleRunner(
+        rules=[
+            python_indexes,
+            SubsystemRule(PythonRepos),
+            SubsystemRule(PythonSetup),
+            QueryRule(ScopedOptions, (Scope,)),
+            QueryRule(PythonIndexes, ()),
+        ]
+    )
 
 
 def test_options_parse_scoped(rule_runner: RuleRunner) -> None:
@@ -42,3 +64,18 @@ def test_options_parse_memoization(rule_runner: RuleRunner) -> None:
     two = parse()
     assert one == two
     assert one is one
+
+
+def test_options_change_invalidates_only_consumers_of_scope(rule_runner: RuleRunner) -> None:
+    def indexes(*args: str) -> PythonIndexes:
+        rule_runner.set_options(args)
+        return rule_runner.request(PythonIndexes, [])
+
+    # Changing the options of an unrelated scope does not re-run rules which consume a scope, and
+    # so the previous (identical) output is returned.
+    one = indexes()
+    two = indexes("--python-setup-interpreter-constraints=['CPython>=3.9']")
+    assert one is two
+
+    three = indexes("--python-repos-indexes=['https://example.com/simple/']")
+    assert three.indexes == ("https://example.com/simple/",)
@@ -3,9 +3,11 @@
 
 from __future__ import annotations
 
+import json
 import logging
 import os
 import time
+from collections import defaultdict
 from dataclasses import dataclass
 from pathlib import PurePath
 from types import CoroutineType
@@ -14,21 +16,29 @@ from typing import Any, Dict, Iterable, List, NoReturn, Optional, Sequence, Tupl
 from typing_extensions import TypedDict
 
 from pants.engine.collection import Collection
+from pants.engine.doctor import DoctorCheck, DoctorCheckStatus
 from pants.engine.engine_aware import EngineAwareParameter, EngineAwareReturnType
 from pants.engine.fs import (
     AddPrefix,
+    BlobFile,
     CreateDigest,
     Digest,
     DigestContents,
     DigestSubset,
+    DigestTreeSize,
+    Directory,
     DownloadFile,
     FileContent,
     FileDigest,
     MergeDigests,
+    MergeCoverageData,
+    MergeTestReports,
+    ParseDepfiles,
     PathGlobs,
     PathGlobsAndRoot,
     Paths,
     RemovePrefix,
+    RewriteDigest,
     Snapshot,
 )
 from pants.engine.goal import Goal
@@ -37,6 +47,7 @@ from pants.engine.internals.native_engine import (
     PyExecutionRequest,
     PyExecutionStrategyOptions,
     PyExecutor,
+    PyFakeProcesses,
     PyLocalStoreOptions,
     PyRemotingOptions,
     PyScheduler,
@@ -50,10 +61,16 @@ from pants.engine.internals.selectors import Params
 from pants.engine.internals.session import SessionValues
 from pants.engine.platform import Platform
 from pants.engine.process import (
+    BinaryPath,
+    BinaryPaths,
     FallibleProcessResultWithPlatform,
     InteractiveProcess,
     InteractiveProcessResult,
+    LocalBinaryPathRequest,
     MultiPlatformProcess,
+    PlannedProcess,
+    Process,
+    ProcessActionDescription,
 )
 from pants.engine.rules import Rule, RuleIndex, TaskRule
 from pants.engine.unions import UnionMembership, union
@@ -61,6 +78,7 @@ from pants.option.global_options import (
     LOCAL_STORE_LEASE_TIME_SECS,
     ExecutionOptions,
     LocalStoreOptions,
+    SessionPriority,
 )
 from pants.util.contextutil import temporary_file_path
 from pants.util.logging import LogLevel
@@ -98,6 +116,55 @@ class ExecutionTimeoutError(ExecutionError):
     """An ExecutionRequest specified a timeout which elapsed before the request completed."""
 
 
+@dataclass(frozen=True)
+class RemoteEndpointHealth:
+    """The result of the most recent warm-up of a remote endpoint.
+
+    The `status` is one of `unknown` (not yet warmed up), `healthy` (in which case the `latency_ms`
+    of the warm-up is set) or `unhealthy` (in which case the `error` is set).
+    """
+
+    name: str
+    address: str
+    status: str
+    latency_ms: int | None
+    error: str | None
+
+
+@dataclass(frozen=True)
+class RunDivergence:
+    """Explains why the nodes of one run were dirtied relative to an earlier run.
+
+    Each root cause is a dict with a `kind` of `changed_path`, `new_node` (for example: because of
+    changed options or arguments), `uncacheable`, `unknown` or `graph_restarted`, along with the
+    `key` of the node that changed (and the `path` that changed, for `changed_path`). Each chain
+    leads from a root of the later run which changed to the node of one of the root causes.
+    """
+
+    dirtied: tuple[str, ...]
+    changed: tuple[str, ...]
+    root_causes: tuple[dict[str, str], ...]
+    chains: tuple[tuple[str, ...], ...]
+
+
+def explain_run_divergence(before_run_graph: str, after_run_graph: str) -> RunDivergence:
+    """Answers "why did this rebuild?" by comparing the run graphs which were recorded for two runs
+    using `SchedulerSession.write_run_graph`.
+
+    Runs are only precisely comparable if they used the same Scheduler (i.e. the same pantsd
+    instance): otherwise the only root cause is `graph_restarted`.
+    """
+    divergence = json.loads(
+        native_engine.explain_run_graph_divergence(before_run_graph, after_run_graph)
+    )
+    return RunDivergence(
+        dirtied=tuple(divergence["dirtied"]),
+        changed=tuple(divergence["changed"]),
+        root_causes=tuple(divergence["root_causes"]),
+        chains=tuple(tuple(chain) for chain in divergence["chains"]),
+    )
+
+
 class Scheduler:
     def __init__(
         self,
@@ -116,6 +183,8 @@ class Scheduler:
         include_trace_on_error: bool = True,
         visualize_to_dir: Optional[str] = None,
         validate_reachability: bool = True,
+        watch_filesystem: bool = True,
+        fake_processes: PyFakeProcesses | None = None,
     ) -> None:
         """
         :param ignore_patterns: A list of gitignore-style file patterns for pants to ignore.
@@ -132,6 +201,10 @@ class Scheduler:
         :param validate_reachability: True to assert that all rules in an otherwise successfully
           constructed rule graph are reachable: if a graph cannot be successfully constructed, it
           is always a fatal error.
+        :param watch_filesystem: If False, the filesystem is not watched for changes, and callers
+          must instead call `invalidate_files` with any paths that changed between sessions.
+        :param fake_processes: If set, Processes are not run, and instead receive the responses
+          which were scripted for them. For tests.
         """
         self.include_trace_on_error = include_trace_on_error
         self._visualize_to_dir = visualize_to_dir
@@ -153,7 +226,15 @@ class Scheduler:
             remove_prefix=RemovePrefix,
             create_digest=CreateDigest,
             digest_subset=DigestSubset,
+            parse_depfiles=ParseDepfiles,
+            merge_coverage_data=MergeCoverageData,
+            merge_test_reports=MergeTestReports,
+            rewrite_digest=RewriteDigest,
             download_file=DownloadFile,
+            blob_file=BlobFile,
+            local_binary_path_request=LocalBinaryPathRequest,
+            binary_path=BinaryPath,
+            binary_paths=BinaryPaths,
             platform=Platform,
             multi_platform_process=MultiPlatformProcess,
             process_result=FallibleProcessResultWithPlatform,
@@ -163,6 +244,7 @@ class Scheduler:
             engine_aware_parameter=EngineAwareParameter,
         )
         remoting_options = PyRemotingOptions(
+            offline=execution_options.offline,
             execution_enable=execution_options.remote_execution,
             store_address=execution_options.remote_store_address,
             execution_address=execution_options.remote_execution_address,
@@ -171,15 +253,28 @@ class Scheduler:
             root_ca_certs_path=execution_options.remote_ca_certs_path,
             store_headers=tuple(execution_options.remote_store_headers.items()),
             store_chunk_bytes=execution_options.remote_store_chunk_bytes,
+            store_chunk_bytes_min=execution_options.remote_store_chunk_bytes_min,
+            store_chunk_bytes_max=execution_options.remote_store_chunk_bytes_max,
             store_chunk_upload_timeout=execution_options.remote_store_chunk_upload_timeout_seconds,
             store_rpc_retries=execution_options.remote_store_rpc_retries,
+            store_rpc_qps=execution_options.remote_store_rpc_qps,
+            store_bandwidth_bytes_per_sec=execution_options.remote_store_bandwidth_bytes_per_sec,
+            store_read_concurrency=execution_options.remote_store_read_concurrency,
+            store_max_inflight_bytes=execution_options.remote_store_max_inflight_bytes,
+            store_encryption_key_path=execution_options.remote_store_encryption_key_path,
+            store_mirrors=execution_options.remote_store_mirrors,
+            store_digest_function=execution_options.remote_store_digest_function.value,
             cache_eager_fetch=execution_options.remote_cache_eager_fetch,
+            cache_partition=execution_options.remote_cache_partition,
+            cache_partition_strategy=execution_options.remote_cache_partition_strategy.value,
             execution_extra_platform_properties=tuple(
                 tuple(pair.split("=", 1))
                 for pair in execution_options.remote_execution_extra_platform_properties
             ),
             execution_headers=tuple(execution_options.remote_execution_headers.items()),
             execution_overall_deadline_secs=execution_options.remote_execution_overall_deadline_secs,
+            execution_rpc_qps=execution_options.remote_execution_rpc_qps,
+            execution_pipeline_uploads=execution_options.remote_execution_pipeline_uploads,
         )
         py_local_store_options = PyLocalStoreOptions(
             store_dir=local_store_options.store_dir,
@@ -188,14 +283,48 @@ class Scheduler:
             directories_max_size_bytes=local_store_options.directories_max_size_bytes,
             lease_time_millis=LOCAL_STORE_LEASE_TIME_SECS * 1000,
             shard_count=local_store_options.shard_count,
+            materialize_umask=local_store_options.materialize_umask,
+            materialize_digest_xattrs=local_store_options.materialize_digest_xattrs,
+            hashing_parallelism=local_store_options.hashing_parallelism,
+            maintenance_idle_io=local_store_options.maintenance_idle_io,
+            download_repin=list(local_store_options.download_repin),
         )
         exec_stategy_opts = PyExecutionStrategyOptions(
             local_cache=execution_options.process_execution_local_cache,
             remote_cache_read=execution_options.remote_cache_read,
             remote_cache_write=execution_options.remote_cache_write,
-            local_cleanup=execution_options.process_execution_local_cleanup,
+            remote_cache_write_strict=execution_options.remote_cache_write_strict,
+            remote_cache_backfill=execution_options.remote_cache_backfill,
+            remote_cache_backfill_interval_millis=(
+                execution_options.remote_cache_backfill_interval_millis
+            ),
+            keep_sandboxes=execution_options.keep_sandboxes.value,
+            keep_sandboxes_max_count=execution_options.keep_sandboxes_max_count,
+            keep_sandboxes_max_age_secs=execution_options.keep_sandboxes_max_age_secs,
+            keep_sandboxes_max_bytes=execution_options.keep_sandboxes_max_bytes,
             local_parallelism=execution_options.process_execution_local_parallelism,
             remote_parallelism=execution_options.process_execution_remote_parallelism,
+            redact_env_vars=tuple(execution_options.redact_env_vars),
+            redact_patterns=tuple(execution_options.redact_patterns),
+            max_process_output_bytes=execution_options.process_execution_max_output_bytes,
+            max_run_output_bytes=execution_options.process_execution_max_run_output_bytes,
+            audit_file_accesses=execution_options.process_execution_audit_file_accesses,
+            fail_on_external_writes=execution_options.process_execution_fail_on_external_writes,
+            local_max_sandboxes=execution_options.process_execution_local_max_sandboxes,
+            local_batch_size=execution_options.process_execution_local_batch_size,
+            local_batch_window_millis=execution_options.process_execution_local_batch_window_millis,
+            local_platform_properties=tuple(
+                execution_options.process_execution_local_platform_properties.items()
+            ),
+            docker_binary=execution_options.process_execution_docker_binary,
+            speculation=execution_options.remote_execution_speculation.value,
+            speculation_runtime_multiplier=(
+                execution_options.remote_execution_speculation_runtime_multiplier
+            ),
+            speculation_min_delay_millis=(
+                execution_options.remote_execution_speculation_min_delay_secs * 1000
+            ),
+            speculation_max_concurrent=execution_options.remote_execution_speculation_max_concurrent,
         )
 
         self._py_scheduler = native_engine.scheduler_create(
@@ -208,9 +337,11 @@ class Scheduler:
             ca_certs_path,
             ignore_patterns,
             use_gitignore,
+            watch_filesystem,
             remoting_options,
             py_local_store_options,
             exec_stategy_opts,
+            fake_processes,
         )
 
         # If configured, visualize the rule graph before asserting that it is valid.
@@ -270,6 +401,33 @@ class Scheduler:
     def invalidate_all_files(self) -> int:
         return native_engine.graph_invalidate_all_paths(self.py_scheduler)
 
+    def warm_up_remote_endpoints(self, only_unhealthy: bool = False) -> None:
+        """Eagerly resolves and connects to the remote endpoints in use, in the background.
+
+        If `only_unhealthy` is set, only endpoints whose most recent warm-up failed are warmed up.
+        """
+        native_engine.scheduler_warm_up_remote_endpoints(self.py_scheduler, only_unhealthy)
+
+    def remote_endpoint_health(self) -> tuple[RemoteEndpointHealth, ...]:
+        return tuple(
+            RemoteEndpointHealth(*endpoint)
+            for endpoint in native_engine.scheduler_remote_endpoint_health(self.py_scheduler)
+        )
+
+    def engine_doctor(self) -> tuple[DoctorCheck, ...]:
+        return tuple(
+            DoctorCheck(name, DoctorCheckStatus(status), detail)
+            for name, status, detail in native_engine.engine_doctor(self.py_scheduler)
+        )
+
+    def shutdown(self, timeout: float) -> tuple[str, ...]:
+        """Waits up to `timeout` seconds for background work (such as cache writes) to complete,
+        and then flushes the local caches to disk.
+
+        Returns the names of any background tasks which did not complete in time.
+        """
+        return native_engine.scheduler_shutdown(self.py_scheduler, int(timeout * 1000))
+
     def check_invalidation_watcher_liveness(self) -> None:
         native_engine.check_invalidation_watcher_liveness(self.py_scheduler)
 
@@ -288,17 +446,27 @@ class Scheduler:
     def visualize_to_dir(self) -> str | None:
         return self._visualize_to_dir
 
-    def garbage_collect_store(self, target_size_bytes: int) -> None:
-        native_engine.garbage_collect_store(self.py_scheduler, target_size_bytes)
-
     def new_session(
         self,
         build_id: str,
         dynamic_ui: bool = False,
         session_values: SessionValues | None = None,
         cancellation_latch: PySessionCancellationLatch | None = None,
+        notification_threshold: float | None = None,
+        notification_command: tuple[str, ...] = (),
+        dry_run: bool = False,
+        priority: SessionPriority = SessionPriority.interactive,
     ) -> SchedulerSession:
-        """Creates a new SchedulerSession for this Scheduler."""
+        """Creates a new SchedulerSession for this Scheduler.
+
+        If a `notification_threshold` (in seconds) is given, desktop notifications are sent for
+        executions and processes which run for longer than the threshold.
+
+        If `dry_run` is set, processes are not executed: see `SchedulerSession.planned_processes`.
+
+        The `priority` orders the processes of this session relative to those of concurrent
+        sessions.
+        """
         return SchedulerSession(
             self,
             PySession(
@@ -307,6 +475,14 @@ class Scheduler:
                 build_id=build_id,
                 session_values=session_values or SessionValues(),
                 cancellation_latch=cancellation_latch or PySessionCancellationLatch(),
+                notification_threshold_millis=(
+                    int(notification_threshold * 1000)
+                    if notification_threshold is not None
+                    else None
+                ),
+                notification_command=list(notification_command),
+                dry_run=dry_run,
+                priority=priority.value,
             ),
         )
 
@@ -349,6 +525,23 @@ class SchedulerSession:
         )
         return {"started": result[0], "completed": result[1]}
 
+    def set_streaming_workunits_queue_bounds(
+        self, max_queued: int | None, overflow_policy: str
+    ) -> None:
+        """Bounds the queue of workunits waiting to be polled (or removes its bound, if None).
+
+        Bounds should only be set while the workunits are being polled: otherwise, the "block"
+        overflow policy will block the engine indefinitely.
+        """
+        native_engine.session_set_streaming_workunits_queue_bounds(
+            self.py_scheduler, self.py_session, max_queued, overflow_policy
+        )
+
+    def record_streaming_workunit_callback_time(self, micros: int) -> None:
+        native_engine.session_record_streaming_workunit_callback_time(
+            self.py_scheduler, self.py_session, micros
+        )
+
     def new_run_id(self) -> None:
         """Assigns a new "run id" to this Session, without creating a new Session.
 
@@ -358,6 +551,11 @@ class SchedulerSession:
         """
         native_engine.session_new_run_id(self.py_session)
 
+    @property
+    def invocation_id(self) -> str:
+        """A unique (ULID) id for this Session, which is exposed to workunits and remote servers."""
+        return cast(str, native_engine.session_get_invocation_id(self.py_session))
+
     def visualize_graph_to_file(self, filename: str) -> None:
         """Visualize a graph walk by writing graphviz `dot` output to a file."""
         native_engine.graph_visualize(self.py_scheduler, self.py_session, filename)
@@ -413,10 +611,43 @@ class SchedulerSession:
         self._maybe_visualize()
         return invalidated
 
+    def per_session_nodes(self) -> list[tuple[str, str]]:
+        """Returns the computed nodes which may not be shared with other sessions.
+
+        Most node results are shared by all sessions, but some (such as uncacheable @rules, and
+        processes which may not be cached) are only valid for the session which computed them.
+
+        :return: A sorted list of (node, reason) tuples.
+        """
+        return native_engine.graph_per_session_nodes(self.py_scheduler, self.py_session)
+
     def metrics(self) -> dict[str, int]:
         """Returns metrics for this SchedulerSession as a dict of metric name to metric value."""
         return native_engine.scheduler_metrics(self.py_scheduler, self.py_session)
 
+    def write_run_graph(self, path: str) -> None:
+        """Records the nodes which are reachable from the roots of this session, along with their
+        versions, to the given path.
+
+        The run graphs of two runs can be compared with `explain_run_divergence`.
+        """
+        native_engine.session_write_run_graph(self.py_scheduler, self.py_session, path)
+
+    def live_node_counts(self) -> dict[str, dict[str, int]]:
+        """Returns the number of nodes in the graph, by node type and then by state.
+
+        Node types are the names of @rules, or the kinds of intrinsic nodes (such as `process` or
+        `snapshot`). States are one of `not_started`, `running` (which includes nodes which are
+        waiting for their dependencies), `completed`, or `dirty`. This is cheap enough to poll, in
+        order to see what the Scheduler is doing when it appears to be idle.
+        """
+        counts: dict[str, dict[str, int]] = defaultdict(dict)
+        for node_type, state, count in native_engine.scheduler_live_node_counts(
+            self.py_scheduler, self.py_session
+        ):
+            counts[node_type][state] = count
+        return dict(counts)
+
     def _maybe_visualize(self) -> None:
         if self._scheduler.visualize_to_dir is not None:
             # TODO: This increment-and-get is racey.
@@ -552,6 +783,21 @@ class SchedulerSession:
         # order in output lists.
         return [ret.value for _, ret in returns]
 
+    def warm_up(self, product: type, subjects: Sequence[Any | Params]) -> None:
+        """Computes a single product for some subjects in the background, at low priority.
+
+        Warm-up work runs only while no other requests are executing, and is preempted as soon as
+        one begins: Nodes which completed before preemption remain memoized, and the rest of the
+        work resumes once the Scheduler is idle again. Use this to precompute results which
+        interactive requests are likely to need (dependency inference for a whole repository, for
+        example) without delaying those requests.
+
+        :param product: A product type for the request.
+        :param subjects: A list of subjects or Params instances for the request.
+        """
+        request = self.execution_request([product], subjects)
+        native_engine.scheduler_warm_up(self.py_scheduler, self.py_session, request.native)
+
     def capture_snapshots(
         self, path_globs_and_roots: Iterable[PathGlobsAndRoot]
     ) -> tuple[Snapshot, ...]:
@@ -566,6 +812,22 @@ class SchedulerSession:
             _PathGlobsAndRootCollection(path_globs_and_roots),
         )
 
+    def snapshot_of_file_contents(
+        self, file_contents: Iterable[FileContent], directories: Iterable[Directory] = ()
+    ) -> Snapshot:
+        """Synchronously stores the given in-memory files (and empty directories) in bulk, and
+        returns a Snapshot of them.
+
+        The files are hashed in parallel, and the Snapshot is created in a single call, which is
+        much cheaper than requesting a `CreateDigest` for many thousands of generated files.
+        """
+        return native_engine.snapshot_of_file_contents(
+            self.py_scheduler,
+            self.py_session,
+            [(fc.path, fc.content, fc.is_executable) for fc in file_contents],
+            [directory.path for directory in directories],
+        )
+
     def single_file_digests_to_bytes(self, digests: Sequence[Digest]) -> tuple[bytes, ...]:
         return tuple(native_engine.single_file_digests_to_bytes(self.py_scheduler, list(digests)))
 
@@ -585,6 +847,34 @@ class SchedulerSession:
     def ensure_remote_has_recursive(self, digests: Sequence[Digest]) -> None:
         native_engine.ensure_remote_has_recursive(self.py_scheduler, list(digests))
 
+    def tree_size(self, digest: Digest) -> DigestTreeSize:
+        """Compute the transitive size of a `Digest` from its directory entries, without loading
+        the contents of any files, in order to warn about unexpectedly large inputs or outputs
+        before transferring them."""
+        return DigestTreeSize(**native_engine.tree_size(self.py_scheduler, digest))
+
+    def expand_digest(self, displayed: str) -> Digest:
+        """Expand a digest as displayed in errors, workunits and logs (`<fingerprint>:<size>`, with
+        the fingerprint abbreviated unless `--full-digests` is set) into a full `Digest`, by finding
+        the unique entry of the local store with that fingerprint prefix."""
+        return native_engine.expand_digest(self.py_scheduler, displayed)
+
+    def print_action(self, process: Process) -> ProcessActionDescription:
+        """Render the Remote Execution API protos and the local sandbox for a `Process` as they
+        would be under the current configuration, without executing it."""
+        return ProcessActionDescription(**native_engine.print_action(self.py_scheduler, process))
+
+    def export_repro_bundle(self, process: Process) -> Digest:
+        """Package a `Process` into a "repro bundle", and return a `Digest` containing it as a single
+        `repro.tar` file.
+
+        The bundle contains the input files of the process, a `__run.sh` script which runs it in
+        them, its environment, the local platform, its expected outputs, and the rendered action
+        (see `print_action`), so that a failing process can be reproduced byte-for-byte on another
+        machine. Configured secrets are redacted from the generated files, but not from the inputs.
+        """
+        return native_engine.export_repro_bundle(self.py_scheduler, process)
+
     def run_local_interactive_process(
         self, request: InteractiveProcess
     ) -> InteractiveProcessResult:
@@ -605,11 +895,37 @@ class SchedulerSession:
         native_engine.lease_files_in_graph(self.py_scheduler, self.py_session)
 
     def garbage_collect_store(self, target_size_bytes: int) -> None:
-        self._scheduler.garbage_collect_store(target_size_bytes)
+        """Garbage collects the local store, retaining the files which are referenced by values in
+        memory or by recently used cached process results."""
+        native_engine.garbage_collect_store(self.py_scheduler, self.py_session, target_size_bytes)
+
+    def engine_doctor(self) -> tuple[DoctorCheck, ...]:
+        """Runs the engine's checks of the environment that it is running in."""
+        return self._scheduler.engine_doctor()
 
     def get_observation_histograms(self) -> dict:
         return native_engine.session_get_observation_histograms(self.py_scheduler, self.py_session)
 
+    def planned_processes(self) -> tuple[PlannedProcess, ...]:
+        """The processes which this (dry-run) session would have executed, in request order.
+
+        Each of them was given a synthesized failed result, which describes it in its stderr.
+        """
+        return tuple(
+            PlannedProcess(
+                description=description,
+                action_digest=action_digest,
+                input_digest=input_digest,
+                input_bytes=input_bytes,
+            )
+            for (
+                description,
+                action_digest,
+                input_digest,
+                input_bytes,
+            ) in native_engine.session_get_planned_processes(self.py_scheduler, self.py_session)
+        )
+
     def record_test_observation(self, value: int) -> None:
         native_engine.session_record_test_observation(self.py_scheduler, self.py_session, value)
 
@@ -628,6 +944,7 @@ def register_rules(rule_index: RuleIndex, union_membership: UnionMembership) ->
             rule.canonical_name,
             rule.desc or "",
             rule.level.level,
+            rule.timeout,
         )
 
         for selector in rule.input_selectors:
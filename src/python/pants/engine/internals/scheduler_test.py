@@ -2,16 +2,22 @@
 # Licensed under the Apache License, Version 2.0 (see LICENSE).
 
 import re
+import tarfile
 from abc import ABC, abstractmethod
 from dataclasses import dataclass
+from io import BytesIO
+from pathlib import Path
 from textwrap import dedent
 from typing import Any
 
 import pytest
 
+from pants.engine.fs import Digest, DigestContents, DigestTreeSize, PathGlobs
 from pants.engine.internals.engine_testutil import remove_locations_from_traceback
-from pants.engine.internals.scheduler import ExecutionError
-from pants.engine.rules import Get, rule
+from pants.engine.internals.scheduler import ExecutionError, explain_run_divergence
+from pants.engine.internals.selectors import Params
+from pants.engine.process import Process
+from pants.engine.rules import Get, _uncacheable_rule, rule
 from pants.engine.unions import UnionRule, union
 from pants.testutil.rule_runner import QueryRule, RuleRunner
 
@@ -101,6 +107,145 @@ def test_transitive_params(transitive_params_rule_runner: RuleRunner) -> None:
     transitive_params_rule_runner.request(D, [c])
 
 
+def test_warm_up(transitive_params_rule_runner: RuleRunner) -> None:
+    # Warm-up roots are computed in the background, and do not affect the results of subsequent
+    # requests for the same roots.
+    a, c = A(), C()
+    transitive_params_rule_runner.scheduler.warm_up(str, [Params(a, c)])
+    result_str = transitive_params_rule_runner.request(str, [a, c])
+    assert remove_locations_from_traceback(result_str) == remove_locations_from_traceback(
+        consumes_a_and_b(a, transitive_b_c(c))
+    )
+
+
+@_uncacheable_rule
+def uncacheable_b(c: C) -> B:
+    return B()
+
+
+def test_per_session_nodes() -> None:
+    rule_runner = RuleRunner(rules=[consumes_a_and_b, uncacheable_b, QueryRule(str, [A, C])])
+    rule_runner.request(str, [A(), C()])
+
+    per_session_nodes = dict(rule_runner.scheduler.per_session_nodes())
+    [uncacheable_node] = [node for node in per_session_nodes if "uncacheable_b" in node]
+    assert "marked uncacheable" in per_session_nodes[uncacheable_node]
+    # Rules which depend on uncacheable rules are not themselves annotated as per-session.
+    assert not any("consumes_a_and_b" in node for node in per_session_nodes)
+
+
+def test_live_node_counts() -> None:
+    rule_runner = RuleRunner(rules=[consumes_a_and_b, QueryRule(str, [A, B])])
+    rule_runner.request(str, [A(), B()])
+
+    counts = rule_runner.scheduler.live_node_counts()
+    [rule_counts] = [
+        node_counts for node_type, node_counts in counts.items() if "consumes_a_and_b" in node_type
+    ]
+    assert rule_counts == {"completed": 1}
+    assert counts["select"].get("completed", 0) >= 1
+
+
+def test_explain_run_divergence(tmp_path: Path) -> None:
+    rule_runner = RuleRunner(rules=[QueryRule(DigestContents, [PathGlobs])])
+    rule_runner.write_files({"a.txt": "one", "b.txt": "two"})
+
+    def run(name: str) -> str:
+        rule_runner.new_session(name)
+        rule_runner.request(DigestContents, [PathGlobs(["*.txt"])])
+        run_graph = str(tmp_path / f"{name}.json")
+        rule_runner.scheduler.write_run_graph(run_graph)
+        return run_graph
+
+    first = run("first")
+    unchanged = run("unchanged")
+    divergence = explain_run_divergence(first, unchanged)
+    assert divergence.dirtied == ()
+    assert divergence.root_causes == ()
+
+    rule_runner.write_files({"a.txt": "three"})
+    changed = run("changed")
+    divergence = explain_run_divergence(unchanged, changed)
+    assert [cause for cause in divergence.root_causes if cause["kind"] == "changed_path"] == [
+        {"kind": "changed_path", "key": divergence.chains[0][-1], "path": "a.txt"}
+    ]
+    assert len(divergence.dirtied) > len(divergence.changed)
+
+
+def test_invocation_id() -> None:
+    rule_runner = RuleRunner()
+    first_id = rule_runner.scheduler.invocation_id
+    assert len(first_id) == 26
+    assert first_id == rule_runner.scheduler.invocation_id
+    rule_runner.new_session("second")
+    assert first_id != rule_runner.scheduler.invocation_id
+
+
+def test_print_action() -> None:
+    rule_runner = RuleRunner()
+    process = Process(["/bin/echo", "hello"], description="echo", output_files=["out/file"])
+    description = rule_runner.scheduler.print_action(process)
+    assert description == rule_runner.scheduler.print_action(process)
+    assert description.action_digest != description.command_digest
+    assert b"/bin/echo" in description.command
+    assert "Local sandbox:" in description.rendered
+    assert "capture file: out/file" in description.rendered
+
+    other = rule_runner.scheduler.print_action(
+        Process(["/bin/echo", "goodbye"], description="echo", output_files=["out/file"])
+    )
+    assert description.action_digest != other.action_digest
+
+
+def test_export_repro_bundle() -> None:
+    rule_runner = RuleRunner(rules=[QueryRule(DigestContents, [Digest])])
+    input_digest = rule_runner.make_snapshot({"src/input.txt": "hello"}).digest
+    process = Process(
+        ["/bin/cat", "input.txt"],
+        description="cat",
+        input_digest=input_digest,
+        working_directory="src",
+        env={"LANG": "C"},
+        output_files=["out/file"],
+    )
+    bundle_digest = rule_runner.scheduler.export_repro_bundle(process)
+    assert bundle_digest == rule_runner.scheduler.export_repro_bundle(process)
+
+    contents = rule_runner.request(DigestContents, [bundle_digest])
+    assert [fc.path for fc in contents] == ["repro.tar"]
+    with tarfile.open(fileobj=BytesIO(contents[0].content)) as archive:
+
+        def read(name: str) -> bytes:
+            member = archive.extractfile(name)
+            assert member is not None
+            return member.read()
+
+        assert read("repro/sandbox/src/input.txt") == b"hello"
+        assert read("repro/env.txt") == b"LANG=C\n"
+        assert b"cd src" in read("repro/__run.sh")
+
+
+def test_expand_digest() -> None:
+    rule_runner = RuleRunner()
+    digest = rule_runner.make_snapshot({"file.txt": "hello"}).digest
+    displayed = f"{digest.fingerprint[:12]}:{digest.serialized_bytes_length}"
+    assert rule_runner.scheduler.expand_digest(displayed) == digest
+    full = f"{digest.fingerprint}:{digest.serialized_bytes_length}"
+    assert rule_runner.scheduler.expand_digest(full) == digest
+    with pytest.raises(Exception, match="Invalid digest"):
+        rule_runner.scheduler.expand_digest(digest.fingerprint[:12])
+
+
+def test_tree_size() -> None:
+    rule_runner = RuleRunner()
+    digest = rule_runner.make_snapshot(
+        {"file.txt": "hello", "dir/a.txt": "hi", "dir/b.txt": "hi"}
+    ).digest
+    assert rule_runner.scheduler.tree_size(digest) == DigestTreeSize(
+        file_count=3, file_bytes=9, directory_count=2
+    )
+
+
 def test_consumed_types(transitive_params_rule_runner: RuleRunner) -> None:
     assert {A, B, C, str} == set(
         transitive_params_rule_runner.scheduler.scheduler.rule_graph_consumed_types([A, C], str)
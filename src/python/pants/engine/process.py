@@ -3,9 +3,11 @@
 
 from __future__ import annotations
 
+import codecs
 import dataclasses
 import hashlib
 import logging
+import re
 from dataclasses import dataclass
 from enum import Enum
 from textwrap import dedent
@@ -18,6 +20,7 @@ from pants.engine.fs import EMPTY_DIGEST, CreateDigest, Digest, FileContent, Fil
 from pants.engine.internals.selectors import MultiGet
 from pants.engine.platform import Platform
 from pants.engine.rules import Get, collect_rules, rule, side_effecting
+from pants.option.global_options import GlobalOptions
 from pants.util.frozendict import FrozenDict
 from pants.util.logging import LogLevel
 from pants.util.meta import frozen_after_init
@@ -47,41 +50,191 @@ class ProcessCacheScope(Enum):
     NEVER = "never"
 
 
+class OutputScannerKind(Enum):
+    # Collects (up to 100 of) the lines which match the pattern.
+    LINES = "lines"
+    # Counts the lines which match the pattern.
+    COUNT = "count"
+    # Counts the errors and warnings emitted by compilers and similar tools (the pattern is unused).
+    DIAGNOSTICS = "diagnostics"
+
+
+@dataclass(frozen=True)
+class OutputScanner:
+    """A scanner which the engine runs over the stdout and stderr of a completed `Process`.
+
+    The finding of the scanner is attached to the workunit for the process as metadata with the
+    given `name`, which allows UIs to summarize outputs without loading them in full.
+    """
+
+    name: str
+    kind: OutputScannerKind
+    pattern: str = ""
+
+
+class ResponseFileQuoting(Enum):
+    # One argument per line, with arguments which contain whitespace, quotes or backslashes
+    # surrounded by double quotes (and backslash escaped), as understood by gcc, clang and javac.
+    QUOTED = "quoted"
+    # One argument per line, without quoting (arguments may not contain newlines).
+    LINES = "lines"
+
+
+@dataclass(frozen=True)
+class ResponseFile:
+    """How a tool accepts arguments from a response file (also known as an "argfile").
+
+    If the arguments (and env) of a `Process` which runs locally would exceed the limits of the OS,
+    the arguments after the first `argv_prefix_len` are moved into a response file in the sandbox,
+    which is passed to the tool as a single argument: its absolute path with the given `prefix`.
+    """
+
+    argv_prefix_len: int
+    prefix: str = "@"
+    quoting: ResponseFileQuoting = ResponseFileQuoting.QUOTED
+
+
+@dataclass(frozen=True)
+class ResourceLimits:
+    """Limits on the resources which a `Process` may use when it runs locally.
+
+    `max_rss_bytes` limits the total resident memory of the process and its descendants (only on
+    Linux), and `cpu_seconds` limits its CPU time: a process which exceeds either is killed, and
+    the limit is reported as the `exit_reason` of its result. `nofile` limits the number of files
+    that the process may have open, and `nproc` limits the number of processes (and threads) of
+    the process and its descendants (only on Linux, where it requires that the `pids` controller of
+    cgroup v2 is available to the children of the cgroup that Pants runs in): a process which
+    exceeds them fails to open or fork more, and must report that itself.
+    """
+
+    max_rss_bytes: int | None = None
+    cpu_seconds: int | None = None
+    nofile: int | None = None
+    nproc: int | None = None
+
+    def __post_init__(self) -> None:
+        for field in dataclasses.fields(self):
+            value = getattr(self, field.name)
+            if value is not None and value < 1:
+                raise ValueError(f"The `{field.name}` resource limit must be positive: {value}.")
+
+
+_DESCRIPTION_PLACEHOLDER = re.compile(r"\{(tool|verb|target_count|targets)\}")
+
+
+def render_process_description(
+    template: str, *, tool: str | None, verb: str | None, target_count: int | None
+) -> str:
+    """Render the structured fields of a `Process` into its description template.
+
+    Any other use of braces is left untouched, and a placeholder for an unset field renders as
+    "unknown".
+    """
+    values = {
+        "tool": tool,
+        "verb": verb,
+        "target_count": None if target_count is None else str(target_count),
+        "targets": None if target_count is None else pluralize(target_count, "target"),
+    }
+
+    def render(match: re.Match[str]) -> str:
+        value = values[match.group(1)]
+        return "unknown" if value is None else value
+
+    return _DESCRIPTION_PLACEHOLDER.sub(render, template)
+
+
 @frozen_after_init
 @dataclass(unsafe_hash=True)
 class Process:
     argv: Tuple[str, ...]
+    pipe_from_argv: Tuple[str, ...]
     description: str = dataclasses.field(compare=False)
+    tool: str | None = dataclasses.field(compare=False)
+    verb: str | None = dataclasses.field(compare=False)
+    target_count: int | None = dataclasses.field(compare=False)
+    tags: FrozenDict[str, str] = dataclasses.field(compare=False)
     level: LogLevel
     input_digest: Digest
     working_directory: str | None
+    create_working_directory: bool
     env: FrozenDict[str, str]
     append_only_caches: FrozenDict[str, str]
     output_files: Tuple[str, ...]
     output_directories: Tuple[str, ...]
+    output_paths: Tuple[str, ...]
     timeout_seconds: int | float
+    resource_limits: ResourceLimits
     jdk_home: str | None
     is_nailgunnable: bool
+    allocate_tty: bool
+    container_image: str | None
+    remote_worker_key: str | None
+    remote_affinity_key: str | None
     execution_slot_variable: str | None
+    leased_port_variables: Tuple[str, ...]
+    invocation_id_variable: str | None
     cache_scope: ProcessCacheScope
+    discovered_inputs_depfile: str | None
+    source_output_files: Tuple[str, ...]
+    version_probes: Tuple[Tuple[str, ...], ...]
+    output_scanners: Tuple[OutputScanner, ...]
+    max_output_bytes: int | None
+    libc: str | None
+    min_libc_version: str | None
+    min_macos_version: str | None
+    platform_properties: FrozenDict[str, str]
+    batch_argv_prefix_len: int | None
+    response_file: ResponseFile | None
+    platform_independent: bool
+    target_platform: str | None
+    output_encoding: str | None
 
     def __init__(
         self,
         argv: Iterable[str],
         *,
         description: str,
+        tool: str | None = None,
+        verb: str | None = None,
+        target_count: int | None = None,
+        tags: Mapping[str, str] | None = None,
         level: LogLevel = LogLevel.INFO,
         input_digest: Digest = EMPTY_DIGEST,
         working_directory: str | None = None,
+        create_working_directory: bool = True,
         env: Mapping[str, str] | None = None,
         append_only_caches: Mapping[str, str] | None = None,
         output_files: Iterable[str] | None = None,
         output_directories: Iterable[str] | None = None,
+        output_paths: Iterable[str] | None = None,
         timeout_seconds: int | float | None = None,
+        resource_limits: ResourceLimits | None = None,
         jdk_home: str | None = None,
         is_nailgunnable: bool = False,
+        allocate_tty: bool = False,
+        container_image: str | None = None,
+        remote_worker_key: str | None = None,
+        remote_affinity_key: str | None = None,
         execution_slot_variable: str | None = None,
+        leased_port_variables: Iterable[str] = (),
+        invocation_id_variable: str | None = None,
         cache_scope: ProcessCacheScope = ProcessCacheScope.SUCCESSFUL,
+        pipe_from_argv: Iterable[str] | None = None,
+        discovered_inputs_depfile: str | None = None,
+        source_output_files: Iterable[str] = (),
+        version_probes: Iterable[Iterable[str]] = (),
+        output_scanners: Iterable[OutputScanner] = (),
+        max_output_bytes: int | None = None,
+        libc: str | None = None,
+        min_libc_version: str | None = None,
+        min_macos_version: str | None = None,
+        platform_properties: Mapping[str, str] | None = None,
+        batch_argv_prefix_len: int | None = None,
+        response_file: ResponseFile | None = None,
+        platform_independent: bool = False,
+        target_platform: Platform | None = None,
+        output_encoding: str | None = None,
     ) -> None:
         """Request to run a subprocess, similar to subprocess.Popen.
 
@@ -98,6 +251,31 @@ class Process:
         populate `output_digest` on the `ProcessResult`. If you want to split up this output digest
         into multiple digests, use `await Get(Digest, DigestSubset)` on the `output_digest`.
 
+        If `working_directory` is set, the process runs in that directory relative to the root of
+        `input_digest`. If the directory does not exist in `input_digest`, it is created (empty),
+        unless `create_working_directory` is False, in which case the process fails with an error
+        naming the missing path.
+
+        If you do not know in advance whether an output will be a file or a directory, set
+        `output_paths` instead, which captures either. Remote execution of a process with
+        `output_paths` requires a server which supports v2.1 of the Remote Execution API.
+
+        Rather than formatting a free-form `description`, processes should set the structured
+        fields `tool` (e.g. "flake8"), `verb` (e.g. "Linting") and `target_count`, in which case
+        `description` is a template which is rendered with them (as `self.description`), such as
+        `"{verb} {targets} with {tool}"`. The supported placeholders are `{tool}`, `{verb}`,
+        `{target_count}` and `{targets}` (e.g. "3 targets"). Structured fields keep the descriptions
+        shown in the dynamic UI and logs consistent, and are attached to the workunit of the process
+        as metadata, so that processes can be aggregated by tool. None of them are part of the cache
+        key of the process.
+
+        Free-form `tags` (such as `{"goal": "test", "target": "src/python/foo:tests"}`) attribute a
+        process, for example in order to attribute the cost of remote execution by team or by
+        target. Tags are attached to the workunit of the process as `tag.<name>` metadata, and are
+        sent to remote servers with the requests made on its behalf: the `target` tag as the
+        `target_id` of the REAPI `RequestMetadata`, and every tag as an `x-pants-tag-<name>` header.
+        Tags are not part of the cache key of the process.
+
         To actually run the process, use `await Get(ProcessResult, Process)` or
         `await Get(FallibleProcessResult, Process)`.
 
@@ -105,24 +283,215 @@ class Process:
 
             result = await Get(ProcessResult, Process(["/bin/echo", "hello world"], description="demo"))
             assert result.stdout == b"hello world"
+
+        If `pipe_from_argv` is set, it will be run in the same sandbox (and with the same env) as
+        `argv`, and its stdout will be streamed directly into the stdin of `argv`, like a shell pipe.
+        This avoids storing potentially large intermediate outputs.
+
+        If `discovered_inputs_depfile` is set, the process is expected to write a Makefile-style
+        depfile (e.g. via `gcc -MD`) to that path, listing any inputs that it discovered while
+        running. Relative paths in the depfile are relative to the `working_directory`. Discovered
+        inputs within the build root which are not already in `input_digest` are added to it, and
+        the process is run again with them (which might hit the cache), so that its result depends
+        on their content. The engine will re-run the process when any of those inputs change. The
+        depfile is automatically included in `output_files`.
+
+        If the process generates code, `source_output_files` should list the generated files which
+        consumers treat like sources. They are automatically included in `output_files`, and the
+        result of the process additionally has a separate digest for each of them in
+        `source_digests`. Rules which consume an individual generated file via its digest (rather
+        than via `output_digest`) are only invalidated when that file changes, as for a source file.
+
+        If the process uses tools from outside of its `input_digest` (e.g. a system compiler), each
+        of `version_probes` should be the argv of a cheap command that reports the version of one of
+        them, such as `["/usr/bin/cc", "--version"]`. The engine runs each probe once per run of
+        Pants (with the same `env`), and includes its output in the cache key of the process, so
+        that upgrading the tool invalidates cached results.
+
+        Locally executed processes are assigned one of `--process-execution-local-parallelism`
+        execution slots, numbered from 1, which is exposed in the `PANTS_EXECUTION_SLOT` env var
+        (and in `execution_slot_variable`, if set). No two concurrently running processes share a
+        slot, so processes may use it to choose disjoint ports or databases. The slot is not part
+        of the cache key of the process.
+
+        Each of `leased_port_variables` names an env var which will be set to a free localhost TCP
+        port, leased to the process while it runs locally. No two concurrently running processes
+        are leased the same port. Leased ports are not part of the cache key of the process, and are
+        not supported by remote execution.
+
+        If `invocation_id_variable` is set, it names an env var which will be set to the unique id of
+        the Session which ran the process, allowing processes to correlate their own telemetry with
+        that of Pants. The id is not part of the cache key of the process, and is not supported by
+        remote execution.
+
+        Values in `env` may contain placeholders, which are expanded by the engine when the process
+        runs locally: `{chroot}` is the absolute path of the sandbox, `{cache_dir:<name>}` is the
+        absolute path of the named cache `<name>`, and `{platform}` is the current platform (e.g.
+        `linux`). Cache keys include only the unexpanded values. Placeholders are not supported by
+        remote execution.
+
+        If `remote_worker_key` is set, remote execution servers which support persistent workers
+        may reuse a warm worker (for example, a compiler daemon) for all processes with the same
+        key, which should therefore be derived from the identity of the tool (such as its digest).
+        The key is part of the cache key of the process, and is ignored by local execution.
+
+        If `remote_affinity_key` is set, remote execution schedulers which support worker affinity
+        prefer to run the process on the worker which last ran a process with the same key, which
+        allows incremental processes (such as compilers in persistent workers) to reuse warm state
+        between runs. The key should identify the unit of incremental work (such as the address of
+        a target) and be stable between runs. It is part of the cache key of the process, and is
+        ignored by local execution.
+
+        Each of `output_scanners` is run over the output of the process once it has completed
+        (whether it ran locally, remotely or was cached), and attaches its findings to the workunit
+        for the process. Scanners do not affect the cache key of the process.
+
+        If `max_output_bytes` is set, the process fails if the total size of its output files
+        exceeds it, rather than having its outputs cached. It overrides
+        `--process-execution-max-output-bytes`, and does not affect the cache key of the process.
+
+        Binary compatibility often depends on more than the OS: a process may require a `libc`
+        flavor (`"glibc"` or `"musl"`), a `min_libc_version` or a `min_macos_version`. Processes
+        may also require arbitrary `platform_properties` (such as `{"xcode": "14.2"}`), which must
+        equal the properties declared for the local machine by
+        `--process-execution-local-platform-properties`. A process whose requirements are not
+        satisfied by the local machine fails rather than running, and the requirements are part of
+        its cache key. The details of the platform that a process
+        actually ran on are reported in the `platform_details` of its
+        `FallibleProcessResultWithPlatform`.
+
+        If `batch_argv_prefix_len` is set, the process may be run locally in a single batch with
+        other processes that differ from it only in their trailing arguments and their outputs,
+        which avoids the overhead of running a tool (such as a linter) once per file. The first
+        `batch_argv_prefix_len` arguments are the tool and its options, and the remaining arguments
+        are items (such as files) that the tool must process independently of one another. If a
+        batch succeeds, each process receives the stdout and stderr of the whole batch, and the
+        outputs that it declared. If a batch fails, each of its processes is re-run individually.
+        See `--process-execution-local-batch-size`.
+
+        If the tool accepts arguments from a response file, set `response_file` to describe its
+        syntax: the engine will then move the arguments of the process into a response file if they
+        would exceed the limits of the OS when it runs locally, rather than failing with `E2BIG`.
+        The response file is not part of the cache key of the process.
+
+        The cache key of a process records the platform that its outputs target, which by default
+        is its platform constraint (if any). If the outputs of the process do not depend on where it
+        runs (such as pure Python sources, or JVM bytecode), set `platform_independent` to share
+        them between all platforms. If the process cross compiles its outputs, set
+        `target_platform` so that they are keyed by that platform rather than by where they ran.
+
+        The stdout and stderr of a process are captured as raw bytes, and the result records their
+        encoding: `utf-8` (or `utf-16`, given a byte order mark) if it can be detected, or None
+        otherwise. If a tool emits output in another encoding (such as `latin-1`), declare it as
+        `output_encoding`, which is then recorded instead. Use `decoded_stdout()` and
+        `decoded_stderr()` on the result to decode output strictly or lossily. The encoding is not
+        part of the cache key of the process.
+
+        If `allocate_tty` is set, the process runs with a pseudo-terminal as its stdin, stdout and
+        stderr, so that tools which check `isatty()` (to decide whether to emit colors or progress
+        bars, for example) behave as they would in a real terminal. The terminal has the size of
+        the terminal that Pants is attached to (or 80x24, if none), and is resized (sending the
+        process `SIGWINCH`) when that terminal is. Its stdin is always at EOF, and its stdout and
+        stderr are merged into the `stdout` of the result. Allocating a TTY is
+        part of the cache key of the process, and is not supported by remote execution or in
+        combination with `pipe_from_argv`.
+
+        Set `resource_limits` to limit the memory, CPU time, open files or processes that a process
+        may use when it runs locally (see `ResourceLimits`). If the process is killed because it
+        exceeded its memory or CPU time, the limit that it exceeded is reported as the
+        `exit_reason` of its `FallibleProcessResult`, and described in its stderr, and the result
+        is not persisted in the process cache (even with `ProcessCacheScope.ALWAYS`). Resource
+        limits are part of the cache key of the process, and are not supported by remote execution.
+
+        Set `container_image` (such as `python@sha256:<digest>`) to run the process in a container
+        of that image, in order to use a hermetic toolchain. The image must be pinned by its
+        digest (rather than only naming a tag, which may refer to a different image over time),
+        because it is part of the cache key of the process. Locally, the container is run using
+        `--process-execution-docker-binary`, with the sandbox of the process mounted at the same
+        path, and as the current user. Remotely, the image is sent as the `container-image`
+        platform property (which some servers require to be prefixed with `docker://`). Containers
+        always run Linux processes, and may not also set `allocate_tty`, `pipe_from_argv` or
+        `resource_limits`.
         """
         if isinstance(argv, str):
             raise ValueError("argv must be a sequence of strings, but was a single string.")
+        if isinstance(pipe_from_argv, str):
+            raise ValueError(
+                "pipe_from_argv must be a sequence of strings, but was a single string."
+            )
+        if allocate_tty and pipe_from_argv:
+            raise ValueError("A process may not set both `allocate_tty` and `pipe_from_argv`.")
+        if container_image is not None:
+            if not container_image:
+                raise ValueError("The `container_image` of a process may not be empty.")
+            if not re.search(r"@sha256:[0-9a-fA-F]{64}$", container_image):
+                raise ValueError(
+                    "The `container_image` of a process must be pinned by its digest (such as "
+                    f"`python@sha256:<digest>`), but was: {container_image}"
+                )
+            if allocate_tty or pipe_from_argv or resource_limits not in (None, ResourceLimits()):
+                raise ValueError(
+                    "A process which sets a `container_image` may not set `allocate_tty`, "
+                    "`pipe_from_argv` or `resource_limits`."
+                )
+        if platform_independent and target_platform:
+            raise ValueError(
+                "A process may not set both `platform_independent` and a `target_platform`."
+            )
+        if output_encoding is not None:
+            try:
+                codecs.lookup(output_encoding)
+            except LookupError:
+                raise ValueError(f"Unknown `output_encoding` for a process: {output_encoding!r}.")
         self.argv = tuple(argv)
-        self.description = description
+        self.pipe_from_argv = tuple(pipe_from_argv or ())
+        self.description = (
+            description
+            if tool is None and verb is None and target_count is None
+            else render_process_description(
+                description, tool=tool, verb=verb, target_count=target_count
+            )
+        )
+        self.tool = tool
+        self.verb = verb
+        self.target_count = target_count
+        self.tags = FrozenDict(tags or {})
         self.level = level
         self.input_digest = input_digest
         self.working_directory = working_directory
+        self.create_working_directory = create_working_directory
         self.env = FrozenDict(env or {})
         self.append_only_caches = FrozenDict(append_only_caches or {})
         self.output_files = tuple(output_files or ())
         self.output_directories = tuple(output_directories or ())
+        self.output_paths = tuple(output_paths or ())
         # NB: A negative or None time value is normalized to -1 to ease the transfer to Rust.
         self.timeout_seconds = timeout_seconds if timeout_seconds and timeout_seconds > 0 else -1
+        self.resource_limits = resource_limits or ResourceLimits()
         self.jdk_home = jdk_home
         self.is_nailgunnable = is_nailgunnable
+        self.allocate_tty = allocate_tty
+        self.container_image = container_image
+        self.remote_worker_key = remote_worker_key
+        self.remote_affinity_key = remote_affinity_key
         self.execution_slot_variable = execution_slot_variable
+        self.leased_port_variables = tuple(leased_port_variables)
+        self.invocation_id_variable = invocation_id_variable
         self.cache_scope = cache_scope
+        self.discovered_inputs_depfile = discovered_inputs_depfile
+        self.source_output_files = tuple(source_output_files)
+        self.version_probes = tuple(tuple(probe) for probe in version_probes)
+        self.output_scanners = tuple(output_scanners)
+        self.max_output_bytes = max_output_bytes
+        self.libc = libc
+        self.min_libc_version = min_libc_version
+        self.min_macos_version = min_macos_version
+        self.platform_properties = FrozenDict(platform_properties or {})
+        self.batch_argv_prefix_len = batch_argv_prefix_len
+        self.response_file = response_file
+        self.platform_independent = platform_independent
+        self.target_platform = target_platform.value if target_platform else None
+        self.output_encoding = output_encoding
 
 
 @frozen_after_init
@@ -151,12 +520,37 @@ class MultiPlatformProcess:
         return ProductDescription(self.processes[0].description)
 
 
+class ProcessOutputDecoding(Enum):
+    """How to decode bytes of process output which are invalid in its encoding."""
+
+    # Raise a `UnicodeDecodeError`.
+    strict = "strict"
+    # Replace them with U+FFFD (the replacement character).
+    lossy = "lossy"
+
+
+def decode_process_output(
+    content: bytes,
+    encoding: str | None,
+    decoding: ProcessOutputDecoding = ProcessOutputDecoding.strict,
+) -> str:
+    """Decodes the stdout or stderr of a process in its detected or declared encoding.
+
+    Output whose encoding could not be detected is decoded as UTF-8.
+    """
+    errors = "replace" if decoding == ProcessOutputDecoding.lossy else "strict"
+    return content.decode(encoding or "utf-8", errors=errors)
+
+
 @dataclass(frozen=True)
 class ProcessResult:
     """Result of executing a process which should not fail.
 
     If the process has a non-zero exit code, this will raise an exception, unlike
     FallibleProcessResult.
+
+    `source_digests` maps each of the `source_output_files` of the process which it created to a
+    digest containing only that file.
     """
 
     stdout: bytes
@@ -164,6 +558,16 @@ class ProcessResult:
     stderr: bytes
     stderr_digest: FileDigest
     output_digest: Digest
+    source_digests: FrozenDict[str, Digest] = FrozenDict()
+    # The detected or declared encodings of stdout and stderr: see `Process.output_encoding`.
+    stdout_encoding: str | None = None
+    stderr_encoding: str | None = None
+
+    def decoded_stdout(self, decoding: ProcessOutputDecoding = ProcessOutputDecoding.strict) -> str:
+        return decode_process_output(self.stdout, self.stdout_encoding, decoding)
+
+    def decoded_stderr(self, decoding: ProcessOutputDecoding = ProcessOutputDecoding.strict) -> str:
+        return decode_process_output(self.stderr, self.stderr_encoding, decoding)
 
 
 @dataclass(frozen=True)
@@ -179,6 +583,19 @@ class FallibleProcessResult:
     stderr_digest: FileDigest
     exit_code: int
     output_digest: Digest
+    source_digests: FrozenDict[str, Digest] = FrozenDict()
+    stdout_encoding: str | None = None
+    stderr_encoding: str | None = None
+    # If the process was stopped because it exceeded one of its `resource_limits` (rather than
+    # exiting of its own accord), the limit that it exceeded: `memory_limit_exceeded` or
+    # `cpu_time_limit_exceeded`.
+    exit_reason: str | None = None
+
+    def decoded_stdout(self, decoding: ProcessOutputDecoding = ProcessOutputDecoding.strict) -> str:
+        return decode_process_output(self.stdout, self.stdout_encoding, decoding)
+
+    def decoded_stderr(self, decoding: ProcessOutputDecoding = ProcessOutputDecoding.strict) -> str:
+        return decode_process_output(self.stderr, self.stderr_encoding, decoding)
 
 
 @dataclass(frozen=True)
@@ -192,6 +609,13 @@ class FallibleProcessResultWithPlatform:
     exit_code: int
     output_digest: Digest
     platform: Platform
+    source_digests: Tuple[Tuple[str, Digest], ...] = ()
+    # The details of the platform (such as its libc) that the process ran on, if it ran locally
+    # during this run of Pants, rather than being served from a cache.
+    platform_details: str | None = None
+    stdout_encoding: str | None = None
+    stderr_encoding: str | None = None
+    exit_reason: str | None = None
 
 
 class ProcessExecutionFailure(Exception):
@@ -201,22 +625,29 @@ class ProcessExecutionFailure(Exception):
     """
 
     def __init__(
-        self, exit_code: int, stdout: bytes, stderr: bytes, process_description: str
+        self,
+        exit_code: int,
+        stdout: bytes,
+        stderr: bytes,
+        process_description: str,
+        stdout_encoding: str | None = None,
+        stderr_encoding: str | None = None,
     ) -> None:
         # These are intentionally "public" members.
         self.exit_code = exit_code
         self.stdout = stdout
         self.stderr = stderr
         # NB: We don't use dedent on a single format string here because it would attempt to
-        # interpret the stdio content.
+        # interpret the stdio content. The output is decoded lossily, since failing to render the
+        # error would hide it.
         super().__init__(
             "\n".join(
                 [
                     f"Process '{process_description}' failed with exit code {exit_code}.",
                     "stdout:",
-                    stdout.decode(),
+                    decode_process_output(stdout, stdout_encoding, ProcessOutputDecoding.lossy),
                     "stderr:",
-                    stderr.decode(),
+                    decode_process_output(stderr, stderr_encoding, ProcessOutputDecoding.lossy),
                 ]
             )
         )
@@ -246,12 +677,17 @@ def fallible_to_exec_result_or_raise(
             stderr=fallible_result.stderr,
             stderr_digest=fallible_result.stderr_digest,
             output_digest=fallible_result.output_digest,
+            source_digests=fallible_result.source_digests,
+            stdout_encoding=fallible_result.stdout_encoding,
+            stderr_encoding=fallible_result.stderr_encoding,
         )
     raise ProcessExecutionFailure(
         fallible_result.exit_code,
         fallible_result.stdout,
         fallible_result.stderr,
         description.value,
+        stdout_encoding=fallible_result.stdout_encoding,
+        stderr_encoding=fallible_result.stderr_encoding,
     )
 
 
@@ -264,9 +700,44 @@ def remove_platform_information(res: FallibleProcessResultWithPlatform) -> Falli
         stderr=res.stderr,
         stderr_digest=res.stderr_digest,
         output_digest=res.output_digest,
+        source_digests=FrozenDict(res.source_digests),
+        stdout_encoding=res.stdout_encoding,
+        stderr_encoding=res.stderr_encoding,
+        exit_reason=res.exit_reason,
     )
 
 
+@dataclass(frozen=True)
+class ProcessActionDescription:
+    """The Remote Execution API protos which would be sent to a server to execute a `Process`, and
+    a human readable rendering of them and of the local sandbox for the process.
+
+    The serialized `action` and `command` may be uploaded with standalone REAPI client tools to
+    reproduce an action, and the `rendered` descriptions of a process under two configurations
+    may be diffed to see what differs between them.
+    """
+
+    action_digest: Digest
+    action: bytes
+    command_digest: Digest
+    command: bytes
+    rendered: str
+
+
+@dataclass(frozen=True)
+class PlannedProcess:
+    """A `Process` which a dry-run session would have executed.
+
+    See `--process-execution-dry-run`.
+    """
+
+    description: str
+    action_digest: Digest
+    input_digest: Digest
+    # The total size of the unique files in the `input_digest`.
+    input_bytes: int
+
+
 @dataclass(frozen=True)
 class InteractiveProcessResult:
     exit_code: int
@@ -389,6 +860,30 @@ class BinaryPathRequest:
         self.test = test
 
 
+@dataclass(frozen=True)
+class LocalBinaryPathRequest:
+    """Request to find a binary of a given name on the local machine, which is resolved natively.
+
+    Each candidate on the search path is tested as described by `BinaryPathRequest`, but the engine
+    caches the result of a test by the mtime and digest of the binary, and re-discovers binaries
+    when their directories change, rather than running processes to find and test them.
+    """
+
+    binary_name: str
+    search_path: Tuple[str, ...]
+    test_args: Tuple[str, ...] | None
+    fingerprint_stdout: bool
+
+    @classmethod
+    def from_request(cls, request: BinaryPathRequest) -> LocalBinaryPathRequest:
+        return cls(
+            binary_name=request.binary_name,
+            search_path=tuple(request.search_path),
+            test_args=request.test.args if request.test else None,
+            fingerprint_stdout=request.test.fingerprint_stdout if request.test else True,
+        )
+
+
 @frozen_after_init
 @dataclass(unsafe_hash=True)
 class BinaryPath:
@@ -494,7 +989,14 @@ async def get_bash() -> BashBinary:
 
 
 @rule
-async def find_binary(request: BinaryPathRequest) -> BinaryPaths:
+async def find_binary(request: BinaryPathRequest, global_options: GlobalOptions) -> BinaryPaths:
+    # When processes run locally, binaries are discovered natively, which avoids running processes.
+    # Otherwise, binaries must be discovered on the (remote) host which will run processes.
+    if not global_options.options.remote_execution:
+        return await Get(
+            BinaryPaths, LocalBinaryPathRequest, LocalBinaryPathRequest.from_request(request)
+        )
+
     # If we are not already locating bash, recurse to locate bash to use it as an absolute path in
     # our shebang. This avoids mixing locations that we would search for bash into the search paths
     # of the request we are servicing.
@@ -1,22 +1,33 @@
 # Copyright 2016 Pants project contributors (see CONTRIBUTORS.md).
 # Licensed under the Apache License, Version 2.0 (see LICENSE).
 
+from __future__ import annotations
+
 import os
+from pathlib import Path
 
 import pytest
 
 from pants.engine.fs import EMPTY_DIGEST, CreateDigest, Digest, DigestContents, FileContent
 from pants.engine.internals.scheduler import ExecutionError
+from pants.engine.platform import Platform
 from pants.engine.process import (
+    BinaryPath,
     BinaryPathRequest,
     BinaryPaths,
+    BinaryPathTest,
     FallibleProcessResult,
     InteractiveProcess,
+    OutputScanner,
+    OutputScannerKind,
     Process,
     ProcessCacheScope,
+    ProcessOutputDecoding,
     ProcessResult,
+    ResourceLimits,
 )
 from pants.testutil.rule_runner import QueryRule, RuleRunner
+from pants.util.collections import assert_single_element
 from pants.util.contextutil import environment_as, temporary_dir
 from pants.util.dirutil import safe_mkdir, touch
 
@@ -90,6 +101,26 @@ def test_output_digest(rule_runner: RuleRunner) -> None:
     assert digest_contents == DigestContents([FileContent("roland", b"European Burmese", False)])
 
 
+def test_source_digests(rule_runner: RuleRunner) -> None:
+    def run_process(content: str) -> ProcessResult:
+        process = Process(
+            argv=("/bin/bash", "-c", f"echo -n {content} > gen.py && echo -n {content} > other.py"),
+            description="generate sources",
+            source_output_files=("gen.py", "missing.py"),
+            output_files=("other.py",),
+        )
+        return rule_runner.request(ProcessResult, [process])
+
+    result = run_process("a")
+    assert set(result.source_digests) == {"gen.py"}
+    digest_contents = rule_runner.request(DigestContents, [result.source_digests["gen.py"]])
+    assert digest_contents == DigestContents([FileContent("gen.py", b"a", False)])
+    output_contents = rule_runner.request(DigestContents, [result.output_digest])
+    assert {fc.path for fc in output_contents} == {"gen.py", "other.py"}
+
+    assert run_process("b").source_digests["gen.py"] != result.source_digests["gen.py"]
+
+
 def test_timeout(rule_runner: RuleRunner) -> None:
     process = Process(
         argv=("/bin/bash", "-c", "/bin/sleep 0.2; /bin/echo -n 'European Burmese'"),
@@ -112,6 +143,65 @@ def test_failing_process(rule_runner: RuleRunner) -> None:
     assert "Process 'failure' failed with exit code 1." in str(exc.value)
 
 
+def test_output_encoding(rule_runner: RuleRunner) -> None:
+    def run_process(output_encoding: str | None = None) -> FallibleProcessResult:
+        # Prints `café` in latin-1 to stdout, and in UTF-8 to stderr.
+        process = Process(
+            argv=("/bin/bash", "-c", "printf 'caf\\xe9'; printf 'caf\\xc3\\xa9' >&2; exit 1"),
+            description="latin-1",
+            output_encoding=output_encoding,
+        )
+        return rule_runner.request(FallibleProcessResult, [process])
+
+    result = run_process()
+    assert result.stdout == b"caf\xe9"
+    assert result.stdout_encoding is None
+    assert result.stderr_encoding == "utf-8"
+    assert result.decoded_stderr() == "café"
+    with pytest.raises(UnicodeDecodeError):
+        result.decoded_stdout()
+    assert result.decoded_stdout(ProcessOutputDecoding.lossy) == "caf\ufffd"
+
+    # A failure still renders undecodable output.
+    with pytest.raises(ExecutionError) as exc:
+        rule_runner.request(
+            ProcessResult,
+            [Process(argv=("/bin/bash", "-c", "printf 'caf\\xe9'; exit 1"), description="fail")],
+        )
+    assert "caf\ufffd" in str(exc.value)
+
+    result = run_process(output_encoding="latin-1")
+    assert result.stdout_encoding == "latin-1"
+    assert result.decoded_stdout() == "café"
+
+    with pytest.raises(ValueError):
+        Process(argv=("/bin/true",), description="bad encoding", output_encoding="not-a-codec")
+
+
+def test_output_scanners(rule_runner: RuleRunner) -> None:
+    scanners = [
+        OutputScanner("errors", OutputScannerKind.LINES, "^error"),
+        OutputScanner("diagnostics", OutputScannerKind.DIAGNOSTICS),
+    ]
+    process = Process(
+        argv=("/bin/bash", "-c", "echo 'error: bad'"),
+        description="scanned",
+        output_scanners=scanners,
+    )
+    # Scanners do not affect the result of the process.
+    result = rule_runner.request(ProcessResult, [process])
+    assert result.stdout == b"error: bad\n"
+
+    invalid = Process(
+        argv=("/bin/bash", "-c", "true"),
+        description="invalid scanner",
+        output_scanners=[OutputScanner("invalid", OutputScannerKind.COUNT, "(")],
+    )
+    with pytest.raises(ExecutionError) as exc:
+        rule_runner.request(ProcessResult, [invalid])
+    assert "Invalid pattern for output scanner `invalid`" in str(exc.value)
+
+
 def test_cache_scope_always(rule_runner: RuleRunner) -> None:
     # Should not re-run on failure, even in a new Session.
     process = Process(
@@ -183,6 +273,35 @@ def test_cache_scope_never(rule_runner: RuleRunner) -> None:
     assert result_one.stdout != result_two.stdout
 
 
+def test_dry_run(rule_runner: RuleRunner, tmp_path: Path) -> None:
+    marker = tmp_path / "marker"
+    input_digest = rule_runner.request(Digest, [CreateDigest([FileContent("input.txt", b"12345")])])
+    process = Process(
+        argv=("/bin/bash", "-c", f"echo ran >> {marker}"),
+        input_digest=input_digest,
+        cache_scope=ProcessCacheScope.ALWAYS,
+        description="append to a marker",
+    )
+
+    # A dry run reports the process, rather than running it.
+    rule_runner.scheduler = rule_runner.scheduler.scheduler.new_session("dry run", dry_run=True)
+    result = rule_runner.request(FallibleProcessResult, [process])
+    assert result.exit_code == 125
+    assert b"Dry run: `append to a marker` was not run." in result.stderr
+    assert not marker.exists()
+    planned_process = assert_single_element(rule_runner.scheduler.planned_processes())
+    assert planned_process.description == "append to a marker"
+    assert planned_process.input_digest == input_digest
+    assert planned_process.input_bytes == 5
+
+    # And nothing is cached for it, so the process runs in a later session.
+    rule_runner.new_session("real run")
+    result = rule_runner.request(FallibleProcessResult, [process])
+    assert result.exit_code == 0
+    assert marker.read_text() == "ran\n"
+    assert rule_runner.scheduler.planned_processes() == ()
+
+
 # TODO: Move to fs_test.py.
 def test_create_files(rule_runner: RuleRunner) -> None:
     files = [FileContent("a.txt", b"hello"), FileContent("somedir/b.txt", b"goodbye")]
@@ -206,6 +325,122 @@ def test_interactive_process_cannot_have_input_files_and_workspace() -> None:
         InteractiveProcess(argv=["/bin/echo"], input_digest=mock_digest, run_in_workspace=True)
 
 
+def test_tags(rule_runner: RuleRunner) -> None:
+    tagged = Process(
+        argv=("/bin/echo", "hello"),
+        description="",
+        tags={"goal": "test", "target": "src/python/foo:tests"},
+    )
+    # Tags are not part of the cache key of a process.
+    assert tagged == Process(argv=("/bin/echo", "hello"), description="")
+    result = rule_runner.request(ProcessResult, [tagged])
+    assert result.stdout == b"hello\n"
+
+
+def test_allocate_tty(rule_runner: RuleRunner) -> None:
+    def run_process(allocate_tty: bool) -> ProcessResult:
+        process = Process(
+            argv=("/bin/bash", "-c", "[ -t 1 ] && echo tty ; echo >&2 err"),
+            description="",
+            allocate_tty=allocate_tty,
+        )
+        return rule_runner.request(ProcessResult, [process])
+
+    result = run_process(allocate_tty=True)
+    assert result.stdout == b"tty\nerr\n"
+    assert result.stderr == b""
+
+    result = run_process(allocate_tty=False)
+    assert result.stdout == b""
+    assert result.stderr == b"err\n"
+
+    with pytest.raises(ValueError):
+        Process(
+            argv=("/bin/cat",),
+            description="",
+            allocate_tty=True,
+            pipe_from_argv=("/bin/echo", "hello"),
+        )
+
+
+def test_resource_limits(rule_runner: RuleRunner) -> None:
+    process = Process(
+        argv=("/bin/bash", "-c", "while true; do :; done"),
+        description="spin",
+        resource_limits=ResourceLimits(cpu_seconds=1),
+    )
+    result = rule_runner.request(FallibleProcessResult, [process])
+    assert result.exit_code != 0
+    assert result.exit_reason == "cpu_time_limit_exceeded"
+    assert b"Exceeded the CPU time limit of 1 seconds" in result.stderr
+
+    result = rule_runner.request(
+        FallibleProcessResult, [Process(argv=("/bin/echo", "hello"), description="")]
+    )
+    assert result.exit_reason is None
+
+    with pytest.raises(ValueError):
+        ResourceLimits(nofile=0)
+
+
+def test_description_fields() -> None:
+    process = Process(
+        argv=("/bin/flake8",),
+        description="{verb} {targets} with {tool}",
+        tool="flake8",
+        verb="Linting",
+        target_count=3,
+    )
+    assert process.description == "Linting 3 targets with flake8"
+    assert (
+        Process(
+            argv=("/bin/flake8",), description="{tool}: {targets} ({target_count})", target_count=1
+        ).description
+        == "unknown: 1 target (1)"
+    )
+    # Other braces are left alone, and a description without structured fields is not a template.
+    process = Process(argv=("/bin/flake8",), description="{tool} {config} {verb", tool="flake8")
+    assert process.description == "flake8 {config} {verb"
+    assert Process(argv=("/bin/flake8",), description="{tool}").description == "{tool}"
+
+
+def test_container_image() -> None:
+    image = f"python@sha256:{'0' * 64}"
+    process = Process(argv=("/usr/bin/python3",), description="", container_image=image)
+    assert process.container_image == image
+    assert process != Process(argv=("/usr/bin/python3",), description="")
+
+    with pytest.raises(ValueError):
+        Process(argv=("/usr/bin/python3",), description="", container_image="")
+    # A tag may refer to a different image over time.
+    with pytest.raises(ValueError, match="pinned by its digest"):
+        Process(argv=("/usr/bin/python3",), description="", container_image="python:3.9")
+    with pytest.raises(ValueError):
+        Process(
+            argv=("/usr/bin/python3",),
+            description="",
+            container_image=image,
+            resource_limits=ResourceLimits(cpu_seconds=1),
+        )
+
+
+def test_output_platform(rule_runner: RuleRunner) -> None:
+    for process in (
+        Process(argv=("/bin/echo", "hello"), description="", platform_independent=True),
+        Process(argv=("/bin/echo", "hello"), description="", target_platform=Platform.linux),
+    ):
+        result = rule_runner.request(ProcessResult, [process])
+        assert result.stdout == b"hello\n"
+
+    with pytest.raises(ValueError):
+        Process(
+            argv=("/bin/echo",),
+            description="",
+            platform_independent=True,
+            target_platform=Platform.linux,
+        )
+
+
 def test_find_binary_non_existent(rule_runner: RuleRunner) -> None:
     with temporary_dir() as tmpdir:
         search_path = [tmpdir]
@@ -233,3 +468,31 @@ def test_find_binary_on_path_without_bash(rule_runner: RuleRunner) -> None:
         assert os.path.exists(os.path.join(binary_dir_abs, binary_name))
         assert binary_paths.first_path is not None
         assert binary_paths.first_path.path == binary_path_abs
+
+
+def test_find_binary_retests_changed_binaries(rule_runner: RuleRunner) -> None:
+    with temporary_dir() as tmpdir:
+        binary_path_abs = os.path.join(tmpdir, "mybin")
+
+        def write_binary(version: str) -> None:
+            with open(binary_path_abs, "w") as f:
+                f.write(f"#!/bin/sh\necho {version}\n")
+            os.chmod(binary_path_abs, 0o755)
+
+        def find_binary() -> BinaryPath | None:
+            request = BinaryPathRequest(
+                binary_name="mybin", search_path=[tmpdir], test=BinaryPathTest(["--version"])
+            )
+            return rule_runner.request(BinaryPaths, [request]).first_path
+
+        write_binary("1.0")
+        assert find_binary() == BinaryPath.fingerprinted(binary_path_abs, b"1.0\n")
+
+        write_binary("10.0")
+        rule_runner.scheduler.invalidate_files([tmpdir])
+        assert find_binary() == BinaryPath.fingerprinted(binary_path_abs, b"10.0\n")
+
+        # A binary which fails its test is not found.
+        write_binary("1.0; exit 1")
+        rule_runner.scheduler.invalidate_files([tmpdir])
+        assert find_binary() is None
@@ -110,6 +110,7 @@ def _make_rule(
     canonical_name: str,
     desc: Optional[str],
     level: LogLevel,
+    timeout: Optional[float] = None,
 ) -> Callable[[Callable], Callable]:
     """A @decorator that declares that a particular static function may be used as a TaskRule.
 
@@ -122,6 +123,8 @@ def _make_rule(
                             decorated function.
     :param cacheable: Whether the results of executing the Rule should be cached as keyed by all of
                       its inputs.
+    :param timeout: An optional wall-clock timeout in seconds for the execution of the Rule, which
+                    is enforced by the engine while the Rule is awaiting a `Get`.
     """
 
     is_goal_cls = issubclass(return_type, Goal)
@@ -189,6 +192,7 @@ def _make_rule(
             desc=desc,
             level=level,
             cacheable=cacheable,
+            timeout=timeout,
         )
 
         return func
@@ -231,7 +235,7 @@ def _ensure_type_annotation(
     return type_annotation
 
 
-PUBLIC_RULE_DECORATOR_ARGUMENTS = {"canonical_name", "desc", "level"}
+PUBLIC_RULE_DECORATOR_ARGUMENTS = {"canonical_name", "desc", "level", "timeout"}
 # We don't want @rule-writers to use 'rule_type' or 'cacheable' as kwargs directly,
 # but rather set them implicitly based on the rule annotation.
 # So we leave it out of PUBLIC_RULE_DECORATOR_ARGUMENTS.
@@ -291,6 +295,15 @@ def rule_decorator(func, **kwargs) -> Callable:
             f"argument, but got: {effective_level}"
         )
 
+    timeout = kwargs.get("timeout")
+    if timeout is not None and (
+        isinstance(timeout, bool) or not isinstance(timeout, (int, float)) or timeout <= 0
+    ):
+        raise ValueError(
+            "Expected to receive a positive number of seconds for the timeout argument, but got: "
+            f"{timeout}"
+        )
+
     return _make_rule(
         rule_type,
         return_type,
@@ -299,6 +312,7 @@ def rule_decorator(func, **kwargs) -> Callable:
         canonical_name=effective_name,
         desc=effective_desc,
         level=effective_level,
+        timeout=timeout,
     )(func)
 
 
@@ -403,6 +417,7 @@ class TaskRule(Rule):
     canonical_name: str
     desc: Optional[str]
     level: LogLevel
+    timeout: Optional[float]
 
     def __init__(
         self,
@@ -414,6 +429,7 @@ class TaskRule(Rule):
         desc: Optional[str] = None,
         level: LogLevel = LogLevel.TRACE,
         cacheable: bool = True,
+        timeout: Optional[float] = None,
     ) -> None:
         self._output_type = output_type
         self.input_selectors = tuple(input_selectors)
@@ -423,6 +439,7 @@ class TaskRule(Rule):
         self.canonical_name = canonical_name
         self.desc = desc
         self.level = level
+        self.timeout = None if timeout is None else float(timeout)
 
     def __str__(self):
         return "(name={}, {}, {!r}, {}, gets={})".format(
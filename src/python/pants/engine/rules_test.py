@@ -449,6 +449,25 @@ class RuleArgumentAnnotationTest(unittest.TestCase):
         self.assertEqual(another_named_rule.rule.desc, "Human readable desc")
         self.assertEqual(another_named_rule.rule.level, LogLevel.TRACE)
 
+    def test_rule_timeout(self):
+        @rule(timeout=30)
+        def a_timed_rule(a: int, b: str) -> bool:
+            return False
+
+        self.assertEqual(a_timed_rule.rule.timeout, 30.0)
+
+        @rule
+        def an_untimed_rule(a: int, b: str) -> bool:
+            return False
+
+        self.assertIsNone(an_untimed_rule.rule.timeout)
+
+        with self.assertRaises(ValueError):
+
+            @rule(timeout=0)
+            def an_invalid_rule(a: int, b: str) -> bool:
+                return False
+
     def test_bogus_rules(self):
         with self.assertRaises(UnrecognizedRuleArgument):
 
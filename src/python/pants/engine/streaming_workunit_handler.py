@@ -5,6 +5,7 @@ from __future__ import annotations
 
 import logging
 import threading
+import time
 from abc import ABC, abstractmethod
 from dataclasses import dataclass
 from typing import Any, Callable, Iterable, Sequence, Tuple
@@ -19,6 +20,7 @@ from pants.engine.rules import Get, MultiGet, QueryRule, collect_rules, rule
 from pants.engine.target import Targets
 from pants.engine.unions import UnionMembership, union
 from pants.goal.run_tracker import RunTracker
+from pants.option.global_options import StreamingWorkunitsOverflowPolicy
 from pants.option.options_bootstrapper import OptionsBootstrapper
 from pants.util.logging import LogLevel
 
@@ -69,11 +71,20 @@ class StreamingWorkunitContext:
         ByteStore, if it exists, has a copy of the files fingerprinted by each Digest."""
         return self._scheduler.ensure_remote_has_recursive(digests)
 
+    def expand_digest(self, displayed: str) -> Digest:
+        """Expand a (possibly abbreviated) digest as displayed in workunits and logs into a full
+        Digest."""
+        return self._scheduler.expand_digest(displayed)
+
     def get_observation_histograms(self):
         """Invoke the internal get_observation_histograms function, which serializes histograms
         generated from Pants-internal observation metrics observed during the current run of Pants.
 
-        These metrics are useful for debugging Pants internals.
+        These metrics are useful for debugging Pants internals. For example, the latencies (in
+        microseconds) of each kind of remote RPC are recorded as `remote_*_time` histograms.
+
+        The result is a dict with a `histograms` key of encoded histograms by name, and a
+        `percentiles` key of the p25, p50, p75, p90, p95 and p99 values of each histogram by name.
         """
         return self._scheduler.get_observation_histograms()
 
@@ -184,6 +195,8 @@ class StreamingWorkunitHandler:
         report_interval_seconds: float,
         pantsd: bool,
         max_workunit_verbosity: LogLevel = LogLevel.TRACE,
+        max_queued_workunits: int | None = None,
+        overflow_policy: StreamingWorkunitsOverflowPolicy = StreamingWorkunitsOverflowPolicy.spill,
     ) -> None:
         scheduler = scheduler.isolated_shallow_clone()
         self.callbacks = callbacks
@@ -203,6 +216,8 @@ class StreamingWorkunitHandler:
                 #  setting.
                 max_workunit_verbosity=max_workunit_verbosity,
                 pantsd=pantsd,
+                max_queued_workunits=max_queued_workunits,
+                overflow_policy=overflow_policy,
             )
             if callbacks
             else None
@@ -230,6 +245,8 @@ class _InnerHandler(threading.Thread):
         report_interval: float,
         max_workunit_verbosity: LogLevel,
         pantsd: bool,
+        max_queued_workunits: int | None,
+        overflow_policy: StreamingWorkunitsOverflowPolicy,
     ) -> None:
         super().__init__(daemon=True)
         self.scheduler = scheduler
@@ -238,6 +255,8 @@ class _InnerHandler(threading.Thread):
         self.report_interval = report_interval
         self.callbacks = callbacks
         self.max_workunit_verbosity = max_workunit_verbosity
+        self.max_queued_workunits = max_queued_workunits
+        self.overflow_policy = overflow_policy
         # TODO: Have a thread per callback so that some callbacks can always finish async even
         #  if others must be finished synchronously.
         self.block_until_complete = not pantsd or any(
@@ -250,12 +269,25 @@ class _InnerHandler(threading.Thread):
     def poll_workunits(self, *, finished: bool) -> None:
         workunits = self.scheduler.poll_workunits(self.max_workunit_verbosity)
         for callback in self.callbacks:
+            start = time.monotonic()
             callback(
                 started_workunits=workunits["started"],
                 completed_workunits=workunits["completed"],
                 finished=finished,
                 context=self.context,
             )
+            self.scheduler.record_streaming_workunit_callback_time(
+                int((time.monotonic() - start) * 1_000_000)
+            )
+
+    def start(self) -> None:
+        # NB: The queue is only bounded while this thread is polling it, since the `block` policy
+        # would otherwise block the engine indefinitely.
+        if self.max_queued_workunits is not None:
+            self.scheduler.set_streaming_workunits_queue_bounds(
+                self.max_queued_workunits, self.overflow_policy.value
+            )
+        super().start()
 
     def run(self) -> None:
         # First, set the thread's logging destination to the parent thread's, meaning the console.
@@ -266,6 +298,7 @@ class _InnerHandler(threading.Thread):
         else:
             # Make one final call. Note that this may run after the Pants run has already
             # completed, depending on whether the thread was joined or not.
+            self.scheduler.set_streaming_workunits_queue_bounds(None, self.overflow_policy.value)
             self.poll_workunits(finished=True)
 
     def end(self) -> None:
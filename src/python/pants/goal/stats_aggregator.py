@@ -36,8 +36,9 @@ class StatsAggregatorSubsystem(Subsystem):
             help=(
                 "At the end of the Pants run, log all counter metrics and summaries of "
                 "observation histograms, e.g. the number of cache hits and the time saved by "
-                "caching.\n\nFor histogram summaries to work, you must add `hdrhistogram` to "
-                "`[GLOBAL].plugins`."
+                "caching.\n\nFor full histogram summaries, you must add `hdrhistogram` to "
+                "`[GLOBAL].plugins`. Otherwise, only percentiles are logged, including the "
+                "latencies of each kind of remote RPC, e.g. `remote_store_read_blob_time`."
             ),
         )
 
@@ -89,15 +90,24 @@ class StatsAggregatorCallback(WorkunitsCallback):
         )
         logger.info(f"Counters:\n{counter_lines}")
 
-        if not self.has_histogram_module:
-            return
-        from hdrh.histogram import HdrHistogram
-
-        histograms = context.get_observation_histograms()["histograms"]
+        observations = context.get_observation_histograms()
+        histograms = observations["histograms"]
         if not histograms:
             logger.info("No observation histogram were recorded.")
             return
 
+        if not self.has_histogram_module:
+            logger.info("Observation histogram percentiles:")
+            for name, percentiles in sorted(observations["percentiles"].items()):
+                percentile_to_vals = "\n".join(
+                    f"  p{percentile:g}: {value}" for percentile, value in percentiles.items()
+                )
+                logger.info(
+                    f"Percentiles of `{name}` observation histogram:\n{percentile_to_vals}"
+                )
+            return
+        from hdrh.histogram import HdrHistogram
+
         logger.info("Observation histogram summaries:")
         for name, encoded_histogram in histograms.items():
             # Note: The Python library for HDR Histogram will only decode compressed histograms
@@ -15,11 +15,16 @@ from pants.base.specs import Specs
 from pants.build_graph.build_configuration import BuildConfiguration
 from pants.engine import desktop, environment, fs, platform, process
 from pants.engine.console import Console
+from pants.engine.doctor import EngineDoctor
 from pants.engine.environment import CompleteEnvironment
 from pants.engine.fs import PathGlobs, Snapshot, Workspace
 from pants.engine.goal import Goal
 from pants.engine.internals import build_files, graph, options_parsing
-from pants.engine.internals.native_engine import PyExecutor, PySessionCancellationLatch
+from pants.engine.internals.native_engine import (
+    PyExecutor,
+    PyFakeProcesses,
+    PySessionCancellationLatch,
+)
 from pants.engine.internals.parser import Parser
 from pants.engine.internals.scheduler import Scheduler, SchedulerSession
 from pants.engine.internals.selectors import Params
@@ -35,6 +40,7 @@ from pants.option.global_options import (
     ExecutionOptions,
     GlobalOptions,
     LocalStoreOptions,
+    SessionPriority,
 )
 from pants.option.options_bootstrapper import OptionsBootstrapper
 from pants.option.subsystem import Subsystem
@@ -58,12 +64,20 @@ class GraphScheduler:
         use_colors=True,
         session_values: Optional[SessionValues] = None,
         cancellation_latch: Optional[PySessionCancellationLatch] = None,
+        notification_threshold: Optional[float] = None,
+        notification_command: Tuple[str, ...] = (),
+        dry_run: bool = False,
+        priority: SessionPriority = SessionPriority.interactive,
     ) -> GraphSession:
         session = self.scheduler.new_session(
             build_id,
             dynamic_ui,
             session_values=session_values,
             cancellation_latch=cancellation_latch,
+            notification_threshold=notification_threshold,
+            notification_command=notification_command,
+            dry_run=dry_run,
+            priority=priority,
         )
         console = Console(use_colors=use_colors, session=session if dynamic_ui else None)
         return GraphSession(session, console, self.goal_map)
@@ -78,7 +92,13 @@ class GraphSession:
     goal_map: Any
 
     # NB: Keep this in sync with the method `run_goal_rules`.
-    goal_param_types: ClassVar[Tuple[Type, ...]] = (Specs, Console, InteractiveRunner, Workspace)
+    goal_param_types: ClassVar[Tuple[Type, ...]] = (
+        Specs,
+        Console,
+        InteractiveRunner,
+        Workspace,
+        EngineDoctor,
+    )
 
     def goal_consumed_subsystem_scopes(self, goal_name: str) -> Tuple[str, ...]:
         """Return the scopes of subsystems that could be consumed while running the given goal."""
@@ -117,6 +137,7 @@ class GraphSession:
 
         workspace = Workspace(self.scheduler_session)
         interactive_runner = InteractiveRunner(self.scheduler_session)
+        engine_doctor = EngineDoctor(self.scheduler_session)
 
         for goal in goals:
             goal_product = self.goal_map[goal]
@@ -129,7 +150,7 @@ class GraphSession:
             if not is_implemented:
                 continue
             # NB: Keep this in sync with the property `goal_param_types`.
-            params = Params(specs, self.console, workspace, interactive_runner)
+            params = Params(specs, self.console, workspace, interactive_runner, engine_doctor)
             logger.debug(f"requesting {goal_product} to satisfy execution of `{goal}` goal")
             try:
                 exit_code = self.scheduler_session.run_goal_rule(
@@ -187,6 +208,7 @@ class EngineInitializer:
             executor=executor,
             pants_ignore_patterns=GlobalOptions.compute_pants_ignore(build_root, bootstrap_options),
             use_gitignore=bootstrap_options.pants_ignore_use_gitignore,
+            watch_filesystem=bootstrap_options.watch_filesystem,
             local_store_options=local_store_options,
             local_execution_root_dir=bootstrap_options.local_execution_root_dir,
             named_caches_dir=bootstrap_options.named_caches_dir,
@@ -211,6 +233,8 @@ class EngineInitializer:
         build_root: Optional[str] = None,
         include_trace_on_error: bool = True,
         native_engine_visualize_to: Optional[str] = None,
+        watch_filesystem: bool = True,
+        fake_processes: PyFakeProcesses | None = None,
     ) -> GraphScheduler:
         build_root = build_root or get_buildroot()
 
@@ -285,6 +309,7 @@ class EngineInitializer:
         scheduler = Scheduler(
             ignore_patterns=pants_ignore_patterns,
             use_gitignore=use_gitignore,
+            watch_filesystem=watch_filesystem,
             build_root=build_root,
             local_execution_root_dir=ensure_absolute_path(local_execution_root_dir),
             named_caches_dir=ensure_absolute_path(named_caches_dir),
@@ -296,6 +321,7 @@ class EngineInitializer:
             local_store_options=local_store_options,
             include_trace_on_error=include_trace_on_error,
             visualize_to_dir=native_engine_visualize_to,
+            fake_processes=fake_processes,
         )
 
         return GraphScheduler(scheduler, goal_map)
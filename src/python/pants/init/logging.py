@@ -130,6 +130,7 @@ def initialize_stdio(global_bootstrap_options: OptionValueContainer) -> Iterator
     log_show_rust_3rdparty = global_bootstrap_options.log_show_rust_3rdparty
     use_color = global_bootstrap_options.colors
     show_target = global_bootstrap_options.show_log_target
+    log_by_workunit = global_bootstrap_options.log_by_workunit
     log_levels_by_target = _get_log_levels_by_target(global_bootstrap_options)
     message_regex_filters = global_bootstrap_options.ignore_pants_warnings
     print_stacktrace = global_bootstrap_options.print_stacktrace
@@ -146,10 +147,14 @@ def initialize_stdio(global_bootstrap_options: OptionValueContainer) -> Iterator
             log_show_rust_3rdparty,
             use_color,
             show_target,
+            log_by_workunit,
             {k: v.level for k, v in log_levels_by_target.items()},
             tuple(message_regex_filters),
+            tuple(global_bootstrap_options.redact_env_vars),
+            tuple(global_bootstrap_options.redact_patterns),
             log_path,
         )
+        native_engine.set_display_full_digests(global_bootstrap_options.full_digests)
         sys.stdin = TextIOWrapper(
             BufferedReader(raw_stdin),
             # NB: We set the default encoding explicitly to bypass logic in the TextIOWrapper
@@ -84,6 +84,61 @@ class OwnersNotFoundBehavior(Enum):
         return GlobMatchErrorBehavior(self.value)
 
 
+@enum.unique
+class KeepSandboxes(Enum):
+    """An enum for the global option `keep_sandboxes`.
+
+    Prefer to use this rather than requesting `GlobalOptions` for more precise invalidation.
+    """
+
+    always = "always"
+    on_failure = "on_failure"
+    never = "never"
+
+
+@enum.unique
+class SessionPriority(Enum):
+    """The priority of a run, which orders its processes relative to those of concurrent runs."""
+
+    interactive = "interactive"
+    batch = "batch"
+    background = "background"
+
+
+@enum.unique
+class StreamingWorkunitsOverflowPolicy(Enum):
+    """What to do with workunits when streaming workunit handlers fall behind the engine."""
+
+    block = "block"
+    drop = "drop"
+    spill = "spill"
+
+
+@enum.unique
+class RemoteCachePartitionStrategy(Enum):
+    """How the global option `remote_cache_partition` is applied to remote cache requests."""
+
+    action_salt = "action_salt"
+    instance_name = "instance_name"
+
+
+@enum.unique
+class RemoteStoreDigestFunction(Enum):
+    """The hash function which the remote store uses to address files."""
+
+    sha256 = "sha256"
+    sha1 = "sha1"
+
+
+@enum.unique
+class RemoteExecutionSpeculation(Enum):
+    """Where straggling remote executions are speculatively re-executed, if at all."""
+
+    none = "none"
+    remote = "remote"
+    local = "local"
+
+
 @enum.unique
 class AuthPluginState(Enum):
     OK = "ok"
@@ -120,31 +175,69 @@ class ExecutionOptions:
     allowing Subsystems to be consumed before the Scheduler has been created).
     """
 
+    offline: bool
+
     remote_execution: bool
     remote_cache_read: bool
     remote_cache_write: bool
+    remote_cache_write_strict: bool
+    remote_cache_backfill: bool
+    remote_cache_backfill_interval_millis: int
 
     remote_instance_name: str | None
     remote_ca_certs_path: str | None
 
     process_execution_local_cache: bool
     process_execution_local_cleanup: bool
+    keep_sandboxes: KeepSandboxes
+    keep_sandboxes_max_count: int | None
+    keep_sandboxes_max_age_secs: int | None
+    keep_sandboxes_max_bytes: int | None
     process_execution_local_parallelism: int
     process_execution_remote_parallelism: int
     process_execution_cache_namespace: str | None
+    process_execution_max_output_bytes: int | None
+    process_execution_max_run_output_bytes: int | None
+    process_execution_audit_file_accesses: bool
+    process_execution_fail_on_external_writes: bool
+    process_execution_local_max_sandboxes: int | None
+    process_execution_local_batch_size: int
+    process_execution_local_batch_window_millis: int
+    process_execution_local_platform_properties: Dict[str, str]
+    process_execution_docker_binary: str
+
+    redact_env_vars: List[str]
+    redact_patterns: List[str]
 
     remote_store_address: str | None
     remote_store_headers: dict[str, str]
     remote_store_chunk_bytes: Any
+    remote_store_chunk_bytes_min: int
+    remote_store_chunk_bytes_max: int
     remote_store_chunk_upload_timeout_seconds: int
     remote_store_rpc_retries: int
+    remote_store_rpc_qps: int | None
+    remote_store_bandwidth_bytes_per_sec: int | None
+    remote_store_read_concurrency: int | None
+    remote_store_max_inflight_bytes: int | None
+    remote_store_encryption_key_path: str | None
+    remote_store_mirrors: List[str]
+    remote_store_digest_function: RemoteStoreDigestFunction
 
     remote_cache_eager_fetch: bool
+    remote_cache_partition: str | None
+    remote_cache_partition_strategy: RemoteCachePartitionStrategy
 
     remote_execution_address: str | None
     remote_execution_extra_platform_properties: List[str]
     remote_execution_headers: Dict[str, str]
     remote_execution_overall_deadline_secs: int
+    remote_execution_rpc_qps: int | None
+    remote_execution_pipeline_uploads: bool
+    remote_execution_speculation: RemoteExecutionSpeculation
+    remote_execution_speculation_runtime_multiplier: float
+    remote_execution_speculation_min_delay_secs: int
+    remote_execution_speculation_max_concurrent: int
 
     @classmethod
     def from_options(
@@ -159,6 +252,10 @@ class ExecutionOptions:
         remote_execution = cast(bool, bootstrap_options.remote_execution) and not local_only
         remote_cache_read = cast(bool, bootstrap_options.remote_cache_read) and not local_only
         remote_cache_write = cast(bool, bootstrap_options.remote_cache_write) and not local_only
+        remote_cache_backfill = (
+            cast(bool, bootstrap_options.remote_cache_backfill) and not local_only
+        )
+        offline = cast(bool, bootstrap_options.offline)
         if not local_only and bootstrap_options.remote_oauth_bearer_token_path:
             oauth_token = (
                 Path(bootstrap_options.remote_oauth_bearer_token_path).resolve().read_text().strip()
@@ -173,8 +270,14 @@ class ExecutionOptions:
             remote_store_headers.update(token_header)
         if (
             not local_only
+            and not offline
             and bootstrap_options.remote_auth_plugin
-            and (remote_execution or remote_cache_read or remote_cache_write)
+            and (
+                remote_execution
+                or remote_cache_read
+                or remote_cache_write
+                or remote_cache_backfill
+            )
         ):
             if ":" not in bootstrap_options.remote_auth_plugin:
                 raise OptionsError(
@@ -203,6 +306,7 @@ class ExecutionOptions:
                 remote_execution = False
                 remote_cache_read = False
                 remote_cache_write = False
+                remote_cache_backfill = False
             else:
                 logger.debug(
                     "`--remote-auth-plugin` succeeded. Remote caching/execution will be attempted."
@@ -236,10 +340,16 @@ class ExecutionOptions:
         )
 
         return cls(
+            offline=offline,
             # Remote execution strategy.
             remote_execution=remote_execution,
             remote_cache_read=remote_cache_read,
             remote_cache_write=remote_cache_write,
+            remote_cache_write_strict=bootstrap_options.remote_cache_write_strict,
+            remote_cache_backfill=remote_cache_backfill,
+            remote_cache_backfill_interval_millis=(
+                bootstrap_options.remote_cache_backfill_interval_millis
+            ),
             # General remote setup.
             remote_instance_name=remote_instance_name,
             remote_ca_certs_path=bootstrap_options.remote_ca_certs_path,
@@ -248,20 +358,74 @@ class ExecutionOptions:
             process_execution_local_parallelism=bootstrap_options.process_execution_local_parallelism,
             process_execution_remote_parallelism=bootstrap_options.process_execution_remote_parallelism,
             process_execution_local_cleanup=bootstrap_options.process_execution_local_cleanup,
+            keep_sandboxes=(
+                bootstrap_options.keep_sandboxes
+                if bootstrap_options.process_execution_local_cleanup
+                else KeepSandboxes.always
+            ),
+            keep_sandboxes_max_count=bootstrap_options.keep_sandboxes_max_count,
+            keep_sandboxes_max_age_secs=bootstrap_options.keep_sandboxes_max_age_secs,
+            keep_sandboxes_max_bytes=bootstrap_options.keep_sandboxes_max_bytes,
             process_execution_cache_namespace=bootstrap_options.process_execution_cache_namespace,
+            process_execution_max_output_bytes=bootstrap_options.process_execution_max_output_bytes,
+            process_execution_max_run_output_bytes=(
+                bootstrap_options.process_execution_max_run_output_bytes
+            ),
+            process_execution_audit_file_accesses=(
+                bootstrap_options.process_execution_audit_file_accesses
+            ),
+            process_execution_fail_on_external_writes=(
+                bootstrap_options.process_execution_fail_on_external_writes
+            ),
+            process_execution_local_max_sandboxes=(
+                bootstrap_options.process_execution_local_max_sandboxes
+            ),
+            process_execution_local_batch_size=bootstrap_options.process_execution_local_batch_size,
+            process_execution_local_batch_window_millis=(
+                bootstrap_options.process_execution_local_batch_window_millis
+            ),
+            process_execution_local_platform_properties=(
+                bootstrap_options.process_execution_local_platform_properties
+            ),
+            process_execution_docker_binary=bootstrap_options.process_execution_docker_binary,
+            redact_env_vars=bootstrap_options.redact_env_vars,
+            redact_patterns=bootstrap_options.redact_patterns,
             # Remote store setup.
             remote_store_address=remote_store_address,
             remote_store_headers=remote_store_headers,
             remote_store_chunk_bytes=bootstrap_options.remote_store_chunk_bytes,
+            remote_store_chunk_bytes_min=bootstrap_options.remote_store_chunk_bytes_min,
+            remote_store_chunk_bytes_max=bootstrap_options.remote_store_chunk_bytes_max,
             remote_store_chunk_upload_timeout_seconds=bootstrap_options.remote_store_chunk_upload_timeout_seconds,
             remote_store_rpc_retries=bootstrap_options.remote_store_rpc_retries,
+            remote_store_rpc_qps=bootstrap_options.remote_store_rpc_qps,
+            remote_store_bandwidth_bytes_per_sec=bootstrap_options.remote_store_bandwidth_bytes_per_sec,
+            remote_store_read_concurrency=bootstrap_options.remote_store_read_concurrency,
+            remote_store_max_inflight_bytes=bootstrap_options.remote_store_max_inflight_bytes,
+            remote_store_encryption_key_path=bootstrap_options.remote_store_encryption_key_path,
+            remote_store_mirrors=bootstrap_options.remote_store_mirrors,
+            remote_store_digest_function=bootstrap_options.remote_store_digest_function,
             # Remote cache setup.
             remote_cache_eager_fetch=bootstrap_options.remote_cache_eager_fetch,
+            remote_cache_partition=bootstrap_options.remote_cache_partition,
+            remote_cache_partition_strategy=bootstrap_options.remote_cache_partition_strategy,
             # Remote execution setup.
             remote_execution_address=remote_execution_address,
             remote_execution_extra_platform_properties=bootstrap_options.remote_execution_extra_platform_properties,
             remote_execution_headers=remote_execution_headers,
             remote_execution_overall_deadline_secs=bootstrap_options.remote_execution_overall_deadline_secs,
+            remote_execution_rpc_qps=bootstrap_options.remote_execution_rpc_qps,
+            remote_execution_pipeline_uploads=bootstrap_options.remote_execution_pipeline_uploads,
+            remote_execution_speculation=bootstrap_options.remote_execution_speculation,
+            remote_execution_speculation_runtime_multiplier=(
+                bootstrap_options.remote_execution_speculation_runtime_multiplier
+            ),
+            remote_execution_speculation_min_delay_secs=(
+                bootstrap_options.remote_execution_speculation_min_delay_secs
+            ),
+            remote_execution_speculation_max_concurrent=(
+                bootstrap_options.remote_execution_speculation_max_concurrent
+            ),
         )
 
 
@@ -278,6 +442,11 @@ class LocalStoreOptions:
     files_max_size_bytes: int = 256 * GIGABYTES
     directories_max_size_bytes: int = 16 * GIGABYTES
     shard_count: int = 16
+    materialize_umask: int = 0o022
+    materialize_digest_xattrs: bool = False
+    hashing_parallelism: int = _CPU_COUNT
+    maintenance_idle_io: bool = True
+    download_repin: Tuple[str, ...] = ()
 
     def target_total_size_bytes(self) -> int:
         """Returns the target total size of all of the stores.
@@ -303,14 +472,23 @@ class LocalStoreOptions:
             files_max_size_bytes=options.local_store_files_max_size_bytes,
             directories_max_size_bytes=options.local_store_directories_max_size_bytes,
             shard_count=options.local_store_shard_count,
+            materialize_umask=int(options.materialize_umask, 8),
+            materialize_digest_xattrs=options.materialize_digest_xattrs,
+            hashing_parallelism=options.local_store_hashing_parallelism,
+            maintenance_idle_io=options.local_store_maintenance_idle_io,
+            download_repin=tuple(options.download_repin),
         )
 
 
 DEFAULT_EXECUTION_OPTIONS = ExecutionOptions(
+    offline=False,
     # Remote execution strategy.
     remote_execution=False,
     remote_cache_read=False,
     remote_cache_write=False,
+    remote_cache_write_strict=False,
+    remote_cache_backfill=False,
+    remote_cache_backfill_interval_millis=500,
     # General remote setup.
     remote_instance_name=None,
     remote_ca_certs_path=None,
@@ -318,21 +496,53 @@ DEFAULT_EXECUTION_OPTIONS = ExecutionOptions(
     process_execution_local_parallelism=_CPU_COUNT,
     process_execution_remote_parallelism=128,
     process_execution_cache_namespace=None,
+    process_execution_max_output_bytes=None,
+    process_execution_max_run_output_bytes=None,
+    process_execution_audit_file_accesses=False,
+    process_execution_fail_on_external_writes=False,
+    process_execution_local_max_sandboxes=None,
+    process_execution_local_batch_size=32,
+    process_execution_local_batch_window_millis=20,
+    process_execution_local_platform_properties={},
+    process_execution_docker_binary="docker",
     process_execution_local_cleanup=True,
+    keep_sandboxes=KeepSandboxes.never,
+    keep_sandboxes_max_count=None,
+    keep_sandboxes_max_age_secs=None,
+    keep_sandboxes_max_bytes=None,
     process_execution_local_cache=True,
+    redact_env_vars=[],
+    redact_patterns=[],
     # Remote store setup.
     remote_store_address=None,
     remote_store_headers={},
     remote_store_chunk_bytes=1024 * 1024,
+    remote_store_chunk_bytes_min=64 * 1024,
+    remote_store_chunk_bytes_max=3 * 1024 * 1024,
     remote_store_chunk_upload_timeout_seconds=60,
     remote_store_rpc_retries=2,
+    remote_store_rpc_qps=None,
+    remote_store_bandwidth_bytes_per_sec=None,
+    remote_store_read_concurrency=None,
+    remote_store_max_inflight_bytes=None,
+    remote_store_encryption_key_path=None,
+    remote_store_mirrors=[],
+    remote_store_digest_function=RemoteStoreDigestFunction.sha256,
     # Remote cache setup.
     remote_cache_eager_fetch=True,
+    remote_cache_partition=None,
+    remote_cache_partition_strategy=RemoteCachePartitionStrategy.action_salt,
     # Remote execution setup.
     remote_execution_address=None,
     remote_execution_extra_platform_properties=[],
     remote_execution_headers={},
     remote_execution_overall_deadline_secs=60 * 60,  # one hour
+    remote_execution_rpc_qps=None,
+    remote_execution_pipeline_uploads=False,
+    remote_execution_speculation=RemoteExecutionSpeculation.none,
+    remote_execution_speculation_runtime_multiplier=3.0,
+    remote_execution_speculation_min_delay_secs=30,
+    remote_execution_speculation_max_concurrent=8,
 )
 
 DEFAULT_LOCAL_STORE_OPTIONS = LocalStoreOptions()
@@ -407,6 +617,30 @@ class GlobalOptions(Subsystem):
             "This can be helpful when paired with --log-levels-by-target.",
         )
 
+        register(
+            "--log-by-workunit",
+            type=bool,
+            default=False,
+            daemon=True,
+            advanced=True,
+            help="Attach log messages which are emitted while a workunit is running to that "
+            "workunit, rather than interleaving them into the console output. Attached messages "
+            "are reported to streaming workunit handlers (under the `logs` key of the workunit) "
+            "and recorded in the log file. Warnings and errors are always displayed in the "
+            "console as well.",
+        )
+
+        register(
+            "--full-digests",
+            type=bool,
+            default=False,
+            daemon=True,
+            advanced=True,
+            help="Display full digests in errors, workunits and logs. By default, digests are "
+            "abbreviated to the first 12 hex characters of their fingerprint and their size "
+            "(e.g. `0123456789ab:42`), which is almost always unique in the local store.",
+        )
+
         register(
             "--log-levels-by-target",
             type=dict,
@@ -454,6 +688,37 @@ class GlobalOptions(Subsystem):
             "matched from the start of the warning string, and are case-insensitive.",
         )
 
+        register(
+            "--redact-env-vars",
+            type=list,
+            member_type=str,
+            default=DEFAULT_EXECUTION_OPTIONS.redact_env_vars,
+            daemon=True,
+            advanced=True,
+            help=(
+                "Names of environment variables which hold secrets, e.g. `[\"GITHUB_TOKEN\"]`. "
+                "Their values are replaced with `<redacted>` in the stdout and stderr of processes "
+                "(including output which is streamed while they run), in the `__run.sh` scripts "
+                "of preserved sandboxes, in process descriptions, and in Pants' own logs before "
+                "any of those are persisted.\n\nValues are taken from both the environment of "
+                "each process and the environment of Pants itself."
+            ),
+        )
+        register(
+            "--redact-patterns",
+            type=list,
+            member_type=str,
+            default=DEFAULT_EXECUTION_OPTIONS.redact_patterns,
+            daemon=True,
+            advanced=True,
+            help=(
+                "Regexps matching secret values which should be redacted from process outputs and "
+                "logs, in the same places as `--redact-env-vars`, e.g. `[\"ghp_[A-Za-z0-9]+\"]`."
+                "\n\nOutput which is streamed while a process runs is only matched one line at a "
+                "time, so patterns should not match across lines."
+            ),
+        )
+
         register(
             "--pants-version",
             advanced=True,
@@ -617,6 +882,19 @@ class GlobalOptions(Subsystem):
             "operations performed by Pants. If used together with `--pants-ignore`, any exclude/include "
             "patterns specified there apply after .gitignore rules.",
         )
+        register(
+            "--watch-filesystem",
+            advanced=True,
+            type=bool,
+            default=True,
+            help=(
+                "Watch the filesystem for changes to invalidate cached work between runs.\n\n"
+                "This can be disabled in environments (such as CI) where the checkout is only "
+                "mutated in known ways between runs of Pants, and watching is wasteful. Without "
+                "watching, any paths that change between runs with `pantsd` must be declared via "
+                "`--invalidate-paths`."
+            ),
+        )
 
         # These logging options are registered in the bootstrap phase so that plugins can log during
         # registration and not so that their values can be interpolated in configs.
@@ -669,6 +947,15 @@ class GlobalOptions(Subsystem):
             "any prior running Pants command must be finished for the current one to start. "
             "To never timeout, use the value -1.",
         )
+        register(
+            "--pantsd-shutdown-timeout",
+            advanced=True,
+            type=float,
+            default=60.0,
+            help="The maximum amount of time (in seconds) that pantsd will wait while exiting for "
+            "background work (such as writes to the remote cache) to complete. Local caches are "
+            "flushed to disk once that work has completed or the timeout has elapsed.",
+        )
         register(
             "--pantsd-max-memory-usage",
             advanced=True,
@@ -820,6 +1107,79 @@ class GlobalOptions(Subsystem):
             ),
             default=DEFAULT_LOCAL_STORE_OPTIONS.directories_max_size_bytes,
         )
+        register(
+            "--local-store-hashing-parallelism",
+            type=int,
+            advanced=True,
+            help=(
+                "The maximum number of files to hash concurrently when capturing files into the "
+                "local store. Hashing is CPU-bound, so values larger than the number of cores are "
+                "unlikely to help, and lower values leave more threads free for other work while "
+                "large outputs are captured."
+            ),
+            default=DEFAULT_LOCAL_STORE_OPTIONS.hashing_parallelism,
+        )
+        register(
+            "--local-store-maintenance-idle-io",
+            type=bool,
+            advanced=True,
+            help=(
+                "If enabled, background maintenance of the local store (extending the leases of "
+                "files which are in use, and garbage collection) runs in the idle IO scheduling "
+                "class, so that it does not compete with builds for disk bandwidth."
+                "\n\n"
+                "Only supported on Linux: elsewhere, this option has no effect."
+            ),
+            default=DEFAULT_LOCAL_STORE_OPTIONS.maintenance_idle_io,
+        )
+        register(
+            "--materialize-umask",
+            type=str,
+            advanced=True,
+            help=(
+                "The umask, in octal, to apply to files and directories when they are written into "
+                "process sandboxes or into the workspace. Directories and executable files start "
+                "from mode `777`, and other files from mode `666`, so the default results in "
+                "`755` and `644` respectively. Executable bits are preserved from the inputs."
+                "\n\n"
+                "Unlike the umask of the Pants process, this umask may add permissions: for "
+                "example, use `002` to make materialized files group-writable when they are "
+                "shared between users on a CI machine."
+            ),
+            default=f"{DEFAULT_LOCAL_STORE_OPTIONS.materialize_umask:03o}",
+        )
+        register(
+            "--materialize-digest-xattrs",
+            type=bool,
+            advanced=True,
+            help=(
+                "If enabled, files written into process sandboxes or into the workspace are tagged "
+                "with their content digest in the `user.pants.digest` extended attribute. Files "
+                "which are unchanged since they were written can then be captured without being "
+                "hashed again, and external tools can cheaply verify the integrity of artifacts."
+                "\n\n"
+                "The attribute contains the SHA-256 fingerprint and size of the file, followed by "
+                "the modification time of the file (in nanoseconds) when it was tagged. Tagging is "
+                "skipped on filesystems which do not support extended attributes."
+            ),
+            default=DEFAULT_LOCAL_STORE_OPTIONS.materialize_digest_xattrs,
+        )
+        register(
+            "--download-repin",
+            type=list,
+            member_type=str,
+            advanced=True,
+            help=(
+                "URLs of downloads which should be re-pinned to the digest of their next download."
+                "\n\n"
+                "Downloads which do not declare an expected digest are trusted on first use: the "
+                "digest of their first download is recorded in the local store directory, and "
+                "later downloads of the same URL fail if their content does not match it. If the "
+                "content at a URL is expected to have changed, list the URL here to replace its "
+                "recorded digest."
+            ),
+            default=list(DEFAULT_LOCAL_STORE_OPTIONS.download_repin),
+        )
         register(
             "--named-caches-dir",
             advanced=True,
@@ -855,7 +1215,57 @@ class GlobalOptions(Subsystem):
                 "If false, Pants will not clean up local directories used as chroots for running "
                 "processes. Pants will log their location so that you can inspect the chroot, and "
                 "run the `__run.sh` script to recreate the process using the same argv and "
-                "environment variables used by Pants. This option is useful for debugging."
+                "environment variables used by Pants. This option is useful for debugging.\n\n"
+                "Equivalent to `--keep-sandboxes=always`."
+            ),
+        )
+        register(
+            "--keep-sandboxes",
+            type=KeepSandboxes,
+            default=DEFAULT_EXECUTION_OPTIONS.keep_sandboxes,
+            advanced=True,
+            help=(
+                "Controls whether Pants preserves the local directories used as chroots for "
+                "running processes. If `always`, all chroots are preserved, and if `on_failure`, "
+                "only the chroots of processes which exit unsuccessfully are preserved. Pants will "
+                "log the location of each preserved chroot so that you can inspect it, and run "
+                "the `__run.sh` script to recreate the process using the same argv and "
+                "environment variables used by Pants. This option is useful for debugging.\n\n"
+                "Preserved chroots are not deleted by Pants unless they exceed one of the "
+                "`--keep-sandboxes-max-*` limits, which only apply to the chroots preserved by "
+                "this repository."
+            ),
+        )
+        register(
+            "--keep-sandboxes-max-count",
+            type=int,
+            default=DEFAULT_EXECUTION_OPTIONS.keep_sandboxes_max_count,
+            advanced=True,
+            help=(
+                "If set, the maximum number of preserved chroots (see `--keep-sandboxes`) to "
+                "retain. When Pants starts, and after each run, the oldest preserved chroots "
+                "beyond this limit are deleted, and a summary of what was deleted is logged."
+            ),
+        )
+        register(
+            "--keep-sandboxes-max-age-secs",
+            type=int,
+            default=DEFAULT_EXECUTION_OPTIONS.keep_sandboxes_max_age_secs,
+            advanced=True,
+            help=(
+                "If set, preserved chroots (see `--keep-sandboxes`) which were preserved more than "
+                "this many seconds ago are deleted when Pants starts, and after each run."
+            ),
+        )
+        register(
+            "--keep-sandboxes-max-bytes",
+            type=int,
+            default=DEFAULT_EXECUTION_OPTIONS.keep_sandboxes_max_bytes,
+            advanced=True,
+            help=(
+                "If set, the maximum total size of the preserved chroots (see `--keep-sandboxes`) "
+                "to retain. When Pants starts, and after each run, the oldest preserved chroots "
+                "beyond this limit are deleted, and a summary of what was deleted is logged."
             ),
         )
 
@@ -893,7 +1303,133 @@ class GlobalOptions(Subsystem):
                 "process cache entries from being (re)used for different usecases or users."
             ),
         )
+        register(
+            "--process-execution-max-output-bytes",
+            advanced=True,
+            type=int,
+            default=DEFAULT_EXECUTION_OPTIONS.process_execution_max_output_bytes,
+            help=(
+                "If set, the maximum total size of the files captured as outputs of a single "
+                "process, unless the process sets its own `max_output_bytes`. A process whose "
+                "outputs exceed the quota fails (naming its largest output files) rather than "
+                "having its outputs cached, which protects shared caches from accidental large "
+                "artifacts."
+            ),
+        )
+        register(
+            "--process-execution-max-run-output-bytes",
+            advanced=True,
+            type=int,
+            default=DEFAULT_EXECUTION_OPTIONS.process_execution_max_run_output_bytes,
+            help=(
+                "If set, the maximum total size of the files captured as outputs of all of the "
+                "processes executed during a run of Pants. Processes which are executed once the "
+                "quota is exceeded fail in the same way as for "
+                "`--process-execution-max-output-bytes`."
+            ),
+        )
+        register(
+            "--process-execution-audit-file-accesses",
+            advanced=True,
+            type=bool,
+            default=DEFAULT_EXECUTION_OPTIONS.process_execution_audit_file_accesses,
+            help=(
+                "If true, trace the file accesses of processes which run locally, and warn about "
+                "any paths outside of the sandbox that they accessed, which were not declared as "
+                "inputs.\n\nThis is useful for finding non-hermetic tools, but slows down "
+                "processes considerably. Only supported on Linux, and requires `strace` to be "
+                "installed."
+            ),
+        )
+        register(
+            "--process-execution-fail-on-external-writes",
+            advanced=True,
+            type=bool,
+            default=DEFAULT_EXECUTION_OPTIONS.process_execution_fail_on_external_writes,
+            help=(
+                "If true, trace the file accesses of processes which run locally, and fail any "
+                "process which writes to a path outside of its sandbox (other than the named "
+                "caches). Processes should write temporary files to the directory advertised via "
+                "`TMPDIR`, which is created inside of each sandbox.\n\nLike "
+                "`--process-execution-audit-file-accesses`, this slows down processes "
+                "considerably, is only supported on Linux, and requires `strace` to be installed."
+            ),
+        )
+        register(
+            "--process-execution-local-max-sandboxes",
+            advanced=True,
+            type=int,
+            default=DEFAULT_EXECUTION_OPTIONS.process_execution_local_max_sandboxes,
+            help=(
+                "If set, the maximum number of sandbox directories for local processes which may "
+                "exist at once, including those which are waiting to be cleaned up. New sandboxes "
+                "wait for completed sandboxes to be deleted before their inputs are materialized, "
+                "which bounds the peak disk usage of sandboxes.\n\nThe peak total size of "
+                "sandboxes is reported as the maximum of the `local_sandboxes_live_bytes` "
+                "observation, which can help to size the disks of CI machines."
+            ),
+        )
+        register(
+            "--process-execution-local-batch-size",
+            advanced=True,
+            type=int,
+            default=DEFAULT_EXECUTION_OPTIONS.process_execution_local_batch_size,
+            help=(
+                "The maximum number of processes which may be run locally as a single batch. Only "
+                "processes which set `batch_argv_prefix_len` are batched, with other compatible "
+                "processes that are requested within `--process-execution-local-batch-window-millis` "
+                "of them.\n\nSet to 1 to disable batching."
+            ),
+        )
+        register(
+            "--process-execution-local-batch-window-millis",
+            advanced=True,
+            type=int,
+            default=DEFAULT_EXECUTION_OPTIONS.process_execution_local_batch_window_millis,
+            help=(
+                "How long to wait for compatible processes to join a batch before running it: see "
+                "`--process-execution-local-batch-size`."
+            ),
+        )
+        register(
+            "--process-execution-local-platform-properties",
+            advanced=True,
+            type=dict,
+            default=DEFAULT_EXECUTION_OPTIONS.process_execution_local_platform_properties,
+            help=(
+                "Capabilities of this machine, as arbitrary properties such as "
+                "`{'xcode': '14.2', 'gpu': 'none'}`.\n\nProcesses may require particular values "
+                "of properties via their `platform_properties`, and a process whose requirements "
+                "are not satisfied by these properties fails with an error explaining why, rather "
+                "than running locally. Properties which are not declared here do not satisfy any "
+                "requirement."
+            ),
+        )
+        register(
+            "--process-execution-docker-binary",
+            advanced=True,
+            type=str,
+            default=DEFAULT_EXECUTION_OPTIONS.process_execution_docker_binary,
+            help=(
+                "The `docker` CLI (or a compatible CLI, such as `podman`) which runs processes "
+                "which set a `container_image`, when they run locally. If it is not an absolute "
+                "path, it is looked up on the `PATH` of Pants.\n\nThe sandbox of each such "
+                "process is bind-mounted into a new container of its image, in which it runs as "
+                "the current user."
+            ),
+        )
 
+        register(
+            "--offline",
+            type=bool,
+            default=DEFAULT_EXECUTION_OPTIONS.offline,
+            help=(
+                "Run without network access: for use when air-gapped or travelling.\n\nRemote "
+                "execution and file downloads fail immediately with an error that mentions this "
+                "option, and the remote cache and remote store are skipped, rather than waiting for "
+                "network timeouts. Downloads which were previously cached continue to work."
+            ),
+        )
         register(
             "--remote-execution",
             advanced=True,
@@ -925,6 +1461,45 @@ class GlobalOptions(Subsystem):
                 "the same time as `--remote-execution`."
             ),
         )
+        register(
+            "--remote-cache-write-strict",
+            type=bool,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_cache_write_strict,
+            advanced=True,
+            help=(
+                "If set, fail processes whose results cannot be written to the remote cache (after "
+                "retries), rather than only logging a warning. Writes are then completed before "
+                "each process finishes, rather than in the background.\n\nUse this for jobs (such "
+                "as in CI) whose purpose is to seed the remote cache, so that broken uploads are "
+                "noticed. Failed writes are also reported as `remote_cache_write` workunits with "
+                "a `remote_cache_write_error_category`, regardless of this option.\n\nThis has "
+                "no effect unless `--remote-cache-write` is set."
+            ),
+        )
+        register(
+            "--remote-cache-backfill",
+            type=bool,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_cache_backfill,
+            advanced=True,
+            help=(
+                "Whether to upload the results of processes which ran locally to the remote cache "
+                "in the background, at low priority, rather than as they complete.\n\nThis allows "
+                "a local build to seed the remote cache for other machines without slowing down "
+                "the build itself. Results which have not been uploaded when Pants exits are "
+                "uploaded by later runs.\n\nThis has no effect if `--remote-cache-write` is set, "
+                "and cannot be used at the same time as `--remote-execution`."
+            ),
+        )
+        register(
+            "--remote-cache-backfill-interval-millis",
+            type=int,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_cache_backfill_interval_millis,
+            advanced=True,
+            help=(
+                "The delay between uploads of results when `--remote-cache-backfill` is enabled, "
+                "which limits the share of the network that backfilling uses."
+            ),
+        )
 
         register(
             "--remote-instance-name",
@@ -1009,7 +1584,33 @@ class GlobalOptions(Subsystem):
             type=int,
             advanced=True,
             default=DEFAULT_EXECUTION_OPTIONS.remote_store_chunk_bytes,
-            help="Size in bytes of chunks transferred to/from the remote file store.",
+            help=(
+                "Initial size in bytes of chunks uploaded to the remote file store.\n\nThe chunk "
+                "size then adapts to the throughput observed for the connection, within the bounds "
+                "of `--remote-store-chunk-bytes-min` and `--remote-store-chunk-bytes-max`."
+            ),
+        )
+        register(
+            "--remote-store-chunk-bytes-min",
+            type=int,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_store_chunk_bytes_min,
+            help=(
+                "The minimum size in bytes of chunks uploaded to the remote file store.\n\nIf "
+                "this is not less than `--remote-store-chunk-bytes-max`, the chunk size does not "
+                "adapt, and `--remote-store-chunk-bytes` is always used."
+            ),
+        )
+        register(
+            "--remote-store-chunk-bytes-max",
+            type=int,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_store_chunk_bytes_max,
+            help=(
+                "The maximum size in bytes of chunks uploaded to the remote file store.\n\nThis "
+                "should be less than the maximum gRPC message size accepted by the server, which "
+                "is commonly 4MiB."
+            ),
         )
         register(
             "--remote-store-chunk-upload-timeout-seconds",
@@ -1025,6 +1626,103 @@ class GlobalOptions(Subsystem):
             default=DEFAULT_EXECUTION_OPTIONS.remote_store_rpc_retries,
             help="Number of times to retry any RPC to the remote store before giving up.",
         )
+        register(
+            "--remote-store-rpc-qps",
+            type=int,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_store_rpc_qps,
+            help=(
+                "The maximum average number of requests per second to make to the remote file "
+                "store. Requests beyond this rate are delayed rather than failed.\n\nIf unset, "
+                "requests are not rate limited."
+            ),
+        )
+        register(
+            "--remote-store-bandwidth-bytes-per-sec",
+            type=int,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_store_bandwidth_bytes_per_sec,
+            help=(
+                "The maximum average number of bytes per second to upload to or download from the "
+                "remote file store. Transfers beyond this rate are delayed rather than failed."
+                "\n\nIf unset, transfers are not rate limited."
+            ),
+        )
+        register(
+            "--remote-store-read-concurrency",
+            type=int,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_store_read_concurrency,
+            help=(
+                "The maximum number of concurrent reads from the remote file store. Queued reads "
+                "are admitted in order of priority: reads which you are directly waiting on (such "
+                "as writing files to the workspace) go first, and speculative remote cache lookups "
+                "go last.\n\nIf unset, reads are not limited."
+            ),
+        )
+        register(
+            "--remote-store-max-inflight-bytes",
+            type=int,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_store_max_inflight_bytes,
+            help=(
+                "The maximum total size of the files which are being uploaded to or downloaded "
+                "from the remote file store at once. This bounds the memory used for remote "
+                "transfers, regardless of how large the files are: transfers which would exceed "
+                "it wait for others to complete, and a file larger than the limit is transferred "
+                "on its own.\n\nIf unset, the size of transfers is not limited."
+            ),
+        )
+        register(
+            "--remote-store-encryption-key-path",
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_store_encryption_key_path,
+            help=(
+                "Path to a file containing a hex-encoded 256-bit key, which is used to encrypt "
+                "(with AES-GCM) the content of files before they are uploaded to the remote "
+                "store, and to decrypt them after they are downloaded.\n\nUse this if source code "
+                "must not be readable by the operator of a third-party cache service. The digest "
+                "of each encrypted file is recorded in the action cache of the remote store, under "
+                "an identifier derived from the key, so that the file can be found by any machine "
+                "which uses the same key.\n\nOnly the content of files (and of directories, "
+                "including the names of their entries) is encrypted. The action cache entries "
+                "which `--remote-cache-write` uploads for processes are stored in plaintext: "
+                "these include the paths of the output files and directories of each process, its "
+                "exit code, and the digests of its outputs.\n\nThis cannot be used with "
+                "`--remote-execution`, since the remote executor could not read the inputs of "
+                "processes."
+            ),
+        )
+        register(
+            "--remote-store-mirrors",
+            type=list,
+            member_type=str,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_store_mirrors,
+            help=(
+                "Base URLs of read-only HTTP mirrors of the file store, which are laid out by "
+                "content: the file with a given digest is fetched from `<url>/<sha256 hex>`.\n\n"
+                "Files which are missing from the local store and from the remote store (if any) "
+                "are fetched from each mirror in order, and are only used if their content "
+                "matches the expected digest. This allows a cheap CDN-backed cache to serve the "
+                "outputs of remote cache hits, rather than re-running processes whose outputs are "
+                "missing.\n\nMirrors are not used with `--offline`."
+            ),
+        )
+        register(
+            "--remote-store-digest-function",
+            type=RemoteStoreDigestFunction,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_store_digest_function,
+            help=(
+                "The hash function which the remote store uses to address files.\n\nUse `sha1` "
+                "to share a legacy file store which only supports SHA-1. Files are re-hashed as "
+                "they are uploaded, and their SHA-1 hashes are recorded in the local store (under "
+                "`--local-store-dir`), and so files are only downloaded by machines which uploaded "
+                "them.\n\nThis cannot be used with `--remote-execution`, since the remote "
+                "executor would not be able to address the inputs of processes."
+            ),
+        )
 
         register(
             "--remote-cache-eager-fetch",
@@ -1037,6 +1735,32 @@ class GlobalOptions(Subsystem):
                 "encountered by reducing the surface area of when remote caching is used."
             ),
         )
+        register(
+            "--remote-cache-partition",
+            type=str,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_cache_partition,
+            help=(
+                "A key which partitions remote cache entries, such as a repository name, branch, "
+                "or other label.\n\nProcesses only hit cache entries which were written with the "
+                "same partition, which prevents cross-contamination between unrelated projects or "
+                "release branches that share a remote cache.\n\nIf unset, entries are not "
+                "partitioned."
+            ),
+        )
+        register(
+            "--remote-cache-partition-strategy",
+            type=RemoteCachePartitionStrategy,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_cache_partition_strategy,
+            help=(
+                "How `--remote-cache-partition` is applied.\n\n`action_salt` mixes the partition "
+                "into the digest of each remote action, and so works with any server. "
+                "`instance_name` appends the partition to `--remote-instance-name`, which allows "
+                "servers to route, quota, or expire partitions independently, but which also "
+                "partitions the remote store."
+            ),
+        )
 
         register(
             "--remote-execution-address",
@@ -1076,6 +1800,76 @@ class GlobalOptions(Subsystem):
             advanced=True,
             help="Overall timeout in seconds for each remote execution request from time of submission",
         )
+        register(
+            "--remote-execution-rpc-qps",
+            type=int,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_execution_rpc_qps,
+            help=(
+                "The maximum average number of requests per second to make to the remote execution "
+                "scheduler. Requests beyond this rate are delayed rather than failed.\n\nIf unset, "
+                "requests are not rate limited."
+            ),
+        )
+        register(
+            "--remote-execution-pipeline-uploads",
+            type=bool,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_execution_pipeline_uploads,
+            help=(
+                "If true, submit each remote execution request while its inputs are still being "
+                "uploaded, rather than after the upload completes.\n\nThis reduces latency for "
+                "processes whose inputs are mostly already present on the server. The server must "
+                "report missing inputs as a `FAILED_PRECONDITION`, in which case the request is "
+                "retried once the upload has completed."
+            ),
+        )
+        register(
+            "--remote-execution-speculation",
+            type=RemoteExecutionSpeculation,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_execution_speculation,
+            help=(
+                "Where to speculatively re-execute straggling remote executions, if at all.\n\n"
+                "A remote execution straggles if it runs for longer than "
+                "`--remote-execution-speculation-runtime-multiplier` times the recent runtime of "
+                "processes with the same description. A second attempt is then started (either "
+                "remotely or locally), and the result of whichever attempt completes first is used. "
+                "The other attempt is cancelled.\n\nNote that some servers merge identical "
+                "concurrent executions, in which case the `remote` strategy will not help."
+            ),
+        )
+        register(
+            "--remote-execution-speculation-runtime-multiplier",
+            type=float,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_execution_speculation_runtime_multiplier,
+            help=(
+                "How many times longer than its recent runtime a remote execution must run before "
+                "it is speculatively re-executed: see `--remote-execution-speculation`."
+            ),
+        )
+        register(
+            "--remote-execution-speculation-min-delay-secs",
+            type=int,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_execution_speculation_min_delay_secs,
+            help=(
+                "The minimum time that a remote execution must run for before it is speculatively "
+                "re-executed, regardless of its recent runtime: see "
+                "`--remote-execution-speculation`."
+            ),
+        )
+        register(
+            "--remote-execution-speculation-max-concurrent",
+            type=int,
+            advanced=True,
+            default=DEFAULT_EXECUTION_OPTIONS.remote_execution_speculation_max_concurrent,
+            help=(
+                "The maximum number of speculative re-executions which may run at once, which "
+                "bounds the amount of duplicated work: see `--remote-execution-speculation`."
+            ),
+        )
 
     @classmethod
     def register_options(cls, register):
@@ -1093,6 +1887,68 @@ class GlobalOptions(Subsystem):
             "if Pants detects a TTY and there is no 'CI' environment variable indicating that "
             "Pants is running in a continuous integration environment.",
         )
+        register(
+            "--session-priority",
+            type=SessionPriority,
+            default=SessionPriority.interactive,
+            advanced=True,
+            help=(
+                "The priority of this run relative to other runs of Pants using the same pantsd "
+                "or remote execution server.\n\nProcesses of runs with a higher priority acquire "
+                "local execution slots (see `--process-execution-local-parallelism`) before those "
+                "of lower priorities, and are submitted to remote execution with a higher "
+                "priority. Use `batch` for CI, and `background` for runs which no one is waiting "
+                "on (such as a scheduled `lint ::`), so that they yield to a developer's runs."
+            ),
+        )
+        register(
+            "--process-execution-dry-run",
+            type=bool,
+            default=False,
+            advanced=True,
+            help=(
+                "Rather than executing processes, record the processes which would have been "
+                "executed, and report their descriptions, action digests and input sizes when the "
+                "run completes. This can be used to preview what a goal would execute, or to "
+                "estimate the cost of running it remotely."
+                "\n\n"
+                "Each process is given a synthesized failed result, so goals will generally fail "
+                "after requesting their first batch of processes, and only those processes which "
+                "could be requested without the results of other processes are reported."
+            ),
+        )
+        register(
+            "--notifications",
+            type=bool,
+            default=False,
+            advanced=True,
+            help=(
+                "Send a desktop notification when a run which took longer than "
+                "`--notifications-threshold` completes, and when any individual process has been "
+                "running for longer than the threshold.\n\nNotifications use `osascript` on macOS "
+                "and `notify-send` on Linux, unless `--notifications-command` is set."
+            ),
+        )
+        register(
+            "--notifications-threshold",
+            type=float,
+            default=60.0,
+            advanced=True,
+            help=(
+                "The number of seconds which a run or a process must take before a notification "
+                "is sent for it, if `--notifications` is enabled."
+            ),
+        )
+        register(
+            "--notifications-command",
+            type=list,
+            advanced=True,
+            help=(
+                "A command (and arguments) to run to send notifications, rather than sending a "
+                "native desktop notification. The title and message of the notification are "
+                "appended as the final two arguments."
+            ),
+        )
 
         register(
             "--tag",
@@ -1111,6 +1967,18 @@ class GlobalOptions(Subsystem):
             help="Exclude targets that match these regexes. This does not impact file arguments.",
         )
 
+        register(
+            "--invalidate-paths",
+            advanced=True,
+            type=list,
+            default=[],
+            help=(
+                "Paths (relative to the build root) which have changed since the previous run of "
+                "Pants, and which should be invalidated before this run. This is only necessary "
+                "with `--no-watch-filesystem`, since changes are otherwise detected automatically."
+            ),
+        )
+
         register(
             "--files-not-found-behavior",
             advanced=True,
@@ -1193,6 +2061,43 @@ class GlobalOptions(Subsystem):
             advanced=True,
             help="Interval in seconds between when streaming workunit event receivers will be polled.",
         )
+        register(
+            "--streaming-workunits-max-queued",
+            type=int,
+            default=100000,
+            advanced=True,
+            help=(
+                "The maximum number of workunit events to hold in memory while waiting for "
+                "streaming workunit event receivers to poll them. When more events are queued, "
+                "`--streaming-workunits-overflow-policy` is applied."
+            ),
+        )
+        register(
+            "--streaming-workunits-overflow-policy",
+            type=StreamingWorkunitsOverflowPolicy,
+            default=StreamingWorkunitsOverflowPolicy.spill,
+            advanced=True,
+            help=(
+                "What to do with workunit events when streaming workunit event receivers fall "
+                "behind, and `--streaming-workunits-max-queued` events are already queued.\n\n"
+                "`block` pauses the run until the receivers catch up; `drop` discards events "
+                "(which are counted in the `streaming_workunits_dropped` metric); and `spill` "
+                "writes events to a temporary file until the receivers catch up."
+            ),
+        )
+        register(
+            "--record-run-graph",
+            type=bool,
+            default=False,
+            advanced=True,
+            help=(
+                "If set, record the nodes of each run's graph (along with their versions) to "
+                "`run_graph.json` in the run's directory under `<pants_workdir>/run-tracker`.\n\n"
+                "The run graphs of two runs can be compared with "
+                "`pants.engine.internals.scheduler.explain_run_divergence` to find out why work "
+                "was redone in the later run."
+            ),
+        )
 
     @classmethod
     def validate_instance(cls, opts):
@@ -1210,6 +2115,57 @@ class GlobalOptions(Subsystem):
             # at a time, and a `@goal_rule` will only block one thread.
             raise OptionsError("--rule-threads-core values less than 2 are not supported.")
 
+        if not re.fullmatch(r"0?[0-7]{1,3}", opts.materialize_umask):
+            raise OptionsError(
+                "The `--materialize-umask` option must be an octal umask such as `022`, but was "
+                f"`{opts.materialize_umask}`."
+            )
+
+        if (
+            opts.process_execution_local_max_sandboxes is not None
+            and opts.process_execution_local_max_sandboxes < 1
+        ):
+            raise OptionsError(
+                "The `--process-execution-local-max-sandboxes` option must be at least 1, but was "
+                f"{opts.process_execution_local_max_sandboxes}."
+            )
+
+        if not opts.process_execution_docker_binary:
+            raise OptionsError("The `--process-execution-docker-binary` option may not be empty.")
+
+        for option_name in (
+            "keep_sandboxes_max_count",
+            "keep_sandboxes_max_age_secs",
+            "keep_sandboxes_max_bytes",
+        ):
+            value = getattr(opts, option_name)
+            if value is not None and value < 0:
+                raise OptionsError(
+                    f"The `--{option_name.replace('_', '-')}` option must not be negative, but "
+                    f"was {value}."
+                )
+
+        if opts.local_store_hashing_parallelism < 1:
+            raise OptionsError(
+                "The `--local-store-hashing-parallelism` option must be at least 1, but was "
+                f"{opts.local_store_hashing_parallelism}."
+            )
+
+        if opts.process_execution_local_batch_size < 1:
+            raise OptionsError(
+                "The `--process-execution-local-batch-size` option must be at least 1, but was "
+                f"{opts.process_execution_local_batch_size}."
+            )
+
+        if (
+            opts.remote_store_max_inflight_bytes is not None
+            and opts.remote_store_max_inflight_bytes < 1
+        ):
+            raise OptionsError(
+                "The `--remote-store-max-inflight-bytes` option must be at least 1, but was "
+                f"{opts.remote_store_max_inflight_bytes}."
+            )
+
         if opts.remote_execution and (opts.remote_cache_read or opts.remote_cache_write):
             raise OptionsError(
                 "`--remote-execution` cannot be set at the same time as either "
@@ -1217,6 +2173,39 @@ class GlobalOptions(Subsystem):
                 "enabled, it will already use remote caching."
             )
 
+        if opts.remote_execution_speculation != RemoteExecutionSpeculation.none:
+            if not opts.remote_execution:
+                raise OptionsError(
+                    "`--remote-execution-speculation` requires `--remote-execution` to be enabled."
+                )
+            if opts.remote_execution_speculation_runtime_multiplier < 1:
+                raise OptionsError(
+                    "The `--remote-execution-speculation-runtime-multiplier` option must be at "
+                    "least 1, but was "
+                    f"{opts.remote_execution_speculation_runtime_multiplier}."
+                )
+
+        if opts.remote_execution and opts.remote_cache_backfill:
+            raise OptionsError(
+                "`--remote-cache-backfill` cannot be used with `--remote-execution`, since remote "
+                "execution already populates the remote cache."
+            )
+
+        if opts.remote_execution and opts.remote_store_encryption_key_path:
+            raise OptionsError(
+                "`--remote-store-encryption-key-path` cannot be used with `--remote-execution`, "
+                "since the remote executor would not be able to read encrypted inputs."
+            )
+
+        if (
+            opts.remote_execution
+            and opts.remote_store_digest_function != RemoteStoreDigestFunction.sha256
+        ):
+            raise OptionsError(
+                "`--remote-store-digest-function` must be `sha256` with `--remote-execution`, "
+                "since the remote executor would not be able to address the inputs of processes."
+            )
+
         if opts.remote_execution and not opts.remote_execution_address:
             raise OptionsError(
                 "The `--remote-execution` option requires also setting "
@@ -1238,6 +2227,16 @@ class GlobalOptions(Subsystem):
                 "The `--remote-cache-write` option requires also setting "
                 "`--remote-store-address` or to work properly."
             )
+        if opts.remote_cache_backfill and not opts.remote_store_address:
+            raise OptionsError(
+                "The `--remote-cache-backfill` option requires also setting "
+                "`--remote-store-address` to work properly."
+            )
+        if opts.remote_cache_backfill_interval_millis < 0:
+            raise OptionsError(
+                "The `--remote-cache-backfill-interval-millis` option must not be negative, but "
+                f"was {opts.remote_cache_backfill_interval_millis}."
+            )
 
         def validate_remote_address(opt_name: str) -> None:
             valid_schemes = [f"{scheme}://" for scheme in ("grpc", "grpcs")]
@@ -1285,7 +2284,7 @@ class GlobalOptions(Subsystem):
         """Computes the merged value of the `--pants-ignore` flag.
 
         This inherently includes the workdir and distdir locations if they are located under the
-        buildroot.
+        buildroot, and the staging directories of digests which are being written.
         """
         pants_ignore = list(global_options.pants_ignore)
 
@@ -1301,6 +2300,9 @@ class GlobalOptions(Subsystem):
         add(global_options.pants_workdir)
         add(global_options.pants_distdir)
         add(global_options.pants_subprocessdir)
+        # The staging directories of transactional writes of digests (see `Scheduler.write_digest`)
+        # are transient, and should never be observed.
+        pants_ignore.append(".pants-staging-*/")
 
         return pants_ignore
 
@@ -4,9 +4,10 @@
 from __future__ import annotations
 
 import copy
-from dataclasses import dataclass
-from typing import Dict, Iterator, List, Optional
+from dataclasses import dataclass, field
+from typing import Any, Dict, Iterator, List, Optional
 
+from pants.base.hash_utils import CoercingEncoder, json_hash
 from pants.option.ranked_value import Rank, RankedValue, Value
 
 Key = str
@@ -58,9 +59,32 @@ class OptionValueContainer:
        the inner one from config).
 
        See ranked_value.py for more details.
+
+    Containers are compared by a fingerprint of their ranked values, so that (since a ScopedOptions
+    holds the container for a single scope) the engine only re-runs rules which consumed the options
+    of a scope when the values in that scope have actually changed.
     """
 
     _value_map: Dict[Key, RankedValue]
+    _fingerprint: str = field(init=False, repr=False)
+
+    def __post_init__(self) -> None:
+        fingerprint = json_hash(
+            [
+                (key, ranked_value.rank.name, ranked_value.value)
+                for key, ranked_value in sorted(self._value_map.items())
+            ],
+            encoder=_OptionValueEncoder,
+        )
+        object.__setattr__(self, "_fingerprint", fingerprint)
+
+    def __eq__(self, other: Any) -> bool:
+        if not isinstance(other, OptionValueContainer):
+            return NotImplemented
+        return self._fingerprint == other._fingerprint
+
+    def __hash__(self) -> int:
+        return hash(self._fingerprint)
 
     def get_explicit_keys(self) -> List[Key]:
         """Returns the keys for any values that were set explicitly (via flag, config, or env
@@ -136,7 +160,7 @@ class OptionValueContainer:
     # Note: Called only if regular attribute lookup fails,
     # so method and member access will be handled the normal way.
     def __getattr__(self, key: Key):
-        if key == "_value_map":
+        if key in ("_value_map", "_fingerprint"):
             # In case we get called in copy, which don't invoke the ctor.
             raise AttributeError(key)
         return self._get_underlying_value(key)
@@ -145,3 +169,13 @@ class OptionValueContainer:
         """Returns an iterator over all option names, in lexicographical order."""
         for name in sorted(self._value_map.keys()):
             yield name
+
+
+class _OptionValueEncoder(CoercingEncoder):
+    """Encodes option values which have no JSON representation by their `repr`."""
+
+    def default(self, o):
+        encoded = super().default(o)
+        if encoded is o and not self._is_natively_encodable(o):
+            return repr(o)
+        return encoded
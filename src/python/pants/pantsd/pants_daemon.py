@@ -204,6 +204,10 @@ class PantsDaemon(PantsDaemonProcessManager):
             # We're exiting: join the server to avoid interrupting ongoing runs.
             self._logger.info("Waiting for ongoing runs to complete before exiting...")
             native_engine.nailgun_server_await_shutdown(self._server)
+            # And then complete the background work of those runs (such as cache writes), so that
+            # it is not lost.
+            self._logger.info("Completing background work before exiting...")
+            self._core.shutdown(global_bootstrap_options.pantsd_shutdown_timeout)
             self._logger.info("Exiting pantsd")
 
 
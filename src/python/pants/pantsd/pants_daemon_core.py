@@ -92,6 +92,9 @@ class PantsDaemonCore:
             self._scheduler = EngineInitializer.setup_graph(
                 options_bootstrapper, build_config, env, executor=self._executor
             )
+            # Connect to any remote endpoints in the background, so that the first run which uses
+            # them does not pay for connection setup.
+            self._scheduler.scheduler.warm_up_remote_endpoints()
             bootstrap_options_values = options.bootstrap_option_values()
             assert bootstrap_options_values is not None
 
@@ -103,6 +106,18 @@ class PantsDaemonCore:
             self._scheduler = None
             raise e
 
+    def shutdown(self, timeout: float) -> None:
+        """Completes the background work of the current scheduler (if any) before exiting."""
+        with self._lifecycle_lock:
+            if self._scheduler is None:
+                return
+            abandoned = self._scheduler.scheduler.shutdown(timeout)
+            if abandoned:
+                logger.warning(
+                    f"Abandoned {len(abandoned)} background tasks after waiting {timeout} "
+                    "seconds. See `--pantsd-shutdown-timeout`."
+                )
+
     def prepare(
         self, options_bootstrapper: OptionsBootstrapper, env: CompleteEnvironment
     ) -> Tuple[GraphScheduler, OptionsInitializer]:
@@ -127,5 +142,9 @@ class PantsDaemonCore:
                 # fingerprint) or because relevant options have changed. Create a new scheduler
                 # and services.
                 self._initialize(options_fingerprint, options_bootstrapper, env)
+            else:
+                # Retry any endpoints which could not be reached (for example, because the network
+                # was unavailable when the scheduler was initialized).
+                self._scheduler.scheduler.warm_up_remote_endpoints(only_unhealthy=True)
             assert self._scheduler is not None
             return self._scheduler, self._options_initializer
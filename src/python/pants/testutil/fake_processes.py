@@ -0,0 +1,66 @@
+# Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+# Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+from __future__ import annotations
+
+from dataclasses import dataclass
+from typing import Sequence
+
+from pants.engine.fs import Digest
+from pants.engine.internals.native_engine import PyFakeProcesses
+
+
+@dataclass(frozen=True)
+class FakeProcessCall:
+    argv: tuple[str, ...]
+    description: str
+
+
+class FakeProcesses:
+    """Scripted results for the Processes run by rules under test, which are then never run.
+
+    Pass an instance to `RuleRunner(fake_processes=...)`, and then script the results of the
+    Processes that the rules under test will run with `respond`. When more than one response
+    matches a Process, the response which was scripted first is used. A Process without a matching
+    response fails.
+    """
+
+    def __init__(self) -> None:
+        self.native = PyFakeProcesses()
+
+    def respond(
+        self,
+        *,
+        argv_prefix: Sequence[str] = (),
+        description_regex: str | None = None,
+        exit_code: int = 0,
+        stdout: bytes = b"",
+        stderr: bytes = b"",
+        output_digest: Digest | None = None,
+        latency: float = 0.0,
+        error: str | None = None,
+    ) -> None:
+        """Responds to the Processes whose argv starts with `argv_prefix`, and whose description
+        matches `description_regex` (if set).
+
+        The `output_digest` must already have been captured by the RuleRunner. If `error` is set,
+        the Process fails to run (as if the engine had failed to run it), rather than exiting.
+        `latency` is the time in seconds that the Process takes to respond.
+        """
+        self.native.respond(
+            tuple(argv_prefix),
+            description_regex,
+            exit_code,
+            stdout,
+            stderr,
+            output_digest,
+            int(latency * 1000),
+            error,
+        )
+
+    @property
+    def calls(self) -> tuple[FakeProcessCall, ...]:
+        """The Processes which have been run so far, in the order that they started running."""
+        return tuple(
+            FakeProcessCall(tuple(argv), description) for argv, description in self.native.calls()
+        )
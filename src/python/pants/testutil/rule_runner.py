@@ -24,6 +24,7 @@ from pants.build_graph.build_configuration import BuildConfiguration
 from pants.build_graph.build_file_aliases import BuildFileAliases
 from pants.engine.addresses import Address
 from pants.engine.console import Console
+from pants.engine.doctor import EngineDoctor
 from pants.engine.environment import CompleteEnvironment
 from pants.engine.fs import PathGlobs, PathGlobsAndRoot, Snapshot, Workspace
 from pants.engine.goal import Goal
@@ -41,6 +42,7 @@ from pants.init.logging import initialize_stdio, stdio_destination
 from pants.option.global_options import ExecutionOptions, GlobalOptions, LocalStoreOptions
 from pants.option.options_bootstrapper import OptionsBootstrapper
 from pants.source import source_root
+from pants.testutil.fake_processes import FakeProcesses
 from pants.testutil.option_util import create_options_bootstrapper
 from pants.util.collections import assert_single_element
 from pants.util.contextutil import temporary_dir, temporary_file
@@ -96,6 +98,7 @@ class RuleRunner:
         preserve_tmpdirs: bool = False,
         ca_certs_path: str | None = None,
         bootstrap_args: Iterable[str] = (),
+        fake_processes: FakeProcesses | None = None,
     ) -> None:
 
         bootstrap_args = [*bootstrap_args]
@@ -160,6 +163,7 @@ class RuleRunner:
                 self.build_root, global_options
             ),
             use_gitignore=False,
+            watch_filesystem=global_options.watch_filesystem,
             local_store_options=local_store_options,
             local_execution_root_dir=local_execution_root_dir,
             named_caches_dir=named_caches_dir,
@@ -169,6 +173,7 @@ class RuleRunner:
             execution_options=ExecutionOptions.from_options(options, self.environment),
             ca_certs_path=ca_certs_path,
             native_engine_visualize_to=None,
+            fake_processes=fake_processes.native if fake_processes else None,
         ).new_session(
             build_id="buildid_for_test",
             session_values=SessionValues(
@@ -237,6 +242,7 @@ class RuleRunner:
                 console,
                 Workspace(self.scheduler),
                 InteractiveRunner(self.scheduler),
+                EngineDoctor(self.scheduler),
             ),
         )
 
@@ -27,18 +27,60 @@
 // Arc<Mutex> can be more clear than needing to grok Orderings:
 #![allow(clippy::mutex_atomic)]
 
-use std::collections::VecDeque;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
 use std::future::Future;
 use std::sync::Arc;
 
 use parking_lot::Mutex;
-use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::sync::oneshot;
 
 struct Inner {
-  sema: Semaphore,
-  available_ids: Mutex<VecDeque<usize>>,
+  state: Mutex<State>,
 }
 
+struct State {
+  available_ids: VecDeque<usize>,
+  waiters: BinaryHeap<Waiter>,
+  next_sequence: u64,
+}
+
+struct Waiter {
+  priority: usize,
+  sequence: u64,
+  sender: oneshot::Sender<Permit>,
+}
+
+impl PartialEq for Waiter {
+  fn eq(&self, other: &Self) -> bool {
+    self.priority == other.priority && self.sequence == other.sequence
+  }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Waiter {
+  fn cmp(&self, other: &Self) -> Ordering {
+    // Higher priorities first, and then earlier arrivals first.
+    self
+      .priority
+      .cmp(&other.priority)
+      .then_with(|| other.sequence.cmp(&self.sequence))
+  }
+}
+
+///
+/// A semaphore whose permits are identified by the integers `1..=permits`.
+///
+/// Waiters are admitted in order of their priority (higher first), and then in the order that they
+/// arrived.
+///
 #[derive(Clone)]
 pub struct AsyncSemaphore {
   inner: Arc<Inner>,
@@ -53,14 +95,17 @@ impl AsyncSemaphore {
 
     AsyncSemaphore {
       inner: Arc::new(Inner {
-        sema: Semaphore::new(permits),
-        available_ids: Mutex::new(available_ids),
+        state: Mutex::new(State {
+          available_ids,
+          waiters: BinaryHeap::new(),
+          next_sequence: 0,
+        }),
       }),
     }
   }
 
   pub fn available_permits(&self) -> usize {
-    self.inner.sema.available_permits()
+    self.inner.state.lock().available_ids.len()
   }
 
   ///
@@ -71,39 +116,93 @@ impl AsyncSemaphore {
     F: FnOnce(usize) -> B + Send + 'static,
     B: Future<Output = O> + Send + 'static,
   {
-    let permit = self.acquire().await;
-    let res = f(permit.id).await;
+    self.with_acquired_at_priority(0, f).await
+  }
+
+  ///
+  /// Runs the given Future-creating function (and the Future it returns) under the semaphore,
+  /// waiting behind any waiters of a higher priority.
+  ///
+  pub async fn with_acquired_at_priority<F, B, O>(self, priority: usize, f: F) -> O
+  where
+    F: FnOnce(usize) -> B + Send + 'static,
+    B: Future<Output = O> + Send + 'static,
+  {
+    let permit = self.acquire_at_priority(priority).await;
+    let res = f(permit.id()).await;
     drop(permit);
     res
   }
 
-  async fn acquire(&self) -> Permit<'_> {
-    let permit = self.inner.sema.acquire().await.expect("semaphore closed");
-    let id = {
-      let mut available_ids = self.inner.available_ids.lock();
-      available_ids
-        .pop_front()
-        .expect("More permits were distributed than ids exist.")
+  #[cfg(test)]
+  async fn acquire(&self) -> Permit {
+    self.acquire_at_priority(0).await
+  }
+
+  async fn acquire_at_priority(&self, priority: usize) -> Permit {
+    let receiver = {
+      let mut state = self.inner.state.lock();
+      if state.waiters.is_empty() {
+        if let Some(id) = state.available_ids.pop_front() {
+          return Permit {
+            inner: self.inner.clone(),
+            id: Some(id),
+          };
+        }
+      }
+      let (sender, receiver) = oneshot::channel();
+      let sequence = state.next_sequence;
+      state.next_sequence += 1;
+      state.waiters.push(Waiter {
+        priority,
+        sequence,
+        sender,
+      });
+      receiver
     };
-    Permit {
-      inner: self.inner.clone(),
-      _permit: permit,
-      id,
-    }
+    // NB: If this Future is dropped after a Permit has been sent, the Permit is dropped (and so
+    // released) along with the receiver.
+    receiver
+      .await
+      .expect("An AsyncSemaphore waiter was dropped without being sent a permit.")
   }
 }
 
-pub struct Permit<'a> {
+pub struct Permit {
   inner: Arc<Inner>,
-  // NB: Kept for its `Drop` impl.
-  _permit: SemaphorePermit<'a>,
-  id: usize,
+  // NB: Only None for a Permit which was defused while being released.
+  id: Option<usize>,
 }
 
-impl<'a> Drop for Permit<'a> {
+impl Permit {
+  fn id(&self) -> usize {
+    self.id.expect("A defused Permit was used.")
+  }
+}
+
+impl Drop for Permit {
   fn drop(&mut self) {
-    let mut available_ids = self.inner.available_ids.lock();
-    available_ids.push_back(self.id);
+    let id = if let Some(id) = self.id.take() {
+      id
+    } else {
+      return;
+    };
+    let mut state = self.inner.state.lock();
+    while let Some(waiter) = state.waiters.pop() {
+      let permit = Permit {
+        inner: self.inner.clone(),
+        id: Some(id),
+      };
+      match waiter.sender.send(permit) {
+        Ok(()) => return,
+        Err(mut permit) => {
+          // The waiter gave up: defuse the Permit (since we already hold the lock), and try the
+          // next waiter.
+          permit.id = None;
+        }
+      }
+    }
+    state.available_ids.push_back(id);
   }
 }
 
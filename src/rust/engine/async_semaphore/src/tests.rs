@@ -1,7 +1,9 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use futures::channel::oneshot;
 use futures::future::{self, FutureExt};
+use parking_lot::Mutex;
 use tokio::time::{sleep, timeout};
 
 use crate::AsyncSemaphore;
@@ -327,3 +329,38 @@ async fn dropped_future_is_removed_from_queue() {
   }
   assert_eq!(1, sema.available_permits());
 }
+
+#[tokio::test]
+async fn admits_waiters_by_priority() {
+  let sema = AsyncSemaphore::new(1);
+  let (unblock_holder, rx_holder) = oneshot::channel::<()>();
+  let holder = tokio::spawn(
+    sema
+      .clone()
+      .with_acquired(move |_id| async move { rx_holder.await.unwrap() }),
+  );
+  sleep(Duration::from_millis(10)).await;
+
+  let admitted = Arc::new(Mutex::new(vec![]));
+  let mut waiters = vec![];
+  for priority in &[0, 1, 2, 0] {
+    let admitted = admitted.clone();
+    let priority = *priority;
+    waiters.push(tokio::spawn(sema.clone().with_acquired_at_priority(
+      priority,
+      move |_id| async move {
+        admitted.lock().push(priority);
+      },
+    )));
+    // Ensure that the waiters arrive in order.
+    sleep(Duration::from_millis(10)).await;
+  }
+
+  unblock_holder.send(()).unwrap();
+  holder.await.unwrap();
+  for waiter in waiters {
+    waiter.await.unwrap();
+  }
+  assert_eq!(vec![2, 1, 0, 0], *admitted.lock());
+  assert_eq!(1, sema.available_permits());
+}
@@ -0,0 +1,87 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+// The C API of the engine: see `src/lib.rs` for documentation of each of these declarations, which
+// must be kept in sync with it.
+
+#ifndef PANTS_ENGINE_H
+#define PANTS_ENGINE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+  PANTS_STATUS_OK = 0,
+  PANTS_STATUS_ERROR = 1,
+  PANTS_STATUS_PANIC = 2,
+} PantsStatus;
+
+typedef struct PantsEngine PantsEngine;
+
+typedef struct {
+  uint8_t fingerprint[32];
+  uint64_t size_bytes;
+} PantsDigest;
+
+typedef struct {
+  uint8_t *data;
+  size_t len;
+} PantsBuffer;
+
+typedef struct {
+  const char *store_path;
+  const char *work_dir;
+  const char *named_caches_dir;
+  size_t core_threads;
+  size_t max_threads;
+} PantsEngineOptions;
+
+typedef struct {
+  const char *const *argv;
+  size_t argv_len;
+  const char *const *env;
+  size_t env_len;
+  PantsDigest input_digest;
+  const char *const *output_files;
+  size_t output_files_len;
+  const char *const *output_directories;
+  size_t output_directories_len;
+  const char *description;
+  uint64_t timeout_millis;
+} PantsProcessRequest;
+
+typedef struct {
+  int32_t exit_code;
+  PantsDigest stdout_digest;
+  PantsDigest stderr_digest;
+  PantsDigest output_digest;
+} PantsProcessResult;
+
+const char *pants_last_error(void);
+
+PantsStatus pants_engine_new(const PantsEngineOptions *options, PantsEngine **engine_out);
+void pants_engine_free(PantsEngine *engine);
+
+void pants_buffer_free(PantsBuffer buffer);
+
+PantsStatus pants_store_file_bytes(const PantsEngine *engine, const uint8_t *data, size_t len,
+                                   PantsDigest *digest_out);
+PantsStatus pants_load_file_bytes(const PantsEngine *engine, PantsDigest digest,
+                                  PantsBuffer *buffer_out);
+PantsStatus pants_capture_directory(const PantsEngine *engine, const char *path,
+                                    PantsDigest *digest_out);
+PantsStatus pants_materialize_directory(const PantsEngine *engine, PantsDigest digest,
+                                        const char *path);
+
+PantsStatus pants_process_run(const PantsEngine *engine, const PantsProcessRequest *request,
+                              PantsProcessResult *result_out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif // PANTS_ENGINE_H
@@ -0,0 +1,534 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+#![deny(warnings)]
+// Enable all clippy lints except for many of the pedantic ones. It's a shame this needs to be copied and pasted across crates, but there doesn't appear to be a way to include inner attributes from a common source.
+#![deny(
+  clippy::all,
+  clippy::default_trait_access,
+  clippy::expl_impl_clone_on_copy,
+  clippy::if_not_else,
+  clippy::needless_continue,
+  clippy::unseparated_literal_suffix,
+  // TODO: Falsely triggers for async/await:
+  //   see https://github.com/rust-lang/rust-clippy/issues/5360
+  // clippy::used_underscore_binding
+)]
+// It is often more clear to show that nothing is being moved.
+#![allow(clippy::match_ref_pats)]
+// Subjective style.
+#![allow(
+  clippy::len_without_is_empty,
+  clippy::redundant_field_names,
+  clippy::too_many_arguments
+)]
+// Default isn't as big a deal as people seem to think it is.
+#![allow(clippy::new_without_default, clippy::new_ret_no_self)]
+// Arc<Mutex> can be more clear than needing to grok Orderings:
+#![allow(clippy::mutex_atomic)]
+// The extern functions validate their pointer arguments, but cannot be marked `unsafe` without
+// making them awkward to call from C.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+//!
+//! A C-compatible API for embedding the engine's Store and local process execution in frontends
+//! which are not written in Python. The API is handle-based: a `PantsEngine` is created with
+//! `pants_engine_new`, passed to each call, and destroyed with `pants_engine_free`. No Python types
+//! are involved. The corresponding declarations are in `include/pants_engine.h`.
+//!
+//! Every call returns a `PantsStatus`. When a call fails, a description of the failure is
+//! available from `pants_last_error` on the same thread until the next call.
+//!
+//! NB: The Scheduler is not yet exposed, because its rules are defined in Python.
+//!
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{CStr, CString};
+use std::future::Future;
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use fs::{GlobExpansionConjunction, GlobMatching, PathGlobs, RelativePath, StrictGlobMatching};
+use hashing::{Digest, Fingerprint, FINGERPRINT_SIZE};
+use process_execution::{CommandRunner, Context, NamedCaches, Process};
+use store::{OneOffStoreFileByDigest, Snapshot, Store};
+use task_executor::Executor;
+use workunit_store::WorkunitStore;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PantsStatus {
+  Ok = 0,
+  /// The call failed: see `pants_last_error`.
+  Error = 1,
+  /// The call panicked: see `pants_last_error`. The engine should not be used further.
+  Panic = 2,
+}
+
+///
+/// A Digest, which identifies a file or directory in the Store.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PantsDigest {
+  pub fingerprint: [u8; FINGERPRINT_SIZE],
+  pub size_bytes: u64,
+}
+
+impl From<Digest> for PantsDigest {
+  fn from(digest: Digest) -> PantsDigest {
+    PantsDigest {
+      fingerprint: *digest.hash.as_bytes(),
+      size_bytes: digest.size_bytes as u64,
+    }
+  }
+}
+
+impl From<PantsDigest> for Digest {
+  fn from(digest: PantsDigest) -> Digest {
+    Digest::new(Fingerprint(digest.fingerprint), digest.size_bytes as usize)
+  }
+}
+
+///
+/// A buffer of bytes which is owned by the caller, and which must be released with
+/// `pants_buffer_free`.
+///
+#[repr(C)]
+#[derive(Debug)]
+pub struct PantsBuffer {
+  pub data: *mut u8,
+  pub len: usize,
+}
+
+impl From<Vec<u8>> for PantsBuffer {
+  fn from(bytes: Vec<u8>) -> PantsBuffer {
+    let mut bytes = bytes.into_boxed_slice();
+    let buffer = PantsBuffer {
+      data: bytes.as_mut_ptr(),
+      len: bytes.len(),
+    };
+    std::mem::forget(bytes);
+    buffer
+  }
+}
+
+///
+/// Options for `pants_engine_new`. Only `store_path` is required: the other pointers may be null,
+/// and the thread counts may be zero, in which case defaults are used.
+///
+#[repr(C)]
+#[derive(Debug)]
+pub struct PantsEngineOptions {
+  /// The directory of the local Store.
+  pub store_path: *const c_char,
+  /// The directory below which process sandboxes are created. Defaults to the temp directory.
+  pub work_dir: *const c_char,
+  /// The directory of named caches. Defaults to the same directory as Pants uses.
+  pub named_caches_dir: *const c_char,
+  pub core_threads: usize,
+  pub max_threads: usize,
+}
+
+///
+/// A process to run with `pants_process_run`. Environment variables are `NAME=value` strings, and
+/// output paths are relative to the sandbox.
+///
+#[repr(C)]
+#[derive(Debug)]
+pub struct PantsProcessRequest {
+  pub argv: *const *const c_char,
+  pub argv_len: usize,
+  pub env: *const *const c_char,
+  pub env_len: usize,
+  pub input_digest: PantsDigest,
+  pub output_files: *const *const c_char,
+  pub output_files_len: usize,
+  pub output_directories: *const *const c_char,
+  pub output_directories_len: usize,
+  /// May be null.
+  pub description: *const c_char,
+  /// Zero for no timeout.
+  pub timeout_millis: u64,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PantsProcessResult {
+  pub exit_code: i32,
+  pub stdout_digest: PantsDigest,
+  pub stderr_digest: PantsDigest,
+  pub output_digest: PantsDigest,
+}
+
+///
+/// The engine state behind a handle.
+///
+pub struct PantsEngine {
+  executor: Executor,
+  store: Store,
+  workunit_store: WorkunitStore,
+  runner: Box<dyn CommandRunner>,
+}
+
+impl PantsEngine {
+  fn new(options: &PantsEngineOptions) -> Result<PantsEngine, String> {
+    let store_path = PathBuf::from(str_arg(options.store_path, "store_path")?);
+    let work_dir = optional_str_arg(options.work_dir, "work_dir")?
+      .map(PathBuf::from)
+      .unwrap_or_else(std::env::temp_dir);
+    let named_caches_dir = optional_str_arg(options.named_caches_dir, "named_caches_dir")?
+      .map(PathBuf::from)
+      .unwrap_or_else(NamedCaches::default_path);
+    let core_threads = if options.core_threads == 0 {
+      4
+    } else {
+      options.core_threads
+    };
+    let max_threads = std::cmp::max(options.max_threads, core_threads * 4);
+
+    let executor = Executor::global(core_threads, max_threads)?;
+    let store = Store::local_only(executor.clone(), store_path)?;
+    let runner = Box::new(process_execution::local::CommandRunner::new(
+      store.clone(),
+      executor.clone(),
+      work_dir,
+      NamedCaches::new(named_caches_dir),
+      process_execution::local::KeepSandboxes::Never,
+      process_execution::Redactor::default(),
+    ));
+    Ok(PantsEngine {
+      executor,
+      store,
+      workunit_store: WorkunitStore::new(false),
+      runner,
+    })
+  }
+
+  fn block_on<F: Future>(&self, future: F) -> F::Output {
+    self.workunit_store.init_thread_state(None);
+    self.executor.block_on(future)
+  }
+
+  fn capture_directory(&self, path: PathBuf) -> Result<Digest, String> {
+    let posix_fs = Arc::new(fs::PosixFS::new(
+      path,
+      fs::GitignoreStyleExcludes::empty(),
+      self.executor.clone(),
+    )?);
+    let path_globs = PathGlobs::new(
+      vec!["**".to_owned()],
+      StrictGlobMatching::Ignore,
+      GlobExpansionConjunction::AllMatch,
+    )
+    .parse()?;
+    let store = self.store.clone();
+    self.block_on(async move {
+      let path_stats = posix_fs
+        .expand_globs(path_globs, None)
+        .await
+        .map_err(|e| format!("Error expanding globs: {}", e))?;
+      let snapshot = Snapshot::from_path_stats(
+        store.clone(),
+        OneOffStoreFileByDigest::new(store, posix_fs),
+        path_stats,
+      )
+      .await?;
+      Ok(snapshot.digest)
+    })
+  }
+
+  fn run_process(&self, request: &PantsProcessRequest) -> Result<PantsProcessResult, String> {
+    let env = str_array_arg(request.env, request.env_len, "env")?
+      .into_iter()
+      .map(|var| match var.find('=') {
+        Some(index) => Ok((var[..index].to_owned(), var[index + 1..].to_owned())),
+        None => Err(format!(
+          "Environment variables must be of the form NAME=value: got {:?}",
+          var
+        )),
+      })
+      .collect::<Result<BTreeMap<_, _>, _>>()?;
+    let relative_paths = |paths: *const *const c_char,
+                          len: usize,
+                          name: &str|
+     -> Result<BTreeSet<RelativePath>, String> {
+      str_array_arg(paths, len, name)?
+        .into_iter()
+        .map(RelativePath::new)
+        .collect()
+    };
+
+    let mut process = Process::new(str_array_arg(request.argv, request.argv_len, "argv")?)
+      .env(env)
+      .output_files(relative_paths(
+        request.output_files,
+        request.output_files_len,
+        "output_files",
+      )?)
+      .output_directories(relative_paths(
+        request.output_directories,
+        request.output_directories_len,
+        "output_directories",
+      )?);
+    process.input_files = request.input_digest.into();
+    if let Some(description) = optional_str_arg(request.description, "description")? {
+      process.description = description;
+    }
+    if request.timeout_millis > 0 {
+      process.timeout = Some(Duration::from_millis(request.timeout_millis));
+    }
+
+    let context = Context::new(
+      self.workunit_store.clone(),
+      String::default(),
+      String::default(),
+      process_execution::SessionPriority::default(),
+      Arc::default(),
+    );
+    let result = self.block_on(self.runner.run(process.into(), context))?;
+    Ok(PantsProcessResult {
+      exit_code: result.exit_code,
+      stdout_digest: result.stdout_digest.into(),
+      stderr_digest: result.stderr_digest.into(),
+      output_digest: result.output_directory.into(),
+    })
+  }
+}
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(error: String) {
+  // NB: Errors cannot contain interior nul bytes, so we replace them.
+  let error = CString::new(error.replace('\0', "\\0")).unwrap();
+  LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(error));
+}
+
+///
+/// Runs the body of an extern function, recording its error (or panic) for `pants_last_error`.
+///
+fn ffi_call<F: FnOnce() -> Result<(), String>>(f: F) -> PantsStatus {
+  LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+  match catch_unwind(AssertUnwindSafe(f)) {
+    Ok(Ok(())) => PantsStatus::Ok,
+    Ok(Err(e)) => {
+      set_last_error(e);
+      PantsStatus::Error
+    }
+    Err(panic) => {
+      let message = panic
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| panic.downcast_ref::<&str>().map(|s| (*s).to_owned()))
+        .unwrap_or_else(|| "(unknown panic payload)".to_owned());
+      set_last_error(format!("The engine panicked: {}", message));
+      PantsStatus::Panic
+    }
+  }
+}
+
+fn str_arg<'a>(value: *const c_char, name: &str) -> Result<&'a str, String> {
+  optional_str_arg(value, name)?.ok_or_else(|| format!("`{}` may not be null.", name))
+}
+
+fn optional_str_arg<'a>(value: *const c_char, name: &str) -> Result<Option<&'a str>, String> {
+  if value.is_null() {
+    return Ok(None);
+  }
+  unsafe { CStr::from_ptr(value) }
+    .to_str()
+    .map(Some)
+    .map_err(|e| format!("`{}` was not valid UTF-8: {}", name, e))
+}
+
+fn str_array_arg(
+  values: *const *const c_char,
+  len: usize,
+  name: &str,
+) -> Result<Vec<String>, String> {
+  if len == 0 {
+    return Ok(vec![]);
+  }
+  if values.is_null() {
+    return Err(format!("`{}` may not be null.", name));
+  }
+  unsafe { std::slice::from_raw_parts(values, len) }
+    .iter()
+    .map(|value| str_arg(*value, name).map(str::to_owned))
+    .collect()
+}
+
+fn engine_arg<'a>(engine: *const PantsEngine) -> Result<&'a PantsEngine, String> {
+  unsafe { engine.as_ref() }.ok_or_else(|| "`engine` may not be null.".to_owned())
+}
+
+fn out_arg<'a, T>(out: *mut T, name: &str) -> Result<&'a mut T, String> {
+  unsafe { out.as_mut() }.ok_or_else(|| format!("`{}` may not be null.", name))
+}
+
+///
+/// Returns a description of the last failed call on this thread, or null. The string is owned by
+/// the engine, and is valid until the next call on this thread.
+///
+#[no_mangle]
+pub extern "C" fn pants_last_error() -> *const c_char {
+  LAST_ERROR.with(|last_error| {
+    last_error
+      .borrow()
+      .as_ref()
+      .map(|error| error.as_ptr())
+      .unwrap_or_else(ptr::null)
+  })
+}
+
+#[no_mangle]
+pub extern "C" fn pants_engine_new(
+  options: *const PantsEngineOptions,
+  engine_out: *mut *mut PantsEngine,
+) -> PantsStatus {
+  ffi_call(|| {
+    let options = unsafe { options.as_ref() }.ok_or_else(|| "`options` may not be null.")?;
+    let engine_out = out_arg(engine_out, "engine_out")?;
+    *engine_out = Box::into_raw(Box::new(PantsEngine::new(options)?));
+    Ok(())
+  })
+}
+
+///
+/// Destroys an engine. Null is ignored.
+///
+#[no_mangle]
+pub extern "C" fn pants_engine_free(engine: *mut PantsEngine) {
+  if !engine.is_null() {
+    std::mem::drop(unsafe { Box::from_raw(engine) });
+  }
+}
+
+///
+/// Releases a buffer which was returned by the engine. Empty buffers are ignored.
+///
+#[no_mangle]
+pub extern "C" fn pants_buffer_free(buffer: PantsBuffer) {
+  if !buffer.data.is_null() {
+    std::mem::drop(unsafe {
+      Box::from_raw(std::slice::from_raw_parts_mut(buffer.data, buffer.len))
+    });
+  }
+}
+
+///
+/// Stores the given bytes as a file in the local Store, and returns its Digest.
+///
+#[no_mangle]
+pub extern "C" fn pants_store_file_bytes(
+  engine: *const PantsEngine,
+  data: *const u8,
+  len: usize,
+  digest_out: *mut PantsDigest,
+) -> PantsStatus {
+  ffi_call(|| {
+    let engine = engine_arg(engine)?;
+    let digest_out = out_arg(digest_out, "digest_out")?;
+    let bytes = if len == 0 {
+      Bytes::new()
+    } else if data.is_null() {
+      return Err("`data` may not be null.".to_owned());
+    } else {
+      Bytes::copy_from_slice(unsafe { std::slice::from_raw_parts(data, len) })
+    };
+    let digest = engine.block_on(engine.store.store_file_bytes(bytes, true))?;
+    *digest_out = digest.into();
+    Ok(())
+  })
+}
+
+///
+/// Loads the bytes of the file with the given Digest from the Store.
+///
+#[no_mangle]
+pub extern "C" fn pants_load_file_bytes(
+  engine: *const PantsEngine,
+  digest: PantsDigest,
+  buffer_out: *mut PantsBuffer,
+) -> PantsStatus {
+  ffi_call(|| {
+    let engine = engine_arg(engine)?;
+    let buffer_out = out_arg(buffer_out, "buffer_out")?;
+    let digest: Digest = digest.into();
+    let (bytes, _metadata) = engine
+      .block_on(
+        engine
+          .store
+          .load_file_bytes_with(digest, |bytes| bytes.to_vec()),
+      )?
+      .ok_or_else(|| format!("File with digest {:?} was not found in the Store.", digest))?;
+    *buffer_out = bytes.into();
+    Ok(())
+  })
+}
+
+///
+/// Captures the contents of the given directory into the Store, and returns the Digest of the
+/// resulting directory tree.
+///
+#[no_mangle]
+pub extern "C" fn pants_capture_directory(
+  engine: *const PantsEngine,
+  path: *const c_char,
+  digest_out: *mut PantsDigest,
+) -> PantsStatus {
+  ffi_call(|| {
+    let engine = engine_arg(engine)?;
+    let path = PathBuf::from(str_arg(path, "path")?);
+    let digest_out = out_arg(digest_out, "digest_out")?;
+    *digest_out = engine.capture_directory(path)?.into();
+    Ok(())
+  })
+}
+
+///
+/// Materializes the directory tree with the given Digest to the given (absolute) path.
+///
+#[no_mangle]
+pub extern "C" fn pants_materialize_directory(
+  engine: *const PantsEngine,
+  digest: PantsDigest,
+  path: *const c_char,
+) -> PantsStatus {
+  ffi_call(|| {
+    let engine = engine_arg(engine)?;
+    let path = PathBuf::from(str_arg(path, "path")?);
+    engine.block_on(engine.store.materialize_directory(path, digest.into()))?;
+    Ok(())
+  })
+}
+
+///
+/// Runs a process locally, in a sandbox which contains its input digest, and returns its exit
+/// code and the Digests of its stdout, stderr and outputs.
+///
+#[no_mangle]
+pub extern "C" fn pants_process_run(
+  engine: *const PantsEngine,
+  request: *const PantsProcessRequest,
+  result_out: *mut PantsProcessResult,
+) -> PantsStatus {
+  ffi_call(|| {
+    let engine = engine_arg(engine)?;
+    let request = unsafe { request.as_ref() }.ok_or_else(|| "`request` may not be null.")?;
+    let result_out = out_arg(result_out, "result_out")?;
+    *result_out = engine.run_process(request)?;
+    Ok(())
+  })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,170 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+use tempfile::TempDir;
+
+use crate::{
+  pants_buffer_free, pants_capture_directory, pants_engine_free, pants_engine_new,
+  pants_last_error, pants_load_file_bytes, pants_process_run, pants_store_file_bytes, PantsBuffer,
+  PantsDigest, PantsEngine, PantsEngineOptions, PantsProcessRequest, PantsProcessResult,
+  PantsStatus,
+};
+
+struct TestEngine {
+  engine: *mut PantsEngine,
+  _store_dir: TempDir,
+  _work_dir: TempDir,
+}
+
+impl TestEngine {
+  fn new() -> TestEngine {
+    let store_dir = TempDir::new().unwrap();
+    let work_dir = TempDir::new().unwrap();
+    let store_path = CString::new(store_dir.path().to_str().unwrap()).unwrap();
+    let work_dir_path = CString::new(work_dir.path().to_str().unwrap()).unwrap();
+    let options = PantsEngineOptions {
+      store_path: store_path.as_ptr(),
+      work_dir: work_dir_path.as_ptr(),
+      named_caches_dir: work_dir_path.as_ptr(),
+      core_threads: 0,
+      max_threads: 0,
+    };
+    let mut engine = ptr::null_mut();
+    assert_eq!(pants_engine_new(&options, &mut engine), PantsStatus::Ok);
+    TestEngine {
+      engine,
+      _store_dir: store_dir,
+      _work_dir: work_dir,
+    }
+  }
+
+  fn load(&self, digest: PantsDigest) -> Vec<u8> {
+    let mut buffer = PantsBuffer {
+      data: ptr::null_mut(),
+      len: 0,
+    };
+    assert_eq!(
+      pants_load_file_bytes(self.engine, digest, &mut buffer),
+      PantsStatus::Ok
+    );
+    let bytes = unsafe { std::slice::from_raw_parts(buffer.data, buffer.len) }.to_vec();
+    pants_buffer_free(buffer);
+    bytes
+  }
+}
+
+impl Drop for TestEngine {
+  fn drop(&mut self) {
+    pants_engine_free(self.engine);
+  }
+}
+
+fn last_error() -> String {
+  unsafe { CStr::from_ptr(pants_last_error()) }
+    .to_str()
+    .unwrap()
+    .to_owned()
+}
+
+fn c_strings(strs: &[&str]) -> (Vec<CString>, Vec<*const c_char>) {
+  let owned = strs
+    .iter()
+    .map(|s| CString::new(*s).unwrap())
+    .collect::<Vec<_>>();
+  let pointers = owned.iter().map(|s| s.as_ptr()).collect();
+  (owned, pointers)
+}
+
+#[test]
+fn store_round_trip() {
+  let engine = TestEngine::new();
+  let data = b"roland";
+  let mut digest = PantsDigest {
+    fingerprint: [0; 32],
+    size_bytes: 0,
+  };
+  assert_eq!(
+    pants_store_file_bytes(engine.engine, data.as_ptr(), data.len(), &mut digest),
+    PantsStatus::Ok
+  );
+  assert_eq!(digest.size_bytes, 6);
+  assert_eq!(engine.load(digest), data.to_vec());
+}
+
+#[test]
+fn errors_are_reported() {
+  let engine = TestEngine::new();
+  let mut buffer = PantsBuffer {
+    data: ptr::null_mut(),
+    len: 0,
+  };
+  let missing = PantsDigest {
+    fingerprint: [1; 32],
+    size_bytes: 3,
+  };
+  assert_eq!(
+    pants_load_file_bytes(engine.engine, missing, &mut buffer),
+    PantsStatus::Error
+  );
+  assert!(last_error().contains("was not found"));
+
+  assert_eq!(
+    pants_load_file_bytes(ptr::null(), missing, &mut buffer),
+    PantsStatus::Error
+  );
+  assert_eq!(last_error(), "`engine` may not be null.");
+}
+
+#[test]
+fn run_process() {
+  let engine = TestEngine::new();
+
+  let input_dir = TempDir::new().unwrap();
+  std::fs::write(input_dir.path().join("input.txt"), "hello").unwrap();
+  let input_path = CString::new(input_dir.path().to_str().unwrap()).unwrap();
+  let mut input_digest = PantsDigest {
+    fingerprint: [0; 32],
+    size_bytes: 0,
+  };
+  assert_eq!(
+    pants_capture_directory(engine.engine, input_path.as_ptr(), &mut input_digest),
+    PantsStatus::Ok
+  );
+
+  let (_argv, argv) = c_strings(&[
+    "/bin/bash",
+    "-c",
+    "cat input.txt; echo -n \"$GREETING\" > out.txt",
+  ]);
+  let (_env, env) = c_strings(&["GREETING=bonjour"]);
+  let (_output_files, output_files) = c_strings(&["out.txt"]);
+  let request = PantsProcessRequest {
+    argv: argv.as_ptr(),
+    argv_len: argv.len(),
+    env: env.as_ptr(),
+    env_len: env.len(),
+    input_digest,
+    output_files: output_files.as_ptr(),
+    output_files_len: output_files.len(),
+    output_directories: ptr::null(),
+    output_directories_len: 0,
+    description: ptr::null(),
+    timeout_millis: 0,
+  };
+  let mut result = PantsProcessResult {
+    exit_code: -1,
+    stdout_digest: input_digest,
+    stderr_digest: input_digest,
+    output_digest: input_digest,
+  };
+  assert_eq!(
+    pants_process_run(engine.engine, &request, &mut result),
+    PantsStatus::Ok,
+    "{}",
+    last_error()
+  );
+  assert_eq!(result.exit_code, 0);
+  assert_eq!(engine.load(result.stdout_digest), b"hello".to_vec());
+  assert_ne!(result.output_digest, input_digest);
+}
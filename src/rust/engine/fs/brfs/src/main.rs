@@ -744,8 +744,12 @@ async fn main() {
         root_ca_certs,
         headers,
         4 * 1024 * 1024,
+        None,
         std::time::Duration::from_secs(5 * 60),
         1,
+        None,
+        None,
+        None,
       )
       .expect("Error making remote store"),
     None => local_only_store,
@@ -0,0 +1,593 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+#![deny(warnings)]
+// Enable all clippy lints except for many of the pedantic ones. It's a shame this needs to be copied and pasted across crates, but there doesn't appear to be a way to include inner attributes from a common source.
+#![deny(
+  clippy::all,
+  clippy::default_trait_access,
+  clippy::expl_impl_clone_on_copy,
+  clippy::if_not_else,
+  clippy::needless_continue,
+  clippy::unseparated_literal_suffix,
+  // TODO: Falsely triggers for async/await:
+  //   see https://github.com/rust-lang/rust-clippy/issues/5360
+  // clippy::used_underscore_binding
+)]
+// It is often more clear to show that nothing is being moved.
+#![allow(clippy::match_ref_pats)]
+// Subjective style.
+#![allow(
+  clippy::len_without_is_empty,
+  clippy::redundant_field_names,
+  clippy::too_many_arguments
+)]
+// Default isn't as big a deal as people seem to think it is.
+#![allow(clippy::new_without_default, clippy::new_ret_no_self)]
+// Arc<Mutex> can be more clear than needing to grok Orderings:
+#![allow(clippy::mutex_atomic)]
+
+use std::convert::TryInto;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
+use bazel_protos::gen::build::bazel::semver::SemVer;
+use bazel_protos::gen::google::bytestream::byte_stream_server::{ByteStream, ByteStreamServer};
+use bazel_protos::gen::google::bytestream::{
+  QueryWriteStatusRequest, QueryWriteStatusResponse, ReadRequest, ReadResponse, WriteRequest,
+  WriteResponse,
+};
+use bazel_protos::gen::google::rpc::Status as StatusProto;
+use bazel_protos::require_digest;
+use bytes::{Bytes, BytesMut};
+use futures::stream::StreamExt;
+use futures::{FutureExt, Stream};
+use hashing::{Digest, Fingerprint, EMPTY_DIGEST};
+use hyper::server::accept::Accept;
+use hyper::server::conn::{AddrIncoming, AddrStream};
+use log::{debug, error};
+use prost::Message;
+use remexec::action_cache_server::{ActionCache, ActionCacheServer};
+use remexec::capabilities_server::{Capabilities, CapabilitiesServer};
+use remexec::content_addressable_storage_server::{
+  ContentAddressableStorage, ContentAddressableStorageServer,
+};
+use remexec::{
+  batch_read_blobs_response, batch_update_blobs_response, ActionCacheUpdateCapabilities,
+  ActionResult, BatchReadBlobsRequest, BatchReadBlobsResponse, BatchUpdateBlobsRequest,
+  BatchUpdateBlobsResponse, CacheCapabilities, FindMissingBlobsRequest, FindMissingBlobsResponse,
+  GetActionResultRequest, GetCapabilitiesRequest, GetTreeRequest, GetTreeResponse,
+  ServerCapabilities, UpdateActionResultRequest,
+};
+use sharded_lmdb::ShardedLmdb;
+use store::Store;
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status};
+
+///
+/// The maximum number of bytes of content included in each message of a streamed read.
+///
+const READ_CHUNK_SIZE_BYTES: usize = 64 * 1024;
+
+///
+/// The maximum total size of blobs which clients may send or request in a single batch RPC.
+///
+const MAX_BATCH_TOTAL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+///
+/// A minimal server for the caching portions of the Remote Execution API: the
+/// ContentAddressableStorage, ByteStream, ActionCache and Capabilities services.
+///
+/// Blobs are held in a local `Store` (as files, since the CAS is untyped), and ActionResults are
+/// held in a separate `ShardedLmdb` keyed by the fingerprint of their Action. This allows a shared
+/// host to provide remote caching without any third-party infrastructure.
+///
+/// The server stops when it is dropped.
+///
+pub struct CacheServer {
+  local_addr: SocketAddr,
+  shutdown_sender: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl CacheServer {
+  ///
+  /// Binds to the given address (which may use port 0 to select an ephemeral port), and begins
+  /// serving in the background. Must be called from within a Tokio runtime.
+  ///
+  pub fn start(
+    store: Store,
+    action_cache: ShardedLmdb,
+    instance_name: Option<String>,
+    addr: SocketAddr,
+  ) -> Result<CacheServer, String> {
+    let responder = CacheResponder {
+      store,
+      action_cache,
+      instance_name: instance_name.unwrap_or_default(),
+    };
+
+    let incoming =
+      AddrIncoming::bind(&addr).map_err(|e| format!("Failed to bind to {}: {}", addr, e))?;
+    let local_addr = incoming.local_addr();
+    let incoming = AddrIncomingWithStream(incoming);
+
+    let (shutdown_sender, shutdown_receiver) = tokio::sync::oneshot::channel();
+
+    tokio::spawn(async move {
+      let mut server = Server::builder();
+      let router = server
+        .add_service(ByteStreamServer::new(responder.clone()))
+        .add_service(ContentAddressableStorageServer::new(responder.clone()))
+        .add_service(ActionCacheServer::new(responder.clone()))
+        .add_service(CapabilitiesServer::new(responder));
+
+      if let Err(e) = router
+        .serve_with_incoming_shutdown(incoming, shutdown_receiver.map(drop))
+        .await
+      {
+        error!("Cache server exited with an error: {}", e);
+      }
+    });
+
+    Ok(CacheServer {
+      local_addr,
+      shutdown_sender: Some(shutdown_sender),
+    })
+  }
+
+  ///
+  /// The address on which this server is listening over insecure HTTP transport.
+  ///
+  pub fn address(&self) -> String {
+    format!("http://{}", self.local_addr)
+  }
+}
+
+impl Drop for CacheServer {
+  fn drop(&mut self) {
+    if let Some(s) = self.shutdown_sender.take() {
+      let _ = s.send(());
+    }
+  }
+}
+
+struct AddrIncomingWithStream(AddrIncoming);
+
+impl Stream for AddrIncomingWithStream {
+  type Item = Result<AddrStream, std::io::Error>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    Pin::new(&mut self.0).poll_accept(cx)
+  }
+}
+
+#[derive(Clone)]
+struct CacheResponder {
+  store: Store,
+  action_cache: ShardedLmdb,
+  instance_name: String,
+}
+
+impl CacheResponder {
+  fn check_instance_name(&self, instance_name: &str) -> Result<(), Status> {
+    // NB: Clients differ in whether they include a leading slash in resource names when the
+    // instance name is empty.
+    if instance_name.trim_matches('/') == self.instance_name {
+      Ok(())
+    } else {
+      Err(Status::not_found(format!(
+        "Instance {} does not exist",
+        instance_name
+      )))
+    }
+  }
+
+  ///
+  /// Parses the Digest from a ByteStream resource name, which is either of the form
+  /// `{instance_name}/blobs/{hash}/{size}` for reads, or of the form
+  /// `{instance_name}/uploads/{uuid}/blobs/{hash}/{size}{/optional_metadata}` for writes.
+  ///
+  fn parse_resource_name(&self, resource_name: &str, upload: bool) -> Result<Digest, Status> {
+    let invalid = || Status::invalid_argument(format!("Bad resource name: {}", resource_name));
+    let parts: Vec<_> = resource_name.split('/').collect();
+    let blobs_index = parts
+      .iter()
+      .position(|part| *part == "blobs")
+      .ok_or_else(invalid)?;
+    let instance_name_parts = if upload {
+      if blobs_index < 2 || parts[blobs_index - 2] != "uploads" {
+        return Err(invalid());
+      }
+      &parts[..blobs_index - 2]
+    } else {
+      &parts[..blobs_index]
+    };
+    self.check_instance_name(&instance_name_parts.join("/"))?;
+
+    let fingerprint = parts
+      .get(blobs_index + 1)
+      .and_then(|hash| Fingerprint::from_hex_string(hash).ok())
+      .ok_or_else(invalid)?;
+    let size_bytes = parts
+      .get(blobs_index + 2)
+      .and_then(|size_bytes| size_bytes.parse::<usize>().ok())
+      .ok_or_else(invalid)?;
+    Ok(Digest::new(fingerprint, size_bytes))
+  }
+
+  async fn load_bytes(&self, digest: Digest) -> Result<Option<Bytes>, Status> {
+    if digest == EMPTY_DIGEST {
+      return Ok(Some(Bytes::new()));
+    }
+    let maybe_bytes = self
+      .store
+      .load_file_bytes_with(digest, Bytes::copy_from_slice)
+      .await
+      .map_err(Status::internal)?;
+    Ok(maybe_bytes.map(|(bytes, _metadata)| bytes))
+  }
+
+  async fn store_bytes(&self, digest: Digest, bytes: Bytes) -> Result<(), Status> {
+    if bytes.len() != digest.size_bytes {
+      return Err(Status::invalid_argument(format!(
+        "Size was incorrect: {:?} was declared, but {} bytes were received",
+        digest,
+        bytes.len()
+      )));
+    }
+    let stored_digest = self
+      .store
+      .store_file_bytes(bytes, true)
+      .await
+      .map_err(Status::internal)?;
+    if stored_digest != digest {
+      return Err(Status::invalid_argument(format!(
+        "Digest was incorrect: {:?} was declared, but the content had digest {:?}",
+        digest, stored_digest
+      )));
+    }
+    Ok(())
+  }
+
+  async fn is_present(&self, digest: Digest) -> Result<bool, Status> {
+    if digest == EMPTY_DIGEST {
+      return Ok(true);
+    }
+    let maybe_present = self
+      .store
+      .load_file_bytes_with(digest, |_| ())
+      .await
+      .map_err(Status::internal)?;
+    Ok(maybe_present.is_some())
+  }
+}
+
+fn status_proto(code: Code, message: String) -> Option<StatusProto> {
+  Some(StatusProto {
+    code: code as i32,
+    message,
+    ..StatusProto::default()
+  })
+}
+
+///
+/// The Digests of the blobs which an ActionResult references directly, all of which must be
+/// present in the CAS for the ActionResult to be usable.
+///
+fn referenced_digests(action_result: &ActionResult) -> Result<Vec<Digest>, String> {
+  let mut digests = Vec::new();
+  for output_file in &action_result.output_files {
+    digests.push(require_digest(output_file.digest.as_ref())?);
+  }
+  for output_directory in &action_result.output_directories {
+    digests.push(require_digest(output_directory.tree_digest.as_ref())?);
+  }
+  if let Some(stdout_digest) = &action_result.stdout_digest {
+    digests.push(stdout_digest.try_into()?);
+  }
+  if let Some(stderr_digest) = &action_result.stderr_digest {
+    digests.push(stderr_digest.try_into()?);
+  }
+  Ok(digests)
+}
+
+#[tonic::async_trait]
+impl ByteStream for CacheResponder {
+  type ReadStream = Pin<Box<dyn Stream<Item = Result<ReadResponse, Status>> + Send + Sync>>;
+
+  async fn read(
+    &self,
+    request: Request<ReadRequest>,
+  ) -> Result<Response<Self::ReadStream>, Status> {
+    let request = request.into_inner();
+    let digest = self.parse_resource_name(&request.resource_name, false)?;
+    let bytes = self
+      .load_bytes(digest)
+      .await?
+      .ok_or_else(|| Status::not_found(format!("Did not find digest {:?}", digest)))?;
+
+    if request.read_offset < 0 || request.read_offset as usize > bytes.len() {
+      return Err(Status::out_of_range(format!(
+        "Read offset {} is out of range for {:?}",
+        request.read_offset, digest
+      )));
+    }
+    if request.read_limit < 0 {
+      return Err(Status::invalid_argument(format!(
+        "Read limit {} must not be negative",
+        request.read_limit
+      )));
+    }
+    let start = request.read_offset as usize;
+    let end = if request.read_limit > 0 {
+      bytes.len().min(start + request.read_limit as usize)
+    } else {
+      bytes.len()
+    };
+    let bytes = bytes.slice(start..end);
+
+    let responses: Vec<_> = (0..bytes.len())
+      .step_by(READ_CHUNK_SIZE_BYTES)
+      .map(|chunk_start| {
+        let chunk_end = bytes.len().min(chunk_start + READ_CHUNK_SIZE_BYTES);
+        Ok(ReadResponse {
+          data: bytes.slice(chunk_start..chunk_end),
+        })
+      })
+      .collect();
+    Ok(Response::new(Box::pin(futures::stream::iter(responses))))
+  }
+
+  async fn write(
+    &self,
+    request: Request<tonic::Streaming<WriteRequest>>,
+  ) -> Result<Response<WriteResponse>, Status> {
+    let mut stream = request.into_inner();
+
+    let mut maybe_digest = None;
+    let mut bytes = BytesMut::new();
+    while let Some(req) = stream.next().await {
+      let req = req?;
+      // Only the first request in a stream is required to include the resource name.
+      if maybe_digest.is_none() {
+        maybe_digest = Some(self.parse_resource_name(&req.resource_name, true)?);
+      }
+      if req.write_offset != bytes.len() as i64 {
+        return Err(Status::invalid_argument(format!(
+          "Missing chunk. Expected next offset {}, got next offset: {}",
+          bytes.len(),
+          req.write_offset
+        )));
+      }
+      bytes.extend_from_slice(&req.data);
+      if req.finish_write {
+        break;
+      }
+    }
+
+    let digest =
+      maybe_digest.ok_or_else(|| Status::invalid_argument("Stream saw no messages".to_owned()))?;
+    self.store_bytes(digest, bytes.freeze()).await?;
+    debug!("Stored {:?}", digest);
+    Ok(Response::new(WriteResponse {
+      committed_size: digest.size_bytes as i64,
+    }))
+  }
+
+  async fn query_write_status(
+    &self,
+    _: Request<QueryWriteStatusRequest>,
+  ) -> Result<Response<QueryWriteStatusResponse>, Status> {
+    Err(Status::unimplemented(
+      "Resumable writes are not supported.".to_owned(),
+    ))
+  }
+}
+
+#[tonic::async_trait]
+impl ContentAddressableStorage for CacheResponder {
+  async fn find_missing_blobs(
+    &self,
+    request: Request<FindMissingBlobsRequest>,
+  ) -> Result<Response<FindMissingBlobsResponse>, Status> {
+    let request = request.into_inner();
+    self.check_instance_name(&request.instance_name)?;
+
+    let mut response = FindMissingBlobsResponse::default();
+    for digest in request.blob_digests {
+      let digest: Digest = digest.try_into().map_err(Status::invalid_argument)?;
+      if !self.is_present(digest).await? {
+        response.missing_blob_digests.push(digest.into());
+      }
+    }
+    Ok(Response::new(response))
+  }
+
+  async fn batch_update_blobs(
+    &self,
+    request: Request<BatchUpdateBlobsRequest>,
+  ) -> Result<Response<BatchUpdateBlobsResponse>, Status> {
+    let request = request.into_inner();
+    self.check_instance_name(&request.instance_name)?;
+
+    let total_size_bytes: usize = request.requests.iter().map(|r| r.data.len()).sum();
+    if total_size_bytes > MAX_BATCH_TOTAL_SIZE_BYTES {
+      return Err(Status::invalid_argument(format!(
+        "Batch of {} bytes exceeds the maximum of {} bytes",
+        total_size_bytes, MAX_BATCH_TOTAL_SIZE_BYTES
+      )));
+    }
+
+    let mut response = BatchUpdateBlobsResponse::default();
+    for blob in request.requests {
+      let digest = require_digest(blob.digest.as_ref()).map_err(Status::invalid_argument)?;
+      let status = match self.store_bytes(digest, blob.data).await {
+        Ok(()) => status_proto(Code::Ok, String::new()),
+        Err(status) => status_proto(status.code(), status.message().to_owned()),
+      };
+      response
+        .responses
+        .push(batch_update_blobs_response::Response {
+          digest: Some(digest.into()),
+          status,
+        });
+    }
+    Ok(Response::new(response))
+  }
+
+  async fn batch_read_blobs(
+    &self,
+    request: Request<BatchReadBlobsRequest>,
+  ) -> Result<Response<BatchReadBlobsResponse>, Status> {
+    let request = request.into_inner();
+    self.check_instance_name(&request.instance_name)?;
+
+    let total_size_bytes: i64 = request.digests.iter().map(|d| d.size_bytes).sum();
+    if total_size_bytes > MAX_BATCH_TOTAL_SIZE_BYTES as i64 {
+      return Err(Status::invalid_argument(format!(
+        "Batch of {} bytes exceeds the maximum of {} bytes",
+        total_size_bytes, MAX_BATCH_TOTAL_SIZE_BYTES
+      )));
+    }
+
+    let mut response = BatchReadBlobsResponse::default();
+    for digest in request.digests {
+      let digest: Digest = digest.try_into().map_err(Status::invalid_argument)?;
+      let (data, status) = match self.load_bytes(digest).await {
+        Ok(Some(bytes)) => (bytes, status_proto(Code::Ok, String::new())),
+        Ok(None) => (
+          Bytes::new(),
+          status_proto(Code::NotFound, format!("Did not find digest {:?}", digest)),
+        ),
+        Err(status) => (
+          Bytes::new(),
+          status_proto(status.code(), status.message().to_owned()),
+        ),
+      };
+      response
+        .responses
+        .push(batch_read_blobs_response::Response {
+          digest: Some(digest.into()),
+          data,
+          status,
+        });
+    }
+    Ok(Response::new(response))
+  }
+
+  type GetTreeStream = tonic::codec::Streaming<GetTreeResponse>;
+
+  async fn get_tree(
+    &self,
+    _: Request<GetTreeRequest>,
+  ) -> Result<Response<Self::GetTreeStream>, Status> {
+    Err(Status::unimplemented(
+      "GetTree is not supported.".to_owned(),
+    ))
+  }
+}
+
+#[tonic::async_trait]
+impl ActionCache for CacheResponder {
+  async fn get_action_result(
+    &self,
+    request: Request<GetActionResultRequest>,
+  ) -> Result<Response<ActionResult>, Status> {
+    let request = request.into_inner();
+    self.check_instance_name(&request.instance_name)?;
+    let action_digest =
+      require_digest(request.action_digest.as_ref()).map_err(Status::invalid_argument)?;
+
+    let maybe_action_result = self
+      .action_cache
+      .load_bytes_with(action_digest.hash, |bytes| {
+        ActionResult::decode(bytes).map_err(|e| format!("Invalid ActionResult: {:?}", e))
+      })
+      .await
+      .map_err(Status::internal)?;
+    let action_result = maybe_action_result.ok_or_else(|| {
+      Status::not_found(format!(
+        "ActionResult for Action {:?} does not exist",
+        action_digest
+      ))
+    })?;
+
+    // An ActionResult which references blobs that are no longer in the CAS is not usable, and so
+    // is treated as a cache miss.
+    for digest in referenced_digests(&action_result).map_err(Status::internal)? {
+      if !self.is_present(digest).await? {
+        return Err(Status::not_found(format!(
+          "ActionResult for Action {:?} references missing output {:?}",
+          action_digest, digest
+        )));
+      }
+    }
+
+    Ok(Response::new(action_result))
+  }
+
+  async fn update_action_result(
+    &self,
+    request: Request<UpdateActionResultRequest>,
+  ) -> Result<Response<ActionResult>, Status> {
+    let request = request.into_inner();
+    self.check_instance_name(&request.instance_name)?;
+    let action_digest =
+      require_digest(request.action_digest.as_ref()).map_err(Status::invalid_argument)?;
+    let action_result = request
+      .action_result
+      .ok_or_else(|| Status::invalid_argument("Must provide action result".to_owned()))?;
+
+    let mut bytes = BytesMut::with_capacity(action_result.encoded_len());
+    action_result
+      .encode(&mut bytes)
+      .map_err(|e| Status::internal(format!("Failed to encode ActionResult: {:?}", e)))?;
+    self
+      .action_cache
+      .store_bytes(action_digest.hash, bytes.freeze(), true)
+      .await
+      .map_err(Status::internal)?;
+    debug!("Stored ActionResult for {:?}", action_digest);
+
+    Ok(Response::new(action_result))
+  }
+}
+
+#[tonic::async_trait]
+impl Capabilities for CacheResponder {
+  async fn get_capabilities(
+    &self,
+    request: Request<GetCapabilitiesRequest>,
+  ) -> Result<Response<ServerCapabilities>, Status> {
+    let request = request.into_inner();
+    self.check_instance_name(&request.instance_name)?;
+
+    let response = ServerCapabilities {
+      cache_capabilities: Some(CacheCapabilities {
+        digest_function: vec![remexec::digest_function::Value::Sha256 as i32],
+        action_cache_update_capabilities: Some(ActionCacheUpdateCapabilities {
+          update_enabled: true,
+        }),
+        max_batch_total_size_bytes: MAX_BATCH_TOTAL_SIZE_BYTES as i64,
+        ..CacheCapabilities::default()
+      }),
+      low_api_version: Some(SemVer {
+        major: 2,
+        ..SemVer::default()
+      }),
+      high_api_version: Some(SemVer {
+        major: 2,
+        minor: 1,
+        ..SemVer::default()
+      }),
+      ..ServerCapabilities::default()
+    };
+
+    Ok(Response::new(response))
+  }
+}
+
+#[cfg(test)]
+mod tests;
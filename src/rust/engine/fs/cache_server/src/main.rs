@@ -0,0 +1,120 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+#![deny(warnings)]
+// Enable all clippy lints except for many of the pedantic ones. It's a shame this needs to be copied and pasted across crates, but there doesn't appear to be a way to include inner attributes from a common source.
+#![deny(
+  clippy::all,
+  clippy::default_trait_access,
+  clippy::expl_impl_clone_on_copy,
+  clippy::if_not_else,
+  clippy::needless_continue,
+  clippy::unseparated_literal_suffix,
+  // TODO: Falsely triggers for async/await:
+  //   see https://github.com/rust-lang/rust-clippy/issues/5360
+  // clippy::used_underscore_binding
+)]
+// It is often more clear to show that nothing is being moved.
+#![allow(clippy::match_ref_pats)]
+// Subjective style.
+#![allow(
+  clippy::len_without_is_empty,
+  clippy::redundant_field_names,
+  clippy::too_many_arguments
+)]
+// Default isn't as big a deal as people seem to think it is.
+#![allow(clippy::new_without_default, clippy::new_ret_no_self)]
+// Arc<Mutex> can be more clear than needing to grok Orderings:
+#![allow(clippy::mutex_atomic)]
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use cache_server::CacheServer;
+use clap::{value_t, App, Arg};
+use sharded_lmdb::ShardedLmdb;
+use store::{LocalOptions, ShrinkBehavior, Store};
+
+#[tokio::main]
+async fn main() -> Result<(), String> {
+  env_logger::init();
+
+  let matches = App::new("cache_server")
+    .about(
+      "A Remote Execution API server which provides a CAS and action cache (but not remote \
+       execution), backed by a local store.",
+    )
+    .arg(
+      Arg::with_name("address")
+        .long("address")
+        .takes_value(true)
+        .default_value("0.0.0.0:9092")
+        .help("The address to listen on."),
+    )
+    .arg(
+      Arg::with_name("instance-name")
+        .long("instance-name")
+        .takes_value(true)
+        .default_value("")
+        .help("The REAPI instance name that clients must use."),
+    )
+    .arg(
+      Arg::with_name("store-dir")
+        .long("store-dir")
+        .takes_value(true)
+        .required(true)
+        .help("The directory in which to store blobs and action results."),
+    )
+    .arg(
+      Arg::with_name("target-size-bytes")
+        .long("target-size-bytes")
+        .takes_value(true)
+        .help(
+          "If set, blobs which have not been used recently are periodically garbage collected \
+           to shrink the store to this size.",
+        ),
+    )
+    .get_matches();
+
+  let address = value_t!(matches, "address", SocketAddr).map_err(|e| e.to_string())?;
+  let instance_name = matches.value_of("instance-name").unwrap().to_owned();
+  let store_dir = PathBuf::from(matches.value_of("store-dir").unwrap());
+  let target_size_bytes = if matches.is_present("target-size-bytes") {
+    Some(value_t!(matches, "target-size-bytes", usize).map_err(|e| e.to_string())?)
+  } else {
+    None
+  };
+
+  let executor = task_executor::Executor::new();
+  let options = LocalOptions::default();
+  let action_cache = ShardedLmdb::new(
+    store_dir.join("action_cache"),
+    options.directories_max_size_bytes,
+    executor.clone(),
+    options.lease_time,
+    options.shard_count,
+  )?;
+  let store = Store::local_only_with_options(executor, store_dir, options)?;
+
+  let server = CacheServer::start(
+    store.clone(),
+    action_cache,
+    if instance_name.is_empty() {
+      None
+    } else {
+      Some(instance_name)
+    },
+    address,
+  )?;
+  println!("Started cache server at address: {}", server.address());
+
+  loop {
+    tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    if let Some(target_size_bytes) = target_size_bytes {
+      if let Err(e) = store.garbage_collect(target_size_bytes, ShrinkBehavior::Fast) {
+        log::warn!("{}", e);
+      }
+    }
+  }
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
+use bytes::Bytes;
+use remexec::action_cache_client::ActionCacheClient;
+use remexec::{ActionResult, GetActionResultRequest, OutputFile, UpdateActionResultRequest};
+use sharded_lmdb::ShardedLmdb;
+use store::{LocalOptions, Store};
+use tempfile::TempDir;
+use testutil::data::{TestData, TestDirectory};
+use tonic::Code;
+
+use crate::CacheServer;
+
+fn start_server(dir: &Path) -> CacheServer {
+  let executor = task_executor::Executor::new();
+  let options = LocalOptions::default();
+  let action_cache = ShardedLmdb::new(
+    dir.join("action_cache"),
+    options.directories_max_size_bytes,
+    executor.clone(),
+    options.lease_time,
+    options.shard_count,
+  )
+  .unwrap();
+  let store = Store::local_only(executor, dir).unwrap();
+  CacheServer::start(store, action_cache, None, "127.0.0.1:0".parse().unwrap()).unwrap()
+}
+
+fn new_client_store(dir: &Path, cas_address: &str) -> Store {
+  Store::local_only(task_executor::Executor::new(), dir)
+    .unwrap()
+    .into_with_remote(
+      cas_address,
+      None,
+      None,
+      BTreeMap::new(),
+      // NB: A small chunk size so that uploads and downloads are streamed in multiple messages.
+      16,
+      None,
+      Duration::from_secs(1),
+      1,
+      None,
+      None,
+      None,
+    )
+    .unwrap()
+}
+
+async fn action_cache_client(server: &CacheServer) -> ActionCacheClient<tonic::transport::Channel> {
+  ActionCacheClient::connect(server.address()).await.unwrap()
+}
+
+#[tokio::test]
+async fn round_trip_blobs() {
+  let server_dir = TempDir::new().unwrap();
+  let server = start_server(server_dir.path());
+
+  let roland = TestData::roland();
+  let directory = TestDirectory::containing_roland();
+
+  // Store a directory in one client, and upload it.
+  let uploader_dir = TempDir::new().unwrap();
+  let uploader = new_client_store(uploader_dir.path(), &server.address());
+  uploader
+    .store_file_bytes(roland.bytes(), false)
+    .await
+    .unwrap();
+  uploader
+    .record_directory(&directory.directory(), false)
+    .await
+    .unwrap();
+  uploader
+    .ensure_remote_has_recursive(vec![directory.digest()])
+    .await
+    .unwrap();
+
+  // And then download it in another.
+  let downloader_dir = TempDir::new().unwrap();
+  let downloader = new_client_store(downloader_dir.path(), &server.address());
+  assert_eq!(
+    downloader
+      .load_file_bytes_with(roland.digest(), Bytes::copy_from_slice)
+      .await
+      .unwrap()
+      .map(|(bytes, _metadata)| bytes),
+    Some(roland.bytes())
+  );
+  assert_eq!(
+    downloader
+      .load_directory(directory.digest())
+      .await
+      .unwrap()
+      .map(|(directory, _metadata)| directory),
+    Some(directory.directory())
+  );
+
+  // Unknown blobs are missing.
+  assert_eq!(
+    downloader
+      .load_file_bytes_with(TestData::catnip().digest(), |_| ())
+      .await
+      .unwrap()
+      .map(|(value, _metadata)| value),
+    None
+  );
+}
+
+#[tokio::test]
+async fn round_trip_action_results() {
+  let server_dir = TempDir::new().unwrap();
+  let server = start_server(server_dir.path());
+  let mut client = action_cache_client(&server).await;
+
+  let action_digest = TestData::catnip().digest();
+  let roland = TestData::roland();
+  let action_result = ActionResult {
+    output_files: vec![OutputFile {
+      path: "roland".to_owned(),
+      digest: Some(roland.digest().into()),
+      ..OutputFile::default()
+    }],
+    exit_code: 0,
+    ..ActionResult::default()
+  };
+
+  // Unknown actions are missing.
+  let get_action_result_request = GetActionResultRequest {
+    action_digest: Some(action_digest.into()),
+    ..GetActionResultRequest::default()
+  };
+  assert_eq!(
+    client
+      .get_action_result(get_action_result_request.clone())
+      .await
+      .unwrap_err()
+      .code(),
+    Code::NotFound
+  );
+
+  client
+    .update_action_result(UpdateActionResultRequest {
+      action_digest: Some(action_digest.into()),
+      action_result: Some(action_result.clone()),
+      ..UpdateActionResultRequest::default()
+    })
+    .await
+    .unwrap();
+
+  // The result references an output which is not present in the CAS, and so is not usable.
+  assert_eq!(
+    client
+      .get_action_result(get_action_result_request.clone())
+      .await
+      .unwrap_err()
+      .code(),
+    Code::NotFound
+  );
+
+  // Once the output is uploaded, the result is usable.
+  let client_store_dir = TempDir::new().unwrap();
+  let client_store = new_client_store(client_store_dir.path(), &server.address());
+  client_store
+    .store_file_bytes(roland.bytes(), false)
+    .await
+    .unwrap();
+  client_store
+    .ensure_remote_has_recursive(vec![roland.digest()])
+    .await
+    .unwrap();
+  assert_eq!(
+    client
+      .get_action_result(get_action_result_request)
+      .await
+      .unwrap()
+      .into_inner(),
+    action_result
+  );
+}
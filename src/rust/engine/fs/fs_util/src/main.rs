@@ -306,6 +306,7 @@ async fn execute(top_match: &clap::ArgMatches<'_>) -> Result<(), ExitError> {
             root_ca_certs,
             headers,
             chunk_size,
+            None,
             // This deadline is really only in place because otherwise DNS failures
             // leave this hanging forever.
             //
@@ -316,6 +317,9 @@ async fn execute(top_match: &clap::ArgMatches<'_>) -> Result<(), ExitError> {
             // See https://github.com/pantsbuild/pants/pull/6433 for more context.
             Duration::from_secs(30 * 60),
             value_t!(top_match.value_of("rpc-attempts"), usize).expect("Bad rpc-attempts flag"),
+            None,
+            None,
+            None,
           ),
           true,
         )
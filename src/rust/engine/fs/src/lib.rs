@@ -287,7 +287,7 @@ impl GitignoreStyleExcludes {
     }))
   }
 
-  fn exclude_patterns(&self) -> &[String] {
+  pub fn exclude_patterns(&self) -> &[String] {
     self.patterns.as_slice()
   }
 
@@ -521,6 +521,13 @@ impl PosixFS {
     self.ignore.is_ignored(stat)
   }
 
+  ///
+  /// The absolute path of the given File.
+  ///
+  pub fn file_path(&self, file: &File) -> PathBuf {
+    self.root.0.join(&file.path)
+  }
+
   pub async fn read_file(&self, file: &File) -> Result<FileContent, io::Error> {
     let path = file.path.clone();
     let path_abs = self.root.0.join(&file.path);
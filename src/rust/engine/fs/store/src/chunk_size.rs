@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The duration which the transfer of a single chunk should take, given the observed throughput.
+///
+/// Larger chunks amortize the per-message overhead of a stream, while smaller chunks bound the
+/// amount of work which is lost (and the time spent without progress) on a slow link.
+pub const TARGET_CHUNK_DURATION: Duration = Duration::from_millis(100);
+
+///
+/// The chunk size to use for ByteStream transfers on a connection, which (if bounds are given)
+/// adapts to the throughput observed for completed transfers on the connection.
+///
+/// On a fast, low-latency link (such as a LAN) the chunk size grows towards the upper bound, while
+/// on a slow link (such as a WAN) it shrinks towards the lower bound.
+///
+#[derive(Debug)]
+pub struct AdaptiveChunkSize {
+  min: usize,
+  max: usize,
+  current: AtomicUsize,
+}
+
+impl AdaptiveChunkSize {
+  ///
+  /// Creates a chunk size which starts at the given initial size. If bounds are given, the chunk
+  /// size adapts within them (and the initial size is clamped to them). Otherwise, the chunk size
+  /// is fixed.
+  ///
+  pub fn new(initial: usize, bounds: Option<(usize, usize)>) -> AdaptiveChunkSize {
+    let (min, max) = match bounds {
+      Some((min, max)) if min < max => (min.max(1), max),
+      _ => (initial, initial),
+    };
+    AdaptiveChunkSize {
+      min,
+      max,
+      current: AtomicUsize::new(initial.max(min).min(max)),
+    }
+  }
+
+  pub fn get(&self) -> usize {
+    self.current.load(Ordering::Relaxed)
+  }
+
+  ///
+  /// Records a completed transfer of the given number of bytes, and adjusts the chunk size
+  /// towards the size which would take `TARGET_CHUNK_DURATION` to transfer at the observed rate.
+  ///
+  /// Transfers smaller than the current chunk size are ignored, because their duration is
+  /// dominated by the latency of the RPC rather than by the throughput of the connection.
+  ///
+  pub fn observe(&self, bytes: usize, elapsed: Duration) {
+    if self.min == self.max || bytes < self.get() || elapsed == Duration::default() {
+      return;
+    }
+    let bytes_per_sec = bytes as f64 / elapsed.as_secs_f64();
+    let target = (bytes_per_sec * TARGET_CHUNK_DURATION.as_secs_f64()) as usize;
+    // Move a quarter of the way towards the target, to smooth out noisy observations.
+    let _ = self
+      .current
+      .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+        let next = (current / 4) * 3 + target.min(self.max) / 4;
+        Some(next.max(self.min).min(self.max))
+      });
+  }
+}
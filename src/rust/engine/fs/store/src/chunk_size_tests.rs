@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use crate::chunk_size::AdaptiveChunkSize;
+use crate::MEGABYTES;
+
+#[test]
+fn fixed_without_bounds() {
+  let chunk_size = AdaptiveChunkSize::new(MEGABYTES, None);
+  chunk_size.observe(100 * MEGABYTES, Duration::from_millis(10));
+  assert_eq!(chunk_size.get(), MEGABYTES);
+
+  // Degenerate bounds also result in a fixed size.
+  let chunk_size = AdaptiveChunkSize::new(MEGABYTES, Some((4 * MEGABYTES, 4 * MEGABYTES)));
+  chunk_size.observe(100 * MEGABYTES, Duration::from_millis(10));
+  assert_eq!(chunk_size.get(), MEGABYTES);
+}
+
+#[test]
+fn initial_size_is_clamped() {
+  let chunk_size = AdaptiveChunkSize::new(10 * MEGABYTES, Some((1024, 4 * MEGABYTES)));
+  assert_eq!(chunk_size.get(), 4 * MEGABYTES);
+}
+
+#[test]
+fn grows_on_fast_connections() {
+  let chunk_size = AdaptiveChunkSize::new(MEGABYTES, Some((64 * 1024, 4 * MEGABYTES)));
+  // 1GB/s: the target is 100MB per chunk, which is beyond the upper bound.
+  for _ in 0..20 {
+    chunk_size.observe(100 * MEGABYTES, Duration::from_millis(100));
+  }
+  assert_eq!(chunk_size.get(), 4 * MEGABYTES);
+}
+
+#[test]
+fn shrinks_on_slow_connections() {
+  let chunk_size = AdaptiveChunkSize::new(MEGABYTES, Some((64 * 1024, 4 * MEGABYTES)));
+  // 100KB/s: the target is 10KB per chunk, which is below the lower bound.
+  for _ in 0..40 {
+    let current = chunk_size.get();
+    chunk_size.observe(current, Duration::from_secs_f64(current as f64 / 100_000.0));
+  }
+  assert_eq!(chunk_size.get(), 64 * 1024);
+}
+
+#[test]
+fn ignores_small_transfers() {
+  let chunk_size = AdaptiveChunkSize::new(MEGABYTES, Some((64 * 1024, 4 * MEGABYTES)));
+  chunk_size.observe(1024, Duration::from_secs(10));
+  assert_eq!(chunk_size.get(), MEGABYTES);
+}
@@ -0,0 +1,119 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use hashing::Digest;
+use sha1::{Digest as Sha1Digest, Sha1};
+use sharded_lmdb::ShardedLmdb;
+
+/// The size in bytes of a SHA-1 hash.
+const SHA1_SIZE: usize = 20;
+
+///
+/// The hash function which a remote CAS uses to address blobs.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DigestFunction {
+  /// SHA-256, which is also used by the local store, and so requires no translation.
+  Sha256,
+  /// SHA-1, which is used by some legacy CAS deployments: see `DigestTranslation`.
+  Sha1,
+}
+
+impl TryFrom<String> for DigestFunction {
+  type Error = String;
+  fn try_from(variant_candidate: String) -> Result<Self, Self::Error> {
+    match variant_candidate.as_ref() {
+      "sha256" => Ok(DigestFunction::Sha256),
+      "sha1" => Ok(DigestFunction::Sha1),
+      other => Err(format!(
+        "Unknown value for remote store digest function: {:?}",
+        other
+      )),
+    }
+  }
+}
+
+///
+/// Translates the SHA-256 digests used by the local store to and from the SHA-1 hashes used by a
+/// remote CAS.
+///
+/// Blobs are re-hashed with SHA-1 as they are uploaded, and the SHA-1 hash of each uploaded blob is
+/// recorded in a local translation table, keyed by its SHA-256 digest. Since the SHA-1 hash of a
+/// blob cannot be computed without its content, blobs which have no entry in the table (because
+/// they were uploaded by another machine) are treated as missing from the remote CAS.
+///
+#[derive(Clone)]
+pub struct DigestTranslation {
+  remote_hashes: Arc<ShardedLmdb>,
+}
+
+impl fmt::Debug for DigestTranslation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "DigestTranslation(sha1)")
+  }
+}
+
+impl DigestTranslation {
+  pub fn new(remote_hashes: Arc<ShardedLmdb>) -> DigestTranslation {
+    DigestTranslation { remote_hashes }
+  }
+
+  ///
+  /// The hex-encoded hash of the given bytes under the digest function of the remote CAS.
+  ///
+  pub fn remote_hash(bytes: &[u8]) -> String {
+    hex::encode(Sha1::digest(bytes))
+  }
+
+  ///
+  /// The hex-encoded remote hash of the blob with the given digest, if it has been uploaded by this
+  /// machine.
+  ///
+  pub async fn translate(&self, digest: Digest) -> Result<Option<String>, String> {
+    self
+      .remote_hashes
+      .load_bytes_with(digest.hash, move |bytes| {
+        if bytes.len() == SHA1_SIZE {
+          Ok(hex::encode(bytes))
+        } else {
+          Err(format!(
+            "Invalid entry in the remote digest translation table for {:?}",
+            digest
+          ))
+        }
+      })
+      .await
+  }
+
+  ///
+  /// Records that the blob with the given digest was uploaded with the given remote hash.
+  ///
+  pub async fn record_remote_hash(&self, digest: Digest, remote_hash: &str) -> Result<(), String> {
+    let remote_hash = hex::decode(remote_hash)
+      .map_err(|e| format!("Invalid remote hash {:?}: {}", remote_hash, e))?;
+    self
+      .remote_hashes
+      .store_bytes(digest.hash, Bytes::from(remote_hash), false)
+      .await
+  }
+
+  ///
+  /// Validates that a blob which was downloaded for the given digest has the expected remote hash.
+  ///
+  pub fn validate(&self, digest: Digest, remote_hash: &str, bytes: &[u8]) -> Result<(), String> {
+    let actual_remote_hash = Self::remote_hash(bytes);
+    if actual_remote_hash == remote_hash {
+      Ok(())
+    } else {
+      Err(format!(
+        "Downloaded blob for {:?} had the wrong remote hash: expected {}, got {}",
+        digest, remote_hash, actual_remote_hash
+      ))
+    }
+  }
+}
@@ -0,0 +1,140 @@
+use std::ffi::CString;
+use std::fs::Metadata;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use hashing::{Digest, Fingerprint};
+
+///
+/// The name of the extended attribute which materialized files are tagged with.
+///
+/// The value of the attribute is `{fingerprint}-{size_bytes}-{mtime}`, where the mtime is recorded
+/// in nanoseconds since the epoch. Because extended attributes survive in-place modification of a
+/// file, the size and mtime recorded when the file was written must still match for the tag to be
+/// trusted.
+///
+pub const DIGEST_XATTR_NAME: &str = "user.pants.digest";
+
+///
+/// Tags the given (fully written) file with its Digest. Tagging is best-effort: not all
+/// filesystems support extended attributes.
+///
+pub fn tag(file: &std::fs::File, digest: Digest) -> Result<(), String> {
+  let metadata = file
+    .metadata()
+    .map_err(|e| format!("Failed to stat file to tag: {}", e))?;
+  let value = format!(
+    "{}-{}-{}",
+    digest.hash,
+    digest.size_bytes,
+    mtime_nanos(&metadata)?
+  );
+  let name = CString::new(DIGEST_XATTR_NAME).unwrap();
+  let res = unsafe {
+    fsetxattr(
+      file.as_raw_fd(),
+      name.as_ptr(),
+      value.as_ptr() as *const libc::c_void,
+      value.len(),
+    )
+  };
+  if res != 0 {
+    return Err(format!(
+      "Failed to set {}: {}",
+      DIGEST_XATTR_NAME,
+      std::io::Error::last_os_error()
+    ));
+  }
+  Ok(())
+}
+
+///
+/// Returns the Digest that the given file was tagged with, if it has a tag which is still valid.
+///
+pub fn read_tag(path: &Path) -> Option<Digest> {
+  let metadata = std::fs::symlink_metadata(path).ok()?;
+  if !metadata.is_file() {
+    return None;
+  }
+  let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let name = CString::new(DIGEST_XATTR_NAME).unwrap();
+  // A hex fingerprint, and two decimal u64s.
+  let mut buf = [0_u8; 128];
+  let len = unsafe {
+    getxattr(
+      path.as_ptr(),
+      name.as_ptr(),
+      buf.as_mut_ptr() as *mut libc::c_void,
+      buf.len(),
+    )
+  };
+  if len <= 0 {
+    return None;
+  }
+  let value = std::str::from_utf8(&buf[..len as usize]).ok()?;
+  let mut parts = value.split('-');
+  let fingerprint = Fingerprint::from_hex_string(parts.next()?).ok()?;
+  let size_bytes = parts.next()?.parse::<usize>().ok()?;
+  let mtime = parts.next()?.parse::<u128>().ok()?;
+  if parts.next().is_some()
+    || size_bytes as u64 != metadata.len()
+    || mtime != mtime_nanos(&metadata).ok()?
+  {
+    return None;
+  }
+  Some(Digest::new(fingerprint, size_bytes))
+}
+
+fn mtime_nanos(metadata: &Metadata) -> Result<u128, String> {
+  metadata
+    .modified()
+    .and_then(|mtime| {
+      mtime
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    })
+    .map(|mtime| mtime.as_nanos())
+    .map_err(|e| format!("Failed to read mtime: {}", e))
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn fsetxattr(
+  fd: libc::c_int,
+  name: *const libc::c_char,
+  value: *const libc::c_void,
+  size: libc::size_t,
+) -> libc::c_int {
+  libc::fsetxattr(fd, name, value, size, 0, 0)
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn fsetxattr(
+  fd: libc::c_int,
+  name: *const libc::c_char,
+  value: *const libc::c_void,
+  size: libc::size_t,
+) -> libc::c_int {
+  libc::fsetxattr(fd, name, value, size, 0)
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn getxattr(
+  path: *const libc::c_char,
+  name: *const libc::c_char,
+  value: *mut libc::c_void,
+  size: libc::size_t,
+) -> libc::ssize_t {
+  libc::getxattr(path, name, value, size, 0, 0)
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn getxattr(
+  path: *const libc::c_char,
+  name: *const libc::c_char,
+  value: *mut libc::c_void,
+  size: libc::size_t,
+) -> libc::ssize_t {
+  libc::getxattr(path, name, value, size)
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
+use hashing::Digest;
+use parking_lot::Mutex;
+
+///
+/// A bounded in-memory cache of parsed Directory protos, keyed by Digest.
+///
+/// The same Directories are loaded many times during a run (while merging, globbing, and
+/// materializing), and decoding them is comparatively expensive, so the Store consults this cache
+/// before loading (and decoding) Directory bytes.
+///
+/// The cache is bounded by the encoded size of the Directories that it holds, and approximates an
+/// LRU cache with two generations: when the current generation is full, it becomes the previous
+/// generation (and the previous generation is discarded). Hits in the previous generation are
+/// promoted to the current generation.
+///
+#[derive(Debug)]
+pub struct DirectoryCache {
+  max_generation_bytes: usize,
+  generations: Mutex<Generations>,
+}
+
+#[derive(Debug, Default)]
+struct Generations {
+  current: HashMap<Digest, remexec::Directory>,
+  current_bytes: usize,
+  previous: HashMap<Digest, remexec::Directory>,
+}
+
+impl DirectoryCache {
+  ///
+  /// Creates a cache which holds up to (approximately) `max_bytes` of encoded Directories.
+  ///
+  pub fn new(max_bytes: usize) -> DirectoryCache {
+    DirectoryCache {
+      max_generation_bytes: max_bytes / 2,
+      generations: Mutex::new(Generations::default()),
+    }
+  }
+
+  pub fn get(&self, digest: Digest) -> Option<remexec::Directory> {
+    let mut generations = self.generations.lock();
+    if let Some(directory) = generations.current.get(&digest) {
+      return Some(directory.clone());
+    }
+    let directory = generations.previous.remove(&digest)?;
+    self.insert_locked(&mut generations, digest, directory.clone());
+    Some(directory)
+  }
+
+  pub fn insert(&self, digest: Digest, directory: remexec::Directory) {
+    let mut generations = self.generations.lock();
+    if !generations.current.contains_key(&digest) {
+      self.insert_locked(&mut generations, digest, directory);
+    }
+  }
+
+  pub fn clear(&self) {
+    *self.generations.lock() = Generations::default();
+  }
+
+  fn insert_locked(
+    &self,
+    generations: &mut Generations,
+    digest: Digest,
+    directory: remexec::Directory,
+  ) {
+    if digest.size_bytes > self.max_generation_bytes {
+      return;
+    }
+    if generations.current_bytes + digest.size_bytes > self.max_generation_bytes {
+      generations.previous = std::mem::take(&mut generations.current);
+      generations.current_bytes = 0;
+    }
+    generations.current_bytes += digest.size_bytes;
+    generations.current.insert(digest, directory);
+  }
+}
@@ -0,0 +1,45 @@
+use testutil::data::TestDirectory;
+
+use crate::directory_cache::DirectoryCache;
+
+#[test]
+fn get_after_insert() {
+  let cache = DirectoryCache::new(1024);
+  let dir = TestDirectory::containing_roland();
+  assert_eq!(cache.get(dir.digest()), None);
+  cache.insert(dir.digest(), dir.directory());
+  assert_eq!(cache.get(dir.digest()), Some(dir.directory()));
+  cache.clear();
+  assert_eq!(cache.get(dir.digest()), None);
+}
+
+#[test]
+fn evicts_least_recently_used_generation() {
+  let roland = TestDirectory::containing_roland();
+  let robin = TestDirectory::containing_robin();
+  let treats = TestDirectory::containing_treats();
+  // Each generation has room for only one of the Directories.
+  let max_size = [&roland, &robin, &treats]
+    .iter()
+    .map(|dir| dir.digest().size_bytes)
+    .max()
+    .unwrap();
+  let cache = DirectoryCache::new(2 * max_size);
+
+  cache.insert(roland.digest(), roland.directory());
+  cache.insert(robin.digest(), robin.directory());
+  // roland is in the previous generation, and a hit promotes it (demoting robin).
+  assert_eq!(cache.get(roland.digest()), Some(roland.directory()));
+  cache.insert(treats.digest(), treats.directory());
+
+  assert_eq!(cache.get(robin.digest()), None);
+  assert_eq!(cache.get(treats.digest()), Some(treats.directory()));
+}
+
+#[test]
+fn oversized_directories_are_not_cached() {
+  let dir = TestDirectory::containing_roland();
+  let cache = DirectoryCache::new(dir.digest().size_bytes);
+  cache.insert(dir.digest(), dir.directory());
+  assert_eq!(cache.get(dir.digest()), None);
+}
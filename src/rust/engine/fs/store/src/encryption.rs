@@ -0,0 +1,187 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::fmt;
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use bytes::Bytes;
+use hashing::{Digest, Fingerprint, FINGERPRINT_SIZE};
+use sharded_lmdb::ShardedLmdb;
+
+/// The size in bytes of an AES-256-GCM key.
+pub const ENCRYPTION_KEY_SIZE: usize = 32;
+
+/// The size in bytes of the nonce which prefixes each encrypted blob.
+const NONCE_SIZE: usize = 12;
+
+/// The size in bytes of the authentication tag which is appended to each encrypted blob.
+const TAG_SIZE: usize = 16;
+
+///
+/// Encrypts blobs with AES-256-GCM before they are uploaded to an untrusted remote CAS, and
+/// decrypts them after they are downloaded.
+///
+/// Digests continue to be computed over the plaintext of blobs, so encryption is invisible outside
+/// of the remote ByteStore. But since the remote CAS addresses blobs by the digest of their
+/// ciphertext, the digest of the ciphertext of each uploaded blob is recorded under its `blob_id`:
+/// both in a local mapping table, and in the action cache of the remote store (see
+/// `ByteStore::encrypted_digest`), so that blobs uploaded by other machines with the same key can
+/// be found. Blobs which were uploaded with another key are treated as missing.
+///
+/// Only blobs are encrypted: the remote mapping entries (which contain only the digest of a
+/// ciphertext), and any ActionResults which are written to the remote action cache for processes
+/// (which contain the paths of their outputs), are stored in plaintext.
+///
+/// Encryption is deterministic: the nonce of a blob is derived from the key and the digest of its
+/// plaintext, so that identical blobs have identical ciphertexts and are deduplicated by the
+/// remote CAS. This reveals which blobs are identical to the server, but nothing about their
+/// content.
+///
+#[derive(Clone)]
+pub struct BlobEncryption {
+  key: Arc<[u8; ENCRYPTION_KEY_SIZE]>,
+  cipher: Arc<Aes256Gcm>,
+  encrypted_digests: Arc<ShardedLmdb>,
+}
+
+impl fmt::Debug for BlobEncryption {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // NB: Never render the key.
+    write!(f, "BlobEncryption(..)")
+  }
+}
+
+impl BlobEncryption {
+  pub fn new(key: &[u8], encrypted_digests: Arc<ShardedLmdb>) -> Result<BlobEncryption, String> {
+    if key.len() != ENCRYPTION_KEY_SIZE {
+      return Err(format!(
+        "Remote store encryption keys must be {} bytes long: got {} bytes.",
+        ENCRYPTION_KEY_SIZE,
+        key.len()
+      ));
+    }
+    let mut key_bytes = [0; ENCRYPTION_KEY_SIZE];
+    key_bytes.copy_from_slice(key);
+    Ok(BlobEncryption {
+      key: Arc::new(key_bytes),
+      cipher: Arc::new(Aes256Gcm::new(Key::from_slice(key))),
+      encrypted_digests,
+    })
+  }
+
+  ///
+  /// A secret identifier for the blob with the given plaintext digest under this key, which is
+  /// used both to derive its nonce and as its key in the mapping tables (so that entries recorded
+  /// under a different key are never used).
+  ///
+  fn blob_id(&self, digest: Digest) -> Fingerprint {
+    let mut input = Vec::with_capacity(ENCRYPTION_KEY_SIZE + FINGERPRINT_SIZE + 8);
+    input.extend_from_slice(&self.key[..]);
+    input.extend_from_slice(&digest.hash.0);
+    input.extend_from_slice(&(digest.size_bytes as u64).to_le_bytes());
+    Digest::of_bytes(&input).hash
+  }
+
+  ///
+  /// The key under which the digest of the ciphertext of the given plaintext digest is recorded in
+  /// the remote action cache. This reveals nothing about the plaintext to the remote store, but
+  /// allows any machine with the same key to find the ciphertext.
+  ///
+  pub fn remote_mapping_key(&self, digest: Digest) -> Digest {
+    Digest::new(self.blob_id(digest), FINGERPRINT_SIZE)
+  }
+
+  ///
+  /// The digest of the ciphertext of the given plaintext digest, if it has been recorded locally
+  /// under this key.
+  ///
+  pub async fn encrypted_digest(&self, digest: Digest) -> Result<Option<Digest>, String> {
+    let size_bytes = digest.size_bytes + NONCE_SIZE + TAG_SIZE;
+    self
+      .encrypted_digests
+      .load_bytes_with(self.blob_id(digest), move |bytes| {
+        if bytes.len() == FINGERPRINT_SIZE {
+          Ok(Digest::new(
+            Fingerprint::from_bytes_unsafe(bytes),
+            size_bytes,
+          ))
+        } else {
+          Err(format!(
+            "Invalid entry in the encrypted digest table for {:?}",
+            digest
+          ))
+        }
+      })
+      .await
+  }
+
+  ///
+  /// Records locally that the given plaintext digest was uploaded with the given ciphertext digest.
+  ///
+  pub async fn record_encrypted_digest(
+    &self,
+    digest: Digest,
+    encrypted_digest: Digest,
+  ) -> Result<(), String> {
+    self
+      .encrypted_digests
+      .store_bytes(
+        self.blob_id(digest),
+        Bytes::copy_from_slice(&encrypted_digest.hash.0),
+        false,
+      )
+      .await
+  }
+
+  ///
+  /// Encrypts the given plaintext (which must have the given digest), returning the nonce followed
+  /// by the ciphertext.
+  ///
+  pub fn encrypt(&self, digest: Digest, plaintext: &[u8]) -> Result<Bytes, String> {
+    let blob_id = self.blob_id(digest);
+    let nonce = &blob_id.0[..NONCE_SIZE];
+    let ciphertext = self
+      .cipher
+      .encrypt(Nonce::from_slice(nonce), plaintext)
+      .map_err(|_| format!("Failed to encrypt {:?}", digest))?;
+    let mut encrypted = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+    encrypted.extend_from_slice(nonce);
+    encrypted.extend_from_slice(&ciphertext);
+    Ok(Bytes::from(encrypted))
+  }
+
+  ///
+  /// Decrypts a blob which was encrypted by `encrypt`, and validates that its plaintext has the
+  /// given digest.
+  ///
+  pub fn decrypt(&self, digest: Digest, encrypted: &[u8]) -> Result<Bytes, String> {
+    if encrypted.len() < NONCE_SIZE + TAG_SIZE {
+      return Err(format!(
+        "Encrypted blob for {:?} is too short: {} bytes",
+        digest,
+        encrypted.len()
+      ));
+    }
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_SIZE);
+    let plaintext = self
+      .cipher
+      .decrypt(Nonce::from_slice(nonce), ciphertext)
+      .map_err(|_| {
+        format!(
+          "Failed to decrypt {:?}: the blob was not encrypted with the configured key, or was \
+           modified by the remote store.",
+          digest
+        )
+      })?;
+    let actual_digest = Digest::of_bytes(&plaintext);
+    if actual_digest != digest {
+      return Err(format!(
+        "Decrypted blob had the wrong digest: expected {:?}, got {:?}",
+        digest, actual_digest
+      ));
+    }
+    Ok(Bytes::from(plaintext))
+  }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use hashing::Digest;
+
+///
+/// How long a root keeps the blobs that it references alive during garbage collection.
+///
+/// NB: The order of the variants is significant: when a blob is referenced by multiple roots, the
+/// greatest lifetime applies.
+///
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum RootLifetime {
+  /// The root is referenced until the given time: for example, a cached action result, which is
+  /// referenced until its lease expires.
+  Until(SystemTime),
+  /// The root is in use: for example, a value which is retained in memory by the graph.
+  Live,
+}
+
+///
+/// The roots which reference blobs in a Store, which are used to compute the reachability of blobs
+/// during garbage collection: see `Store::garbage_collect_with_roots`.
+///
+#[derive(Clone, Debug, Default)]
+pub struct GcRoots {
+  roots: HashMap<Digest, RootLifetime>,
+}
+
+impl GcRoots {
+  pub fn new() -> GcRoots {
+    GcRoots::default()
+  }
+
+  ///
+  /// Adds a root, which may be either a File or a Directory (in which case everything below it is
+  /// referenced).
+  ///
+  pub fn add(&mut self, digest: Digest, lifetime: RootLifetime) {
+    let existing = self.roots.entry(digest).or_insert(lifetime);
+    *existing = std::cmp::max(*existing, lifetime);
+  }
+
+  pub fn lifetime(&self, digest: &Digest) -> Option<RootLifetime> {
+    self.roots.get(digest).copied()
+  }
+
+  pub fn len(&self) -> usize {
+    self.roots.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.roots.is_empty()
+  }
+
+  pub(crate) fn into_roots(self) -> impl Iterator<Item = (Digest, RootLifetime)> {
+    self.roots.into_iter()
+  }
+}
+
+impl Extend<(Digest, RootLifetime)> for GcRoots {
+  fn extend<I: IntoIterator<Item = (Digest, RootLifetime)>>(&mut self, iter: I) {
+    for (digest, lifetime) in iter {
+      self.add(digest, lifetime);
+    }
+  }
+}
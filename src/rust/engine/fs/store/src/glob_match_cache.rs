@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use fs::{PathGlob, PreparedPathGlobs};
+use hashing::Digest;
+use parking_lot::Mutex;
+
+///
+/// The identity of a glob match against a Directory: the Directory's Digest, and a canonical form
+/// of the globs which does not depend on their order, their duplication, or on the (symbolic)
+/// paths that they were spelled with.
+///
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct GlobMatchKey {
+  digest: Digest,
+  // Each include glob as whether it can match directories, and its component patterns.
+  include: Vec<(bool, Vec<String>)>,
+  // NB: The order of exclude patterns is significant, because later patterns may negate earlier
+  // patterns.
+  exclude: Vec<String>,
+}
+
+impl GlobMatchKey {
+  pub fn new(digest: Digest, globs: &PreparedPathGlobs) -> GlobMatchKey {
+    let expandable_globs = globs.as_expandable_globs();
+    let mut include = expandable_globs
+      .include
+      .iter()
+      .map(|glob| match glob {
+        PathGlob::Wildcard { wildcard, .. } => (false, vec![wildcard.as_str().to_owned()]),
+        PathGlob::DirWildcard {
+          wildcard,
+          remainder,
+          ..
+        } => (
+          true,
+          std::iter::once(wildcard)
+            .chain(remainder.iter())
+            .map(|pattern| pattern.as_str().to_owned())
+            .collect(),
+        ),
+      })
+      .collect::<Vec<_>>();
+    include.sort();
+    include.dedup();
+    GlobMatchKey {
+      digest,
+      include,
+      exclude: expandable_globs.exclude.exclude_patterns().to_vec(),
+    }
+  }
+}
+
+///
+/// A bounded in-memory cache of the results of matching globs against Directories (i.e. of
+/// `SnapshotOps::subset`), keyed by GlobMatchKey.
+///
+/// The same globs are matched against the same Directories many times (for example, while
+/// collecting the sources of many targets), independent of whether the callers are memoized. Like
+/// the DirectoryCache, the cache approximates an LRU cache with two generations, but is bounded by
+/// its number of entries.
+///
+#[derive(Debug)]
+pub struct GlobMatchCache {
+  max_generation_entries: usize,
+  generations: Mutex<Generations>,
+}
+
+#[derive(Debug, Default)]
+struct Generations {
+  current: HashMap<GlobMatchKey, Digest>,
+  previous: HashMap<GlobMatchKey, Digest>,
+}
+
+impl GlobMatchCache {
+  ///
+  /// Creates a cache which holds up to (approximately) `max_entries` glob match results.
+  ///
+  pub fn new(max_entries: usize) -> GlobMatchCache {
+    GlobMatchCache {
+      max_generation_entries: std::cmp::max(max_entries / 2, 1),
+      generations: Mutex::new(Generations::default()),
+    }
+  }
+
+  pub fn get(&self, key: &GlobMatchKey) -> Option<Digest> {
+    let mut generations = self.generations.lock();
+    if let Some(digest) = generations.current.get(key) {
+      return Some(*digest);
+    }
+    let (key, digest) = generations.previous.remove_entry(key)?;
+    self.insert_locked(&mut generations, key, digest);
+    Some(digest)
+  }
+
+  pub fn insert(&self, key: GlobMatchKey, digest: Digest) {
+    let mut generations = self.generations.lock();
+    if !generations.current.contains_key(&key) {
+      self.insert_locked(&mut generations, key, digest);
+    }
+  }
+
+  pub fn clear(&self) {
+    *self.generations.lock() = Generations::default();
+  }
+
+  fn insert_locked(&self, generations: &mut Generations, key: GlobMatchKey, digest: Digest) {
+    if generations.current.len() >= self.max_generation_entries {
+      generations.previous = std::mem::take(&mut generations.current);
+    }
+    generations.current.insert(key, digest);
+  }
+}
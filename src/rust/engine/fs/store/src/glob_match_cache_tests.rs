@@ -0,0 +1,59 @@
+use fs::{GlobExpansionConjunction, PreparedPathGlobs, StrictGlobMatching};
+use testutil::data::TestDirectory;
+
+use crate::glob_match_cache::{GlobMatchCache, GlobMatchKey};
+
+fn globs(globs: &[&str]) -> PreparedPathGlobs {
+  PreparedPathGlobs::create(
+    globs.iter().map(|s| s.to_string()).collect(),
+    StrictGlobMatching::Ignore,
+    GlobExpansionConjunction::AllMatch,
+  )
+  .unwrap()
+}
+
+#[test]
+fn keys_are_canonical() {
+  let digest = TestDirectory::containing_roland().digest();
+  let key = GlobMatchKey::new(digest, &globs(&["a/*.py", "b/**", "!a/c.py"]));
+
+  // The order and duplication of include globs does not matter.
+  assert_eq!(
+    key,
+    GlobMatchKey::new(digest, &globs(&["b/**", "a/*.py", "!a/c.py", "a/*.py"]))
+  );
+  // But the globs, excludes, and digest do.
+  assert_ne!(key, GlobMatchKey::new(digest, &globs(&["a/*.py", "b/**"])));
+  assert_ne!(
+    key,
+    GlobMatchKey::new(digest, &globs(&["a/*.py", "b/*", "!a/c.py"]))
+  );
+  assert_ne!(
+    key,
+    GlobMatchKey::new(
+      TestDirectory::containing_robin().digest(),
+      &globs(&["a/*.py", "b/**", "!a/c.py"])
+    )
+  );
+}
+
+#[test]
+fn evicts_least_recently_used_generation() {
+  let cache = GlobMatchCache::new(2);
+  let key = |glob: &str| GlobMatchKey::new(TestDirectory::nested().digest(), &globs(&[glob]));
+  let roland = TestDirectory::containing_roland().digest();
+  let robin = TestDirectory::containing_robin().digest();
+  let treats = TestDirectory::containing_treats().digest();
+
+  cache.insert(key("roland"), roland);
+  cache.insert(key("robin"), robin);
+  // roland is in the previous generation, and a hit promotes it (demoting robin).
+  assert_eq!(cache.get(&key("roland")), Some(roland));
+  cache.insert(key("treats"), treats);
+
+  assert_eq!(cache.get(&key("robin")), None);
+  assert_eq!(cache.get(&key("treats")), Some(treats));
+
+  cache.clear();
+  assert_eq!(cache.get(&key("treats")), None);
+}
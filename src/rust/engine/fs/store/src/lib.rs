@@ -29,7 +29,23 @@
 #![type_length_limit = "95595489"]
 #![recursion_limit = "256"]
 
+mod chunk_size;
+#[cfg(test)]
+mod chunk_size_tests;
+mod directory_cache;
+#[cfg(test)]
+mod directory_cache_tests;
+use crate::directory_cache::DirectoryCache;
+mod digest_xattr;
+pub use crate::digest_xattr::DIGEST_XATTR_NAME;
+mod gc_roots;
+pub use crate::gc_roots::{GcRoots, RootLifetime};
+mod glob_match_cache;
+#[cfg(test)]
+mod glob_match_cache_tests;
+pub use crate::glob_match_cache::{GlobMatchCache, GlobMatchKey};
 mod snapshot;
+use crate::snapshot::StoreManyFileDigests;
 pub use crate::snapshot::{OneOffStoreFileByDigest, Snapshot, StoreFileByDigest};
 mod snapshot_ops;
 #[cfg(test)]
@@ -37,6 +53,8 @@ mod snapshot_ops_tests;
 #[cfg(test)]
 mod snapshot_tests;
 pub use crate::snapshot_ops::{SnapshotOps, SnapshotOpsError, StoreWrapper, SubsetParams};
+pub use grpc_util::health::{EndpointStatus, RemoteEndpoint};
+pub use grpc_util::priority::{with_request_priority, RequestPriority};
 
 use async_trait::async_trait;
 use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
@@ -46,15 +64,17 @@ use concrete_time::TimeSpan;
 use fs::{default_cache_path, FileContent, RelativePath};
 use futures::future::{self, BoxFuture, Either, FutureExt, TryFutureExt};
 use grpc_util::prost::MessageExt;
-use hashing::Digest;
+use hashing::{Digest, Fingerprint, WriterHasher, EMPTY_DIGEST};
 use serde_derive::Serialize;
 use sharded_lmdb::DEFAULT_LEASE_TIME;
+use task_executor::IoPriority;
 use tryfuture::try_future;
+use workunit_store::{Metric, ProgressReporter};
 
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
@@ -66,6 +86,12 @@ use remexec::Tree;
 const MEGABYTES: usize = 1024 * 1024;
 const GIGABYTES: usize = 1024 * MEGABYTES;
 
+// The maximum encoded size of the parsed Directories which are cached in memory.
+const DIRECTORY_CACHE_MAX_BYTES: usize = 64 * MEGABYTES;
+
+// The maximum number of glob match results which are cached in memory.
+const GLOB_MATCH_CACHE_MAX_ENTRIES: usize = 100_000;
+
 mod local;
 #[cfg(test)]
 pub mod local_tests;
@@ -74,11 +100,52 @@ mod remote;
 #[cfg(test)]
 mod remote_tests;
 
+mod encryption;
+use crate::encryption::BlobEncryption;
+pub use crate::encryption::ENCRYPTION_KEY_SIZE;
+
+mod digest_function;
+pub use crate::digest_function::DigestFunction;
+use crate::digest_function::DigestTranslation;
+
+mod mirror;
+#[cfg(test)]
+mod mirror_tests;
+pub use crate::mirror::{DigestResolver, HttpMirrors};
+
+mod transaction;
+#[cfg(test)]
+mod transaction_tests;
+use crate::transaction::StagedRoot;
+
+///
+/// The umask which is applied to the modes of materialized files and directories by default. This
+/// results in the conventional `0o644` for files, and `0o755` for directories and executables.
+///
+pub const DEFAULT_MATERIALIZE_UMASK: u32 = 0o022;
+
 pub struct LocalOptions {
   pub files_max_size_bytes: usize,
   pub directories_max_size_bytes: usize,
   pub lease_time: Duration,
   pub shard_count: u8,
+  ///
+  /// The umask to apply when materializing files and directories: see `Store::materialize_umask`.
+  ///
+  pub materialize_umask: u32,
+  ///
+  /// Whether to tag materialized files with their digests: see `Store::digest_of_tagged_file`.
+  ///
+  pub materialize_digest_xattrs: bool,
+  ///
+  /// If set, the maximum number of blobs to hash concurrently, so that hashing large captures does
+  /// not occupy every thread of the blocking pool.
+  ///
+  pub hashing_parallelism: Option<usize>,
+  ///
+  /// The IO priority of background maintenance, such as lease extension and garbage collection.
+  ///
+  pub maintenance_io_priority: IoPriority,
 }
 
 ///
@@ -92,6 +159,10 @@ impl Default for LocalOptions {
       directories_max_size_bytes: 2 * 4 * GIGABYTES,
       lease_time: DEFAULT_LEASE_TIME,
       shard_count: 16,
+      materialize_umask: DEFAULT_MATERIALIZE_UMASK,
+      materialize_digest_xattrs: false,
+      hashing_parallelism: None,
+      maintenance_io_priority: IoPriority::Default,
     }
   }
 }
@@ -109,6 +180,19 @@ pub struct UploadSummary {
   pub upload_wall_time: Duration,
 }
 
+///
+/// The transitive size of a Directory, as computed by `Store::tree_size`.
+///
+/// NB: Files and Directories which occur at multiple paths are counted once per path, as they
+/// would be when the Directory is materialized.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct TreeSize {
+  pub file_count: usize,
+  pub file_bytes: usize,
+  pub directory_count: usize,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum LoadMetadata {
   Local,
@@ -209,10 +293,18 @@ enum RootOrParentMetadataBuilder {
 /// It can also write back to a remote gRPC server, but will only do so when explicitly instructed
 /// to do so.
 ///
+/// Digests which are missing from both the local and remote stores may additionally be fetched
+/// from read-only `DigestResolver`s (such as HTTP mirrors).
+///
 #[derive(Debug, Clone)]
 pub struct Store {
   local: local::ByteStore,
   remote: Option<remote::ByteStore>,
+  resolvers: Vec<Arc<dyn DigestResolver>>,
+  directory_cache: Arc<DirectoryCache>,
+  glob_match_cache: Arc<GlobMatchCache>,
+  materialize_umask: u32,
+  digest_xattrs: bool,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -248,6 +340,11 @@ impl Store {
     Ok(Store {
       local: local::ByteStore::new(executor, path)?,
       remote: None,
+      directory_cache: Arc::new(DirectoryCache::new(DIRECTORY_CACHE_MAX_BYTES)),
+      glob_match_cache: Arc::new(GlobMatchCache::new(GLOB_MATCH_CACHE_MAX_ENTRIES)),
+      resolvers: Vec::new(),
+      materialize_umask: DEFAULT_MATERIALIZE_UMASK,
+      digest_xattrs: false,
     })
   }
 
@@ -256,14 +353,92 @@ impl Store {
     path: P,
     options: LocalOptions,
   ) -> Result<Store, String> {
+    let materialize_umask = options.materialize_umask;
+    let digest_xattrs = options.materialize_digest_xattrs;
     Ok(Store {
       local: local::ByteStore::new_with_options(executor, path, options)?,
       remote: None,
+      directory_cache: Arc::new(DirectoryCache::new(DIRECTORY_CACHE_MAX_BYTES)),
+      glob_match_cache: Arc::new(GlobMatchCache::new(GLOB_MATCH_CACHE_MAX_ENTRIES)),
+      resolvers: Vec::new(),
+      materialize_umask,
+      digest_xattrs,
     })
   }
 
   ///
-  /// Converts this (copy of) a Store to local only by dropping the remote half.
+  /// Returns a copy of this Store which applies the given umask when materializing files and
+  /// directories.
+  ///
+  pub fn with_materialize_umask(self, materialize_umask: u32) -> Store {
+    Store {
+      materialize_umask,
+      ..self
+    }
+  }
+
+  ///
+  /// The umask which is applied to the "natural" modes of materialized entries: `0o777` for
+  /// directories and executable files, and `0o666` for other files.
+  ///
+  /// Unlike the umask of this process (which the OS applies implicitly when creating entries), a
+  /// non-default umask is applied explicitly after creating each entry, and so it may _add_
+  /// permissions (such as group-writability for caches which are shared between users on CI) that
+  /// the process umask would otherwise remove.
+  ///
+  pub fn materialize_umask(&self) -> u32 {
+    self.materialize_umask
+  }
+
+  ///
+  /// Returns a copy of this Store which (if enabled) tags materialized files with their digests.
+  ///
+  pub fn with_digest_xattrs(self, digest_xattrs: bool) -> Store {
+    Store {
+      digest_xattrs,
+      ..self
+    }
+  }
+
+  ///
+  /// If tagging is enabled, returns the Digest of the file at the given path if it was materialized
+  /// by this Store (and has not been modified since), and if its content is still available locally.
+  /// This allows capturing files which the engine wrote without reading and hashing them.
+  ///
+  /// Materialized files are tagged in the `DIGEST_XATTR_NAME` extended attribute, which external
+  /// tools may also use to cheaply verify the integrity of artifacts.
+  ///
+  pub async fn digest_of_tagged_file(&self, path: PathBuf) -> Result<Option<Digest>, String> {
+    if !self.digest_xattrs {
+      return Ok(None);
+    }
+    let digest = match self
+      .local
+      .executor()
+      .spawn_blocking(move || digest_xattr::read_tag(&path))
+      .await
+    {
+      Some(digest) => digest,
+      None => return Ok(None),
+    };
+    match self.local.entry_type(digest.hash).await? {
+      Some(EntryType::File) => Ok(Some(digest)),
+      _ => Ok(None),
+    }
+  }
+
+  fn materialized_mode(&self, is_directory_or_executable: bool) -> u32 {
+    let mode = if is_directory_or_executable {
+      0o777
+    } else {
+      0o666
+    };
+    mode & !self.materialize_umask
+  }
+
+  ///
+  /// Converts this (copy of) a Store to local only by dropping the remote half (and any
+  /// resolvers).
   ///
   /// Because both underlying stores are reference counted, this is cheap, and has no effect on
   /// other clones of the Store.
@@ -272,13 +447,31 @@ impl Store {
     Store {
       local: self.local,
       remote: None,
+      resolvers: Vec::new(),
+      directory_cache: self.directory_cache,
+      glob_match_cache: self.glob_match_cache,
+      materialize_umask: self.materialize_umask,
+      digest_xattrs: self.digest_xattrs,
     }
   }
 
+  ///
+  /// The endpoint of the remote half of this Store, if any.
+  ///
+  pub fn remote_endpoint(&self) -> Option<RemoteEndpoint> {
+    self.remote.as_ref().map(|remote| remote.endpoint().clone())
+  }
+
   ///
   /// Add remote storage to a Store. If it is missing a value which it tries to load, it will
   /// attempt to back-fill its local storage from the remote storage.
   ///
+  /// If `chunk_size_bounds` are given, the size of ByteStream chunks starts at `chunk_size_bytes`
+  /// and then adapts within the bounds to the throughput observed for the connection.
+  ///
+  /// If `read_concurrency` is given, the number of concurrent reads from the remote store is
+  /// limited, and queued reads are admitted in order of their `RequestPriority`.
+  ///
   pub fn into_with_remote(
     self,
     cas_address: &str,
@@ -286,8 +479,12 @@ impl Store {
     root_ca_certs: Option<Vec<u8>>,
     headers: BTreeMap<String, String>,
     chunk_size_bytes: usize,
+    chunk_size_bounds: Option<(usize, usize)>,
     upload_timeout: Duration,
     rpc_retries: usize,
+    rpc_qps: Option<usize>,
+    bandwidth_bytes_per_sec: Option<usize>,
+    read_concurrency: Option<usize>,
   ) -> Result<Store, String> {
     Ok(Store {
       local: self.local,
@@ -297,12 +494,85 @@ impl Store {
         root_ca_certs,
         headers,
         chunk_size_bytes,
+        chunk_size_bounds,
         upload_timeout,
         rpc_retries,
+        rpc_qps,
+        bandwidth_bytes_per_sec,
+        read_concurrency,
       )?),
+      resolvers: self.resolvers,
+      directory_cache: self.directory_cache,
+      glob_match_cache: self.glob_match_cache,
+      materialize_umask: self.materialize_umask,
+      digest_xattrs: self.digest_xattrs,
+    })
+  }
+
+  ///
+  /// Add a read-only source of blobs to a Store, which is consulted (after any previously added
+  /// resolvers) for digests which are missing from both the local and remote stores. Resolved
+  /// blobs are back-filled into local storage.
+  ///
+  pub fn with_digest_resolver(self, resolver: Arc<dyn DigestResolver>) -> Store {
+    let mut resolvers = self.resolvers;
+    resolvers.push(resolver);
+    Store { resolvers, ..self }
+  }
+
+  ///
+  /// Encrypt blobs with the given AES-256-GCM key before they are uploaded to the remote half of
+  /// this Store, and decrypt them after they are downloaded. See `BlobEncryption`.
+  ///
+  pub fn with_remote_encryption(self, key: &[u8]) -> Result<Store, String> {
+    let remote = self
+      .remote
+      .ok_or("Cannot encrypt blobs without a remote store")?;
+    let encryption = BlobEncryption::new(key, self.local.encrypted_digest_dbs()?)?;
+    Ok(Store {
+      remote: Some(remote.with_encryption(encryption)),
+      ..self
     })
   }
 
+  ///
+  /// Limit the total size of the blobs which are being uploaded to or downloaded from the remote
+  /// half of this Store at once. Transfers which would exceed the budget wait for others to
+  /// complete.
+  ///
+  pub fn with_remote_max_inflight_bytes(self, max_bytes: usize) -> Result<Store, String> {
+    let remote = self
+      .remote
+      .ok_or("Cannot limit in-flight bytes without a remote store")?;
+    Ok(Store {
+      remote: Some(remote.with_max_inflight_bytes(max_bytes)),
+      ..self
+    })
+  }
+
+  ///
+  /// Address blobs in the remote half of this Store using the given digest function, which may
+  /// differ from the SHA-256 digests of the local store. See `DigestTranslation`.
+  ///
+  pub fn with_remote_digest_function(
+    self,
+    digest_function: DigestFunction,
+  ) -> Result<Store, String> {
+    match digest_function {
+      DigestFunction::Sha256 => Ok(self),
+      DigestFunction::Sha1 => {
+        let remote = self
+          .remote
+          .ok_or("Cannot translate digests without a remote store")?;
+        let digest_translation = DigestTranslation::new(self.local.remote_hash_dbs()?);
+        Ok(Store {
+          remote: Some(remote.with_digest_translation(digest_translation)),
+          ..self
+        })
+      }
+    }
+  }
+
   // This default suffix is also hard-coded into the Python options code in global_options.py
   pub fn default_path() -> PathBuf {
     default_cache_path().join("lmdb_store")
@@ -329,6 +599,93 @@ impl Store {
       .await
   }
 
+  ///
+  /// Store a batch of files locally, hashing them in parallel. Returns their Digests, in order.
+  ///
+  pub async fn store_file_bytes_batch(
+    &self,
+    items: Vec<Bytes>,
+    initial_lease: bool,
+  ) -> Result<Vec<Digest>, String> {
+    self
+      .local
+      .store_bytes_batch(EntryType::File, items, initial_lease)
+      .await
+  }
+
+  ///
+  /// Stores the given in-memory files (and empty directories) in bulk, returning a Snapshot of
+  /// them. This is much cheaper than storing and snapshotting each file individually and then
+  /// merging the results, which matters when many thousands of files are generated at once.
+  ///
+  /// Identical duplicate files are permitted, but two files at the same path must not differ.
+  ///
+  pub async fn snapshot_of_file_contents(
+    &self,
+    files: Vec<FileContent>,
+    empty_directories: Vec<RelativePath>,
+  ) -> Result<Snapshot, String> {
+    let (paths, contents): (Vec<_>, Vec<_>) = files
+      .into_iter()
+      .map(|file| ((file.path, file.is_executable), file.content))
+      .unzip();
+    let digests = self.store_file_bytes_batch(contents, true).await?;
+
+    let mut files_by_path: HashMap<PathBuf, (Digest, bool)> = HashMap::new();
+    for ((path, is_executable), digest) in paths.into_iter().zip(digests) {
+      if let Some(existing) = files_by_path.insert(path.clone(), (digest, is_executable)) {
+        if existing != (digest, is_executable) {
+          return Err(format!(
+            "Cannot create a digest containing two different files at the path {}.",
+            path.display()
+          ));
+        }
+      }
+    }
+
+    #[derive(Clone)]
+    struct Digester {
+      digests: Arc<HashMap<PathBuf, (Digest, bool)>>,
+    }
+
+    impl StoreFileByDigest<String> for Digester {
+      fn store_by_digest(
+        &self,
+        file: fs::File,
+      ) -> future::BoxFuture<'static, Result<hashing::Digest, String>> {
+        let res = self
+          .digests
+          .get(&file.path)
+          .map(|(digest, _)| *digest)
+          .ok_or_else(|| format!("No digest for {}", file.path.display()));
+        future::ready(res).boxed()
+      }
+    }
+
+    let path_stats = files_by_path
+      .iter()
+      .map(|(path, (_, is_executable))| fs::PathStat::File {
+        path: path.clone(),
+        stat: fs::File {
+          path: path.clone(),
+          is_executable: *is_executable,
+        },
+      })
+      .chain(empty_directories.into_iter().map(|path| {
+        let path: PathBuf = path.into();
+        fs::PathStat::dir(path.clone(), fs::Dir(path))
+      }))
+      .collect();
+    Snapshot::from_path_stats(
+      self.clone(),
+      Digester {
+        digests: Arc::new(files_by_path),
+      },
+      path_stats,
+    )
+    .await
+  }
+
   /// Store a digest under a given file path, returning a Snapshot
   pub async fn snapshot_of_one_file(
     &self,
@@ -403,9 +760,11 @@ impl Store {
     initial_lease: bool,
   ) -> Result<Digest, String> {
     let local = self.local.clone();
-    local
+    let digest = local
       .store_bytes(EntryType::Directory, directory.to_bytes(), initial_lease)
-      .await
+      .await?;
+    self.directory_cache.insert(digest, directory.clone());
+    Ok(digest)
   }
 
   ///
@@ -415,11 +774,17 @@ impl Store {
   /// fingerprint exactly matches that which is requested. Will return an Err if it would return a
   /// non-canonical Directory.
   ///
+  /// Recently loaded Directories are cached in memory in parsed form.
+  ///
   pub async fn load_directory(
     &self,
     digest: Digest,
   ) -> Result<Option<(remexec::Directory, LoadMetadata)>, String> {
-    self
+    if let Some(directory) = self.directory_cache.get(digest) {
+      return Ok(Some((directory, LoadMetadata::Local)));
+    }
+
+    let maybe_directory = self
       .load_bytes_with(
         EntryType::Directory,
         digest,
@@ -450,13 +815,18 @@ impl Store {
           Ok(directory)
         },
       )
-      .await
+      .await?;
+    if let Some((directory, _)) = &maybe_directory {
+      self.directory_cache.insert(digest, directory.clone());
+    }
+    Ok(maybe_directory)
   }
 
   ///
-  /// Loads bytes from remote cas if required and possible (i.e. if remote is configured). Takes
-  /// two functions f_local and f_remote. These functions are any validation or transformations you
-  /// want to perform on the bytes received from the local and remote cas (if remote is configured).
+  /// Loads bytes from remote cas if required and possible (i.e. if remote is configured), and then
+  /// from any resolvers. Takes two functions f_local and f_remote. These functions are any
+  /// validation or transformations you want to perform on the bytes received from the local and
+  /// remote cas (or resolvers).
   ///
   async fn load_bytes_with<
     T: Send + 'static,
@@ -477,29 +847,36 @@ impl Store {
       .load_bytes_with(entry_type, digest, f_local)
       .await?;
 
-    match (maybe_local_value, maybe_remote) {
-      (Some(value_result), _) => value_result.map(|res| Some((res, LoadMetadata::Local))),
-      (None, None) => Ok(None),
-      (None, Some(remote)) => {
-        let maybe_bytes = remote.load_bytes_with(digest, Ok).await?;
-
-        match maybe_bytes {
-          Some(bytes) => {
-            let value = f_remote(bytes.clone())?;
-            let stored_digest = local.store_bytes(entry_type, bytes, true).await?;
-            if digest == stored_digest {
-              let time_span = TimeSpan::since(&start);
-              Ok(Some((value, LoadMetadata::Remote(time_span))))
-            } else {
-              Err(format!(
-                "CAS gave wrong digest: expected {:?}, got {:?}",
-                digest, stored_digest
-              ))
-            }
-          }
-          None => Ok(None),
+    if let Some(value_result) = maybe_local_value {
+      return value_result.map(|res| Some((res, LoadMetadata::Local)));
+    }
+
+    let mut maybe_bytes = match maybe_remote {
+      Some(remote) => remote.load_bytes_with(digest, Ok).await?,
+      None => None,
+    };
+    for resolver in &self.resolvers {
+      if maybe_bytes.is_some() {
+        break;
+      }
+      maybe_bytes = resolver.resolve(digest).await?;
+    }
+
+    match maybe_bytes {
+      Some(bytes) => {
+        let value = f_remote(bytes.clone())?;
+        let stored_digest = local.store_bytes(entry_type, bytes, true).await?;
+        if digest == stored_digest {
+          let time_span = TimeSpan::since(&start);
+          Ok(Some((value, LoadMetadata::Remote(time_span))))
+        } else {
+          Err(format!(
+            "CAS gave wrong digest: expected {:?}, got {:?}",
+            digest, stored_digest
+          ))
         }
       }
+      None => Ok(None),
     }
   }
 
@@ -524,6 +901,7 @@ impl Store {
 
     let store = self.clone();
     let remote = remote.clone();
+    let progress = Arc::new(ProgressReporter::for_current_workunit());
     async move {
       let ingested_digests = store
         .expand_digests(digests.iter(), LocalMissingBehavior::Fetch)
@@ -536,6 +914,19 @@ impl Store {
           remote.list_missing_digests(request).await?
         };
 
+      // The empty blob is always available from a REAPI server, and is never stored locally.
+      let digests_to_upload = digests_to_upload
+        .into_iter()
+        .filter(|digest| *digest != EMPTY_DIGEST)
+        .collect::<Vec<_>>();
+      progress.add_total(
+        digests_to_upload.len() as u64,
+        digests_to_upload
+          .iter()
+          .map(|digest| digest.size_bytes as u64)
+          .sum(),
+      );
+
       let uploaded_digests = future::try_join_all(
         digests_to_upload
           .into_iter()
@@ -543,6 +934,7 @@ impl Store {
             let entry_type = ingested_digests[&digest];
             let local = store.local.clone();
             let remote = remote.clone();
+            let progress = progress.clone();
 
             async move {
               let executor = local.executor().clone();
@@ -552,10 +944,12 @@ impl Store {
                   executor.block_on(remote.store_bytes(bytes))
                 })
                 .await?;
-              match maybe_upload {
-                Some(res) => res,
-                None => Err(format!("Failed to upload digest {:?}: Not found", digest)),
-              }
+              let uploaded = match maybe_upload {
+                Some(res) => res?,
+                None => return Err(format!("Failed to upload digest {:?}: Not found", digest)),
+              };
+              progress.advance(1, digest.size_bytes as u64);
+              Ok(uploaded)
             }
           })
           .collect::<Vec<_>>(),
@@ -583,6 +977,15 @@ impl Store {
   pub fn ensure_local_has_recursive_directory(
     &self,
     dir_digest: Digest,
+  ) -> BoxFuture<'static, Result<(), String>> {
+    let progress = Arc::new(ProgressReporter::for_current_workunit());
+    self.ensure_local_has_recursive_directory_with(dir_digest, progress)
+  }
+
+  fn ensure_local_has_recursive_directory_with(
+    &self,
+    dir_digest: Digest,
+    progress: Arc<ProgressReporter>,
   ) -> BoxFuture<'static, Result<(), String>> {
     let loaded_directory = {
       let store = self.clone();
@@ -600,6 +1003,16 @@ impl Store {
         )
       })
       .and_then(move |directory| {
+        progress.add_total(
+          directory.files.len() as u64,
+          directory
+            .files
+            .iter()
+            .filter_map(|file_node| file_node.digest.as_ref())
+            .map(|digest| digest.size_bytes as u64)
+            .sum(),
+        );
+
         // Traverse the files within directory
         let file_futures = directory
           .files
@@ -608,7 +1021,13 @@ impl Store {
             // TODO(tonic): Find better idiom for these conversions.
             let file_digest = try_future!(require_digest(file_node.digest.as_ref()));
             let store = store.clone();
-            async move { store.ensure_local_has_file(file_digest).await }.boxed()
+            let progress = progress.clone();
+            async move {
+              store.ensure_local_has_file(file_digest).await?;
+              progress.advance(1, file_digest.size_bytes as u64);
+              Ok::<_, String>(())
+            }
+            .boxed()
           })
           .collect::<Vec<_>>();
 
@@ -619,7 +1038,7 @@ impl Store {
           .map(move |child_dir| {
             // TODO(tonic): Find better idiom for these conversions.
             let child_digest = try_future!(require_digest(child_dir.digest.as_ref()));
-            store.ensure_local_has_recursive_directory(child_digest)
+            store.ensure_local_has_recursive_directory_with(child_digest, progress.clone())
           })
           .collect::<Vec<_>>();
 
@@ -711,12 +1130,124 @@ impl Store {
       .await
   }
 
+  ///
+  /// Flushes the local store to disk, so that recent writes survive a crash of the machine.
+  ///
+  pub async fn sync(&self) -> Result<(), String> {
+    self.local.sync().await
+  }
+
+  ///
+  /// Begins staging small blobs in memory (rather than writing each of them to the local store)
+  /// for the duration of a session. Must be paired with a call to `end_staging`.
+  ///
+  pub fn begin_staging(&self) {
+    self.local.begin_staging()
+  }
+
+  ///
+  /// Ends staging for a session. Once no sessions are active, all staged blobs are committed to
+  /// the local store.
+  ///
+  pub async fn end_staging(&self) -> Result<(), String> {
+    self.local.end_staging().await
+  }
+
+  ///
+  /// Commits any staged blobs reachable from the given Digests to the local store: this should be
+  /// called for the outputs of any result which will be cached beyond the current session.
+  ///
+  pub async fn commit_staged<'a, Ds: Iterator<Item = &'a Digest>>(
+    &self,
+    digests: Ds,
+  ) -> Result<(), String> {
+    let reachable_digests_and_types = self
+      .expand_digests(digests, LocalMissingBehavior::Ignore)
+      .await?;
+    self
+      .local
+      .commit_staged(reachable_digests_and_types.into_iter())
+      .await
+  }
+
   pub fn garbage_collect(
     &self,
     target_size_bytes: usize,
     shrink_behavior: ShrinkBehavior,
   ) -> Result<(), String> {
-    match self.local.shrink(target_size_bytes, shrink_behavior) {
+    self.shrink_local(target_size_bytes, shrink_behavior, &HashMap::new())
+  }
+
+  ///
+  /// As `garbage_collect`, but computes which blobs are reachable from the given roots (such as
+  /// cached action results and values retained in memory), rather than relying only on leases.
+  /// Unreachable blobs are collected before any reachable blobs, blobs which are reachable from
+  /// live roots are never collected, and other reachable blobs are retained at least as long as
+  /// their roots are.
+  ///
+  pub async fn garbage_collect_with_roots(
+    &self,
+    target_size_bytes: usize,
+    shrink_behavior: ShrinkBehavior,
+    roots: GcRoots,
+  ) -> Result<(), String> {
+    let references = self.expand_roots(roots).await;
+    let store = self.clone();
+    self
+      .local
+      .executor()
+      .spawn_blocking_with_io_priority(self.local.maintenance_io_priority(), move || {
+        store.shrink_local(target_size_bytes, shrink_behavior, &references)
+      })
+      .await
+  }
+
+  ///
+  /// Computes the lifetime of each blob which is reachable from the given roots.
+  ///
+  async fn expand_roots(&self, roots: GcRoots) -> HashMap<Fingerprint, RootLifetime> {
+    let expanded = future::join_all(roots.into_roots().map(|(digest, lifetime)| async move {
+      // NB: A root which is not completely present locally (for example, a cached action result
+      // whose outputs were already collected) references whatever remains of it.
+      let reachable = self
+        .expand_digests(std::iter::once(&digest), LocalMissingBehavior::Ignore)
+        .await
+        .unwrap_or_else(|e| {
+          log::debug!(
+            "Failed to expand garbage collection root {:?}: {}",
+            digest,
+            e
+          );
+          HashMap::new()
+        });
+      (reachable, lifetime)
+    }))
+    .await;
+
+    let mut references = HashMap::new();
+    for (reachable, lifetime) in expanded {
+      for digest in reachable.keys() {
+        let reference = references.entry(digest.hash).or_insert(lifetime);
+        *reference = std::cmp::max(*reference, lifetime);
+      }
+    }
+    references
+  }
+
+  fn shrink_local(
+    &self,
+    target_size_bytes: usize,
+    shrink_behavior: ShrinkBehavior,
+    references: &HashMap<Fingerprint, RootLifetime>,
+  ) -> Result<(), String> {
+    // Cached Directories (and the results of glob matches) might be garbage collected, and should
+    // then no longer be loadable.
+    self.directory_cache.clear();
+    self.glob_match_cache.clear();
+    match self
+      .local
+      .shrink_with_references(target_size_bytes, shrink_behavior, references)
+    {
       Ok(size) => {
         if size > target_size_bytes {
           log::warn!(
@@ -845,13 +1376,187 @@ impl Store {
     &self,
     destination: PathBuf,
     digest: Digest,
+  ) -> BoxFuture<'static, Result<DirectoryMaterializeMetadata, String>> {
+    self.materialize_directory_with(destination, digest, false)
+  }
+
+  ///
+  /// As `materialize_directory`, but skips re-writing files which already exist with the expected
+  /// content and mode. This makes repeatedly materializing the same outputs (into `dist/`, for
+  /// example) nearly free, and leaves the mtimes of unchanged files alone.
+  ///
+  /// The content of an existing file is checked using its digest tag (see `DIGEST_XATTR_NAME`)
+  /// when it has a valid one, and by hashing it otherwise.
+  ///
+  pub fn materialize_directory_skipping_unchanged(
+    &self,
+    destination: PathBuf,
+    digest: Digest,
+  ) -> BoxFuture<'static, Result<DirectoryMaterializeMetadata, String>> {
+    self.materialize_directory_with(destination, digest, true)
+  }
+
+  ///
+  /// As `materialize_directory_skipping_unchanged`, but transactional: either all of the changed
+  /// entries of the directory are written, or none of them are.
+  ///
+  /// The "roots" of the materialization are each file which is changed, and each directory which
+  /// does not already exist at the destination. All roots are first materialized into a staging
+  /// directory beneath the destination (see `transaction::STAGING_DIR_PREFIX`), and are then
+  /// renamed into place. If staging or renaming fails (or if staging is interrupted), the
+  /// destination is left as it was, and any staging directory which is left behind by an
+  /// interrupted process is removed by the next materialization into the same destination. Entries
+  /// which exist at the destination but not in the directory are left alone.
+  ///
+  pub async fn materialize_directory_transactionally(
+    &self,
+    destination: PathBuf,
+    digest: Digest,
+  ) -> Result<(), String> {
+    let roots = self
+      .materialization_roots(destination.clone(), digest)
+      .await?;
+    if roots.is_empty() {
+      return Ok(());
+    }
+
+    let staging_dir = self
+      .local
+      .executor()
+      .spawn_blocking(move || transaction::create_staging_dir(&destination))
+      .await?;
+
+    let mut staged_roots = Vec::with_capacity(roots.len());
+    let mut staging_futures = Vec::with_capacity(roots.len());
+    for (i, (root_destination, root)) in roots.into_iter().enumerate() {
+      let staged = staging_dir.path().join(i.to_string());
+      staging_futures.push(match root {
+        MaterializationRoot::File {
+          digest,
+          is_executable,
+        } => self
+          .materialize_file(staged.clone(), digest, is_executable, false)
+          .map_ok(|_| ())
+          .boxed(),
+        MaterializationRoot::Directory(digest) => self
+          .materialize_directory(staged.clone(), digest)
+          .map_ok(|_| ())
+          .boxed(),
+      });
+      staged_roots.push(StagedRoot {
+        staged,
+        destination: root_destination,
+      });
+    }
+    future::try_join_all(staging_futures).await?;
+
+    self
+      .local
+      .executor()
+      .spawn_blocking(move || transaction::commit(staging_dir.path(), &staged_roots))
+      .await
+  }
+
+  ///
+  /// Computes the roots of a transactional materialization of the given directory (see
+  /// `materialize_directory_transactionally`), by walking it alongside the destination.
+  ///
+  async fn materialization_roots(
+    &self,
+    destination: PathBuf,
+    digest: Digest,
+  ) -> Result<Vec<(PathBuf, MaterializationRoot)>, String> {
+    let explicit_mode = self.materialize_umask != DEFAULT_MATERIALIZE_UMASK;
+    let mut roots = Vec::new();
+    let mut to_visit = vec![(destination, digest)];
+    while let Some((destination, digest)) = to_visit.pop() {
+      let (directory, _metadata) = self
+        .load_directory(digest)
+        .await?
+        .ok_or_else(|| format!("Directory with digest {:?} not found", digest))?;
+      let mut files = Vec::with_capacity(directory.files.len());
+      for file_node in &directory.files {
+        let digest = require_digest(file_node.digest.as_ref())?;
+        files.push((
+          destination.join(&file_node.name),
+          digest,
+          file_node.is_executable,
+          self.materialized_mode(file_node.is_executable),
+        ));
+      }
+      let mut directories = Vec::with_capacity(directory.directories.len());
+      for directory_node in &directory.directories {
+        let digest = require_digest(directory_node.digest.as_ref())?;
+        directories.push((destination.join(&directory_node.name), digest));
+      }
+
+      let (changed_files, existing_directories, new_directories) = self
+        .local
+        .executor()
+        .spawn_blocking(move || {
+          let file_count = files.len();
+          let changed_files = files
+            .into_iter()
+            .filter(|(path, digest, _, mode)| {
+              // NB: Files whose mode has changed are replaced, rather than having their mode
+              // changed in place, so that they are changed along with the rest of the transaction.
+              existing_file(path, *digest, *mode, explicit_mode) != ExistingFile::Unchanged
+            })
+            .collect::<Vec<_>>();
+          if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+            workunit_store_handle.store.increment_counter(
+              Metric::LocalMaterializeUnchangedFiles,
+              (file_count - changed_files.len()) as u64,
+            );
+          }
+          let (existing_directories, new_directories): (Vec<_>, Vec<_>) =
+            directories.into_iter().partition(|(path, _)| {
+              std::fs::symlink_metadata(path)
+                .map(|metadata| metadata.is_dir())
+                .unwrap_or(false)
+            });
+          (changed_files, existing_directories, new_directories)
+        })
+        .await;
+
+      roots.extend(
+        changed_files
+          .into_iter()
+          .map(|(path, digest, is_executable, _)| {
+            (
+              path,
+              MaterializationRoot::File {
+                digest,
+                is_executable,
+              },
+            )
+          }),
+      );
+      roots.extend(
+        new_directories
+          .into_iter()
+          .map(|(path, digest)| (path, MaterializationRoot::Directory(digest))),
+      );
+      to_visit.extend(existing_directories);
+    }
+    Ok(roots)
+  }
+
+  fn materialize_directory_with(
+    &self,
+    destination: PathBuf,
+    digest: Digest,
+    skip_unchanged: bool,
   ) -> BoxFuture<'static, Result<DirectoryMaterializeMetadata, String>> {
     let root = Arc::new(Mutex::new(None));
+    let progress = Arc::new(ProgressReporter::for_current_workunit());
     self
       .materialize_directory_helper(
         destination,
         RootOrParentMetadataBuilder::Root(root.clone()),
         digest,
+        skip_unchanged,
+        progress,
       )
       .and_then(move |()| {
         future::ready(Ok(
@@ -866,25 +1571,39 @@ impl Store {
     destination: PathBuf,
     root_or_parent_metadata: RootOrParentMetadataBuilder,
     digest: Digest,
+    skip_unchanged: bool,
+    progress: Arc<ProgressReporter>,
   ) -> BoxFuture<'static, Result<(), String>> {
     let store = self.clone();
     async move {
-      let directory_creation =
-        if let RootOrParentMetadataBuilder::Root(..) = root_or_parent_metadata {
-          let destination = destination.clone();
-          store
-            .local
-            .executor()
-            .spawn_blocking(move || fs::safe_create_dir_all(&destination))
-            .await
-        } else {
-          let destination = destination.clone();
-          store
-            .local
-            .executor()
-            .spawn_blocking(move || fs::safe_create_dir(&destination))
-            .await
-        };
+      let explicit_mode = if store.materialize_umask != DEFAULT_MATERIALIZE_UMASK {
+        Some(store.materialized_mode(true))
+      } else {
+        None
+      };
+      let is_root = matches!(
+        root_or_parent_metadata,
+        RootOrParentMetadataBuilder::Root(..)
+      );
+      let directory_creation = {
+        let destination = destination.clone();
+        store
+          .local
+          .executor()
+          .spawn_blocking(move || -> Result<(), String> {
+            if is_root {
+              fs::safe_create_dir_all(&destination)?;
+            } else {
+              fs::safe_create_dir(&destination)?;
+            }
+            if let Some(mode) = explicit_mode {
+              std::fs::set_permissions(&destination, std::fs::Permissions::from_mode(mode))
+                .map_err(|e| format!("Failed to set permissions: {}", e))?;
+            }
+            Ok(())
+          })
+          .await
+      };
       directory_creation.map_err(|e| {
         format!(
           "Failed to create directory {}: {}",
@@ -919,6 +1638,15 @@ impl Store {
         }
       };
 
+      progress.add_total(
+        directory.files.len() as u64,
+        directory
+          .files
+          .iter()
+          .filter_map(|file_node| file_node.digest.as_ref())
+          .map(|digest| digest.size_bytes as u64)
+          .sum(),
+      );
       let file_futures = directory
         .files
         .iter()
@@ -928,9 +1656,15 @@ impl Store {
           let digest = try_future!(require_digest(file_node.digest.as_ref()));
           let child_files = child_files.clone();
           let name = file_node.name.to_owned();
+          let progress = progress.clone();
           store
-            .materialize_file(path, digest, file_node.is_executable)
-            .map(move |result| result.map(|metadata| child_files.lock().insert(name, metadata)))
+            .materialize_file(path, digest, file_node.is_executable, skip_unchanged)
+            .map(move |result| {
+              result.map(|metadata| {
+                progress.advance(1, digest.size_bytes as u64);
+                child_files.lock().insert(name, metadata)
+              })
+            })
             .boxed()
         })
         .collect::<Vec<_>>();
@@ -948,7 +1682,13 @@ impl Store {
             child_files.clone(),
           ));
 
-          store.materialize_directory_helper(path, builder, digest)
+          store.materialize_directory_helper(
+            path,
+            builder,
+            digest,
+            skip_unchanged,
+            progress.clone(),
+          )
         })
         .collect::<Vec<_>>();
       let _ = future::try_join(
@@ -967,9 +1707,47 @@ impl Store {
     destination: PathBuf,
     digest: Digest,
     is_executable: bool,
+    skip_unchanged: bool,
   ) -> BoxFuture<'static, Result<LoadMetadata, String>> {
     let store = self.clone();
+    let mode = self.materialized_mode(is_executable);
+    let explicit_mode = self.materialize_umask != DEFAULT_MATERIALIZE_UMASK;
+    let digest_xattrs = self.digest_xattrs;
     let res = async move {
+      if skip_unchanged {
+        let destination = destination.clone();
+        let unchanged = store
+          .local
+          .executor()
+          .spawn_blocking(move || -> Result<bool, String> {
+            match existing_file(&destination, digest, mode, explicit_mode) {
+              ExistingFile::Unchanged => Ok(true),
+              // Only the mode (commonly, executability) differs, so the content needn't be
+              // re-written.
+              ExistingFile::ModeChanged => {
+                std::fs::set_permissions(&destination, std::fs::Permissions::from_mode(mode))
+                  .map_err(|e| {
+                    format!(
+                      "Error setting permissions of file {}: {:?}",
+                      destination.display(),
+                      e
+                    )
+                  })?;
+                Ok(true)
+              }
+              ExistingFile::Changed => Ok(false),
+            }
+          })
+          .await?;
+        if unchanged {
+          if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+            workunit_store_handle
+              .store
+              .increment_counter(Metric::LocalMaterializeUnchangedFiles, 1);
+          }
+          return Ok(LoadMetadata::Local);
+        }
+      }
       let write_result = store
         .load_file_bytes_with(digest, move |bytes| {
           if destination.exists() {
@@ -979,7 +1757,7 @@ impl Store {
           let mut f = OpenOptions::new()
             .create(true)
             .write(true)
-            .mode(if is_executable { 0o755 } else { 0o644 })
+            .mode(mode)
             .open(&destination)
             .map_err(|e| {
               format!(
@@ -988,8 +1766,23 @@ impl Store {
                 e
               )
             })?;
+          if explicit_mode {
+            f.set_permissions(std::fs::Permissions::from_mode(mode))
+              .map_err(|e| {
+                format!(
+                  "Error setting permissions of file {}: {:?}",
+                  destination.display(),
+                  e
+                )
+              })?;
+          }
           f.write_all(&bytes)
             .map_err(|e| format!("Error writing file {}: {:?}", destination.display(), e))?;
+          if digest_xattrs {
+            if let Err(e) = digest_xattr::tag(&f, digest) {
+              log::debug!("Failed to tag {}: {}", destination.display(), e);
+            }
+          }
           Ok(())
         })
         .await?;
@@ -1136,9 +1929,195 @@ impl Store {
     res.boxed()
   }
 
+  ///
+  /// Computes the number and total size of the files below the given Directory (and the number
+  /// of directories, including itself), using only the sizes recorded in its Directory protos. The
+  /// contents of files are never loaded, so this is cheap enough to call before transferring a
+  /// Directory, in order to warn about unexpectedly large inputs or outputs.
+  ///
+  pub async fn tree_size(&self, digest: Digest) -> Result<TreeSize, String> {
+    let directory_sizes = self
+      .walk(digest, |_, _, _, directory| {
+        let file_bytes = directory
+          .files
+          .iter()
+          .map(|file_node| {
+            require_digest(file_node.digest.as_ref()).map(|digest| digest.size_bytes)
+          })
+          .sum::<Result<usize, String>>();
+        let file_count = directory.files.len();
+        future::ready(file_bytes.map(|file_bytes| TreeSize {
+          file_count,
+          file_bytes,
+          directory_count: 1,
+        }))
+        .boxed()
+      })
+      .await?;
+    Ok(
+      directory_sizes
+        .into_iter()
+        .fold(TreeSize::default(), |total, size| TreeSize {
+          file_count: total.file_count + size.file_count,
+          file_bytes: total.file_bytes + size.file_bytes,
+          directory_count: total.directory_count + size.directory_count,
+        }),
+    )
+  }
+
+  ///
+  /// Returns the digest of a Directory which is identical to the given Directory, except that the
+  /// files at the given paths have the given digests (while preserving their executable bits).
+  ///
+  /// Each of the paths must already be a file in the Directory. The replacement file contents
+  /// are not loaded, and so must already have been stored.
+  ///
+  pub async fn replace_files(
+    &self,
+    digest: Digest,
+    replacements: HashMap<PathBuf, Digest>,
+  ) -> Result<Digest, String> {
+    if replacements.is_empty() {
+      return Ok(digest);
+    }
+
+    let file_digests_per_directory = self
+      .walk(digest, |_, path_so_far, _, directory| {
+        let file_digests = directory
+          .files
+          .iter()
+          .map(|file_node| {
+            require_digest(file_node.digest.as_ref())
+              .map(|digest| (path_so_far.join(&file_node.name), digest))
+          })
+          .collect::<Result<Vec<_>, String>>();
+        future::ready(file_digests).boxed()
+      })
+      .await?;
+    let mut file_digests = file_digests_per_directory
+      .into_iter()
+      .flatten()
+      .collect::<HashMap<_, _>>();
+    for (path, replacement) in replacements {
+      match file_digests.get_mut(&path) {
+        Some(file_digest) => *file_digest = replacement,
+        None => {
+          return Err(format!(
+            "Cannot replace {}: it is not a file in {:?}",
+            path.display(),
+            digest
+          ))
+        }
+      }
+    }
+
+    let snapshot = Snapshot::from_digest(self.clone(), digest).await?;
+    Snapshot::digest_from_path_stats(
+      self.clone(),
+      StoreManyFileDigests { hash: file_digests },
+      snapshot.path_stats,
+    )
+    .await
+  }
+
   pub fn all_local_digests(&self, entry_type: EntryType) -> Result<Vec<Digest>, String> {
     self.local.all_digests(entry_type)
   }
+
+  ///
+  /// Expands a Digest as displayed in errors, workunits and logs (i.e. `<fingerprint>:<size>`,
+  /// with a possibly abbreviated fingerprint) into a full Digest, by finding the unique entry of
+  /// the local store whose fingerprint has the given prefix.
+  ///
+  pub async fn expand_digest(&self, displayed: &str) -> Result<Digest, String> {
+    let (hex_prefix, size_bytes) = Digest::parse_displayed(displayed)?;
+    if hex_prefix.len() == hashing::FINGERPRINT_SIZE * 2 {
+      return Ok(Digest::new(
+        Fingerprint::from_hex_string(&hex_prefix)?,
+        size_bytes,
+      ));
+    }
+    let mut fingerprints = self
+      .local
+      .find_by_prefix(&hex_prefix)
+      .await?
+      .into_iter()
+      .map(|(_, fingerprint)| fingerprint)
+      .collect::<Vec<_>>();
+    fingerprints.sort();
+    fingerprints.dedup();
+    match &fingerprints[..] {
+      [fingerprint] => Ok(Digest::new(*fingerprint, size_bytes)),
+      [] => Err(format!(
+        "No entry of the local store matches the digest {}.",
+        displayed
+      )),
+      _ => Err(format!(
+        "The digest {} is ambiguous: it matches {} entries of the local store. Use \
+         `--full-digests` to display full digests.",
+        displayed,
+        fingerprints.len()
+      )),
+    }
+  }
+}
+
+///
+/// A file or directory which is replaced as a unit by `Store::materialize_directory_transactionally`.
+///
+enum MaterializationRoot {
+  File { digest: Digest, is_executable: bool },
+  Directory(Digest),
+}
+
+///
+/// The state of an existing file, relative to a file which is to be materialized in its place.
+///
+#[derive(Debug, PartialEq)]
+enum ExistingFile {
+  /// The file already has the expected content and mode.
+  Unchanged,
+  /// The file has the expected content, but not the expected mode.
+  ModeChanged,
+  /// The file does not exist, or does not have the expected content.
+  Changed,
+}
+
+///
+/// Compares the given path to a file with the given Digest and mode. If the mode was not
+/// explicitly set (see `Store::materialize_umask`), only executability is compared.
+///
+fn existing_file(path: &Path, digest: Digest, mode: u32, explicit_mode: bool) -> ExistingFile {
+  let metadata = match std::fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.is_file() => metadata,
+    _ => return ExistingFile::Changed,
+  };
+  if metadata.len() != digest.size_bytes as u64 {
+    return ExistingFile::Changed;
+  }
+  let content_matches = if let Some(tagged_digest) = digest_xattr::read_tag(path) {
+    tagged_digest == digest
+  } else {
+    let mut hasher = WriterHasher::new(std::io::sink());
+    match std::fs::File::open(path).and_then(|mut file| std::io::copy(&mut file, &mut hasher)) {
+      Ok(_) => hasher.finish().0 == digest,
+      Err(_) => false,
+    }
+  };
+  if !content_matches {
+    return ExistingFile::Changed;
+  }
+  let existing_mode = metadata.permissions().mode() & 0o777;
+  let mode_matches = if explicit_mode {
+    existing_mode == mode
+  } else {
+    (existing_mode & 0o100 != 0) == (mode & 0o100 != 0)
+  };
+  if mode_matches {
+    ExistingFile::Unchanged
+  } else {
+    ExistingFile::ModeChanged
+  }
 }
 
 /// Behavior in case a needed digest is missing in the local store.
@@ -1181,6 +2160,10 @@ impl StoreWrapper for Store {
   async fn record_directory(&self, directory: &remexec::Directory) -> Result<Digest, String> {
     Store::record_directory(self, directory, true).await
   }
+
+  fn glob_match_cache(&self) -> Option<&GlobMatchCache> {
+    Some(&self.glob_match_cache)
+  }
 }
 
 // Only public for testing.
@@ -1,6 +1,6 @@
-use super::{EntryType, ShrinkBehavior};
+use super::{EntryType, RootLifetime, ShrinkBehavior};
 
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
 use std::path::Path;
 use std::sync::Arc;
 use std::time::{self, Duration};
@@ -10,7 +10,10 @@ use futures::future;
 use hashing::{Digest, Fingerprint, EMPTY_DIGEST};
 use lmdb::Error::NotFound;
 use lmdb::{self, Cursor, Transaction};
-use sharded_lmdb::{ShardedLmdb, VersionedFingerprint};
+use parking_lot::Mutex;
+use sharded_lmdb::{begin_ro_txn, ShardedLmdb, VersionedFingerprint};
+use task_executor::IoPriority;
+use tokio::sync::Semaphore;
 use workunit_store::ObservationMetric;
 
 #[derive(Debug, Clone)]
@@ -25,7 +28,93 @@ struct InnerStore {
   //  2. It's nice to know whether we should be able to parse something as a proto.
   file_dbs: Result<Arc<ShardedLmdb>, String>,
   directory_dbs: Result<Arc<ShardedLmdb>, String>,
+  // The digests of the ciphertexts of blobs which were encrypted before being uploaded to a remote
+  // store: see `BlobEncryption`.
+  encrypted_digest_dbs: Result<Arc<ShardedLmdb>, String>,
+  // The hashes of blobs which were uploaded to a remote store which uses a different digest
+  // function: see `DigestTranslation`.
+  remote_hash_dbs: Result<Arc<ShardedLmdb>, String>,
   executor: task_executor::Executor,
+  // If set, bounds the number of blobs which are hashed concurrently.
+  hashing_permits: Option<Semaphore>,
+  // The IO priority of background maintenance, such as lease extension and garbage collection.
+  maintenance_io_priority: IoPriority,
+  staging: Mutex<Staging>,
+  small_files: Mutex<SmallFiles>,
+}
+
+/// Blobs larger than this are never staged, since writing them is dominated by their size rather
+/// than by the cost of a transaction.
+const MAX_STAGED_BLOB_BYTES: usize = 64 * 1024;
+
+/// Once this many bytes are staged, further blobs are written through to LMDB.
+const MAX_STAGED_BYTES: usize = 64 * 1024 * 1024;
+
+/// The target number of bytes hashed by each blocking task when a batch of blobs is stored.
+const HASH_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+/// Files no larger than this are held in the `SmallFiles` table.
+const MAX_SMALL_FILE_BYTES: usize = 256;
+
+/// Once the `SmallFiles` table holds this many bytes, further files are not added to it.
+const MAX_SMALL_FILES_BYTES: usize = 16 * 1024 * 1024;
+
+///
+/// Small blobs which have been stored while a session is active, but which have not yet been
+/// written to LMDB.
+///
+/// Many small blobs (such as tiny generated files which are consumed immediately) are created and
+/// read during a session: staging them in memory avoids a write transaction per blob. Staged blobs
+/// are committed in batches: either explicitly (when they are referenced by a cacheable action
+/// result, see `commit_staged`) or when the last active session ends, since memoized values in
+/// pantsd may continue to reference them.
+///
+#[derive(Debug, Default)]
+struct Staging {
+  sessions: usize,
+  blobs: HashMap<(EntryType, Fingerprint), StagedBlob>,
+  total_bytes: usize,
+}
+
+#[derive(Debug)]
+struct StagedBlob {
+  bytes: Bytes,
+  initial_lease: bool,
+}
+
+///
+/// The content of tiny files which have been stored or loaded, held in memory for the lifetime of
+/// the store.
+///
+/// Tiny files (such as `__init__.py` files containing only a license header, or `py.typed`
+/// markers) are extremely common, and are read repeatedly: holding them in memory avoids a read
+/// transaction for each load, which is dominated by overhead rather than by the size of the file.
+/// Files in the table are always also stored (or staged), so the table needn't be persisted: it
+/// is only a read cache, and does not reduce the number of entries in LMDB.
+///
+#[derive(Debug, Default)]
+struct SmallFiles {
+  files: HashMap<Fingerprint, Bytes>,
+  total_bytes: usize,
+}
+
+impl SmallFiles {
+  fn insert(&mut self, fingerprint: Fingerprint, bytes: Bytes) {
+    if bytes.len() > MAX_SMALL_FILE_BYTES
+      || self.total_bytes + bytes.len() > MAX_SMALL_FILES_BYTES
+      || self.files.contains_key(&fingerprint)
+    {
+      return;
+    }
+    self.total_bytes += bytes.len();
+    self.files.insert(fingerprint, bytes);
+  }
+
+  fn remove(&mut self, fingerprint: &Fingerprint) {
+    if let Some(bytes) = self.files.remove(fingerprint) {
+      self.total_bytes -= bytes.len();
+    }
+  }
 }
 
 impl ByteStore {
@@ -44,6 +133,8 @@ impl ByteStore {
     let root = path.as_ref();
     let files_root = root.join("files");
     let directories_root = root.join("directories");
+    let encrypted_digests_root = root.join("encrypted_digests");
+    let remote_hashes_root = root.join("remote_hashes");
     Ok(ByteStore {
       inner: Arc::new(InnerStore {
         file_dbs: ShardedLmdb::new(
@@ -62,7 +153,28 @@ impl ByteStore {
           options.shard_count,
         )
         .map(Arc::new),
+        // NB: Entries are tiny, so a single shard suffices.
+        encrypted_digest_dbs: ShardedLmdb::new(
+          encrypted_digests_root,
+          options.directories_max_size_bytes,
+          executor.clone(),
+          options.lease_time,
+          1,
+        )
+        .map(Arc::new),
+        remote_hash_dbs: ShardedLmdb::new(
+          remote_hashes_root,
+          options.directories_max_size_bytes,
+          executor.clone(),
+          options.lease_time,
+          1,
+        )
+        .map(Arc::new),
         executor,
+        hashing_permits: options.hashing_parallelism.map(Semaphore::new),
+        maintenance_io_priority: options.maintenance_io_priority,
+        staging: Mutex::default(),
+        small_files: Mutex::default(),
       }),
     })
   }
@@ -71,6 +183,35 @@ impl ByteStore {
     &self.inner.executor
   }
 
+  pub fn maintenance_io_priority(&self) -> IoPriority {
+    self.inner.maintenance_io_priority
+  }
+
+  ///
+  /// Runs the given hashing work on the blocking pool, waiting for a permit first if hashing
+  /// parallelism is bounded.
+  ///
+  async fn spawn_hashing<F: FnOnce() -> R + Send + 'static, R: Send + 'static>(&self, f: F) -> R {
+    let _permit = match &self.inner.hashing_permits {
+      Some(permits) => Some(
+        permits
+          .acquire()
+          .await
+          .expect("The hashing semaphore is never closed."),
+      ),
+      None => None,
+    };
+    self.inner.executor.spawn_blocking(f).await
+  }
+
+  pub fn encrypted_digest_dbs(&self) -> Result<Arc<ShardedLmdb>, String> {
+    self.inner.encrypted_digest_dbs.clone()
+  }
+
+  pub fn remote_hash_dbs(&self) -> Result<Arc<ShardedLmdb>, String> {
+    self.inner.remote_hash_dbs.clone()
+  }
+
   pub async fn entry_type(&self, fingerprint: Fingerprint) -> Result<Option<EntryType>, String> {
     if fingerprint == EMPTY_DIGEST.hash {
       // Technically this is valid as both; choose Directory in case a caller is checking whether
@@ -78,6 +219,15 @@ impl ByteStore {
       return Ok(Some(EntryType::Directory));
     }
 
+    {
+      let staging = self.inner.staging.lock();
+      for entry_type in &[EntryType::Directory, EntryType::File] {
+        if staging.blobs.contains_key(&(*entry_type, fingerprint)) {
+          return Ok(Some(*entry_type));
+        }
+      }
+    }
+
     // In parallel, check for the given fingerprint in both databases.
     let d_dbs = self.inner.directory_dbs.clone()?;
     let is_dir = d_dbs.exists(fingerprint);
@@ -93,18 +243,73 @@ impl ByteStore {
     }
   }
 
+  ///
+  /// Finds the fingerprints of the stored entries whose hex representation starts with the given
+  /// (lowercase) prefix, along with their types.
+  ///
+  pub async fn find_by_prefix(
+    &self,
+    hex_prefix: &str,
+  ) -> Result<Vec<(EntryType, Fingerprint)>, String> {
+    let mut found = self
+      .inner
+      .staging
+      .lock()
+      .blobs
+      .keys()
+      .filter(|(_, fingerprint)| fingerprint.to_hex().starts_with(hex_prefix))
+      .cloned()
+      .collect::<Vec<_>>();
+
+    let d_dbs = self.inner.directory_dbs.clone()?;
+    let f_dbs = self.inner.file_dbs.clone()?;
+    let (directories, files) = future::try_join(
+      d_dbs.find_by_prefix(hex_prefix.to_owned()),
+      f_dbs.find_by_prefix(hex_prefix.to_owned()),
+    )
+    .await?;
+    found.extend(
+      directories
+        .into_iter()
+        .map(|fingerprint| (EntryType::Directory, fingerprint)),
+    );
+    found.extend(
+      files
+        .into_iter()
+        .map(|fingerprint| (EntryType::File, fingerprint)),
+    );
+    found.sort();
+    found.dedup();
+    Ok(found)
+  }
+
   pub async fn lease_all(
     &self,
     digests: impl Iterator<Item = (Digest, EntryType)>,
   ) -> Result<(), String> {
     // NB: Lease extension happens periodically in the background, so this code needn't be parallel.
     for (digest, entry_type) in digests {
+      if digest == EMPTY_DIGEST {
+        // The empty digest is never stored, so there is nothing to lease.
+        continue;
+      }
+      if let Some(staged) = self
+        .inner
+        .staging
+        .lock()
+        .blobs
+        .get_mut(&(entry_type, digest.hash))
+      {
+        // The blob will be leased when it is committed.
+        staged.initial_lease = true;
+        continue;
+      }
       let dbs = match entry_type {
         EntryType::File => self.inner.file_dbs.clone(),
         EntryType::Directory => self.inner.directory_dbs.clone(),
       };
       dbs?
-        .lease(digest.hash)
+        .lease(digest.hash, self.inner.maintenance_io_priority)
         .await
         .map_err(|err| format!("Error leasing digest {:?}: {}", digest, err))?;
     }
@@ -123,33 +328,61 @@ impl ByteStore {
     &self,
     target_bytes: usize,
     shrink_behavior: ShrinkBehavior,
+  ) -> Result<usize, String> {
+    self.shrink_with_references(target_bytes, shrink_behavior, &HashMap::new())
+  }
+
+  ///
+  /// As `shrink`, but given the blobs which are referenced by roots (see `GcRoots`): expired blobs
+  /// which are not referenced are removed before any which are, blobs which are referenced by live
+  /// roots are never removed, and other referenced blobs are treated as leased until their roots
+  /// expire.
+  ///
+  pub fn shrink_with_references(
+    &self,
+    target_bytes: usize,
+    shrink_behavior: ShrinkBehavior,
+    references: &HashMap<Fingerprint, RootLifetime>,
   ) -> Result<usize, String> {
     let mut used_bytes: usize = 0;
     let mut fingerprints_by_expired_ago = BinaryHeap::new();
 
     self.aged_fingerprints(
       EntryType::File,
+      references,
       &mut used_bytes,
       &mut fingerprints_by_expired_ago,
     )?;
     self.aged_fingerprints(
       EntryType::Directory,
+      references,
       &mut used_bytes,
       &mut fingerprints_by_expired_ago,
     )?;
     while used_bytes > target_bytes {
-      let aged_fingerprint = fingerprints_by_expired_ago
-        .pop()
-        .expect("lmdb corruption detected, sum of size of blobs exceeded stored blobs");
-      if aged_fingerprint.expired_seconds_ago == 0 {
+      let aged_fingerprint = if let Some(aged_fingerprint) = fingerprints_by_expired_ago.pop() {
+        aged_fingerprint
+      } else {
         // Ran out of expired blobs - everything remaining is leased and cannot be collected.
         return Ok(used_bytes);
+      };
+      if aged_fingerprint.expired_seconds_ago == 0 {
+        // Leased (or referenced by a live root), but referenced blobs which have expired may
+        // remain.
+        continue;
       }
       let lmdbs = match aged_fingerprint.entry_type {
         EntryType::File => self.inner.file_dbs.clone(),
         EntryType::Directory => self.inner.directory_dbs.clone(),
       };
       let (env, database, lease_database) = lmdbs.clone()?.get(&aged_fingerprint.fingerprint);
+      if aged_fingerprint.entry_type == EntryType::File {
+        self
+          .inner
+          .small_files
+          .lock()
+          .remove(&aged_fingerprint.fingerprint);
+      }
       {
         env
           .begin_rw_txn()
@@ -181,6 +414,7 @@ impl ByteStore {
   fn aged_fingerprints(
     &self,
     entry_type: EntryType,
+    references: &HashMap<Fingerprint, RootLifetime>,
     used_bytes: &mut usize,
     fingerprints_by_expired_ago: &mut BinaryHeap<AgedFingerprint>,
   ) -> Result<(), String> {
@@ -190,8 +424,7 @@ impl ByteStore {
     };
 
     for &(ref env, ref database, ref lease_database) in &database?.all_lmdbs() {
-      let txn = env
-        .begin_ro_txn()
+      let txn = begin_ro_txn(env)
         .map_err(|err| format!("Error beginning transaction to garbage collect: {}", err))?;
       let mut cursor = txn
         .open_ro_cursor(*database)
@@ -215,17 +448,26 @@ impl ByteStore {
             e => panic!("Error reading lease, probable lmdb corruption: {:?}", e),
           });
 
+        let v = VersionedFingerprint::from_bytes_unsafe(key);
+        let fingerprint = v.get_fingerprint();
+
+        // A blob which is referenced by a root is leased for at least as long as the root is.
         let leased_until = time::UNIX_EPOCH + Duration::from_secs(lease_until_unix_timestamp);
+        let reference = references.get(&fingerprint);
+        let leased_until = match reference {
+          Some(RootLifetime::Live) => None,
+          Some(RootLifetime::Until(root_until)) => Some(std::cmp::max(leased_until, *root_until)),
+          None => Some(leased_until),
+        };
 
-        let expired_seconds_ago = time::SystemTime::now()
-          .duration_since(leased_until)
+        let expired_seconds_ago = leased_until
+          .and_then(|leased_until| time::SystemTime::now().duration_since(leased_until).ok())
           .map(|t| t.as_secs())
           // 0 indicates unleased.
           .unwrap_or(0);
 
-        let v = VersionedFingerprint::from_bytes_unsafe(key);
-        let fingerprint = v.get_fingerprint();
         fingerprints_by_expired_ago.push(AgedFingerprint {
+          unreferenced: reference.is_none(),
           expired_seconds_ago,
           fingerprint,
           size_bytes: bytes.len(),
@@ -237,6 +479,15 @@ impl ByteStore {
   }
 
   pub async fn remove(&self, entry_type: EntryType, digest: Digest) -> Result<bool, String> {
+    {
+      let mut staging = self.inner.staging.lock();
+      if let Some(staged) = staging.blobs.remove(&(entry_type, digest.hash)) {
+        staging.total_bytes -= staged.bytes.len();
+      }
+    }
+    if entry_type == EntryType::File {
+      self.inner.small_files.lock().remove(&digest.hash);
+    }
     let dbs = match entry_type {
       EntryType::Directory => self.inner.directory_dbs.clone(),
       EntryType::File => self.inner.file_dbs.clone(),
@@ -250,20 +501,210 @@ impl ByteStore {
     bytes: Bytes,
     initial_lease: bool,
   ) -> Result<Digest, String> {
+    if bytes.is_empty() {
+      // Empty files (such as `__init__.py` files) and empty directories are extremely common, but
+      // are known without being stored (see `load_bytes_with`), so skip hashing and storing them.
+      return Ok(EMPTY_DIGEST);
+    }
+
     let dbs = match entry_type {
       EntryType::Directory => self.inner.directory_dbs.clone(),
       EntryType::File => self.inner.file_dbs.clone(),
     };
     let bytes2 = bytes.clone();
-    let digest = self
-      .inner
-      .executor
-      .spawn_blocking(move || Digest::of_bytes(&bytes))
-      .await;
+    let digest = self.spawn_hashing(move || Digest::of_bytes(&bytes)).await;
+
+    if entry_type == EntryType::File {
+      self
+        .inner
+        .small_files
+        .lock()
+        .insert(digest.hash, bytes2.clone());
+    }
+
+    {
+      let mut staging = self.inner.staging.lock();
+      if staging.sessions > 0
+        && bytes2.len() <= MAX_STAGED_BLOB_BYTES
+        && staging.total_bytes + bytes2.len() <= MAX_STAGED_BYTES
+      {
+        let key = (entry_type, digest.hash);
+        if let Some(staged) = staging.blobs.get_mut(&key) {
+          staged.initial_lease |= initial_lease;
+        } else {
+          staging.total_bytes += bytes2.len();
+          staging.blobs.insert(
+            key,
+            StagedBlob {
+              bytes: bytes2,
+              initial_lease,
+            },
+          );
+        }
+        return Ok(digest);
+      }
+    }
+
     dbs?.store_bytes(digest.hash, bytes2, initial_lease).await?;
     Ok(digest)
   }
 
+  ///
+  /// Stores a batch of blobs, hashing them in parallel and then writing them with a single write
+  /// transaction per shard. Returns the Digests of the blobs, in order.
+  ///
+  pub async fn store_bytes_batch(
+    &self,
+    entry_type: EntryType,
+    items: Vec<Bytes>,
+    initial_lease: bool,
+  ) -> Result<Vec<Digest>, String> {
+    // Split the items into chunks of roughly HASH_CHUNK_BYTES, to be hashed in parallel.
+    let mut chunks: Vec<Vec<Bytes>> = vec![];
+    let mut chunk_bytes = 0;
+    for bytes in items {
+      if chunks.is_empty() || chunk_bytes >= HASH_CHUNK_BYTES {
+        chunks.push(vec![]);
+        chunk_bytes = 0;
+      }
+      chunk_bytes += bytes.len();
+      chunks.last_mut().unwrap().push(bytes);
+    }
+    let hashed_chunks = future::join_all(chunks.into_iter().map(|chunk| {
+      self.spawn_hashing(move || {
+        chunk
+          .into_iter()
+          .map(|bytes| {
+            let digest = if bytes.is_empty() {
+              EMPTY_DIGEST
+            } else {
+              Digest::of_bytes(&bytes)
+            };
+            (digest, bytes)
+          })
+          .collect::<Vec<_>>()
+      })
+    }))
+    .await;
+
+    let mut digests = Vec::new();
+    let mut to_store = Vec::new();
+    for (digest, bytes) in hashed_chunks.into_iter().flatten() {
+      digests.push(digest);
+      // Empty blobs are known without being stored: see `store_bytes`.
+      if digest != EMPTY_DIGEST {
+        to_store.push((digest.hash, bytes));
+      }
+    }
+    if entry_type == EntryType::File {
+      let mut small_files = self.inner.small_files.lock();
+      for (fingerprint, bytes) in &to_store {
+        small_files.insert(*fingerprint, bytes.clone());
+      }
+    }
+    if !to_store.is_empty() {
+      let dbs = match entry_type {
+        EntryType::Directory => self.inner.directory_dbs.clone(),
+        EntryType::File => self.inner.file_dbs.clone(),
+      };
+      dbs?.store_bytes_batch(to_store, initial_lease).await?;
+    }
+    Ok(digests)
+  }
+
+  ///
+  /// Flushes all of the databases of this store to disk: see `ShardedLmdb::sync`.
+  ///
+  pub async fn sync(&self) -> Result<(), String> {
+    let dbs = [
+      &self.inner.file_dbs,
+      &self.inner.directory_dbs,
+      &self.inner.encrypted_digest_dbs,
+      &self.inner.remote_hash_dbs,
+    ];
+    // NB: Databases which failed to open have nothing to flush.
+    for dbs in dbs.iter().copied().flatten() {
+      dbs.sync().await?;
+    }
+    Ok(())
+  }
+
+  ///
+  /// Begins staging small blobs in memory for an active session. Must be paired with a call to
+  /// `end_staging`.
+  ///
+  pub fn begin_staging(&self) {
+    self.inner.staging.lock().sessions += 1;
+  }
+
+  ///
+  /// Ends staging for a session, and commits all staged blobs if it was the last active session.
+  ///
+  pub async fn end_staging(&self) -> Result<(), String> {
+    let keys = {
+      let mut staging = self.inner.staging.lock();
+      staging.sessions = staging.sessions.saturating_sub(1);
+      if staging.sessions > 0 {
+        return Ok(());
+      }
+      staging.blobs.keys().cloned().collect::<Vec<_>>()
+    };
+    self.commit(keys).await
+  }
+
+  ///
+  /// Commits any of the given blobs which are staged, so that they will outlive the session which
+  /// created them.
+  ///
+  pub async fn commit_staged(
+    &self,
+    digests: impl Iterator<Item = (Digest, EntryType)>,
+  ) -> Result<(), String> {
+    let keys = {
+      let staging = self.inner.staging.lock();
+      digests
+        .map(|(digest, entry_type)| (entry_type, digest.hash))
+        .filter(|key| staging.blobs.contains_key(key))
+        .collect::<Vec<_>>()
+    };
+    self.commit(keys).await
+  }
+
+  async fn commit(&self, keys: Vec<(EntryType, Fingerprint)>) -> Result<(), String> {
+    if keys.is_empty() {
+      return Ok(());
+    }
+
+    let mut batches: HashMap<(EntryType, bool), Vec<(Fingerprint, Bytes)>> = HashMap::new();
+    {
+      let staging = self.inner.staging.lock();
+      for key in &keys {
+        if let Some(staged) = staging.blobs.get(key) {
+          batches
+            .entry((key.0, staged.initial_lease))
+            .or_default()
+            .push((key.1, staged.bytes.clone()));
+        }
+      }
+    }
+    for ((entry_type, initial_lease), items) in batches {
+      let dbs = match entry_type {
+        EntryType::Directory => self.inner.directory_dbs.clone(),
+        EntryType::File => self.inner.file_dbs.clone(),
+      };
+      dbs?.store_bytes_batch(items, initial_lease).await?;
+    }
+
+    // Only remove the blobs once they have been written, so that they remain loadable throughout.
+    let mut staging = self.inner.staging.lock();
+    for key in &keys {
+      if let Some(staged) = staging.blobs.remove(key) {
+        staging.total_bytes -= staged.bytes.len();
+      }
+    }
+    Ok(())
+  }
+
   ///
   /// Loads bytes from the underlying LMDB store using the given function. Because the database is
   /// blocking, this accepts a function that views a slice rather than returning a clone of the
@@ -286,6 +727,30 @@ impl ByteStore {
       return Ok(Some(self.executor().spawn_blocking(move || f(&[])).await));
     }
 
+    let in_memory_bytes = self
+      .inner
+      .staging
+      .lock()
+      .blobs
+      .get(&(entry_type, digest.hash))
+      .map(|staged| staged.bytes.clone())
+      .or_else(|| match entry_type {
+        EntryType::File if digest.size_bytes <= MAX_SMALL_FILE_BYTES => self
+          .inner
+          .small_files
+          .lock()
+          .files
+          .get(&digest.hash)
+          .cloned(),
+        _ => None,
+      })
+      .filter(|bytes| bytes.len() == digest.size_bytes);
+    if let Some(bytes) = in_memory_bytes {
+      return Ok(Some(
+        self.executor().spawn_blocking(move || f(&bytes)).await,
+      ));
+    }
+
     if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
       workunit_store_handle.store.record_observation(
         ObservationMetric::LocalStoreReadBlobSize,
@@ -298,13 +763,21 @@ impl ByteStore {
       EntryType::File => self.inner.file_dbs.clone(),
     };
 
-    dbs?.load_bytes_with(digest.hash, move |bytes| {
+    let small_file = entry_type == EntryType::File && digest.size_bytes <= MAX_SMALL_FILE_BYTES;
+    let loaded = dbs?.load_bytes_with(digest.hash, move |bytes| {
         if bytes.len() == digest.size_bytes {
-            Ok(f(bytes))
+            let small_file_bytes = Some(bytes).filter(|_| small_file).map(Bytes::copy_from_slice);
+            Ok((f(bytes), small_file_bytes))
         } else {
             Err(format!("Got hash collision reading from store - digest {:?} was requested, but retrieved bytes with that fingerprint had length {}. Congratulations, you may have broken sha256! Underlying bytes: {:?}", digest, bytes.len(), bytes))
         }
-    }).await
+    }).await?;
+    Ok(loaded.map(|(value, small_file_bytes)| {
+      if let Some(bytes) = small_file_bytes {
+        self.inner.small_files.lock().insert(digest.hash, bytes);
+      }
+      value
+    }))
   }
 
   pub fn all_digests(&self, entry_type: EntryType) -> Result<Vec<Digest>, String> {
@@ -314,8 +787,7 @@ impl ByteStore {
     };
     let mut digests = vec![];
     for &(ref env, ref database, ref _lease_database) in &database?.all_lmdbs() {
-      let txn = env
-        .begin_ro_txn()
+      let txn = begin_ro_txn(env)
         .map_err(|err| format!("Error beginning transaction to garbage collect: {}", err))?;
       let mut cursor = txn
         .open_ro_cursor(*database)
@@ -332,7 +804,9 @@ impl ByteStore {
 
 #[derive(Eq, PartialEq, Ord, PartialOrd)]
 struct AgedFingerprint {
-  // expired_seconds_ago must be the first field for the Ord implementation.
+  // unreferenced and expired_seconds_ago must be the first fields for the Ord implementation:
+  // unreferenced blobs are removed before referenced blobs, and then the longest expired first.
+  unreferenced: bool,
   expired_seconds_ago: u64,
   fingerprint: Fingerprint,
   size_bytes: usize,
@@ -1,11 +1,13 @@
 use crate::local::ByteStore;
-use crate::{EntryType, LocalOptions, ShrinkBehavior};
+use crate::{EntryType, LocalOptions, RootLifetime, ShrinkBehavior};
 
+use std::collections::HashSet;
 use std::path::Path;
 use std::time::Duration;
 
 use bytes::{BufMut, Bytes, BytesMut};
 use hashing::{Digest, Fingerprint};
+use task_executor::IoPriority;
 use tempfile::TempDir;
 use testutil::data::{TestData, TestDirectory};
 use tokio::time::sleep;
@@ -250,6 +252,71 @@ async fn garbage_collect_remove_one_of_two_files_no_leases() {
   );
 }
 
+#[tokio::test]
+async fn garbage_collect_never_removes_live_references() {
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path());
+  let roland = TestData::roland();
+  let catnip = TestData::catnip();
+  for testdata in &[&roland, &catnip] {
+    store
+      .store_bytes(EntryType::File, testdata.bytes(), false)
+      .await
+      .expect("Error storing");
+  }
+
+  let references = vec![(roland.fingerprint(), RootLifetime::Live)]
+    .into_iter()
+    .collect();
+  assert_eq!(
+    roland.len(),
+    store
+      .shrink_with_references(0, ShrinkBehavior::Fast, &references)
+      .expect("Error shrinking")
+  );
+  assert_eq!(
+    load_file_bytes(&store, roland.digest()).await,
+    Ok(Some(roland.bytes()))
+  );
+  assert_eq!(load_file_bytes(&store, catnip.digest()).await, Ok(None));
+}
+
+#[tokio::test]
+async fn garbage_collect_removes_unreferenced_files_first() {
+  let lease_time = Duration::from_secs(1);
+  let dir = TempDir::new().unwrap();
+  let store = new_store_with_lease_time(dir.path(), lease_time);
+  let roland = TestData::roland();
+  let catnip = TestData::catnip();
+
+  // roland is referenced by a root which expired long ago, and catnip was leased until recently,
+  // but is unreferenced.
+  store
+    .store_bytes(EntryType::File, roland.bytes(), false)
+    .await
+    .expect("Error storing");
+  store
+    .store_bytes(EntryType::File, catnip.bytes(), true)
+    .await
+    .expect("Error storing");
+  sleep(lease_time * 2).await;
+
+  let references = vec![(
+    roland.fingerprint(),
+    RootLifetime::Until(std::time::UNIX_EPOCH + Duration::from_secs(1)),
+  )]
+  .into_iter()
+  .collect();
+  store
+    .shrink_with_references(roland.len(), ShrinkBehavior::Fast, &references)
+    .expect("Error shrinking");
+  assert_eq!(
+    load_file_bytes(&store, roland.digest()).await,
+    Ok(Some(roland.bytes()))
+  );
+  assert_eq!(load_file_bytes(&store, catnip.digest()).await, Ok(None));
+}
+
 #[tokio::test]
 async fn garbage_collect_remove_both_files_no_leases() {
   let dir = TempDir::new().unwrap();
@@ -555,6 +622,149 @@ async fn empty_directory_is_known() {
   )
 }
 
+#[tokio::test]
+async fn empty_file_is_not_stored() {
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path());
+  let empty_file = TestData::empty();
+  assert_eq!(
+    store
+      .store_bytes(EntryType::File, empty_file.bytes(), true)
+      .await,
+    Ok(empty_file.digest())
+  );
+  assert_eq!(Ok(vec![]), store.all_digests(EntryType::File));
+  assert_eq!(
+    load_file_bytes(&store, empty_file.digest()).await,
+    Ok(Some(empty_file.bytes()))
+  );
+}
+
+#[tokio::test]
+async fn small_files_are_forgotten_when_removed() {
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path());
+  let small_file = TestData::roland();
+  store
+    .store_bytes(EntryType::File, small_file.bytes(), false)
+    .await
+    .expect("Error storing");
+  assert_eq!(
+    load_file_bytes(&store, small_file.digest()).await,
+    Ok(Some(small_file.bytes()))
+  );
+
+  assert_eq!(
+    store.remove(EntryType::File, small_file.digest()).await,
+    Ok(true)
+  );
+  assert_eq!(load_file_bytes(&store, small_file.digest()).await, Ok(None));
+}
+
+#[tokio::test]
+async fn staged_blobs_are_committed_when_staging_ends() {
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path());
+  let testdata = TestData::roland();
+
+  store.begin_staging();
+  store
+    .store_bytes(EntryType::File, testdata.bytes(), false)
+    .await
+    .unwrap();
+  // Staged blobs are loadable, but are not yet in LMDB.
+  assert_eq!(
+    load_file_bytes(&store, testdata.digest()).await,
+    Ok(Some(testdata.bytes()))
+  );
+  assert_eq!(
+    store.entry_type(testdata.fingerprint()).await,
+    Ok(Some(EntryType::File))
+  );
+  assert_eq!(Ok(vec![]), store.all_digests(EntryType::File));
+
+  store.end_staging().await.unwrap();
+  assert_eq!(
+    Ok(vec![testdata.digest()]),
+    store.all_digests(EntryType::File)
+  );
+  assert_eq!(
+    load_file_bytes(&store, testdata.digest()).await,
+    Ok(Some(testdata.bytes()))
+  );
+}
+
+#[tokio::test]
+async fn staged_blobs_are_committed_on_demand() {
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path());
+  let roland = TestData::roland();
+  let catnip = TestData::catnip();
+
+  // Staging continues until the last active session ends.
+  store.begin_staging();
+  store.begin_staging();
+  store
+    .store_bytes(EntryType::File, roland.bytes(), false)
+    .await
+    .unwrap();
+  store
+    .store_bytes(EntryType::File, catnip.bytes(), false)
+    .await
+    .unwrap();
+
+  store
+    .commit_staged(vec![(roland.digest(), EntryType::File)].into_iter())
+    .await
+    .unwrap();
+  assert_eq!(
+    Ok(vec![roland.digest()]),
+    store.all_digests(EntryType::File)
+  );
+
+  store.end_staging().await.unwrap();
+  assert_eq!(
+    Ok(vec![roland.digest()]),
+    store.all_digests(EntryType::File)
+  );
+  store.end_staging().await.unwrap();
+  assert_eq!(
+    store
+      .all_digests(EntryType::File)
+      .unwrap()
+      .into_iter()
+      .collect::<HashSet<_>>(),
+    vec![roland.digest(), catnip.digest()].into_iter().collect()
+  );
+}
+
+#[tokio::test]
+async fn find_by_prefix() {
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path());
+  let roland = prime_store_with_file_bytes(&store, TestData::roland().bytes()).await;
+  let testdir = TestDirectory::containing_roland();
+  store
+    .store_bytes(EntryType::Directory, testdir.bytes(), false)
+    .await
+    .expect("Error storing");
+
+  let roland_hex = roland.hash.to_hex();
+  assert_eq!(
+    Ok(vec![(EntryType::File, roland.hash)]),
+    store.find_by_prefix(&roland_hex[..12]).await
+  );
+  let testdir_hex = testdir.fingerprint().to_hex();
+  assert_eq!(
+    Ok(vec![(EntryType::Directory, testdir.fingerprint())]),
+    store.find_by_prefix(&testdir_hex[..7]).await
+  );
+  assert_eq!(
+    Ok(2),
+    store.find_by_prefix("").await.map(|found| found.len())
+  );
+}
+
 #[tokio::test]
 async fn all_digests() {
   let dir = TempDir::new().unwrap();
@@ -563,6 +773,46 @@ async fn all_digests() {
   assert_eq!(Ok(vec![digest]), store.all_digests(EntryType::File));
 }
 
+#[tokio::test]
+async fn bounded_hashing_and_idle_maintenance() {
+  let dir = TempDir::new().unwrap();
+  let store = ByteStore::new_with_options(
+    task_executor::Executor::new(),
+    dir.path(),
+    LocalOptions {
+      hashing_parallelism: Some(1),
+      maintenance_io_priority: IoPriority::Idle,
+      ..LocalOptions::default()
+    },
+  )
+  .unwrap();
+
+  // Large enough to be hashed in multiple chunks, which must wait for one another.
+  let items = (0..3_u8)
+    .map(|byte| Bytes::from(vec![byte; 3 * 1024 * 1024]))
+    .collect::<Vec<_>>();
+  let digests = store
+    .store_bytes_batch(EntryType::File, items.clone(), false)
+    .await
+    .unwrap();
+  assert_eq!(
+    digests,
+    items
+      .iter()
+      .map(|bytes| Digest::of_bytes(bytes))
+      .collect::<Vec<_>>()
+  );
+
+  store
+    .lease_all(digests.iter().map(|digest| (*digest, EntryType::File)))
+    .await
+    .expect("Error leasing");
+  assert_eq!(
+    load_bytes(&store, EntryType::File, digests[0]).await,
+    Ok(Some(items[0].clone()))
+  );
+}
+
 pub fn new_store<P: AsRef<Path>>(dir: P) -> ByteStore {
   ByteStore::new(task_executor::Executor::new(), dir).unwrap()
 }
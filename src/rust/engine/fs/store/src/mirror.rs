@@ -0,0 +1,96 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use hashing::Digest;
+use log::{debug, warn};
+
+/// The timeout for each request to a mirror.
+const MIRROR_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+///
+/// A read-only source of blobs, which a Store consults for digests which are missing from both its
+/// local and remote halves.
+///
+/// Resolvers are best-effort: a resolver which fails to fetch a digest should return `Ok(None)`
+/// (after logging, if appropriate), so that the caller can fall back to other sources, or to
+/// re-execution.
+///
+#[async_trait]
+pub trait DigestResolver: Debug + Send + Sync {
+  async fn resolve(&self, digest: Digest) -> Result<Option<Bytes>, String>;
+}
+
+///
+/// Resolves digests from HTTP mirrors which are laid out content-addressably: the blob with a
+/// given digest is served at `{base_url}/{hex fingerprint}`. This allows a read-only cache to be
+/// served cheaply by a static file server or CDN.
+///
+/// Mirrors are tried in order, and the content that they serve is only used if it matches the
+/// requested digest.
+///
+#[derive(Debug)]
+pub struct HttpMirrors {
+  base_urls: Vec<String>,
+  client: reqwest::Client,
+}
+
+impl HttpMirrors {
+  pub fn new(base_urls: Vec<String>) -> Result<HttpMirrors, String> {
+    let client = reqwest::Client::builder()
+      .timeout(MIRROR_REQUEST_TIMEOUT)
+      .build()
+      .map_err(|e| format!("Failed to create HTTP client for mirrors: {}", e))?;
+    let base_urls = base_urls
+      .into_iter()
+      .map(|url| url.trim_end_matches('/').to_owned())
+      .collect();
+    Ok(HttpMirrors { base_urls, client })
+  }
+
+  async fn fetch(&self, url: &str) -> Result<Option<Bytes>, String> {
+    let response = self
+      .client
+      .get(url)
+      .send()
+      .await
+      .map_err(|e| format!("{}", e))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+      return Ok(None);
+    }
+    let response = response.error_for_status().map_err(|e| format!("{}", e))?;
+    response
+      .bytes()
+      .await
+      .map(Some)
+      .map_err(|e| format!("{}", e))
+  }
+}
+
+#[async_trait]
+impl DigestResolver for HttpMirrors {
+  async fn resolve(&self, digest: Digest) -> Result<Option<Bytes>, String> {
+    for base_url in &self.base_urls {
+      let url = format!("{}/{}", base_url, digest.hash.to_hex());
+      match self.fetch(&url).await {
+        Ok(Some(bytes)) if Digest::of_bytes(&bytes) == digest => {
+          debug!("Fetched {:?} from mirror {}", digest, url);
+          return Ok(Some(bytes));
+        }
+        Ok(Some(bytes)) => warn!(
+          "Mirror {} served content with the wrong digest: expected {:?}, got {:?}",
+          url,
+          digest,
+          Digest::of_bytes(&bytes)
+        ),
+        Ok(None) => {}
+        Err(e) => warn!("Failed to fetch {:?} from mirror {}: {}", digest, url, e),
+      }
+    }
+    Ok(None)
+  }
+}
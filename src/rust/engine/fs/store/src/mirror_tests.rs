@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener};
+use std::sync::Arc;
+
+use tempfile::TempDir;
+use testutil::data::TestData;
+
+use crate::mirror::{DigestResolver, HttpMirrors};
+use crate::tests::load_file_bytes;
+use crate::Store;
+
+///
+/// Serves the given bodies at the given paths (and a 404 for any other path) over HTTP on
+/// localhost, returning the base URL of the server.
+///
+fn serve(bodies: HashMap<String, Vec<u8>>) -> String {
+  let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+  let address = listener.local_addr().unwrap();
+  std::thread::spawn(move || {
+    for stream in listener.incoming() {
+      let mut stream = match stream {
+        Ok(stream) => stream,
+        Err(_) => return,
+      };
+      let mut reader = BufReader::new(stream.try_clone().unwrap());
+      let mut request_line = String::new();
+      reader.read_line(&mut request_line).unwrap();
+      loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header.trim().is_empty() {
+          break;
+        }
+      }
+      let path = request_line.split_whitespace().nth(1).unwrap_or("");
+      let (status, body) = match bodies.get(path) {
+        Some(body) => ("200 OK", body.clone()),
+        None => ("404 Not Found", Vec::new()),
+      };
+      write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+      )
+      .unwrap();
+      stream.write_all(&body).unwrap();
+    }
+  });
+  format!("http://{}", address)
+}
+
+#[tokio::test]
+async fn resolves_verified_content_in_mirror_order() {
+  let roland = TestData::roland();
+  let hash = roland.fingerprint().to_hex();
+  let server = serve(
+    vec![
+      (format!("/corrupt/{}", hash), b"not roland".to_vec()),
+      (format!("/valid/{}", hash), roland.bytes().to_vec()),
+    ]
+    .into_iter()
+    .collect(),
+  );
+
+  let mirrors = HttpMirrors::new(vec![
+    format!("{}/missing", server),
+    format!("{}/corrupt", server),
+    // A trailing slash is allowed.
+    format!("{}/valid/", server),
+  ])
+  .unwrap();
+  assert_eq!(
+    mirrors.resolve(roland.digest()).await,
+    Ok(Some(roland.bytes()))
+  );
+  assert_eq!(mirrors.resolve(TestData::catnip().digest()).await, Ok(None));
+}
+
+#[tokio::test]
+async fn unreachable_mirrors_are_skipped() {
+  // Bind and then drop a listener to find a port which (very likely) has no server.
+  let address = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+    .unwrap()
+    .local_addr()
+    .unwrap();
+  let mirrors = HttpMirrors::new(vec![format!("http://{}/cas", address)]).unwrap();
+  assert_eq!(mirrors.resolve(TestData::roland().digest()).await, Ok(None));
+}
+
+#[tokio::test]
+async fn store_backfills_from_mirrors() {
+  let roland = TestData::roland();
+  let server = serve(
+    vec![(
+      format!("/cas/{}", roland.fingerprint().to_hex()),
+      roland.bytes().to_vec(),
+    )]
+    .into_iter()
+    .collect(),
+  );
+
+  let dir = TempDir::new().unwrap();
+  let store = Store::local_only(task_executor::Executor::new(), dir.path())
+    .unwrap()
+    .with_digest_resolver(Arc::new(
+      HttpMirrors::new(vec![format!("{}/cas", server)]).unwrap(),
+    ));
+  assert_eq!(
+    load_file_bytes(&store, roland.digest()).await,
+    Ok(Some(roland.bytes()))
+  );
+  assert_eq!(
+    load_file_bytes(&store, TestData::catnip().digest()).await,
+    Ok(None)
+  );
+
+  // The resolved content was stored locally.
+  assert_eq!(
+    load_file_bytes(&store.into_local_only(), roland.digest()).await,
+    Ok(Some(roland.bytes()))
+  );
+}
@@ -1,35 +1,59 @@
 use std::cmp::min;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
 use std::fmt;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use crate::chunk_size::AdaptiveChunkSize;
+use crate::digest_function::DigestTranslation;
+use crate::encryption::BlobEncryption;
 use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
 use bazel_protos::gen::google::bytestream::byte_stream_client::ByteStreamClient;
 use bazel_protos::{self};
 use bytes::{Bytes, BytesMut};
-use futures::future::TryFutureExt;
+use futures::future::{self, FutureExt};
 use futures::Future;
 use futures::StreamExt;
 use grpc_util::headers_to_interceptor_fn;
-use hashing::Digest;
+use grpc_util::health::RemoteEndpoint;
+use grpc_util::priority::{current_request_priority, PriorityPermit, PrioritySemaphore};
+use grpc_util::rate_limit::RateLimiter;
+use grpc_util::retry::{retry_call, RetryThrottle};
+use hashing::{Digest, EMPTY_DIGEST};
 use log::Level;
+use remexec::action_cache_client::ActionCacheClient;
 use remexec::content_addressable_storage_client::ContentAddressableStorageClient;
+use tokio::sync::{Semaphore, SemaphorePermit};
 use tonic::transport::Channel;
 use tonic::{Code, Interceptor, Request};
-use workunit_store::{with_workunit, ObservationMetric, WorkunitMetadata};
+use workunit_store::{with_workunit, Metric, ObservationMetric, WorkunitMetadata};
+
+///
+/// The output path of the single file of the ActionResults which record the digests of encrypted
+/// blobs in the remote action cache: see `ByteStore::encrypted_digest`.
+///
+const ENCRYPTED_BLOB_PATH: &str = "encrypted_blob";
 
 #[derive(Clone)]
 pub struct ByteStore {
   instance_name: Option<String>,
-  chunk_size_bytes: usize,
+  chunk_size: Arc<AdaptiveChunkSize>,
   upload_timeout: Duration,
   rpc_attempts: usize,
   channel: Channel,
   interceptor: Option<Interceptor>,
   byte_stream_client: Arc<ByteStreamClient<Channel>>,
   cas_client: Arc<ContentAddressableStorageClient<Channel>>,
+  action_cache_client: Arc<ActionCacheClient<Channel>>,
+  rpc_limiter: Option<Arc<RateLimiter>>,
+  bandwidth_limiter: Option<Arc<RateLimiter>>,
+  read_semaphore: Option<Arc<PrioritySemaphore>>,
+  inflight_bytes: Option<Arc<InflightBytes>>,
+  throttle: Arc<RetryThrottle>,
+  endpoint: RemoteEndpoint,
+  encryption: Option<BlobEncryption>,
+  digest_translation: Option<DigestTranslation>,
 }
 
 impl fmt::Debug for ByteStore {
@@ -47,8 +71,12 @@ impl ByteStore {
     root_ca_certs: Option<Vec<u8>>,
     headers: BTreeMap<String, String>,
     chunk_size_bytes: usize,
+    chunk_size_bounds: Option<(usize, usize)>,
     upload_timeout: Duration,
     rpc_retries: usize,
+    rpc_qps: Option<usize>,
+    bandwidth_bytes_per_sec: Option<usize>,
+    read_concurrency: Option<usize>,
   ) -> Result<ByteStore, String> {
     let tls_client_config = if cas_address.starts_with("https://") {
       Some(grpc_util::create_tls_config(root_ca_certs)?)
@@ -76,26 +104,278 @@ impl ByteStore {
       None => ContentAddressableStorageClient::new(channel.clone()),
     });
 
+    let action_cache_client = Arc::new(match interceptor.as_ref() {
+      Some(interceptor) => {
+        ActionCacheClient::with_interceptor(channel.clone(), interceptor.clone())
+      }
+      None => ActionCacheClient::new(channel.clone()),
+    });
+
+    // Warm up using an empty FindMissingBlobs request, which all servers support cheaply.
+    let endpoint = {
+      let cas_client = cas_client.clone();
+      let instance_name = instance_name.clone().unwrap_or_default();
+      RemoteEndpoint::new(
+        "store",
+        cas_address,
+        Arc::new(move || {
+          let mut client = cas_client.as_ref().clone();
+          let request = remexec::FindMissingBlobsRequest {
+            instance_name: instance_name.clone(),
+            blob_digests: vec![],
+          };
+          async move {
+            client
+              .find_missing_blobs(request)
+              .await
+              .map(|_| ())
+              .map_err(|status| status.to_string())
+          }
+          .boxed()
+        }),
+      )
+    };
+
     Ok(ByteStore {
       instance_name,
-      chunk_size_bytes,
+      chunk_size: Arc::new(AdaptiveChunkSize::new(chunk_size_bytes, chunk_size_bounds)),
       upload_timeout,
       channel,
       rpc_attempts: rpc_retries + 1,
       interceptor,
       byte_stream_client,
       cas_client,
+      action_cache_client,
+      rpc_limiter: rpc_qps.map(|qps| Arc::new(RateLimiter::new(qps))),
+      bandwidth_limiter: bandwidth_bytes_per_sec.map(|rate| Arc::new(RateLimiter::new(rate))),
+      read_semaphore: read_concurrency.map(PrioritySemaphore::new),
+      inflight_bytes: None,
+      throttle: RetryThrottle::new(),
+      endpoint,
+      encryption: None,
+      digest_translation: None,
     })
   }
 
+  ///
+  /// Encrypt blobs before they are uploaded, and decrypt them after they are downloaded. The
+  /// digests accepted and returned by this ByteStore remain those of the plaintext.
+  ///
+  pub fn with_encryption(self, encryption: BlobEncryption) -> ByteStore {
+    ByteStore {
+      encryption: Some(encryption),
+      ..self
+    }
+  }
+
+  ///
+  /// Address blobs in the remote CAS by a different digest function than the local store. The
+  /// digests accepted and returned by this ByteStore remain SHA-256 digests.
+  ///
+  pub fn with_digest_translation(self, digest_translation: DigestTranslation) -> ByteStore {
+    ByteStore {
+      digest_translation: Some(digest_translation),
+      ..self
+    }
+  }
+
+  ///
+  /// Limit the total size of the blobs which are being uploaded or downloaded at once to
+  /// `max_bytes` (in addition to any limit on the number of concurrent reads): see `InflightBytes`.
+  ///
+  pub fn with_max_inflight_bytes(self, max_bytes: usize) -> ByteStore {
+    ByteStore {
+      inflight_bytes: Some(Arc::new(InflightBytes::new(max_bytes))),
+      ..self
+    }
+  }
+
+  pub fn endpoint(&self) -> &RemoteEndpoint {
+    &self.endpoint
+  }
+
+  ///
+  /// If the number of concurrent reads is limited, waits for a permit to read at the priority of
+  /// the current task (see `grpc_util::priority::with_request_priority`), so that reads which the
+  /// user is waiting on are not queued behind speculative or background reads.
+  ///
+  async fn acquire_read_permit(&self) -> Option<PriorityPermit> {
+    let read_semaphore = self.read_semaphore.as_ref()?;
+    let start_time = Instant::now();
+    let permit = read_semaphore.acquire(current_request_priority()).await;
+    if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+      workunit_store_handle
+        .store
+        .record_observation_since(ObservationMetric::RemoteStoreReadQueueTime, start_time);
+    }
+    Some(permit)
+  }
+
+  ///
+  /// If the number of bytes in flight is limited, waits until a transfer of the given size fits
+  /// within the budget, and records any time spent waiting.
+  ///
+  async fn acquire_inflight_bytes(&self, bytes: usize) -> Option<SemaphorePermit<'_>> {
+    let inflight_bytes = self.inflight_bytes.as_ref()?;
+    let start_time = Instant::now();
+    let permit = inflight_bytes.acquire(bytes).await;
+    if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+      workunit_store_handle.store.record_observation_since(
+        ObservationMetric::RemoteStoreInflightBytesQueueTime,
+        start_time,
+      );
+    }
+    Some(permit)
+  }
+
+  ///
+  /// Waits until the configured RPC and bandwidth budgets allow for a request which will transfer
+  /// the given number of bytes, and records any time spent waiting.
+  ///
+  async fn wait_for_budget(&self, bytes: usize) {
+    let mut waited = Duration::default();
+    if let Some(rpc_limiter) = &self.rpc_limiter {
+      waited += rpc_limiter.acquire(1).await;
+    }
+    if let Some(bandwidth_limiter) = &self.bandwidth_limiter {
+      waited += bandwidth_limiter.acquire(bytes).await;
+    }
+    if waited > Duration::default() {
+      if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+        workunit_store_handle
+          .store
+          .increment_counter(Metric::RemoteStoreRateLimitedMs, waited.as_millis() as u64);
+      }
+    }
+  }
+
   pub async fn store_bytes(&self, bytes: &[u8]) -> Result<Digest, String> {
+    if let Some(encryption) = &self.encryption {
+      let digest = Digest::of_bytes(bytes);
+      let encrypted_digest = self
+        .store_raw_bytes(&encryption.encrypt(digest, bytes)?)
+        .await?;
+      self
+        .record_encrypted_digest(encryption, digest, encrypted_digest)
+        .await?;
+      Ok(digest)
+    } else {
+      self.store_raw_bytes(bytes).await
+    }
+  }
+
+  ///
+  /// The digest of the ciphertext of the given plaintext digest, if it was uploaded with the
+  /// configured key: either by this machine (in which case it is recorded locally), or by another
+  /// (in which case it is recorded in the remote action cache, and is then recorded locally).
+  ///
+  async fn encrypted_digest(
+    &self,
+    encryption: &BlobEncryption,
+    digest: Digest,
+  ) -> Result<Option<Digest>, String> {
+    if let Some(encrypted_digest) = encryption.encrypted_digest(digest).await? {
+      return Ok(Some(encrypted_digest));
+    }
+
+    let request = remexec::GetActionResultRequest {
+      instance_name: self.instance_name.clone().unwrap_or_default(),
+      action_digest: Some(encryption.remote_mapping_key(digest).into()),
+      ..remexec::GetActionResultRequest::default()
+    };
+    let response = {
+      let _read_permit = self.acquire_read_permit().await;
+      self.wait_for_budget(0).await;
+      retry_call(&self.throttle, self.rpc_attempts, || {
+        let mut client = self.action_cache_client.as_ref().clone();
+        let request = request.clone();
+        async move { client.get_action_result(request).await }
+      })
+      .await
+    };
+    let action_result = match response {
+      Ok(response) => response.into_inner(),
+      // The blob was never uploaded with this key.
+      Err(status) if status.code() == Code::NotFound => return Ok(None),
+      Err(status) => {
+        return Err(format!(
+          "Error looking up the encrypted digest of {:?}: {:?}",
+          digest, status
+        ))
+      }
+    };
+    let encrypted_digest: Digest = action_result
+      .output_files
+      .iter()
+      .find(|output_file| output_file.path == ENCRYPTED_BLOB_PATH)
+      .and_then(|output_file| output_file.digest.as_ref())
+      .ok_or_else(|| {
+        format!(
+          "Invalid entry in the remote encrypted digest table for {:?}",
+          digest
+        )
+      })?
+      .try_into()?;
+    encryption
+      .record_encrypted_digest(digest, encrypted_digest)
+      .await?;
+    Ok(Some(encrypted_digest))
+  }
+
+  ///
+  /// Records that the given plaintext digest was uploaded with the given ciphertext digest: first
+  /// in the remote action cache, and then locally (so that a failure to record it remotely is
+  /// retried by the next upload of the blob).
+  ///
+  async fn record_encrypted_digest(
+    &self,
+    encryption: &BlobEncryption,
+    digest: Digest,
+    encrypted_digest: Digest,
+  ) -> Result<(), String> {
+    let request = remexec::UpdateActionResultRequest {
+      instance_name: self.instance_name.clone().unwrap_or_default(),
+      action_digest: Some(encryption.remote_mapping_key(digest).into()),
+      action_result: Some(remexec::ActionResult {
+        output_files: vec![remexec::OutputFile {
+          path: ENCRYPTED_BLOB_PATH.to_owned(),
+          digest: Some(encrypted_digest.into()),
+          ..remexec::OutputFile::default()
+        }],
+        ..remexec::ActionResult::default()
+      }),
+      ..remexec::UpdateActionResultRequest::default()
+    };
+    self.wait_for_budget(0).await;
+    retry_call(&self.throttle, self.rpc_attempts, || {
+      let mut client = self.action_cache_client.as_ref().clone();
+      let request = request.clone();
+      async move { client.update_action_result(request).await }
+    })
+    .await
+    .map_err(|status| {
+      format!(
+        "Error recording the encrypted digest of {:?}: {:?}",
+        digest, status
+      )
+    })?;
+    encryption
+      .record_encrypted_digest(digest, encrypted_digest)
+      .await
+  }
+
+  async fn store_raw_bytes(&self, bytes: &[u8]) -> Result<Digest, String> {
     let len = bytes.len();
     let digest = Digest::of_bytes(&bytes);
+    let remote_hash = match self.digest_translation {
+      Some(_) => DigestTranslation::remote_hash(bytes),
+      None => digest.hash.to_hex(),
+    };
     let resource_name = format!(
       "{}/uploads/{}/blobs/{}/{}",
       self.instance_name.clone().unwrap_or_default(),
       uuid::Uuid::new_v4(),
-      digest.hash,
+      remote_hash,
       digest.size_bytes,
     );
     let workunit_name = format!("store_bytes({})", resource_name.clone());
@@ -105,37 +385,62 @@ impl ByteStore {
     };
     let store = self.clone();
 
-    let mut client = self.byte_stream_client.as_ref().clone();
+    let client = self.byte_stream_client.as_ref().clone();
 
     let resource_name = resource_name.clone();
-    let chunk_size_bytes = store.chunk_size_bytes;
+    let chunk_size_bytes = store.chunk_size.get();
+
+    // NB: The budget is acquired before the blob is copied, since the copy is what occupies memory.
+    let inflight_permit = self.acquire_inflight_bytes(len).await;
 
     // NOTE(tonic): The call into the Tonic library wants the slice to last for the 'static
     // lifetime but the slice passed into this method generally points into the shared memory
     // of the LMDB store which is on the other side of the FFI boundary.
     let bytes = Bytes::copy_from_slice(bytes);
 
-    let stream = futures::stream::unfold((0, false), move |(offset, has_sent_any)| {
-      if offset >= bytes.len() && has_sent_any {
-        futures::future::ready(None)
-      } else {
-        let next_offset = min(offset + chunk_size_bytes, bytes.len());
-        let req = bazel_protos::gen::google::bytestream::WriteRequest {
-          resource_name: resource_name.clone(),
-          write_offset: offset as i64,
-          finish_write: next_offset == bytes.len(),
-          // TODO(tonic): Explore using the unreleased `Bytes` support in Prost from:
-          // https://github.com/danburkert/prost/pull/341
-          data: bytes.slice(offset..next_offset),
-        };
-        futures::future::ready(Some((req, (next_offset, true))))
-      }
-    });
+    // NB: The stream is consumed by each attempt to write, so it is recreated for retries.
+    let make_stream = move || {
+      let bytes = bytes.clone();
+      let resource_name = resource_name.clone();
+      futures::stream::unfold((0, false), move |(offset, has_sent_any)| {
+        if offset >= bytes.len() && has_sent_any {
+          futures::future::ready(None)
+        } else {
+          let next_offset = min(offset + chunk_size_bytes, bytes.len());
+          let req = bazel_protos::gen::google::bytestream::WriteRequest {
+            resource_name: resource_name.clone(),
+            write_offset: offset as i64,
+            finish_write: next_offset == bytes.len(),
+            // TODO(tonic): Explore using the unreleased `Bytes` support in Prost from:
+            // https://github.com/danburkert/prost/pull/341
+            data: bytes.slice(offset..next_offset),
+          };
+          futures::future::ready(Some((req, (next_offset, true))))
+        }
+      })
+    };
 
     // NOTE: This async closure must be boxed or else it triggers a consistent stack overflow
     // when awaited with the `with_workunit` call below.
     let result_future = Box::pin(async move {
-      let response = client.write(Request::new(stream)).await.map_err(|err| {
+      let _inflight_permit = inflight_permit;
+      store.wait_for_budget(len).await;
+      let start_time = Instant::now();
+      let response = retry_call(&store.throttle, store.rpc_attempts, || {
+        let mut client = client.clone();
+        let stream = make_stream();
+        async move { client.write(Request::new(stream)).await }
+      })
+      .await;
+      if response.is_ok() {
+        store.chunk_size.observe(len, start_time.elapsed());
+      }
+      if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+        workunit_store_handle
+          .store
+          .record_observation_since(ObservationMetric::RemoteStoreWriteBlobTime, start_time);
+      }
+      let response = response.map_err(|err| {
         format!(
           "Error from server while uploading digest {:?}: {:?}",
           digest, err
@@ -144,6 +449,16 @@ impl ByteStore {
 
       let response = response.into_inner();
       if response.committed_size == len as i64 {
+        if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+          workunit_store_handle
+            .store
+            .increment_counter(Metric::RemoteStoreBytesUploaded, len as u64);
+        }
+        if let Some(digest_translation) = &store.digest_translation {
+          digest_translation
+            .record_remote_hash(digest, &remote_hash)
+            .await?;
+        }
         Ok(digest)
       } else {
         Err(format!(
@@ -175,12 +490,44 @@ impl ByteStore {
     &self,
     digest: Digest,
     f: F,
+  ) -> Result<Option<T>, String> {
+    if let Some(encryption) = self.encryption.clone() {
+      let encrypted_digest = match self.encrypted_digest(&encryption, digest).await? {
+        Some(encrypted_digest) => encrypted_digest,
+        // The blob was never uploaded with this key.
+        None => return Ok(None),
+      };
+      self
+        .load_raw_bytes_with(encrypted_digest, move |bytes| {
+          f(encryption.decrypt(digest, &bytes)?)
+        })
+        .await
+    } else {
+      self.load_raw_bytes_with(digest, f).await
+    }
+  }
+
+  async fn load_raw_bytes_with<
+    T: Send + 'static,
+    F: Fn(Bytes) -> Result<T, String> + Send + Sync + Clone + 'static,
+  >(
+    &self,
+    digest: Digest,
+    f: F,
   ) -> Result<Option<T>, String> {
     let store = self.clone();
+    let remote_hash = match &self.digest_translation {
+      Some(digest_translation) => match digest_translation.translate(digest).await? {
+        Some(remote_hash) => remote_hash,
+        // The blob was never uploaded by this machine.
+        None => return Ok(None),
+      },
+      None => digest.hash.to_hex(),
+    };
     let resource_name = format!(
       "{}/blobs/{}/{}",
       store.instance_name.clone().unwrap_or_default(),
-      digest.hash,
+      remote_hash,
       digest.size_bytes
     );
     let workunit_name = format!("load_bytes_with({})", resource_name.clone());
@@ -191,21 +538,25 @@ impl ByteStore {
     let resource_name = resource_name.clone();
     let f = f.clone();
 
-    let mut client = self.byte_stream_client.as_ref().clone();
+    let client = self.byte_stream_client.as_ref().clone();
 
     let result_future = async move {
+      let _read_permit = store.acquire_read_permit().await;
+      let _inflight_permit = store.acquire_inflight_bytes(digest.size_bytes).await;
+      store.wait_for_budget(digest.size_bytes).await;
       let start_time = Instant::now();
 
-      let stream_result = client
-        .read({
-          bazel_protos::gen::google::bytestream::ReadRequest {
-            resource_name: resource_name.clone(),
-            read_offset: 0,
-            // 0 means no limit.
-            read_limit: 0,
-          }
-        })
-        .await;
+      let stream_result = retry_call(&store.throttle, store.rpc_attempts, || {
+        let mut client = client.clone();
+        let request = bazel_protos::gen::google::bytestream::ReadRequest {
+          resource_name: resource_name.clone(),
+          read_offset: 0,
+          // 0 means no limit.
+          read_limit: 0,
+        };
+        async move { client.read(request).await }
+      })
+      .await;
 
       let mut stream = match stream_result {
         Ok(response) => response.into_inner(),
@@ -247,9 +598,22 @@ impl ByteStore {
       };
 
       let read_result: Result<Bytes, tonic::Status> = read_result_closure.await;
+      if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+        workunit_store_handle
+          .store
+          .record_observation_since(ObservationMetric::RemoteStoreReadBlobTime, start_time);
+      }
 
       let maybe_bytes = match read_result {
-        Ok(bytes) => Some(bytes),
+        Ok(bytes) => {
+          store.chunk_size.observe(bytes.len(), start_time.elapsed());
+          if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+            workunit_store_handle
+              .store
+              .increment_counter(Metric::RemoteStoreBytesDownloaded, bytes.len() as u64);
+          }
+          Some(bytes)
+        }
         Err(status) => {
           if status.code() == tonic::Code::NotFound {
             None
@@ -263,7 +627,12 @@ impl ByteStore {
       };
 
       match maybe_bytes {
-        Some(b) => f(b).map(Some),
+        Some(b) => {
+          if let Some(digest_translation) = &store.digest_translation {
+            digest_translation.validate(digest, &remote_hash, &b)?;
+          }
+          f(b).map(Some)
+        }
         None => Ok(None),
       }
     };
@@ -300,25 +669,119 @@ impl ByteStore {
       ..WorkunitMetadata::default()
     };
     let result_future = async move {
-      let store2 = store.clone();
-      let mut client = store2.cas_client.as_ref().clone();
-      let request = request.clone();
-      let response = client
-        .find_missing_blobs(request)
-        .map_err(|err| {
-          format!(
-            "Error from server in response to find_missing_blobs_request: {:?}",
-            err
+      // When blobs are encrypted, blobs which were never uploaded with the current key are missing,
+      // and the digests of the remainder are translated to the digests of their ciphertext.
+      let (request, mut missing, plaintext_digests) = match &store.encryption {
+        Some(encryption) => {
+          let digests = request
+            .blob_digests
+            .iter()
+            .map(|digest| digest.try_into())
+            .collect::<Result<Vec<Digest>, _>>()?;
+          let encrypted_digests = future::try_join_all(
+            digests
+              .iter()
+              .map(|digest| store.encrypted_digest(encryption, *digest)),
           )
-        })
-        .await?;
+          .await?;
+          let mut missing = HashSet::new();
+          let mut plaintext_digests = HashMap::new();
+          for (digest, encrypted_digest) in digests.into_iter().zip(encrypted_digests) {
+            match encrypted_digest {
+              Some(encrypted_digest) => {
+                plaintext_digests.insert(encrypted_digest, digest);
+              }
+              None => {
+                missing.insert(digest);
+              }
+            }
+          }
+          let request = remexec::FindMissingBlobsRequest {
+            blob_digests: plaintext_digests.keys().map(|d| d.into()).collect(),
+            ..request
+          };
+          (request, missing, Some(plaintext_digests))
+        }
+        None => (request, HashSet::new(), None),
+      };
+
+      // When the remote CAS uses another digest function, blobs which were never uploaded by this
+      // machine are missing, and the digests of the remainder are translated to their remote hashes.
+      let (request, local_digests) = match &store.digest_translation {
+        Some(digest_translation) => {
+          let digests = request
+            .blob_digests
+            .iter()
+            .map(|digest| digest.try_into())
+            .collect::<Result<Vec<Digest>, _>>()?;
+          let remote_hashes = future::try_join_all(
+            digests
+              .iter()
+              .map(|digest| digest_translation.translate(*digest)),
+          )
+          .await?;
+          let mut local_digests = HashMap::new();
+          for (digest, remote_hash) in digests.into_iter().zip(remote_hashes) {
+            match remote_hash {
+              Some(remote_hash) => {
+                local_digests.insert(remote_hash, digest);
+              }
+              None => {
+                missing.insert(plaintext_digest(&plaintext_digests, digest)?);
+              }
+            }
+          }
+          let request = remexec::FindMissingBlobsRequest {
+            blob_digests: local_digests
+              .iter()
+              .map(|(remote_hash, digest)| remexec::Digest {
+                hash: remote_hash.clone(),
+                size_bytes: digest.size_bytes as i64,
+              })
+              .collect(),
+            ..request
+          };
+          (request, Some(local_digests))
+        }
+        None => (request, None),
+      };
+
+      let _read_permit = store.acquire_read_permit().await;
+      store.wait_for_budget(0).await;
+      let store2 = store.clone();
+      let start_time = Instant::now();
+      let response = retry_call(&store.throttle, store.rpc_attempts, || {
+        let mut client = store2.cas_client.as_ref().clone();
+        let request = request.clone();
+        async move { client.find_missing_blobs(request).await }
+      })
+      .await;
+      if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
+        workunit_store_handle.store.record_observation_since(
+          ObservationMetric::RemoteStoreFindMissingBlobsTime,
+          start_time,
+        );
+      }
+      let response = response.map_err(|err| {
+        format!(
+          "Error from server in response to find_missing_blobs_request: {:?}",
+          err
+        )
+      })?;
 
-      response
-        .into_inner()
-        .missing_blob_digests
-        .iter()
-        .map(|digest| digest.try_into())
-        .collect::<Result<HashSet<_>, _>>()
+      for remote_digest in &response.into_inner().missing_blob_digests {
+        let digest: Digest = match &local_digests {
+          Some(local_digests) => *local_digests.get(&remote_digest.hash).ok_or_else(|| {
+            format!(
+              "Server reported a digest missing which was not requested: {:?}",
+              remote_digest
+            )
+          })?,
+          None => remote_digest.try_into()?,
+        };
+        missing.insert(plaintext_digest(&plaintext_digests, digest)?);
+      }
+      Ok(missing)
     };
     async {
       if let Some(workunit_store_handle) = workunit_store::get_workunit_store_handle() {
@@ -342,7 +805,65 @@ impl ByteStore {
   ) -> remexec::FindMissingBlobsRequest {
     remexec::FindMissingBlobsRequest {
       instance_name: self.instance_name.as_ref().cloned().unwrap_or_default(),
-      blob_digests: digests.map(|d| d.into()).collect::<Vec<_>>(),
+      // NB: REAPI servers must behave as though the empty blob is always present.
+      blob_digests: digests
+        .filter(|d| **d != EMPTY_DIGEST)
+        .map(|d| d.into())
+        .collect::<Vec<_>>(),
+    }
+  }
+}
+
+///
+/// Translates the digest of an (encrypted, if `plaintext_digests` is set) blob which was requested
+/// from the remote CAS back to the digest of its plaintext.
+///
+fn plaintext_digest(
+  plaintext_digests: &Option<HashMap<Digest, Digest>>,
+  digest: Digest,
+) -> Result<Digest, String> {
+  match plaintext_digests {
+    Some(plaintext_digests) => plaintext_digests.get(&digest).cloned().ok_or_else(|| {
+      format!(
+        "Server reported a digest missing which was not requested: {:?}",
+        digest
+      )
+    }),
+    None => Ok(digest),
+  }
+}
+
+///
+/// A budget for the number of bytes of blobs which may be in flight (and so buffered in memory) for
+/// remote transfers at once, which keeps peak memory usage predictable when blobs vary widely in
+/// size: a limit on the number of concurrent requests alone allows a few huge blobs to be buffered
+/// at once.
+///
+/// Each transfer reserves its size from the budget before it begins, and returns it when it
+/// completes. A blob which is larger than the entire budget reserves all of it, and so is streamed
+/// (in chunks) while no other transfers are in flight.
+///
+struct InflightBytes {
+  semaphore: Semaphore,
+  max_bytes: usize,
+}
+
+impl InflightBytes {
+  fn new(max_bytes: usize) -> InflightBytes {
+    // NB: Permits are acquired in `u32` quantities.
+    let max_bytes = std::cmp::max(min(max_bytes, u32::MAX as usize), 1);
+    InflightBytes {
+      semaphore: Semaphore::new(max_bytes),
+      max_bytes,
     }
   }
+
+  async fn acquire(&self, bytes: usize) -> SemaphorePermit<'_> {
+    let bytes = std::cmp::max(min(bytes, self.max_bytes), 1);
+    self
+      .semaphore
+      .acquire_many(bytes as u32)
+      .await
+      .expect("The in-flight bytes semaphore is never closed.")
+  }
 }
@@ -8,7 +8,7 @@ use testutil::data::{TestData, TestDirectory};
 
 use crate::remote::ByteStore;
 use crate::tests::{big_file_bytes, big_file_digest, big_file_fingerprint, new_cas};
-use crate::MEGABYTES;
+use crate::{EndpointStatus, MEGABYTES};
 
 #[tokio::test]
 async fn loads_file() {
@@ -156,8 +156,12 @@ async fn write_file_multiple_chunks() {
     None,
     BTreeMap::new(),
     10 * 1024,
+    None,
     Duration::from_secs(5),
     1,
+    None,
+    None,
+    None,
   )
   .unwrap();
 
@@ -205,6 +209,29 @@ async fn write_empty_file() {
   );
 }
 
+#[tokio::test]
+async fn transfers_larger_than_max_inflight_bytes() {
+  let cas = StubCAS::empty();
+  let store = new_byte_store(&cas).with_max_inflight_bytes(1024);
+
+  // Blobs which are larger than the entire budget are transferred on their own.
+  let all_the_henries = big_file_bytes();
+  assert!(all_the_henries.len() > 1024);
+  let (roland, henries) = futures::join!(
+    store.store_bytes(&TestData::roland().bytes()),
+    store.store_bytes(&all_the_henries)
+  );
+  assert_eq!(roland, Ok(TestData::roland().digest()));
+  assert_eq!(henries, Ok(big_file_digest()));
+
+  let (roland, henries) = futures::join!(
+    load_file_bytes(&store, TestData::roland().digest()),
+    load_file_bytes(&store, big_file_digest())
+  );
+  assert_eq!(roland, Ok(Some(TestData::roland().bytes())));
+  assert_eq!(henries, Ok(Some(all_the_henries)));
+}
+
 #[tokio::test]
 async fn write_file_errors() {
   let cas = StubCAS::always_errors();
@@ -232,8 +259,12 @@ async fn write_connection_error() {
     None,
     BTreeMap::new(),
     10 * 1024 * 1024,
+    None,
     Duration::from_secs(1),
     1,
+    None,
+    None,
+    None,
   )
   .unwrap();
   let error = store
@@ -298,6 +329,23 @@ async fn list_missing_digests_error() {
   );
 }
 
+#[tokio::test]
+async fn warm_up_endpoint() {
+  let cas = StubCAS::empty();
+  let store = new_byte_store(&cas);
+
+  assert_eq!(store.endpoint().status(), EndpointStatus::Unknown);
+  assert_eq!(store.endpoint().warm_up().await.name(), "healthy");
+}
+
+#[tokio::test]
+async fn warm_up_endpoint_unavailable() {
+  let cas = StubCAS::always_errors();
+  let store = new_byte_store(&cas);
+
+  assert_eq!(store.endpoint().warm_up().await.name(), "unhealthy");
+}
+
 fn new_byte_store(cas: &StubCAS) -> ByteStore {
   ByteStore::new(
     &cas.address(),
@@ -305,8 +353,12 @@ fn new_byte_store(cas: &StubCAS) -> ByteStore {
     None,
     BTreeMap::new(),
     10 * MEGABYTES,
+    None,
     Duration::from_secs(1),
     1,
+    None,
+    None,
+    None,
   )
   .unwrap()
 }
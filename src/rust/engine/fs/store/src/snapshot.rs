@@ -17,6 +17,7 @@ use futures::future;
 use futures::FutureExt;
 use hashing::{Digest, EMPTY_DIGEST};
 use itertools::Itertools;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
 
 use crate::Store;
 
@@ -42,7 +43,7 @@ impl Snapshot {
     file_digester: S,
     path_stats: Vec<PathStat>,
   ) -> Result<Snapshot, String> {
-    let path_stats = PathStat::normalize_path_stats(path_stats)?;
+    let path_stats = PathStat::normalize_path_stats(normalize_unicode(path_stats)?)?;
     let digest =
       Snapshot::ingest_directory_from_sorted_path_stats(store, file_digester, &path_stats).await?;
     Ok(Snapshot { digest, path_stats })
@@ -85,7 +86,7 @@ impl Snapshot {
     file_digester: S,
     path_stats: Vec<PathStat>,
   ) -> Result<Digest, String> {
-    let path_stats = PathStat::normalize_path_stats(path_stats)?;
+    let path_stats = PathStat::normalize_path_stats(normalize_unicode(path_stats)?)?;
     Snapshot::ingest_directory_from_sorted_path_stats(store, file_digester, &path_stats).await
   }
 
@@ -289,6 +290,57 @@ impl fmt::Debug for Snapshot {
   }
 }
 
+///
+/// On macOS, normalizes the names of the given PathStats to Unicode NFC, so that a tree has the
+/// same digest regardless of the filesystem that it was captured from: HFS+ lists names in NFD,
+/// while other filesystems generally preserve the form that names were created in, which is
+/// usually NFC. Along with the ordering of entries by the bytes of their (normalized) names in
+/// `PathStat::normalize_path_stats`, this makes the digest of a tree independent of the platform.
+///
+/// Elsewhere, names which differ only in their normalization are distinct files (which a process
+/// would fail to open by their normalized names), and so are left alone.
+///
+/// Only the logical paths of the PathStats are normalized: files continue to be read from the
+/// paths in their Stats. Names which are not valid UTF8 are left alone (and fail later).
+///
+fn normalize_unicode(path_stats: Vec<PathStat>) -> Result<Vec<PathStat>, String> {
+  if !cfg!(target_os = "macos") {
+    return Ok(path_stats);
+  }
+  let mut originals: HashMap<PathBuf, PathBuf> = HashMap::new();
+  path_stats
+    .into_iter()
+    .map(|path_stat| {
+      let normalized = match path_stat.path().to_str() {
+        Some(path) if !is_nfc(path) => Some(PathBuf::from(path.nfc().collect::<String>())),
+        _ => None,
+      };
+      let key = normalized
+        .clone()
+        .unwrap_or_else(|| path_stat.path().to_owned());
+      if let Some(original) = originals.insert(key, path_stat.path().to_owned()) {
+        if original != path_stat.path() {
+          return Err(format!(
+            "The paths {:?} and {:?} are identical after Unicode normalization, and so cannot both \
+             be captured in a Snapshot.",
+            original,
+            path_stat.path()
+          ));
+        }
+      }
+      let path = if let Some(path) = normalized {
+        path
+      } else {
+        return Ok(path_stat);
+      };
+      Ok(match path_stat {
+        PathStat::File { stat, .. } => PathStat::File { path, stat },
+        PathStat::Dir { stat, .. } => PathStat::Dir { path, stat },
+      })
+    })
+    .collect()
+}
+
 fn paths_of_child_dir(paths: Vec<PathStat>) -> Vec<PathStat> {
   paths
     .into_iter()
@@ -344,6 +396,12 @@ impl StoreFileByDigest<String> for OneOffStoreFileByDigest {
     let store = self.store.clone();
     let posix_fs = self.posix_fs.clone();
     let res = async move {
+      if let Some(digest) = store
+        .digest_of_tagged_file(posix_fs.file_path(&file))
+        .await?
+      {
+        return Ok(digest);
+      }
       let content = posix_fs
         .read_file(&file)
         .await
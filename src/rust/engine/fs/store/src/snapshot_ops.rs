@@ -22,7 +22,7 @@ use indexmap::{self, IndexMap};
 use itertools::{Either, Itertools};
 use log::log_enabled;
 
-use crate::{snapshot::osstring_as_utf8, Snapshot};
+use crate::{snapshot::osstring_as_utf8, GlobMatchCache, GlobMatchKey, Snapshot};
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub enum SnapshotOpsError {
@@ -61,6 +61,13 @@ pub trait StoreWrapper: Clone + Send + Sync {
   async fn load_directory_or_err(&self, digest: Digest) -> Result<remexec::Directory, String>;
 
   async fn record_directory(&self, directory: &remexec::Directory) -> Result<Digest, String>;
+
+  ///
+  /// A cache of the results of `SnapshotOps::subset`, if this store has one.
+  ///
+  fn glob_match_cache(&self) -> Option<&GlobMatchCache> {
+    None
+  }
 }
 
 ///
@@ -765,7 +772,19 @@ pub trait SnapshotOps: StoreWrapper + 'static {
 
   async fn subset(&self, digest: Digest, params: SubsetParams) -> Result<Digest, SnapshotOpsError> {
     let SubsetParams { globs } = params;
-    snapshot_glob_match(self.clone(), digest, globs).await
+    let cache = if let Some(cache) = self.glob_match_cache() {
+      cache
+    } else {
+      return snapshot_glob_match(self.clone(), digest, globs).await;
+    };
+
+    let key = GlobMatchKey::new(digest, &globs);
+    if let Some(subset_digest) = cache.get(&key) {
+      return Ok(subset_digest);
+    }
+    let subset_digest = snapshot_glob_match(self.clone(), digest, globs).await?;
+    cache.insert(key, subset_digest);
+    Ok(subset_digest)
   }
 
   async fn create_empty_dir(&self, path: RelativePath) -> Result<Digest, SnapshotOpsError> {
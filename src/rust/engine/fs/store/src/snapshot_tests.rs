@@ -2,6 +2,7 @@ use std::convert::TryInto;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use bytes::Bytes;
 use hashing::{Digest, Fingerprint, EMPTY_DIGEST};
 use task_executor;
 use tempfile;
@@ -10,8 +11,8 @@ use testutil::make_file;
 
 use crate::{OneOffStoreFileByDigest, RelativePath, Snapshot, SnapshotOps, Store};
 use fs::{
-  Dir, File, GitignoreStyleExcludes, GlobExpansionConjunction, GlobMatching, PathGlobs, PathStat,
-  PosixFS, StrictGlobMatching,
+  Dir, File, FileContent, GitignoreStyleExcludes, GlobExpansionConjunction, GlobMatching,
+  PathGlobs, PathStat, PosixFS, StrictGlobMatching,
 };
 
 pub const STR: &str = "European Burmese";
@@ -115,6 +116,68 @@ async fn snapshot_from_digest() {
   );
 }
 
+#[cfg(target_os = "macos")]
+#[tokio::test]
+async fn snapshot_normalizes_unicode() {
+  // The same names, in NFD (as listed by HFS+) and in NFC (as usually created on Linux).
+  let nfd = "cafe\u{301}/ma\u{303}o";
+  let nfc = "caf\u{e9}/m\u{e3}o";
+
+  let snapshot_of = |path: &'static str| async move {
+    let (store, dir, posix_fs, digester) = setup();
+    let path = dir.path().join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    make_file(&path, STR.as_bytes(), 0o600);
+    make_file(&dir.path().join("zebra"), STR2.as_bytes(), 0o600);
+    // The order of the PathStats does not affect the Snapshot.
+    let mut path_stats = expand_all_sorted(posix_fs).await;
+    path_stats.reverse();
+    Snapshot::from_path_stats(store, digester, path_stats)
+      .await
+      .unwrap()
+  };
+
+  let nfd_snapshot = snapshot_of(nfd).await;
+  let nfc_snapshot = snapshot_of(nfc).await;
+  assert_eq!(nfd_snapshot, nfc_snapshot);
+  assert_eq!(
+    nfd_snapshot
+      .path_stats
+      .iter()
+      .map(|path_stat| path_stat.path().to_owned())
+      .collect::<Vec<_>>(),
+    vec![
+      PathBuf::from("caf\u{e9}"),
+      PathBuf::from(nfc),
+      PathBuf::from("zebra")
+    ]
+  );
+}
+
+// NB: Other platforms may not allow both of these names to exist in one directory.
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn snapshot_preserves_unicode_names() {
+  let (store, dir, posix_fs, digester) = setup();
+  let nfd = "cafe\u{301}";
+  let nfc = "caf\u{e9}";
+  make_file(&dir.path().join(nfd), STR.as_bytes(), 0o600);
+  make_file(&dir.path().join(nfc), STR2.as_bytes(), 0o600);
+
+  let path_stats = expand_all_sorted(posix_fs).await;
+  let snapshot = Snapshot::from_path_stats(store, digester, path_stats)
+    .await
+    .unwrap();
+  assert_eq!(
+    snapshot
+      .path_stats
+      .iter()
+      .map(|path_stat| path_stat.path().to_owned())
+      .collect::<Vec<_>>(),
+    vec![PathBuf::from(nfd), PathBuf::from(nfc)]
+  );
+}
+
 #[tokio::test]
 async fn snapshot_recursive_directories_including_empty() {
   let (store, dir, posix_fs, digester) = setup();
@@ -148,6 +211,67 @@ async fn snapshot_recursive_directories_including_empty() {
   );
 }
 
+#[tokio::test]
+async fn snapshot_of_file_contents() {
+  let (store, _, _, _) = setup();
+
+  let roland = || FileContent {
+    path: PathBuf::from("cats/roland"),
+    content: Bytes::from(STR),
+    is_executable: false,
+  };
+  let snapshot = store
+    .snapshot_of_file_contents(
+      // Identical duplicates are permitted.
+      vec![roland(), roland()],
+      vec![
+        RelativePath::new("dogs").unwrap(),
+        RelativePath::new("llamas").unwrap(),
+      ],
+    )
+    .await
+    .unwrap();
+  // The same digest as `snapshot_recursive_directories_including_empty`.
+  assert_eq!(
+    snapshot.digest,
+    Digest::new(
+      Fingerprint::from_hex_string(
+        "fbff703bdaac62accf2ea5083bcfed89292073bf710ef9ad14d9298c637e777b",
+      )
+      .unwrap(),
+      232,
+    )
+  );
+  assert_eq!(
+    store
+      .load_file_bytes_with(Digest::of_bytes(STR.as_bytes()), |bytes| bytes.to_vec())
+      .await
+      .unwrap()
+      .map(|(bytes, _)| bytes),
+    Some(STR.as_bytes().to_vec())
+  );
+}
+
+#[tokio::test]
+async fn snapshot_of_file_contents_clashing_files() {
+  let (store, _, _, _) = setup();
+
+  let file_content = |content: &'static str| FileContent {
+    path: PathBuf::from("cats/roland"),
+    content: Bytes::from(content),
+    is_executable: false,
+  };
+  let err = store
+    .snapshot_of_file_contents(vec![file_content(STR), file_content(STR2)], vec![])
+    .await
+    .unwrap_err();
+  assert!(
+    err.contains("two different files at the path cats/roland"),
+    "{}",
+    err
+  );
+}
+
 #[tokio::test]
 async fn merge_directories_two_files() {
   let (store, _, _, _) = setup();
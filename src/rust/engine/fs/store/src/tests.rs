@@ -1,7 +1,7 @@
 use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::Read;
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tempfile::TempDir;
@@ -16,8 +16,8 @@ use maplit::btreemap;
 use mock::StubCAS;
 
 use crate::{
-  DirectoryMaterializeMetadata, EntryType, FileContent, LoadMetadata, Store, UploadSummary,
-  MEGABYTES,
+  DigestFunction, DirectoryMaterializeMetadata, EntryType, FileContent, GcRoots, LoadMetadata,
+  RootLifetime, ShrinkBehavior, Store, TreeSize, UploadSummary, ENCRYPTION_KEY_SIZE, MEGABYTES,
 };
 
 impl LoadMetadata {
@@ -105,12 +105,118 @@ fn new_store<P: AsRef<Path>>(dir: P, cas_address: &str) -> Store {
       None,
       BTreeMap::new(),
       10 * MEGABYTES,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap()
 }
 
+#[tokio::test]
+async fn expand_digest() {
+  let dir = TempDir::new().unwrap();
+  let store = new_local_store(dir.path());
+  let testdata = TestData::roland();
+  store
+    .store_file_bytes(testdata.bytes(), false)
+    .await
+    .expect("Store failed");
+
+  let digest = testdata.digest();
+  assert_eq!(
+    store.expand_digest(&digest.to_display_string(false)).await,
+    Ok(digest)
+  );
+  assert_eq!(
+    store.expand_digest(&digest.to_display_string(true)).await,
+    Ok(digest)
+  );
+  assert!(store
+    .expand_digest(&TestData::catnip().digest().to_display_string(false))
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn tree_size() {
+  let dir = TempDir::new().unwrap();
+  let store = new_local_store(dir.path());
+  let testdir = TestDirectory::containing_roland();
+  let recursive_testdir = TestDirectory::recursive();
+  // NB: Only the directories are stored: the files are never loaded.
+  store
+    .record_directory(&testdir.directory(), false)
+    .await
+    .expect("Error storing directory");
+  store
+    .record_directory(&recursive_testdir.directory(), false)
+    .await
+    .expect("Error storing directory");
+
+  assert_eq!(
+    store.tree_size(recursive_testdir.digest()).await,
+    Ok(TreeSize {
+      file_count: 2,
+      file_bytes: TestData::roland().len() + TestData::catnip().len(),
+      directory_count: 2,
+    })
+  );
+  assert_eq!(
+    store.tree_size(TestDirectory::nested().digest()).await,
+    Err(format!(
+      "Could not walk unknown directory: {:?}",
+      TestDirectory::nested().digest()
+    ))
+  );
+}
+
+#[tokio::test]
+async fn replace_files() {
+  let dir = TempDir::new().unwrap();
+  let store = new_local_store(dir.path());
+  store
+    .record_directory(&TestDirectory::containing_roland().directory(), false)
+    .await
+    .expect("Error storing directory");
+  store
+    .record_directory(&TestDirectory::nested().directory(), false)
+    .await
+    .expect("Error storing directory");
+
+  let replaced = store
+    .replace_files(
+      TestDirectory::nested().digest(),
+      vec![(PathBuf::from("cats/roland"), TestData::catnip().digest())]
+        .into_iter()
+        .collect(),
+    )
+    .await
+    .unwrap();
+  let (directory, _) = store.load_directory(replaced).await.unwrap().unwrap();
+  assert_eq!(
+    directory.directories[0].digest,
+    Some((&TestDirectory::containing_wrong_roland().digest()).into())
+  );
+
+  assert_eq!(
+    store
+      .replace_files(
+        TestDirectory::nested().digest(),
+        vec![(PathBuf::from("cats"), TestData::catnip().digest())]
+          .into_iter()
+          .collect(),
+      )
+      .await,
+    Err(format!(
+      "Cannot replace cats: it is not a file in {:?}",
+      TestDirectory::nested().digest()
+    ))
+  );
+}
+
 #[tokio::test]
 async fn load_file_prefers_local() {
   let dir = TempDir::new().unwrap();
@@ -582,6 +688,144 @@ async fn uploads_files() {
   );
 }
 
+#[tokio::test]
+async fn encrypts_uploaded_files() {
+  let dir = TempDir::new().unwrap();
+  let cas = StubCAS::empty();
+
+  let testdata = TestData::roland();
+
+  new_local_store(dir.path())
+    .store_file_bytes(testdata.bytes(), false)
+    .await
+    .expect("Error storing file locally");
+
+  {
+    let store = new_store(dir.path(), &cas.address())
+      .with_remote_encryption(&[1; ENCRYPTION_KEY_SIZE])
+      .unwrap();
+    store
+      .ensure_remote_has_recursive(vec![testdata.digest()])
+      .await
+      .expect("Error uploading file");
+
+    // Only the ciphertext is stored remotely.
+    {
+      let blobs = cas.blobs.lock();
+      assert_eq!(blobs.get(&testdata.fingerprint()), None);
+      assert_eq!(blobs.len(), 1);
+      assert_ne!(blobs.values().next(), Some(&testdata.bytes()));
+    }
+    // Along with the digest of the ciphertext, under an identifier which is derived from the key.
+    assert_eq!(cas.action_results.lock().len(), 1);
+
+    // And is downloaded and decrypted once the local copy is removed.
+    assert!(store.remove_file(testdata.digest()).await.unwrap());
+    assert_eq!(
+      load_file_bytes(&store, testdata.digest()).await,
+      Ok(Some(testdata.bytes()))
+    );
+  }
+
+  // A store using another key does not find the file.
+  let store = new_store(dir.path(), &cas.address())
+    .with_remote_encryption(&[2; ENCRYPTION_KEY_SIZE])
+    .unwrap();
+  assert!(store.remove_file(testdata.digest()).await.unwrap());
+  assert_eq!(load_file_bytes(&store, testdata.digest()).await, Ok(None));
+}
+
+#[tokio::test]
+async fn loads_files_encrypted_by_another_machine() {
+  let uploader_dir = TempDir::new().unwrap();
+  let cas = StubCAS::empty();
+
+  let testdata = TestData::roland();
+
+  new_local_store(uploader_dir.path())
+    .store_file_bytes(testdata.bytes(), false)
+    .await
+    .expect("Error storing file locally");
+  new_store(uploader_dir.path(), &cas.address())
+    .with_remote_encryption(&[1; ENCRYPTION_KEY_SIZE])
+    .unwrap()
+    .ensure_remote_has_recursive(vec![testdata.digest()])
+    .await
+    .expect("Error uploading file");
+
+  // A store with an empty local store and the same key finds the ciphertext via the remote action
+  // cache.
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path(), &cas.address())
+    .with_remote_encryption(&[1; ENCRYPTION_KEY_SIZE])
+    .unwrap();
+  assert_eq!(
+    load_file_bytes(&store, testdata.digest()).await,
+    Ok(Some(testdata.bytes()))
+  );
+
+  // But a store with another key does not.
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path(), &cas.address())
+    .with_remote_encryption(&[2; ENCRYPTION_KEY_SIZE])
+    .unwrap();
+  assert_eq!(load_file_bytes(&store, testdata.digest()).await, Ok(None));
+}
+
+#[tokio::test]
+async fn translates_digests_for_sha1_remote() {
+  let dir = TempDir::new().unwrap();
+  let cas = StubCAS::empty();
+
+  let testdata = TestData::roland();
+
+  new_local_store(dir.path())
+    .store_file_bytes(testdata.bytes(), false)
+    .await
+    .expect("Error storing file locally");
+
+  let store = new_store(dir.path(), &cas.address())
+    .with_remote_digest_function(DigestFunction::Sha1)
+    .unwrap();
+  let summary = store
+    .ensure_remote_has_recursive(vec![testdata.digest()])
+    .await
+    .expect("Error uploading file");
+  assert_eq!(summary.uploaded_file_count, 1);
+
+  // The blob is stored under its SHA-1 hash.
+  {
+    let blobs = cas.blobs.lock();
+    assert_eq!(blobs.get(&testdata.fingerprint()), None);
+    assert_eq!(blobs.len(), 1);
+    assert_eq!(blobs.values().next(), Some(&testdata.bytes()));
+  }
+
+  // Is found by its SHA-256 digest when uploading again.
+  let summary = store
+    .ensure_remote_has_recursive(vec![testdata.digest()])
+    .await
+    .expect("Error uploading file");
+  assert_eq!(summary.uploaded_file_count, 0);
+
+  // And is downloaded once the local copy is removed.
+  assert!(store.remove_file(testdata.digest()).await.unwrap());
+  assert_eq!(
+    load_file_bytes(&store, testdata.digest()).await,
+    Ok(Some(testdata.bytes()))
+  );
+
+  // A store which did not upload the blob cannot translate its digest, and so does not find it.
+  let other_dir = TempDir::new().unwrap();
+  let other_store = new_store(other_dir.path(), &cas.address())
+    .with_remote_digest_function(DigestFunction::Sha1)
+    .unwrap();
+  assert_eq!(
+    load_file_bytes(&other_store, testdata.digest()).await,
+    Ok(None)
+  );
+}
+
 #[tokio::test]
 async fn uploads_directories_recursively() {
   let dir = TempDir::new().unwrap();
@@ -845,8 +1089,12 @@ async fn instance_name_upload() {
       None,
       BTreeMap::new(),
       10 * MEGABYTES,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap();
 
@@ -872,8 +1120,12 @@ async fn instance_name_download() {
       None,
       BTreeMap::new(),
       10 * MEGABYTES,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap();
 
@@ -921,8 +1173,12 @@ async fn auth_upload() {
       None,
       headers,
       10 * MEGABYTES,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap();
 
@@ -950,8 +1206,12 @@ async fn auth_download() {
       None,
       headers,
       10 * MEGABYTES,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap();
 
@@ -974,7 +1234,7 @@ async fn materialize_missing_file() {
   let store_dir = TempDir::new().unwrap();
   let store = new_local_store(store_dir.path());
   store
-    .materialize_file(file.clone(), TestData::roland().digest(), false)
+    .materialize_file(file.clone(), TestData::roland().digest(), false, false)
     .await
     .expect_err("Want unknown digest error");
 }
@@ -993,7 +1253,7 @@ async fn materialize_file() {
     .await
     .expect("Error saving bytes");
   store
-    .materialize_file(file.clone(), testdata.digest(), false)
+    .materialize_file(file.clone(), testdata.digest(), false, false)
     .await
     .expect("Error materializing file");
   assert_eq!(file_contents(&file), testdata.bytes());
@@ -1014,13 +1274,72 @@ async fn materialize_file_executable() {
     .await
     .expect("Error saving bytes");
   store
-    .materialize_file(file.clone(), testdata.digest(), true)
+    .materialize_file(file.clone(), testdata.digest(), true, false)
     .await
     .expect("Error materializing file");
   assert_eq!(file_contents(&file), testdata.bytes());
   assert!(is_executable(&file));
 }
 
+#[tokio::test]
+async fn materialize_with_umask() {
+  let materialize_dir = TempDir::new().unwrap();
+
+  let catnip = TestData::catnip();
+  let testdir = TestDirectory::with_mixed_executable_files();
+
+  let store_dir = TempDir::new().unwrap();
+  let store = new_local_store(store_dir.path()).with_materialize_umask(0o002);
+  store
+    .record_directory(&testdir.directory(), false)
+    .await
+    .expect("Error saving Directory");
+  store
+    .store_file_bytes(catnip.bytes(), false)
+    .await
+    .expect("Error saving catnip file bytes");
+
+  let destination = materialize_dir.path().join("sandbox");
+  store
+    .materialize_directory(destination.clone(), testdir.digest())
+    .await
+    .expect("Error materializing");
+
+  let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+  assert_eq!(mode(&destination), 0o775);
+  assert_eq!(mode(&destination.join("feed")), 0o775);
+  assert_eq!(mode(&destination.join("food")), 0o664);
+}
+
+#[tokio::test]
+async fn materialize_with_digest_xattrs() {
+  let materialize_dir = TempDir::new().unwrap();
+  let file = materialize_dir.path().join("file");
+
+  let testdata = TestData::roland();
+
+  let store_dir = TempDir::new().unwrap();
+  let store = new_local_store(store_dir.path()).with_digest_xattrs(true);
+  store
+    .store_file_bytes(testdata.bytes(), false)
+    .await
+    .expect("Error saving bytes");
+  store
+    .materialize_file(file.clone(), testdata.digest(), false, false)
+    .await
+    .expect("Error materializing file");
+
+  // Tagging is best-effort, because not all filesystems support extended attributes.
+  let tagged_digest = store.digest_of_tagged_file(file.clone()).await.unwrap();
+  if tagged_digest.is_some() {
+    assert_eq!(tagged_digest, Some(testdata.digest()));
+  }
+
+  // Once the file has been modified, its tag is no longer trusted.
+  std::fs::write(&file, "modified").unwrap();
+  assert_eq!(store.digest_of_tagged_file(file).await.unwrap(), None);
+}
+
 #[tokio::test]
 async fn materialize_missing_directory() {
   let materialize_dir = TempDir::new().unwrap();
@@ -1087,6 +1406,124 @@ async fn materialize_directory() {
   );
 }
 
+#[tokio::test]
+async fn materialize_directory_skipping_unchanged() {
+  let materialize_dir = TempDir::new().unwrap();
+  let file = materialize_dir.path().join("roland");
+
+  let roland = TestData::roland();
+  let testdir = TestDirectory::containing_roland();
+
+  let store_dir = TempDir::new().unwrap();
+  let store = new_local_store(store_dir.path());
+  store
+    .record_directory(&testdir.directory(), false)
+    .await
+    .expect("Error saving Directory");
+  store
+    .store_file_bytes(roland.bytes(), false)
+    .await
+    .expect("Error saving file bytes");
+
+  let materialize = || {
+    store
+      .materialize_directory_skipping_unchanged(materialize_dir.path().to_owned(), testdir.digest())
+  };
+  materialize().await.expect("Error materializing");
+  assert_eq!(file_contents(&file), roland.bytes());
+
+  // Hard link the file, so that we can tell whether it was replaced.
+  let link_dir = TempDir::new().unwrap();
+  std::fs::hard_link(&file, link_dir.path().join("roland")).unwrap();
+
+  // An unchanged file is left alone.
+  materialize().await.expect("Error materializing");
+  assert_eq!(std::fs::metadata(&file).unwrap().nlink(), 2);
+
+  // As is the content of a file with the wrong mode: only its mode is changed.
+  std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o755)).unwrap();
+  materialize().await.expect("Error materializing");
+  assert_eq!(std::fs::metadata(&file).unwrap().nlink(), 2);
+  assert_eq!(
+    std::fs::metadata(&file).unwrap().permissions().mode() & 0o777,
+    0o644
+  );
+
+  // But a file with the wrong content (even of the right length) is replaced.
+  std::fs::write(&file, "Not a cat at all").unwrap();
+  materialize().await.expect("Error materializing");
+  assert_eq!(std::fs::metadata(&file).unwrap().nlink(), 1);
+  assert_eq!(file_contents(&file), roland.bytes());
+}
+
+#[tokio::test]
+async fn materialize_directory_transactionally() {
+  let materialize_dir = TempDir::new().unwrap();
+  let roland_file = materialize_dir.path().join("cats").join("roland");
+
+  let roland = TestData::roland();
+  let treats = TestData::catnip();
+  let nested = TestDirectory::nested().digest();
+  let recursive = TestDirectory::recursive().digest();
+
+  let store_dir = TempDir::new().unwrap();
+  let store = new_local_store(store_dir.path());
+  for directory in &[
+    TestDirectory::containing_roland(),
+    TestDirectory::nested(),
+    TestDirectory::recursive(),
+  ] {
+    store
+      .record_directory(&directory.directory(), false)
+      .await
+      .expect("Error saving Directory");
+  }
+  store
+    .store_file_bytes(roland.bytes(), false)
+    .await
+    .expect("Error saving file bytes");
+
+  store
+    .materialize_directory_transactionally(materialize_dir.path().to_owned(), nested)
+    .await
+    .expect("Error materializing");
+  assert_eq!(list_dir(materialize_dir.path()), vec!["cats"]);
+  assert_eq!(file_contents(&roland_file), roland.bytes());
+
+  // If any root fails to materialize (here, because the content of `treats` is missing), then
+  // nothing is changed.
+  std::fs::write(&roland_file, "Not a cat at all").unwrap();
+  std::fs::write(materialize_dir.path().join("unrelated"), "unrelated").unwrap();
+  store
+    .materialize_directory_transactionally(materialize_dir.path().to_owned(), recursive)
+    .await
+    .expect_err("Want error materializing");
+  assert_eq!(list_dir(materialize_dir.path()), vec!["cats", "unrelated"]);
+  assert_eq!(
+    file_contents(&roland_file),
+    Bytes::from_static(b"Not a cat at all")
+  );
+
+  // Otherwise, all roots are replaced, and unrelated files are left alone.
+  store
+    .store_file_bytes(treats.bytes(), false)
+    .await
+    .expect("Error saving file bytes");
+  store
+    .materialize_directory_transactionally(materialize_dir.path().to_owned(), recursive)
+    .await
+    .expect("Error materializing");
+  assert_eq!(
+    list_dir(materialize_dir.path()),
+    vec!["cats", "treats", "unrelated"]
+  );
+  assert_eq!(file_contents(&roland_file), roland.bytes());
+  assert_eq!(
+    file_contents(&materialize_dir.path().join("treats")),
+    treats.bytes()
+  );
+}
+
 #[tokio::test]
 async fn materialize_directory_executable() {
   let materialize_dir = TempDir::new().unwrap();
@@ -1605,3 +2042,46 @@ fn directory_materialize_metadata_empty_dir() {
   assert!(!md.contains_file(&RelativePath::new("./script").unwrap()));
   assert!(!md.contains_file(&RelativePath::new("script").unwrap()));
 }
+
+#[tokio::test]
+async fn garbage_collect_with_roots_retains_reachable_blobs() {
+  let dir = TempDir::new().unwrap();
+  let store = new_local_store(dir.path());
+  let roland = TestData::roland();
+  let catnip = TestData::catnip();
+  let directory = TestDirectory::containing_roland();
+  store
+    .store_file_bytes(roland.bytes(), false)
+    .await
+    .expect("Error storing");
+  store
+    .store_file_bytes(catnip.bytes(), false)
+    .await
+    .expect("Error storing");
+  store
+    .record_directory(&directory.directory(), false)
+    .await
+    .expect("Error storing");
+
+  let mut roots = GcRoots::new();
+  roots.add(directory.digest(), RootLifetime::Live);
+  store
+    .garbage_collect_with_roots(0, ShrinkBehavior::Fast, roots)
+    .await
+    .expect("Error garbage collecting");
+
+  // The Directory and the file below it are reachable from the root, but catnip is not.
+  assert_eq!(
+    store
+      .load_directory(directory.digest())
+      .await
+      .unwrap()
+      .map(|(d, _)| d),
+    Some(directory.directory())
+  );
+  assert_eq!(
+    load_file_bytes(&store, roland.digest()).await,
+    Ok(Some(roland.bytes()))
+  );
+  assert_eq!(load_file_bytes(&store, catnip.digest()).await, Ok(None));
+}
@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+
+///
+/// The prefix of the names of the staging directories which are created beneath the destinations
+/// of transactional materializations. Staging directories are hidden, so the default
+/// `--pants-ignore` excludes them from globs and from the filesystem watcher (and
+/// `GlobalOptions.compute_pants_ignore` excludes them even if it is overridden).
+///
+pub(crate) const STAGING_DIR_PREFIX: &str = ".pants-staging-";
+
+///
+/// Creates a staging directory beneath the given destination (so that renames out of it are
+/// atomic), named for the current process. The directory is removed when it is dropped.
+///
+pub(crate) fn create_staging_dir(destination: &Path) -> Result<tempfile::TempDir, String> {
+  fs::safe_create_dir_all(destination)?;
+  remove_stale_staging_dirs(destination);
+  tempfile::Builder::new()
+    .prefix(&format!("{}{}-", STAGING_DIR_PREFIX, std::process::id()))
+    .tempdir_in(destination)
+    .map_err(|e| {
+      format!(
+        "Failed to create staging directory in {}: {}",
+        destination.display(),
+        e
+      )
+    })
+}
+
+///
+/// Removes any staging directories beneath the given destination which were left behind by a
+/// process which is no longer running (because it was killed while staging, for example).
+/// Staging directories of running processes (including this one) are left alone.
+///
+pub(crate) fn remove_stale_staging_dirs(destination: &Path) {
+  let entries = match std::fs::read_dir(destination) {
+    Ok(entries) => entries,
+    Err(_) => return,
+  };
+  for entry in entries.flatten() {
+    let name = entry.file_name();
+    let pid = match name
+      .to_str()
+      .and_then(|name| name.strip_prefix(STAGING_DIR_PREFIX))
+      .and_then(|suffix| suffix.split('-').next())
+      .and_then(|pid| pid.parse::<libc::pid_t>().ok())
+    {
+      Some(pid) => pid,
+      None => continue,
+    };
+    if process_is_running(pid) {
+      continue;
+    }
+    if let Err(e) = std::fs::remove_dir_all(entry.path()) {
+      debug!(
+        "Failed to remove stale staging directory {}: {}",
+        entry.path().display(),
+        e
+      );
+    }
+  }
+}
+
+fn process_is_running(pid: libc::pid_t) -> bool {
+  // NB: Signal 0 only checks for the existence of the process. EPERM indicates that the process
+  // exists, but belongs to another user.
+  unsafe { libc::kill(pid, 0) == 0 }
+  || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+///
+/// A file or directory which has been materialized at a staging path, and which should replace
+/// whatever exists at its destination path when a transaction is committed.
+///
+#[derive(Clone, Debug)]
+pub(crate) struct StagedRoot {
+  pub staged: PathBuf,
+  pub destination: PathBuf,
+}
+
+///
+/// Moves each of the given staged roots to its destination, using the given staging directory
+/// (which must be on the same filesystem as the destinations) to hold any existing entries which
+/// are replaced.
+///
+/// Each root is moved with a single rename, so the window in which an interruption could leave
+/// only some of the roots in place is as small as possible. If any rename fails, the roots which
+/// were already moved are rolled back, and the replaced entries are restored.
+///
+pub(crate) fn commit(staging_dir: &Path, roots: &[StagedRoot]) -> Result<(), String> {
+  // The destinations which have been replaced so far, with their backups, if any.
+  let mut committed: Vec<(&Path, Option<PathBuf>)> = Vec::with_capacity(roots.len());
+  for (i, root) in roots.iter().enumerate() {
+    let backup = if std::fs::symlink_metadata(&root.destination).is_ok() {
+      let backup = staging_dir.join(format!("backup-{}", i));
+      if let Err(e) = std::fs::rename(&root.destination, &backup) {
+        rollback(committed);
+        return Err(format!(
+          "Failed to move aside {}: {}",
+          root.destination.display(),
+          e
+        ));
+      }
+      Some(backup)
+    } else {
+      None
+    };
+    if let Err(e) = std::fs::rename(&root.staged, &root.destination) {
+      committed.push((&root.destination, backup));
+      rollback(committed);
+      return Err(format!(
+        "Failed to move {} into place: {}",
+        root.destination.display(),
+        e
+      ));
+    }
+    committed.push((&root.destination, backup));
+  }
+  Ok(())
+}
+
+fn rollback(committed: Vec<(&Path, Option<PathBuf>)>) {
+  for (destination, backup) in committed.into_iter().rev() {
+    let removal = match std::fs::symlink_metadata(destination) {
+      Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(destination),
+      Ok(_) => std::fs::remove_file(destination),
+      Err(_) => Ok(()),
+    };
+    let restoration = removal.and_then(|()| match backup {
+      Some(backup) => std::fs::rename(backup, destination),
+      None => Ok(()),
+    });
+    if let Err(e) = restoration {
+      warn!(
+        "Failed to roll back the materialization of {}: {}",
+        destination.display(),
+        e
+      );
+    }
+  }
+}
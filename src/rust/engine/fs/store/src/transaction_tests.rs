@@ -0,0 +1,87 @@
+use tempfile::TempDir;
+
+use crate::transaction::{commit, remove_stale_staging_dirs, StagedRoot, STAGING_DIR_PREFIX};
+
+#[test]
+fn commit_replaces_roots() {
+  let staging_dir = TempDir::new().unwrap();
+  let destination_dir = TempDir::new().unwrap();
+  std::fs::write(staging_dir.path().join("0"), "new").unwrap();
+  std::fs::create_dir(staging_dir.path().join("1")).unwrap();
+  std::fs::write(destination_dir.path().join("file"), "old").unwrap();
+
+  commit(
+    staging_dir.path(),
+    &[
+      StagedRoot {
+        staged: staging_dir.path().join("0"),
+        destination: destination_dir.path().join("file"),
+      },
+      StagedRoot {
+        staged: staging_dir.path().join("1"),
+        destination: destination_dir.path().join("dir"),
+      },
+    ],
+  )
+  .unwrap();
+
+  assert_eq!(
+    std::fs::read_to_string(destination_dir.path().join("file")).unwrap(),
+    "new"
+  );
+  assert!(destination_dir.path().join("dir").is_dir());
+}
+
+#[test]
+fn commit_rolls_back_on_failure() {
+  let staging_dir = TempDir::new().unwrap();
+  let destination_dir = TempDir::new().unwrap();
+  std::fs::write(staging_dir.path().join("0"), "new").unwrap();
+  std::fs::write(staging_dir.path().join("1"), "new").unwrap();
+  std::fs::write(destination_dir.path().join("file"), "old").unwrap();
+
+  // The second root fails to be moved into place, because its parent directory does not exist.
+  commit(
+    staging_dir.path(),
+    &[
+      StagedRoot {
+        staged: staging_dir.path().join("0"),
+        destination: destination_dir.path().join("file"),
+      },
+      StagedRoot {
+        staged: staging_dir.path().join("1"),
+        destination: destination_dir.path().join("missing").join("file"),
+      },
+    ],
+  )
+  .expect_err("Want error committing");
+
+  assert_eq!(
+    std::fs::read_to_string(destination_dir.path().join("file")).unwrap(),
+    "old"
+  );
+  assert!(!destination_dir.path().join("missing").exists());
+}
+
+#[test]
+fn remove_stale_staging_dirs_of_exited_processes() {
+  let destination_dir = TempDir::new().unwrap();
+  let mut child = std::process::Command::new("true").spawn().unwrap();
+  let exited_pid = child.id();
+  child.wait().unwrap();
+
+  let stale = format!("{}{}-abc", STAGING_DIR_PREFIX, exited_pid);
+  let live = format!("{}{}-def", STAGING_DIR_PREFIX, std::process::id());
+  for name in &[stale.as_str(), live.as_str(), "unrelated"] {
+    std::fs::create_dir(destination_dir.path().join(name)).unwrap();
+  }
+
+  remove_stale_staging_dirs(destination_dir.path());
+
+  let mut remaining = std::fs::read_dir(destination_dir.path())
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+    .collect::<Vec<_>>();
+  remaining.sort();
+  assert_eq!(remaining, vec![live, "unrelated".to_owned()]);
+}
@@ -206,6 +206,33 @@ impl<N: Node> EntryState<N> {
   }
 }
 
+///
+/// The coarse state of an Entry, as reported by `Graph::node_counts`.
+///
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum NodeState {
+  /// Not yet requested, or cleared or interrupted by invalidation: will run when next requested.
+  NotStarted,
+  /// Running, or waiting for its dependencies.
+  Running,
+  /// Completed, and clean for the current run.
+  Completed,
+  /// Completed, but dirtied by invalidation (or uncacheable, and completed in an earlier run): its
+  /// dependencies will be checked when it is next requested.
+  Dirty,
+}
+
+impl NodeState {
+  pub fn name(&self) -> &'static str {
+    match self {
+      NodeState::NotStarted => "not_started",
+      NodeState::Running => "running",
+      NodeState::Completed => "completed",
+      NodeState::Dirty => "dirty",
+    }
+  }
+}
+
 ///
 /// An Entry and its adjacencies.
 ///
@@ -754,6 +781,29 @@ impl<N: Node> Entry<N> {
     }
   }
 
+  ///
+  /// The current RunToken and Generation of this Entry, as integers.
+  ///
+  pub(crate) fn versions(&self) -> (u32, u32) {
+    match *self.state.lock() {
+      EntryState::NotStarted {
+        run_token,
+        generation,
+        ..
+      }
+      | EntryState::Running {
+        run_token,
+        generation,
+        ..
+      }
+      | EntryState::Completed {
+        run_token,
+        generation,
+        ..
+      } => (run_token.0, generation.0),
+    }
+  }
+
   pub fn is_started(&self) -> bool {
     match *self.state.lock() {
       EntryState::NotStarted { .. } => false,
@@ -768,6 +818,18 @@ impl<N: Node> Entry<N> {
     }
   }
 
+  ///
+  /// The coarse state of this Entry, for introspection.
+  ///
+  pub fn node_state(&self, context: &N::Context) -> NodeState {
+    match *self.state.lock() {
+      EntryState::NotStarted { .. } => NodeState::NotStarted,
+      EntryState::Running { .. } => NodeState::Running,
+      EntryState::Completed { ref result, .. } if result.is_clean(context) => NodeState::Completed,
+      EntryState::Completed { .. } => NodeState::Dirty,
+    }
+  }
+
   pub fn is_clean(&self, context: &N::Context) -> bool {
     match *self.state.lock() {
       EntryState::NotStarted {
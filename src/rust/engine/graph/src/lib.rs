@@ -32,15 +32,15 @@
 pub mod entry;
 mod node;
 
-pub use crate::entry::{Entry, EntryState};
+pub use crate::entry::{Entry, EntryState, NodeState};
 use crate::entry::{Generation, NodeResult, RunToken};
 
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
-use std::hash::BuildHasherDefault;
+use std::hash::{BuildHasherDefault, Hash};
 use std::io::{self, BufWriter, Write};
 use std::path::Path;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_value::AsyncValueSender;
 use fnv::FnvHasher;
@@ -64,6 +64,21 @@ pub struct InvalidationResult {
   pub dirtied: usize,
 }
 
+///
+/// A lightweight record of a Node which was reachable from the roots of a run: see
+/// `Graph::run_graph`.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RunGraphEntry<N: Node> {
+  pub node: N,
+  /// Incremented whenever the Node re-runs.
+  pub run_token: u32,
+  /// Incremented whenever the output of the Node changes.
+  pub generation: u32,
+  /// The indexes of the dependencies of this Node in the result of `Graph::run_graph`.
+  pub dependencies: Vec<usize>,
+}
+
 type Nodes<N> = HashMap<N, EntryId>;
 
 struct InnerGraph<N: Node> {
@@ -472,6 +487,23 @@ impl<N: Node> InnerGraph<N> {
 pub struct Graph<N: Node> {
   inner: Mutex<InnerGraph<N>>,
   invalidation_delay: Duration,
+  invalidation_epoch: Mutex<InvalidationEpoch>,
+}
+
+///
+/// The maximum factor by which the quiet period that a request interrupted by invalidation waits
+/// for is backed off: see `Graph::await_quiescence`.
+///
+const MAX_INVALIDATION_BACKOFF_FACTOR: u32 = 16;
+
+///
+/// Counts the invalidations of a Graph which actually cleared or dirtied Nodes, so that requests
+/// which are interrupted by invalidation can restart from a consistent epoch.
+///
+#[derive(Default)]
+struct InvalidationEpoch {
+  epoch: u64,
+  last_invalidated: Option<Instant>,
 }
 
 impl<N: Node> Graph<N> {
@@ -487,6 +519,7 @@ impl<N: Node> Graph<N> {
     Graph {
       inner: Mutex::new(inner),
       invalidation_delay,
+      invalidation_epoch: Mutex::default(),
     }
   }
 
@@ -549,6 +582,7 @@ impl<N: Node> Graph<N> {
     if dst_retry {
       // Retry the dst a number of times to handle Node invalidation.
       let context = context.clone();
+      let mut attempt = 0;
       loop {
         match entry.get_node_result(&context, entry_id).await {
           Ok(r) => break Ok(r),
@@ -558,10 +592,12 @@ impl<N: Node> Graph<N> {
               inner.unsafe_entry_for_id(entry_id).node().clone()
             };
             info!(
-              "Filesystem changed during run: retrying `{}` in {:?}...",
-              node, self.invalidation_delay
+              "Filesystem changed during run: retrying `{}` once changes have settled...",
+              node
             );
-            sleep(self.invalidation_delay).await;
+            let epoch = self.await_quiescence(attempt).await;
+            debug!("Retrying `{}` in invalidation epoch {}.", node, epoch);
+            attempt += 1;
             continue;
           }
           Err(other_err) => break Err(other_err),
@@ -862,7 +898,51 @@ impl<N: Node> Graph<N> {
 
   pub fn invalidate_from_roots<P: Fn(&N) -> bool>(&self, predicate: P) -> InvalidationResult {
     let mut inner = self.inner.lock();
-    inner.invalidate_from_roots(predicate)
+    let result = inner.invalidate_from_roots(predicate);
+    if result.cleared + result.dirtied > 0 {
+      let mut invalidation_epoch = self.invalidation_epoch.lock();
+      invalidation_epoch.epoch += 1;
+      invalidation_epoch.last_invalidated = Some(Instant::now());
+    }
+    result
+  }
+
+  ///
+  /// The number of invalidations which have cleared or dirtied Nodes in this Graph.
+  ///
+  pub fn invalidation_epoch(&self) -> u64 {
+    self.invalidation_epoch.lock().epoch
+  }
+
+  ///
+  /// Waits until no invalidation has occurred for a quiet period, and then returns the current
+  /// invalidation epoch.
+  ///
+  /// A request which is interrupted by invalidation waits for invalidation to settle before it
+  /// restarts, rather than restarting (and being interrupted again) repeatedly during a storm of
+  /// invalidation. The quiet period starts at the `invalidation_delay`, and is backed off
+  /// exponentially for each consecutive `attempt`.
+  ///
+  async fn await_quiescence(&self, attempt: u32) -> u64 {
+    let factor = 2_u32
+      .saturating_pow(attempt)
+      .min(MAX_INVALIDATION_BACKOFF_FACTOR);
+    let quiet_period = self.invalidation_delay * factor;
+    loop {
+      let (epoch, since_last_invalidated) = {
+        let invalidation_epoch = self.invalidation_epoch.lock();
+        (
+          invalidation_epoch.epoch,
+          invalidation_epoch
+            .last_invalidated
+            .map(|last_invalidated| last_invalidated.elapsed()),
+        )
+      };
+      match since_last_invalidated {
+        Some(elapsed) if elapsed < quiet_period => sleep(quiet_period - elapsed).await,
+        _ => return epoch,
+      }
+    }
   }
 
   pub fn visualize<V: NodeVisualizer<N>>(
@@ -876,6 +956,71 @@ impl<N: Node> Graph<N> {
     inner.visualize(visualizer, roots, path, context)
   }
 
+  ///
+  /// Counts the Nodes in the Graph by their type (as computed by `node_type`) and state. This
+  /// takes the Graph lock, but only briefly locks each Entry, and so is cheap enough to call
+  /// periodically.
+  ///
+  pub fn node_counts<K: Eq + Hash>(
+    &self,
+    context: &N::Context,
+    node_type: impl Fn(&N) -> K,
+  ) -> HashMap<(K, NodeState), usize> {
+    let inner = self.inner.lock();
+    let mut counts = HashMap::new();
+    for &id in inner.nodes.values() {
+      let entry = inner.unsafe_entry_for_id(id);
+      *counts
+        .entry((node_type(entry.node()), entry.node_state(context)))
+        .or_insert(0) += 1;
+    }
+    counts
+  }
+
+  ///
+  /// Records the Nodes which are reachable from the given roots (in any state), along with their
+  /// current versions and dependencies.
+  ///
+  /// Because RunTokens and Generations only ever increase, the records of two runs against the
+  /// same Graph can be compared to determine which Nodes re-ran and which changed between them.
+  ///
+  pub fn run_graph(&self, roots: &[N]) -> Vec<RunGraphEntry<N>> {
+    let inner = self.inner.lock();
+    let root_ids = roots
+      .iter()
+      .filter_map(|node| inner.entry_id(node))
+      .cloned()
+      .collect();
+    let entry_ids = inner
+      .walk(root_ids, Direction::Outgoing, |_| false)
+      .collect::<Vec<_>>();
+    let indexes = entry_ids
+      .iter()
+      .enumerate()
+      .map(|(index, &id)| (id, index))
+      .collect::<HashMap<_, _>>();
+    entry_ids
+      .into_iter()
+      .map(|id| {
+        let entry = inner.unsafe_entry_for_id(id);
+        let (run_token, generation) = entry.versions();
+        let mut dependencies = inner
+          .pg
+          .neighbors_directed(id, Direction::Outgoing)
+          .filter_map(|dep_id| indexes.get(&dep_id).cloned())
+          .collect::<Vec<_>>();
+        dependencies.sort_unstable();
+        dependencies.dedup();
+        RunGraphEntry {
+          node: entry.node().clone(),
+          run_token,
+          generation,
+          dependencies,
+        }
+      })
+      .collect()
+  }
+
   pub fn visit_live_reachable(
     &self,
     roots: &[N],
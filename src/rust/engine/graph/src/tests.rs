@@ -13,7 +13,9 @@ use parking_lot::Mutex;
 use rand::{self, Rng};
 use tokio::time::{error::Elapsed, sleep, timeout};
 
-use crate::{EntryId, Graph, InvalidationResult, Node, NodeContext, NodeError, Stats};
+use crate::{
+  EntryId, Graph, InvalidationResult, Node, NodeContext, NodeError, NodeState, RunGraphEntry, Stats,
+};
 
 #[tokio::test]
 async fn create() {
@@ -25,6 +27,40 @@ async fn create() {
   );
 }
 
+#[tokio::test]
+async fn run_graph() {
+  let graph = Arc::new(Graph::new());
+  let context = TContext::new(graph.clone());
+  graph.create(TNode::new(2), &context).await.unwrap();
+  let versions = |run_graph: Vec<RunGraphEntry<TNode>>| {
+    run_graph
+      .into_iter()
+      .map(|e| (e.node, e.run_token, e.generation, e.dependencies))
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(
+    versions(graph.run_graph(&[TNode::new(2)])),
+    vec![
+      (TNode::new(2), 1, 1, vec![1]),
+      (TNode::new(1), 1, 1, vec![2]),
+      (TNode::new(0), 1, 1, vec![]),
+    ]
+  );
+
+  // Clear the middle Node: it re-runs and its dependent is cleaned, which advances both of their
+  // RunTokens, but neither of their outputs (and so Generations) change.
+  graph.invalidate_from_roots(|&TNode(n, _)| n == 1);
+  graph.create(TNode::new(2), &context).await.unwrap();
+  assert_eq!(
+    versions(graph.run_graph(&[TNode::new(2)])),
+    vec![
+      (TNode::new(2), 2, 1, vec![1]),
+      (TNode::new(1), 3, 1, vec![2]),
+      (TNode::new(0), 1, 1, vec![]),
+    ]
+  );
+}
+
 #[tokio::test]
 async fn invalidate_and_clean() {
   let graph = Arc::new(Graph::new());
@@ -60,6 +96,39 @@ async fn invalidate_and_clean() {
   );
 }
 
+#[tokio::test]
+async fn node_counts() {
+  let graph = Arc::new(Graph::new());
+  let context = TContext::new(graph.clone());
+  let counts = |graph: &Graph<TNode>| {
+    let mut counts = graph
+      .node_counts(&context, |_| "TNode")
+      .into_iter()
+      .map(|((node_type, state), count)| (node_type, state, count))
+      .collect::<Vec<_>>();
+    counts.sort();
+    counts
+  };
+
+  assert_eq!(counts(&graph), vec![]);
+  assert_eq!(
+    graph.create(TNode::new(2), &context).await,
+    Ok(vec![T(0, 0), T(1, 0), T(2, 0)])
+  );
+  assert_eq!(counts(&graph), vec![("TNode", NodeState::Completed, 3)]);
+
+  // Clear the middle Node, which dirties the upper node.
+  graph.invalidate_from_roots(|&TNode(n, _)| n == 1);
+  assert_eq!(
+    counts(&graph),
+    vec![
+      ("TNode", NodeState::NotStarted, 1),
+      ("TNode", NodeState::Completed, 1),
+      ("TNode", NodeState::Dirty, 1),
+    ]
+  );
+}
+
 #[tokio::test]
 async fn invalidate_and_rerun() {
   let graph = Arc::new(Graph::new());
@@ -526,6 +595,43 @@ async fn canceled_on_invalidation() {
   );
 }
 
+#[tokio::test]
+async fn retry_awaits_quiescence() {
+  let _logger = env_logger::try_init();
+  let invalidation_delay = Duration::from_millis(50);
+  let graph = Arc::new(Graph::new_with_invalidation_delay(invalidation_delay));
+  let context = TContext::new(graph.clone());
+
+  // With no invalidation, there is nothing to wait for.
+  let start_time = Instant::now();
+  assert_eq!(graph.await_quiescence(0).await, 0);
+  assert!(start_time.elapsed() < invalidation_delay);
+
+  // Invalidate repeatedly, at an interval shorter than the invalidation_delay: quiescence should
+  // only be reached once the invalidation has stopped.
+  let iterations = 5;
+  let sleep_per_invalidation = invalidation_delay / 2;
+  let graph2 = graph.clone();
+  let context2 = context.clone();
+  let invalidator = tokio::spawn(async move {
+    for _ in 0..iterations {
+      graph2.create(TNode::new(2), &context2).await.unwrap();
+      graph2.invalidate_from_roots(|&TNode(n, _)| n == 0);
+      sleep(sleep_per_invalidation).await;
+    }
+  });
+  sleep(sleep_per_invalidation / 2).await;
+  let start_time = Instant::now();
+  assert_eq!(graph.await_quiescence(0).await, iterations);
+  assert!(start_time.elapsed() >= sleep_per_invalidation * (iterations as u32 - 1));
+  assert_eq!(graph.invalidation_epoch(), iterations);
+  invalidator.await.unwrap();
+
+  // Invalidation which does not clear or dirty anything does not start a new epoch.
+  graph.invalidate_from_roots(|&TNode(n, _)| n == 0);
+  assert_eq!(graph.invalidation_epoch(), iterations);
+}
+
 #[tokio::test]
 async fn canceled_on_loss_of_interest() {
   let _logger = env_logger::try_init();
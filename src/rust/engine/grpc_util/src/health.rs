@@ -0,0 +1,177 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use tonic::transport::Uri;
+
+///
+/// How long a warm-up may take before the endpoint is considered to be unhealthy.
+///
+pub const WARM_UP_TIMEOUT: Duration = Duration::from_secs(30);
+
+///
+/// The result of the most recent warm-up of a RemoteEndpoint.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EndpointStatus {
+  /// The endpoint has not been warmed up (or a warm-up is in progress).
+  Unknown,
+  /// The endpoint was resolved and connected to, and responded to a probe in the given time.
+  Healthy { latency: Duration },
+  /// The endpoint could not be resolved, connected to, or probed.
+  Unhealthy { error: String },
+}
+
+impl EndpointStatus {
+  pub fn name(&self) -> &'static str {
+    match self {
+      EndpointStatus::Unknown => "unknown",
+      EndpointStatus::Healthy { .. } => "healthy",
+      EndpointStatus::Unhealthy { .. } => "unhealthy",
+    }
+  }
+}
+
+///
+/// A cheap request to a remote endpoint, which must be made using the same `Channel` as the client
+/// that it is warming up, so that the connection it establishes is the one that will be reused.
+///
+pub type Probe = Arc<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+///
+/// A remote endpoint, which can be eagerly resolved and connected to (rather than lazily, by the
+/// first request which uses it), and which tracks the result of doing so.
+///
+#[derive(Clone)]
+pub struct RemoteEndpoint {
+  name: String,
+  address: String,
+  probe: Probe,
+  status: Arc<Mutex<EndpointStatus>>,
+}
+
+impl fmt::Debug for RemoteEndpoint {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("RemoteEndpoint")
+      .field("name", &self.name)
+      .field("address", &self.address)
+      .field("status", &self.status())
+      .finish()
+  }
+}
+
+impl RemoteEndpoint {
+  pub fn new(name: &str, address: &str, probe: Probe) -> RemoteEndpoint {
+    RemoteEndpoint {
+      name: name.to_owned(),
+      address: address.to_owned(),
+      probe,
+      status: Arc::new(Mutex::new(EndpointStatus::Unknown)),
+    }
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn address(&self) -> &str {
+    &self.address
+  }
+
+  pub fn status(&self) -> EndpointStatus {
+    self.status.lock().unwrap().clone()
+  }
+
+  ///
+  /// Resolves the address of the endpoint, and then probes it in order to establish a connection.
+  /// The outcome is recorded as the status of the endpoint, and returned.
+  ///
+  pub async fn warm_up(&self) -> EndpointStatus {
+    let start = Instant::now();
+    let result = tokio::time::timeout(WARM_UP_TIMEOUT, async {
+      resolve(&self.address).await?;
+      (self.probe)().await
+    })
+    .await
+    .unwrap_or_else(|_| Err(format!("Timed out after {:?}", WARM_UP_TIMEOUT)));
+    let status = match result {
+      Ok(()) => EndpointStatus::Healthy {
+        latency: start.elapsed(),
+      },
+      Err(error) => EndpointStatus::Unhealthy { error },
+    };
+    *self.status.lock().unwrap() = status.clone();
+    status
+  }
+}
+
+///
+/// Resolves the host of the given address, in order to fail fast (and clearly) for unresolvable
+/// hosts, and to populate any caching resolver before the first request.
+///
+async fn resolve(address: &str) -> Result<(), String> {
+  let uri = Uri::try_from(address).map_err(|e| format!("Invalid address: {}", e))?;
+  let host = uri
+    .host()
+    .ok_or_else(|| format!("Address {} has no host.", address))?;
+  let port = uri.port_u16().unwrap_or_else(|| {
+    if uri.scheme_str() == Some("https") {
+      443
+    } else {
+      80
+    }
+  });
+  let mut addrs = tokio::net::lookup_host((host, port))
+    .await
+    .map_err(|e| format!("Failed to resolve {}: {}", host, e))?;
+  if addrs.next().is_none() {
+    return Err(format!("{} did not resolve to any addresses.", host));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{EndpointStatus, RemoteEndpoint};
+  use futures::future::{self, FutureExt};
+  use std::sync::Arc;
+
+  #[tokio::test]
+  async fn warm_up_records_status() {
+    let endpoint = RemoteEndpoint::new(
+      "store",
+      "http://127.0.0.1:1234",
+      Arc::new(|| future::ready(Ok(())).boxed()),
+    );
+    assert_eq!(endpoint.status(), EndpointStatus::Unknown);
+    assert_eq!(endpoint.warm_up().await.name(), "healthy");
+    assert_eq!(endpoint.status().name(), "healthy");
+  }
+
+  #[tokio::test]
+  async fn warm_up_probe_failure() {
+    let endpoint = RemoteEndpoint::new(
+      "store",
+      "http://127.0.0.1:1234",
+      Arc::new(|| future::ready(Err("Connection refused".to_owned())).boxed()),
+    );
+    assert_eq!(
+      endpoint.warm_up().await,
+      EndpointStatus::Unhealthy {
+        error: "Connection refused".to_owned()
+      }
+    );
+  }
+
+  #[tokio::test]
+  async fn warm_up_invalid_address() {
+    let endpoint = RemoteEndpoint::new(
+      "store",
+      "not a uri",
+      Arc::new(|| future::ready(Ok(())).boxed()),
+    );
+    assert_eq!(endpoint.warm_up().await.name(), "unhealthy");
+  }
+}
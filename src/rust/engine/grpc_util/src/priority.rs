@@ -0,0 +1,243 @@
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::oneshot;
+use tokio::task_local;
+
+///
+/// The priority class of a request to a remote server. When the number of concurrent requests is
+/// limited, waiting requests of a higher class are admitted before those of lower classes.
+///
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum RequestPriority {
+  /// Speculative lookups and background cache warming, whose results may never be needed.
+  Background,
+  /// Requests made while computing a result for the user.
+  Normal,
+  /// Requests which the user is directly waiting on, such as materializing files.
+  Interactive,
+}
+
+task_local! {
+  static TASK_REQUEST_PRIORITY: RequestPriority;
+}
+
+///
+/// Runs the given Future with the given priority for any requests that it makes. Note that (as with
+/// all task locals) the priority does not propagate into spawned tasks.
+///
+pub async fn with_request_priority<F: Future>(priority: RequestPriority, f: F) -> F::Output {
+  TASK_REQUEST_PRIORITY.scope(priority, f).await
+}
+
+///
+/// The priority of requests made by the current task, which defaults to `Normal`.
+///
+pub fn current_request_priority() -> RequestPriority {
+  TASK_REQUEST_PRIORITY
+    .try_with(|priority| *priority)
+    .unwrap_or(RequestPriority::Normal)
+}
+
+///
+/// A semaphore which limits the number of concurrent requests, and which admits waiting requests in
+/// order of their priority (and then in the order that they arrived).
+///
+#[derive(Debug)]
+pub struct PrioritySemaphore {
+  state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+  available: usize,
+  waiters: BinaryHeap<Waiter>,
+  next_sequence: u64,
+}
+
+#[derive(Debug)]
+struct Waiter {
+  priority: RequestPriority,
+  sequence: u64,
+  sender: oneshot::Sender<PriorityPermit>,
+}
+
+impl PartialEq for Waiter {
+  fn eq(&self, other: &Self) -> bool {
+    self.priority == other.priority && self.sequence == other.sequence
+  }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Waiter {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    // Higher priorities first, and then earlier arrivals first.
+    self
+      .priority
+      .cmp(&other.priority)
+      .then_with(|| other.sequence.cmp(&self.sequence))
+  }
+}
+
+impl PrioritySemaphore {
+  pub fn new(permits: usize) -> Arc<PrioritySemaphore> {
+    Arc::new(PrioritySemaphore {
+      state: Mutex::new(State {
+        available: std::cmp::max(1, permits),
+        waiters: BinaryHeap::new(),
+        next_sequence: 0,
+      }),
+    })
+  }
+
+  ///
+  /// Waits for a permit to make a request with the given priority. The request may proceed while
+  /// the returned permit is held.
+  ///
+  pub async fn acquire(self: &Arc<Self>, priority: RequestPriority) -> PriorityPermit {
+    let receiver = {
+      let mut state = self.state.lock().unwrap();
+      if state.available > 0 && state.waiters.is_empty() {
+        state.available -= 1;
+        return PriorityPermit {
+          semaphore: Some(self.clone()),
+        };
+      }
+      let (sender, receiver) = oneshot::channel();
+      let sequence = state.next_sequence;
+      state.next_sequence += 1;
+      state.waiters.push(Waiter {
+        priority,
+        sequence,
+        sender,
+      });
+      receiver
+    };
+    // NB: If this Future is dropped after a permit has been sent, the permit is dropped (and so
+    // released) along with the receiver.
+    receiver
+      .await
+      .expect("A PrioritySemaphore waiter was dropped without being sent a permit.")
+  }
+
+  fn release(self: &Arc<Self>) {
+    let mut state = self.state.lock().unwrap();
+    while let Some(waiter) = state.waiters.pop() {
+      let permit = PriorityPermit {
+        semaphore: Some(self.clone()),
+      };
+      match waiter.sender.send(permit) {
+        Ok(()) => return,
+        Err(mut permit) => {
+          // The waiter gave up: defuse the permit (since we already hold the lock), and try the
+          // next waiter.
+          permit.semaphore = None;
+        }
+      }
+    }
+    state.available += 1;
+  }
+}
+
+///
+/// A permit to make a request, which is released when dropped.
+///
+#[derive(Debug)]
+pub struct PriorityPermit {
+  // NB: Only None for a permit which was never handed out.
+  semaphore: Option<Arc<PrioritySemaphore>>,
+}
+
+impl Drop for PriorityPermit {
+  fn drop(&mut self) {
+    if let Some(semaphore) = self.semaphore.take() {
+      semaphore.release();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    current_request_priority, with_request_priority, PrioritySemaphore, RequestPriority,
+  };
+  use std::sync::{Arc, Mutex};
+  use std::time::Duration;
+
+  #[tokio::test]
+  async fn admits_waiters_by_priority() {
+    let semaphore = PrioritySemaphore::new(1);
+    let held = semaphore.acquire(RequestPriority::Normal).await;
+
+    let admitted = Arc::new(Mutex::new(vec![]));
+    let mut handles = vec![];
+    for priority in &[
+      RequestPriority::Background,
+      RequestPriority::Normal,
+      RequestPriority::Interactive,
+      RequestPriority::Background,
+    ] {
+      let semaphore = semaphore.clone();
+      let admitted = admitted.clone();
+      let priority = *priority;
+      handles.push(tokio::spawn(async move {
+        let _permit = semaphore.acquire(priority).await;
+        admitted.lock().unwrap().push(priority);
+      }));
+      // Ensure that the waiters arrive in order.
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    drop(held);
+    for handle in handles {
+      handle.await.unwrap();
+    }
+    assert_eq!(
+      *admitted.lock().unwrap(),
+      vec![
+        RequestPriority::Interactive,
+        RequestPriority::Normal,
+        RequestPriority::Background,
+        RequestPriority::Background,
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn abandoned_waiters_do_not_leak_permits() {
+    let semaphore = PrioritySemaphore::new(1);
+    let held = semaphore.acquire(RequestPriority::Normal).await;
+    let abandoned = tokio::time::timeout(
+      Duration::from_millis(10),
+      semaphore.acquire(RequestPriority::Interactive),
+    )
+    .await;
+    assert!(abandoned.is_err());
+
+    drop(held);
+    tokio::time::timeout(
+      Duration::from_secs(1),
+      semaphore.acquire(RequestPriority::Background),
+    )
+    .await
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn task_priority() {
+    assert_eq!(current_request_priority(), RequestPriority::Normal);
+    let priority = with_request_priority(RequestPriority::Interactive, async {
+      current_request_priority()
+    })
+    .await;
+    assert_eq!(priority, RequestPriority::Interactive);
+  }
+}
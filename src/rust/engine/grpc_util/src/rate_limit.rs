@@ -0,0 +1,110 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+///
+/// A token bucket, which allows up to `rate_per_sec` units (requests, bytes, etc) per second on
+/// average, with bursts of up to one second's worth of units.
+///
+/// Callers which exceed the budget are delayed rather than rejected. Because units are reserved
+/// before waiting, requests larger than the burst size are still allowed through (after a
+/// proportionally longer delay), and concurrent callers are delayed in the order that they arrived.
+///
+#[derive(Debug)]
+pub struct RateLimiter {
+  rate_per_sec: f64,
+  state: Mutex<RateLimiterState>,
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+  // NB: May be negative, when units have been reserved ahead of their availability.
+  available: f64,
+  last_refill: Instant,
+}
+
+impl RateLimiter {
+  pub fn new(rate_per_sec: usize) -> RateLimiter {
+    let rate_per_sec = std::cmp::max(1, rate_per_sec) as f64;
+    RateLimiter {
+      rate_per_sec,
+      state: Mutex::new(RateLimiterState {
+        available: rate_per_sec,
+        last_refill: Instant::now(),
+      }),
+    }
+  }
+
+  ///
+  /// Reserves the given number of units, and returns how long the caller must wait before using
+  /// them.
+  ///
+  pub fn reserve(&self, units: usize) -> Duration {
+    let mut state = self.state.lock().unwrap();
+    let now = Instant::now();
+    let refill = now.duration_since(state.last_refill).as_secs_f64() * self.rate_per_sec;
+    state.available = (state.available + refill).min(self.rate_per_sec);
+    state.last_refill = now;
+
+    state.available -= units as f64;
+    if state.available >= 0.0 {
+      Duration::default()
+    } else {
+      Duration::from_secs_f64(-state.available / self.rate_per_sec)
+    }
+  }
+
+  ///
+  /// Reserves the given number of units and waits until they are available, returning the amount
+  /// of time spent waiting.
+  ///
+  pub async fn acquire(&self, units: usize) -> Duration {
+    let delay = self.reserve(units);
+    if delay > Duration::default() {
+      tokio::time::sleep(delay).await;
+    }
+    delay
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::RateLimiter;
+  use std::time::Duration;
+
+  #[test]
+  fn allows_bursts_within_budget() {
+    let limiter = RateLimiter::new(10);
+    assert_eq!(limiter.reserve(4), Duration::default());
+    assert_eq!(limiter.reserve(6), Duration::default());
+  }
+
+  #[test]
+  fn delays_once_budget_is_exhausted() {
+    let limiter = RateLimiter::new(10);
+    assert_eq!(limiter.reserve(10), Duration::default());
+    let delay = limiter.reserve(5);
+    assert!(
+      delay > Duration::from_millis(400) && delay <= Duration::from_millis(500),
+      "Unexpected delay: {:?}",
+      delay
+    );
+    // Subsequent callers queue behind the earlier reservation.
+    let delay = limiter.reserve(5);
+    assert!(
+      delay > Duration::from_millis(900) && delay <= Duration::from_millis(1000),
+      "Unexpected delay: {:?}",
+      delay
+    );
+  }
+
+  #[test]
+  fn allows_requests_larger_than_burst() {
+    let limiter = RateLimiter::new(10);
+    let delay = limiter.reserve(30);
+    assert!(
+      delay > Duration::from_millis(1900) && delay <= Duration::from_millis(2000),
+      "Unexpected delay: {:?}",
+      delay
+    );
+  }
+}
@@ -0,0 +1,366 @@
+use std::convert::TryFrom;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ::prost::Message;
+use tokio::sync::Notify;
+use tonic::{Code, Status};
+
+/// The delay before the first retry of a request for which the server gave no hint.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// The maximum delay between retries of a request for which the server gave no hint.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+const RETRY_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.RetryInfo";
+
+///
+/// The subset of `google.rpc.Status` which is needed to find its `RetryInfo` detail. Defined here
+/// rather than depending on the generated REAPI protos.
+///
+#[derive(Clone, PartialEq, Message)]
+struct RpcStatus {
+  #[prost(message, repeated, tag = "3")]
+  details: Vec<prost_types::Any>,
+}
+
+///
+/// `google.rpc.RetryInfo`: the server's suggestion of how long a client should wait before
+/// retrying a request.
+///
+#[derive(Clone, PartialEq, Message)]
+struct RetryInfo {
+  #[prost(message, optional, tag = "1")]
+  retry_delay: Option<prost_types::Duration>,
+}
+
+///
+/// Returns the delay suggested by a `google.rpc.RetryInfo` among the given `google.rpc.Status`
+/// details, if there is one.
+///
+pub fn retry_delay_from_details(details: &[prost_types::Any]) -> Option<Duration> {
+  details
+    .iter()
+    .filter(|detail| detail.type_url == RETRY_INFO_TYPE_URL)
+    .find_map(|detail| RetryInfo::decode(&detail.value[..]).ok()?.retry_delay)
+    .map(|retry_delay| Duration::try_from(retry_delay).unwrap_or_default())
+}
+
+///
+/// Returns the delay suggested by a `google.rpc.RetryInfo` in the (binary) details of the given
+/// Status, if there is one.
+///
+pub fn retry_delay(status: &Status) -> Option<Duration> {
+  if status.details().is_empty() {
+    return None;
+  }
+  let rpc_status = RpcStatus::decode(status.details()).ok()?;
+  retry_delay_from_details(&rpc_status.details)
+}
+
+///
+/// True if a request which failed with the given Status may succeed if it is retried.
+///
+pub fn is_retryable(status: &Status) -> bool {
+  match status.code() {
+    Code::Aborted
+    | Code::Internal
+    | Code::ResourceExhausted
+    | Code::Unavailable
+    | Code::Unknown => true,
+    // A server which suggests a retry delay is explicitly inviting a retry.
+    _ => retry_delay(status).is_some(),
+  }
+}
+
+///
+/// Limits requests to a throttled server. When the server is overloaded (it responds with
+/// RESOURCE_EXHAUSTED, or suggests a retry delay), no new requests are started until the suggested
+/// delay has elapsed, and the number of concurrent requests is halved. Each subsequent successful
+/// request raises the limit by one, until it has recovered to the concurrency that we had when we
+/// were first throttled.
+///
+#[derive(Debug, Default)]
+pub struct RetryThrottle {
+  state: Mutex<RetryThrottleState>,
+  released: Notify,
+}
+
+#[derive(Debug, Default)]
+struct RetryThrottleState {
+  in_flight: usize,
+  // The maximum number of concurrent requests, or None if we are not currently throttled.
+  limit: Option<usize>,
+  // The number of concurrent requests when we were first throttled.
+  unthrottled_limit: usize,
+  // No new requests may start before this instant.
+  paused_until: Option<Instant>,
+}
+
+impl RetryThrottle {
+  pub fn new() -> Arc<RetryThrottle> {
+    Arc::new(RetryThrottle::default())
+  }
+
+  pub fn limit(&self) -> Option<usize> {
+    self.state.lock().unwrap().limit
+  }
+
+  pub fn in_flight(&self) -> usize {
+    self.state.lock().unwrap().in_flight
+  }
+
+  ///
+  /// Returns a permit to make a request, or (if one may not be made yet) the instant until which
+  /// requests are paused, if any.
+  ///
+  fn try_acquire(self: &Arc<Self>) -> Result<RetryPermit, Option<Instant>> {
+    let mut state = self.state.lock().unwrap();
+    if let Some(paused_until) = state.paused_until {
+      if Instant::now() < paused_until {
+        return Err(Some(paused_until));
+      }
+      state.paused_until = None;
+    }
+    if state.limit.map_or(false, |limit| state.in_flight >= limit) {
+      return Err(None);
+    }
+    state.in_flight += 1;
+    Ok(RetryPermit(self.clone()))
+  }
+
+  ///
+  /// Waits until a request may be made. The request may proceed while the returned permit is held.
+  ///
+  pub async fn acquire(self: &Arc<Self>) -> RetryPermit {
+    loop {
+      match self.try_acquire() {
+        Ok(permit) => return permit,
+        Err(Some(paused_until)) => {
+          tokio::time::sleep_until(tokio::time::Instant::from_std(paused_until)).await
+        }
+        // NB: Permits are released one at a time, but the limit may also be raised without a
+        // permit being released, so we poll as well.
+        Err(None) => {
+          let _ = tokio::time::timeout(Duration::from_millis(100), self.released.notified()).await;
+        }
+      }
+    }
+  }
+
+  ///
+  /// Records that the server is overloaded, and asked us to wait for the given delay before
+  /// retrying.
+  ///
+  pub fn throttled(&self, retry_delay: Duration) {
+    let mut state = self.state.lock().unwrap();
+    let current_limit = match state.limit {
+      Some(limit) => limit,
+      None => {
+        state.unthrottled_limit = std::cmp::max(1, state.in_flight);
+        state.unthrottled_limit
+      }
+    };
+    state.limit = Some(std::cmp::max(1, current_limit / 2));
+    let paused_until = Instant::now() + retry_delay;
+    if state
+      .paused_until
+      .map_or(true, |until| until < paused_until)
+    {
+      state.paused_until = Some(paused_until);
+    }
+    log::debug!(
+      "server is overloaded: pausing requests for {:?}, and then throttling to {} concurrent \
+       requests",
+      retry_delay,
+      state.limit.unwrap()
+    );
+  }
+
+  ///
+  /// Records a successful request, which raises the limit (if any).
+  ///
+  pub fn succeeded(&self) {
+    let mut state = self.state.lock().unwrap();
+    if let Some(limit) = state.limit {
+      if limit + 1 >= state.unthrottled_limit {
+        state.limit = None;
+      } else {
+        state.limit = Some(limit + 1);
+      }
+      self.released.notify_one();
+    }
+  }
+}
+
+///
+/// A slot for one concurrent request, which is released when dropped.
+///
+#[derive(Debug)]
+pub struct RetryPermit(Arc<RetryThrottle>);
+
+impl Drop for RetryPermit {
+  fn drop(&mut self) {
+    self.0.state.lock().unwrap().in_flight -= 1;
+    self.0.released.notify_one();
+  }
+}
+
+///
+/// Makes a request up to `attempts` times, while it fails with a retryable error. If the server
+/// suggests a retry delay, it is honored (by pausing all requests made via the given throttle),
+/// and otherwise the request is retried with an exponential backoff.
+///
+pub async fn retry_call<T, F, Fut>(
+  throttle: &Arc<RetryThrottle>,
+  attempts: usize,
+  mut f: F,
+) -> Result<T, Status>
+where
+  F: FnMut() -> Fut,
+  Fut: Future<Output = Result<T, Status>>,
+{
+  let mut attempt = 1;
+  loop {
+    let result = {
+      let _permit = throttle.acquire().await;
+      f().await
+    };
+    let status = match result {
+      Ok(value) => {
+        throttle.succeeded();
+        return Ok(value);
+      }
+      Err(status) => status,
+    };
+
+    let retry_delay = retry_delay(&status);
+    if status.code() == Code::ResourceExhausted || retry_delay.is_some() {
+      throttle.throttled(retry_delay.unwrap_or_default());
+    }
+    if attempt >= attempts || !is_retryable(&status) {
+      return Err(status);
+    }
+    if retry_delay.is_none() {
+      let backoff = RETRY_BASE_DELAY * 2_u32.pow(std::cmp::min(attempt, 16) as u32 - 1);
+      tokio::time::sleep(std::cmp::min(backoff, MAX_RETRY_DELAY)).await;
+    }
+    attempt += 1;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use std::time::{Duration, Instant};
+
+  use ::prost::Message;
+  use tonic::{Code, Status};
+
+  use super::{retry_call, retry_delay, RetryInfo, RetryThrottle, RpcStatus, RETRY_INFO_TYPE_URL};
+  use crate::prost::MessageExt;
+
+  fn throttled_status(code: Code, delay: Duration) -> Status {
+    let retry_info = RetryInfo {
+      retry_delay: Some(delay.into()),
+    };
+    let rpc_status = RpcStatus {
+      details: vec![prost_types::Any {
+        type_url: RETRY_INFO_TYPE_URL.to_owned(),
+        value: retry_info.to_bytes().to_vec(),
+      }],
+    };
+    Status::with_details(code, "slow down", rpc_status.to_bytes())
+  }
+
+  #[test]
+  fn parses_retry_delay() {
+    let status = throttled_status(Code::Unavailable, Duration::from_millis(1500));
+    assert_eq!(retry_delay(&status), Some(Duration::from_millis(1500)));
+    assert_eq!(retry_delay(&Status::unavailable("no hint")), None);
+    assert_eq!(
+      retry_delay(&Status::with_details(
+        Code::Unavailable,
+        "garbage",
+        vec![0xff, 0xff].into()
+      )),
+      None
+    );
+    // Sanity check that our subset of google.rpc.Status decodes as the full message would.
+    assert_eq!(
+      RpcStatus::decode(status.details()).unwrap().details.len(),
+      1
+    );
+  }
+
+  #[test]
+  fn throttle_backs_off_and_recovers() {
+    let throttle = RetryThrottle::new();
+    let mut permits = (0..4)
+      .map(|_| throttle.try_acquire().ok().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(throttle.limit(), None);
+
+    throttle.throttled(Duration::from_secs(60));
+    assert_eq!(throttle.limit(), Some(2));
+    // No requests may start while paused, even under the limit.
+    permits.truncate(1);
+    assert!(throttle.try_acquire().unwrap_err().is_some());
+
+    throttle.succeeded();
+    assert_eq!(throttle.limit(), Some(3));
+    throttle.succeeded();
+    assert_eq!(throttle.limit(), None);
+  }
+
+  #[tokio::test]
+  async fn retry_call_honors_retry_delay() {
+    let throttle = RetryThrottle::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let result = retry_call(&throttle, 3, || {
+      let calls = calls.clone();
+      async move {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+          Err(throttled_status(
+            Code::ResourceExhausted,
+            Duration::from_millis(300),
+          ))
+        } else {
+          Ok(42)
+        }
+      }
+    })
+    .await;
+    assert_eq!(result.unwrap(), 42);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    // The successful retry restored the original concurrency.
+    assert_eq!(throttle.limit(), None);
+  }
+
+  #[tokio::test]
+  async fn retry_call_gives_up() {
+    let throttle = RetryThrottle::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let result: Result<(), Status> = retry_call(&throttle, 2, || {
+      calls.fetch_add(1, Ordering::SeqCst);
+      async { Err(Status::unavailable("down")) }
+    })
+    .await;
+    assert_eq!(result.unwrap_err().code(), Code::Unavailable);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Errors which are not retryable are returned immediately.
+    calls.store(0, Ordering::SeqCst);
+    let result: Result<(), Status> = retry_call(&throttle, 5, || {
+      calls.fetch_add(1, Ordering::SeqCst);
+      async { Err(Status::not_found("missing")) }
+    })
+    .await;
+    assert_eq!(result.unwrap_err().code(), Code::NotFound);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+  }
+}
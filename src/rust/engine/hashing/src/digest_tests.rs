@@ -27,3 +27,38 @@ fn serialize_and_deserialize() {
     ],
   );
 }
+
+#[test]
+fn display() {
+  let digest = Digest::new(
+    Fingerprint::from_hex_string(
+      "0123456789abcdeffedcba98765432100000000000000000ffffffffffffffff",
+    )
+    .unwrap(),
+    42,
+  );
+  assert_eq!(digest.to_display_string(false), "0123456789ab:42");
+  assert_eq!(
+    digest.to_display_string(true),
+    "0123456789abcdeffedcba98765432100000000000000000ffffffffffffffff:42"
+  );
+}
+
+#[test]
+fn parse_displayed() {
+  assert_eq!(
+    Digest::parse_displayed("0123456789AB:42"),
+    Ok(("0123456789ab".to_owned(), 42))
+  );
+  assert_eq!(
+    Digest::parse_displayed("0123456789abcdeffedcba98765432100000000000000000ffffffffffffffff:42"),
+    Ok((
+      "0123456789abcdeffedcba98765432100000000000000000ffffffffffffffff".to_owned(),
+      42
+    ))
+  );
+  assert!(Digest::parse_displayed("0123456789ab").is_err());
+  assert!(Digest::parse_displayed(":42").is_err());
+  assert!(Digest::parse_displayed("0123456789xy:42").is_err());
+  assert!(Digest::parse_displayed("0123456789ab:big").is_err());
+}
@@ -41,6 +41,7 @@ use std::convert::TryFrom;
 use std::fmt;
 use std::io::{self, Write};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub const EMPTY_FINGERPRINT: Fingerprint = Fingerprint([
   0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14, 0x9a, 0xfb, 0xf4, 0xc8, 0x99, 0x6f, 0xb9, 0x24,
@@ -53,6 +54,20 @@ pub const EMPTY_DIGEST: Digest = Digest {
 
 pub const FINGERPRINT_SIZE: usize = 32;
 
+/// The number of hex characters of its fingerprint which an abbreviated Digest displays.
+pub const ABBREVIATED_HEX_LENGTH: usize = 12;
+
+static DISPLAY_FULL_DIGESTS: AtomicBool = AtomicBool::new(false);
+
+///
+/// Sets whether Digests are displayed (in errors, workunits and logs) with their full fingerprint,
+/// rather than abbreviated. Abbreviated Digests can be expanded with `Digest::parse_displayed`
+/// and a lookup in the store.
+///
+pub fn set_display_full_digests(full: bool) {
+  DISPLAY_FULL_DIGESTS.store(full, Ordering::Relaxed);
+}
+
 #[derive(Clone, Copy, Eq, Hash, PartialEq, Ord, PartialOrd)]
 pub struct Fingerprint(pub [u8; FINGERPRINT_SIZE]);
 
@@ -182,12 +197,25 @@ impl TryFrom<&str> for Fingerprint {
 /// It is equivalent to a Bazel Remote Execution Digest, but without the overhead (and awkward API)
 /// of needing to create an entire protobuf to pass around the two fields.
 ///
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub struct Digest {
   pub hash: Fingerprint,
   pub size_bytes: usize,
 }
 
+impl fmt::Display for Digest {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let full = DISPLAY_FULL_DIGESTS.load(Ordering::Relaxed);
+    write!(f, "{}", self.to_display_string(full))
+  }
+}
+
+impl fmt::Debug for Digest {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Digest<{}>", self)
+  }
+}
+
 impl Serialize for Digest {
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
   where
@@ -267,6 +295,48 @@ impl Digest {
 
     Digest::new(Fingerprint::from_bytes(hasher.finalize()), bytes.len())
   }
+
+  ///
+  /// Renders this Digest as `<fingerprint>:<size_bytes>`, with the fingerprint abbreviated to its
+  /// first `ABBREVIATED_HEX_LENGTH` hex characters unless `full` is set.
+  ///
+  pub fn to_display_string(&self, full: bool) -> String {
+    let hex = self.hash.to_hex();
+    let hex = if full {
+      &hex[..]
+    } else {
+      &hex[..ABBREVIATED_HEX_LENGTH]
+    };
+    format!("{}:{}", hex, self.size_bytes)
+  }
+
+  ///
+  /// Parses a (possibly abbreviated) Digest rendered by `to_display_string`, and returns the
+  /// (lowercase) hex prefix of its fingerprint, and its size.
+  ///
+  pub fn parse_displayed(displayed: &str) -> Result<(String, usize), String> {
+    let invalid = |reason: &str| {
+      format!(
+        "Invalid digest {:?}: {}. Expected `<fingerprint>:<size_bytes>`, where the fingerprint may \
+         be abbreviated.",
+        displayed, reason
+      )
+    };
+    let (hex, size_bytes) = match displayed.trim().rsplitn(2, ':').collect::<Vec<_>>()[..] {
+      [size_bytes, hex] => (hex, size_bytes),
+      _ => return Err(invalid("missing size")),
+    };
+    let size_bytes = size_bytes
+      .parse::<usize>()
+      .map_err(|e| invalid(&format!("invalid size: {}", e)))?;
+    if hex.is_empty() || hex.len() > FINGERPRINT_SIZE * 2 {
+      return Err(invalid("invalid fingerprint length"));
+    }
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+      return Err(invalid("fingerprint is not hex"));
+    }
+    Ok((hex.to_ascii_lowercase(), size_bytes))
+  }
 }
 
 ///
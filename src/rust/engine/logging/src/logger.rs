@@ -31,8 +31,10 @@ pub struct PantsLogger {
   use_color: AtomicBool,
   show_rust_3rdparty_logs: AtomicBool,
   show_target: AtomicBool,
+  log_by_workunit: AtomicBool,
   log_level_filters: Mutex<HashMap<String, log::LevelFilter>>,
   message_regex_filters: Mutex<Vec<Regex>>,
+  message_redactions: Mutex<Vec<Regex>>,
 }
 
 impl PantsLogger {
@@ -44,8 +46,10 @@ impl PantsLogger {
       show_rust_3rdparty_logs: AtomicBool::new(true),
       use_color: AtomicBool::new(false),
       show_target: AtomicBool::new(false),
+      log_by_workunit: AtomicBool::new(false),
       log_level_filters: Mutex::new(HashMap::new()),
       message_regex_filters: Mutex::new(Vec::new()),
+      message_redactions: Mutex::new(Vec::new()),
     }
   }
 
@@ -54,8 +58,10 @@ impl PantsLogger {
     show_rust_3rdparty_logs: bool,
     use_color: bool,
     show_target: bool,
+    log_by_workunit: bool,
     log_levels_by_target: HashMap<String, u64>,
     message_regex_filters: Vec<Regex>,
+    message_redactions: Vec<Regex>,
     log_file_path: PathBuf,
   ) -> Result<(), String> {
     let log_levels_by_target = log_levels_by_target
@@ -85,9 +91,13 @@ impl PantsLogger {
       .store(show_rust_3rdparty_logs, Ordering::SeqCst);
     *PANTS_LOGGER.log_level_filters.lock() = log_levels_by_target;
     *PANTS_LOGGER.message_regex_filters.lock() = message_regex_filters;
+    *PANTS_LOGGER.message_redactions.lock() = message_redactions;
     PANTS_LOGGER
       .show_target
       .store(show_target, Ordering::SeqCst);
+    PANTS_LOGGER
+      .log_by_workunit
+      .store(log_by_workunit, Ordering::SeqCst);
     *PANTS_LOGGER.log_file.lock() = {
       let log_file = OpenOptions::new()
         .create(true)
@@ -212,6 +222,9 @@ impl Log for PantsLogger {
       }
     }
 
+    // Scrub any secrets from the message before it is written anywhere.
+    let log_string = self.redact(log_string);
+
     let log_bytes = log_string.as_bytes();
 
     {
@@ -222,23 +235,54 @@ impl Log for PantsLogger {
       }
     }
 
+    // If the record was emitted while a workunit was running, attach it to that workunit (to be
+    // displayed along with it) rather than interleaving it into stdio, and record it in the pantsd
+    // log. Warnings and errors are always written to stdio as well.
+    if self.log_by_workunit.load(Ordering::SeqCst) {
+      if let Some(handle) = workunit_store::get_workunit_store_handle() {
+        if let Some(span_id) = handle.parent_id {
+          let message = self.redact(record.args().to_string());
+          if handle.store.record_log(span_id, level, message) && level > Level::Warn {
+            self.write_log_file(log_bytes);
+            return;
+          }
+        }
+      }
+    }
+
     // Attempt to write to stdio, and write to the pantsd log if we fail (either because we don't
     // have a valid stdio instance, or because of an error).
     let destination = stdio::get_destination();
     if stdio::Destination::write_stderr_raw(&destination, log_bytes).is_err() {
-      let mut maybe_file = self.log_file.lock();
-      if let Some(ref mut file) = *maybe_file {
-        match file.write_all(log_bytes) {
-          Ok(()) => (),
-          Err(e) => {
-            // If we've failed to write to stdio, but also to our log file, our only recourse is to
-            // try to write to a different file.
-            debug_log!("fatal.log", "Failed to write to log file {:?}: {}", file, e);
-          }
-        }
-      }
+      self.write_log_file(log_bytes);
     }
   }
 
   fn flush(&self) {}
 }
+
+impl PantsLogger {
+  fn redact(&self, message: String) -> String {
+    let message_redactions = self.message_redactions.lock();
+    message_redactions.iter().fold(message, |message, re| {
+      re.replace_all(&message, "<redacted>").into_owned()
+    })
+  }
+
+  ///
+  /// Writes to the pantsd log, which is used when stdio is unavailable.
+  ///
+  fn write_log_file(&self, log_bytes: &[u8]) {
+    let mut maybe_file = self.log_file.lock();
+    if let Some(ref mut file) = *maybe_file {
+      match file.write_all(log_bytes) {
+        Ok(()) => (),
+        Err(e) => {
+          // If we've failed to write to our log file, our only recourse is to try to write to a
+          // different file.
+          debug_log!("fatal.log", "Failed to write to log file {:?}: {}", file, e);
+        }
+      }
+    }
+  }
+}
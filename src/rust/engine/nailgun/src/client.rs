@@ -30,14 +30,20 @@
 use std::io;
 use std::net::Ipv4Addr;
 
+use bytes::Bytes;
 use futures::channel::mpsc;
 use futures::{try_join, SinkExt, Stream, StreamExt};
-use nails::execution::{stream_for, ChildInput, ChildOutput, ExitCode};
+use nails::execution::{ChildInput, ChildOutput, ExitCode};
 use nails::Config;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::signal::unix::{signal, Signal, SignalKind};
 
+use crate::stdio::{
+  Frame, DATA_FRAME_BYTES_ENV_VAR, DEFAULT_DATA_FRAME_BYTES, HEARTBEAT_INTERVAL,
+  MULTIPLEXED_STDIO_ENV_VAR,
+};
+
 pub enum NailgunClientError {
   PreConnect(String),
   PostConnect(String),
@@ -95,18 +101,52 @@ async fn handle_client_output(
   Ok(())
 }
 
-async fn handle_client_input(mut stdin_write: mpsc::Sender<ChildInput>) -> Result<(), io::Error> {
+///
+/// Multiplexes stdin into Frames (see `Frame`) of at most the given size, followed by the end of
+/// stdin.
+///
+/// Stdin is read one frame at a time, and each frame is sent before the next is read, so a very
+/// large stdin is only read as quickly as the server consumes it.
+///
+async fn handle_client_input(
+  mut stdin_write: mpsc::Sender<ChildInput>,
+  frame_bytes: usize,
+) -> Result<(), io::Error> {
   use nails::execution::send_to_io;
-  let mut stdin = stream_for(tokio::io::stdin());
-  while let Some(input_bytes) = stdin.next().await {
+  let mut stdin = tokio::io::stdin();
+  let mut buffer = vec![0; frame_bytes];
+  loop {
+    let read = stdin.read(&mut buffer).await?;
+    if read == 0 {
+      break;
+    }
+    let frame = Frame::Data(Bytes::copy_from_slice(&buffer[..read]));
     stdin_write
-      .send(ChildInput::Stdin(input_bytes?))
+      .send(ChildInput::Stdin(frame.encode()))
       .await
       .map_err(send_to_io)?;
   }
+  stdin_write
+    .send(ChildInput::Stdin(Frame::EndOfStdin.encode()))
+    .await
+    .map_err(send_to_io)?;
   Ok(())
 }
 
+///
+/// Sends heartbeats until the connection is closed, so that the server can detect a hung client.
+///
+async fn send_heartbeats(mut stdin_write: mpsc::Sender<ChildInput>) {
+  let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+  loop {
+    interval.tick().await;
+    let heartbeat = ChildInput::Stdin(Frame::Heartbeat.encode());
+    if stdin_write.send(heartbeat).await.is_err() {
+      break;
+    }
+  }
+}
+
 ///
 /// Execute the given command on the given localhost port.
 ///
@@ -130,6 +170,10 @@ pub async fn client_execute(
     std::env::current_dir().map_err(|e| NailgunClientError::PreConnect(e.to_string()))?;
 
   let config = Config::default();
+  let frame_bytes = DEFAULT_DATA_FRAME_BYTES;
+  let mut env = env;
+  env.push((MULTIPLEXED_STDIO_ENV_VAR.to_owned(), "1".to_owned()));
+  env.push((DATA_FRAME_BYTES_ENV_VAR.to_owned(), frame_bytes.to_string()));
   let command = Command {
     command,
     args,
@@ -151,7 +195,8 @@ pub async fn client_execute(
 
   let mut child = nails::client::handle_connection(config, socket, command, async {
     let (stdin_write, stdin_read) = child_channel::<ChildInput>();
-    let _input_handler = tokio::spawn(handle_client_input(stdin_write));
+    let _heartbeat_sender = tokio::spawn(send_heartbeats(stdin_write.clone()));
+    let _input_handler = tokio::spawn(handle_client_input(stdin_write, frame_bytes));
     stdin_read
   })
   .await
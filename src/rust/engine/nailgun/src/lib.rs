@@ -33,7 +33,12 @@ mod tests;
 
 mod client;
 mod server;
+mod stdio;
 
 pub use client::{client_execute, NailgunClientError};
 pub use nails::execution::ExitCode;
 pub use server::{RawFdExecution, Server};
+pub use stdio::{
+  data_frame_bytes, Frame, FrameDecoder, DATA_FRAME_BYTES_ENV_VAR, MAX_DATA_FRAME_BYTES,
+  MULTIPLEXED_STDIO_ENV_VAR,
+};
@@ -36,9 +36,9 @@ use std::sync::Arc;
 
 use async_latch::AsyncLatch;
 use bytes::Bytes;
-use futures::channel::oneshot;
-use futures::{future, sink, stream, FutureExt, SinkExt, StreamExt, TryStreamExt};
-use log::{debug, info};
+use futures::channel::{mpsc, oneshot};
+use futures::{future, sink, stream, FutureExt, SinkExt, Stream, StreamExt, TryStreamExt};
+use log::{debug, info, warn};
 use nails::execution::{
   self, child_channel, sink_for, stream_for, ChildInput, ChildOutput, ExitCode,
 };
@@ -47,6 +47,18 @@ use task_executor::Executor;
 use tokio::fs::File;
 use tokio::net::TcpListener;
 use tokio::sync::{Notify, RwLock};
+use tokio::time::timeout;
+
+use crate::stdio::{
+  data_frame_bytes, Frame, FrameDecoder, HEARTBEAT_TIMEOUT, MULTIPLEXED_STDIO_ENV_VAR,
+};
+
+///
+/// The number of bytes of multiplexed stdin which may be buffered (in data frames of the size
+/// declared by the client) while the process is not reading its stdin. Beyond this, the client is
+/// not read from until the process catches up.
+///
+const MAX_BUFFERED_STDIN_BYTES: usize = 16 * 1024 * 1024;
 
 pub struct Server {
   exit_sender: oneshot::Sender<()>,
@@ -232,9 +244,26 @@ impl Nail for RawFdNail {
   fn spawn(&self, cmd: execution::Command) -> Result<nails::server::Child, io::Error> {
     let env = cmd.env.iter().cloned().collect::<HashMap<_, _>>();
 
+    // Set up a cancellation token that is triggered on client shutdown, or when a client which
+    // multiplexes its stdin stops sending heartbeats.
+    let cancelled = AsyncLatch::new();
+
     // Handle stdin.
     let (stdin_handle, stdin_sink) = Self::input(Self::ttypath_from_env(&env, 0))?;
-    let maybe_stdin_write = if let Some(mut stdin_sink) = stdin_sink {
+    let maybe_stdin_write = if env.contains_key(MULTIPLEXED_STDIO_ENV_VAR) {
+      let frame_bytes =
+        data_frame_bytes(&env).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+      // NB: Even if stdin is a TTY, the client sends heartbeats over the protocol.
+      let (stdin_write, stdin_read) = child_channel::<ChildInput>();
+      let _join = self.executor.spawn(Self::demultiplex_stdin(
+        self.executor.clone(),
+        stdin_read,
+        stdin_sink,
+        frame_bytes,
+        cancelled.clone(),
+      ));
+      Some(stdin_write)
+    } else if let Some(mut stdin_sink) = stdin_sink {
       let (stdin_write, stdin_read) = child_channel::<ChildInput>();
       // Spawn a task that will propagate the input stream.
       let _join = self.executor.spawn(async move {
@@ -253,8 +282,6 @@ impl Nail for RawFdNail {
     let (stdout_stream, stdout_handle) = Self::output(Self::ttypath_from_env(&env, 1))?;
     let (stderr_stream, stderr_handle) = Self::output(Self::ttypath_from_env(&env, 2))?;
 
-    // Set up a cancellation token that is triggered on client shutdown.
-    let cancelled = AsyncLatch::new();
     let shutdown = {
       let cancelled = cancelled.clone();
       async move {
@@ -294,6 +321,76 @@ impl Nail for RawFdNail {
 }
 
 impl RawFdNail {
+  ///
+  /// Decodes the frames of a client which multiplexes its stdin, and copies its data to the given
+  /// Sink (if any).
+  ///
+  /// Data is buffered (up to a limit) between the connection and the stdin of the process, so that
+  /// a large paste does not prevent heartbeats from being received while the process is not yet
+  /// reading its stdin. If no frame is received within the `HEARTBEAT_TIMEOUT` (other than while
+  /// the buffer is full), the client is assumed to be hung, and the run is cancelled.
+  ///
+  /// Heartbeats may arrive at any point in the stream (including after the end of stdin), and
+  /// data which arrives after the end of stdin is ignored.
+  ///
+  async fn demultiplex_stdin(
+    executor: Executor,
+    mut stdin_read: impl Stream<Item = ChildInput> + Unpin,
+    stdin_sink: Option<impl sink::Sink<Bytes> + Send + Unpin + 'static>,
+    frame_bytes: usize,
+    cancelled: AsyncLatch,
+  ) {
+    let mut data_write = stdin_sink.map(|mut stdin_sink| {
+      let buffered_frames = std::cmp::max(1, MAX_BUFFERED_STDIN_BYTES / frame_bytes);
+      let (data_write, data_read) = mpsc::channel::<Bytes>(buffered_frames);
+      let _join = executor.spawn(async move {
+        // When the client's stdin ends (and `data_write` is dropped), so does this stream, and
+        // dropping the sink closes the stdin of the process.
+        let mut input_stream = data_read.map(Ok);
+        let _ = stdin_sink.send_all(&mut input_stream).await;
+      });
+      data_write
+    });
+
+    let mut decoder = FrameDecoder::new(frame_bytes);
+    loop {
+      let input = match timeout(HEARTBEAT_TIMEOUT, stdin_read.next()).await {
+        Ok(Some(ChildInput::Stdin(bytes))) => bytes,
+        Ok(None) => break,
+        Err(_) => {
+          warn!(
+            "The client did not send a heartbeat for {:?}: cancelling its run.",
+            HEARTBEAT_TIMEOUT
+          );
+          cancelled.trigger();
+          break;
+        }
+      };
+      decoder.push(&input);
+      loop {
+        match decoder.next_frame() {
+          Ok(Some(Frame::Data(bytes))) => {
+            // NB: Data which arrives for a TTY stdin (or after the process has stopped reading its
+            // stdin) is dropped.
+            if let Some(ref mut data_write) = data_write {
+              if data_write.send(bytes).await.is_err() {
+                debug!("The process stopped reading stdin.");
+              }
+            }
+          }
+          Ok(Some(Frame::EndOfStdin)) => data_write = None,
+          Ok(Some(Frame::Heartbeat)) => {}
+          Ok(None) => break,
+          Err(e) => {
+            warn!("Cancelling run due to an invalid stdin stream: {}", e);
+            cancelled.trigger();
+            return;
+          }
+        }
+      }
+    }
+  }
+
   ///
   /// Returns a tuple of a readable file handle and an optional sink for nails to send stdin to.
   ///
@@ -0,0 +1,178 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::Duration;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+///
+/// The environment variable with which a client advertises that it multiplexes its stdin (see
+/// `Frame`), rather than sending raw stdin.
+///
+pub const MULTIPLEXED_STDIO_ENV_VAR: &str = "NAILGUN_MULTIPLEXED_STDIO";
+
+///
+/// The environment variable with which a client which multiplexes its stdin declares the maximum
+/// size of the payloads of the data frames that it will send. The server validates the declared
+/// size (see `data_frame_bytes`) before starting a run, and rejects any larger frame.
+///
+pub const DATA_FRAME_BYTES_ENV_VAR: &str = "NAILGUN_MULTIPLEXED_STDIO_FRAME_BYTES";
+
+///
+/// How often a client which multiplexes its stdin sends heartbeats.
+///
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+///
+/// How long the server waits for a frame from a client which multiplexes its stdin before it
+/// considers the client to be hung, and cancels its run.
+///
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+///
+/// The size of the payload of a data frame for clients which do not declare one. Large chunks of
+/// stdin (such as a big paste) are split into frames of at most this size, so that no single write
+/// to the stdin pipe of the server (which has a similar capacity) can block for long.
+///
+pub const DEFAULT_DATA_FRAME_BYTES: usize = 64 * 1024;
+
+///
+/// The largest data frame size which a client may declare.
+///
+pub const MAX_DATA_FRAME_BYTES: usize = 1024 * 1024;
+
+// A tag byte and a big-endian u32 length.
+const HEADER_BYTES: usize = 5;
+
+///
+/// A frame of the multiplexed stdin protocol.
+///
+/// The nailgun protocol only allows a client to send stdin (and its end), so a client which
+/// multiplexes its stdin sends a stream of frames as the content of its stdin instead. This allows
+/// it to send heartbeats while the server is running, including after its own stdin has ended.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Frame {
+  Data(Bytes),
+  EndOfStdin,
+  Heartbeat,
+}
+
+impl Frame {
+  ///
+  /// Splits the given chunk of stdin into Data frames of the default size.
+  ///
+  pub fn data(bytes: Bytes) -> Vec<Frame> {
+    Frame::data_of_size(bytes, DEFAULT_DATA_FRAME_BYTES)
+  }
+
+  ///
+  /// Splits the given chunk of stdin into Data frames of at most the given size.
+  ///
+  pub fn data_of_size(mut bytes: Bytes, frame_bytes: usize) -> Vec<Frame> {
+    let mut frames = Vec::with_capacity(bytes.len() / frame_bytes + 1);
+    while bytes.len() > frame_bytes {
+      frames.push(Frame::Data(bytes.split_to(frame_bytes)));
+    }
+    if !bytes.is_empty() {
+      frames.push(Frame::Data(bytes));
+    }
+    frames
+  }
+
+  pub fn encode(&self) -> Bytes {
+    let (tag, payload) = match self {
+      Frame::Data(bytes) => (b'D', &bytes[..]),
+      Frame::EndOfStdin => (b'E', &[][..]),
+      Frame::Heartbeat => (b'H', &[][..]),
+    };
+    let mut encoded = BytesMut::with_capacity(HEADER_BYTES + payload.len());
+    encoded.put_u8(tag);
+    encoded.put_u32(payload.len() as u32);
+    encoded.put_slice(payload);
+    encoded.freeze()
+  }
+}
+
+///
+/// Returns the data frame size declared by a client with the given environment, or the default if
+/// it did not declare one.
+///
+pub fn data_frame_bytes(env: &HashMap<String, String>) -> Result<usize, String> {
+  let declared = match env.get(DATA_FRAME_BYTES_ENV_VAR) {
+    Some(declared) => declared,
+    None => return Ok(DEFAULT_DATA_FRAME_BYTES),
+  };
+  match declared.parse::<usize>() {
+    Ok(frame_bytes) if frame_bytes > 0 && frame_bytes <= MAX_DATA_FRAME_BYTES => Ok(frame_bytes),
+    _ => Err(format!(
+      "The declared multiplexed stdin frame size {:?} was not an integer between 1 and {}.",
+      declared, MAX_DATA_FRAME_BYTES
+    )),
+  }
+}
+
+///
+/// Decodes Frames from the chunks of a multiplexed stdin stream, which may split or combine frames
+/// arbitrarily.
+///
+pub struct FrameDecoder {
+  buffer: BytesMut,
+  max_frame_bytes: usize,
+}
+
+impl Default for FrameDecoder {
+  fn default() -> FrameDecoder {
+    FrameDecoder::new(DEFAULT_DATA_FRAME_BYTES)
+  }
+}
+
+impl FrameDecoder {
+  ///
+  /// Creates a decoder which rejects data frames larger than the given size.
+  ///
+  pub fn new(max_frame_bytes: usize) -> FrameDecoder {
+    FrameDecoder {
+      buffer: BytesMut::new(),
+      max_frame_bytes,
+    }
+  }
+
+  pub fn push(&mut self, bytes: &[u8]) {
+    self.buffer.extend_from_slice(bytes);
+  }
+
+  ///
+  /// Returns the next complete Frame, if any.
+  ///
+  pub fn next_frame(&mut self) -> Result<Option<Frame>, String> {
+    if self.buffer.len() < HEADER_BYTES {
+      return Ok(None);
+    }
+    let tag = self.buffer[0];
+    let len = u32::from_be_bytes(self.buffer[1..HEADER_BYTES].try_into().unwrap()) as usize;
+    if len > self.max_frame_bytes {
+      return Err(format!(
+        "Multiplexed stdin frame of {} bytes exceeded the maximum of {}.",
+        len, self.max_frame_bytes
+      ));
+    }
+    if self.buffer.len() < HEADER_BYTES + len {
+      return Ok(None);
+    }
+    self.buffer.advance(HEADER_BYTES);
+    let payload = self.buffer.split_to(len).freeze();
+    match (tag, len) {
+      (b'D', _) => Ok(Some(Frame::Data(payload))),
+      (b'E', 0) => Ok(Some(Frame::EndOfStdin)),
+      // NB: The payload of a heartbeat is reserved for future use, and is ignored.
+      (b'H', _) => Ok(Some(Frame::Heartbeat)),
+      _ => Err(format!(
+        "Invalid multiplexed stdin frame with tag {:?} and length {}.",
+        tag as char, len
+      )),
+    }
+  }
+}
@@ -1,12 +1,21 @@
-use crate::Server;
+use crate::{
+  data_frame_bytes, Frame, FrameDecoder, Server, DATA_FRAME_BYTES_ENV_VAR, MAX_DATA_FRAME_BYTES,
+  MULTIPLEXED_STDIO_ENV_VAR,
+};
 
+use std::collections::HashMap;
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
-use futures::{future, FutureExt};
+use bytes::Bytes;
+use futures::{future, FutureExt, SinkExt};
 use nails::execution::{child_channel, ChildInput, Command, ExitCode};
 use nails::Config;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use task_executor::Executor;
 use tokio::net::TcpStream;
 use tokio::sync::Notify;
@@ -77,6 +86,179 @@ async fn shutdown_awaits_ongoing() {
   server_shutdown.await.unwrap().unwrap();
 }
 
+#[test]
+fn frames_roundtrip_across_arbitrary_chunks() {
+  let stdin = Bytes::from(vec![7_u8; 200 * 1024]);
+  let mut frames = Frame::data(stdin.clone());
+  assert_eq!(frames.len(), 4);
+  frames.push(Frame::Heartbeat);
+  frames.push(Frame::EndOfStdin);
+  let encoded = frames
+    .iter()
+    .flat_map(|frame| frame.encode().to_vec())
+    .collect::<Vec<_>>();
+
+  let mut decoder = FrameDecoder::default();
+  let mut decoded = vec![];
+  for chunk in encoded.chunks(1000) {
+    decoder.push(chunk);
+    while let Some(frame) = decoder.next_frame().unwrap() {
+      decoded.push(frame);
+    }
+  }
+  assert_eq!(decoded, frames);
+}
+
+#[test]
+fn invalid_frame() {
+  let mut decoder = FrameDecoder::default();
+  decoder.push(&[b'X', 0, 0, 0, 0]);
+  assert!(decoder.next_frame().is_err());
+}
+
+#[test]
+fn negotiated_frame_size() {
+  let env = |frame_bytes: &str| {
+    vec![(DATA_FRAME_BYTES_ENV_VAR.to_owned(), frame_bytes.to_owned())]
+      .into_iter()
+      .collect::<HashMap<_, _>>()
+  };
+  assert_eq!(Ok(16), data_frame_bytes(&env("16")));
+  assert!(data_frame_bytes(&env("0")).is_err());
+  assert!(data_frame_bytes(&env("many")).is_err());
+  assert!(data_frame_bytes(&env(&(MAX_DATA_FRAME_BYTES + 1).to_string())).is_err());
+
+  // Frames larger than the negotiated size are rejected.
+  let mut decoder = FrameDecoder::new(16);
+  decoder.push(&Frame::Data(Bytes::from(vec![0_u8; 16])).encode());
+  assert!(decoder.next_frame().unwrap().is_some());
+  decoder.push(&Frame::Data(Bytes::from(vec![0_u8; 17])).encode());
+  assert!(decoder.next_frame().is_err());
+}
+
+#[test]
+fn heartbeats_with_payloads() {
+  // The payload of a heartbeat is ignored.
+  let mut decoder = FrameDecoder::default();
+  decoder.push(&[b'H', 0, 0, 0, 2, 1, 2]);
+  decoder.push(&Frame::EndOfStdin.encode());
+  assert_eq!(Some(Frame::Heartbeat), decoder.next_frame().unwrap());
+  assert_eq!(Some(Frame::EndOfStdin), decoder.next_frame().unwrap());
+  assert_eq!(None, decoder.next_frame().unwrap());
+}
+
+#[test]
+fn fuzz_frames_roundtrip() {
+  let mut rng = StdRng::seed_from_u64(0);
+  for _ in 0..200 {
+    let frame_bytes = rng.gen_range(1..4096);
+    // Data of random lengths, interleaved with heartbeats at random points (including after the
+    // end of stdin).
+    let mut frames = vec![];
+    for _ in 0..rng.gen_range(0..20) {
+      if rng.gen_bool(0.3) {
+        frames.push(Frame::Heartbeat);
+      } else {
+        let data = (0..rng.gen_range(0..3 * frame_bytes))
+          .map(|_| rng.gen())
+          .collect::<Vec<u8>>();
+        frames.extend(Frame::data_of_size(Bytes::from(data), frame_bytes));
+      }
+    }
+    frames.push(Frame::EndOfStdin);
+    for _ in 0..rng.gen_range(0..3) {
+      frames.push(Frame::Heartbeat);
+    }
+    let encoded = frames
+      .iter()
+      .flat_map(|frame| frame.encode().to_vec())
+      .collect::<Vec<_>>();
+
+    // Decoded from chunks of random sizes.
+    let mut decoder = FrameDecoder::new(frame_bytes);
+    let mut decoded = vec![];
+    let mut remaining = &encoded[..];
+    while !remaining.is_empty() {
+      let (chunk, rest) = remaining.split_at(rng.gen_range(1..=remaining.len()));
+      remaining = rest;
+      decoder.push(chunk);
+      while let Some(frame) = decoder.next_frame().unwrap() {
+        decoded.push(frame);
+      }
+    }
+    assert_eq!(decoded, frames);
+  }
+}
+
+#[test]
+fn fuzz_garbage() {
+  // Arbitrary input is either decoded or rejected, but never causes a panic (or an unbounded
+  // allocation).
+  let mut rng = StdRng::seed_from_u64(0);
+  for _ in 0..1000 {
+    let garbage = (0..rng.gen_range(0..64))
+      .map(|_| rng.gen())
+      .collect::<Vec<u8>>();
+    let mut decoder = FrameDecoder::new(16);
+    decoder.push(&garbage);
+    while let Ok(Some(_)) = decoder.next_frame() {}
+  }
+}
+
+#[tokio::test]
+async fn multiplexed_stdin() {
+  // A server which reads all of its stdin (without closing the fd), and succeeds if it was the
+  // expected size. The stdin is larger than the server will buffer, so the client is subject to
+  // backpressure.
+  let stdin_size = 32 * 1024 * 1024;
+  let server = Server::new(Executor::new(), 0, move |exe: crate::RawFdExecution| {
+    let mut stdin =
+      std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(exe.stdin_fd) });
+    let mut content = Vec::new();
+    stdin.read_to_end(&mut content).unwrap();
+    ExitCode(if content.len() == stdin_size { 0 } else { 1 })
+  })
+  .await
+  .unwrap();
+
+  // NB: A non-default frame size is negotiated, and heartbeats are interleaved with the data.
+  let frame_bytes = 256 * 1024;
+  let cmd = Command {
+    command: "nothing".to_owned(),
+    args: vec![],
+    env: vec![
+      (MULTIPLEXED_STDIO_ENV_VAR.to_owned(), "1".to_owned()),
+      (DATA_FRAME_BYTES_ENV_VAR.to_owned(), frame_bytes.to_string()),
+    ],
+    working_dir: PathBuf::from("/dev/null"),
+  };
+  let stream = TcpStream::connect(("127.0.0.1", server.port()))
+    .await
+    .unwrap();
+  let child = nails::client::handle_connection(Config::default(), stream, cmd, async move {
+    let (mut stdin_write, stdin_read) = child_channel::<ChildInput>();
+    let _join = tokio::spawn(async move {
+      let mut frames = vec![];
+      for frame in Frame::data_of_size(Bytes::from(vec![0_u8; stdin_size]), frame_bytes) {
+        frames.push(frame);
+        frames.push(Frame::Heartbeat);
+      }
+      frames.push(Frame::EndOfStdin);
+      for frame in frames {
+        stdin_write
+          .send(ChildInput::Stdin(frame.encode()))
+          .await
+          .unwrap();
+      }
+    });
+    stdin_read
+  })
+  .await
+  .unwrap();
+  assert_eq!(ExitCode(0), child.wait().await.unwrap());
+  server.shutdown().await.unwrap();
+}
+
 async fn run_client(port: u16) -> Result<ExitCode, String> {
   let cmd = Command {
     command: "nothing".to_owned(),
@@ -0,0 +1,315 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+/// Paths under these prefixes are pseudo-filesystems, which are not considered to be inputs.
+const IGNORED_SYSTEM_PREFIXES: &[&str] = &["/dev", "/proc", "/sys"];
+
+/// The maximum number of undeclared paths which are rendered for a process.
+const MAX_REPORTED_PATHS: usize = 20;
+
+/// Syscalls which modify the path which is their first argument.
+const WRITE_SYSCALLS: &[&str] = &[
+  "creat",
+  "mkdir",
+  "mkdirat",
+  "mknod",
+  "mknodat",
+  "rmdir",
+  "unlink",
+  "unlinkat",
+  "rename",
+  "renameat",
+  "renameat2",
+  "truncate",
+  "chmod",
+  "fchmodat",
+  "chown",
+  "lchown",
+  "fchownat",
+  "utime",
+  "utimes",
+  "utimensat",
+];
+
+/// Flags which make an `open` of a path a write.
+const WRITE_OPEN_FLAGS: &[&str] = &["O_WRONLY", "O_RDWR", "O_CREAT", "O_TRUNC"];
+
+///
+/// Audits which paths local processes actually access, in order to find reads of undeclared inputs
+/// (which make a process non-hermetic, and so unsafe to cache).
+///
+/// Processes are run under `strace`, which logs the file-related syscalls of the process (and of
+/// its children) to a trace file outside of the sandbox. After the process exits, the successful
+/// accesses of absolute paths outside of the sandbox are reported as undeclared: anything that the
+/// process needed from inside the sandbox was either part of its declared input digest, or was
+/// created by the process itself.
+///
+/// Only the sink of a piped process (see `Process::pipe_from_argv`) is traced.
+///
+/// The auditor may also be used to fail processes which write to paths outside of their sandbox,
+/// which (like undeclared reads) make a process non-hermetic.
+///
+#[derive(Clone, Debug)]
+pub struct FileAccessAuditor {
+  strace: PathBuf,
+  ignored_prefixes: Vec<PathBuf>,
+  warn_undeclared_accesses: bool,
+  fail_on_external_writes: bool,
+}
+
+impl FileAccessAuditor {
+  ///
+  /// Creates an auditor which will not report accesses of paths under the given prefixes (such as
+  /// the named caches directory).
+  ///
+  pub fn new(
+    ignored_prefixes: Vec<PathBuf>,
+    warn_undeclared_accesses: bool,
+    fail_on_external_writes: bool,
+  ) -> Result<FileAccessAuditor, String> {
+    if !cfg!(target_os = "linux") {
+      return Err("Auditing the file accesses of processes is only supported on Linux.".to_owned());
+    }
+    let strace = find_executable("strace").ok_or_else(|| {
+      "Auditing the file accesses of processes requires `strace`, but it was not found on the \
+       PATH."
+        .to_owned()
+    })?;
+    Ok(FileAccessAuditor {
+      strace,
+      ignored_prefixes,
+      warn_undeclared_accesses,
+      fail_on_external_writes,
+    })
+  }
+
+  ///
+  /// Returns an argv which runs the given argv (in the given working directory) under the tracer,
+  /// logging to the given trace file.
+  ///
+  pub fn traced_argv(&self, argv: &[String], cwd: &Path, trace_file: &Path) -> Vec<String> {
+    // NB: A relative argv[0] is relative to the working directory (see `run_in_workdir`), but the
+    // tracer would search the PATH for it, so it is absolutized.
+    let argv0 = if Path::new(&argv[0]).is_relative() {
+      cwd.join(&argv[0]).to_string_lossy().into_owned()
+    } else {
+      argv[0].clone()
+    };
+    let mut traced = vec![
+      self.strace.to_string_lossy().into_owned(),
+      // Follow children.
+      "-f".to_owned(),
+      // Suppress messages about attaching and exiting.
+      "-qq".to_owned(),
+      // Don't truncate paths.
+      "-s".to_owned(),
+      "4096".to_owned(),
+      "-e".to_owned(),
+      "trace=%file".to_owned(),
+      "-o".to_owned(),
+      trace_file.to_string_lossy().into_owned(),
+      "--".to_owned(),
+      argv0,
+    ];
+    traced.extend(argv[1..].iter().cloned());
+    traced
+  }
+
+  ///
+  /// Reads the trace file of a process which ran in the given sandbox, and warns about any
+  /// undeclared paths that it accessed.
+  ///
+  /// If the auditor fails processes which write outside of their sandbox, returns an error
+  /// describing any such writes, which should fail the process.
+  ///
+  pub fn report(&self, description: &str, sandbox: &Path, trace_file: &Path) -> Option<String> {
+    let trace = match std::fs::read_to_string(trace_file) {
+      Ok(trace) => trace,
+      Err(e) => {
+        warn!(
+          "Failed to read the file access trace of process `{}`: {}",
+          description, e
+        );
+        return None;
+      }
+    };
+
+    if self.warn_undeclared_accesses {
+      let undeclared = undeclared_accesses(&parse_trace(&trace), sandbox, &self.ignored_prefixes);
+      if !undeclared.is_empty() {
+        warn!(
+          "Process `{}` accessed {} undeclared path{} outside of its sandbox:\n{}",
+          description,
+          undeclared.len(),
+          if undeclared.len() == 1 { "" } else { "s" },
+          render_paths(&undeclared)
+        );
+      }
+    }
+
+    if self.fail_on_external_writes {
+      let external_writes =
+        undeclared_accesses(&parse_trace_writes(&trace), sandbox, &self.ignored_prefixes);
+      if !external_writes.is_empty() {
+        return Some(format!(
+          "Process `{}` wrote to {} path{} outside of its sandbox:\n{}",
+          description,
+          external_writes.len(),
+          if external_writes.len() == 1 { "" } else { "s" },
+          render_paths(&external_writes)
+        ));
+      }
+    }
+    None
+  }
+}
+
+fn render_paths(paths: &[PathBuf]) -> String {
+  let mut rendered = paths
+    .iter()
+    .take(MAX_REPORTED_PATHS)
+    .map(|path| format!("  {}", path.display()))
+    .collect::<Vec<_>>();
+  if paths.len() > MAX_REPORTED_PATHS {
+    rendered.push(format!(
+      "  ... and {} more",
+      paths.len() - MAX_REPORTED_PATHS
+    ));
+  }
+  rendered.join("\n")
+}
+
+///
+/// Parses the output of `strace -f -e trace=%file`, returning the paths which were accessed by
+/// successful syscalls.
+///
+pub fn parse_trace(trace: &str) -> BTreeSet<PathBuf> {
+  successful_calls(trace)
+    .into_iter()
+    .map(|(_, path)| path)
+    .collect()
+}
+
+///
+/// Parses the output of `strace -f -e trace=%file`, returning the paths which were modified (or
+/// opened for writing) by successful syscalls.
+///
+pub fn parse_trace_writes(trace: &str) -> BTreeSet<PathBuf> {
+  successful_calls(trace)
+    .into_iter()
+    .filter(|(call, _)| is_write(call))
+    .map(|(_, path)| path)
+    .collect()
+}
+
+fn is_write(call: &str) -> bool {
+  let syscall = match call.find('(') {
+    Some(i) => &call[..i],
+    None => return false,
+  };
+  if syscall.starts_with("open") {
+    WRITE_OPEN_FLAGS.iter().any(|flag| call.contains(flag))
+  } else {
+    WRITE_SYSCALLS.contains(&syscall)
+  }
+}
+
+///
+/// Returns the successful syscalls of the given trace, along with the first path that each
+/// accessed. For syscalls which were interrupted, the call is the text of its first line, which
+/// includes its arguments.
+///
+fn successful_calls(trace: &str) -> Vec<(&str, PathBuf)> {
+  let mut accessed = Vec::new();
+  // Syscalls which were interrupted by a syscall of another process, by pid.
+  let mut unfinished: HashMap<&str, (&str, PathBuf)> = HashMap::new();
+  for line in trace.lines() {
+    // When following children, each line is prefixed with a pid.
+    let (pid, call) = match line.find(' ') {
+      Some(i) if line[..i].bytes().all(|b| b.is_ascii_digit()) => {
+        (&line[..i], line[i..].trim_start())
+      }
+      _ => ("", line),
+    };
+    if call.starts_with("<...") {
+      if let Some((unfinished_call, path)) = unfinished.remove(pid) {
+        if succeeded(call) {
+          accessed.push((unfinished_call, path));
+        }
+      }
+      continue;
+    }
+    let path = match first_quoted_string(call) {
+      Some(path) => PathBuf::from(path),
+      None => continue,
+    };
+    if call.ends_with("<unfinished ...>") {
+      unfinished.insert(pid, (call, path));
+    } else if succeeded(call) {
+      accessed.push((call, path));
+    }
+  }
+  accessed
+}
+
+///
+/// Filters the given accessed paths to those which are absolute and outside of the sandbox (and
+/// of any ignored prefixes).
+///
+pub fn undeclared_accesses(
+  accessed: &BTreeSet<PathBuf>,
+  sandbox: &Path,
+  ignored_prefixes: &[PathBuf],
+) -> Vec<PathBuf> {
+  accessed
+    .iter()
+    .filter(|path| {
+      path.is_absolute()
+        && !path.starts_with(sandbox)
+        && !IGNORED_SYSTEM_PREFIXES
+          .iter()
+          .any(|prefix| path.starts_with(prefix))
+        && !ignored_prefixes
+          .iter()
+          .any(|prefix| path.starts_with(prefix))
+    })
+    .cloned()
+    .collect()
+}
+
+fn succeeded(call: &str) -> bool {
+  match call.rfind(") = ") {
+    Some(i) => !call[i + 4..].starts_with('-'),
+    None => false,
+  }
+}
+
+///
+/// Returns the first (unescaped) string argument of a traced syscall.
+///
+fn first_quoted_string(call: &str) -> Option<String> {
+  let start = call.find('"')?;
+  let mut result = String::new();
+  let mut chars = call[start + 1..].chars();
+  loop {
+    match chars.next()? {
+      '"' => return Some(result),
+      '\\' => match chars.next()? {
+        'n' => result.push('\n'),
+        't' => result.push('\t'),
+        c => result.push(c),
+      },
+      c => result.push(c),
+    }
+  }
+}
+
+fn find_executable(name: &str) -> Option<PathBuf> {
+  let path = std::env::var_os("PATH").unwrap_or_else(|| OsString::from("/usr/bin:/bin"));
+  std::env::split_paths(&path)
+    .map(|dir| dir.join(name))
+    .find(|candidate| candidate.is_file())
+}
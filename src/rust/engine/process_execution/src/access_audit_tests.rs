@@ -0,0 +1,86 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::access_audit::{parse_trace, parse_trace_writes, undeclared_accesses};
+
+fn paths(paths: &[&str]) -> BTreeSet<PathBuf> {
+  paths.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn parse_successful_accesses() {
+  let trace = r#"101 execve("/usr/bin/python3", ["python3", "main.py"], 0x7ffd /* 3 vars */) = 0
+101 openat(AT_FDCWD, "/etc/ld.so.cache", O_RDONLY|O_CLOEXEC) = 3
+101 openat(AT_FDCWD, "/usr/lib/missing.so", O_RDONLY|O_CLOEXEC) = -1 ENOENT (No such file or directory)
+101 stat("main.py", {st_mode=S_IFREG|0644, st_size=10, ...}) = 0
+101 openat(AT_FDCWD, "/home/user/with \"quotes\"", O_RDONLY) = 4
+"#;
+  assert_eq!(
+    parse_trace(trace),
+    paths(&[
+      "/usr/bin/python3",
+      "/etc/ld.so.cache",
+      "main.py",
+      "/home/user/with \"quotes\"",
+    ])
+  );
+}
+
+#[test]
+fn parse_unfinished_accesses() {
+  let trace = r#"101 openat(AT_FDCWD, "/etc/hosts", O_RDONLY <unfinished ...>
+102 openat(AT_FDCWD, "/etc/passwd", O_RDONLY <unfinished ...>
+101 <... openat resumed>) = 3
+102 <... openat resumed>) = -1 EACCES (Permission denied)
+"#;
+  assert_eq!(parse_trace(trace), paths(&["/etc/hosts"]));
+}
+
+#[test]
+fn parse_without_pids() {
+  let trace = r#"openat(AT_FDCWD, "/etc/hosts", O_RDONLY) = 3
+--- SIGCHLD {si_signo=SIGCHLD, si_code=CLD_EXITED, si_pid=102} ---
+"#;
+  assert_eq!(parse_trace(trace), paths(&["/etc/hosts"]));
+}
+
+#[test]
+fn parse_writes() {
+  let trace = r#"101 openat(AT_FDCWD, "/etc/hosts", O_RDONLY|O_CLOEXEC) = 3
+101 openat(AT_FDCWD, "/tmp/out.txt", O_WRONLY|O_CREAT|O_TRUNC, 0666) = 4
+101 openat(AT_FDCWD, "/tmp/failed.txt", O_WRONLY|O_CREAT, 0666) = -1 EACCES (Permission denied)
+101 stat("/tmp", {st_mode=S_IFDIR|0777, ...}) = 0
+101 mkdir("/home/user/.cache", 0777) = 0
+102 unlinkat(AT_FDCWD, "/tmp/lock" <unfinished ...>
+101 access("/usr/bin/python3", X_OK) = 0
+102 <... unlinkat resumed>, 0) = 0
+"#;
+  assert_eq!(
+    parse_trace_writes(trace),
+    paths(&["/tmp/out.txt", "/home/user/.cache", "/tmp/lock"])
+  );
+}
+
+#[test]
+fn undeclared_accesses_outside_sandbox() {
+  let accessed = paths(&[
+    "relative/input.txt",
+    "/sandbox/input.txt",
+    "/sandbox2/other.txt",
+    "/proc/self/maps",
+    "/dev/null",
+    "/named_caches/pip/wheel.whl",
+    "/usr/lib/libc.so.6",
+  ]);
+  assert_eq!(
+    undeclared_accesses(
+      &accessed,
+      Path::new("/sandbox"),
+      &[PathBuf::from("/named_caches")]
+    ),
+    vec![
+      PathBuf::from("/sandbox2/other.txt"),
+      PathBuf::from("/usr/lib/libc.so.6"),
+    ]
+  );
+}
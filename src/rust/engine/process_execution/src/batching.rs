@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use fs::{GlobExpansionConjunction, PathGlobs, StrictGlobMatching};
+use futures::future;
+use hashing::{Digest, EMPTY_DIGEST};
+use log::debug;
+use parking_lot::Mutex;
+use store::{SnapshotOps, Store, SubsetParams};
+use task_executor::Executor;
+use tokio::sync::oneshot;
+use workunit_store::{
+  get_workunit_store_handle, scope_task_workunit_store_handle, with_workunit, WorkunitMetadata,
+  WorkunitStoreHandle,
+};
+
+use crate::{Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, Process};
+
+type ProcessResult = Result<FallibleProcessResultWithPlatform, String>;
+
+/// A Process which is waiting to be run in a batch, with the Context and workunit that it was
+/// requested in, and a Sender for its result.
+type BatchItem = (
+  Process,
+  Context,
+  Option<WorkunitStoreHandle>,
+  oneshot::Sender<ProcessResult>,
+);
+
+///
+/// A CommandRunner wrapper which runs compatible processes as a single batch, in order to reduce
+/// the per-process overhead of tools (such as linters) which are run once per file.
+///
+/// Processes opt in to batching by setting `Process::batch_argv_prefix_len`. Requests for
+/// compatible processes (which differ only in the items at the end of their argv, and in their
+/// outputs) which are requested by the same Session within `window` of one another are merged
+/// into one process, whose argv is the shared prefix followed by the items of each of them, and
+/// which captures all of their outputs. A batch is run as soon as it contains `max_batch_size`
+/// processes.
+///
+/// If the batch succeeds, each process receives its stdout and stderr, and the subset of its
+/// outputs which that process declared. Since the stdout and stderr are those of the whole batch,
+/// these results are marked `batched`, and are not cached. If the batch fails, it is not possible
+/// to tell which of its items caused the failure, so each of its processes is run again
+/// individually (within the workunit that requested it), and receives its own result.
+///
+/// The batch itself runs in a workunit of its own, since it belongs to none of its items in
+/// particular.
+///
+#[derive(Clone)]
+pub struct CommandRunner {
+  underlying: Arc<dyn crate::CommandRunner>,
+  store: Store,
+  executor: Executor,
+  max_batch_size: usize,
+  window: Duration,
+  // Batches which are waiting to run, keyed by the shared portion of their processes and the
+  // Session which requested them, with a unique id per batch.
+  pending: Arc<Mutex<HashMap<BatchKey, (usize, Vec<BatchItem>)>>>,
+  next_id: Arc<AtomicUsize>,
+}
+
+impl CommandRunner {
+  pub fn new(
+    underlying: Arc<dyn crate::CommandRunner>,
+    store: Store,
+    executor: Executor,
+    max_batch_size: usize,
+    window: Duration,
+  ) -> CommandRunner {
+    CommandRunner {
+      underlying,
+      store,
+      executor,
+      max_batch_size,
+      window,
+      pending: Arc::default(),
+      next_id: Arc::default(),
+    }
+  }
+
+  ///
+  /// Runs the batch with the given key and id once the window has elapsed, unless it has already
+  /// been run because it filled up.
+  ///
+  fn run_after_window(&self, key: BatchKey, id: usize) {
+    let command_runner = self.clone();
+    let _join = self.executor.spawn(async move {
+      tokio::time::sleep(command_runner.window).await;
+      let items = {
+        let mut pending = command_runner.pending.lock();
+        match pending.get(&key) {
+          Some((entry_id, _)) if *entry_id == id => pending.remove(&key).map(|(_, items)| items),
+          _ => None,
+        }
+      };
+      if let Some(items) = items {
+        command_runner.run_batch(items).await;
+      }
+    });
+  }
+
+  async fn run_batch(self, mut items: Vec<BatchItem>) {
+    if items.len() == 1 {
+      let (process, context, workunit_store_handle, sender) = items.pop().unwrap();
+      let result = scope_task_workunit_store_handle(
+        workunit_store_handle,
+        self.underlying.run(process.into(), context),
+      )
+      .await;
+      let _ = sender.send(result);
+      return;
+    }
+
+    let processes = items
+      .iter()
+      .map(|(process, _, _, _)| process)
+      .collect::<Vec<_>>();
+    let batch = batch_process(&processes);
+    debug!(
+      "Running {} processes as a batch: {}",
+      processes.len(),
+      batch.description
+    );
+    // All items of a batch were requested by the same Session, and so share its Context.
+    let context = items[0].1.clone();
+    let metadata = WorkunitMetadata {
+      desc: Some(batch.description.clone()),
+      ..WorkunitMetadata::default()
+    };
+    let batch_workunit_store_handle = WorkunitStoreHandle {
+      store: context.workunit_store.clone(),
+      parent_id: None,
+    };
+    let batch_result = scope_task_workunit_store_handle(
+      Some(batch_workunit_store_handle),
+      with_workunit(
+        context.workunit_store.clone(),
+        "run_process_batch".to_owned(),
+        metadata,
+        self.underlying.run(batch.into(), context.clone()),
+        |_, md| md,
+      ),
+    )
+    .await;
+    match batch_result {
+      Ok(result) if result.exit_code == 0 => {
+        for (process, _, _, sender) in items {
+          let item_result = self
+            .output_subset(&process, result.output_directory)
+            .await
+            .map(|output_directory| {
+              let mut item_result = FallibleProcessResultWithPlatform {
+                output_directory,
+                ..result.clone()
+              };
+              item_result.metadata.batched = true;
+              item_result
+            });
+          let _ = sender.send(item_result);
+        }
+      }
+      _ => {
+        // Run each process individually, so that a failure is attributed to the right process.
+        future::join_all(items.into_iter().map(
+          |(process, context, workunit_store_handle, sender)| {
+            let underlying = self.underlying.clone();
+            async move {
+              let result = scope_task_workunit_store_handle(
+                workunit_store_handle,
+                underlying.run(process.into(), context),
+              )
+              .await;
+              let _ = sender.send(result);
+            }
+          },
+        ))
+        .await;
+      }
+    }
+  }
+
+  ///
+  /// Returns the subset of the outputs of a batch which the given process declared.
+  ///
+  async fn output_subset(&self, process: &Process, output_directory: Digest) -> ProcessResult {
+    let globs = process
+      .output_files
+      .iter()
+      .map(|path| path.to_str().unwrap().to_owned())
+      .chain(
+        process
+          .output_directories
+          .iter()
+          .chain(process.output_paths.iter())
+          .flat_map(|path| {
+            let path = path.to_str().unwrap();
+            vec![path.to_owned(), format!("{}/**", path)]
+          }),
+      )
+      .collect::<Vec<_>>();
+    if globs.is_empty() {
+      return Ok(EMPTY_DIGEST);
+    }
+    let globs = PathGlobs::new(
+      globs,
+      StrictGlobMatching::Ignore,
+      GlobExpansionConjunction::AnyMatch,
+    )
+    .parse()?;
+    self
+      .store
+      .subset(output_directory, SubsetParams { globs })
+      .await
+      .map_err(|e| format!("{:?}", e))
+  }
+}
+
+///
+/// The batches which are waiting to run are keyed by the `batch_key` of their processes, and the
+/// id of the Session which requested them.
+///
+type BatchKey = (Process, String);
+
+///
+/// The key of the batches that the given Process may join: the Process without its items or
+/// outputs. Returns None if the Process may not be batched.
+///
+pub fn batch_key(process: &Process) -> Option<Process> {
+  let prefix_len = process.batch_argv_prefix_len?;
+  // A depfile is written to a single path, and so cannot be shared by a batch.
+  if prefix_len > process.argv.len() || process.discovered_inputs_depfile.is_some() {
+    return None;
+  }
+  let mut key = process.clone();
+  key.argv.truncate(prefix_len);
+  key.output_files.clear();
+  key.output_directories.clear();
+  key.output_paths.clear();
+  key.source_output_files.clear();
+  Some(key)
+}
+
+///
+/// Merges compatible processes (which have equal `batch_key`s) into a single Process, which runs
+/// all of their items, and captures all of their outputs.
+///
+pub fn batch_process(processes: &[&Process]) -> Process {
+  let first = processes[0];
+  let prefix_len = first.batch_argv_prefix_len.unwrap_or(first.argv.len());
+  let mut batch = first.clone();
+  for process in &processes[1..] {
+    batch
+      .argv
+      .extend(process.argv[prefix_len..].iter().cloned());
+    batch
+      .output_files
+      .extend(process.output_files.iter().cloned());
+    batch
+      .output_directories
+      .extend(process.output_directories.iter().cloned());
+    batch
+      .output_paths
+      .extend(process.output_paths.iter().cloned());
+    batch
+      .source_output_files
+      .extend(process.source_output_files.iter().cloned());
+  }
+  batch.timeout = processes.iter().map(|process| process.timeout).sum();
+  batch.description = format!(
+    "{} (batched with {} other processes)",
+    first.description,
+    processes.len() - 1
+  );
+  batch
+}
+
+#[async_trait]
+impl crate::CommandRunner for CommandRunner {
+  async fn run(&self, req: MultiPlatformProcess, context: Context) -> ProcessResult {
+    let key = match self.underlying.extract_compatible_request(&req) {
+      Some(process) if self.max_batch_size > 1 => {
+        batch_key(&process).map(|key| ((key, context.invocation_id.clone()), process))
+      }
+      _ => None,
+    };
+    let (key, process) = match key {
+      Some(key) => key,
+      None => return self.underlying.run(req, context).await,
+    };
+
+    let (sender, receiver) = oneshot::channel();
+    let item = (process, context, get_workunit_store_handle(), sender);
+    {
+      let mut pending = self.pending.lock();
+      match pending.get_mut(&key) {
+        Some((_, items)) => items.push(item),
+        None => {
+          let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+          pending.insert(key.clone(), (id, vec![item]));
+          self.run_after_window(key.clone(), id);
+        }
+      }
+      if pending.get(&key).map(|(_, items)| items.len()) >= Some(self.max_batch_size) {
+        let (_, items) = pending.remove(&key).unwrap();
+        let _join = self.executor.spawn(self.clone().run_batch(items));
+      }
+    }
+
+    receiver.await.map_err(|_| {
+      format!(
+        "The batch containing `{}` was canceled.",
+        req.user_facing_name()
+      )
+    })?
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    self.underlying.extract_compatible_request(req)
+  }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use fs::RelativePath;
+use hashing::EMPTY_DIGEST;
+use store::Store;
+use tempfile::TempDir;
+use testutil::data::TestDirectory;
+use testutil::owned_string_vec;
+
+use crate::batching::{batch_key, batch_process};
+use crate::{
+  CommandRunner as CommandRunnerTrait, Context, FallibleProcessResultWithPlatform,
+  MultiPlatformProcess, Platform, Process, ProcessResultMetadata,
+};
+
+/// A mock runner which records the argv of each Process that it runs, and fails any Process which
+/// has a "bad" argument. Every Process outputs `roland` and `treats`.
+#[derive(Clone, Default)]
+struct MockCommandRunner {
+  calls: Arc<parking_lot::Mutex<Vec<Vec<String>>>>,
+}
+
+#[async_trait]
+impl CommandRunnerTrait for MockCommandRunner {
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    _context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    let process = self.extract_compatible_request(&req).unwrap();
+    let exit_code = if process.argv.iter().any(|arg| arg == "bad") {
+      1
+    } else {
+      0
+    };
+    self.calls.lock().push(process.argv);
+    Ok(FallibleProcessResultWithPlatform {
+      stdout_digest: EMPTY_DIGEST,
+      stderr_digest: EMPTY_DIGEST,
+      exit_code,
+      output_directory: TestDirectory::containing_roland_and_treats().digest(),
+      platform: Platform::Linux,
+      metadata: ProcessResultMetadata::default(),
+    })
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    req.0.get(&None).cloned()
+  }
+}
+
+async fn batching_runner(
+  underlying: &MockCommandRunner,
+  max_batch_size: usize,
+) -> (crate::batching::CommandRunner, TempDir) {
+  let store_dir = TempDir::new().unwrap();
+  let executor = task_executor::Executor::new();
+  let store = Store::local_only(executor.clone(), store_dir.path()).unwrap();
+  store
+    .record_directory(
+      &TestDirectory::containing_roland_and_treats().directory(),
+      true,
+    )
+    .await
+    .unwrap();
+  let runner = crate::batching::CommandRunner::new(
+    Arc::new(underlying.clone()),
+    store,
+    executor,
+    max_batch_size,
+    Duration::from_millis(100),
+  );
+  (runner, store_dir)
+}
+
+fn lint(item: &str, output_file: Option<&str>) -> Process {
+  let mut process = Process::new(owned_string_vec(&["/bin/lint", "--strict", item]));
+  process.description = format!("Linting {}", item);
+  process.batch_argv_prefix_len = Some(2);
+  process.output_files = output_file
+    .into_iter()
+    .map(|path| RelativePath::new(path).unwrap())
+    .collect();
+  process
+}
+
+#[test]
+fn batch_key_ignores_items_and_outputs() {
+  let roland = lint("roland.py", Some("roland"));
+  let treats = lint("treats.py", Some("treats"));
+  assert_eq!(batch_key(&roland), batch_key(&treats));
+  assert!(batch_key(&roland).is_some());
+
+  let mut unbatched = lint("roland.py", None);
+  unbatched.batch_argv_prefix_len = None;
+  assert_eq!(batch_key(&unbatched), None);
+
+  let mut other_tool = lint("treats.py", None);
+  other_tool.argv[1] = "--lenient".to_owned();
+  assert_ne!(batch_key(&roland), batch_key(&other_tool));
+}
+
+#[test]
+fn batch_process_merges_items_and_outputs() {
+  let mut roland = lint("roland.py", Some("roland"));
+  roland.timeout = Some(Duration::from_secs(1));
+  let mut treats = lint("treats.py", Some("treats"));
+  treats.timeout = Some(Duration::from_secs(2));
+
+  let batch = batch_process(&[&roland, &treats]);
+  assert_eq!(
+    batch.argv,
+    owned_string_vec(&["/bin/lint", "--strict", "roland.py", "treats.py"])
+  );
+  assert_eq!(
+    batch.output_files,
+    vec!["roland", "treats"]
+      .into_iter()
+      .map(|path| RelativePath::new(path).unwrap())
+      .collect::<BTreeSet<_>>()
+  );
+  assert_eq!(batch.timeout, Some(Duration::from_secs(3)));
+}
+
+#[tokio::test]
+async fn compatible_processes_are_batched() {
+  let underlying = MockCommandRunner::default();
+  let (runner, _store_dir) = batching_runner(&underlying, 10).await;
+
+  let (roland, treats) = futures::join!(
+    runner.run(lint("roland.py", Some("roland")).into(), Context::default()),
+    runner.run(lint("treats.py", Some("treats")).into(), Context::default()),
+  );
+
+  assert_eq!(
+    *underlying.calls.lock(),
+    vec![owned_string_vec(&[
+      "/bin/lint",
+      "--strict",
+      "roland.py",
+      "treats.py"
+    ])]
+  );
+  // Each process receives only the outputs that it declared, and its result is marked as batched
+  // (so that it is not cached).
+  let roland = roland.unwrap();
+  let treats = treats.unwrap();
+  assert_eq!(
+    roland.output_directory,
+    TestDirectory::containing_roland().digest()
+  );
+  assert_eq!(
+    treats.output_directory,
+    TestDirectory::containing_treats().digest()
+  );
+  assert!(roland.metadata.batched);
+  assert!(treats.metadata.batched);
+}
+
+#[tokio::test]
+async fn processes_of_different_sessions_are_not_batched() {
+  let underlying = MockCommandRunner::default();
+  let (runner, _store_dir) = batching_runner(&underlying, 10).await;
+
+  let other_session = Context {
+    invocation_id: "other".to_owned(),
+    ..Context::default()
+  };
+  let (roland, treats) = futures::join!(
+    runner.run(lint("roland.py", None).into(), Context::default()),
+    runner.run(lint("treats.py", None).into(), other_session),
+  );
+  assert!(!roland.unwrap().metadata.batched);
+  assert!(!treats.unwrap().metadata.batched);
+
+  let mut calls = underlying.calls.lock().clone();
+  calls.sort();
+  assert_eq!(
+    calls,
+    vec![
+      owned_string_vec(&["/bin/lint", "--strict", "roland.py"]),
+      owned_string_vec(&["/bin/lint", "--strict", "treats.py"]),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn full_batches_run_separately() {
+  let underlying = MockCommandRunner::default();
+  let (runner, _store_dir) = batching_runner(&underlying, 2).await;
+
+  let (first, second, third) = futures::join!(
+    runner.run(lint("a.py", None).into(), Context::default()),
+    runner.run(lint("b.py", None).into(), Context::default()),
+    runner.run(lint("c.py", None).into(), Context::default()),
+  );
+  assert_eq!(first.unwrap().output_directory, EMPTY_DIGEST);
+  second.unwrap();
+  third.unwrap();
+
+  let mut calls = underlying.calls.lock().clone();
+  calls.sort();
+  assert_eq!(
+    calls,
+    vec![
+      owned_string_vec(&["/bin/lint", "--strict", "a.py", "b.py"]),
+      owned_string_vec(&["/bin/lint", "--strict", "c.py"]),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn failed_batches_are_split() {
+  let underlying = MockCommandRunner::default();
+  let (runner, _store_dir) = batching_runner(&underlying, 10).await;
+
+  let (good, bad) = futures::join!(
+    runner.run(lint("good", None).into(), Context::default()),
+    runner.run(lint("bad", None).into(), Context::default()),
+  );
+  // The results of the individual runs are not marked as batched.
+  let good = good.unwrap();
+  assert_eq!(good.exit_code, 0);
+  assert!(!good.metadata.batched);
+  assert_eq!(bad.unwrap().exit_code, 1);
+
+  let mut calls = underlying.calls.lock().clone();
+  calls.sort();
+  assert_eq!(
+    calls,
+    vec![
+      owned_string_vec(&["/bin/lint", "--strict", "bad"]),
+      owned_string_vec(&["/bin/lint", "--strict", "good"]),
+      owned_string_vec(&["/bin/lint", "--strict", "good", "bad"]),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn unbatched_processes_run_directly() {
+  let underlying = MockCommandRunner::default();
+  let (runner, _store_dir) = batching_runner(&underlying, 10).await;
+
+  let mut process = lint("roland.py", None);
+  process.batch_argv_prefix_len = None;
+  runner
+    .run(process.into(), Context::default())
+    .await
+    .unwrap();
+
+  assert_eq!(
+    *underlying.calls.lock(),
+    vec![owned_string_vec(&["/bin/lint", "--strict", "roland.py"])]
+  );
+}
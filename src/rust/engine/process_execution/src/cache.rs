@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::sync::Arc;
 use std::time::Instant;
 
@@ -5,12 +6,13 @@ use async_trait::async_trait;
 use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
 use bytes::Bytes;
 use futures::{future, FutureExt};
-use hashing::Fingerprint;
+use hashing::{Digest, Fingerprint};
 use log::{debug, warn};
 use prost::Message;
 use serde::{Deserialize, Serialize};
 use sharded_lmdb::ShardedLmdb;
-use store::Store;
+use store::{GcRoots, RootLifetime, Store};
+use task_executor::IoPriority;
 use workunit_store::{with_workunit, Level, Metric, ObservationMetric, WorkunitMetadata};
 
 use crate::{
@@ -67,7 +69,13 @@ impl crate::CommandRunner for CommandRunner {
         .workunit_store
         .increment_counter(Metric::LocalCacheRequests, 1);
 
-      let digest = crate::digest(req.clone(), &self.metadata);
+      // Key on the Process which will actually run, so that requests which differ only in their
+      // incompatible variants share a cache entry.
+      let cache_key_request = match self.extract_compatible_request(&req) {
+        Some(compatible_request) => compatible_request.into(),
+        None => req.clone(),
+      };
+      let digest = crate::digest(cache_key_request, &self.metadata);
       let key = digest.hash;
 
       let cache_failures = req
@@ -113,7 +121,13 @@ impl crate::CommandRunner for CommandRunner {
       }
 
       let result = command_runner.underlying.run(req, context.clone()).await?;
-      if result.exit_code == 0 || cache_failures {
+      // NB: A process which was stopped because it exceeded one of its resource limits might not
+      // exceed it when it runs again (and its `exit_reason` is not persisted), so it is not cached.
+      // Nor is the result of a process which ran as part of a batch.
+      if (result.exit_code == 0 || cache_failures)
+        && result.metadata.exit_reason.is_none()
+        && !result.metadata.batched
+      {
         if let Err(err) = command_runner.store(key, &result).await {
           warn!(
             "Error storing process execution result to local cache: {} - ignoring and continuing",
@@ -197,6 +211,19 @@ impl CommandRunner {
     ])
     .await?;
 
+    // Lease the entry, so that its outputs continue to be referenced by it during garbage
+    // collection: see `gc_roots`.
+    if let Err(err) = self
+      .process_execution_store
+      .lease(fingerprint, IoPriority::Default)
+      .await
+    {
+      debug!(
+        "Failed to lease process cache entry {:?}: {}",
+        fingerprint, err
+      );
+    }
+
     Ok(Some(result))
   }
 
@@ -208,6 +235,12 @@ impl CommandRunner {
     let stdout_digest = result.stdout_digest;
     let stderr_digest = result.stderr_digest;
 
+    // The outputs of a cached result must outlive the session which produced them.
+    self
+      .file_store
+      .commit_staged([stdout_digest, stderr_digest, result.output_directory].iter())
+      .await?;
+
     let action_result = remexec::ActionResult {
       exit_code: result.exit_code,
       output_directories: vec![remexec::OutputDirectory {
@@ -225,8 +258,8 @@ impl CommandRunner {
       ..remexec::ExecuteResponse::default()
     };
 
-    // TODO: Should probably have a configurable lease time which is larger than default.
-    // (This isn't super urgent because we don't ever actually GC this store. So also...)
+    // NB: The lease of an entry determines how long its outputs are referenced during garbage
+    // collection of the Store: see `gc_roots`.
     // TODO: GC the local process execution cache.
 
     let mut response_bytes = Vec::with_capacity(execute_response.encoded_len());
@@ -248,7 +281,44 @@ impl CommandRunner {
 
     self
       .process_execution_store
-      .store_bytes(fingerprint, bytes_to_store, false)
+      .store_bytes(fingerprint, bytes_to_store, true)
       .await
   }
 }
+
+///
+/// The outputs of the results in the given local process cache, as roots for garbage collection of
+/// the Store: each result references its outputs until its lease (which is renewed whenever it is
+/// used) expires.
+///
+pub async fn gc_roots(process_execution_store: &ShardedLmdb) -> Result<GcRoots, String> {
+  let entries = process_execution_store
+    .load_all_with_leases(|bytes| {
+      let decoded: PlatformAndResponseBytes = bincode::deserialize(bytes)
+        .map_err(|err| format!("Could not deserialize platform and response: {}", err))?;
+      remexec::ExecuteResponse::decode(&decoded.response_bytes[..])
+        .map_err(|e| format!("Invalid ExecuteResponse: {:?}", e))
+    })
+    .await?;
+
+  let mut roots = GcRoots::new();
+  for (_, execute_response, leased_until) in entries {
+    let action_result = if let Some(action_result) = execute_response.result {
+      action_result
+    } else {
+      continue;
+    };
+    let digests = vec![action_result.stdout_digest, action_result.stderr_digest]
+      .into_iter()
+      .chain(
+        action_result
+          .output_directories
+          .into_iter()
+          .map(|output_directory| output_directory.tree_digest),
+      )
+      .flatten()
+      .filter_map(|digest| Digest::try_from(&digest).ok());
+    roots.extend(digests.map(|digest| (digest, RootLifetime::Until(leased_until))));
+  }
+  Ok(roots)
+}
@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
+use bytes::Bytes;
+use hashing::{Digest, Fingerprint};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sharded_lmdb::ShardedLmdb;
+use store::Store;
+
+use crate::{FallibleProcessResultWithPlatform, Platform};
+
+/// Entries which have not been backfilled after this long are discarded, because the local store
+/// may have garbage collected their outputs, and teammates are unlikely to still need them.
+const MAX_ENTRY_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Serialize, Deserialize)]
+struct BackfillRecord {
+  recorded_at_secs: u64,
+  action_size_bytes: usize,
+  platform: Platform,
+  // An encoded `ActionResult`, in the format used by the local process cache (i.e.: with the
+  // digest of the output root directory in place of a Tree digest).
+  action_result_bytes: Vec<u8>,
+}
+
+///
+/// An entry of the local action cache which should be uploaded to the remote cache.
+///
+pub struct BackfillEntry {
+  pub action_digest: Digest,
+  pub platform: Platform,
+  pub action_result: remexec::ActionResult,
+}
+
+///
+/// A journal of recent results of processes which ran locally without being written to the
+/// remote cache, which is drained in the background by the remote cache `CommandRunner` (at low
+/// priority, and with a delay between entries) in order to seed the remote cache for other
+/// machines without slowing down the runs which produced the results.
+///
+/// The journal is persistent, so entries which were recorded by a previous run are backfilled by
+/// the next one.
+///
+pub struct CacheBackfill {
+  journal: ShardedLmdb,
+  store: Store,
+  interval: Duration,
+  draining: AtomicBool,
+}
+
+impl CacheBackfill {
+  pub fn new(journal: ShardedLmdb, store: Store, interval: Duration) -> CacheBackfill {
+    CacheBackfill {
+      journal,
+      store,
+      interval,
+      draining: AtomicBool::new(false),
+    }
+  }
+
+  ///
+  /// The delay between uploads of entries.
+  ///
+  pub fn interval(&self) -> Duration {
+    self.interval
+  }
+
+  ///
+  /// Records a successful result for the given Action (which must already be stored locally), so
+  /// that it will be backfilled.
+  ///
+  pub async fn record(
+    &self,
+    action_digest: Digest,
+    result: &FallibleProcessResultWithPlatform,
+  ) -> Result<(), String> {
+    // The outputs must outlive the session which produced them in order to be uploaded later.
+    self
+      .store
+      .commit_staged(
+        [
+          result.stdout_digest,
+          result.stderr_digest,
+          result.output_directory,
+        ]
+        .iter(),
+      )
+      .await?;
+
+    let action_result = remexec::ActionResult {
+      exit_code: result.exit_code,
+      output_directories: vec![remexec::OutputDirectory {
+        path: String::new(),
+        tree_digest: Some((&result.output_directory).into()),
+      }],
+      stdout_digest: Some((&result.stdout_digest).into()),
+      stderr_digest: Some((&result.stderr_digest).into()),
+      execution_metadata: Some(result.metadata.clone().into()),
+      ..remexec::ActionResult::default()
+    };
+    let mut action_result_bytes = Vec::with_capacity(action_result.encoded_len());
+    action_result
+      .encode(&mut action_result_bytes)
+      .map_err(|e| format!("Error serializing action result for backfill: {}", e))?;
+
+    let bytes = bincode::serialize(&BackfillRecord {
+      recorded_at_secs: now_secs(),
+      action_size_bytes: action_digest.size_bytes,
+      platform: result.platform,
+      action_result_bytes,
+    })
+    .map(Bytes::from)
+    .map_err(|e| format!("Error serializing backfill record: {}", e))?;
+    self
+      .journal
+      .store_bytes(action_digest.hash, bytes, false)
+      .await
+  }
+
+  ///
+  /// Returns the entries which are waiting to be backfilled, most recent first, and excluding the
+  /// given Actions (which have already been attempted). Entries which are too old to be worth
+  /// backfilling are discarded.
+  ///
+  pub async fn pending(
+    &self,
+    attempted: &HashSet<Fingerprint>,
+  ) -> Result<Vec<BackfillEntry>, String> {
+    let records = self
+      .journal
+      .load_all_with(|bytes| {
+        bincode::deserialize::<BackfillRecord>(bytes)
+          .map_err(|e| format!("Could not deserialize backfill record: {}", e))
+      })
+      .await?;
+
+    let oldest_secs = now_secs().saturating_sub(MAX_ENTRY_AGE.as_secs());
+    let mut entries = Vec::new();
+    for (fingerprint, record) in records {
+      if record.recorded_at_secs < oldest_secs {
+        self.remove(fingerprint).await?;
+        continue;
+      }
+      if attempted.contains(&fingerprint) {
+        continue;
+      }
+      let action_result = remexec::ActionResult::decode(&record.action_result_bytes[..])
+        .map_err(|e| format!("Invalid ActionResult in backfill record: {:?}", e))?;
+      entries.push((
+        record.recorded_at_secs,
+        BackfillEntry {
+          action_digest: Digest::new(fingerprint, record.action_size_bytes),
+          platform: record.platform,
+          action_result,
+        },
+      ));
+    }
+    entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+    Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+  }
+
+  pub async fn remove(&self, action_fingerprint: Fingerprint) -> Result<(), String> {
+    self.journal.remove(action_fingerprint).await.map(|_| ())
+  }
+
+  ///
+  /// Marks the journal as being drained, returning false if it was already being drained.
+  ///
+  pub fn start_draining(&self) -> bool {
+    !self.draining.swap(true, Ordering::SeqCst)
+  }
+
+  pub fn finish_draining(&self) {
+    self.draining.store(false, Ordering::SeqCst);
+  }
+}
+
+fn now_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .expect("Surely you're not before the unix epoch?")
+    .as_secs()
+}
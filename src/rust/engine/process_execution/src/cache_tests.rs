@@ -1,9 +1,10 @@
 use std::convert::TryInto;
 use std::io::Write;
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 use sharded_lmdb::{ShardedLmdb, DEFAULT_LEASE_TIME};
-use store::Store;
+use store::{RootLifetime, Store};
 use tempfile::TempDir;
 use testutil::data::TestData;
 use testutil::relative_paths;
@@ -11,7 +12,7 @@ use workunit_store::WorkunitStore;
 
 use crate::{
   CommandRunner as CommandRunnerTrait, Context, FallibleProcessResultWithPlatform, NamedCaches,
-  Process, ProcessMetadata,
+  Process, ProcessCacheScope, ProcessMetadata,
 };
 
 struct RoundtripResults {
@@ -30,7 +31,8 @@ fn create_local_runner() -> (Box<dyn CommandRunnerTrait>, Store, TempDir) {
     runtime.clone(),
     base_dir.path().to_owned(),
     NamedCaches::new(named_cache_dir),
-    true,
+    crate::local::KeepSandboxes::Never,
+    crate::Redactor::default(),
   ));
   (runner, store, base_dir)
 }
@@ -38,7 +40,7 @@ fn create_local_runner() -> (Box<dyn CommandRunnerTrait>, Store, TempDir) {
 fn create_cached_runner(
   local: Box<dyn CommandRunnerTrait>,
   store: Store,
-) -> (Box<dyn CommandRunnerTrait>, TempDir) {
+) -> (Box<dyn CommandRunnerTrait>, ShardedLmdb, TempDir) {
   let runtime = task_executor::Executor::new();
   let cache_dir = TempDir::new().unwrap();
   let max_lmdb_size = 50 * 1024 * 1024; //50 MB - I didn't pick that number but it seems reasonable.
@@ -54,12 +56,12 @@ fn create_cached_runner(
 
   let runner = Box::new(crate::cache::CommandRunner::new(
     local.into(),
-    process_execution_store,
+    process_execution_store.clone(),
     store,
     ProcessMetadata::default(),
   ));
 
-  (runner, cache_dir)
+  (runner, process_execution_store, cache_dir)
 }
 
 fn create_script(script_exit_code: i8) -> (Process, PathBuf, TempDir) {
@@ -91,7 +93,7 @@ async fn run_roundtrip(script_exit_code: i8) -> RoundtripResults {
 
   let local_result = local.run(process.clone().into(), Context::default()).await;
 
-  let (caching, _cache_dir) = create_cached_runner(local, store.clone());
+  let (caching, _, _cache_dir) = create_cached_runner(local, store.clone());
 
   let uncached_result = caching
     .run(process.clone().into(), Context::default())
@@ -127,12 +129,44 @@ async fn failures_not_cached() {
   assert_eq!(results.maybe_cached.unwrap().exit_code, 127); // aka the return code for file not found
 }
 
+#[tokio::test]
+#[cfg(unix)]
+async fn resource_limit_failures_not_cached() {
+  WorkunitStore::setup_for_tests();
+
+  let (local, store, _local_runner_dir) = create_local_runner();
+  let (caching, _, _cache_dir) = create_cached_runner(local, store.clone());
+  let script_dir = TempDir::new().unwrap();
+  let script_path = script_dir.path().join("script");
+  std::fs::write(&script_path, "while true; do :; done").unwrap();
+  let mut process = Process::new(vec![
+    testutil::path::find_bash(),
+    format!("{}", script_path.display()),
+  ]);
+  process.cache_scope = ProcessCacheScope::Always;
+  process.resource_limits.cpu_seconds = Some(1);
+
+  let result = caching
+    .run(process.clone().into(), Context::default())
+    .await
+    .unwrap();
+  assert_eq!(result.exit_code, -libc::SIGXCPU);
+
+  // Although failures are cached for this process, one which exceeded a limit is not.
+  std::fs::remove_file(&script_path).unwrap();
+  let result = caching
+    .run(process.into(), Context::default())
+    .await
+    .unwrap();
+  assert_eq!(result.exit_code, 127);
+}
+
 #[tokio::test]
 async fn recover_from_missing_store_contents() {
   WorkunitStore::setup_for_tests();
 
   let (local, store, _local_runner_dir) = create_local_runner();
-  let (caching, _cache_dir) = create_cached_runner(local, store.clone());
+  let (caching, _, _cache_dir) = create_cached_runner(local, store.clone());
   let (process, _script_path, _script_dir) = create_script(0);
 
   // Run once to cache the process.
@@ -181,3 +215,30 @@ async fn recover_from_missing_store_contents() {
     .ok()
     .is_some())
 }
+
+#[tokio::test]
+async fn cached_results_are_gc_roots() {
+  WorkunitStore::setup_for_tests();
+  let (local, store, _local_runner_dir) = create_local_runner();
+  let (process, _script_path, _script_dir) = create_script(0);
+  let (caching, process_execution_store, _cache_dir) = create_cached_runner(local, store);
+
+  let result = caching
+    .run(process.into(), Context::default())
+    .await
+    .unwrap();
+
+  let roots = crate::cache::gc_roots(&process_execution_store)
+    .await
+    .unwrap();
+  for digest in &[
+    result.stdout_digest,
+    result.stderr_digest,
+    result.output_directory,
+  ] {
+    match roots.lifetime(digest) {
+      Some(RootLifetime::Until(leased_until)) => assert!(leased_until > SystemTime::now()),
+      other => panic!("Want a leased root for {:?}, got {:?}", digest, other),
+    }
+  }
+}
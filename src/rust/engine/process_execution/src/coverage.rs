@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::Write as IoWrite;
+use std::path::PathBuf;
+
+use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
+
+///
+/// Merges LCOV tracefiles (as emitted by `geninfo`, `c8` or `grcov`, for example) into a single
+/// tracefile.
+///
+/// Line, function and branch hit counts for the same source file are summed, and the summary
+/// counts (`LF`, `LH`, `FNF`, `FNH`, `BRF` and `BRH`) are recomputed from the merged data. Test
+/// names and line checksums are dropped, and records are emitted in sorted order so that the output
+/// is deterministic regardless of the order of the inputs.
+///
+pub fn merge_lcov(reports: &[&[u8]]) -> Result<Vec<u8>, String> {
+  let mut files: BTreeMap<String, LcovSourceFile> = BTreeMap::new();
+  for (report_index, report) in reports.iter().enumerate() {
+    parse_lcov(report, &mut files)
+      .map_err(|e| format!("Failed to parse LCOV report {}: {}", report_index, e))?;
+  }
+
+  let mut output = String::new();
+  for (path, file) in files {
+    file.write_to(&path, &mut output);
+  }
+  Ok(output.into_bytes())
+}
+
+#[derive(Default)]
+struct LcovSourceFile {
+  // Function name to the line on which it is declared.
+  functions: BTreeMap<String, u64>,
+  // Function name to the number of times it was called.
+  function_hits: BTreeMap<String, u64>,
+  // (line, block, branch) to the number of times it was taken, or None if it was never evaluated.
+  branches: BTreeMap<(u64, u64, u64), Option<u64>>,
+  // Line number to the number of times it was executed.
+  lines: BTreeMap<u64, u64>,
+}
+
+impl LcovSourceFile {
+  fn write_to(&self, path: &str, output: &mut String) {
+    let mut functions = self
+      .functions
+      .iter()
+      .map(|(name, line)| (*line, name))
+      .collect::<Vec<_>>();
+    functions.sort();
+
+    writeln!(output, "SF:{}", path).unwrap();
+    for (line, name) in &functions {
+      writeln!(output, "FN:{},{}", line, name).unwrap();
+    }
+    for (_, name) in &functions {
+      let hits = self.function_hits.get(*name).cloned().unwrap_or(0);
+      writeln!(output, "FNDA:{},{}", hits, name).unwrap();
+    }
+    if !functions.is_empty() {
+      writeln!(output, "FNF:{}", functions.len()).unwrap();
+      let functions_hit = functions
+        .iter()
+        .filter(|(_, name)| self.function_hits.get(*name).cloned().unwrap_or(0) > 0)
+        .count();
+      writeln!(output, "FNH:{}", functions_hit).unwrap();
+    }
+    for ((line, block, branch), taken) in &self.branches {
+      match taken {
+        Some(taken) => writeln!(output, "BRDA:{},{},{},{}", line, block, branch, taken).unwrap(),
+        None => writeln!(output, "BRDA:{},{},{},-", line, block, branch).unwrap(),
+      }
+    }
+    if !self.branches.is_empty() {
+      writeln!(output, "BRF:{}", self.branches.len()).unwrap();
+      let branches_hit = self
+        .branches
+        .values()
+        .filter(|taken| taken.unwrap_or(0) > 0)
+        .count();
+      writeln!(output, "BRH:{}", branches_hit).unwrap();
+    }
+    for (line, hits) in &self.lines {
+      writeln!(output, "DA:{},{}", line, hits).unwrap();
+    }
+    writeln!(output, "LF:{}", self.lines.len()).unwrap();
+    let lines_hit = self.lines.values().filter(|hits| **hits > 0).count();
+    writeln!(output, "LH:{}", lines_hit).unwrap();
+    writeln!(output, "end_of_record").unwrap();
+  }
+}
+
+fn parse_lcov(report: &[u8], files: &mut BTreeMap<String, LcovSourceFile>) -> Result<(), String> {
+  let report =
+    std::str::from_utf8(report).map_err(|e| format!("Report is not valid UTF-8: {}", e))?;
+  let mut current: Option<&mut LcovSourceFile> = None;
+  for (line_index, line) in report.lines().enumerate() {
+    let line_number = line_index + 1;
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("TN:") {
+      continue;
+    }
+    if line == "end_of_record" {
+      current = None;
+      continue;
+    }
+    let (key, value) = match line.find(':') {
+      Some(i) => (&line[..i], &line[i + 1..]),
+      None => {
+        return Err(format!(
+          "Unexpected content on line {}: {}",
+          line_number, line
+        ))
+      }
+    };
+    if key == "SF" {
+      current = Some(files.entry(value.to_owned()).or_default());
+      continue;
+    }
+    let file = match current.as_mut() {
+      Some(file) => file,
+      None => {
+        return Err(format!(
+          "Line {} is not within a source file record: {}",
+          line_number, line
+        ))
+      }
+    };
+    let fields = value.split(',').collect::<Vec<_>>();
+    let parse_err = |e: String| format!("Failed to parse line {} ({}): {}", line_number, line, e);
+    match key {
+      "FN" if fields.len() >= 2 => {
+        let line = parse_count(fields[0]).map_err(parse_err)?;
+        file.functions.insert(fields[1..].join(","), line);
+      }
+      "FNDA" if fields.len() >= 2 => {
+        let hits = parse_count(fields[0]).map_err(parse_err)?;
+        let total = file.function_hits.entry(fields[1..].join(",")).or_insert(0);
+        *total = total.saturating_add(hits);
+      }
+      "BRDA" if fields.len() == 4 => {
+        let key = (
+          parse_count(fields[0]).map_err(parse_err)?,
+          parse_count(fields[1]).map_err(parse_err)?,
+          parse_count(fields[2]).map_err(parse_err)?,
+        );
+        let taken = match fields[3] {
+          "-" => None,
+          taken => Some(parse_count(taken).map_err(parse_err)?),
+        };
+        let total = file.branches.entry(key).or_insert(None);
+        *total = match (*total, taken) {
+          (Some(a), Some(b)) => Some(a.saturating_add(b)),
+          (a, b) => a.or(b),
+        };
+      }
+      "DA" if fields.len() >= 2 => {
+        // NB: An optional third field contains a checksum of the line, which we drop.
+        let line = parse_count(fields[0]).map_err(parse_err)?;
+        let hits = parse_count(fields[1]).map_err(parse_err)?;
+        let total = file.lines.entry(line).or_insert(0);
+        *total = total.saturating_add(hits);
+      }
+      "FN" | "FNDA" | "BRDA" | "DA" => {
+        return Err(parse_err("Unexpected number of fields.".to_owned()));
+      }
+      // Summary counts are recomputed when the merged report is written, and any other keys are
+      // not understood, and so are dropped.
+      _ => (),
+    }
+  }
+  Ok(())
+}
+
+fn parse_count(value: &str) -> Result<u64, String> {
+  value
+    .parse::<u64>()
+    .map_err(|e| format!("Invalid count `{}`: {}", value, e))
+}
+
+///
+/// Merges coverage.py SQLite data files (as written by `coverage run --parallel-mode`) into a single
+/// data file, equivalent to running `coverage combine`.
+///
+/// Measured files, contexts, arcs and file tracers are unioned, and the line numbers measured for
+/// each file and context are combined. All of the data files must use the same schema version,
+/// and must all measure either lines or arcs (branches).
+///
+pub fn merge_coverage_py(data_files: &[&[u8]]) -> Result<Vec<u8>, String> {
+  let (first, rest) = match data_files.split_first() {
+    Some(split) => split,
+    None => return Err("No coverage.py data files to merge.".to_owned()),
+  };
+  let tempdir = tempfile::Builder::new()
+    .prefix("coverage-merge")
+    .tempdir()
+    .map_err(|e| format!("Failed to create a temporary directory: {}", e))?;
+  let write_data_file = |name: &str, content: &[u8]| -> Result<PathBuf, String> {
+    let path = tempdir.path().join(name);
+    std::fs::File::create(&path)
+      .and_then(|mut file| file.write_all(content))
+      .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+  };
+
+  let merged_path = write_data_file("merged", first)?;
+  let mut merged = Connection::open(&merged_path)
+    .map_err(|e| format!("Failed to open coverage.py data file 0: {}", e))?;
+  for (index, data_file) in rest.iter().enumerate() {
+    let index = index + 1;
+    let path = write_data_file(&format!("{}", index), data_file)?;
+    merge_coverage_py_into(&mut merged, &path.to_string_lossy())
+      .map_err(|e| format!("Failed to merge coverage.py data file {}: {}", index, e))?;
+  }
+  merged
+    .close()
+    .map_err(|(_, e)| format!("Failed to close merged coverage.py data file: {}", e))?;
+
+  std::fs::read(&merged_path).map_err(|e| format!("Failed to read merged data file: {}", e))
+}
+
+fn merge_coverage_py_into(merged: &mut Connection, path: &str) -> Result<(), String> {
+  let sql_err = |e: rusqlite::Error| format!("{}", e);
+  merged
+    .execute("ATTACH DATABASE ?1 AS other", params![path])
+    .map_err(sql_err)?;
+
+  let schema_version = |schema: &str| {
+    merged
+      .query_row(
+        &format!("SELECT version FROM {}.coverage_schema", schema),
+        NO_PARAMS,
+        |row| row.get::<_, i64>(0),
+      )
+      .map_err(|e| format!("Failed to read schema version: {}", e))
+  };
+  let (merged_version, other_version) = (schema_version("main")?, schema_version("other")?);
+  if merged_version != other_version {
+    return Err(format!(
+      "Cannot merge schema version {} into schema version {}.",
+      other_version, merged_version
+    ));
+  }
+
+  let has_arcs = |schema: &str| {
+    merged
+      .query_row(
+        &format!("SELECT value FROM {}.meta WHERE key = 'has_arcs'", schema),
+        NO_PARAMS,
+        |row| row.get::<_, String>(0),
+      )
+      .optional()
+      .map_err(sql_err)
+  };
+  match (has_arcs("main")?, has_arcs("other")?) {
+    (Some(merged_arcs), Some(other_arcs)) if merged_arcs != other_arcs => {
+      return Err("Cannot merge line data with arc (branch) data.".to_owned());
+    }
+    (None, Some(other_arcs)) => {
+      merged
+        .execute(
+          "INSERT INTO main.meta (key, value) VALUES ('has_arcs', ?1)",
+          params![other_arcs],
+        )
+        .map_err(sql_err)?;
+    }
+    _ => (),
+  }
+
+  let tx = merged.transaction().map_err(sql_err)?;
+  tx.execute_batch(
+    "
+    INSERT OR IGNORE INTO main.file (path) SELECT path FROM other.file;
+    INSERT OR IGNORE INTO main.context (context) SELECT context FROM other.context;
+    INSERT OR IGNORE INTO main.arc (file_id, context_id, fromno, tono)
+      SELECT main_file.id, main_context.id, arc.fromno, arc.tono
+      FROM other.arc AS arc
+      JOIN other.file AS file ON arc.file_id = file.id
+      JOIN main.file AS main_file ON main_file.path = file.path
+      JOIN other.context AS context ON arc.context_id = context.id
+      JOIN main.context AS main_context ON main_context.context = context.context;
+    INSERT OR IGNORE INTO main.tracer (file_id, tracer)
+      SELECT main_file.id, tracer.tracer
+      FROM other.tracer AS tracer
+      JOIN other.file AS file ON tracer.file_id = file.id
+      JOIN main.file AS main_file ON main_file.path = file.path;
+    ",
+  )
+  .map_err(sql_err)?;
+
+  // Line numbers are stored as "numbits" bitmaps, which must be unioned rather than replaced.
+  let line_bits = {
+    let mut statement = tx
+      .prepare(
+        "
+        SELECT main_file.id, main_context.id, line_bits.numbits, main_line_bits.numbits
+        FROM other.line_bits AS line_bits
+        JOIN other.file AS file ON line_bits.file_id = file.id
+        JOIN main.file AS main_file ON main_file.path = file.path
+        JOIN other.context AS context ON line_bits.context_id = context.id
+        JOIN main.context AS main_context ON main_context.context = context.context
+        LEFT JOIN main.line_bits AS main_line_bits
+          ON main_line_bits.file_id = main_file.id AND main_line_bits.context_id = main_context.id
+        ",
+      )
+      .map_err(sql_err)?;
+    let rows = statement
+      .query_map(NO_PARAMS, |row| {
+        let file_id: i64 = row.get(0)?;
+        let context_id: i64 = row.get(1)?;
+        let numbits: Vec<u8> = row.get(2)?;
+        let merged_numbits: Option<Vec<u8>> = row.get(3)?;
+        Ok((file_id, context_id, numbits, merged_numbits))
+      })
+      .map_err(sql_err)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(sql_err)?
+  };
+  for (file_id, context_id, numbits, merged_numbits) in line_bits {
+    let numbits = match merged_numbits {
+      Some(merged_numbits) => numbits_union(&merged_numbits, &numbits),
+      None => numbits,
+    };
+    tx.execute(
+      "INSERT OR REPLACE INTO main.line_bits (file_id, context_id, numbits) VALUES (?1, ?2, ?3)",
+      params![file_id, context_id, numbits],
+    )
+    .map_err(sql_err)?;
+  }
+  tx.commit().map_err(sql_err)?;
+
+  merged
+    .execute("DETACH DATABASE other", NO_PARAMS)
+    .map_err(sql_err)?;
+  Ok(())
+}
+
+///
+/// Unions two "numbits" bitmaps, in which bit `n % 8` of byte `n / 8` is set if line `n` was
+/// measured.
+///
+fn numbits_union(a: &[u8], b: &[u8]) -> Vec<u8> {
+  let (longer, shorter) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+  let mut union = longer.to_vec();
+  for (byte, other) in union.iter_mut().zip(shorter) {
+    *byte |= other;
+  }
+  union
+}
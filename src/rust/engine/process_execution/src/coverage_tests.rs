@@ -0,0 +1,226 @@
+use rusqlite::{params, Connection, NO_PARAMS};
+
+use crate::coverage::{merge_coverage_py, merge_lcov};
+
+fn merged_lcov(reports: &[&str]) -> Result<String, String> {
+  let reports = reports.iter().map(|r| r.as_bytes()).collect::<Vec<_>>();
+  merge_lcov(&reports).map(|merged| String::from_utf8(merged).unwrap())
+}
+
+#[test]
+fn lcov_sums_counts() {
+  let first = "\
+TN:shard1
+SF:src/a.js
+FN:1,main
+FNDA:1,main
+FNF:1
+FNH:1
+BRDA:2,0,0,1
+BRDA:2,0,1,-
+DA:1,1
+DA:2,1
+DA:3,0,abcdef
+LF:3
+LH:2
+end_of_record
+";
+  let second = "\
+TN:shard2
+SF:src/a.js
+FN:1,main
+FN:5,helper
+FNDA:2,main
+FNDA:0,helper
+BRDA:2,0,0,-
+BRDA:2,0,1,3
+DA:3,4
+DA:5,0
+end_of_record
+";
+  assert_eq!(
+    merged_lcov(&[first, second]),
+    Ok(
+      "\
+SF:src/a.js
+FN:1,main
+FN:5,helper
+FNDA:3,main
+FNDA:0,helper
+FNF:2
+FNH:1
+BRDA:2,0,0,1
+BRDA:2,0,1,3
+BRF:2
+BRH:2
+DA:1,1
+DA:2,1
+DA:3,4
+DA:5,0
+LF:4
+LH:3
+end_of_record
+"
+      .to_owned()
+    )
+  );
+}
+
+#[test]
+fn lcov_sorts_source_files() {
+  assert_eq!(
+    merged_lcov(&[
+      "SF:z.c\nDA:1,1\nend_of_record\n",
+      "SF:a.c\nDA:1,0\nend_of_record\n"
+    ]),
+    Ok(
+      "SF:a.c\nDA:1,0\nLF:1\nLH:0\nend_of_record\nSF:z.c\nDA:1,1\nLF:1\nLH:1\nend_of_record\n"
+        .to_owned()
+    )
+  );
+  assert_eq!(merged_lcov(&[]), Ok("".to_owned()));
+}
+
+#[test]
+fn lcov_malformed() {
+  let err = merged_lcov(&["SF:a.c\nend_of_record\n", "DA:1,1\n"]).unwrap_err();
+  assert!(
+    err.starts_with("Failed to parse LCOV report 1: Line 1 is not within a source file record"),
+    "Unexpected error: {}",
+    err
+  );
+
+  let err = merged_lcov(&["SF:a.c\nDA:one,1\n"]).unwrap_err();
+  assert!(
+    err.contains("Invalid count `one`"),
+    "Unexpected error: {}",
+    err
+  );
+}
+
+///
+/// Creates a coverage.py data file (using a subset of schema version 7) containing the given
+/// (path, context, numbits) line data.
+///
+fn coverage_py_data_file(has_arcs: Option<&str>, line_bits: &[(&str, &str, &[u8])]) -> Vec<u8> {
+  let tempdir = tempfile::tempdir().unwrap();
+  let path = tempdir.path().join("data");
+  let connection = Connection::open(&path).unwrap();
+  connection
+    .execute_batch(
+      "
+      CREATE TABLE coverage_schema (version integer);
+      INSERT INTO coverage_schema (version) VALUES (7);
+      CREATE TABLE meta (key text, value text, unique (key));
+      CREATE TABLE file (id integer primary key, path text, unique (path));
+      CREATE TABLE context (id integer primary key, context text, unique (context));
+      CREATE TABLE line_bits (
+        file_id integer, context_id integer, numbits blob, unique (file_id, context_id)
+      );
+      CREATE TABLE arc (
+        file_id integer, context_id integer, fromno integer, tono integer,
+        unique (file_id, context_id, fromno, tono)
+      );
+      CREATE TABLE tracer (file_id integer primary key, tracer text);
+      ",
+    )
+    .unwrap();
+  if let Some(has_arcs) = has_arcs {
+    connection
+      .execute(
+        "INSERT INTO meta (key, value) VALUES ('has_arcs', ?1)",
+        params![has_arcs],
+      )
+      .unwrap();
+  }
+  for (file, context, numbits) in line_bits {
+    connection
+      .execute(
+        "INSERT OR IGNORE INTO file (path) VALUES (?1)",
+        params![file],
+      )
+      .unwrap();
+    connection
+      .execute(
+        "INSERT OR IGNORE INTO context (context) VALUES (?1)",
+        params![context],
+      )
+      .unwrap();
+    connection
+      .execute(
+        "INSERT INTO line_bits (file_id, context_id, numbits) VALUES (
+          (SELECT id FROM file WHERE path = ?1),
+          (SELECT id FROM context WHERE context = ?2),
+          ?3
+        )",
+        params![file, context, numbits.to_vec()],
+      )
+      .unwrap();
+  }
+  connection.close().unwrap();
+  std::fs::read(&path).unwrap()
+}
+
+fn coverage_py_line_bits(data_file: &[u8]) -> Vec<(String, String, Vec<u8>)> {
+  let tempdir = tempfile::tempdir().unwrap();
+  let path = tempdir.path().join("data");
+  std::fs::write(&path, data_file).unwrap();
+  let connection = Connection::open(&path).unwrap();
+  let mut statement = connection
+    .prepare(
+      "
+      SELECT file.path, context.context, line_bits.numbits
+      FROM line_bits
+      JOIN file ON line_bits.file_id = file.id
+      JOIN context ON line_bits.context_id = context.id
+      ORDER BY file.path, context.context
+      ",
+    )
+    .unwrap();
+  let rows = statement
+    .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+    .unwrap();
+  rows.collect::<Result<Vec<_>, _>>().unwrap()
+}
+
+#[test]
+fn coverage_py_unions_line_bits() {
+  let first = coverage_py_data_file(
+    Some("0"),
+    &[("a.py", "", &[0b0000_0110]), ("b.py", "", &[0b0000_0010])],
+  );
+  let second = coverage_py_data_file(
+    Some("0"),
+    &[
+      ("b.py", "", &[0b0000_0100, 0b0000_0001]),
+      ("c.py", "test_c", &[0b1000_0000]),
+    ],
+  );
+  let merged = merge_coverage_py(&[&first, &second]).unwrap();
+  assert_eq!(
+    coverage_py_line_bits(&merged),
+    vec![
+      ("a.py".to_owned(), "".to_owned(), vec![0b0000_0110]),
+      (
+        "b.py".to_owned(),
+        "".to_owned(),
+        vec![0b0000_0110, 0b0000_0001]
+      ),
+      ("c.py".to_owned(), "test_c".to_owned(), vec![0b1000_0000]),
+    ]
+  );
+}
+
+#[test]
+fn coverage_py_incompatible_measurements() {
+  let lines = coverage_py_data_file(Some("0"), &[("a.py", "", &[1])]);
+  let arcs = coverage_py_data_file(Some("1"), &[]);
+  let err = merge_coverage_py(&[&lines, &arcs]).unwrap_err();
+  assert!(
+    err.contains("Cannot merge line data with arc (branch) data."),
+    "Unexpected error: {}",
+    err
+  );
+
+  assert!(merge_coverage_py(&[]).is_err());
+}
@@ -0,0 +1,217 @@
+use std::collections::{BTreeMap, HashSet};
+use std::iter::Peekable;
+use std::str::Chars;
+
+///
+/// Parses a Makefile-style depfile (as emitted by `gcc -MD` or `clang -MD`, for example), and
+/// returns the unique prerequisites of all of its rules, in the order in which they were first
+/// encountered.
+///
+/// Supports line continuations, escaped spaces and hashes (`\ ` and `\#`), escaped dollar signs
+/// (`$$`), comments, and multiple rules (including the empty "phony" rules emitted by `-MP`).
+/// Backslashes which do not escape one of the above are preserved, so Windows-style paths are
+/// returned as-is.
+///
+pub fn parse_depfile(content: &str) -> Result<Vec<String>, String> {
+  let mut parser = DepfileParser {
+    line: 1,
+    ..DepfileParser::default()
+  };
+  let mut chars = content.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => match chars.peek() {
+        Some(' ') | Some('#') => {
+          parser.word.push(chars.next().unwrap());
+        }
+        Some('\n') => {
+          // A line continuation.
+          chars.next();
+          parser.line += 1;
+          parser.finish_word();
+        }
+        Some('\r') => {
+          chars.next();
+          if chars.peek() == Some(&'\n') {
+            chars.next();
+            parser.line += 1;
+          }
+          parser.finish_word();
+        }
+        _ => parser.word.push('\\'),
+      },
+      '$' if chars.peek() == Some(&'$') => {
+        chars.next();
+        parser.word.push('$');
+      }
+      ':' if !parser.in_prerequisites && at_separator(&mut chars) => {
+        parser.finish_word();
+        if !parser.has_targets {
+          return Err(format!(
+            "Malformed depfile: rule on line {} has no targets.",
+            parser.line
+          ));
+        }
+        parser.in_prerequisites = true;
+      }
+      '#' if parser.word.is_empty() => {
+        // A comment, which extends to the end of the line.
+        while let Some(&c) = chars.peek() {
+          if c == '\n' {
+            break;
+          }
+          chars.next();
+        }
+      }
+      '\n' => {
+        parser.finish_rule()?;
+        parser.line += 1;
+      }
+      c if c.is_whitespace() => parser.finish_word(),
+      c => parser.word.push(c),
+    }
+  }
+  parser.finish_rule()?;
+  Ok(parser.prerequisites)
+}
+
+///
+/// A colon only separates targets from prerequisites if it is followed by whitespace (or the end
+/// of the input), which allows for Windows-style drive letters in paths.
+///
+fn at_separator(chars: &mut Peekable<Chars>) -> bool {
+  match chars.peek() {
+    None => true,
+    Some(c) => c.is_whitespace(),
+  }
+}
+
+#[derive(Default)]
+struct DepfileParser {
+  word: String,
+  line: usize,
+  has_targets: bool,
+  in_prerequisites: bool,
+  prerequisites: Vec<String>,
+  seen: HashSet<String>,
+}
+
+impl DepfileParser {
+  fn finish_word(&mut self) {
+    if self.word.is_empty() {
+      return;
+    }
+    let word = std::mem::take(&mut self.word);
+    if self.in_prerequisites {
+      if self.seen.insert(word.clone()) {
+        self.prerequisites.push(word);
+      }
+    } else {
+      self.has_targets = true;
+    }
+  }
+
+  fn finish_rule(&mut self) -> Result<(), String> {
+    self.finish_word();
+    if self.has_targets && !self.in_prerequisites {
+      return Err(format!(
+        "Malformed depfile: rule on line {} is missing a `:` separator.",
+        self.line
+      ));
+    }
+    self.has_targets = false;
+    self.in_prerequisites = false;
+    Ok(())
+  }
+}
+
+const NINJA_DEPS_SIGNATURE: &[u8] = b"# ninjadeps\n";
+
+///
+/// Parses a ninja deps log (the binary `.ninja_deps` file, versions 3 and 4), and returns the
+/// unique inputs of all of the outputs that it records, ordered by output.
+///
+/// The log is append-only, so only the last record for each output is used. As in ninja itself, a
+/// truncated final record (from an interrupted write) is ignored.
+///
+pub fn parse_ninja_deps_log(content: &[u8]) -> Result<Vec<String>, String> {
+  let header_len = NINJA_DEPS_SIGNATURE.len() + 4;
+  if content.len() < header_len || !content.starts_with(NINJA_DEPS_SIGNATURE) {
+    return Err("Not a ninja deps log: missing signature.".to_owned());
+  }
+  // Deps records start with the id of the output and its mtime, the width of which differs by
+  // version.
+  let deps_record_header_len = match read_u32(&content[NINJA_DEPS_SIGNATURE.len()..]) {
+    3 => 8,
+    4 => 12,
+    version => return Err(format!("Unsupported ninja deps log version: {}", version)),
+  };
+
+  let mut paths: Vec<String> = Vec::new();
+  let mut inputs_by_output: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+  let mut offset = header_len;
+  while offset + 4 <= content.len() {
+    let record_header = read_u32(&content[offset..]);
+    let is_deps_record = record_header & 0x8000_0000 != 0;
+    let size = (record_header & 0x7FFF_FFFF) as usize;
+    offset += 4;
+    if offset + size > content.len() {
+      break;
+    }
+    let record = &content[offset..offset + size];
+    offset += size;
+
+    if is_deps_record {
+      if size < deps_record_header_len || size % 4 != 0 {
+        return Err(format!(
+          "Malformed ninja deps log: invalid deps record size {}.",
+          size
+        ));
+      }
+      let inputs = record[deps_record_header_len..]
+        .chunks(4)
+        .map(read_u32)
+        .collect();
+      inputs_by_output.insert(read_u32(record), inputs);
+    } else {
+      if size < 4 {
+        return Err(format!(
+          "Malformed ninja deps log: invalid path record size {}.",
+          size
+        ));
+      }
+      // Path records are NUL-padded to a multiple of four bytes, and followed by a checksum which
+      // is the complement of the id of the path.
+      let (path, checksum) = record.split_at(size - 4);
+      if (!read_u32(checksum)) as usize != paths.len() {
+        return Err(format!(
+          "Malformed ninja deps log: invalid checksum for path record {}.",
+          paths.len()
+        ));
+      }
+      let path_len = path.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+      let path = String::from_utf8(path[..path_len].to_vec())
+        .map_err(|e| format!("Malformed ninja deps log: non-UTF8 path: {}", e))?;
+      paths.push(path);
+    }
+  }
+
+  let mut seen = HashSet::new();
+  let mut inputs = Vec::new();
+  for input_id in inputs_by_output.values().flatten() {
+    let path = paths.get(*input_id as usize).ok_or_else(|| {
+      format!(
+        "Malformed ninja deps log: reference to unknown path {}.",
+        input_id
+      )
+    })?;
+    if seen.insert(path) {
+      inputs.push(path.clone());
+    }
+  }
+  Ok(inputs)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+  u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
@@ -0,0 +1,177 @@
+use crate::depfile::{parse_depfile, parse_ninja_deps_log};
+use testutil::owned_string_vec;
+
+#[test]
+fn single_rule() {
+  assert_eq!(
+    parse_depfile("main.o: main.c main.h\n"),
+    Ok(owned_string_vec(&["main.c", "main.h"]))
+  );
+}
+
+#[test]
+fn line_continuations() {
+  assert_eq!(
+    parse_depfile("main.o: main.c \\\n  include/a.h \\\r\n  include/b.h\n"),
+    Ok(owned_string_vec(&["main.c", "include/a.h", "include/b.h"]))
+  );
+}
+
+#[test]
+fn escapes() {
+  assert_eq!(
+    parse_depfile("main.o: dir\\ with\\ spaces/a.h \\#hash.h $$dollar.h\n"),
+    Ok(owned_string_vec(&[
+      "dir with spaces/a.h",
+      "#hash.h",
+      "$dollar.h"
+    ]))
+  );
+}
+
+#[test]
+fn multiple_rules_are_deduplicated() {
+  // As emitted by `gcc -MD -MP`.
+  let content = "\
+main.o: main.c include/a.h include/b.h
+
+# A comment.
+other.o: other.c include/a.h
+include/a.h:
+include/b.h:
+";
+  assert_eq!(
+    parse_depfile(content),
+    Ok(owned_string_vec(&[
+      "main.c",
+      "include/a.h",
+      "include/b.h",
+      "other.c"
+    ]))
+  );
+}
+
+#[test]
+fn multiple_targets() {
+  assert_eq!(
+    parse_depfile("main.o main.d : main.c"),
+    Ok(owned_string_vec(&["main.c"]))
+  );
+}
+
+#[test]
+fn windows_paths() {
+  assert_eq!(
+    parse_depfile("C:\\out\\main.obj: C:\\src\\main.c\n"),
+    Ok(owned_string_vec(&["C:\\src\\main.c"]))
+  );
+}
+
+#[test]
+fn empty() {
+  assert_eq!(parse_depfile(""), Ok(vec![]));
+  assert_eq!(parse_depfile("\n\n# Only a comment.\n"), Ok(vec![]));
+}
+
+#[test]
+fn missing_separator() {
+  assert_eq!(
+    parse_depfile("main.o: main.c\nmain.h\n"),
+    Err("Malformed depfile: rule on line 2 is missing a `:` separator.".to_owned())
+  );
+}
+
+#[test]
+fn missing_targets() {
+  assert_eq!(
+    parse_depfile("main.o: main.c\n: main.h\n"),
+    Err("Malformed depfile: rule on line 2 has no targets.".to_owned())
+  );
+}
+
+fn ninja_deps_log(version: u32, records: Vec<Vec<u8>>) -> Vec<u8> {
+  let mut log = b"# ninjadeps\n".to_vec();
+  log.extend_from_slice(&version.to_le_bytes());
+  for record in records {
+    log.extend(record);
+  }
+  log
+}
+
+fn ninja_path_record(path: &str, id: u32) -> Vec<u8> {
+  let mut payload = path.as_bytes().to_vec();
+  while payload.len() % 4 != 0 {
+    payload.push(0);
+  }
+  payload.extend_from_slice(&(!id).to_le_bytes());
+  let mut record = (payload.len() as u32).to_le_bytes().to_vec();
+  record.extend(payload);
+  record
+}
+
+fn ninja_deps_record(output_id: u32, input_ids: &[u32]) -> Vec<u8> {
+  // The output id, followed by a (version 4) 64 bit mtime.
+  let mut payload = output_id.to_le_bytes().to_vec();
+  payload.extend_from_slice(&[0; 8]);
+  for input_id in input_ids {
+    payload.extend_from_slice(&input_id.to_le_bytes());
+  }
+  let mut record = (payload.len() as u32 | 0x8000_0000).to_le_bytes().to_vec();
+  record.extend(payload);
+  record
+}
+
+#[test]
+fn ninja_deps_log_latest_records() {
+  let log = ninja_deps_log(
+    4,
+    vec![
+      ninja_path_record("main.o", 0),
+      ninja_path_record("main.c", 1),
+      ninja_path_record("include/a.h", 2),
+      ninja_deps_record(0, &[1, 2]),
+      ninja_path_record("include/b.h", 3),
+      // A later record for the same output replaces the earlier one.
+      ninja_deps_record(0, &[1, 3, 1]),
+    ],
+  );
+  assert_eq!(
+    parse_ninja_deps_log(&log),
+    Ok(owned_string_vec(&["main.c", "include/b.h"]))
+  );
+}
+
+#[test]
+fn ninja_deps_log_truncated() {
+  let mut log = ninja_deps_log(
+    4,
+    vec![
+      ninja_path_record("main.o", 0),
+      ninja_path_record("main.c", 1),
+      ninja_deps_record(0, &[1]),
+    ],
+  );
+  let mut truncated_record = ninja_path_record("include/a.h", 2);
+  truncated_record.truncate(6);
+  log.extend(truncated_record);
+  assert_eq!(
+    parse_ninja_deps_log(&log),
+    Ok(owned_string_vec(&["main.c"]))
+  );
+}
+
+#[test]
+fn ninja_deps_log_invalid() {
+  assert_eq!(
+    parse_ninja_deps_log(b"main.o: main.c"),
+    Err("Not a ninja deps log: missing signature.".to_owned())
+  );
+  assert_eq!(
+    parse_ninja_deps_log(&ninja_deps_log(2, vec![])),
+    Err("Unsupported ninja deps log version: 2".to_owned())
+  );
+  assert_eq!(
+    parse_ninja_deps_log(&ninja_deps_log(4, vec![ninja_path_record("main.o", 1)])),
+    Err("Malformed ninja deps log: invalid checksum for path record 0.".to_owned())
+  );
+}
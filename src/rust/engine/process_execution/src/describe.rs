@@ -0,0 +1,129 @@
+use std::fmt::Write;
+
+use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
+use bazel_protos::require_digest;
+use bytes::Bytes;
+use grpc_util::prost::MessageExt;
+use hashing::Digest;
+
+use crate::local::sandbox_parent_directories;
+use crate::remote::make_execute_request;
+use crate::{NamedCaches, Process, ProcessMetadata};
+
+///
+/// The REAPI protos which would be sent to a server to execute a Process, and a human readable
+/// rendering of them and of the local sandbox which would be created for the Process.
+///
+/// Rendering a Process does not execute it, and does not require its inputs to be present.
+///
+#[derive(Clone, Debug)]
+pub struct ActionDescription {
+  pub action_digest: Digest,
+  pub action: Bytes,
+  pub command_digest: Digest,
+  pub command: Bytes,
+  pub rendered: String,
+}
+
+///
+/// Describes the given Process as it would be executed with the given metadata.
+///
+/// NB: Processes which are never cached (or which are only cached per-restart) include a random
+/// salt in their Command, and so are described differently each time.
+///
+pub fn describe_action(
+  req: &Process,
+  metadata: ProcessMetadata,
+  named_caches: &NamedCaches,
+) -> Result<ActionDescription, String> {
+  let (action, command, execute_request) = make_execute_request(req, metadata)?;
+  let action_digest = require_digest(execute_request.action_digest.as_ref())?;
+  let command_digest = require_digest(action.command_digest.as_ref())?;
+
+  let mut rendered = String::new();
+  render_action(
+    &mut rendered,
+    action_digest,
+    &action,
+    command_digest,
+    &command,
+  )
+  .and_then(|()| render_sandbox(&mut rendered, req, named_caches))
+  .map_err(|e| format!("Failed to render action: {}", e))?;
+
+  Ok(ActionDescription {
+    action_digest,
+    action: action.to_bytes(),
+    command_digest,
+    command: command.to_bytes(),
+    rendered,
+  })
+}
+
+fn render_action(
+  out: &mut String,
+  action_digest: Digest,
+  action: &remexec::Action,
+  command_digest: Digest,
+  command: &remexec::Command,
+) -> std::fmt::Result {
+  writeln!(
+    out,
+    "Action ({}/{}):\n{:#?}\n",
+    action_digest.hash, action_digest.size_bytes, action
+  )?;
+  writeln!(
+    out,
+    "Command ({}/{}):\n{:#?}\n",
+    command_digest.hash, command_digest.size_bytes, command
+  )
+}
+
+fn render_sandbox(out: &mut String, req: &Process, named_caches: &NamedCaches) -> std::fmt::Result {
+  let named_cache_symlinks = named_caches
+    .local_paths(&req.append_only_caches)
+    .collect::<Vec<_>>();
+
+  writeln!(out, "Local sandbox:")?;
+  writeln!(
+    out,
+    "  input root: {}/{}",
+    req.input_files.hash, req.input_files.size_bytes
+  )?;
+  if let Some(working_directory) = &req.working_directory {
+    writeln!(out, "  working directory: {}", working_directory.display())?;
+  }
+  for directory in sandbox_parent_directories(req, &named_cache_symlinks) {
+    writeln!(out, "  create directory: {}", directory.display())?;
+  }
+  if let Some(jdk_home) = &req.jdk_home {
+    writeln!(out, "  symlink: .jdk -> {}", jdk_home.display())?;
+  }
+  for symlink in &named_cache_symlinks {
+    writeln!(
+      out,
+      "  symlink: {} -> {}",
+      symlink.dst.display(),
+      symlink.src.display()
+    )?;
+  }
+  if let Some(variable) = &req.execution_slot_variable {
+    writeln!(out, "  execution slot variable: {}", variable)?;
+  }
+  for variable in &req.leased_port_variables {
+    writeln!(out, "  leased port variable: {}", variable)?;
+  }
+  if let Some(variable) = &req.invocation_id_variable {
+    writeln!(out, "  invocation id variable: {}", variable)?;
+  }
+  for path in &req.output_files {
+    writeln!(out, "  capture file: {}", path.display())?;
+  }
+  for path in &req.output_directories {
+    writeln!(out, "  capture directory: {}", path.display())?;
+  }
+  for path in &req.output_paths {
+    writeln!(out, "  capture path: {}", path.display())?;
+  }
+  Ok(())
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
+use prost::Message;
+use testutil::{owned_string_vec, relative_paths};
+
+use crate::describe::describe_action;
+use crate::{CacheDest, CacheName, NamedCaches, Process, ProcessMetadata};
+
+#[test]
+fn describes_action_and_sandbox() {
+  let mut append_only_caches = BTreeMap::new();
+  append_only_caches.insert(
+    CacheName::new("pip".to_owned()).unwrap(),
+    CacheDest::new(".cache/pip".to_owned()).unwrap(),
+  );
+  let req = Process::new(owned_string_vec(&["/bin/echo", "yo"]))
+    .output_files(relative_paths(&["out/file"]).collect())
+    .append_only_caches(append_only_caches);
+  let named_caches = NamedCaches::new(PathBuf::from("/named_caches"));
+
+  let metadata = ProcessMetadata {
+    instance_name: Some("cluster-a".to_owned()),
+    ..ProcessMetadata::default()
+  };
+  let description = describe_action(&req, metadata.clone(), &named_caches).unwrap();
+
+  // The serialized protos are exactly those which would be sent to the server.
+  let (action, command, execute_request) =
+    crate::remote::make_execute_request(&req, metadata).unwrap();
+  assert_eq!(
+    execute_request.action_digest,
+    Some((&description.action_digest).into())
+  );
+  assert_eq!(
+    remexec::Action::decode(description.action.clone()).unwrap(),
+    action
+  );
+  assert_eq!(
+    remexec::Command::decode(description.command.clone()).unwrap(),
+    command
+  );
+
+  for expected in &[
+    "Command (",
+    "\"/bin/echo\"",
+    "Local sandbox:",
+    "  create directory: out\n",
+    "  create directory: .cache\n",
+    "  symlink: .cache/pip -> /named_caches/pip\n",
+    "  capture file: out/file\n",
+  ] {
+    assert!(
+      description.rendered.contains(expected),
+      "Expected {:?} in:\n{}",
+      expected,
+      description.rendered
+    );
+  }
+}
@@ -0,0 +1,44 @@
+use workunit_store::UserMetadataItem;
+
+///
+/// Structured fields which describe what a Process does, and from which its `description` was
+/// rendered (see `render_process_description` in `pants.engine.process`).
+///
+/// Rendering descriptions from structured fields (rather than having each caller format a
+/// free-form string) keeps the one-liners shown in the dynamic UI, logs and workunits consistent
+/// and greppable, and allows consumers of workunits to aggregate processes by tool.
+///
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DescriptionFields {
+  pub tool: Option<String>,
+  pub verb: Option<String>,
+  pub target_count: Option<usize>,
+}
+
+impl DescriptionFields {
+  ///
+  /// The fields which are set, as workunit metadata.
+  ///
+  pub fn user_metadata(&self) -> Vec<(String, UserMetadataItem)> {
+    let mut user_metadata = Vec::new();
+    if let Some(tool) = &self.tool {
+      user_metadata.push((
+        "tool".to_owned(),
+        UserMetadataItem::ImmediateString(tool.clone()),
+      ));
+    }
+    if let Some(verb) = &self.verb {
+      user_metadata.push((
+        "verb".to_owned(),
+        UserMetadataItem::ImmediateString(verb.clone()),
+      ));
+    }
+    if let Some(target_count) = self.target_count {
+      user_metadata.push((
+        "target_count".to_owned(),
+        UserMetadataItem::ImmediateId(target_count as i64),
+      ));
+    }
+    user_metadata
+  }
+}
@@ -0,0 +1,30 @@
+use workunit_store::UserMetadataItem;
+
+use crate::description::DescriptionFields;
+
+fn fields() -> DescriptionFields {
+  DescriptionFields {
+    tool: Some("flake8".to_owned()),
+    verb: Some("Linting".to_owned()),
+    target_count: Some(3),
+  }
+}
+
+#[test]
+fn user_metadata() {
+  assert_eq!(
+    fields().user_metadata(),
+    vec![
+      (
+        "tool".to_owned(),
+        UserMetadataItem::ImmediateString("flake8".to_owned())
+      ),
+      (
+        "verb".to_owned(),
+        UserMetadataItem::ImmediateString("Linting".to_owned())
+      ),
+      ("target_count".to_owned(), UserMetadataItem::ImmediateId(3)),
+    ]
+  );
+  assert_eq!(DescriptionFields::default().user_metadata(), vec![]);
+}
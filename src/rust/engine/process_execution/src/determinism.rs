@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+
+use fs::FileContent;
+use hashing::Digest;
+use store::Store;
+
+use crate::{CommandRunner, Context, MultiPlatformProcess, ProcessCacheScope};
+
+/// The maximum number of differing lines which are rendered for a divergent text output.
+pub const MAX_RENDERED_DIFF_LINES: usize = 10;
+
+///
+/// The outcome of running a Process repeatedly in order to check whether it is deterministic.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeterminismReport {
+  pub runs: usize,
+  pub divergences: Vec<Divergence>,
+}
+
+impl DeterminismReport {
+  pub fn is_deterministic(&self) -> bool {
+    self.divergences.is_empty()
+  }
+}
+
+///
+/// An output of a Process which differed between runs.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Divergence {
+  /// The output which diverged: either a path in the output directory, or one of `<exit code>`,
+  /// `<stdout>` or `<stderr>`.
+  pub output: String,
+  /// The (zero-indexed) runs in which the output differed from the first run.
+  pub divergent_runs: Vec<usize>,
+  /// A rendering of the difference between the first run and the first divergent run.
+  pub diff: String,
+}
+
+///
+/// Runs the given Process `runs` times (sequentially, and without consulting or populating any
+/// caches), and compares the outputs of each run to those of the first run.
+///
+/// Useful for rule authors to find nondeterministic tools before relying on remote caching, since
+/// nondeterministic outputs cause cache misses for all downstream processes.
+///
+pub async fn check_determinism(
+  runner: &dyn CommandRunner,
+  store: &Store,
+  mut req: MultiPlatformProcess,
+  context: Context,
+  runs: usize,
+) -> Result<DeterminismReport, String> {
+  if runs < 2 {
+    return Err(format!(
+      "Checking determinism requires at least 2 runs, but got {}.",
+      runs
+    ));
+  }
+  for process in req.0.values_mut() {
+    process.cache_scope = ProcessCacheScope::Never;
+  }
+
+  let mut results = Vec::with_capacity(runs);
+  for _ in 0..runs {
+    results.push(runner.run(req.clone(), context.clone()).await?);
+  }
+
+  let mut divergences: BTreeMap<String, Divergence> = BTreeMap::new();
+  let mut record = |output: String, run: usize, diff: String| {
+    divergences
+      .entry(output.clone())
+      .or_insert_with(|| Divergence {
+        output,
+        divergent_runs: vec![],
+        diff,
+      })
+      .divergent_runs
+      .push(run);
+  };
+
+  let expected = &results[0];
+  let expected_outputs = output_files(store, expected.output_directory).await?;
+  for (run, actual) in results.iter().enumerate().skip(1) {
+    if expected.exit_code != actual.exit_code {
+      record(
+        "<exit code>".to_owned(),
+        run,
+        format!("- {}\n+ {}", expected.exit_code, actual.exit_code),
+      );
+    }
+    for (name, expected_digest, actual_digest) in &[
+      ("<stdout>", expected.stdout_digest, actual.stdout_digest),
+      ("<stderr>", expected.stderr_digest, actual.stderr_digest),
+    ] {
+      if expected_digest != actual_digest {
+        let diff = render_diff(
+          &load_bytes(store, *expected_digest).await?,
+          &load_bytes(store, *actual_digest).await?,
+        );
+        record((*name).to_owned(), run, diff);
+      }
+    }
+    if expected.output_directory != actual.output_directory {
+      let actual_outputs = output_files(store, actual.output_directory).await?;
+      diff_output_files(&expected_outputs, &actual_outputs, |output, diff| {
+        record(output, run, diff)
+      });
+    }
+  }
+
+  Ok(DeterminismReport {
+    runs,
+    divergences: divergences.into_iter().map(|(_, d)| d).collect(),
+  })
+}
+
+///
+/// Renders a human readable report of the divergences found in a check.
+///
+pub fn render_report(report: &DeterminismReport) -> String {
+  if report.is_deterministic() {
+    return format!("All {} runs produced identical outputs.", report.runs);
+  }
+  let mut rendered = format!(
+    "{} output(s) differed across {} runs:\n",
+    report.divergences.len(),
+    report.runs
+  );
+  for divergence in &report.divergences {
+    rendered.push_str(&format!(
+      "\n{} (differed in runs {:?}):\n{}\n",
+      divergence.output, divergence.divergent_runs, divergence.diff
+    ));
+  }
+  rendered
+}
+
+fn diff_output_files(
+  expected: &BTreeMap<String, FileContent>,
+  actual: &BTreeMap<String, FileContent>,
+  mut record: impl FnMut(String, String),
+) {
+  for (path, expected_file) in expected {
+    match actual.get(path) {
+      None => record(path.clone(), "File was not produced.".to_owned()),
+      Some(actual_file) if expected_file.is_executable != actual_file.is_executable => record(
+        path.clone(),
+        format!(
+          "- is_executable: {}\n+ is_executable: {}",
+          expected_file.is_executable, actual_file.is_executable
+        ),
+      ),
+      Some(actual_file) if expected_file.content != actual_file.content => record(
+        path.clone(),
+        render_diff(&expected_file.content, &actual_file.content),
+      ),
+      Some(_) => (),
+    }
+  }
+  for path in actual.keys() {
+    if !expected.contains_key(path) {
+      record(
+        path.clone(),
+        "File was not produced by the first run.".to_owned(),
+      );
+    }
+  }
+}
+
+///
+/// Renders the difference between two versions of an output: as the differing lines of text if
+/// both versions are UTF-8, and otherwise as the first differing byte offset.
+///
+pub fn render_diff(expected: &[u8], actual: &[u8]) -> String {
+  match (std::str::from_utf8(expected), std::str::from_utf8(actual)) {
+    (Ok(expected), Ok(actual)) => {
+      let expected_lines = expected.lines().collect::<Vec<_>>();
+      let actual_lines = actual.lines().collect::<Vec<_>>();
+      let line_count = expected_lines.len().max(actual_lines.len());
+      let differing_lines = (0..line_count)
+        .filter(|i| expected_lines.get(*i) != actual_lines.get(*i))
+        .collect::<Vec<_>>();
+      if differing_lines.is_empty() {
+        // The lines are identical, so the difference is in line endings.
+        return "Outputs differ only in line endings or trailing newlines.".to_owned();
+      }
+      let mut rendered = differing_lines
+        .iter()
+        .take(MAX_RENDERED_DIFF_LINES)
+        .map(|i| {
+          let render_line = |line: Option<&&str>| match line {
+            Some(line) => format!("\"{}\"", line),
+            None => "<missing>".to_owned(),
+          };
+          format!(
+            "line {}:\n- {}\n+ {}",
+            i + 1,
+            render_line(expected_lines.get(*i)),
+            render_line(actual_lines.get(*i))
+          )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+      if differing_lines.len() > MAX_RENDERED_DIFF_LINES {
+        rendered.push_str(&format!(
+          "\n... and {} more differing line(s).",
+          differing_lines.len() - MAX_RENDERED_DIFF_LINES
+        ));
+      }
+      rendered
+    }
+    _ => {
+      let first_difference = expected
+        .iter()
+        .zip(actual.iter())
+        .position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+      format!(
+        "Binary outputs of {} and {} bytes first differ at byte offset {}.",
+        expected.len(),
+        actual.len(),
+        first_difference
+      )
+    }
+  }
+}
+
+async fn load_bytes(store: &Store, digest: Digest) -> Result<Vec<u8>, String> {
+  store
+    .load_file_bytes_with(digest, |bytes| bytes.to_vec())
+    .await?
+    .map(|(bytes, _)| bytes)
+    .ok_or_else(|| format!("Output with digest {:?} was not found.", digest))
+}
+
+async fn output_files(
+  store: &Store,
+  digest: Digest,
+) -> Result<BTreeMap<String, FileContent>, String> {
+  Ok(
+    store
+      .contents_for_directory(digest)
+      .await?
+      .into_iter()
+      .map(|file| (file.path.display().to_string(), file))
+      .collect(),
+  )
+}
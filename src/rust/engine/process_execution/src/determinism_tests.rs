@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use hashing::EMPTY_DIGEST;
+use parking_lot::Mutex;
+use store::Store;
+use tempfile::TempDir;
+use testutil::owned_string_vec;
+
+use crate::determinism::{check_determinism, render_diff, Divergence};
+use crate::{
+  CommandRunner as CommandRunnerTrait, Context, FallibleProcessResultWithPlatform,
+  MultiPlatformProcess, Platform, Process, ProcessCacheScope, ProcessResultMetadata,
+};
+
+/// A mock runner which writes the given stdout for each run, and records the cache scope of each
+/// Process that it runs.
+#[derive(Clone)]
+struct MockCommandRunner {
+  store: Store,
+  stdouts: Arc<Mutex<Vec<&'static str>>>,
+  cache_scopes: Arc<Mutex<Vec<ProcessCacheScope>>>,
+}
+
+#[async_trait]
+impl CommandRunnerTrait for MockCommandRunner {
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    _context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    let process = self.extract_compatible_request(&req).unwrap();
+    self.cache_scopes.lock().push(process.cache_scope);
+    let stdout = self.stdouts.lock().remove(0);
+    let stdout_digest = self
+      .store
+      .store_file_bytes(Bytes::from(stdout), false)
+      .await?;
+    Ok(FallibleProcessResultWithPlatform {
+      stdout_digest,
+      stderr_digest: EMPTY_DIGEST,
+      exit_code: 0,
+      output_directory: EMPTY_DIGEST,
+      platform: Platform::Linux,
+      metadata: ProcessResultMetadata::default(),
+    })
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    req.0.get(&None).cloned()
+  }
+}
+
+fn mock_runner(stdouts: Vec<&'static str>) -> (MockCommandRunner, TempDir) {
+  let store_dir = TempDir::new().unwrap();
+  let store = Store::local_only(task_executor::Executor::new(), store_dir.path()).unwrap();
+  let runner = MockCommandRunner {
+    store,
+    stdouts: Arc::new(Mutex::new(stdouts)),
+    cache_scopes: Arc::default(),
+  };
+  (runner, store_dir)
+}
+
+#[tokio::test]
+async fn deterministic() {
+  let (runner, _store_dir) = mock_runner(vec!["same\n", "same\n", "same\n"]);
+  let report = check_determinism(
+    &runner,
+    &runner.store,
+    Process::new(owned_string_vec(&["/bin/echo", "same"])).into(),
+    Context::default(),
+    3,
+  )
+  .await
+  .unwrap();
+
+  assert!(report.is_deterministic());
+  assert_eq!(report.runs, 3);
+  // Caches must not be consulted or populated, or the process would not actually run again.
+  assert_eq!(
+    *runner.cache_scopes.lock(),
+    vec![ProcessCacheScope::Never; 3]
+  );
+}
+
+#[tokio::test]
+async fn nondeterministic_stdout() {
+  let (runner, _store_dir) = mock_runner(vec!["a\nb\n", "a\nb\n", "a\nc\n"]);
+  let report = check_determinism(
+    &runner,
+    &runner.store,
+    Process::new(owned_string_vec(&["/bin/date"])).into(),
+    Context::default(),
+    3,
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(
+    report.divergences,
+    vec![Divergence {
+      output: "<stdout>".to_owned(),
+      divergent_runs: vec![2],
+      diff: "line 2:\n- \"b\"\n+ \"c\"".to_owned(),
+    }]
+  );
+}
+
+#[tokio::test]
+async fn requires_multiple_runs() {
+  let (runner, _store_dir) = mock_runner(vec![]);
+  let err = check_determinism(
+    &runner,
+    &runner.store,
+    Process::new(owned_string_vec(&["/bin/true"])).into(),
+    Context::default(),
+    1,
+  )
+  .await
+  .unwrap_err();
+  assert_eq!(
+    err,
+    "Checking determinism requires at least 2 runs, but got 1."
+  );
+}
+
+#[test]
+fn binary_diff() {
+  assert_eq!(
+    render_diff(&[0, 1, 2, 0xff], &[0, 1, 3]),
+    "Binary outputs of 4 and 3 bytes first differ at byte offset 2."
+  );
+}
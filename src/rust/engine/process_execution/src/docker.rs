@@ -0,0 +1,452 @@
+use std::collections::BTreeMap;
+use std::fs::create_dir_all;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use fs::RelativePath;
+use futures::future::{FutureExt, TryFutureExt};
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use log::debug;
+use nails::execution::ExitCode;
+use store::Store;
+use tokio::process::Command;
+use tokio_util::codec::{BytesCodec, FramedRead};
+use workunit_store::Metric;
+
+use crate::access_audit::FileAccessAuditor;
+use crate::local::{exit_code, CapturedWorkdir, ChildOutput, KeepSandboxes};
+use crate::ports::PortLeases;
+use crate::sandbox::{SandboxProvider, TempdirSandboxProvider};
+use crate::sandbox_slots::SandboxSlots;
+use crate::{
+  Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, NamedCaches, Platform, Process,
+  Redactor,
+};
+
+///
+/// The prefix of the names of the containers which are created to run processes.
+///
+pub const CONTAINER_NAME_PREFIX: &str = "pants";
+
+///
+/// A CommandRunner which runs processes which set a `container_image` in a new container of that
+/// image, using the `docker` CLI, and delegates all other processes to the wrapped CommandRunner.
+///
+/// The sandbox of each process is created and captured exactly as for a local process, and is
+/// bind-mounted into the container at the same path (as is the directory of named caches, if the
+/// process uses any), so that absolute paths into the sandbox are valid both inside and outside
+/// of the container. The process runs as the current user, so that its outputs can be captured
+/// and cleaned up, and with the network of the host, so that it may use leased ports.
+///
+pub struct CommandRunner {
+  underlying: Box<dyn crate::CommandRunner>,
+  store: Store,
+  executor: task_executor::Executor,
+  work_dir_base: PathBuf,
+  named_caches: NamedCaches,
+  keep_sandboxes: KeepSandboxes,
+  redactor: Redactor,
+  docker_binary: PathBuf,
+  port_leases: PortLeases,
+  sandbox_slots: SandboxSlots,
+  sandbox_provider: Arc<dyn SandboxProvider>,
+  workspace: Option<PathBuf>,
+}
+
+impl CommandRunner {
+  pub fn new(
+    underlying: Box<dyn crate::CommandRunner>,
+    store: Store,
+    executor: task_executor::Executor,
+    work_dir_base: PathBuf,
+    named_caches: NamedCaches,
+    keep_sandboxes: KeepSandboxes,
+    redactor: Redactor,
+  ) -> CommandRunner {
+    let sandbox_provider = Arc::new(TempdirSandboxProvider::new(executor.clone()));
+    CommandRunner {
+      underlying,
+      store,
+      executor,
+      work_dir_base,
+      named_caches,
+      keep_sandboxes,
+      redactor,
+      docker_binary: PathBuf::from("docker"),
+      port_leases: PortLeases::default(),
+      sandbox_slots: SandboxSlots::new(None),
+      sandbox_provider,
+      workspace: None,
+    }
+  }
+
+  ///
+  /// The `docker` CLI (or a compatible CLI, such as `podman`) to run containers with. If it is not
+  /// an absolute path, it is looked up on the `PATH`.
+  ///
+  pub fn with_docker_binary(self, docker_binary: PathBuf) -> CommandRunner {
+    CommandRunner {
+      docker_binary,
+      ..self
+    }
+  }
+
+  ///
+  /// The SandboxSlots which bound the number of sandboxes which exist at once, which should be
+  /// shared with the local runner that runs processes outside of containers: see `SandboxSlots`.
+  ///
+  pub fn with_sandbox_slots(self, sandbox_slots: SandboxSlots) -> CommandRunner {
+    CommandRunner {
+      sandbox_slots,
+      ..self
+    }
+  }
+
+  ///
+  /// The PortLeases from which ports are leased to containers, which should be shared with the
+  /// local runner, so that concurrent processes are never leased the same port.
+  ///
+  pub fn with_port_leases(self, port_leases: PortLeases) -> CommandRunner {
+    CommandRunner {
+      port_leases,
+      ..self
+    }
+  }
+
+  ///
+  /// Marks the sandboxes which are preserved as belonging to the given workspace, so that they are
+  /// only pruned by it: see `SandboxRetention`.
+  ///
+  pub fn with_workspace(self, workspace: PathBuf) -> CommandRunner {
+    CommandRunner {
+      workspace: Some(workspace),
+      ..self
+    }
+  }
+
+  ///
+  /// The request of the given MultiPlatformProcess which runs in a container, if any. Containers
+  /// always run Linux processes, regardless of the platform of this machine.
+  ///
+  fn extract_container_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    [None, Some(Platform::Linux)]
+      .iter()
+      .filter_map(|constraint| req.0.get(constraint))
+      .find(|compatible_req| compatible_req.container_image.is_some())
+      .cloned()
+  }
+}
+
+#[async_trait]
+impl crate::CommandRunner for CommandRunner {
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    self
+      .extract_container_request(req)
+      .or_else(|| self.underlying.extract_compatible_request(req))
+  }
+
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    let req = match self.extract_container_request(&req) {
+      Some(req) => req,
+      None => return self.underlying.run(req, context).await,
+    };
+    context
+      .workunit_store
+      .increment_counter(Metric::LocalExecutionRequests, 1);
+
+    if req.allocate_tty || req.pipe_from_argv.is_some() || !req.resource_limits.is_empty() {
+      return Err(format!(
+        "Allocating a TTY, `pipe_from_argv`, and resource limits are not supported for processes \
+         which run in a container: {}",
+        req.description
+      ));
+    }
+    // The image is part of the cache key of the process, so it must always refer to the same image.
+    if let Some(image) = &req.container_image {
+      if !is_pinned_image(image) {
+        return Err(format!(
+          "The container image `{}` must be pinned by its digest (such as \
+           `python@sha256:<digest>`) so that it always refers to the same image: {}",
+          image, req.description
+        ));
+      }
+    }
+    // NB: The `platform_requirements` of a process describe the machine that it runs on, which for
+    // a container is defined by its image, and so they are not checked.
+    let req_debug_repr = self
+      .redactor
+      .redact_str(&req.env, &format!("{:#?}", req))
+      .into_owned();
+    self
+      .run_and_capture_workdir(
+        req,
+        context,
+        self.store.clone(),
+        self.executor.clone(),
+        self.keep_sandboxes,
+        &self.work_dir_base,
+        Platform::Linux,
+      )
+      .map_err(|msg| {
+        format!(
+          "Failed to execute in a container: {}\n\n{}",
+          req_debug_repr, msg
+        )
+      })
+      .await
+  }
+}
+
+#[async_trait]
+impl CapturedWorkdir for CommandRunner {
+  fn named_caches(&self) -> &NamedCaches {
+    &self.named_caches
+  }
+
+  fn redactor(&self) -> &Redactor {
+    &self.redactor
+  }
+
+  fn port_leases(&self) -> &PortLeases {
+    &self.port_leases
+  }
+
+  fn file_access_auditor(&self) -> Option<&FileAccessAuditor> {
+    // Accesses within a container cannot be traced from outside of it.
+    None
+  }
+
+  fn sandbox_slots(&self) -> &SandboxSlots {
+    &self.sandbox_slots
+  }
+
+  fn sandbox_provider(&self) -> &Arc<dyn SandboxProvider> {
+    &self.sandbox_provider
+  }
+
+  fn workspace(&self) -> Option<&Path> {
+    self.workspace.as_deref()
+  }
+
+  ///
+  /// NB: The process is spawned by the Docker daemon rather than by forking this process, and so
+  /// `exclusive_spawn` does not apply.
+  ///
+  async fn run_in_workdir<'a, 'b, 'c>(
+    &'a self,
+    workdir_path: &'b Path,
+    req: Process,
+    _context: Context,
+    _exclusive_spawn: bool,
+  ) -> Result<BoxStream<'c, Result<ChildOutput, String>>, String> {
+    let image = req
+      .container_image
+      .as_ref()
+      .ok_or_else(|| format!("No container image was set for: {}", req.description))?;
+
+    // If the process uses named caches, their directory must exist before it is mounted, or else
+    // it would be created (as root) by the Docker daemon.
+    let named_caches_base = if req.append_only_caches.is_empty() {
+      None
+    } else {
+      let named_caches_base = self.named_caches.local_base();
+      create_dir_all(named_caches_base).map_err(|e| {
+        format!(
+          "Error making the named caches directory {:?}: {:?}",
+          named_caches_base, e
+        )
+      })?;
+      Some(named_caches_base)
+    };
+
+    // The environment is passed in a file rather than as arguments, so that its values are not
+    // visible in the arguments of the `docker` process.
+    let env_file = write_env_file(&req.env)?;
+    let name = format!("{}-{}", CONTAINER_NAME_PREFIX, uuid::Uuid::new_v4());
+    let user = unsafe { (libc::getuid(), libc::getgid()) };
+    let args = container_args(
+      &name,
+      image,
+      workdir_path,
+      named_caches_base,
+      env_file.path(),
+      user,
+      &req,
+    )?;
+
+    let mut child = Command::new(&self.docker_binary)
+      .args(&args)
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .kill_on_drop(true)
+      .spawn()
+      .map_err(|e| {
+        format!(
+          "Error launching process in a container with {:?}: {:?}",
+          self.docker_binary, e
+        )
+      })?;
+    debug!(
+      "spawned container {} as {:?} for {:?}",
+      name,
+      child.id(),
+      req
+    );
+
+    let stdout_stream = FramedRead::new(child.stdout.take().unwrap(), BytesCodec::new())
+      .map_ok(|bytes| ChildOutput::Stdout(bytes.into()))
+      .fuse()
+      .boxed();
+    let stderr_stream = FramedRead::new(child.stderr.take().unwrap(), BytesCodec::new())
+      .map_ok(|bytes| ChildOutput::Stderr(bytes.into()))
+      .fuse()
+      .boxed();
+
+    let mut container = Container {
+      docker_binary: self.docker_binary.clone(),
+      name,
+      exited: false,
+    };
+    let exit_stream = async move {
+      let exit_status = child.wait().await?;
+      // If the `docker` process exited of its own accord, then so did the container (which it
+      // removes), but if it was killed by a signal, the container might still be running.
+      container.exited = exit_status.code().is_some();
+      // NB: The environment file must exist until the container has started.
+      std::mem::drop(env_file);
+      Ok::<_, std::io::Error>(ChildOutput::Exit(ExitCode(exit_code(exit_status))))
+    }
+    .into_stream()
+    .boxed();
+
+    Ok(
+      futures::stream::select_all(vec![stdout_stream, stderr_stream, exit_stream])
+        .map_err(|e| format!("Failed to consume process outputs: {:?}", e))
+        .boxed(),
+    )
+  }
+}
+
+///
+/// True if the given image reference is pinned by its digest, rather than only naming a tag (which
+/// may refer to a different image over time).
+///
+pub fn is_pinned_image(image: &str) -> bool {
+  const DIGEST_PREFIX: &str = "@sha256:";
+  image.rfind(DIGEST_PREFIX).map_or(false, |index| {
+    let digest = &image[index + DIGEST_PREFIX.len()..];
+    digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit())
+  })
+}
+
+///
+/// The arguments to `docker` which run the given process (whose sandbox is at `workdir_path`) in
+/// a new container of the given image and name, as the given user and group ids.
+///
+pub fn container_args(
+  name: &str,
+  image: &str,
+  workdir_path: &Path,
+  named_caches_base: Option<&Path>,
+  env_file: &Path,
+  user: (u32, u32),
+  req: &Process,
+) -> Result<Vec<String>, String> {
+  let cwd = if let Some(ref working_directory) = req.working_directory {
+    workdir_path.join(working_directory)
+  } else {
+    workdir_path.to_owned()
+  };
+  // As for local processes, a relative argv0 is relative to the working directory.
+  let argv0 = match RelativePath::new(&req.argv[0]) {
+    Ok(rel_path) => cwd.join(rel_path),
+    Err(_) => PathBuf::from(&req.argv[0]),
+  };
+
+  let mut args = vec![
+    "run".to_owned(),
+    "--rm".to_owned(),
+    "--init".to_owned(),
+    format!("--name={}", name),
+    "--network=host".to_owned(),
+    format!("--user={}:{}", user.0, user.1),
+  ];
+  for path in std::iter::once(workdir_path).chain(named_caches_base) {
+    let path = path_str(path)?;
+    args.push(format!("--volume={}:{}", path, path));
+  }
+  args.push(format!("--env-file={}", path_str(env_file)?));
+  args.push(format!("--workdir={}", path_str(&cwd)?));
+  args.push(format!("--entrypoint={}", path_str(&argv0)?));
+  args.push(image.to_owned());
+  args.extend(req.argv[1..].iter().cloned());
+  Ok(args)
+}
+
+fn path_str(path: &Path) -> Result<&str, String> {
+  path
+    .to_str()
+    .ok_or_else(|| format!("Non-UTF8 path cannot be used in a container: {:?}", path))
+}
+
+///
+/// Writes the given environment to a temporary file, in the format of `docker run --env-file`.
+///
+fn write_env_file(env: &BTreeMap<String, String>) -> Result<tempfile::NamedTempFile, String> {
+  let mut env_file = tempfile::Builder::new()
+    .prefix("container-env")
+    .tempfile()
+    .map_err(|e| format!("Error making container environment file: {:?}", e))?;
+  for (key, value) in env {
+    // The format has no quoting, and so each variable must fit on a single line.
+    if key.contains('\n') || value.contains('\n') {
+      return Err(format!(
+        "The environment variable `{}` of a process which runs in a container may not contain \
+         a newline.",
+        key
+      ));
+    }
+    writeln!(env_file, "{}={}", key, value)
+      .map_err(|e| format!("Error writing container environment file: {:?}", e))?;
+  }
+  Ok(env_file)
+}
+
+///
+/// A running container, which is forcibly removed when it is dropped unless it has exited (if,
+/// for example, its process timed out).
+///
+struct Container {
+  docker_binary: PathBuf,
+  name: String,
+  exited: bool,
+}
+
+impl Drop for Container {
+  fn drop(&mut self) {
+    if self.exited {
+      return;
+    }
+    debug!("removing container {}", self.name);
+    let docker_binary = self.docker_binary.clone();
+    let name = std::mem::take(&mut self.name);
+    // NB: Removal is waited for on a thread, so that the `docker` process is reaped without
+    // blocking.
+    std::thread::spawn(move || {
+      let _ = std::process::Command::new(docker_binary)
+        .args(&["rm", "--force", &name])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    });
+  }
+}
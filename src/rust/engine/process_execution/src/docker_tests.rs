@@ -0,0 +1,228 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use store::Store;
+use tempfile::TempDir;
+use testutil::data::{TestData, TestDirectory};
+use testutil::path::find_bash;
+use testutil::{owned_string_vec, relative_paths};
+use workunit_store::WorkunitStore;
+
+use crate::docker::{container_args, is_pinned_image};
+use crate::local::KeepSandboxes;
+use crate::testing::{FakeCommandRunner, FakeProcesses, FakeResponse, ProcessMatcher};
+use crate::{
+  CommandRunner as CommandRunnerTrait, Context, MultiPlatformProcess, NamedCaches, Platform,
+  Process, RelativePath,
+};
+
+///
+/// An image which is pinned by its digest.
+///
+const IMAGE: &str =
+  "python@sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+///
+/// A fake `docker` CLI, which runs the entrypoint of the container directly, in its working
+/// directory and with its environment, and which reports the image on stderr.
+///
+const FAKE_DOCKER: &str = r#"
+shift
+while [[ "$1" == --* ]]; do
+  case "$1" in
+    --workdir=*) cd "${1#--workdir=}" ;;
+    --entrypoint=*) entrypoint="${1#--entrypoint=}" ;;
+    --env-file=*) mapfile -t env < "${1#--env-file=}" ;;
+  esac
+  shift
+done
+echo -n "$1" >&2
+shift
+exec /usr/bin/env -i "${env[@]}" "$entrypoint" "$@"
+"#;
+
+struct Runner {
+  runner: crate::docker::CommandRunner,
+  processes: FakeProcesses,
+  store: Store,
+  work_dir: PathBuf,
+  _dirs: Vec<TempDir>,
+}
+
+fn runner() -> Runner {
+  let store_dir = TempDir::new().unwrap();
+  let work_dir = TempDir::new().unwrap();
+  let named_cache_dir = TempDir::new().unwrap();
+  let bin_dir = TempDir::new().unwrap();
+  let executor = task_executor::Executor::new();
+  let store = Store::local_only(executor.clone(), store_dir.path()).unwrap();
+
+  let docker_binary = bin_dir.path().join("docker");
+  std::fs::write(
+    &docker_binary,
+    format!("#!{}\n{}", find_bash(), FAKE_DOCKER),
+  )
+  .unwrap();
+  std::fs::set_permissions(&docker_binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+  let processes = FakeProcesses::new();
+  let runner = crate::docker::CommandRunner::new(
+    Box::new(FakeCommandRunner::new(store.clone(), processes.clone())),
+    store.clone(),
+    executor,
+    work_dir.path().to_owned(),
+    NamedCaches::new(named_cache_dir.path().to_owned()),
+    KeepSandboxes::Never,
+    crate::Redactor::default(),
+  )
+  .with_docker_binary(docker_binary);
+  Runner {
+    runner,
+    processes,
+    store,
+    work_dir: work_dir.path().to_owned(),
+    _dirs: vec![store_dir, work_dir, named_cache_dir, bin_dir],
+  }
+}
+
+async fn load(store: &Store, digest: hashing::Digest) -> String {
+  store
+    .load_file_bytes_with(digest, |bytes| String::from_utf8(bytes.to_vec()).unwrap())
+    .await
+    .unwrap()
+    .unwrap()
+    .0
+}
+
+#[tokio::test]
+async fn runs_processes_with_an_image_in_a_container() {
+  WorkunitStore::setup_for_tests();
+  let Runner {
+    runner,
+    processes,
+    store,
+    work_dir,
+    _dirs,
+  } = runner();
+
+  let mut req = Process::new(vec![
+    find_bash(),
+    "-c".to_owned(),
+    "echo -n $ROLAND > roland ; echo -n $PWD".to_owned(),
+  ])
+  .output_files(relative_paths(&["roland"]).collect());
+  req
+    .env
+    .insert("ROLAND".to_owned(), TestData::roland().string());
+  req.container_image = Some(IMAGE.to_owned());
+
+  let result = runner.run(req.into(), Context::default()).await.unwrap();
+  assert_eq!(result.exit_code, 0);
+  assert_eq!(
+    result.output_directory,
+    TestDirectory::containing_roland().digest()
+  );
+  assert_eq!(result.platform, Platform::Linux);
+  assert_eq!(load(&store, result.stderr_digest).await, IMAGE);
+  // The process runs in its sandbox, below the base directory of sandboxes.
+  let cwd = PathBuf::from(load(&store, result.stdout_digest).await);
+  assert!(cwd.starts_with(&work_dir));
+
+  assert!(processes.calls().is_empty());
+}
+
+#[tokio::test]
+async fn rejects_images_which_are_not_pinned() {
+  WorkunitStore::setup_for_tests();
+  let Runner { runner, _dirs, .. } = runner();
+
+  // A tag may refer to a different image over time, and so it cannot be part of a cache key.
+  let mut req = Process::new(owned_string_vec(&["/usr/bin/python3"]));
+  req.container_image = Some("python:3.9-slim".to_owned());
+  let err = runner
+    .run(req.into(), Context::default())
+    .await
+    .unwrap_err();
+  assert!(err.contains("pinned"), "Unexpected error: {}", err);
+}
+
+#[test]
+fn pinned_images() {
+  assert!(is_pinned_image(IMAGE));
+  assert!(is_pinned_image(&format!("docker.io/library/{}", IMAGE)));
+  assert!(!is_pinned_image("python:3.9-slim"));
+  assert!(!is_pinned_image("python@sha256:0123"));
+}
+
+#[tokio::test]
+async fn delegates_processes_without_an_image() {
+  WorkunitStore::setup_for_tests();
+  let Runner {
+    runner,
+    processes,
+    _dirs,
+    ..
+  } = runner();
+  processes.respond(ProcessMatcher::any(), FakeResponse::exit(3));
+
+  let req = Process::new(owned_string_vec(&["/bin/echo", "-n", "foo"]));
+  let result = runner
+    .run(req.clone().into(), Context::default())
+    .await
+    .unwrap();
+  assert_eq!(result.exit_code, 3);
+  assert_eq!(processes.calls(), vec![req]);
+}
+
+#[tokio::test]
+async fn extracts_linux_requests_with_an_image() {
+  let Runner { runner, _dirs, .. } = runner();
+
+  let native_req = Process::new(owned_string_vec(&["/usr/bin/python3"]));
+  let mut container_req = native_req.clone();
+  container_req.container_image = Some(IMAGE.to_owned());
+  let req = MultiPlatformProcess(
+    vec![
+      (None, native_req),
+      (Some(Platform::Linux), container_req.clone()),
+    ]
+    .into_iter()
+    .collect(),
+  );
+  assert_eq!(runner.extract_compatible_request(&req), Some(container_req));
+}
+
+#[test]
+fn container_args_for_process() {
+  let mut req = Process::new(owned_string_vec(&["bin/tool", "--flag", "arg"]));
+  req.working_directory = Some(RelativePath::new("src").unwrap());
+  let args = container_args(
+    "pants-abc",
+    IMAGE,
+    Path::new("/tmp/sandbox"),
+    Some(Path::new("/home/user/.cache/named_caches")),
+    Path::new("/tmp/container-env"),
+    (1000, 100),
+    &req,
+  )
+  .unwrap();
+  assert_eq!(
+    args,
+    owned_string_vec(&[
+      "run",
+      "--rm",
+      "--init",
+      "--name=pants-abc",
+      "--network=host",
+      "--user=1000:100",
+      "--volume=/tmp/sandbox:/tmp/sandbox",
+      "--volume=/home/user/.cache/named_caches:/home/user/.cache/named_caches",
+      "--env-file=/tmp/container-env",
+      "--workdir=/tmp/sandbox/src",
+      "--entrypoint=/tmp/sandbox/src/bin/tool",
+      IMAGE,
+      "--flag",
+      "arg",
+    ])
+  );
+}
@@ -38,20 +38,59 @@ use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 use workunit_store::{with_workunit, UserMetadataItem, WorkunitMetadata, WorkunitStore};
 
 use async_semaphore::AsyncSemaphore;
-use hashing::{Digest, EMPTY_FINGERPRINT};
+use hashing::{Digest, Fingerprint, EMPTY_FINGERPRINT};
+
+pub mod access_audit;
+#[cfg(test)]
+mod access_audit_tests;
+
+pub mod batching;
+#[cfg(test)]
+mod batching_tests;
 
 pub mod cache;
 #[cfg(test)]
 mod cache_tests;
 
+pub mod cache_backfill;
+
+pub mod coverage;
+#[cfg(test)]
+mod coverage_tests;
+
+pub mod depfile;
+#[cfg(test)]
+mod depfile_tests;
+
+pub mod description;
+#[cfg(test)]
+mod description_tests;
+
+pub mod describe;
+#[cfg(test)]
+mod describe_tests;
+
+pub mod determinism;
+#[cfg(test)]
+mod determinism_tests;
+
+pub mod docker;
+#[cfg(test)]
+mod docker_tests;
+
 pub mod local;
 #[cfg(test)]
 mod local_tests;
 
+pub mod merging;
+#[cfg(test)]
+mod merging_tests;
+
 pub mod remote;
 #[cfg(test)]
 pub mod remote_tests;
@@ -62,11 +101,101 @@ mod remote_cache_tests;
 
 pub mod named_caches;
 
+pub mod output_encoding;
+#[cfg(test)]
+mod output_encoding_tests;
+
+pub mod placeholders;
+#[cfg(test)]
+mod placeholders_tests;
+
+pub mod platform_details;
+#[cfg(test)]
+mod platform_details_tests;
+
+pub mod ports;
+#[cfg(test)]
+mod ports_tests;
+
+pub mod pty;
+#[cfg(test)]
+mod pty_tests;
+
+pub mod quota;
+#[cfg(test)]
+mod quota_tests;
+
+pub mod redaction;
+#[cfg(test)]
+mod redaction_tests;
+
+pub mod repro;
+#[cfg(test)]
+mod repro_tests;
+
+pub mod resource_limits;
+#[cfg(test)]
+mod resource_limits_tests;
+
+pub mod response_files;
+#[cfg(test)]
+mod response_files_tests;
+
+pub mod sandbox;
+#[cfg(test)]
+mod sandbox_tests;
+
+pub mod sandbox_retention;
+#[cfg(test)]
+mod sandbox_retention_tests;
+
+pub mod sandbox_slots;
+#[cfg(test)]
+mod sandbox_slots_tests;
+
+pub mod scanners;
+#[cfg(test)]
+mod scanners_tests;
+
+pub mod speculation;
+#[cfg(test)]
+mod speculation_tests;
+
+pub mod tags;
+#[cfg(test)]
+mod tags_tests;
+
+pub mod test_report;
+#[cfg(test)]
+mod test_report_tests;
+
+pub mod testing;
+#[cfg(test)]
+mod testing_tests;
+
+pub mod text_rewrite;
+#[cfg(test)]
+mod text_rewrite_tests;
+
+pub mod working_directory;
+#[cfg(test)]
+mod working_directory_tests;
+
 extern crate uname;
 
+pub use crate::description::DescriptionFields;
 pub use crate::named_caches::{CacheDest, CacheName, NamedCaches};
+pub use crate::platform_details::{PlatformDetails, PlatformRequirements};
+pub use crate::redaction::Redactor;
+pub use crate::resource_limits::{ExitReason, ResourceLimits};
+pub use crate::response_files::ResponseFileSpec;
+pub use crate::sandbox::{SandboxProvider, TempdirSandboxProvider};
+pub use crate::scanners::OutputScannerSpec;
+pub use crate::tags::ProcessTags;
+use bytes::Bytes;
 use concrete_time::{Duration, TimeSpan};
 use fs::RelativePath;
+use tokio::sync::mpsc;
 
 #[derive(PartialOrd, Ord, Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Platform {
@@ -109,6 +238,93 @@ impl TryFrom<String> for Platform {
   }
 }
 
+///
+/// The platform that the outputs of a Process target, which is recorded in its cache key so that
+/// outputs which do not depend on where the Process ran may be shared between platforms.
+///
+#[derive(PartialOrd, Ord, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum OutputPlatform {
+  /// The outputs may be specific to the platform that the Process runs on, which is recorded in
+  /// the cache key only via its `platform_constraint` (if any).
+  Execution,
+  /// The outputs are platform independent (for example: pure Python sources, or JVM bytecode), and
+  /// so they may be shared between all platforms, even if the Process is platform constrained.
+  Independent,
+  /// The outputs target the given platform regardless of the platform that the Process runs on
+  /// (for example: when cross compiling).
+  Target(Platform),
+}
+
+impl OutputPlatform {
+  ///
+  /// The value which represents this OutputPlatform (for a Process with the given constraint) in
+  /// cache keys.
+  ///
+  pub fn cache_key_value(&self, platform_constraint: Option<Platform>) -> String {
+    match (*self, platform_constraint) {
+      (OutputPlatform::Execution, Some(platform)) | (OutputPlatform::Target(platform), _) => {
+        platform.into()
+      }
+      (OutputPlatform::Execution, None) => "none".to_owned(),
+      (OutputPlatform::Independent, _) => "any".to_owned(),
+    }
+  }
+}
+
+///
+/// The priority that a client declared for its Session. Processes for Sessions of a higher priority
+/// acquire execution slots in a `BoundedCommandRunner` before those of lower priorities, and are
+/// submitted to remote executors with a higher priority.
+///
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SessionPriority {
+  // Work which no one is waiting on, such as a scheduled `lint --all`.
+  Background,
+  // Work which is waited on, but not by a developer at their terminal (e.g. CI).
+  Batch,
+  // Work which a developer is waiting on.
+  Interactive,
+}
+
+impl SessionPriority {
+  ///
+  /// The priority with which to wait for an execution slot: higher values are admitted first.
+  ///
+  pub fn permit_priority(self) -> usize {
+    self as usize
+  }
+
+  ///
+  /// The REAPI `ExecutionPolicy.priority` for this Session. Lower values should run sooner, and
+  /// zero is the server's default priority.
+  ///
+  pub fn execution_priority(self) -> i32 {
+    match self {
+      SessionPriority::Interactive => 0,
+      SessionPriority::Batch => 1,
+      SessionPriority::Background => 2,
+    }
+  }
+}
+
+impl Default for SessionPriority {
+  fn default() -> Self {
+    SessionPriority::Interactive
+  }
+}
+
+impl TryFrom<String> for SessionPriority {
+  type Error = String;
+  fn try_from(variant_candidate: String) -> Result<Self, Self::Error> {
+    match variant_candidate.to_lowercase().as_ref() {
+      "interactive" => Ok(SessionPriority::Interactive),
+      "batch" => Ok(SessionPriority::Batch),
+      "background" => Ok(SessionPriority::Background),
+      other => Err(format!("Unknown Session priority: {:?}", other)),
+    }
+  }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum ProcessCacheScope {
   // Cached in all locations, regardless of success or failure.
@@ -150,6 +366,17 @@ pub struct Process {
   /// No shell expansion will take place.
   ///
   pub argv: Vec<String>,
+
+  ///
+  /// If set, the arguments of a "source" process which is spawned alongside this one (in the same
+  /// working directory, and with the same environment), and whose stdout is streamed directly into
+  /// the stdin of this process, as with a shell pipe. The intermediate stream is never stored.
+  ///
+  /// The stderr of both processes is captured. If this process succeeds but the source process
+  /// fails, the exit code of the source process is reported (similar to bash's `pipefail`).
+  ///
+  pub pipe_from_argv: Option<Vec<String>>,
+
   ///
   /// The environment variables to set for the execution.
   ///
@@ -163,20 +390,68 @@ pub struct Process {
   ///
   pub working_directory: Option<RelativePath>,
 
+  ///
+  /// Whether to create the `working_directory` (as an empty directory) if it does not exist in the
+  /// `input_files` digest. If false, a missing `working_directory` is an error.
+  ///
+  pub create_working_directory: bool,
+
   pub input_files: hashing::Digest,
 
   pub output_files: BTreeSet<RelativePath>,
 
   pub output_directories: BTreeSet<RelativePath>,
 
+  ///
+  /// Output paths which may be either files or directories, whose type is determined after the
+  /// process has run. Files and directories which are declared via `output_files` or
+  /// `output_directories` need not also be declared here.
+  ///
+  /// Remote execution of a Process with output paths requires a server which supports v2.1 of
+  /// the Remote Execution API.
+  ///
+  pub output_paths: BTreeSet<RelativePath>,
+
   pub timeout: Option<std::time::Duration>,
 
+  ///
+  /// Limits on the memory, CPU time, open files and processes which this process may use when it
+  /// runs locally: see `ResourceLimits`. Not supported by remote execution.
+  ///
+  pub resource_limits: ResourceLimits,
+
   /// If not None, then if a BoundedCommandRunner executes this Process
   pub execution_slot_variable: Option<String>,
 
+  ///
+  /// The names of env vars to populate with free localhost TCP ports, which are leased to this
+  /// process while it runs locally: no two concurrently running processes are leased the same
+  /// port. Leased ports are not part of the cache key of the process.
+  ///
+  pub leased_port_variables: Vec<String>,
+
+  /// If set, the name of an env var in which to expose the invocation id of the requesting
+  /// Session to the process when it runs locally. The id is not part of the cache key.
+  pub invocation_id_variable: Option<String>,
+
   #[derivative(PartialEq = "ignore", Hash = "ignore")]
   pub description: String,
 
+  ///
+  /// Structured fields describing this process, which its `description` was rendered from, and
+  /// which are attached to its workunit as metadata so that processes can be aggregated by tool.
+  ///
+  #[derivative(PartialEq = "ignore", Hash = "ignore")]
+  pub description_fields: DescriptionFields,
+
+  ///
+  /// Free-form tags which attribute this process (to a goal or target, for example), and which are
+  /// attached to its workunit and to its remote requests, but are not part of its cache key: see
+  /// `ProcessTags`.
+  ///
+  #[derivative(PartialEq = "ignore", Hash = "ignore")]
+  pub tags: ProcessTags,
+
   pub level: log::Level,
 
   ///
@@ -206,9 +481,124 @@ pub struct Process {
 
   pub platform_constraint: Option<Platform>,
 
+  ///
+  /// Requirements on the details of the platform that this process runs on (such as its libc),
+  /// beyond its `platform_constraint`. They are checked before the process runs locally, and are
+  /// part of its cache key.
+  ///
+  pub platform_requirements: PlatformRequirements,
+
+  ///
+  /// The platform that the outputs of this process target, which (rather than the platform that
+  /// it runs on) is part of its cache key.
+  ///
+  pub output_platform: OutputPlatform,
+
   pub is_nailgunnable: bool,
 
+  ///
+  /// If set, the process is run locally with a pseudo-terminal as its stdin, stdout and stderr, so
+  /// that tools which check `isatty()` (to decide whether to emit colors or progress bars, for
+  /// example) behave as they would in a real terminal. The terminal has the window size of the
+  /// terminal that Pants is attached to (if any), and is resized (sending the process SIGWINCH)
+  /// when that terminal is. Stdout and stderr are merged into the captured stdout. Not supported
+  /// by remote execution, or in combination with `pipe_from_argv`.
+  ///
+  pub allocate_tty: bool,
+
+  ///
+  /// If set, the name of a container image (such as `python:3.9-slim`) that the process runs in,
+  /// so that it uses the toolchain of the image rather than of the machine. Locally, the image is
+  /// run with Docker, with the sandbox of the process bind-mounted at the same path; remotely, the
+  /// image is sent as the `container-image` platform property (which some servers require to be
+  /// prefixed with `docker://`). Containers always run Linux processes.
+  ///
+  pub container_image: Option<String>,
+
+  ///
+  /// If set, the key of a remote persistent worker which may be reused to run this process, for
+  /// remote execution servers which support persistent workers. Processes with equal keys must be
+  /// able to share a worker (usually because they run the same tool), and so the key should be
+  /// derived from (for example) the digest of the tool. Because the key is sent as a platform
+  /// property, it is part of the cache key of the process.
+  ///
+  pub remote_worker_key: Option<String>,
+
+  ///
+  /// If set, a stable key which remote execution schedulers which support it use to route this
+  /// process to the worker which most recently ran a process with the same key, so that repeated
+  /// runs of an incremental process (such as a compiler with warm on-disk state in a persistent
+  /// worker) are likely to find that state. Unlike `remote_worker_key`, the key is only a hint:
+  /// it should identify the unit of incremental work (such as a target), rather than a tool.
+  /// Because the key is sent as a platform property, it is part of the cache key of the process.
+  ///
+  pub remote_affinity_key: Option<String>,
+
   pub cache_scope: ProcessCacheScope,
+
+  ///
+  /// If set, the path of an output file in which the process will record the additional inputs
+  /// that it discovered while running, in the format of a Makefile-style depfile (as emitted by
+  /// `gcc -MD`, for example). The path is automatically captured as an output file.
+  ///
+  /// The engine will record the discovered inputs which are within the build root as dependencies
+  /// of the process, so that it is re-run when any of them change.
+  ///
+  pub discovered_inputs_depfile: Option<RelativePath>,
+
+  ///
+  /// Output files of a code generating process which should be treated like source files by
+  /// consumers of its outputs. The paths are automatically captured as output files.
+  ///
+  /// The engine computes a separate digest for each of them (see `ProcessResult` in the engine),
+  /// so that consumers which depend on an individual generated file are only invalidated when the
+  /// content of that file changes, rather than when any output of the process does.
+  ///
+  pub source_output_files: BTreeSet<RelativePath>,
+
+  ///
+  /// The arguments of cheap "version probe" processes (e.g. `cc --version`) for tools that this
+  /// process uses from outside of its inputs. The engine runs each probe once per session (with
+  /// the same environment as this process), and mixes their outputs into `version_probe_fingerprint`
+  /// before running this process, so that upgrading a tool invalidates previously cached results.
+  ///
+  pub version_probes: Vec<Vec<String>>,
+
+  ///
+  /// A fingerprint of the outputs of the `version_probes`, which is populated by the engine and
+  /// contributes to the cache key of this process.
+  ///
+  pub version_probe_fingerprint: Option<Fingerprint>,
+
+  ///
+  /// Scanners which the engine runs over the stdout and stderr of this process once it has
+  /// completed (regardless of where it ran), and whose findings are attached to its workunit.
+  /// Scanners do not affect the cache key of the process.
+  ///
+  pub output_scanners: Vec<OutputScannerSpec>,
+
+  ///
+  /// If set, the maximum total size of the files captured in the output directory of this process,
+  /// beyond which it fails (rather than having its outputs cached). Overrides the default quota
+  /// for processes: see `quota::CommandRunner`.
+  ///
+  pub max_output_bytes: Option<usize>,
+
+  ///
+  /// If set, this process may be run in a batch with other compatible processes when it runs
+  /// locally: see `batching::CommandRunner`. The first `batch_argv_prefix_len` arguments are the
+  /// tool and its options, and the remaining arguments are items (such as files) which the tool
+  /// processes independently of one another. Processes are compatible if they differ only in
+  /// their items and outputs.
+  ///
+  pub batch_argv_prefix_len: Option<usize>,
+
+  ///
+  /// If set, how the tool accepts arguments from a response file, which is used if the arguments
+  /// (and env) of this process exceed the limits of the OS when it runs locally: see
+  /// `response_files::ResponseFileSpec`.
+  ///
+  pub response_file: Option<ResponseFileSpec>,
 }
 
 impl Process {
@@ -225,20 +615,42 @@ impl Process {
   pub fn new(argv: Vec<String>) -> Process {
     Process {
       argv,
+      pipe_from_argv: None,
       env: BTreeMap::new(),
       working_directory: None,
+      create_working_directory: true,
       input_files: hashing::EMPTY_DIGEST,
       output_files: BTreeSet::new(),
       output_directories: BTreeSet::new(),
+      output_paths: BTreeSet::new(),
       timeout: None,
+      resource_limits: ResourceLimits::default(),
       description: "".to_string(),
+      description_fields: DescriptionFields::default(),
+      tags: ProcessTags::default(),
       level: log::Level::Info,
       append_only_caches: BTreeMap::new(),
       jdk_home: None,
       platform_constraint: None,
+      platform_requirements: PlatformRequirements::default(),
+      output_platform: OutputPlatform::Execution,
       is_nailgunnable: false,
+      allocate_tty: false,
+      container_image: None,
+      remote_worker_key: None,
+      remote_affinity_key: None,
       execution_slot_variable: None,
+      leased_port_variables: vec![],
+      invocation_id_variable: None,
       cache_scope: ProcessCacheScope::Successful,
+      discovered_inputs_depfile: None,
+      source_output_files: BTreeSet::new(),
+      version_probes: vec![],
+      version_probe_fingerprint: None,
+      output_scanners: vec![],
+      max_output_bytes: None,
+      batch_argv_prefix_len: None,
+      response_file: None,
     }
   }
 
@@ -250,6 +662,14 @@ impl Process {
     self
   }
 
+  ///
+  /// Replaces the source process whose stdout is piped into the stdin of this process.
+  ///
+  pub fn pipe_from_argv(mut self, pipe_from_argv: Vec<String>) -> Process {
+    self.pipe_from_argv = Some(pipe_from_argv);
+    self
+  }
+
   ///
   /// Replaces the output files for this process.
   ///
@@ -266,6 +686,14 @@ impl Process {
     self
   }
 
+  ///
+  /// Replaces the output paths (which may be either files or directories) for this process.
+  ///
+  pub fn output_paths(mut self, output_paths: BTreeSet<RelativePath>) -> Process {
+    self.output_paths = output_paths;
+    self
+  }
+
   ///
   /// Replaces the append only caches for this process.
   ///
@@ -307,6 +735,24 @@ impl MultiPlatformProcess {
       .unwrap_or_else(|| "<Unnamed process>".to_string())
   }
 
+  pub fn description_fields(&self) -> DescriptionFields {
+    self
+      .0
+      .iter()
+      .next()
+      .map(|(_platforms, process)| process.description_fields.clone())
+      .unwrap_or_default()
+  }
+
+  pub fn tags(&self) -> ProcessTags {
+    self
+      .0
+      .iter()
+      .next()
+      .map(|(_platforms, process)| process.tags.clone())
+      .unwrap_or_default()
+  }
+
   pub fn workunit_level(&self) -> log::Level {
     self
       .0
@@ -362,11 +808,34 @@ pub struct ProcessResultMetadata {
   /// Corresponds to `worker_start_timestamp` and `worker_completed_timestamp` from
   /// `ExecutedActionMetadata`.
   pub total_elapsed: Option<Duration>,
+  /// The local sandbox of the process, if it was preserved for inspection. This is not persisted
+  /// by caches, since a cache hit does not create a sandbox.
+  pub preserved_sandbox: Option<PathBuf>,
+  /// True if the process was not actually run (because it was requested by a dry-run Session), and
+  /// this result was synthesized to describe it instead.
+  pub dry_run: bool,
+  /// The details of the platform that the process ran on, if it ran locally. Like the preserved
+  /// sandbox, this is not persisted by caches.
+  pub platform_details: Option<PlatformDetails>,
+  /// If the process was stopped because it exceeded one of its `ResourceLimits`, the limit that it
+  /// exceeded. Like the platform details, this is not persisted by caches.
+  pub exit_reason: Option<ExitReason>,
+  /// True if the process ran as part of a batch of processes: see `batching::CommandRunner`. Its
+  /// stdout and stderr are then those of the whole batch, which running it alone would not
+  /// reproduce, and so such a result is not persisted by caches.
+  pub batched: bool,
 }
 
 impl ProcessResultMetadata {
   pub fn new(total_elapsed: Option<Duration>) -> Self {
-    ProcessResultMetadata { total_elapsed }
+    ProcessResultMetadata {
+      total_elapsed,
+      preserved_sandbox: None,
+      dry_run: false,
+      platform_details: None,
+      exit_reason: None,
+      batched: false,
+    }
   }
 
   /// How much faster a cache hit was than running the process again.
@@ -405,7 +874,7 @@ impl From<ExecutedActionMetadata> for ProcessResultMetadata {
         .ok(),
       _ => None,
     };
-    Self { total_elapsed }
+    Self::new(total_elapsed)
   }
 }
 
@@ -435,10 +904,31 @@ impl Into<ExecutedActionMetadata> for ProcessResultMetadata {
   }
 }
 
+///
+/// A chunk of the output of a running process: see `CommandRunner::run_streaming`.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OutputChunk {
+  Stdout(Bytes),
+  Stderr(Bytes),
+}
+
+pub type OutputSender = mpsc::UnboundedSender<OutputChunk>;
+
 #[derive(Clone)]
 pub struct Context {
   workunit_store: WorkunitStore,
   build_id: String,
+  // A unique id for the Session which requested the process.
+  invocation_id: String,
+  // The priority of the Session which requested the process.
+  priority: SessionPriority,
+  // The total size of the outputs captured for the Session which requested the process, which is
+  // used to enforce a per-run quota.
+  captured_output_bytes: Arc<AtomicUsize>,
+  // If set, the output of processes which run locally is sent here as it is produced: see
+  // `CommandRunner::run_streaming`.
+  output_sender: Option<OutputSender>,
 }
 
 impl Default for Context {
@@ -446,15 +936,36 @@ impl Default for Context {
     Context {
       workunit_store: WorkunitStore::new(false),
       build_id: String::default(),
+      invocation_id: String::default(),
+      priority: SessionPriority::default(),
+      captured_output_bytes: Arc::default(),
+      output_sender: None,
     }
   }
 }
 
 impl Context {
-  pub fn new(workunit_store: WorkunitStore, build_id: String) -> Context {
+  pub fn new(
+    workunit_store: WorkunitStore,
+    build_id: String,
+    invocation_id: String,
+    priority: SessionPriority,
+    captured_output_bytes: Arc<AtomicUsize>,
+  ) -> Context {
     Context {
       workunit_store,
       build_id,
+      invocation_id,
+      priority,
+      captured_output_bytes,
+      output_sender: None,
+    }
+  }
+
+  pub fn with_output_sender(self, output_sender: OutputSender) -> Context {
+    Context {
+      output_sender: Some(output_sender),
+      ..self
     }
   }
 }
@@ -471,6 +982,28 @@ pub trait CommandRunner: Send + Sync {
     context: Context,
   ) -> Result<FallibleProcessResultWithPlatform, String>;
 
+  ///
+  /// As `run`, but additionally sends the stdout and stderr of the process to the given channel
+  /// while it runs, so that the output of long-running processes (such as test suites) can be
+  /// rendered before they complete. The complete output is also available from the result, as
+  /// usual.
+  ///
+  /// Output is only streamed by processes which actually run locally: a result which hits a cache
+  /// or which runs remotely sends nothing. And if multiple attempts are made to run the process
+  /// (due to speculation, for example), chunks may be sent by an attempt whose result is not the
+  /// one which is returned.
+  ///
+  async fn run_streaming(
+    &self,
+    req: MultiPlatformProcess,
+    context: Context,
+    output_sender: OutputSender,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    self
+      .run(req, context.with_output_sender(output_sender))
+      .await
+  }
+
   ///
   /// Given a multi platform request which may have some platform
   /// constraints determine if any of the requests contained within are compatible
@@ -504,18 +1037,33 @@ pub fn digest(req: MultiPlatformProcess, metadata: &ProcessMetadata) -> Digest {
   )
 }
 
+/// The env var which (if configured) exposes the execution slot of a process to the process.
+pub const EXECUTION_SLOT_ENV_VAR_NAME: &str = "PANTS_EXECUTION_SLOT";
+
 ///
 /// A CommandRunner wrapper that limits the number of concurrent requests.
 ///
+/// Each running process holds one of `bound` execution slots, which are identified by the
+/// integers `1..=bound`. No two concurrently running processes hold the same slot, so processes
+/// may use their slot to deterministically choose disjoint resources (ports, databases, etc).
+///
 #[derive(Clone)]
 pub struct BoundedCommandRunner {
   inner: Arc<(Box<dyn CommandRunner>, AsyncSemaphore)>,
+  // If set, the execution slot is exposed to every process in this env var (in addition to the
+  // `execution_slot_variable` requested by the process itself).
+  execution_slot_env_var: Option<String>,
 }
 
 impl BoundedCommandRunner {
-  pub fn new(inner: Box<dyn CommandRunner>, bound: usize) -> BoundedCommandRunner {
+  pub fn new(
+    inner: Box<dyn CommandRunner>,
+    bound: usize,
+    execution_slot_env_var: Option<String>,
+  ) -> BoundedCommandRunner {
     BoundedCommandRunner {
       inner: Arc::new((inner, AsyncSemaphore::new(bound))),
+      execution_slot_env_var,
     }
   }
 }
@@ -542,23 +1090,29 @@ impl CommandRunner for BoundedCommandRunner {
     let bounded_fut = {
       let inner = self.inner.clone();
       let semaphore = self.inner.1.clone();
+      let execution_slot_env_var = self.execution_slot_env_var.clone();
       let context = context.clone();
       let name = format!("{}-running", req.workunit_name());
+      let priority = context.priority.permit_priority();
 
-      semaphore.with_acquired(move |concurrency_id| {
+      semaphore.with_acquired_at_priority(priority, move |concurrency_id| {
         log::debug!(
           "Running {} under semaphore with concurrency id: {}",
           desc,
           concurrency_id
         );
+        let description_fields = req.description_fields();
+        let tags = req.tags();
+        // Tags are attached immediately, so that they are present even if the process fails to run.
         let metadata = WorkunitMetadata {
           level: req.workunit_level(),
           desc: Some(desc),
+          user_metadata: tags.user_metadata(),
           ..WorkunitMetadata::default()
         };
 
-        let metadata_updater = |result: &Result<FallibleProcessResultWithPlatform, String>,
-                                old_metadata| match result {
+        let metadata_updater = move |result: &Result<FallibleProcessResultWithPlatform, String>,
+                                     old_metadata| match result {
           Err(_) => old_metadata,
           Ok(FallibleProcessResultWithPlatform {
             stdout_digest,
@@ -571,17 +1125,24 @@ impl CommandRunner for BoundedCommandRunner {
             user_metadata: vec![(
               "exit_code".to_string(),
               UserMetadataItem::ImmediateId(*exit_code as i64),
-            )],
+            )]
+            .into_iter()
+            .chain(description_fields.user_metadata())
+            .chain(tags.user_metadata())
+            .collect(),
             ..old_metadata
           },
         };
 
         for (_, process) in req.0.iter_mut() {
-          if let Some(ref execution_slot_env_var) = process.execution_slot_variable {
-            let execution_slot = format!("{}", concurrency_id);
-            process
-              .env
-              .insert(execution_slot_env_var.clone(), execution_slot);
+          let execution_slot_env_vars = process
+            .execution_slot_variable
+            .iter()
+            .chain(execution_slot_env_var.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+          for env_var in execution_slot_env_vars {
+            process.env.insert(env_var, format!("{}", concurrency_id));
           }
         }
 
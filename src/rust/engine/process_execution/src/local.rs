@@ -1,6 +1,8 @@
-use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::fs::create_dir_all;
+use std::future::Future;
 use std::io::Write;
 use std::ops::Neg;
 use std::os::unix::{
@@ -15,37 +17,187 @@ use std::time::Instant;
 
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
-use fs::{
-  self, GlobExpansionConjunction, GlobMatching, PathGlobs, RelativePath, StrictGlobMatching,
-};
-use futures::future::{BoxFuture, FutureExt, TryFutureExt};
+use fs::RelativePath;
+use futures::future::{BoxFuture, Either, FutureExt, TryFutureExt};
 use futures::stream::{BoxStream, StreamExt, TryStreamExt};
 use log::{debug, info};
 use nails::execution::ExitCode;
 use shell_quote::bash;
-use store::{OneOffStoreFileByDigest, Snapshot, Store};
+use store::Store;
 use tokio::process::{Child, Command};
 use tokio::sync::RwLock;
 use tokio::time::{timeout, Duration};
 use tokio_util::codec::{BytesCodec, FramedRead};
-use tryfuture::try_future;
-use workunit_store::Metric;
+use workunit_store::{with_workunit, Level, Metric, ObservationMetric, WorkunitMetadata};
 
+use crate::access_audit::FileAccessAuditor;
+use crate::named_caches::NamedCacheSymlink;
+use crate::placeholders::Placeholders;
+use crate::platform_details::PlatformDetails;
+use crate::ports::PortLeases;
+use crate::pty::{Pty, WindowSize};
+use crate::redaction::StreamRedactor;
+use crate::resource_limits::wait_within_limits;
+#[cfg(target_os = "linux")]
+use crate::resource_limits::PidsCgroup;
+use crate::response_files::{self, RESPONSE_FILE_NAME};
+use crate::sandbox::{Sandbox, SandboxProvider, TempdirSandboxProvider};
+use crate::sandbox_retention::mark_preserved_sandbox;
+use crate::sandbox_slots::SandboxSlots;
 use crate::{
-  Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, NamedCaches, Platform, Process,
-  ProcessResultMetadata,
+  Context, ExitReason, FallibleProcessResultWithPlatform, MultiPlatformProcess, NamedCaches,
+  OutputChunk, OutputSender, Platform, Process, ProcessResultMetadata, Redactor, ResourceLimits,
 };
 
 pub const USER_EXECUTABLE_MODE: u32 = 0o100755;
 
+///
+/// The path (relative to the sandbox) of the dedicated temporary directory which is created for
+/// each local process, and which is advertised to it via `TMPDIR` (unless the process sets
+/// `TMPDIR` itself). A process may capture it as an output in order to debug its temporary files,
+/// but it is otherwise always cleaned up, even if the sandbox is preserved.
+///
+pub const SANDBOX_TMPDIR: &str = "__tmp";
+
+///
+/// The name of the script which is written to each preserved sandbox, and which re-runs its
+/// process with the same argv and environment.
+///
+pub const RUN_SCRIPT_NAME: &str = "__run.sh";
+
+///
+/// A phase of running a process in a local sandbox. Each phase runs in a child workunit of the
+/// process, and its duration is recorded separately, so that a slow process can be attributed to
+/// (for example) materializing its inputs rather than to executing.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SandboxPhase {
+  MaterializeInputs,
+  CreateOutputDirs,
+  Execute,
+  CaptureOutputs,
+  StoreResults,
+}
+
+impl SandboxPhase {
+  pub fn workunit_name(self) -> &'static str {
+    match self {
+      SandboxPhase::MaterializeInputs => "local_sandbox_materialize_inputs",
+      SandboxPhase::CreateOutputDirs => "local_sandbox_create_output_dirs",
+      SandboxPhase::Execute => "local_sandbox_execute",
+      SandboxPhase::CaptureOutputs => "local_sandbox_capture_outputs",
+      SandboxPhase::StoreResults => "local_sandbox_store_results",
+    }
+  }
+
+  fn metric(self) -> ObservationMetric {
+    match self {
+      SandboxPhase::MaterializeInputs => ObservationMetric::LocalSandboxMaterializeInputsTime,
+      SandboxPhase::CreateOutputDirs => ObservationMetric::LocalSandboxCreateOutputDirsTime,
+      SandboxPhase::Execute => ObservationMetric::LocalSandboxExecuteTime,
+      SandboxPhase::CaptureOutputs => ObservationMetric::LocalSandboxCaptureOutputsTime,
+      SandboxPhase::StoreResults => ObservationMetric::LocalSandboxStoreResultsTime,
+    }
+  }
+
+  ///
+  /// Runs the given Future as this phase.
+  ///
+  pub async fn run<F: Future>(self, f: F) -> F::Output {
+    let workunit_store_handle = match workunit_store::get_workunit_store_handle() {
+      Some(workunit_store_handle) => workunit_store_handle,
+      None => return f.await,
+    };
+    let start_time = Instant::now();
+    let metadata = WorkunitMetadata {
+      level: Level::Debug,
+      ..WorkunitMetadata::default()
+    };
+    let result = with_workunit(
+      workunit_store_handle.store.clone(),
+      self.workunit_name().to_owned(),
+      metadata,
+      f,
+      |_, md| md,
+    )
+    .await;
+    workunit_store_handle
+      .store
+      .record_observation_since(self.metric(), start_time);
+    result
+  }
+}
+
+///
+/// When to preserve the sandboxes of local processes (rather than deleting them once the process
+/// has completed), so that they can be inspected and the processes reproduced via `__run.sh`.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeepSandboxes {
+  Always,
+  Never,
+  /// Preserve sandboxes only for processes which fail (including those which time out). Each
+  /// attempt to run a process has its own sandbox, so the sandbox of a failed attempt is preserved
+  /// even if a later attempt (which is not served from the cache of failures) succeeds.
+  OnFailure,
+}
+
+impl TryFrom<String> for KeepSandboxes {
+  type Error = String;
+  fn try_from(variant_candidate: String) -> Result<Self, Self::Error> {
+    match variant_candidate.as_ref() {
+      "always" => Ok(KeepSandboxes::Always),
+      "never" => Ok(KeepSandboxes::Never),
+      "on_failure" => Ok(KeepSandboxes::OnFailure),
+      other => Err(format!("Unknown value for keep_sandboxes: {:?}", other)),
+    }
+  }
+}
+
+///
+/// The directories (relative to the sandbox) which are created before a Process runs: the parents
+/// of its outputs, and of the symlinks to its named caches.
+///
+pub fn sandbox_parent_directories(
+  req: &Process,
+  named_cache_symlinks: &[NamedCacheSymlink],
+) -> BTreeSet<PathBuf> {
+  // The bazel remote execution API specifies that the parent directories for output files and
+  // output directories should be created before execution completes: see
+  //   https://github.com/pantsbuild/pants/issues/7084.
+  // TODO: we use a set to deduplicate directory paths to create, but it would probably be
+  // even more efficient to only retain the directories at greatest nesting depth, as
+  // create_dir_all() will ensure all parents are created. At that point, we might consider
+  // explicitly enumerating all the directories to be created and just using create_dir(),
+  // unless there is some optimization in create_dir_all() that makes that less efficient.
+  req
+    .output_files
+    .iter()
+    .chain(req.output_directories.iter())
+    .chain(req.output_paths.iter())
+    .map(|relative_path| relative_path.as_ref())
+    .chain(named_cache_symlinks.iter().map(|s| s.dst.as_path()))
+    .filter_map(|rel_path| rel_path.parent())
+    .filter(|parent| *parent != Path::new(""))
+    .map(|parent| parent.to_path_buf())
+    .collect()
+}
+
 pub struct CommandRunner {
   pub store: Store,
   executor: task_executor::Executor,
   work_dir_base: PathBuf,
   named_caches: NamedCaches,
-  cleanup_local_dirs: bool,
+  keep_sandboxes: KeepSandboxes,
+  redactor: Redactor,
+  port_leases: PortLeases,
   platform: Platform,
+  platform_details: PlatformDetails,
   spawn_lock: RwLock<()>,
+  file_access_auditor: Option<FileAccessAuditor>,
+  sandbox_slots: SandboxSlots,
+  sandbox_provider: Arc<dyn SandboxProvider>,
+  workspace: Option<PathBuf>,
 }
 
 impl CommandRunner {
@@ -54,69 +206,99 @@ impl CommandRunner {
     executor: task_executor::Executor,
     work_dir_base: PathBuf,
     named_caches: NamedCaches,
-    cleanup_local_dirs: bool,
+    keep_sandboxes: KeepSandboxes,
+    redactor: Redactor,
   ) -> CommandRunner {
+    let platform = Platform::current().unwrap();
+    let sandbox_provider = Arc::new(TempdirSandboxProvider::new(executor.clone()));
     CommandRunner {
       store,
       executor,
       work_dir_base,
       named_caches,
-      cleanup_local_dirs,
-      platform: Platform::current().unwrap(),
+      keep_sandboxes,
+      redactor,
+      port_leases: PortLeases::default(),
+      platform,
+      platform_details: PlatformDetails::detect(platform),
       spawn_lock: RwLock::new(()),
+      file_access_auditor: None,
+      sandbox_slots: SandboxSlots::new(None),
+      sandbox_provider,
+      workspace: None,
     }
   }
 
-  fn platform(&self) -> Platform {
-    self.platform
+  ///
+  /// The SandboxSlots which bound the number of sandboxes which exist at once, which should be
+  /// shared with any other runners which create local sandboxes: see `SandboxSlots`.
+  ///
+  pub fn with_sandbox_slots(self, sandbox_slots: SandboxSlots) -> CommandRunner {
+    CommandRunner {
+      sandbox_slots,
+      ..self
+    }
   }
 
-  fn construct_output_snapshot(
-    store: Store,
-    posix_fs: Arc<fs::PosixFS>,
-    output_file_paths: BTreeSet<RelativePath>,
-    output_dir_paths: BTreeSet<RelativePath>,
-  ) -> BoxFuture<'static, Result<Snapshot, String>> {
-    let output_paths: Result<Vec<String>, String> = output_dir_paths
-      .into_iter()
-      .flat_map(|p| {
-        let mut dir_glob = PathBuf::from(p).into_os_string();
-        let dir = dir_glob.clone();
-        dir_glob.push("/**");
-        vec![dir, dir_glob]
-      })
-      .chain(
-        output_file_paths
-          .into_iter()
-          .map(|p| PathBuf::from(p).into_os_string()),
-      )
-      .map(|s| {
-        s.into_string()
-          .map_err(|e| format!("Error stringifying output paths: {:?}", e))
-      })
-      .collect();
+  ///
+  /// The PortLeases from which ports are leased to processes, which should be shared with any other
+  /// runners which lease ports, so that concurrent processes are never leased the same port.
+  ///
+  pub fn with_port_leases(self, port_leases: PortLeases) -> CommandRunner {
+    CommandRunner {
+      port_leases,
+      ..self
+    }
+  }
 
-    // TODO: should we error when globs fail?
-    let output_globs = try_future!(PathGlobs::new(
-      try_future!(output_paths),
-      StrictGlobMatching::Ignore,
-      GlobExpansionConjunction::AllMatch,
-    )
-    .parse());
+  ///
+  /// If set, runs processes under the given auditor, which reports their accesses of undeclared
+  /// paths.
+  ///
+  pub fn with_file_access_auditor(
+    self,
+    file_access_auditor: Option<FileAccessAuditor>,
+  ) -> CommandRunner {
+    CommandRunner {
+      file_access_auditor,
+      ..self
+    }
+  }
 
-    Box::pin(async move {
-      let path_stats = posix_fs
-        .expand_globs(output_globs, None)
-        .map_err(|err| format!("Error expanding output globs: {}", err))
-        .await?;
-      Snapshot::from_path_stats(
-        store.clone(),
-        OneOffStoreFileByDigest::new(store, posix_fs),
-        path_stats,
-      )
-      .await
-    })
-    .boxed()
+  ///
+  /// Declares properties of this machine (such as `xcode=14.2`) which processes may require via
+  /// their `PlatformRequirements`.
+  ///
+  pub fn with_platform_properties(self, properties: BTreeMap<String, String>) -> CommandRunner {
+    CommandRunner {
+      platform_details: self.platform_details.with_properties(properties),
+      ..self
+    }
+  }
+
+  ///
+  /// Creates sandboxes using the given SandboxProvider, rather than as temporary directories.
+  ///
+  pub fn with_sandbox_provider(self, sandbox_provider: Arc<dyn SandboxProvider>) -> CommandRunner {
+    CommandRunner {
+      sandbox_provider,
+      ..self
+    }
+  }
+
+  ///
+  /// Marks the sandboxes which are preserved as belonging to the given workspace, so that they are
+  /// only pruned by it: see `SandboxRetention`.
+  ///
+  pub fn with_workspace(self, workspace: PathBuf) -> CommandRunner {
+    CommandRunner {
+      workspace: Some(workspace),
+      ..self
+    }
+  }
+
+  fn platform(&self) -> Platform {
+    self.platform
   }
 }
 
@@ -125,7 +307,8 @@ pub struct HermeticCommand {
 }
 
 ///
-/// A command that accepts no input stream and does not consult the `PATH`.
+/// A command that accepts no input stream (unless it is explicitly piped from another process)
+/// and does not consult the `PATH`.
 ///
 impl HermeticCommand {
   fn new<S: AsRef<OsStr>>(program: S) -> HermeticCommand {
@@ -166,14 +349,50 @@ impl HermeticCommand {
     self
   }
 
-  fn spawn<O: Into<Stdio>, E: Into<Stdio>>(
+  ///
+  /// Makes the stdin of the command (which must be the slave side of a pseudo-terminal) its
+  /// controlling terminal, in a new session.
+  ///
+  fn controlling_terminal(&mut self) -> &mut HermeticCommand {
+    unsafe {
+      self
+        .inner
+        .pre_exec(|| crate::pty::set_controlling_terminal(libc::STDIN_FILENO));
+    }
+    self
+  }
+
+  ///
+  /// Applies the given limits to the command before it execs.
+  ///
+  fn resource_limits(&mut self, resource_limits: ResourceLimits) -> &mut HermeticCommand {
+    unsafe {
+      self.inner.pre_exec(move || resource_limits.apply());
+    }
+    self
+  }
+
+  ///
+  /// Runs the command in the given cgroup, which must outlive the spawning of the command.
+  ///
+  #[cfg(target_os = "linux")]
+  fn pids_cgroup(&mut self, cgroup: &PidsCgroup) -> &mut HermeticCommand {
+    let procs = cgroup.procs();
+    unsafe {
+      self.inner.pre_exec(move || PidsCgroup::enter(procs));
+    }
+    self
+  }
+
+  fn spawn<I: Into<Stdio>, O: Into<Stdio>, E: Into<Stdio>>(
     &mut self,
+    stdin: I,
     stdout: O,
     stderr: E,
   ) -> std::io::Result<Child> {
     self
       .inner
-      .stdin(Stdio::null())
+      .stdin(stdin)
       .stdout(stdout)
       .stderr(stderr)
       .spawn()
@@ -187,6 +406,8 @@ impl HermeticCommand {
 pub enum ChildOutput {
   Stdout(Bytes),
   Stderr(Bytes),
+  /// Precedes `Exit` if the process was stopped because it exceeded one of its `ResourceLimits`.
+  LimitExceeded(ExitReason),
   Exit(ExitCode),
 }
 
@@ -197,6 +418,7 @@ pub struct ChildResults {
   pub stdout: Bytes,
   pub stderr: Bytes,
   pub exit_code: i32,
+  pub exit_reason: Option<ExitReason>,
 }
 
 impl ChildResults {
@@ -206,12 +428,14 @@ impl ChildResults {
     let mut stdout = BytesMut::with_capacity(8192);
     let mut stderr = BytesMut::with_capacity(8192);
     let mut exit_code = 1;
+    let mut exit_reason = None;
 
     async move {
       while let Some(child_output_res) = stream.next().await {
         match child_output_res? {
           ChildOutput::Stdout(bytes) => stdout.extend_from_slice(&bytes),
           ChildOutput::Stderr(bytes) => stderr.extend_from_slice(&bytes),
+          ChildOutput::LimitExceeded(reason) => exit_reason = Some(reason),
           ChildOutput::Exit(code) => exit_code = code.0,
         };
       }
@@ -219,12 +443,81 @@ impl ChildResults {
         stdout: stdout.into(),
         stderr: stderr.into(),
         exit_code,
+        exit_reason,
       })
     }
     .boxed()
   }
 }
 
+///
+/// Sends each chunk of stdout and stderr in the given stream to the given sender as it passes
+/// through, with secrets redacted. If the receiver has been dropped, the chunks are discarded.
+///
+/// Output which might contain the start of a secret is held back until the next chunk of the same
+/// stream arrives. It is flushed (after being redacted) when the process exits, or when the stream
+/// ends or is dropped without an exit (because the process timed out, for example).
+///
+pub fn forward_output<'a>(
+  child_outputs: BoxStream<'a, Result<ChildOutput, String>>,
+  output_sender: OutputSender,
+  redactor: &Redactor,
+  env: &BTreeMap<String, String>,
+) -> BoxStream<'a, Result<ChildOutput, String>> {
+  let mut forwarder = OutputForwarder {
+    stdout_redactor: redactor.stream(env),
+    stderr_redactor: redactor.stream(env),
+    output_sender,
+  };
+  child_outputs
+    .inspect(move |child_output| match child_output {
+      Ok(ChildOutput::Stdout(bytes)) => {
+        let chunk = forwarder.stdout_redactor.redact_chunk(bytes);
+        forwarder.send(OutputChunk::Stdout(chunk));
+      }
+      Ok(ChildOutput::Stderr(bytes)) => {
+        let chunk = forwarder.stderr_redactor.redact_chunk(bytes);
+        forwarder.send(OutputChunk::Stderr(chunk));
+      }
+      Ok(ChildOutput::Exit(_)) => forwarder.flush(),
+      _ => (),
+    })
+    .boxed()
+}
+
+///
+/// The state of `forward_output`, which flushes any held back output when it is dropped.
+///
+struct OutputForwarder {
+  stdout_redactor: StreamRedactor,
+  stderr_redactor: StreamRedactor,
+  output_sender: OutputSender,
+}
+
+impl OutputForwarder {
+  fn send(&self, chunk: OutputChunk) {
+    let is_empty = match &chunk {
+      OutputChunk::Stdout(bytes) | OutputChunk::Stderr(bytes) => bytes.is_empty(),
+    };
+    if !is_empty {
+      let _ = self.output_sender.send(chunk);
+    }
+  }
+
+  fn flush(&mut self) {
+    let stdout = self.stdout_redactor.finish();
+    self.send(OutputChunk::Stdout(stdout));
+    let stderr = self.stderr_redactor.finish();
+    self.send(OutputChunk::Stderr(stderr));
+  }
+}
+
+impl Drop for OutputForwarder {
+  fn drop(&mut self) {
+    self.flush();
+  }
+}
+
 #[async_trait]
 impl super::CommandRunner for CommandRunner {
   fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
@@ -251,17 +544,33 @@ impl super::CommandRunner for CommandRunner {
       .increment_counter(Metric::LocalExecutionRequests, 1);
 
     let req = self.extract_compatible_request(&req).unwrap();
-    let req_debug_repr = format!("{:#?}", req);
+    req
+      .platform_requirements
+      .check(&self.platform_details)
+      .map_err(|e| {
+        format!(
+          "`{}` cannot run on this machine ({}): it {}.",
+          req.description, self.platform_details, e
+        )
+      })?;
+    let req_debug_repr = self
+      .redactor
+      .redact_str(&req.env, &format!("{:#?}", req))
+      .into_owned();
     self
       .run_and_capture_workdir(
         req,
         context,
         self.store.clone(),
         self.executor.clone(),
-        self.cleanup_local_dirs,
+        self.keep_sandboxes,
         &self.work_dir_base,
         self.platform(),
       )
+      .map_ok(|mut result| {
+        result.metadata.platform_details = Some(self.platform_details.clone());
+        result
+      })
       .map_err(|msg| {
         // Processes that experience no infrastructure issues should result in an "Ok" return,
         // potentially with an exit code that indicates that they failed (with more information
@@ -283,6 +592,30 @@ impl CapturedWorkdir for CommandRunner {
     &self.named_caches
   }
 
+  fn redactor(&self) -> &Redactor {
+    &self.redactor
+  }
+
+  fn port_leases(&self) -> &PortLeases {
+    &self.port_leases
+  }
+
+  fn file_access_auditor(&self) -> Option<&FileAccessAuditor> {
+    self.file_access_auditor.as_ref()
+  }
+
+  fn sandbox_slots(&self) -> &SandboxSlots {
+    &self.sandbox_slots
+  }
+
+  fn sandbox_provider(&self) -> &Arc<dyn SandboxProvider> {
+    &self.sandbox_provider
+  }
+
+  fn workspace(&self) -> Option<&Path> {
+    self.workspace.as_deref()
+  }
+
   async fn run_in_workdir<'a, 'b, 'c>(
     &'a self,
     workdir_path: &'b Path,
@@ -295,14 +628,65 @@ impl CapturedWorkdir for CommandRunner {
     } else {
       workdir_path.to_owned()
     };
-    // TODO(#11406): Go back to using relative paths, rather than absolutifying them, once Rust
-    //  fixes https://github.com/rust-lang/rust/issues/80819 for macOS.
-    let argv0 = match RelativePath::new(&req.argv[0]) {
-      Ok(rel_path) => cwd.join(rel_path),
-      Err(_) => PathBuf::from(&req.argv[0]),
+    let hermetic_command = |argv: &[String]| {
+      // TODO(#11406): Go back to using relative paths, rather than absolutifying them, once Rust
+      //  fixes https://github.com/rust-lang/rust/issues/80819 for macOS.
+      let argv0 = match RelativePath::new(&argv[0]) {
+        Ok(rel_path) => cwd.join(rel_path),
+        Err(_) => PathBuf::from(&argv[0]),
+      };
+      let mut command = HermeticCommand::new(argv0);
+      command.args(&argv[1..]).current_dir(&cwd).envs(&req.env);
+      command
+    };
+    if req.allocate_tty && req.pipe_from_argv.is_some() {
+      return Err(format!(
+        "A process which allocates a TTY may not also set `pipe_from_argv`: {}",
+        req.description
+      ));
+    }
+    let mut command = hermetic_command(&req.argv);
+    // If the process allocates a TTY, the slave side of the terminal is its stdin, stdout and
+    // stderr, and everything that it writes is read from the master side. While the process runs,
+    // the terminal is resized to follow the terminal that Pants is attached to.
+    let (maybe_pty, maybe_pty_resizer) = if req.allocate_tty {
+      command.controlling_terminal();
+      let window_size = WindowSize::of_destination();
+      let pty = Pty::open(window_size)?;
+      let pty_resizer = pty.resizer(window_size)?;
+      (Some(pty), Some(pty_resizer))
+    } else {
+      (None, None)
     };
-    let mut command = HermeticCommand::new(argv0);
-    command.args(&req.argv[1..]).current_dir(cwd).envs(&req.env);
+    if !req.resource_limits.is_empty() {
+      if req.resource_limits.max_rss_bytes.is_some() && !cfg!(target_os = "linux") {
+        return Err(format!(
+          "Memory limits for processes are only supported on Linux: {}",
+          req.description
+        ));
+      }
+      if req.resource_limits.nproc.is_some() && !cfg!(target_os = "linux") {
+        return Err(format!(
+          "Limits on the number of processes are only supported on Linux: {}",
+          req.description
+        ));
+      }
+      command.resource_limits(req.resource_limits);
+    }
+    // The cgroup which limits the number of processes is removed once the process has exited.
+    #[cfg(target_os = "linux")]
+    let pids_cgroup = match req.resource_limits.nproc {
+      Some(nproc) => {
+        let cgroup = PidsCgroup::create(nproc)
+          .map_err(|e| format!("Failed to limit processes of {}: {}", req.description, e))?;
+        command.pids_cgroup(&cgroup);
+        Some(cgroup)
+      }
+      None => None,
+    };
+    // If this process is the sink of a pipe, its source is spawned first, and then the output of
+    // the source is streamed into the sink's stdin below.
+    let mut maybe_source_command = req.pipe_from_argv.as_deref().map(hermetic_command);
 
     // See the documentation of the `CapturedWorkdir::run_in_workdir` method, but `exclusive_spawn`
     // indicates the binary we're spawning was written out by the current thread, and, as such,
@@ -321,8 +705,25 @@ impl CapturedWorkdir for CommandRunner {
     //
     // See: https://github.com/golang/go/issues/22315 for an excellent description of this generic
     // unix problem.
-    let mut fork_exec = move || command.spawn(Stdio::piped(), Stdio::piped());
-    let mut child = {
+    let mut fork_exec = || -> std::io::Result<(Child, Option<Child>)> {
+      if let Some(pty) = &maybe_pty {
+        let child = command.spawn(pty.slave()?, pty.slave()?, pty.slave()?)?;
+        return Ok((child, None));
+      }
+      // NB: If spawning the sink fails, the source will be killed when it is dropped.
+      let maybe_source = maybe_source_command
+        .as_mut()
+        .map(|source_command| source_command.spawn(Stdio::null(), Stdio::piped(), Stdio::piped()))
+        .transpose()?;
+      let stdin = if maybe_source.is_some() {
+        Stdio::piped()
+      } else {
+        Stdio::null()
+      };
+      let child = command.spawn(stdin, Stdio::piped(), Stdio::piped())?;
+      Ok((child, maybe_source))
+    };
+    let (mut child, maybe_source) = {
       if exclusive_spawn {
         let _write_locked = self.spawn_lock.write().await;
 
@@ -354,7 +755,7 @@ impl CapturedWorkdir for CommandRunner {
                 break Err(format!("Error launching process: {:?}", e));
               }
             }
-            Ok(child) => break Ok(child),
+            Ok(children) => break Ok(children),
           }
         }
       } else {
@@ -362,33 +763,105 @@ impl CapturedWorkdir for CommandRunner {
         fork_exec().map_err(|e| format!("Error launching process: {:?}", e))
       }
     }?;
+    // NB: The command holds handles to the stdio of the child, which must be closed before the
+    // master side of a TTY will observe EOF.
+    std::mem::drop(command);
 
     debug!("spawned local process as {:?} for {:?}", child.id(), req);
-    let stdout_stream = FramedRead::new(child.stdout.take().unwrap(), BytesCodec::new())
-      .map_ok(|bytes| ChildOutput::Stdout(bytes.into()))
-      .fuse()
-      .boxed();
-    let stderr_stream = FramedRead::new(child.stderr.take().unwrap(), BytesCodec::new())
-      .map_ok(|bytes| ChildOutput::Stderr(bytes.into()))
-      .fuse()
-      .boxed();
+    let mut streams = if let Some(pty) = maybe_pty {
+      // The output of a process with a TTY is merged, and so is all captured as its stdout.
+      vec![FramedRead::new(pty.into_master()?, BytesCodec::new())
+        .map_ok(|bytes| ChildOutput::Stdout(bytes.into()))
+        .fuse()
+        .boxed()]
+    } else {
+      let stdout_stream = FramedRead::new(child.stdout.take().unwrap(), BytesCodec::new())
+        .map_ok(|bytes| ChildOutput::Stdout(bytes.into()))
+        .fuse()
+        .boxed();
+      let stderr_stream = FramedRead::new(child.stderr.take().unwrap(), BytesCodec::new())
+        .map_ok(|bytes| ChildOutput::Stderr(bytes.into()))
+        .fuse()
+        .boxed();
+      vec![stdout_stream, stderr_stream]
+    };
+
+    let maybe_source = maybe_source.map(|mut source| {
+      debug!(
+        "spawned local process as {:?} to pipe into {:?}",
+        source.id(),
+        child.id()
+      );
+      streams.push(
+        FramedRead::new(source.stderr.take().unwrap(), BytesCodec::new())
+          .map_ok(|bytes| ChildOutput::Stderr(bytes.into()))
+          .fuse()
+          .boxed(),
+      );
+      let mut source_stdout = source.stdout.take().unwrap();
+      let mut sink_stdin = child.stdin.take().unwrap();
+      let pipe = async move {
+        match tokio::io::copy(&mut source_stdout, &mut sink_stdin).await {
+          // As with a shell pipe, the sink may exit without consuming all of its input, in which
+          // case the rest of the input is discarded (and the source will receive SIGPIPE).
+          Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e),
+          _ => Ok(()),
+        }
+        // NB: Dropping the handles here closes the stdin of the sink.
+      };
+      (source, pipe)
+    });
+
+    let resource_limits = req.resource_limits;
     let exit_stream = async move {
-      child
-        .wait()
-        .map_ok(|exit_status| {
-          ChildOutput::Exit(ExitCode(
-            exit_status
-              .code()
-              .or_else(|| exit_status.signal().map(Neg::neg))
-              .expect("Child process should exit via returned code or signal."),
-          ))
-        })
-        .await
+      let source_exit = async {
+        if let Some((mut source, pipe)) = maybe_source {
+          let (pipe_result, source_exit_status) = futures::future::join(pipe, source.wait()).await;
+          pipe_result?;
+          Ok::<_, std::io::Error>(Some(exit_code(source_exit_status?)))
+        } else {
+          Ok(None)
+        }
+      };
+      let sink_wait = wait_within_limits(&mut child, resource_limits);
+      let sink_wait = async {
+        match maybe_pty_resizer {
+          Some(pty_resizer) => {
+            match futures::future::select(
+              sink_wait.boxed(),
+              pty_resizer.follow_destination().boxed(),
+            )
+            .await
+            {
+              Either::Left((sink_exit, _)) => sink_exit,
+              Either::Right(((), sink_wait)) => sink_wait.await,
+            }
+          }
+          None => sink_wait.await,
+        }
+      };
+      // NB: The sink is waited for concurrently with the source, so that its memory is sampled for
+      // as long as it runs.
+      let (source_exit_code, sink_exit) = futures::future::join(source_exit, sink_wait).await;
+      let (sink_exit_status, exit_reason) = sink_exit?;
+      let sink_exit_code = exit_code(sink_exit_status);
+      let code = match source_exit_code? {
+        Some(source_exit_code) if sink_exit_code == 0 => source_exit_code,
+        _ => sink_exit_code,
+      };
+      #[cfg(target_os = "linux")]
+      std::mem::drop(pids_cgroup);
+      let outputs = exit_reason
+        .map(ChildOutput::LimitExceeded)
+        .into_iter()
+        .chain(std::iter::once(ChildOutput::Exit(ExitCode(code))))
+        .map(Ok);
+      Ok::<_, std::io::Error>(futures::stream::iter(outputs))
     }
-    .into_stream()
+    .try_flatten_stream()
     .boxed();
-    let result_stream =
-      futures::stream::select_all(vec![stdout_stream, stderr_stream, exit_stream]);
+    streams.push(exit_stream);
+    let result_stream = futures::stream::select_all(streams);
 
     Ok(
       result_stream
@@ -398,38 +871,43 @@ impl CapturedWorkdir for CommandRunner {
   }
 }
 
+pub(crate) fn exit_code(exit_status: std::process::ExitStatus) -> i32 {
+  exit_status
+    .code()
+    .or_else(|| exit_status.signal().map(Neg::neg))
+    .expect("Child process should exit via returned code or signal.")
+}
+
 #[async_trait]
 pub trait CapturedWorkdir {
   async fn run_and_capture_workdir(
     &self,
-    req: Process,
+    mut req: Process,
     context: Context,
     store: Store,
     executor: task_executor::Executor,
-    cleanup_local_dirs: bool,
+    keep_sandboxes: KeepSandboxes,
     workdir_base: &Path,
     platform: Platform,
   ) -> Result<FallibleProcessResultWithPlatform, String> {
     let start_time = Instant::now();
+    let workunit_store = context.workunit_store.clone();
+
+    // Wait for a slot for the sandbox, which is held until it has been cleaned up.
+    let sandbox_slot = self.sandbox_slots().acquire().await?;
 
-    // Set up a temporary workdir, which will optionally be preserved.
+    // Set up a sandbox, which will optionally be preserved.
+    let sandbox_provider = self.sandbox_provider().clone();
     let (workdir_path, maybe_workdir) = {
-      let workdir = tempfile::Builder::new()
-        .prefix("process-execution")
-        .tempdir_in(workdir_base)
-        .map_err(|err| {
-          format!(
-            "Error making tempdir for local process execution: {:?}",
-            err
-          )
-        })?;
-      if cleanup_local_dirs {
-        // Hold on to the workdir so that we can drop it explicitly after we've finished using it.
+      let workdir =
+        Sandbox::create(sandbox_provider.clone(), executor.clone(), workdir_base).await?;
+      if keep_sandboxes != KeepSandboxes::Always {
+        // Hold on to the sandbox so that we can destroy (or preserve) it explicitly after we've
+        // finished using it.
         (workdir.path().to_owned(), Some(workdir))
       } else {
-        // This consumes the `TempDir` without deleting directory on the filesystem, meaning
-        // that the temporary directory will no longer be automatically deleted when dropped.
-        let preserved_path = workdir.into_path();
+        // The sandbox will not be destroyed when it is dropped.
+        let preserved_path = workdir.preserve();
         info!(
           "preserving local process execution dir `{:?}` for {:?}",
           preserved_path, req.description
@@ -438,6 +916,54 @@ pub trait CapturedWorkdir {
       }
     };
 
+    // Expand any placeholders in the env now that the sandbox exists.
+    req.env = Placeholders {
+      chroot: &workdir_path,
+      named_caches: self.named_caches(),
+      platform,
+    }
+    .expand_env(&req.env)?;
+
+    if let Some(ref invocation_id_variable) = req.invocation_id_variable {
+      req.env.insert(
+        invocation_id_variable.clone(),
+        context.invocation_id.clone(),
+      );
+    }
+
+    // Lease any requested ports, which are held until the process has exited.
+    let port_leases = self.port_leases().lease(req.leased_port_variables.len())?;
+    for (env_var, lease) in req.leased_port_variables.iter().zip(port_leases.iter()) {
+      req.env.insert(env_var.clone(), lease.port.to_string());
+    }
+
+    let tmpdir_path = workdir_path.join(SANDBOX_TMPDIR);
+    if !req.env.contains_key("TMPDIR") {
+      req.env.insert(
+        "TMPDIR".to_owned(),
+        tmpdir_path.to_string_lossy().into_owned(),
+      );
+    }
+
+    // If the arguments and env of the process would exceed the limits of the OS, spill its
+    // arguments into a response file (if it supports one) rather than failing with E2BIG.
+    let maybe_response_file = match req.response_file {
+      Some(ref response_file)
+        if response_files::exceeds_arg_max(&req.argv, &req.env, response_files::arg_max()) =>
+      {
+        let path = workdir_path.join(RESPONSE_FILE_NAME);
+        let (argv, content) = response_file.spill(&req.argv, &path)?;
+        debug!(
+          "Spilled {} arguments of `{}` into a response file.",
+          req.argv.len() + 1 - argv.len(),
+          req.description
+        );
+        req.argv = argv;
+        Some((path, content))
+      }
+      _ => None,
+    };
+
     // If named caches are configured, collect the symlinks to create.
     let named_cache_symlinks = self
       .named_caches()
@@ -447,37 +973,34 @@ pub trait CapturedWorkdir {
     // Start with async materialization of input snapshots, followed by synchronous materialization
     // of other configured inputs. Note that we don't do this in parallel, as that might cause
     // non-determinism when paths overlap.
-    let sandbox = store
-      .materialize_directory(workdir_path.clone(), req.input_files)
+    let sandbox = SandboxPhase::MaterializeInputs
+      .run(sandbox_provider.materialize(&store, &workdir_path, req.input_files))
       .await?;
     let workdir_path2 = workdir_path.clone();
-    let output_file_paths = req.output_files.clone();
-    let output_dir_paths = req.output_directories.clone();
+    let parent_paths_to_create = sandbox_parent_directories(&req, &named_cache_symlinks);
     let maybe_jdk_home = req.jdk_home.clone();
-    executor
-      .spawn_blocking(move || {
+    let tmpdir_path2 = tmpdir_path.clone();
+    SandboxPhase::CreateOutputDirs
+      .run(executor.spawn_blocking(move || {
+        create_dir_all(&tmpdir_path2).map_err(|err| {
+          format!(
+            "Error making temporary directory {:?} for local execution: {:?}",
+            tmpdir_path2, err
+          )
+        })?;
+
+        if let Some((path, content)) = maybe_response_file {
+          std::fs::write(&path, content)
+            .map_err(|err| format!("Error writing response file {:?}: {:?}", path, err))?;
+        }
+
         if let Some(jdk_home) = maybe_jdk_home {
           symlink(jdk_home, workdir_path2.join(".jdk"))
             .map_err(|err| format!("Error making JDK symlink for local execution: {:?}", err))?
         }
 
-        // The bazel remote execution API specifies that the parent directories for output files and
-        // output directories should be created before execution completes: see
-        //   https://github.com/pantsbuild/pants/issues/7084.
-        // TODO: we use a HashSet to deduplicate directory paths to create, but it would probably be
-        // even more efficient to only retain the directories at greatest nesting depth, as
-        // create_dir_all() will ensure all parents are created. At that point, we might consider
-        // explicitly enumerating all the directories to be created and just using create_dir(),
-        // unless there is some optimization in create_dir_all() that makes that less efficient.
-        let parent_paths_to_create: HashSet<_> = output_file_paths
-          .iter()
-          .chain(output_dir_paths.iter())
-          .map(|relative_path| relative_path.as_ref())
-          .chain(named_cache_symlinks.iter().map(|s| s.dst.as_path()))
-          .filter_map(|rel_path| rel_path.parent())
-          .map(|parent_relpath| workdir_path2.join(parent_relpath))
-          .collect();
-        for path in parent_paths_to_create {
+        for parent_relpath in parent_paths_to_create {
+          let path = workdir_path2.join(parent_relpath);
           create_dir_all(path.clone()).map_err(|err| {
             format!(
               "Error making parent directory {:?} for local execution: {:?}",
@@ -501,7 +1024,7 @@ pub trait CapturedWorkdir {
 
         let res: Result<_, String> = Ok(());
         res
-      })
+      }))
       .await?;
 
     let exclusive_spawn = RelativePath::new(&req.argv[0]).map_or(false, |relative_path| {
@@ -517,81 +1040,203 @@ pub trait CapturedWorkdir {
       exe_was_materialized
     });
 
+    // If file accesses are being audited, run the process under the tracer, which logs to a file
+    // outside of the sandbox.
+    let maybe_audit = match self.file_access_auditor() {
+      Some(auditor) => {
+        let trace_file = tempfile::Builder::new()
+          .prefix("file-access-trace")
+          .tempfile_in(workdir_base)
+          .map_err(|e| format!("Error making file access trace file: {:?}", e))?;
+        Some((auditor, trace_file))
+      }
+      None => None,
+    };
+    let spawned_req = match &maybe_audit {
+      Some((auditor, trace_file)) => {
+        let cwd = match &req.working_directory {
+          Some(working_directory) => workdir_path.join(working_directory),
+          None => workdir_path.clone(),
+        };
+        Process {
+          argv: auditor.traced_argv(&req.argv, &cwd, trace_file.path()),
+          ..req.clone()
+        }
+      }
+      None => req.clone(),
+    };
+
     // Spawn the process.
-    // NB: We fully buffer up the `Stream` above into final `ChildResults` below and so could
-    // instead be using `CommandExt::output_async` above to avoid the `ChildResults::collect_from`
-    // code. The idea going forward though is we eventually want to pass incremental results on
-    // down the line for streaming process results to console logs, etc. as tracked by:
-    //   https://github.com/pantsbuild/pants/issues/6089
-    let child_results_result = {
-      let child_results_future = ChildResults::collect_from(
-        self
-          .run_in_workdir(&workdir_path, req.clone(), context, exclusive_spawn)
-          .await?,
-      );
-      if let Some(req_timeout) = req.timeout {
-        timeout(req_timeout, child_results_future)
-          .await
-          .map_err(|e| e.to_string())
-          .and_then(|r| r)
-      } else {
-        child_results_future.await
+    // NB: The output of the process is fully buffered into the final `ChildResults` below, but if
+    // it was requested (see `CommandRunner::run_streaming`), it is also forwarded as it arrives.
+    let output_sender = context.output_sender.clone();
+    let child_results_result = SandboxPhase::Execute
+      .run(async {
+        let child_outputs = self
+          .run_in_workdir(&workdir_path, spawned_req, context, exclusive_spawn)
+          .await?;
+        let child_outputs = match output_sender {
+          Some(output_sender) => {
+            forward_output(child_outputs, output_sender, self.redactor(), &req.env)
+          }
+          None => child_outputs,
+        };
+        let child_results_future = ChildResults::collect_from(child_outputs);
+        let child_results_result = if let Some(req_timeout) = req.timeout {
+          timeout(req_timeout, child_results_future)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r)
+        } else {
+          child_results_future.await
+        };
+        Ok::<_, String>(child_results_result)
+      })
+      .await?;
+
+    std::mem::drop(port_leases);
+
+    // If writes outside of the sandbox are forbidden, this is the error which fails the process.
+    let external_writes_error = match maybe_audit {
+      Some((auditor, trace_file)) => {
+        auditor.report(&req.description, &workdir_path, trace_file.path())
       }
+      None => None,
+    };
+
+    // Measure the sandbox while it is at its largest, including any outputs and temporary files.
+    let sandbox_slot = {
+      let workdir_path = workdir_path.clone();
+      let (sandbox_slot, live_sandbox_bytes) = executor
+        .spawn_blocking(move || {
+          let mut sandbox_slot = sandbox_slot;
+          let live_sandbox_bytes = sandbox_slot.measure(&workdir_path);
+          (sandbox_slot, live_sandbox_bytes)
+        })
+        .await;
+      workunit_store.record_observation(
+        ObservationMetric::LocalSandboxesLiveBytes,
+        live_sandbox_bytes,
+      );
+      sandbox_slot
     };
 
     // Capture the process outputs, and optionally clean up the workdir.
-    let output_snapshot = if req.output_files.is_empty() && req.output_directories.is_empty() {
+    let output_files = req.output_files;
+    // Output paths are captured like directories, which also captures them if they are files.
+    let output_directories = req
+      .output_directories
+      .into_iter()
+      .chain(req.output_paths.into_iter())
+      .collect::<BTreeSet<_>>();
+    let output_snapshot = if output_files.is_empty() && output_directories.is_empty() {
       store::Snapshot::empty()
     } else {
-      // Use no ignore patterns, because we are looking for explicitly listed paths.
-      let posix_fs = Arc::new(
-        fs::PosixFS::new(
-          workdir_path.clone(),
-          fs::GitignoreStyleExcludes::empty(),
-          executor.clone(),
-        )
-        .map_err(|err| {
-          format!(
-            "Error making posix_fs to fetch local process execution output files: {}",
-            err
-          )
-        })?,
-      );
-      CommandRunner::construct_output_snapshot(
-        store.clone(),
-        posix_fs,
-        req.output_files,
-        req.output_directories,
-      )
-      .await?
+      SandboxPhase::CaptureOutputs
+        .run(sandbox_provider.capture(&store, &workdir_path, output_files, output_directories))
+        .await?
     };
 
-    match maybe_workdir {
+    let failed = external_writes_error.is_some()
+      || !matches!(&child_results_result, Ok(child_results) if child_results.exit_code == 0);
+    let preserved_sandbox = match maybe_workdir {
+      Some(workdir) if keep_sandboxes == KeepSandboxes::OnFailure && failed => {
+        let preserved_path = workdir.preserve();
+        info!(
+          "preserving local process execution dir `{:?}` for failed process {:?}",
+          preserved_path, req.description
+        );
+        Some(preserved_path)
+      }
       Some(workdir) => {
-        // Dropping the temporary directory will likely involve a lot of IO: do it in the
-        // background.
-        let _background_cleanup = executor.spawn_blocking(|| std::mem::drop(workdir));
+        // Destroying the sandbox will likely involve a lot of IO: do it in the background, and
+        // only then release the slot of the sandbox.
+        workdir.destroy_then_drop(sandbox_slot);
+        None
       }
-      None => {
-        setup_run_sh_script(&req.env, &req.working_directory, &req.argv, &workdir_path)?;
+      None => Some(workdir_path.clone()),
+    };
+    if let Some(ref preserved_sandbox) = preserved_sandbox {
+      // Temporary files are not preserved, but the (empty) directory is, so that the process can
+      // be re-run using `__run.sh`.
+      let tmpdir_path = preserved_sandbox.join(SANDBOX_TMPDIR);
+      executor
+        .spawn_blocking(move || {
+          // The process may have removed the directory itself.
+          match std::fs::remove_dir_all(&tmpdir_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+          }
+          .and_then(|()| create_dir_all(&tmpdir_path))
+          .map_err(|err| {
+            format!(
+              "Error cleaning temporary directory {:?} of preserved sandbox: {:?}",
+              tmpdir_path, err
+            )
+          })
+        })
+        .await?;
+      setup_run_sh_script(
+        &req.env,
+        &req.working_directory,
+        &req.argv,
+        preserved_sandbox,
+        self.redactor(),
+      )?;
+      if let Some(workspace) = self.workspace() {
+        mark_preserved_sandbox(preserved_sandbox, workspace)?;
       }
     }
 
     let elapsed = start_time.elapsed();
-    let result_metadata = ProcessResultMetadata::new(Some(elapsed.into()));
+    let mut result_metadata = ProcessResultMetadata::new(Some(elapsed.into()));
+    result_metadata.preserved_sandbox = preserved_sandbox;
 
     match child_results_result {
       Ok(child_results) => {
-        let stdout = child_results.stdout;
-        let stdout_digest = store.store_file_bytes(stdout.clone(), true).await?;
+        let redactor = self.redactor();
+        let env = &req.env;
+        let (exit_code, stderr) = match external_writes_error {
+          Some(error) => {
+            let mut stderr = BytesMut::from(&child_results.stderr[..]);
+            stderr.extend_from_slice(format!("\n{}\n", error).as_bytes());
+            let exit_code = if child_results.exit_code == 0 {
+              1
+            } else {
+              child_results.exit_code
+            };
+            (exit_code, stderr.freeze())
+          }
+          None => (child_results.exit_code, child_results.stderr),
+        };
+        // If the process exceeded one of its resource limits, say so, since its exit code alone
+        // (a signal) would not.
+        let stderr = match child_results.exit_reason {
+          Some(exit_reason) => {
+            let mut stderr = BytesMut::from(&stderr[..]);
+            stderr.extend_from_slice(
+              format!("\n{}\n", exit_reason.describe(&req.resource_limits)).as_bytes(),
+            );
+            stderr.freeze()
+          }
+          None => stderr,
+        };
+        result_metadata.exit_reason = child_results.exit_reason;
+        let (stdout_digest, stderr_digest) = SandboxPhase::StoreResults
+          .run(async {
+            let stdout = redactor.redact_bytes(env, child_results.stdout);
+            let stdout_digest = store.store_file_bytes(stdout, true).await?;
 
-        let stderr = child_results.stderr;
-        let stderr_digest = store.store_file_bytes(stderr.clone(), true).await?;
+            let stderr = redactor.redact_bytes(env, stderr);
+            let stderr_digest = store.store_file_bytes(stderr, true).await?;
+            Ok::<_, String>((stdout_digest, stderr_digest))
+          })
+          .await?;
 
         Ok(FallibleProcessResultWithPlatform {
           stdout_digest,
           stderr_digest,
-          exit_code: child_results.exit_code,
+          exit_code,
           output_directory: output_snapshot.digest,
           platform,
           metadata: result_metadata,
@@ -619,6 +1264,37 @@ pub trait CapturedWorkdir {
 
   fn named_caches(&self) -> &NamedCaches;
 
+  ///
+  /// The Redactor to apply to the captured stdio of processes, and to any debugging artifacts
+  /// which describe them.
+  ///
+  fn redactor(&self) -> &Redactor;
+
+  ///
+  /// The PortLeases from which to lease the `leased_port_variables` of processes.
+  ///
+  fn port_leases(&self) -> &PortLeases;
+
+  ///
+  /// The FileAccessAuditor to run processes under, if any.
+  ///
+  fn file_access_auditor(&self) -> Option<&FileAccessAuditor>;
+
+  ///
+  /// The SandboxSlots which bound the number of sandboxes which exist at once.
+  ///
+  fn sandbox_slots(&self) -> &SandboxSlots;
+
+  ///
+  /// The SandboxProvider which creates (and destroys) the sandboxes of processes.
+  ///
+  fn sandbox_provider(&self) -> &Arc<dyn SandboxProvider>;
+
+  ///
+  /// The workspace which preserved sandboxes are marked as belonging to, if any.
+  ///
+  fn workspace(&self) -> Option<&Path>;
+
   ///
   /// Spawn the given process in a working directory prepared with its expected input digest.
   ///
@@ -652,6 +1328,7 @@ fn setup_run_sh_script(
   working_directory: &Option<RelativePath>,
   argv: &[String],
   workdir_path: &PathBuf,
+  redactor: &Redactor,
 ) -> Result<(), String> {
   let mut env_var_strings: Vec<String> = vec![];
   for (key, value) in env.iter() {
@@ -697,7 +1374,7 @@ cd {}
     stringified_env_vars, stringified_cwd, stringified_command_line,
   );
 
-  let full_file_path = workdir_path.join("__run.sh");
+  let full_file_path = workdir_path.join(RUN_SCRIPT_NAME);
 
   std::fs::OpenOptions::new()
     .create_new(true)
@@ -705,6 +1382,6 @@ cd {}
     .mode(USER_EXECUTABLE_MODE) // Executable for user, read-only for others.
     .open(&full_file_path)
     .map_err(|e| format!("{:?}", e))?
-    .write_all(full_script.as_bytes())
+    .write_all(&redactor.redact(env, full_script.as_bytes()))
     .map_err(|e| format!("{:?}", e))
 }
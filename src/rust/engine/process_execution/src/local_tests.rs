@@ -1,15 +1,17 @@
 use tempfile;
 use testutil;
 
+use crate::local::{forward_output, ChildOutput, KeepSandboxes, SandboxPhase, SANDBOX_TMPDIR};
 use crate::{
-  CacheDest, CacheName, CommandRunner as CommandRunnerTrait, Context,
-  FallibleProcessResultWithPlatform, NamedCaches, Platform, Process, RelativePath,
+  CacheDest, CacheName, CommandRunner as CommandRunnerTrait, Context, ExitReason,
+  FallibleProcessResultWithPlatform, NamedCaches, OutputChunk, Platform, Process, RelativePath,
+  ResponseFileSpec,
 };
 use hashing::EMPTY_DIGEST;
 use shell_quote::bash;
 use spectral::{assert_that, string::StrAssertions};
 use std;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::path::PathBuf;
 use std::str;
 use std::time::Duration;
@@ -61,6 +63,165 @@ async fn stdout_and_stderr_and_exit_code() {
   assert_eq!(result.original.output_directory, EMPTY_DIGEST);
 }
 
+#[tokio::test]
+#[cfg(unix)]
+async fn output_is_streamed_while_running() {
+  WorkunitStore::setup_for_tests();
+
+  let work_dir = TempDir::new().unwrap();
+  let store_dir = TempDir::new().unwrap();
+  let named_cache_dir = TempDir::new().unwrap();
+  let release_dir = TempDir::new().unwrap();
+  let release_file = release_dir.path().join("release");
+  let executor = task_executor::Executor::new();
+  let store = Store::local_only(executor.clone(), store_dir.path()).unwrap();
+  let runner = crate::local::CommandRunner::new(
+    store,
+    executor.clone(),
+    work_dir.path().to_owned(),
+    NamedCaches::new(named_cache_dir.path().to_owned()),
+    KeepSandboxes::Never,
+    crate::Redactor::default(),
+  );
+
+  // The process blocks after writing its first output, until it is released.
+  let process = Process::new(owned_string_vec(&[
+    "/bin/bash",
+    "-c",
+    &format!(
+      "echo -n started ; while [ ! -f {} ]; do sleep 0.05; done ; echo >&2 -n finished",
+      str::from_utf8(&bash::escape(release_file.to_str().unwrap())).unwrap()
+    ),
+  ]));
+  let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+  let result = executor.spawn(async move {
+    runner
+      .run_streaming(process.into(), Context::default(), sender)
+      .await
+  });
+
+  assert_eq!(
+    receiver.recv().await,
+    Some(OutputChunk::Stdout("started".into()))
+  );
+  std::fs::write(&release_file, b"").unwrap();
+  assert_eq!(
+    receiver.recv().await,
+    Some(OutputChunk::Stderr("finished".into()))
+  );
+  assert_eq!(result.await.unwrap().exit_code, 0);
+  // The sender is dropped once the process has completed.
+  assert_eq!(receiver.recv().await, None);
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn streamed_output_is_redacted() {
+  WorkunitStore::setup_for_tests();
+
+  let work_dir = TempDir::new().unwrap();
+  let store_dir = TempDir::new().unwrap();
+  let named_cache_dir = TempDir::new().unwrap();
+  let executor = task_executor::Executor::new();
+  let store = Store::local_only(executor.clone(), store_dir.path()).unwrap();
+  let runner = crate::local::CommandRunner::new(
+    store,
+    executor.clone(),
+    work_dir.path().to_owned(),
+    NamedCaches::new(named_cache_dir.path().to_owned()),
+    KeepSandboxes::Never,
+    crate::Redactor::new(vec!["SECRET_TOKEN".to_owned()], &[]).unwrap(),
+  );
+
+  // The secret is split across two writes, which arrive as separate chunks.
+  let mut process = Process::new(owned_string_vec(&[
+    "/bin/bash",
+    "-c",
+    "echo -n token=hun ; sleep 0.2 ; echo ter2 done",
+  ]));
+  process.env = vec![("SECRET_TOKEN".to_owned(), "hunter2".to_owned())]
+    .into_iter()
+    .collect();
+  let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+  let result = runner
+    .run_streaming(process.into(), Context::default(), sender)
+    .await
+    .unwrap();
+  assert_eq!(result.exit_code, 0);
+
+  let mut stdout = Vec::new();
+  while let Some(chunk) = receiver.recv().await {
+    match chunk {
+      OutputChunk::Stdout(bytes) => stdout.extend_from_slice(&bytes),
+      OutputChunk::Stderr(bytes) => panic!("Unexpected stderr: {:?}", bytes),
+    }
+  }
+  assert_eq!(str::from_utf8(&stdout).unwrap(), "token=<redacted> done\n");
+}
+
+#[tokio::test]
+async fn held_back_output_is_flushed_when_forwarding_stops() {
+  use futures::stream::StreamExt;
+
+  let redactor = crate::Redactor::new(vec!["SECRET_TOKEN".to_owned()], &[]).unwrap();
+  let env = vec![("SECRET_TOKEN".to_owned(), "hunter2".to_owned())]
+    .into_iter()
+    .collect();
+  let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+  let child_outputs = futures::stream::iter(vec![Ok(ChildOutput::Stdout(
+    "token=hunter2 and hun".into(),
+  ))])
+  .boxed();
+  let mut forwarded = forward_output(child_outputs, sender, &redactor, &env);
+  assert!(forwarded.next().await.is_some());
+
+  // Without an exit (as when the process times out), the output which was held back in case it was
+  // the start of a secret is flushed once the stream is dropped.
+  std::mem::drop(forwarded);
+  let mut stdout = Vec::new();
+  while let Some(chunk) = receiver.recv().await {
+    match chunk {
+      OutputChunk::Stdout(bytes) => stdout.extend_from_slice(&bytes),
+      OutputChunk::Stderr(bytes) => panic!("Unexpected stderr: {:?}", bytes),
+    }
+  }
+  assert_eq!(str::from_utf8(&stdout).unwrap(), "token=<redacted> and hun");
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn sandbox_phases_are_recorded_as_workunits() {
+  let mut workunit_store = WorkunitStore::setup_for_tests();
+
+  let mut process = Process::new(owned_string_vec(&[
+    "/bin/bash",
+    "-c",
+    "echo -n foo > roland",
+  ]));
+  process.output_files = relative_paths(&["roland"]).collect();
+  run_command_locally(process).await.unwrap();
+
+  let phases: HashSet<String> =
+    workunit_store.with_latest_workunits(log::Level::Trace, |_, completed| {
+      completed
+        .iter()
+        .map(|workunit| workunit.name.clone())
+        .filter(|name| name.starts_with("local_sandbox_"))
+        .collect()
+    });
+  let expected: HashSet<String> = vec![
+    SandboxPhase::MaterializeInputs,
+    SandboxPhase::CreateOutputDirs,
+    SandboxPhase::Execute,
+    SandboxPhase::CaptureOutputs,
+    SandboxPhase::StoreResults,
+  ]
+  .into_iter()
+  .map(|phase| phase.workunit_name().to_owned())
+  .collect();
+  assert_eq!(phases, expected);
+}
+
 #[tokio::test]
 #[cfg(unix)]
 async fn capture_exit_code_signal() {
@@ -107,12 +268,113 @@ async fn env() {
         parts.next().unwrap_or("").to_string(),
       )
     })
-    .filter(|x| x.0 != "PATH")
+    .filter(|x| x.0 != "PATH" && x.0 != "TMPDIR")
     .collect();
 
   assert_eq!(env, got_env);
 }
 
+#[tokio::test]
+#[cfg(unix)]
+async fn leased_ports() {
+  WorkunitStore::setup_for_tests();
+
+  let mut req = Process::new(owned_string_vec(&["/usr/bin/env"]));
+  req.leased_port_variables = owned_string_vec(&["HTTP_PORT", "DB_PORT"]);
+  let result = run_command_locally(req).await.unwrap();
+
+  let stdout = String::from_utf8(result.stdout_bytes.to_vec()).unwrap();
+  let ports = ["HTTP_PORT", "DB_PORT"]
+    .iter()
+    .map(|name| {
+      let prefix = format!("{}=", name);
+      stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .unwrap_or_else(|| panic!("{} was not set in: {}", name, stdout))
+        .parse::<u16>()
+        .unwrap()
+    })
+    .collect::<Vec<_>>();
+  assert_ne!(ports[0], ports[1]);
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn tmpdir() {
+  WorkunitStore::setup_for_tests();
+
+  let result = run_command_locally(Process::new(vec![
+    "/bin/bash".to_owned(),
+    "-c".to_owned(),
+    "touch \"$TMPDIR/scratch\" && echo -n \"$TMPDIR\"".to_owned(),
+  ]))
+  .await
+  .unwrap();
+
+  assert_eq!(result.original.exit_code, 0);
+  let tmpdir = String::from_utf8(result.stdout_bytes.to_vec()).unwrap();
+  assert!(
+    tmpdir.ends_with(&format!("/{}", SANDBOX_TMPDIR)),
+    "Unexpected TMPDIR: {}",
+    tmpdir
+  );
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn tmpdir_set_by_process() {
+  WorkunitStore::setup_for_tests();
+
+  let mut env = BTreeMap::new();
+  env.insert("TMPDIR".to_owned(), "/custom/tmp".to_owned());
+  let result = run_command_locally(
+    Process::new(vec![
+      "/bin/bash".to_owned(),
+      "-c".to_owned(),
+      "echo -n \"$TMPDIR\"".to_owned(),
+    ])
+    .env(env),
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(result.stdout_bytes, "/custom/tmp".as_bytes());
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn response_file_for_long_argv() {
+  WorkunitStore::setup_for_tests();
+
+  // Exceed ARG_MAX, which is at most a few MB on common platforms.
+  let item_count = 20_000;
+  let mut argv = owned_string_vec(&["/bin/bash", "-c", "wc -l < \"${1#@}\"", "count_lines"]);
+  argv.extend((0..item_count).map(|i| format!("{:0>1000}", i)));
+  let mut process = Process::new(argv);
+  process.response_file = Some(ResponseFileSpec::new(4, "@".to_owned(), "quoted").unwrap());
+
+  let result = run_command_locally(process).await.unwrap();
+
+  assert_eq!(result.original.exit_code, 0);
+  assert_eq!(
+    str::from_utf8(&result.stdout_bytes).unwrap().trim(),
+    item_count.to_string()
+  );
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn unsatisfied_platform_requirements() {
+  WorkunitStore::setup_for_tests();
+
+  let mut process = Process::new(owned_string_vec(&["/bin/true"]));
+  process.platform_requirements.min_macos_version = Some("11".to_owned());
+  let err = run_command_locally(process).await.unwrap_err();
+
+  assert_that(&err).contains("requires macOS 11 or newer");
+}
+
 #[tokio::test]
 #[cfg(unix)]
 async fn env_is_deterministic() {
@@ -122,7 +384,8 @@ async fn env_is_deterministic() {
     let mut env = BTreeMap::new();
     env.insert("FOO".to_string(), "foo".to_string());
     env.insert("BAR".to_string(), "not foo".to_string());
-    Process::new(owned_string_vec(&["/usr/bin/env"])).env(env)
+    // The TMPDIR of each process is inside of its (uniquely named) sandbox.
+    Process::new(owned_string_vec(&["/usr/bin/env", "-u", "TMPDIR"])).env(env)
   }
 
   let result1 = run_command_locally(make_request()).await;
@@ -131,6 +394,180 @@ async fn env_is_deterministic() {
   assert_eq!(result1.unwrap(), result2.unwrap());
 }
 
+#[tokio::test]
+#[cfg(unix)]
+async fn pipe_from_argv() {
+  WorkunitStore::setup_for_tests();
+
+  let result = run_command_locally(
+    Process::new(vec![
+      find_bash(),
+      "-c".to_owned(),
+      "/bin/cat ; echo >&2 -n sink".to_owned(),
+    ])
+    .pipe_from_argv(vec![
+      find_bash(),
+      "-c".to_owned(),
+      format!("echo -n {}", TestData::roland().string()),
+    ]),
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(result.stdout_bytes, TestData::roland().bytes());
+  assert_eq!(result.stderr_bytes, "sink".as_bytes());
+  assert_eq!(result.original.exit_code, 0);
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn pipe_from_argv_source_fails() {
+  WorkunitStore::setup_for_tests();
+
+  let result = run_command_locally(
+    Process::new(owned_string_vec(&["/bin/cat"])).pipe_from_argv(vec![
+      find_bash(),
+      "-c".to_owned(),
+      "echo -n partial ; echo >&2 -n source ; exit 3".to_owned(),
+    ]),
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(result.stdout_bytes, "partial".as_bytes());
+  assert_eq!(result.stderr_bytes, "source".as_bytes());
+  assert_eq!(result.original.exit_code, 3);
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn allocate_tty() {
+  WorkunitStore::setup_for_tests();
+
+  let mut process = Process::new(vec![
+    find_bash(),
+    "-c".to_owned(),
+    "[ -t 0 ] && [ -t 1 ] && [ -t 2 ] && echo tty ; echo >&2 err ; /bin/cat ; exit 2".to_owned(),
+  ]);
+  process.allocate_tty = true;
+  let result = run_command_locally(process).await.unwrap();
+
+  // Stdout and stderr are merged, and stdin is at EOF.
+  assert_eq!(result.stdout_bytes, "tty\nerr\n".as_bytes());
+  assert_eq!(result.stderr_bytes, "".as_bytes());
+  assert_eq!(result.original.exit_code, 2);
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn allocate_tty_with_pipe_from_argv() {
+  WorkunitStore::setup_for_tests();
+
+  let mut process =
+    Process::new(owned_string_vec(&["/bin/cat"])).pipe_from_argv(owned_string_vec(&["/bin/true"]));
+  process.allocate_tty = true;
+  let err_string = run_command_locally(process).await.expect_err("Want Err");
+  assert!(err_string.contains("pipe_from_argv"));
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn cpu_time_limit() {
+  WorkunitStore::setup_for_tests();
+
+  let mut process = Process::new(vec![
+    find_bash(),
+    "-c".to_owned(),
+    "while true; do :; done".to_owned(),
+  ]);
+  process.resource_limits.cpu_seconds = Some(1);
+  let result = run_command_locally(process).await.unwrap();
+
+  assert_eq!(result.original.exit_code, -libc::SIGXCPU);
+  assert_eq!(
+    result.original.metadata.exit_reason,
+    Some(ExitReason::CpuTimeLimitExceeded)
+  );
+  assert_that(&String::from_utf8(result.stderr_bytes).unwrap())
+    .contains("Exceeded the CPU time limit of 1 seconds");
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn cpu_time_limit_ignoring_sigxcpu() {
+  WorkunitStore::setup_for_tests();
+
+  // A process which ignores SIGXCPU is killed once it reaches the hard limit.
+  let mut process = Process::new(vec![
+    find_bash(),
+    "-c".to_owned(),
+    "trap '' XCPU; while true; do :; done".to_owned(),
+  ]);
+  process.resource_limits.cpu_seconds = Some(1);
+  let result = run_command_locally(process).await.unwrap();
+
+  assert_eq!(result.original.exit_code, -libc::SIGKILL);
+  assert_eq!(
+    result.original.metadata.exit_reason,
+    Some(ExitReason::CpuTimeLimitExceeded)
+  );
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn sigkill_is_not_attributed_to_cpu_time_limit() {
+  WorkunitStore::setup_for_tests();
+
+  // A process which was killed before it used its CPU time was killed by something else.
+  let mut process = Process::new(vec![
+    find_bash(),
+    "-c".to_owned(),
+    "kill -KILL $$".to_owned(),
+  ]);
+  process.resource_limits.cpu_seconds = Some(1);
+  let result = run_command_locally(process).await.unwrap();
+
+  assert_eq!(result.original.exit_code, -libc::SIGKILL);
+  assert_eq!(result.original.metadata.exit_reason, None);
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn memory_limit() {
+  WorkunitStore::setup_for_tests();
+
+  // `tail` buffers its (newline-free) input in memory, and its memory counts towards the limit of
+  // its parent.
+  let mut process = Process::new(vec![
+    find_bash(),
+    "-c".to_owned(),
+    "/usr/bin/head -c 4000000000 /dev/zero | /usr/bin/tail".to_owned(),
+  ]);
+  process.resource_limits.max_rss_bytes = Some(16 * 1024 * 1024);
+  let result = run_command_locally(process).await.unwrap();
+
+  assert_eq!(result.original.exit_code, -libc::SIGKILL);
+  assert_eq!(
+    result.original.metadata.exit_reason,
+    Some(ExitReason::MemoryLimitExceeded)
+  );
+  assert_that(&String::from_utf8(result.stderr_bytes).unwrap())
+    .contains("Exceeded the memory limit of 16777216 bytes");
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn open_files_limit() {
+  WorkunitStore::setup_for_tests();
+
+  let mut process = Process::new(vec![find_bash(), "-c".to_owned(), "ulimit -n".to_owned()]);
+  process.resource_limits.nofile = Some(64);
+  let result = run_command_locally(process).await.unwrap();
+
+  assert_eq!(result.stdout_bytes, "64\n".as_bytes());
+  assert_eq!(result.original.metadata.exit_reason, None);
+}
+
 #[tokio::test]
 async fn binary_not_found() {
   WorkunitStore::setup_for_tests();
@@ -216,6 +653,34 @@ async fn output_dirs() {
   assert_eq!(result.original.platform, Platform::current().unwrap());
 }
 
+#[tokio::test]
+async fn output_paths() {
+  WorkunitStore::setup_for_tests();
+
+  // Output paths are captured whether they turn out to be files or directories.
+  let result = run_command_locally(
+    Process::new(vec![
+      find_bash(),
+      "-c".to_owned(),
+      format!(
+        "/bin/mkdir cats && echo -n {} > {} ; echo -n {} > treats",
+        TestData::roland().string(),
+        "cats/roland",
+        TestData::catnip().string()
+      ),
+    ])
+    .output_paths(relative_paths(&["cats", "treats"]).collect()),
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(result.original.exit_code, 0);
+  assert_eq!(
+    result.original.output_directory,
+    TestDirectory::recursive().digest()
+  );
+}
+
 #[tokio::test]
 async fn output_files_many() {
   WorkunitStore::setup_for_tests();
@@ -411,7 +876,7 @@ async fn test_directory_preservation() {
   let result = run_command_locally_in_dir(
     process,
     preserved_work_root.clone(),
-    false,
+    KeepSandboxes::Always,
     Some(store),
     Some(executor),
   )
@@ -466,7 +931,7 @@ async fn test_directory_preservation_error() {
   run_command_locally_in_dir(
     Process::new(vec!["doesnotexist".to_owned()]),
     preserved_work_root.clone(),
-    false,
+    KeepSandboxes::Always,
     None,
     None,
   )
@@ -478,6 +943,47 @@ async fn test_directory_preservation_error() {
   assert_eq!(testutil::file::list_dir(&preserved_work_root).len(), 1);
 }
 
+#[tokio::test]
+async fn test_directory_preservation_on_failure() {
+  WorkunitStore::setup_for_tests();
+
+  let preserved_work_tmpdir = TempDir::new().unwrap();
+  let preserved_work_root = preserved_work_tmpdir.path().to_owned();
+
+  // A successful process does not have its sandbox preserved.
+  let result = run_command_locally_in_dir(
+    Process::new(owned_string_vec(&["/bin/echo", "-n", "foo"])),
+    preserved_work_root.clone(),
+    KeepSandboxes::OnFailure,
+    None,
+    None,
+  )
+  .await
+  .unwrap();
+  assert_eq!(result.original.metadata.preserved_sandbox, None);
+  assert_eq!(testutil::file::list_dir(&preserved_work_root).len(), 0);
+
+  // But a failed process does, and its location is reported in its metadata.
+  let result = run_command_locally_in_dir(
+    Process::new(vec![find_bash(), "-c".to_owned(), "exit 1".to_owned()]),
+    preserved_work_root.clone(),
+    KeepSandboxes::OnFailure,
+    None,
+    None,
+  )
+  .await
+  .unwrap();
+  assert_eq!(result.original.exit_code, 1);
+  let subdirs = testutil::file::list_dir(&preserved_work_root);
+  assert_eq!(subdirs.len(), 1);
+  let preserved_sandbox = preserved_work_root.join(&subdirs[0]);
+  assert_eq!(
+    result.original.metadata.preserved_sandbox,
+    Some(preserved_sandbox.clone())
+  );
+  assert!(preserved_sandbox.join("__run.sh").exists());
+}
+
 #[tokio::test]
 async fn all_containing_directories_for_outputs_are_created() {
   WorkunitStore::setup_for_tests();
@@ -591,7 +1097,7 @@ async fn working_directory() {
   let result = run_command_locally_in_dir(
     process,
     work_dir.path().to_owned(),
-    true,
+    KeepSandboxes::Never,
     Some(store),
     Some(executor),
   )
@@ -608,13 +1114,13 @@ async fn working_directory() {
 async fn run_command_locally(req: Process) -> Result<LocalTestResult, String> {
   let work_dir = TempDir::new().unwrap();
   let work_dir_path = work_dir.path().to_owned();
-  run_command_locally_in_dir(req, work_dir_path, true, None, None).await
+  run_command_locally_in_dir(req, work_dir_path, KeepSandboxes::Never, None, None).await
 }
 
 async fn run_command_locally_in_dir(
   req: Process,
   dir: PathBuf,
-  cleanup: bool,
+  keep_sandboxes: KeepSandboxes,
   store: Option<Store>,
   executor: Option<task_executor::Executor>,
 ) -> Result<LocalTestResult, String> {
@@ -628,7 +1134,8 @@ async fn run_command_locally_in_dir(
     executor.clone(),
     dir,
     NamedCaches::new(named_cache_dir.path().to_owned()),
-    cleanup,
+    keep_sandboxes,
+    crate::Redactor::default(),
   );
   let original = runner.run(req.into(), Context::default()).await?;
   let stdout_bytes: Vec<u8> = store
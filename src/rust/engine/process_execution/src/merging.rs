@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use hashing::Digest;
+use log::debug;
+use parking_lot::Mutex;
+
+use crate::{
+  Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, Process, ProcessMetadata,
+};
+
+type SharedResult = Shared<BoxFuture<'static, Result<FallibleProcessResultWithPlatform, String>>>;
+
+///
+/// A CommandRunner wrapper which merges concurrent requests that would run the same action.
+///
+/// Distinct MultiPlatformProcesses may differ only in the variants which are not compatible with
+/// the underlying runner, in which case they reduce to the same action when they are run. Requests
+/// are keyed by the digest of the compatible Process alone, so the first request to arrive runs
+/// the action, and any identical requests which arrive while it is in flight await its result.
+///
+#[derive(Clone)]
+pub struct CommandRunner {
+  underlying: Arc<dyn crate::CommandRunner>,
+  metadata: ProcessMetadata,
+  in_flight: Arc<Mutex<HashMap<Digest, (usize, SharedResult)>>>,
+  next_id: Arc<AtomicUsize>,
+}
+
+impl CommandRunner {
+  pub fn new(
+    underlying: Arc<dyn crate::CommandRunner>,
+    metadata: ProcessMetadata,
+  ) -> CommandRunner {
+    CommandRunner {
+      underlying,
+      metadata,
+      in_flight: Arc::default(),
+      next_id: Arc::default(),
+    }
+  }
+}
+
+#[async_trait]
+impl crate::CommandRunner for CommandRunner {
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    let compatible_request = match self.underlying.extract_compatible_request(&req) {
+      Some(compatible_request) => compatible_request,
+      None => return self.underlying.run(req, context).await,
+    };
+    let key = crate::digest(compatible_request.into(), &self.metadata);
+
+    let (id, result_future) = {
+      let mut in_flight = self.in_flight.lock();
+      if let Some((id, result_future)) = in_flight.get(&key) {
+        debug!(
+          "Merging {} with an identical in-flight action",
+          req.user_facing_name()
+        );
+        (*id, result_future.clone())
+      } else {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let underlying = self.underlying.clone();
+        let result_future = async move { underlying.run(req, context).await }
+          .boxed()
+          .shared();
+        in_flight.insert(key, (id, result_future.clone()));
+        (id, result_future)
+      }
+    };
+
+    let result = result_future.await;
+
+    // Once the action has completed, identical requests should go to the underlying runner (and
+    // its caches) again. But only remove our own entry: it may already have been replaced.
+    let mut in_flight = self.in_flight.lock();
+    if in_flight.get(&key).map(|(entry_id, _)| *entry_id) == Some(id) {
+      in_flight.remove(&key);
+    }
+    result
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    self.underlying.extract_compatible_request(req)
+  }
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hashing::EMPTY_DIGEST;
+use maplit::btreemap;
+use testutil::owned_string_vec;
+use tokio::time::sleep;
+
+use crate::{
+  CommandRunner as CommandRunnerTrait, Context, FallibleProcessResultWithPlatform,
+  MultiPlatformProcess, Platform, Process, ProcessMetadata, ProcessResultMetadata,
+};
+
+/// A mock runner for Linux, which records the argv of each Process that it runs.
+#[derive(Clone, Default)]
+struct MockCommandRunner {
+  calls: Arc<parking_lot::Mutex<Vec<Vec<String>>>>,
+}
+
+#[async_trait]
+impl CommandRunnerTrait for MockCommandRunner {
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    _context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    let process = self.extract_compatible_request(&req).unwrap();
+    sleep(Duration::from_millis(100)).await;
+    self.calls.lock().push(process.argv);
+    Ok(FallibleProcessResultWithPlatform {
+      stdout_digest: EMPTY_DIGEST,
+      stderr_digest: EMPTY_DIGEST,
+      exit_code: 0,
+      output_directory: EMPTY_DIGEST,
+      platform: Platform::Linux,
+      metadata: ProcessResultMetadata::default(),
+    })
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    for compatible_constraint in vec![None, Some(Platform::Linux)].iter() {
+      if let Some(compatible_req) = req.0.get(compatible_constraint) {
+        return Some(compatible_req.clone());
+      }
+    }
+    None
+  }
+}
+
+fn multi_platform_request(linux_argv: &[&str], darwin_argv: &[&str]) -> MultiPlatformProcess {
+  MultiPlatformProcess(btreemap! {
+    Some(Platform::Linux) => Process::new(owned_string_vec(linux_argv)),
+    Some(Platform::Darwin) => Process::new(owned_string_vec(darwin_argv)),
+  })
+}
+
+fn merging_runner(underlying: &MockCommandRunner) -> crate::merging::CommandRunner {
+  crate::merging::CommandRunner::new(Arc::new(underlying.clone()), ProcessMetadata::default())
+}
+
+#[tokio::test]
+async fn merges_requests_with_the_same_compatible_process() {
+  let underlying = MockCommandRunner::default();
+  let runner = merging_runner(&underlying);
+
+  let (first, second) = futures::join!(
+    runner.run(
+      multi_platform_request(&["/bin/echo", "linux"], &["/bin/echo", "darwin"]),
+      Context::default()
+    ),
+    runner.run(
+      multi_platform_request(&["/bin/echo", "linux"], &["/usr/bin/printf", "darwin"]),
+      Context::default()
+    ),
+  );
+
+  assert_eq!(first.unwrap(), second.unwrap());
+  assert_eq!(
+    *underlying.calls.lock(),
+    vec![owned_string_vec(&["/bin/echo", "linux"])]
+  );
+}
+
+#[tokio::test]
+async fn does_not_merge_different_compatible_processes() {
+  let underlying = MockCommandRunner::default();
+  let runner = merging_runner(&underlying);
+
+  let (first, second) = futures::join!(
+    runner.run(
+      multi_platform_request(&["/bin/echo", "one"], &["/bin/echo", "darwin"]),
+      Context::default()
+    ),
+    runner.run(
+      multi_platform_request(&["/bin/echo", "two"], &["/bin/echo", "darwin"]),
+      Context::default()
+    ),
+  );
+  first.unwrap();
+  second.unwrap();
+
+  assert_eq!(underlying.calls.lock().len(), 2);
+}
+
+#[tokio::test]
+async fn does_not_merge_sequential_requests() {
+  let underlying = MockCommandRunner::default();
+  let runner = merging_runner(&underlying);
+
+  for _ in 0..2 {
+    runner
+      .run(
+        multi_platform_request(&["/bin/echo", "linux"], &["/bin/echo", "darwin"]),
+        Context::default(),
+      )
+      .await
+      .unwrap();
+  }
+
+  assert_eq!(underlying.calls.lock().len(), 2);
+}
@@ -1,5 +1,5 @@
 use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use fs::default_cache_path;
 
@@ -61,6 +61,20 @@ impl NamedCaches {
     default_cache_path().join("named_caches")
   }
 
+  ///
+  /// Returns the absolute path of the directory which contains all named caches.
+  ///
+  pub fn local_base(&self) -> &Path {
+    &self.local_base
+  }
+
+  ///
+  /// Returns the absolute path of the given named cache.
+  ///
+  pub fn local_path(&self, cache_name: &CacheName) -> PathBuf {
+    self.local_base.join(&cache_name.0)
+  }
+
   ///
   /// Returns symlinks to create for the given set of NamedCaches.
   ///
@@ -71,7 +85,7 @@ impl NamedCaches {
     caches
       .iter()
       .map(move |(cache_name, cache_dest)| NamedCacheSymlink {
-        src: self.local_base.join(&cache_name.0),
+        src: self.local_path(cache_name),
         dst: PathBuf::from(&cache_dest.0),
       })
   }
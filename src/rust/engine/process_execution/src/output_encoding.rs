@@ -0,0 +1,22 @@
+///
+/// Determines the encoding of the stdout or stderr of a process, so that consumers can decode it
+/// explicitly rather than assuming that it is UTF-8 (which fails for tools which emit latin-1, for
+/// example).
+///
+/// An encoding which was declared for the output of the process always wins. Otherwise, output
+/// which is valid UTF-8 (including empty and ASCII output) is detected as `utf-8`, and output with
+/// a UTF-16 byte order mark as `utf-16`. The encoding of any other output cannot be reliably
+/// detected, and is None: its raw bytes are still available.
+///
+pub fn detect_output_encoding(bytes: &[u8], declared: Option<&str>) -> Option<String> {
+  if let Some(declared) = declared {
+    return Some(declared.to_owned());
+  }
+  if bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]) {
+    Some("utf-16".to_owned())
+  } else if std::str::from_utf8(bytes).is_ok() {
+    Some("utf-8".to_owned())
+  } else {
+    None
+  }
+}
@@ -0,0 +1,40 @@
+use crate::output_encoding::detect_output_encoding;
+
+#[test]
+fn detects_utf8() {
+  assert_eq!(detect_output_encoding(b"", None), Some("utf-8".to_owned()));
+  assert_eq!(
+    detect_output_encoding("caf\u{e9}".as_bytes(), None),
+    Some("utf-8".to_owned())
+  );
+}
+
+#[test]
+fn detects_utf16_byte_order_marks() {
+  assert_eq!(
+    detect_output_encoding(&[0xFF, 0xFE, b'h', 0], None),
+    Some("utf-16".to_owned())
+  );
+  assert_eq!(
+    detect_output_encoding(&[0xFE, 0xFF, 0, b'h'], None),
+    Some("utf-16".to_owned())
+  );
+}
+
+#[test]
+fn undetectable_encodings() {
+  // `café` in latin-1.
+  assert_eq!(detect_output_encoding(b"caf\xe9", None), None);
+}
+
+#[test]
+fn declared_encoding_wins() {
+  assert_eq!(
+    detect_output_encoding(b"caf\xe9", Some("latin-1")),
+    Some("latin-1".to_owned())
+  );
+  assert_eq!(
+    detect_output_encoding(b"plain ascii", Some("cp1252")),
+    Some("cp1252".to_owned())
+  );
+}
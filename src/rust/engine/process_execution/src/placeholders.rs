@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::{CacheName, NamedCaches, Platform};
+
+///
+/// The values which placeholders in the env of a Process are expanded to when it is run.
+///
+/// The supported placeholders are:
+///   * `{chroot}`: the absolute path of the sandbox that the Process runs in.
+///   * `{cache_dir:<name>}`: the absolute path of the named cache with the given name.
+///   * `{platform}`: the platform that the Process runs on.
+///
+/// Because placeholders are expanded by the runner at execution time, cache keys include only the
+/// unexpanded values, and so are not affected by (for example) the randomly generated path of a
+/// sandbox. Any other use of braces is left untouched.
+///
+pub struct Placeholders<'a> {
+  pub chroot: &'a Path,
+  pub named_caches: &'a NamedCaches,
+  pub platform: Platform,
+}
+
+impl<'a> Placeholders<'a> {
+  ///
+  /// Expands all placeholders in the values of the given env.
+  ///
+  pub fn expand_env(
+    &self,
+    env: &BTreeMap<String, String>,
+  ) -> Result<BTreeMap<String, String>, String> {
+    env
+      .iter()
+      .map(|(key, value)| {
+        let value = self
+          .expand(value)
+          .map_err(|e| format!("Failed to expand env var {}: {}", key, e))?;
+        Ok((key.clone(), value))
+      })
+      .collect()
+  }
+
+  fn expand(&self, value: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut remainder = value;
+    while let Some((prefix, placeholder, suffix)) = next_placeholder(remainder) {
+      expanded.push_str(prefix);
+      match placeholder {
+        Placeholder::Chroot => expanded.push_str(&self.chroot.to_string_lossy()),
+        Placeholder::Platform => expanded.push_str(&String::from(self.platform)),
+        Placeholder::CacheDir(name) => {
+          let cache_name = CacheName::new(name.to_owned())?;
+          expanded.push_str(&self.named_caches.local_path(&cache_name).to_string_lossy());
+        }
+      }
+      remainder = suffix;
+    }
+    expanded.push_str(remainder);
+    Ok(expanded)
+  }
+}
+
+///
+/// True if the given env contains any placeholders which would be expanded at execution time.
+///
+pub fn contains_placeholders(env: &BTreeMap<String, String>) -> bool {
+  env.values().any(|value| next_placeholder(value).is_some())
+}
+
+enum Placeholder<'a> {
+  Chroot,
+  Platform,
+  CacheDir(&'a str),
+}
+
+///
+/// Finds the first placeholder in the given value, and returns it along with the content before
+/// and after it.
+///
+fn next_placeholder(value: &str) -> Option<(&str, Placeholder, &str)> {
+  let mut offset = 0;
+  while let Some(start) = value[offset..].find('{').map(|i| i + offset) {
+    let end = match value[start..].find('}') {
+      Some(i) => start + i,
+      None => return None,
+    };
+    let placeholder = match &value[start + 1..end] {
+      "chroot" => Some(Placeholder::Chroot),
+      "platform" => Some(Placeholder::Platform),
+      inner if inner.starts_with("cache_dir:") => {
+        Some(Placeholder::CacheDir(&inner["cache_dir:".len()..]))
+      }
+      _ => None,
+    };
+    if let Some(placeholder) = placeholder {
+      return Some((&value[..start], placeholder, &value[end + 1..]));
+    }
+    offset = start + 1;
+  }
+  None
+}
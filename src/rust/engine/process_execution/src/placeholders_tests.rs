@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use maplit::btreemap;
+
+use crate::placeholders::{contains_placeholders, Placeholders};
+use crate::{NamedCaches, Platform};
+
+fn expand(value: &str) -> Result<String, String> {
+  let named_caches = NamedCaches::new(PathBuf::from("/caches"));
+  let placeholders = Placeholders {
+    chroot: Path::new("/tmp/sandbox"),
+    named_caches: &named_caches,
+    platform: Platform::Linux,
+  };
+  placeholders
+    .expand_env(&btreemap! { "KEY".to_owned() => value.to_owned() })
+    .map(|env| env["KEY"].clone())
+}
+
+#[test]
+fn expands_placeholders() {
+  assert_eq!(
+    expand("{chroot}/bin:{cache_dir:pip}/bin"),
+    Ok("/tmp/sandbox/bin:/caches/pip/bin".to_owned())
+  );
+  assert_eq!(expand("{platform}"), Ok("linux".to_owned()));
+  assert_eq!(
+    expand("{chroot}{chroot}"),
+    Ok("/tmp/sandbox/tmp/sandbox".to_owned())
+  );
+}
+
+#[test]
+fn preserves_other_braces() {
+  assert_eq!(
+    expand("{\"key\": \"{chroot}\"}"),
+    Ok("{\"key\": \"/tmp/sandbox\"}".to_owned())
+  );
+  assert_eq!(expand("{unknown} {"), Ok("{unknown} {".to_owned()));
+  assert_eq!(expand("no placeholders"), Ok("no placeholders".to_owned()));
+}
+
+#[test]
+fn invalid_cache_name() {
+  let err = expand("{cache_dir:Not/Valid}").unwrap_err();
+  assert!(
+    err.starts_with("Failed to expand env var KEY: Cache names may only contain"),
+    "Unexpected error: {}",
+    err
+  );
+}
+
+#[test]
+fn detects_placeholders() {
+  assert!(contains_placeholders(
+    &btreemap! { "KEY".to_owned() => "{chroot}/bin".to_owned() }
+  ));
+  assert!(!contains_placeholders(
+    &btreemap! { "KEY".to_owned() => "{\"json\": true}".to_owned() }
+  ));
+  assert!(!contains_placeholders(&BTreeMap::new()));
+}
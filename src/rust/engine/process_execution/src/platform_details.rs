@@ -0,0 +1,312 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::process::Command;
+
+use crate::Platform;
+
+///
+/// The flavor of the C standard library of a Linux machine, which determines whether binaries
+/// which were linked against it may run there.
+///
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LibcFlavor {
+  Glibc,
+  Musl,
+}
+
+impl LibcFlavor {
+  pub fn new(flavor: &str) -> Result<LibcFlavor, String> {
+    match flavor.to_lowercase().as_ref() {
+      "glibc" => Ok(LibcFlavor::Glibc),
+      "musl" => Ok(LibcFlavor::Musl),
+      other => Err(format!("Unknown libc flavor: {:?}", other)),
+    }
+  }
+}
+
+impl fmt::Display for LibcFlavor {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LibcFlavor::Glibc => write!(f, "glibc"),
+      LibcFlavor::Musl => write!(f, "musl"),
+    }
+  }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Libc {
+  pub flavor: LibcFlavor,
+  pub version: Option<String>,
+}
+
+///
+/// Details of the local platform beyond its OS, which frequently determine binary compatibility:
+/// the flavor and version of libc on Linux, and the version of macOS. Details which could not be
+/// detected are None.
+///
+/// Capabilities which cannot be detected (such as the version of Xcode, or the presence of a GPU)
+/// may be declared in configuration as arbitrary `properties`.
+///
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PlatformDetails {
+  pub libc: Option<Libc>,
+  pub macos_version: Option<String>,
+  pub properties: BTreeMap<String, String>,
+}
+
+impl PlatformDetails {
+  ///
+  /// Detects the details of the given (local) platform by running probe commands, which is
+  /// relatively slow, and so should be done once.
+  ///
+  pub fn detect(platform: Platform) -> PlatformDetails {
+    match platform {
+      Platform::Linux => PlatformDetails {
+        libc: probe("getconf", &["GNU_LIBC_VERSION"])
+          .and_then(|output| parse_getconf_libc_version(&output))
+          .or_else(|| probe("ldd", &["--version"]).and_then(|output| parse_ldd_version(&output))),
+        macos_version: None,
+        properties: BTreeMap::new(),
+      },
+      Platform::Darwin => PlatformDetails {
+        libc: None,
+        macos_version: probe("sw_vers", &["-productVersion"])
+          .map(|output| output.trim().to_owned())
+          .filter(|version| !version.is_empty()),
+        properties: BTreeMap::new(),
+      },
+    }
+  }
+
+  ///
+  /// Declares the given properties of the local platform, in addition to those which were
+  /// detected.
+  ///
+  pub fn with_properties(self, properties: BTreeMap<String, String>) -> PlatformDetails {
+    PlatformDetails { properties, ..self }
+  }
+}
+
+impl fmt::Display for PlatformDetails {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut details = vec![];
+    if let Some(ref libc) = self.libc {
+      match libc.version {
+        Some(ref version) => details.push(format!("{} {}", libc.flavor, version)),
+        None => details.push(libc.flavor.to_string()),
+      }
+    }
+    if let Some(ref macos_version) = self.macos_version {
+      details.push(format!("macOS {}", macos_version));
+    }
+    for (name, value) in &self.properties {
+      details.push(format!("{}={}", name, value));
+    }
+    if details.is_empty() {
+      write!(f, "no details detected")
+    } else {
+      write!(f, "{}", details.join(", "))
+    }
+  }
+}
+
+///
+/// Returns the combined stdout and stderr of the given command, if it could be run (regardless of
+/// its exit code, since some versions of `ldd --version` exit non-zero).
+///
+fn probe(command: &str, args: &[&str]) -> Option<String> {
+  let output = Command::new(command).args(args).output().ok()?;
+  let mut combined = String::from_utf8_lossy(&output.stdout).into_owned();
+  combined.push_str(&String::from_utf8_lossy(&output.stderr));
+  Some(combined)
+}
+
+///
+/// Parses the output of `getconf GNU_LIBC_VERSION`, such as `glibc 2.31`.
+///
+pub fn parse_getconf_libc_version(output: &str) -> Option<Libc> {
+  let mut parts = output.split_whitespace();
+  match (parts.next(), parts.next()) {
+    (Some("glibc"), Some(version)) => Some(Libc {
+      flavor: LibcFlavor::Glibc,
+      version: Some(version.to_owned()),
+    }),
+    _ => None,
+  }
+}
+
+///
+/// Parses the output of `ldd --version`, which differs between glibc (which reports its version
+/// at the end of the first line) and musl (which reports it on a `Version` line).
+///
+pub fn parse_ldd_version(output: &str) -> Option<Libc> {
+  if output.contains("musl") {
+    let version = output
+      .lines()
+      .find_map(|line| line.trim().strip_prefix("Version "))
+      .map(|version| version.trim().to_owned());
+    Some(Libc {
+      flavor: LibcFlavor::Musl,
+      version,
+    })
+  } else if output.contains("GLIBC") || output.contains("GNU libc") {
+    let version = output
+      .lines()
+      .next()
+      .and_then(|line| line.split_whitespace().last())
+      .map(str::to_owned);
+    Some(Libc {
+      flavor: LibcFlavor::Glibc,
+      version,
+    })
+  } else {
+    None
+  }
+}
+
+///
+/// Compares dotted versions (such as `2.31` and `2.4`) numerically, component by component. Any
+/// non-numeric suffix of a component is ignored, and missing components are treated as zero.
+///
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+  fn components(version: &str) -> Vec<u64> {
+    version
+      .split('.')
+      .map(|component| {
+        let digits = component
+          .chars()
+          .take_while(char::is_ascii_digit)
+          .collect::<String>();
+        digits.parse().unwrap_or(0)
+      })
+      .collect()
+  }
+  let (a, b) = (components(a), components(b));
+  let len = std::cmp::max(a.len(), b.len());
+  let pad = |v: Vec<u64>| v.into_iter().chain(std::iter::repeat(0)).take(len);
+  pad(a).cmp(pad(b))
+}
+
+///
+/// Requirements of a Process on the details of the platform that it runs on, beyond its
+/// `platform_constraint`. Requirements which cannot be verified (because the detail could not be
+/// detected) are not satisfied.
+///
+/// Required `properties` are satisfied only by equal values of properties which were declared for
+/// the local platform: see `PlatformDetails::with_properties`.
+///
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PlatformRequirements {
+  pub libc: Option<LibcFlavor>,
+  pub min_libc_version: Option<String>,
+  pub min_macos_version: Option<String>,
+  pub properties: BTreeMap<String, String>,
+}
+
+impl PlatformRequirements {
+  pub fn is_empty(&self) -> bool {
+    self.libc.is_none()
+      && self.min_libc_version.is_none()
+      && self.min_macos_version.is_none()
+      && self.properties.is_empty()
+  }
+
+  ///
+  /// A stable rendering of these requirements, which is used in cache keys.
+  ///
+  pub fn cache_key_value(&self) -> String {
+    let mut value = format!(
+      "libc={};min_libc_version={};min_macos_version={}",
+      self
+        .libc
+        .map(|flavor| flavor.to_string())
+        .unwrap_or_default(),
+      self.min_libc_version.as_deref().unwrap_or_default(),
+      self.min_macos_version.as_deref().unwrap_or_default(),
+    );
+    // NB: Properties are only rendered when present, so that the cache keys of processes which
+    // do not require any are unchanged.
+    if !self.properties.is_empty() {
+      let properties = self
+        .properties
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>();
+      value.push_str(&format!(";properties={}", properties.join(",")));
+    }
+    value
+  }
+
+  ///
+  /// Returns an error describing the first of these requirements which the given details do not
+  /// satisfy, if any.
+  ///
+  pub fn check(&self, details: &PlatformDetails) -> Result<(), String> {
+    if let Some(flavor) = self.libc {
+      match details.libc {
+        Some(ref libc) if libc.flavor == flavor => (),
+        Some(ref libc) => {
+          return Err(format!(
+            "requires {}, but the local libc is {}",
+            flavor, libc.flavor
+          ))
+        }
+        None => return Err(format!("requires {}, but no libc was detected", flavor)),
+      }
+    }
+    if let Some(ref min_version) = self.min_libc_version {
+      match details.libc.as_ref().and_then(|libc| libc.version.as_ref()) {
+        Some(version) if compare_versions(version, min_version) != Ordering::Less => (),
+        Some(version) => {
+          return Err(format!(
+            "requires libc version {} or newer, but the local version is {}",
+            min_version, version
+          ))
+        }
+        None => {
+          return Err(format!(
+            "requires libc version {} or newer, but the local version could not be detected",
+            min_version
+          ))
+        }
+      }
+    }
+    if let Some(ref min_version) = self.min_macos_version {
+      match details.macos_version {
+        Some(ref version) if compare_versions(version, min_version) != Ordering::Less => (),
+        Some(ref version) => {
+          return Err(format!(
+            "requires macOS {} or newer, but the local version is {}",
+            min_version, version
+          ))
+        }
+        None => {
+          return Err(format!(
+            "requires macOS {} or newer, but the local version could not be detected",
+            min_version
+          ))
+        }
+      }
+    }
+    for (name, required) in &self.properties {
+      match details.properties.get(name) {
+        Some(value) if value == required => (),
+        Some(value) => {
+          return Err(format!(
+            "requires `{}={}`, but this machine declares `{}={}`",
+            name, required, name, value
+          ))
+        }
+        None => {
+          return Err(format!(
+            "requires `{}={}`, but this machine does not declare `{}` (see \
+             `--process-execution-local-platform-properties`)",
+            name, required, name
+          ))
+        }
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,170 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::platform_details::{
+  compare_versions, parse_getconf_libc_version, parse_ldd_version, Libc, LibcFlavor,
+  PlatformDetails, PlatformRequirements,
+};
+
+fn glibc(version: &str) -> PlatformDetails {
+  PlatformDetails {
+    libc: Some(Libc {
+      flavor: LibcFlavor::Glibc,
+      version: Some(version.to_owned()),
+    }),
+    macos_version: None,
+    properties: BTreeMap::new(),
+  }
+}
+
+#[test]
+fn parse_getconf() {
+  assert_eq!(
+    parse_getconf_libc_version("glibc 2.31\n"),
+    Some(Libc {
+      flavor: LibcFlavor::Glibc,
+      version: Some("2.31".to_owned()),
+    })
+  );
+  assert_eq!(
+    parse_getconf_libc_version("getconf: Invalid argument"),
+    None
+  );
+}
+
+#[test]
+fn parse_ldd() {
+  let glibc_output = "ldd (Ubuntu GLIBC 2.31-0ubuntu9.2) 2.31\nCopyright (C) 2020 Free Software \
+                      Foundation, Inc.\n";
+  assert_eq!(
+    parse_ldd_version(glibc_output),
+    Some(Libc {
+      flavor: LibcFlavor::Glibc,
+      version: Some("2.31".to_owned()),
+    })
+  );
+
+  let musl_output = "musl libc (x86_64)\nVersion 1.2.2\nDynamic Program Loader\n";
+  assert_eq!(
+    parse_ldd_version(musl_output),
+    Some(Libc {
+      flavor: LibcFlavor::Musl,
+      version: Some("1.2.2".to_owned()),
+    })
+  );
+
+  assert_eq!(parse_ldd_version("ldd: command not found"), None);
+}
+
+#[test]
+fn versions() {
+  assert_eq!(compare_versions("2.31", "2.4"), Ordering::Greater);
+  assert_eq!(compare_versions("2.17", "2.17.0"), Ordering::Equal);
+  assert_eq!(compare_versions("10.15.7", "11.0"), Ordering::Less);
+  assert_eq!(compare_versions("2.31-0ubuntu9", "2.31"), Ordering::Equal);
+}
+
+#[test]
+fn display() {
+  assert_eq!(glibc("2.31").to_string(), "glibc 2.31");
+  let macos = PlatformDetails {
+    libc: None,
+    macos_version: Some("11.4".to_owned()),
+    properties: BTreeMap::new(),
+  };
+  assert_eq!(macos.to_string(), "macOS 11.4");
+  assert_eq!(
+    PlatformDetails::default().to_string(),
+    "no details detected"
+  );
+}
+
+#[test]
+fn requirements() {
+  assert!(PlatformRequirements::default().is_empty());
+  assert_eq!(
+    PlatformRequirements::default().check(&glibc("2.31")),
+    Ok(())
+  );
+
+  let requirements = PlatformRequirements {
+    libc: Some(LibcFlavor::Glibc),
+    min_libc_version: Some("2.17".to_owned()),
+    min_macos_version: None,
+    properties: BTreeMap::new(),
+  };
+  assert_eq!(requirements.check(&glibc("2.31")), Ok(()));
+  assert_eq!(
+    requirements.check(&glibc("2.12")),
+    Err("requires libc version 2.17 or newer, but the local version is 2.12".to_owned())
+  );
+
+  let musl = PlatformDetails {
+    libc: Some(Libc {
+      flavor: LibcFlavor::Musl,
+      version: Some("1.2.2".to_owned()),
+    }),
+    macos_version: None,
+    properties: BTreeMap::new(),
+  };
+  assert_eq!(
+    requirements.check(&musl),
+    Err("requires glibc, but the local libc is musl".to_owned())
+  );
+
+  let macos_requirements = PlatformRequirements {
+    min_macos_version: Some("11".to_owned()),
+    ..PlatformRequirements::default()
+  };
+  assert!(macos_requirements
+    .check(&glibc("2.31"))
+    .unwrap_err()
+    .contains("could not be detected"));
+}
+
+#[test]
+fn cache_key_value() {
+  let requirements = PlatformRequirements {
+    libc: Some(LibcFlavor::Musl),
+    min_libc_version: Some("1.2".to_owned()),
+    min_macos_version: None,
+    properties: BTreeMap::new(),
+  };
+  assert_eq!(
+    requirements.cache_key_value(),
+    "libc=musl;min_libc_version=1.2;min_macos_version="
+  );
+}
+
+#[test]
+fn property_requirements() {
+  let properties = |pairs: &[(&str, &str)]| {
+    pairs
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect::<BTreeMap<_, _>>()
+  };
+  let details = glibc("2.31").with_properties(properties(&[("gpu", "none"), ("xcode", "14.2")]));
+  assert_eq!(details.to_string(), "glibc 2.31, gpu=none, xcode=14.2");
+
+  let requirements = PlatformRequirements {
+    properties: properties(&[("xcode", "14.2")]),
+    ..PlatformRequirements::default()
+  };
+  assert!(!requirements.is_empty());
+  assert_eq!(requirements.check(&details), Ok(()));
+  assert_eq!(
+    requirements.cache_key_value(),
+    "libc=;min_libc_version=;min_macos_version=;properties=xcode=14.2"
+  );
+
+  let mismatched = glibc("2.31").with_properties(properties(&[("xcode", "15.0")]));
+  assert_eq!(
+    requirements.check(&mismatched),
+    Err("requires `xcode=14.2`, but this machine declares `xcode=15.0`".to_owned())
+  );
+  assert!(requirements
+    .check(&glibc("2.31"))
+    .unwrap_err()
+    .contains("this machine does not declare `xcode`"));
+}
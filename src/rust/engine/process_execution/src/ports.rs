@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, TcpListener};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+/// The number of times to ask the OS for a free port before giving up on a lease.
+const MAX_ATTEMPTS_PER_PORT: usize = 100;
+
+///
+/// Leases free localhost TCP ports to processes, guaranteeing that no two outstanding leases are
+/// for the same port.
+///
+/// Ports are chosen by asking the OS for a free ephemeral port, so a leased port is also unlikely
+/// to be in use by anything outside of Pants. But because the port must be released before the
+/// process can bind it, this is not a guarantee.
+///
+#[derive(Clone, Default)]
+pub struct PortLeases {
+  leased: Arc<Mutex<HashSet<u16>>>,
+}
+
+impl PortLeases {
+  ///
+  /// Leases the given number of distinct ports, which are released when the returned leases are
+  /// dropped.
+  ///
+  pub fn lease(&self, count: usize) -> Result<Vec<PortLease>, String> {
+    let mut leases = Vec::with_capacity(count);
+    for _ in 0..count {
+      leases.push(self.lease_one()?);
+    }
+    Ok(leases)
+  }
+
+  fn lease_one(&self) -> Result<PortLease, String> {
+    for _ in 0..MAX_ATTEMPTS_PER_PORT {
+      let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .map_err(|e| format!("Failed to find a free port to lease: {}", e))?
+        .port();
+      if self.leased.lock().insert(port) {
+        return Ok(PortLease {
+          leased: self.leased.clone(),
+          port,
+        });
+      }
+    }
+    Err(format!(
+      "Failed to find a free port to lease after {} attempts.",
+      MAX_ATTEMPTS_PER_PORT
+    ))
+  }
+
+  pub fn leased_count(&self) -> usize {
+    self.leased.lock().len()
+  }
+}
+
+pub struct PortLease {
+  leased: Arc<Mutex<HashSet<u16>>>,
+  pub port: u16,
+}
+
+impl Drop for PortLease {
+  fn drop(&mut self) {
+    self.leased.lock().remove(&self.port);
+  }
+}
@@ -0,0 +1,29 @@
+use std::collections::HashSet;
+
+use crate::ports::PortLeases;
+
+#[test]
+fn leases_distinct_ports() {
+  let port_leases = PortLeases::default();
+  let leases = port_leases.lease(10).unwrap();
+  let ports = leases
+    .iter()
+    .map(|lease| lease.port)
+    .collect::<HashSet<_>>();
+  assert_eq!(ports.len(), 10);
+  assert!(!ports.contains(&0));
+  assert_eq!(port_leases.leased_count(), 10);
+
+  // Concurrent leases never overlap with outstanding leases.
+  let more_leases = port_leases.lease(10).unwrap();
+  assert!(more_leases.iter().all(|lease| !ports.contains(&lease.port)));
+}
+
+#[test]
+fn releases_ports_on_drop() {
+  let port_leases = PortLeases::default();
+  let leases = port_leases.lease(3).unwrap();
+  assert_eq!(port_leases.leased_count(), 3);
+  std::mem::drop(leases);
+  assert_eq!(port_leases.leased_count(), 0);
+}
@@ -0,0 +1,299 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::ready;
+use log::debug;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// How often the size of the terminal that Pants is attached to is checked while a process with a
+/// pseudo-terminal runs: see `PtyResizer::follow_destination`.
+const WINDOW_SIZE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+///
+/// The size of a terminal window, in characters.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WindowSize {
+  pub rows: u16,
+  pub cols: u16,
+}
+
+impl Default for WindowSize {
+  fn default() -> Self {
+    WindowSize { rows: 24, cols: 80 }
+  }
+}
+
+impl WindowSize {
+  ///
+  /// The window size of the given file descriptor, if it is a terminal.
+  ///
+  pub fn of(fd: RawFd) -> Option<WindowSize> {
+    if unsafe { libc::isatty(fd) } != 1 {
+      return None;
+    }
+    let mut winsize: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut winsize) } != 0 {
+      debug!(
+        "Failed to get the window size of the terminal: {}",
+        io::Error::last_os_error()
+      );
+      return None;
+    }
+    if winsize.ws_row == 0 || winsize.ws_col == 0 {
+      return None;
+    }
+    Some(WindowSize {
+      rows: winsize.ws_row,
+      cols: winsize.ws_col,
+    })
+  }
+
+  ///
+  /// The window size of the terminal that the stdout of the current stdio Destination is attached
+  /// to (including while the dynamic UI has exclusive access to it), or a default size if it is not
+  /// attached to a terminal.
+  ///
+  pub fn of_destination() -> WindowSize {
+    stdio::get_destination()
+      .terminal_as_raw_fd()
+      .ok()
+      .and_then(WindowSize::of)
+      .unwrap_or_default()
+  }
+}
+
+///
+/// A pseudo-terminal, whose slave side is used as the stdio of a process, and whose master side
+/// is read by the engine to capture the (merged) output of the process.
+///
+pub struct Pty {
+  master: File,
+  slave: File,
+}
+
+impl Pty {
+  ///
+  /// Opens a pseudo-terminal of the given size.
+  ///
+  /// Output post-processing (which would translate `\n` to `\r\n`) and echoing of input are
+  /// disabled, so that the captured output of a process is the same as what it wrote. No input is
+  /// ever written to the terminal, so it is immediately at EOF (as if a user typed Ctrl+D), rather
+  /// than leaving a process which reads its stdin to wait forever.
+  ///
+  pub fn open(window_size: WindowSize) -> Result<Pty, String> {
+    let mut master: libc::c_int = -1;
+    let mut slave: libc::c_int = -1;
+    let mut winsize = libc::winsize {
+      ws_row: window_size.rows,
+      ws_col: window_size.cols,
+      ws_xpixel: 0,
+      ws_ypixel: 0,
+    };
+    let res = unsafe {
+      libc::openpty(
+        &mut master,
+        &mut slave,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+        &mut winsize,
+      )
+    };
+    if res != 0 {
+      return Err(format!(
+        "Failed to allocate a pseudo-terminal: {}",
+        io::Error::last_os_error()
+      ));
+    }
+    // NB: Take ownership immediately, so that the descriptors are closed on any error below.
+    let pty = unsafe {
+      Pty {
+        master: File::from_raw_fd(master),
+        slave: File::from_raw_fd(slave),
+      }
+    };
+    // Neither side may be inherited by other processes which are spawned concurrently: in
+    // particular, the master only reaches EOF once every copy of the slave has been closed.
+    set_cloexec(pty.master.as_raw_fd())?;
+    set_cloexec(pty.slave.as_raw_fd())?;
+
+    let slave_fd = pty.slave.as_raw_fd();
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(slave_fd, &mut termios) } != 0 {
+      return Err(format!(
+        "Failed to get the attributes of a pseudo-terminal: {}",
+        io::Error::last_os_error()
+      ));
+    }
+    termios.c_oflag &= !libc::OPOST;
+    termios.c_lflag &= !(libc::ECHO | libc::ECHONL);
+    if unsafe { libc::tcsetattr(slave_fd, libc::TCSANOW, &termios) } != 0 {
+      return Err(format!(
+        "Failed to set the attributes of a pseudo-terminal: {}",
+        io::Error::last_os_error()
+      ));
+    }
+    (&pty.master)
+      .write_all(&[termios.c_cc[libc::VEOF]])
+      .map_err(|e| format!("Failed to write to a pseudo-terminal: {}", e))?;
+    Ok(pty)
+  }
+
+  ///
+  /// A new handle to the slave side of the terminal, for use as one of the stdio streams of a
+  /// process.
+  ///
+  pub fn slave(&self) -> io::Result<File> {
+    self.slave.try_clone()
+  }
+
+  ///
+  /// A handle which can resize the terminal while a process uses it.
+  ///
+  pub fn resizer(&self, window_size: WindowSize) -> Result<PtyResizer, String> {
+    let master = self
+      .master
+      .try_clone()
+      .map_err(|e| format!("Failed to duplicate a pseudo-terminal: {}", e))?;
+    Ok(PtyResizer {
+      master,
+      window_size,
+    })
+  }
+
+  ///
+  /// Closes the slave side of the terminal in this process (which must happen once the process
+  /// which uses it has been spawned, so that the master observes EOF when that process exits),
+  /// and returns the master side, which streams everything written to the terminal.
+  ///
+  pub fn into_master(self) -> Result<PtyMaster, String> {
+    let Pty { master, slave } = self;
+    std::mem::drop(slave);
+    let fd = master.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } != 0 {
+      return Err(format!(
+        "Failed to make a pseudo-terminal non-blocking: {}",
+        io::Error::last_os_error()
+      ));
+    }
+    let master =
+      AsyncFd::new(master).map_err(|e| format!("Failed to register a pseudo-terminal: {}", e))?;
+    Ok(PtyMaster { master })
+  }
+}
+
+///
+/// Resizes a pseudo-terminal, which causes the kernel to send SIGWINCH to the foreground process
+/// group of the terminal (so that programs which draw to the terminal can redraw at the new size).
+///
+pub struct PtyResizer {
+  master: File,
+  window_size: WindowSize,
+}
+
+impl PtyResizer {
+  pub fn resize(&mut self, window_size: WindowSize) -> Result<(), String> {
+    let winsize = libc::winsize {
+      ws_row: window_size.rows,
+      ws_col: window_size.cols,
+      ws_xpixel: 0,
+      ws_ypixel: 0,
+    };
+    if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) } != 0 {
+      return Err(format!(
+        "Failed to resize a pseudo-terminal: {}",
+        io::Error::last_os_error()
+      ));
+    }
+    self.window_size = window_size;
+    Ok(())
+  }
+
+  ///
+  /// Resizes the terminal whenever the size of the terminal that Pants is attached to changes.
+  /// Only completes if the terminal cannot be resized: otherwise it should be dropped once the
+  /// process which uses the terminal has exited.
+  ///
+  /// The size is polled rather than updated on SIGWINCH, since when Pants runs with pantsd, the
+  /// terminal belongs to the client, and so the signal is not delivered to this process.
+  ///
+  pub async fn follow_destination(mut self) {
+    loop {
+      tokio::time::sleep(WINDOW_SIZE_POLL_INTERVAL).await;
+      let window_size = WindowSize::of_destination();
+      if window_size != self.window_size {
+        if let Err(e) = self.resize(window_size) {
+          debug!("{}", e);
+          return;
+        }
+      }
+    }
+  }
+}
+
+///
+/// Makes the given terminal the controlling terminal of the calling process, after moving it into
+/// a new session. Called in a forked child before it execs, and so must be async-signal-safe.
+///
+pub(crate) fn set_controlling_terminal(fd: RawFd) -> io::Result<()> {
+  if unsafe { libc::setsid() } < 0 {
+    return Err(io::Error::last_os_error());
+  }
+  // NB: The request argument of `ioctl` is a `c_int` on some platforms, and a `c_ulong` on others.
+  if unsafe { libc::ioctl(fd, libc::TIOCSCTTY as _, 0) } < 0 {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(())
+}
+
+fn set_cloexec(fd: RawFd) -> Result<(), String> {
+  let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+  if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } != 0 {
+    return Err(format!(
+      "Failed to set FD_CLOEXEC on a pseudo-terminal: {}",
+      io::Error::last_os_error()
+    ));
+  }
+  Ok(())
+}
+
+///
+/// The master side of a pseudo-terminal, which reads everything written to the terminal, and then
+/// reaches EOF once every handle to the slave side has been closed.
+///
+pub struct PtyMaster {
+  master: AsyncFd<File>,
+}
+
+impl AsyncRead for PtyMaster {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    loop {
+      let mut guard = ready!(self.master.poll_read_ready(cx))?;
+      let unfilled = buf.initialize_unfilled();
+      match guard.try_io(|master| {
+        let mut file: &File = master.get_ref();
+        file.read(unfilled)
+      }) {
+        Ok(Ok(read)) => {
+          buf.advance(read);
+          return Poll::Ready(Ok(()));
+        }
+        // NB: Reading the master of a terminal whose slave has been closed fails with EIO (on
+        // Linux) rather than returning EOF.
+        Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => return Poll::Ready(Ok(())),
+        Ok(Err(e)) => return Poll::Ready(Err(e)),
+        Err(_would_block) => continue,
+      }
+    }
+  }
+}
@@ -0,0 +1,49 @@
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+
+use tokio::io::AsyncReadExt;
+
+use crate::pty::{Pty, WindowSize};
+
+#[test]
+fn window_size_of_non_terminal() {
+  let file = tempfile::tempfile().unwrap();
+  assert_eq!(WindowSize::of(file.as_raw_fd()), None);
+}
+
+#[tokio::test]
+async fn window_size_and_output() {
+  let window_size = WindowSize {
+    rows: 40,
+    cols: 132,
+  };
+  let pty = Pty::open(window_size).unwrap();
+  let mut slave = pty.slave().unwrap();
+  assert_eq!(WindowSize::of(slave.as_raw_fd()), Some(window_size));
+
+  // Newlines are not translated, and the master reaches EOF once the slave has been closed.
+  slave.write_all(b"one\ntwo\n").unwrap();
+  std::mem::drop(slave);
+  let mut output = Vec::new();
+  pty
+    .into_master()
+    .unwrap()
+    .read_to_end(&mut output)
+    .await
+    .unwrap();
+  assert_eq!(output, b"one\ntwo\n");
+}
+
+#[test]
+fn resize() {
+  let pty = Pty::open(WindowSize::default()).unwrap();
+  let slave = pty.slave().unwrap();
+  let mut resizer = pty.resizer(WindowSize::default()).unwrap();
+
+  let window_size = WindowSize {
+    rows: 50,
+    cols: 200,
+  };
+  resizer.resize(window_size).unwrap();
+  assert_eq!(WindowSize::of(slave.as_raw_fd()), Some(window_size));
+}
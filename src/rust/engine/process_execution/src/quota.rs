@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bazel_protos::require_digest;
+use futures::future::{self, FutureExt};
+use hashing::{Digest, EMPTY_DIGEST};
+use store::Store;
+
+use crate::{Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, Process};
+
+///
+/// The number of the largest output files which are named when a quota is exceeded.
+///
+const LARGEST_FILES_TO_REPORT: usize = 5;
+
+///
+/// A CommandRunner wrapper which fails processes whose captured outputs exceed a quota, before
+/// those outputs can be stored in any cache.
+///
+/// The total size of the files in the output directory of each process may not exceed the
+/// `max_output_bytes` of the process (or, if it is not set, the default for this runner). The
+/// total size of the outputs of all of the processes run for a Session may not exceed the
+/// `max_run_output_bytes`.
+///
+#[derive(Clone)]
+pub struct CommandRunner {
+  underlying: Arc<dyn crate::CommandRunner>,
+  store: Store,
+  max_process_output_bytes: Option<usize>,
+  max_run_output_bytes: Option<usize>,
+}
+
+impl CommandRunner {
+  pub fn new(
+    underlying: Arc<dyn crate::CommandRunner>,
+    store: Store,
+    max_process_output_bytes: Option<usize>,
+    max_run_output_bytes: Option<usize>,
+  ) -> CommandRunner {
+    CommandRunner {
+      underlying,
+      store,
+      max_process_output_bytes,
+      max_run_output_bytes,
+    }
+  }
+
+  async fn output_file_sizes(
+    &self,
+    output_directory: Digest,
+  ) -> Result<Vec<(PathBuf, usize)>, String> {
+    if output_directory == EMPTY_DIGEST {
+      return Ok(vec![]);
+    }
+    let file_sizes = self
+      .store
+      .walk(output_directory, |_, path_so_far, _, directory| {
+        let file_sizes = directory
+          .files
+          .iter()
+          .map(|file_node| {
+            let digest = require_digest(file_node.digest.as_ref())?;
+            Ok((path_so_far.join(&file_node.name), digest.size_bytes))
+          })
+          .collect::<Result<Vec<_>, String>>();
+        future::ready(file_sizes).boxed()
+      })
+      .await?;
+    Ok(file_sizes.into_iter().flatten().collect())
+  }
+}
+
+///
+/// Formats an error for outputs which exceeded a quota, naming the largest of them.
+///
+fn quota_exceeded(
+  process: &Process,
+  quota_name: &str,
+  max_bytes: usize,
+  summary: String,
+  mut file_sizes: Vec<(PathBuf, usize)>,
+) -> String {
+  file_sizes.sort_by(|(p1, s1), (p2, s2)| s2.cmp(s1).then_with(|| p1.cmp(p2)));
+  let largest_files = file_sizes
+    .iter()
+    .take(LARGEST_FILES_TO_REPORT)
+    .map(|(path, size)| format!("  {} ({} bytes)", path.display(), size))
+    .collect::<Vec<_>>()
+    .join("\n");
+  format!(
+    "The outputs of `{}` exceeded the {} output quota of {} bytes: {}. Its largest output files \
+     were:\n{}",
+    process.description, quota_name, max_bytes, summary, largest_files
+  )
+}
+
+#[async_trait]
+impl crate::CommandRunner for CommandRunner {
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    let process = self.underlying.extract_compatible_request(&req);
+    let max_process_output_bytes = process
+      .as_ref()
+      .and_then(|process| process.max_output_bytes)
+      .or(self.max_process_output_bytes);
+    let captured_output_bytes = context.captured_output_bytes.clone();
+
+    let result = self.underlying.run(req, context).await?;
+    let process = match process {
+      Some(process)
+        if max_process_output_bytes.is_some() || self.max_run_output_bytes.is_some() =>
+      {
+        process
+      }
+      _ => return Ok(result),
+    };
+
+    let file_sizes = self.output_file_sizes(result.output_directory).await?;
+    let total_bytes: usize = file_sizes.iter().map(|(_, size)| size).sum();
+    if let Some(max_bytes) = max_process_output_bytes {
+      if total_bytes > max_bytes {
+        let summary = format!(
+          "its {} output files totalled {} bytes",
+          file_sizes.len(),
+          total_bytes
+        );
+        return Err(quota_exceeded(
+          &process,
+          "per-process",
+          max_bytes,
+          summary,
+          file_sizes,
+        ));
+      }
+    }
+    let run_bytes = captured_output_bytes.fetch_add(total_bytes, Ordering::SeqCst) + total_bytes;
+    if let Some(max_bytes) = self.max_run_output_bytes {
+      if run_bytes > max_bytes {
+        let summary = format!(
+          "its outputs brought the total captured during this run to {} bytes",
+          run_bytes
+        );
+        return Err(quota_exceeded(
+          &process, "per-run", max_bytes, summary, file_sizes,
+        ));
+      }
+    }
+    Ok(result)
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    self.underlying.extract_compatible_request(req)
+  }
+}
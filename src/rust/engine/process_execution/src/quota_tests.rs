@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use hashing::{Digest, EMPTY_DIGEST};
+use store::Store;
+use tempfile::TempDir;
+use testutil::data::{TestData, TestDirectory};
+use testutil::owned_string_vec;
+
+use crate::{
+  CommandRunner as CommandRunnerTrait, Context, FallibleProcessResultWithPlatform,
+  MultiPlatformProcess, Platform, Process, ProcessResultMetadata,
+};
+
+/// A mock runner whose processes all output the given directory.
+#[derive(Clone)]
+struct MockCommandRunner {
+  output_directory: Digest,
+}
+
+#[async_trait]
+impl CommandRunnerTrait for MockCommandRunner {
+  async fn run(
+    &self,
+    _req: MultiPlatformProcess,
+    _context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    Ok(FallibleProcessResultWithPlatform {
+      stdout_digest: EMPTY_DIGEST,
+      stderr_digest: EMPTY_DIGEST,
+      exit_code: 0,
+      output_directory: self.output_directory,
+      platform: Platform::Linux,
+      metadata: ProcessResultMetadata::default(),
+    })
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    req.0.get(&None).cloned()
+  }
+}
+
+async fn quota_runner(
+  max_process_output_bytes: Option<usize>,
+  max_run_output_bytes: Option<usize>,
+) -> (crate::quota::CommandRunner, TempDir) {
+  let store_dir = TempDir::new().unwrap();
+  let executor = task_executor::Executor::new();
+  let store = Store::local_only(executor, store_dir.path()).unwrap();
+  store
+    .record_directory(
+      &TestDirectory::containing_roland_and_treats().directory(),
+      true,
+    )
+    .await
+    .unwrap();
+  let runner = crate::quota::CommandRunner::new(
+    Arc::new(MockCommandRunner {
+      output_directory: TestDirectory::containing_roland_and_treats().digest(),
+    }),
+    store,
+    max_process_output_bytes,
+    max_run_output_bytes,
+  );
+  (runner, store_dir)
+}
+
+fn process(max_output_bytes: Option<usize>) -> MultiPlatformProcess {
+  let mut process = Process::new(owned_string_vec(&["/bin/true"]));
+  process.description = "Running true".to_owned();
+  process.max_output_bytes = max_output_bytes;
+  process.into()
+}
+
+fn total_output_bytes() -> usize {
+  TestData::roland().len() + TestData::catnip().len()
+}
+
+#[tokio::test]
+async fn within_quota() {
+  let (runner, _store_dir) = quota_runner(Some(total_output_bytes()), None).await;
+  let result = runner.run(process(None), Context::default()).await.unwrap();
+  assert_eq!(
+    result.output_directory,
+    TestDirectory::containing_roland_and_treats().digest()
+  );
+}
+
+#[tokio::test]
+async fn exceeds_process_quota() {
+  let (runner, _store_dir) = quota_runner(Some(total_output_bytes() - 1), None).await;
+  let error = runner
+    .run(process(None), Context::default())
+    .await
+    .unwrap_err();
+  assert!(
+    error.contains("`Running true` exceeded the per-process output quota"),
+    "{}",
+    error
+  );
+  // The largest file is named first.
+  let roland = error.find("roland (16 bytes)").unwrap();
+  let treats = error.find("treats (6 bytes)").unwrap();
+  assert!(roland < treats, "{}", error);
+}
+
+#[tokio::test]
+async fn process_overrides_default_quota() {
+  let (runner, _store_dir) = quota_runner(Some(1), None).await;
+  runner
+    .run(process(Some(total_output_bytes())), Context::default())
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn exceeds_run_quota() {
+  let (runner, _store_dir) = quota_runner(None, Some(total_output_bytes() + 1)).await;
+  let context = Context::default();
+  runner.run(process(None), context.clone()).await.unwrap();
+  let error = runner.run(process(None), context).await.unwrap_err();
+  assert!(error.contains("per-run output quota"), "{}", error);
+
+  // A different run has its own quota.
+  runner.run(process(None), Context::default()).await.unwrap();
+}
@@ -0,0 +1,222 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use regex::bytes::Regex;
+
+/// The text which redacted secrets are replaced with.
+pub const REDACTED: &str = "<redacted>";
+
+/// The maximum number of bytes which a StreamRedactor holds back while it waits for the end of a
+/// line, so that output which never contains a newline is still streamed.
+const MAX_PENDING_LINE_BYTES: usize = 64 * 1024;
+
+///
+/// Scrubs configured secrets from content produced by (or describing) a Process before it is
+/// persisted.
+///
+/// Secrets are identified either by the names of environment variables which hold them, or by
+/// patterns which match them. The values of the named environment variables are collected from
+/// both the environment of the Process and the environment of this process (since a secret might
+/// be passed to a Process on its command line rather than in its environment).
+///
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+  env_var_names: Vec<String>,
+  patterns: Vec<Regex>,
+}
+
+impl Redactor {
+  pub fn new(env_var_names: Vec<String>, patterns: &[String]) -> Result<Redactor, String> {
+    let patterns = patterns
+      .iter()
+      .map(|pattern| {
+        Regex::new(pattern).map_err(|e| format!("Invalid redaction pattern `{}`: {}", pattern, e))
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(Redactor {
+      env_var_names,
+      patterns,
+    })
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.env_var_names.is_empty() && self.patterns.is_empty()
+  }
+
+  ///
+  /// The secret values of the configured environment variables, longest first (so that a secret
+  /// which contains another is redacted in its entirety).
+  ///
+  fn secret_values(&self, env: &BTreeMap<String, String>) -> Vec<String> {
+    let mut values = self
+      .env_var_names
+      .iter()
+      .flat_map(|name| {
+        let process_value = env.get(name).cloned();
+        let local_value = std::env::var(name).ok();
+        process_value.into_iter().chain(local_value)
+      })
+      .filter(|value| !value.is_empty())
+      .collect::<Vec<_>>();
+    values.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    values.dedup();
+    values
+  }
+
+  ///
+  /// Redacts secrets from the given content, which was produced by a Process with the given env.
+  ///
+  pub fn redact<'a>(&self, env: &BTreeMap<String, String>, content: &'a [u8]) -> Cow<'a, [u8]> {
+    if self.is_empty() {
+      return Cow::Borrowed(content);
+    }
+    let content = redact_values(&self.secret_values(env), Cow::Borrowed(content));
+    redact_patterns(&self.patterns, content)
+  }
+
+  ///
+  /// Creates a StreamRedactor for one output stream of a Process with the given env.
+  ///
+  pub fn stream(&self, env: &BTreeMap<String, String>) -> StreamRedactor {
+    let secret_values = self.secret_values(env);
+    StreamRedactor {
+      holdback: secret_values
+        .iter()
+        .map(|value| value.len().saturating_sub(1))
+        .max()
+        .unwrap_or(0),
+      secret_values,
+      patterns: self.patterns.clone(),
+      pending: Vec::new(),
+    }
+  }
+
+  pub fn redact_bytes(&self, env: &BTreeMap<String, String>, content: Bytes) -> Bytes {
+    match self.redact(env, &content) {
+      Cow::Borrowed(_) => content.clone(),
+      Cow::Owned(redacted) => Bytes::from(redacted),
+    }
+  }
+
+  pub fn redact_str<'a>(&self, env: &BTreeMap<String, String>, content: &'a str) -> Cow<'a, str> {
+    match self.redact(env, content.as_bytes()) {
+      Cow::Borrowed(_) => Cow::Borrowed(content),
+      Cow::Owned(redacted) => Cow::Owned(String::from_utf8_lossy(&redacted).into_owned()),
+    }
+  }
+}
+
+///
+/// Redacts secrets from output which arrives in chunks, and which is forwarded as it arrives.
+///
+/// Since a secret might be split across chunks, the end of each chunk is held back until the next
+/// one arrives: secret values are redacted from all of the content received so far, but the last
+/// `len - 1` bytes (for the longest secret value) are not emitted, since they might be the start of
+/// a secret. Patterns have no maximum length, so they are only applied to complete lines (or to
+/// lines which exceed `MAX_PENDING_LINE_BYTES`), and content is held back until the end of its line.
+///
+pub struct StreamRedactor {
+  secret_values: Vec<String>,
+  patterns: Vec<Regex>,
+  holdback: usize,
+  // Content which has had secret values (but not patterns) redacted, and which has not yet been
+  // emitted.
+  pending: Vec<u8>,
+}
+
+impl StreamRedactor {
+  pub fn is_empty(&self) -> bool {
+    self.secret_values.is_empty() && self.patterns.is_empty()
+  }
+
+  ///
+  /// Accepts the next chunk of output, and returns the redacted content which can be emitted.
+  ///
+  pub fn redact_chunk(&mut self, chunk: &[u8]) -> Bytes {
+    if self.is_empty() {
+      return Bytes::copy_from_slice(chunk);
+    }
+    self.pending.extend_from_slice(chunk);
+    let redacted = match redact_values(&self.secret_values, Cow::Borrowed(&self.pending)) {
+      Cow::Owned(redacted) => Some(redacted),
+      Cow::Borrowed(_) => None,
+    };
+    if let Some(redacted) = redacted {
+      self.pending = redacted;
+    }
+
+    let mut emit = self.pending.len().saturating_sub(self.holdback);
+    if !self.patterns.is_empty() && self.pending.len() <= MAX_PENDING_LINE_BYTES {
+      let line_end = self.pending[..emit]
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+      emit = line_end;
+    }
+    let rest = self.pending.split_off(emit);
+    let emitted = std::mem::replace(&mut self.pending, rest);
+    Bytes::from(redact_patterns(&self.patterns, Cow::Owned(emitted)).into_owned())
+  }
+
+  ///
+  /// Returns the redacted remainder of the output, once it has ended.
+  ///
+  pub fn finish(&mut self) -> Bytes {
+    let pending = std::mem::take(&mut self.pending);
+    Bytes::from(redact_patterns(&self.patterns, Cow::Owned(pending)).into_owned())
+  }
+}
+
+///
+/// Redacts the given secret values (which must be sorted longest first) from the given content.
+///
+fn redact_values<'a>(secret_values: &[String], mut content: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+  for value in secret_values {
+    if let Some(redacted) = replace_all(&content, value.as_bytes()) {
+      content = Cow::Owned(redacted);
+    }
+  }
+  content
+}
+
+///
+/// Redacts all matches of the given patterns from the given content.
+///
+fn redact_patterns<'a>(patterns: &[Regex], mut content: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+  for pattern in patterns {
+    let redacted = match pattern.replace_all(&content, REDACTED.as_bytes()) {
+      Cow::Owned(redacted) => Some(redacted),
+      Cow::Borrowed(_) => None,
+    };
+    if let Some(redacted) = redacted {
+      content = Cow::Owned(redacted);
+    }
+  }
+  content
+}
+
+///
+/// Replaces all occurrences of `needle` in `haystack`, or returns None if there were none.
+///
+fn replace_all(haystack: &[u8], needle: &[u8]) -> Option<Vec<u8>> {
+  let mut result: Option<Vec<u8>> = None;
+  let mut start = 0;
+  let mut i = 0;
+  while i + needle.len() <= haystack.len() {
+    if &haystack[i..i + needle.len()] == needle {
+      let result = result.get_or_insert_with(|| Vec::with_capacity(haystack.len()));
+      result.extend_from_slice(&haystack[start..i]);
+      result.extend_from_slice(REDACTED.as_bytes());
+      i += needle.len();
+      start = i;
+    } else {
+      i += 1;
+    }
+  }
+  result.map(|mut result| {
+    result.extend_from_slice(&haystack[start..]);
+    result
+  })
+}
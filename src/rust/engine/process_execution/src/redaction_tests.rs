@@ -0,0 +1,133 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use maplit::btreemap;
+
+use crate::redaction::Redactor;
+
+fn redacted(redactor: &Redactor, env: &BTreeMap<String, String>, content: &str) -> String {
+  redactor.redact_str(env, content).into_owned()
+}
+
+#[test]
+fn empty_redactor_borrows() {
+  let redactor = Redactor::default();
+  assert!(redactor.is_empty());
+  assert!(matches!(
+    redactor.redact(&BTreeMap::new(), b"some output"),
+    Cow::Borrowed(_)
+  ));
+}
+
+#[test]
+fn redacts_env_var_values() {
+  let redactor = Redactor::new(vec!["SECRET_TOKEN".to_owned()], &[]).unwrap();
+  let env = btreemap! {
+    "SECRET_TOKEN".to_owned() => "hunter2".to_owned(),
+    "OTHER".to_owned() => "visible".to_owned(),
+  };
+  assert_eq!(
+    redacted(&redactor, &env, "token=hunter2 other=visible hunter2"),
+    "token=<redacted> other=visible <redacted>"
+  );
+  // Content without secrets is untouched.
+  assert!(matches!(
+    redactor.redact(&env, b"nothing to see here"),
+    Cow::Borrowed(_)
+  ));
+}
+
+#[test]
+fn ignores_empty_env_var_values() {
+  let redactor = Redactor::new(vec!["SECRET_TOKEN".to_owned()], &[]).unwrap();
+  let env = btreemap! { "SECRET_TOKEN".to_owned() => "".to_owned() };
+  assert_eq!(redacted(&redactor, &env, "output"), "output");
+}
+
+#[test]
+fn redacts_longest_values_first() {
+  let redactor = Redactor::new(vec!["SHORT".to_owned(), "LONG".to_owned()], &[]).unwrap();
+  let env = btreemap! {
+    "SHORT".to_owned() => "abc".to_owned(),
+    "LONG".to_owned() => "abcdef".to_owned(),
+  };
+  assert_eq!(
+    redacted(&redactor, &env, "abcdef abc"),
+    "<redacted> <redacted>"
+  );
+}
+
+#[test]
+fn redacts_patterns() {
+  let redactor = Redactor::new(vec![], &["ghp_[A-Za-z0-9]+".to_owned()]).unwrap();
+  assert_eq!(
+    redacted(
+      &redactor,
+      &BTreeMap::new(),
+      "token: ghp_abc123, again: ghp_XYZ"
+    ),
+    "token: <redacted>, again: <redacted>"
+  );
+}
+
+#[test]
+fn invalid_pattern() {
+  let err = Redactor::new(vec![], &["(".to_owned()]).unwrap_err();
+  assert!(
+    err.starts_with("Invalid redaction pattern `(`"),
+    "Unexpected error: {}",
+    err
+  );
+}
+
+///
+/// Streams the given chunks through a StreamRedactor, and returns the emitted chunks.
+///
+fn streamed(redactor: &Redactor, env: &BTreeMap<String, String>, chunks: &[&str]) -> Vec<String> {
+  let mut stream = redactor.stream(env);
+  let mut emitted = chunks
+    .iter()
+    .map(|chunk| stream.redact_chunk(chunk.as_bytes()))
+    .collect::<Vec<_>>();
+  emitted.push(stream.finish());
+  emitted
+    .into_iter()
+    .map(|bytes| String::from_utf8(bytes.to_vec()).unwrap())
+    .collect()
+}
+
+#[test]
+fn stream_redacts_values_split_across_chunks() {
+  let redactor = Redactor::new(vec!["SECRET_TOKEN".to_owned()], &[]).unwrap();
+  let env = btreemap! { "SECRET_TOKEN".to_owned() => "hunter2".to_owned() };
+  let chunks = streamed(&redactor, &env, &["token=hun", "ter2 and hunter2", " done"]);
+  assert_eq!(chunks.concat(), "token=<redacted> and <redacted> done");
+  // No chunk contains the start of the secret.
+  for chunk in chunks {
+    assert!(!chunk.contains("hun"), "Chunk leaked a secret: {:?}", chunk);
+  }
+}
+
+#[test]
+fn stream_redacts_patterns_in_complete_lines() {
+  let redactor = Redactor::new(vec![], &["ghp_[A-Za-z0-9]+".to_owned()]).unwrap();
+  let chunks = streamed(
+    &redactor,
+    &BTreeMap::new(),
+    &["first line\ntoken=ghp_ab", "cd\nlast"],
+  );
+  assert_eq!(
+    chunks,
+    vec![
+      "first line\n".to_owned(),
+      "token=<redacted>\n".to_owned(),
+      "last".to_owned()
+    ]
+  );
+}
+
+#[test]
+fn empty_stream_redactor_passes_chunks_through() {
+  let chunks = streamed(&Redactor::default(), &BTreeMap::new(), &["a", "b"]);
+  assert_eq!(chunks, vec!["a".to_owned(), "b".to_owned(), "".to_owned()]);
+}
@@ -16,7 +16,7 @@ use bytes::Bytes;
 use concrete_time::TimeSpan;
 use double_checked_cell_async::DoubleCheckedCell;
 use fs::{self, File, PathStat};
-use futures::future::{self, BoxFuture, TryFutureExt};
+use futures::future::{self, BoxFuture, Shared, TryFutureExt};
 use futures::FutureExt;
 use futures::{Stream, StreamExt};
 use grpc_util::prost::MessageExt;
@@ -29,7 +29,8 @@ use remexec::{
   execution_client::ExecutionClient, Action, Command, ExecuteRequest, ExecuteResponse,
   ExecutedActionMetadata, ServerCapabilities, WaitExecutionRequest,
 };
-use store::{Snapshot, SnapshotOps, Store, StoreFileByDigest};
+use shell_quote::bash;
+use store::{RemoteEndpoint, Snapshot, SnapshotOps, Store, StoreFileByDigest};
 use tonic::metadata::BinaryMetadataValue;
 use tonic::transport::Channel;
 use tonic::{Code, Interceptor, Request, Status};
@@ -41,9 +42,11 @@ use workunit_store::{
 
 use crate::{
   Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, Platform, Process,
-  ProcessCacheScope, ProcessMetadata, ProcessResultMetadata,
+  ProcessCacheScope, ProcessMetadata, ProcessResultMetadata, ProcessTags,
 };
 use grpc_util::headers_to_interceptor_fn;
+use grpc_util::rate_limit::RateLimiter;
+use grpc_util::retry::{retry_call, retry_delay_from_details, RetryThrottle};
 
 // Environment variable which is exclusively used for cache key invalidation.
 // This may be not specified in an Process, and may be populated only by the
@@ -59,6 +62,82 @@ pub const CACHE_KEY_SALT_ENV_VAR_NAME: &str = "PANTS_CACHE_KEY_SALT";
 // CommandRunner.
 pub const CACHE_KEY_TARGET_PLATFORM_ENV_VAR_NAME: &str = "PANTS_CACHE_KEY_TARGET_PLATFORM";
 
+// Environment variable which is exclusively used for cache key invalidation, based on the outputs
+// of the version probes of a Process.
+pub const CACHE_KEY_VERSION_PROBES_ENV_VAR_NAME: &str = "PANTS_CACHE_KEY_VERSION_PROBES";
+
+// Environment variable which is exclusively used for cache key invalidation, based on the
+// `platform_requirements` of a Process (if it has any).
+pub const CACHE_KEY_PLATFORM_REQUIREMENTS_ENV_VAR_NAME: &str =
+  "PANTS_CACHE_KEY_PLATFORM_REQUIREMENTS";
+
+// Environment variable which is exclusively used for cache key invalidation, for processes which
+// run with a pseudo-terminal (and so whose stdout and stderr are merged).
+pub const CACHE_KEY_TTY_ENV_VAR_NAME: &str = "PANTS_CACHE_KEY_TTY";
+
+// Environment variable which is exclusively used for cache key invalidation, based on the
+// `resource_limits` of a Process (if it has any), which may cause it to fail.
+pub const CACHE_KEY_RESOURCE_LIMITS_ENV_VAR_NAME: &str = "PANTS_CACHE_KEY_RESOURCE_LIMITS";
+
+// Platform property which identifies the persistent worker that an action may be routed to, as
+// understood by servers which support remote persistent workers.
+pub const PERSISTENT_WORKER_KEY_PLATFORM_PROPERTY_NAME: &str = "persistentWorkerKey";
+
+// Platform property which schedulers which support worker affinity use to route actions with the
+// same key to the same worker.
+pub const AFFINITY_KEY_PLATFORM_PROPERTY_NAME: &str = "affinityKey";
+
+// Platform property which selects the container image that an action runs in, as understood by
+// servers which run actions in containers (such as RBE and Buildbarn).
+pub const CONTAINER_IMAGE_PLATFORM_PROPERTY_NAME: &str = "container-image";
+
+// The number of times that a request to the action cache is attempted, while it fails with a
+// retryable error.
+pub const ACTION_CACHE_RPC_ATTEMPTS: usize = 3;
+
+///
+/// How the outputs of a Process are declared in a REAPI `Command`. The declaration is derived from
+/// the Process alone, so that the Command (and so the cache key) of a Process is the same whether
+/// it is computed for execution, for caching, or for display.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputDeclaration {
+  /// The pre-v2.1 `output_files` and `output_directories` fields, which cannot represent the
+  /// `output_paths` of a Process.
+  Separate,
+  /// The v2.1 `output_paths` field, which supersedes the pre-v2.1 fields. For compatibility, the
+  /// pre-v2.1 fields are also populated for outputs which were declared as files or directories.
+  Unified,
+}
+
+impl OutputDeclaration {
+  ///
+  /// The declaration to use for the given Process: only uses the unified field if the Process
+  /// requires it.
+  ///
+  pub fn for_process(req: &Process) -> OutputDeclaration {
+    if req.output_paths.is_empty() {
+      OutputDeclaration::Separate
+    } else {
+      OutputDeclaration::Unified
+    }
+  }
+
+  ///
+  /// True if a server with the given capabilities supports the unified field, which was added in
+  /// v2.1 of the API.
+  ///
+  pub fn is_supported_by(self, capabilities: &remexec::ServerCapabilities) -> bool {
+    match self {
+      OutputDeclaration::Separate => true,
+      OutputDeclaration::Unified => matches!(
+        &capabilities.high_api_version,
+        Some(version) if (version.major, version.minor) >= (2, 1)
+      ),
+    }
+  }
+}
+
 #[derive(Debug)]
 pub enum OperationOrStatus {
   Operation(Operation),
@@ -76,6 +155,131 @@ pub enum ExecutionError {
   Timeout,
   // String is the error message.
   Retryable(String),
+  // The server indicated that it is out of capacity (or otherwise asked us to back off). Retried
+  // without limit (until the overall deadline), while throttling the number of concurrent
+  // executions.
+  // String is the error message, and the Duration is the retry delay that the server suggested (via
+  // a google.rpc.RetryInfo detail), if any.
+  ResourceExhausted(String, Option<Duration>),
+}
+
+///
+/// Adaptively limits the number of concurrent executions that are submitted to the server using
+/// additive-increase/multiplicative-decrease: each RESOURCE_EXHAUSTED response from the server
+/// halves the limit, and each successful execution raises it by one, until the limit has recovered
+/// to the concurrency that we had when we were first throttled. If the server suggests a retry
+/// delay, no executions are submitted until it has elapsed.
+///
+#[derive(Default)]
+pub(crate) struct ExecutionThrottle {
+  state: parking_lot::Mutex<ExecutionThrottleState>,
+  released: tokio::sync::Notify,
+}
+
+#[derive(Default)]
+struct ExecutionThrottleState {
+  in_flight: usize,
+  // The maximum number of concurrent executions, or None if we are not currently throttled.
+  limit: Option<usize>,
+  // The number of concurrent executions when we were first throttled.
+  unthrottled_limit: usize,
+  // No executions may be submitted before this instant.
+  paused_until: Option<Instant>,
+}
+
+impl ExecutionThrottle {
+  pub(crate) fn limit(&self) -> Option<usize> {
+    self.state.lock().limit
+  }
+
+  pub(crate) fn in_flight(&self) -> usize {
+    self.state.lock().in_flight
+  }
+
+  pub(crate) fn try_acquire(throttle: &Arc<ExecutionThrottle>) -> Option<ExecutionPermit> {
+    let mut state = throttle.state.lock();
+    if let Some(paused_until) = state.paused_until {
+      if Instant::now() < paused_until {
+        return None;
+      }
+      state.paused_until = None;
+    }
+    if state.limit.map_or(false, |limit| state.in_flight >= limit) {
+      return None;
+    }
+    state.in_flight += 1;
+    Some(ExecutionPermit(throttle.clone()))
+  }
+
+  pub(crate) async fn acquire(throttle: Arc<ExecutionThrottle>) -> ExecutionPermit {
+    loop {
+      if let Some(permit) = ExecutionThrottle::try_acquire(&throttle) {
+        return permit;
+      }
+      // NB: Permits are released one at a time, but the limit may also be raised without a permit
+      // being released, so we poll as well.
+      let _ = tokio::time::timeout(Duration::from_millis(100), throttle.released.notified()).await;
+    }
+  }
+
+  pub(crate) fn resource_exhausted(&self) {
+    let mut state = self.state.lock();
+    let current_limit = match state.limit {
+      Some(limit) => limit,
+      None => {
+        state.unthrottled_limit = std::cmp::max(1, state.in_flight);
+        state.unthrottled_limit
+      }
+    };
+    let limit = std::cmp::max(1, current_limit / 2);
+    if state.limit != Some(limit) {
+      debug!(
+        "remote execution is out of capacity: throttling to {} concurrent executions",
+        limit
+      );
+    }
+    state.limit = Some(limit);
+  }
+
+  ///
+  /// Prevents any executions from being submitted until the given delay (which was suggested by
+  /// the server) has elapsed.
+  ///
+  pub(crate) fn pause_for(&self, retry_delay: Duration) {
+    let mut state = self.state.lock();
+    let paused_until = Instant::now() + retry_delay;
+    if state
+      .paused_until
+      .map_or(true, |until| until < paused_until)
+    {
+      state.paused_until = Some(paused_until);
+    }
+  }
+
+  pub(crate) fn succeeded(&self) {
+    let mut state = self.state.lock();
+    if let Some(limit) = state.limit {
+      if limit + 1 >= state.unthrottled_limit {
+        debug!("remote execution is no longer throttled");
+        state.limit = None;
+      } else {
+        state.limit = Some(limit + 1);
+      }
+      self.released.notify_one();
+    }
+  }
+}
+
+///
+/// A slot for one concurrent execution, which is released when dropped.
+///
+pub(crate) struct ExecutionPermit(Arc<ExecutionThrottle>);
+
+impl Drop for ExecutionPermit {
+  fn drop(&mut self) {
+    self.0.state.lock().in_flight -= 1;
+    self.0.released.notify_one();
+  }
 }
 
 /// Implementation of CommandRunner that runs a command via the Bazel Remote Execution API
@@ -106,6 +310,11 @@ pub struct CommandRunner {
   retry_interval_duration: Duration,
   capabilities_cell: Arc<DoubleCheckedCell<ServerCapabilities>>,
   capabilities_client: Arc<CapabilitiesClient<Channel>>,
+  throttle: Arc<ExecutionThrottle>,
+  action_cache_throttle: Arc<RetryThrottle>,
+  rpc_limiter: Option<Arc<RateLimiter>>,
+  endpoints: Vec<RemoteEndpoint>,
+  pipeline_uploads: bool,
 }
 
 enum StreamOutcome {
@@ -113,6 +322,123 @@ enum StreamOutcome {
   StreamClosed(Option<String>),
 }
 
+///
+/// The progress of an execution, as reported by the server. If the execution times out, this is
+/// used to explain whether the time was spent waiting for a worker (which suggests that the
+/// cluster is short of capacity) or executing (which suggests that the action is slow).
+///
+#[derive(Debug)]
+pub(crate) struct ExecutionProgress {
+  submitted: Instant,
+  // When the server first reported that the action was queued, and executing.
+  queued: Option<Instant>,
+  executing: Option<Instant>,
+  // The worker and the queue time reported in the ExecutedActionMetadata of a response, if any.
+  worker: Option<String>,
+  server_queue_time: Option<Duration>,
+}
+
+impl ExecutionProgress {
+  pub(crate) fn new() -> ExecutionProgress {
+    ExecutionProgress {
+      submitted: Instant::now(),
+      queued: None,
+      executing: None,
+      worker: None,
+      server_queue_time: None,
+    }
+  }
+
+  ///
+  /// Records the stage reported in the metadata of the given Operation, and the
+  /// ExecutedActionMetadata of its response (if it is done).
+  ///
+  pub(crate) fn observe(&mut self, operation: &Operation) {
+    let stage = operation
+      .metadata
+      .as_ref()
+      .and_then(|any| remexec::ExecuteOperationMetadata::decode(&any.value[..]).ok())
+      .and_then(|metadata| remexec::execution_stage::Value::from_i32(metadata.stage));
+    match stage {
+      Some(remexec::execution_stage::Value::Queued) => {
+        self.queued.get_or_insert_with(Instant::now);
+      }
+      Some(remexec::execution_stage::Value::Executing) => {
+        self.executing.get_or_insert_with(Instant::now);
+      }
+      _ => (),
+    }
+
+    use bazel_protos::gen::google::longrunning::operation::Result as OperationResult;
+    let executed_action_metadata = match operation.result {
+      Some(OperationResult::Response(ref response_any)) => {
+        ExecuteResponse::decode(&response_any.value[..])
+          .ok()
+          .and_then(|response| response.result)
+          .and_then(|action_result| action_result.execution_metadata)
+      }
+      _ => None,
+    };
+    if let Some(metadata) = executed_action_metadata {
+      if !metadata.worker.is_empty() {
+        self.worker = Some(metadata.worker);
+      }
+      if let (Some(queued), Some(worker_start)) = (
+        metadata.queued_timestamp.as_ref(),
+        metadata.worker_start_timestamp.as_ref(),
+      ) {
+        self.server_queue_time = TimeSpan::from_start_and_end(queued, worker_start, "remote queue")
+          .ok()
+          .map(|time_span| time_span.duration.into());
+      }
+    }
+  }
+
+  ///
+  /// Explains where the time went for an execution which has timed out.
+  ///
+  pub(crate) fn diagnosis(&self) -> String {
+    let elapsed = self.submitted.elapsed();
+    let (waiting, executing) = if let Some(executing) = self.executing {
+      let waiting = executing.duration_since(self.submitted);
+      (waiting, Some(elapsed - waiting))
+    } else if let Some(waiting) = self.server_queue_time {
+      (
+        waiting,
+        Some(elapsed.checked_sub(waiting).unwrap_or_default()),
+      )
+    } else if self.queued.is_some() {
+      (elapsed, None)
+    } else {
+      return "The server did not report whether the action was queued or executing.".to_owned();
+    };
+
+    match executing {
+      Some(executing) => {
+        let worker = self
+          .worker
+          .as_ref()
+          .map(|worker| format!(" on worker `{}`", worker))
+          .unwrap_or_default();
+        let suggestion = if waiting > executing {
+          "so the remote execution cluster may be short of capacity"
+        } else {
+          "so the action may be slow: consider raising its timeout"
+        };
+        format!(
+          "The action waited {:?} for a worker, and then executed for {:?}{}, {}.",
+          waiting, executing, worker, suggestion
+        )
+      }
+      None => format!(
+        "The action was still waiting for a worker after {:?}, and never began executing, so the \
+         remote execution cluster may be short of capacity.",
+        waiting
+      ),
+    }
+  }
+}
+
 impl CommandRunner {
   /// Construct a new CommandRunner
   pub fn new(
@@ -125,6 +451,8 @@ impl CommandRunner {
     platform: Platform,
     overall_deadline: Duration,
     retry_interval_duration: Duration,
+    rpc_qps: Option<usize>,
+    pipeline_uploads: bool,
   ) -> Result<Self, String> {
     let execution_use_tls = execution_address.starts_with("https://");
     let store_use_tls = store_address.starts_with("https://");
@@ -161,8 +489,10 @@ impl CommandRunner {
     let store_channel = tonic::transport::Channel::balance_list(vec![store_endpoint].into_iter());
 
     let action_cache_client = Arc::new(match interceptor.as_ref() {
-      Some(interceptor) => ActionCacheClient::with_interceptor(store_channel, interceptor.clone()),
-      None => ActionCacheClient::new(store_channel),
+      Some(interceptor) => {
+        ActionCacheClient::with_interceptor(store_channel.clone(), interceptor.clone())
+      }
+      None => ActionCacheClient::new(store_channel.clone()),
     });
 
     let capabilities_client = Arc::new(match interceptor.as_ref() {
@@ -172,6 +502,26 @@ impl CommandRunner {
       None => CapabilitiesClient::new(execution_channel.clone()),
     });
 
+    let endpoints = vec![
+      capabilities_endpoint(
+        "execution",
+        execution_address,
+        capabilities_client.as_ref().clone(),
+        metadata.instance_name.clone(),
+      ),
+      capabilities_endpoint(
+        "action_cache",
+        store_address,
+        match interceptor.as_ref() {
+          Some(interceptor) => {
+            CapabilitiesClient::with_interceptor(store_channel, interceptor.clone())
+          }
+          None => CapabilitiesClient::new(store_channel),
+        },
+        metadata.instance_name.clone(),
+      ),
+    ];
+
     let command_runner = CommandRunner {
       metadata,
       headers,
@@ -184,6 +534,11 @@ impl CommandRunner {
       retry_interval_duration,
       capabilities_cell: Arc::new(DoubleCheckedCell::new()),
       capabilities_client,
+      throttle: Arc::new(ExecutionThrottle::default()),
+      action_cache_throttle: RetryThrottle::new(),
+      rpc_limiter: rpc_qps.map(|qps| Arc::new(RateLimiter::new(qps))),
+      endpoints,
+      pipeline_uploads,
     };
 
     Ok(command_runner)
@@ -193,6 +548,14 @@ impl CommandRunner {
     self.platform
   }
 
+  ///
+  /// The endpoints of the channels that this CommandRunner uses, which may be warmed up before
+  /// it is first used.
+  ///
+  pub fn endpoints(&self) -> Vec<RemoteEndpoint> {
+    self.endpoints.clone()
+  }
+
   async fn get_capabilities(&self) -> Result<&remexec::ServerCapabilities, String> {
     let capabilities_fut = async {
       let mut request = remexec::GetCapabilitiesRequest::default();
@@ -200,7 +563,11 @@ impl CommandRunner {
         request.instance_name = s.clone();
       }
 
-      let request = apply_headers(Request::new(request), "");
+      let request = apply_headers(
+        Request::new(request),
+        &Context::default(),
+        &ProcessTags::default(),
+      );
 
       let mut client = self.capabilities_client.as_ref().clone();
       client
@@ -220,7 +587,12 @@ impl CommandRunner {
   // Outputs progress reported by the server and returns the next actionable operation
   // or gRPC status back to the main loop (plus the operation name so the main loop can
   // reconnect).
-  async fn wait_on_operation_stream<S>(&self, mut stream: S, context: &Context) -> StreamOutcome
+  async fn wait_on_operation_stream<S>(
+    &self,
+    mut stream: S,
+    context: &Context,
+    progress: &parking_lot::Mutex<ExecutionProgress>,
+  ) -> StreamOutcome
   where
     S: Stream<Item = Result<Operation, Status>> + Unpin,
   {
@@ -254,6 +626,7 @@ impl CommandRunner {
             &context.build_id,
             &operation
           );
+          progress.lock().observe(&operation);
 
           // Extract the operation name.
           // Note: protobuf can return empty string for an empty field so convert empty strings
@@ -545,11 +918,24 @@ impl CommandRunner {
         Err(self.extract_missing_digests(&precondition_failure))
       }
 
-      Code::Aborted
-      | Code::Internal
-      | Code::ResourceExhausted
-      | Code::Unavailable
-      | Code::Unknown => Err(ExecutionError::Retryable(status.message)),
+      Code::ResourceExhausted => {
+        let retry_delay = retry_delay_from_details(&status.details);
+        Err(ExecutionError::ResourceExhausted(
+          status.message,
+          retry_delay,
+        ))
+      }
+      Code::Aborted | Code::Internal | Code::Unavailable | Code::Unknown => {
+        // A server which suggests a retry delay is asking us to back off, rather than to retry
+        // immediately.
+        match retry_delay_from_details(&status.details) {
+          Some(retry_delay) => Err(ExecutionError::ResourceExhausted(
+            status.message,
+            Some(retry_delay),
+          )),
+          None => Err(ExecutionError::Retryable(status.message)),
+        }
+      }
       code => Err(ExecutionError::Fatal(format!(
         "Error from remote execution: {:?}: {:?}",
         code, status.message,
@@ -557,6 +943,32 @@ impl CommandRunner {
     }
   }
 
+  ///
+  /// Acquires a permit to submit an execution, waiting (visibly) while we are throttled due to the
+  /// server being out of capacity.
+  ///
+  async fn acquire_execution_permit(&self, context: &Context) -> ExecutionPermit {
+    if let Some(permit) = ExecutionThrottle::try_acquire(&self.throttle) {
+      return permit;
+    }
+    let limit = self.throttle.limit().unwrap_or_default();
+    with_workunit(
+      context.workunit_store.clone(),
+      "wait_for_remote_capacity".to_owned(),
+      WorkunitMetadata {
+        level: Level::Info,
+        desc: Some(format!(
+          "Waiting for remote execution capacity (throttled to {} concurrent executions)",
+          limit
+        )),
+        ..WorkunitMetadata::default()
+      },
+      ExecutionThrottle::acquire(self.throttle.clone()),
+      |_, md| md,
+    )
+    .await
+  }
+
   // Main loop: This function connects to the RE server and submits the given remote execution
   // request via the REv2 Execute method. It then monitors the operation stream until the
   // request completes. It will reconnect using the REv2 WaitExecution method if the connection
@@ -565,11 +977,16 @@ impl CommandRunner {
   // The `run` method on CommandRunner uses this function to implement the bulk of the
   // processing for remote execution requests. The `run` method wraps the call with the method
   // with an overall deadline timeout.
+  //
+  // If the inputs of the request are still being uploaded (see `pipeline_uploads`), then a
+  // report of missing digests from the server waits for the upload to complete before retrying.
   async fn run_execute_request(
     &self,
     execute_request: ExecuteRequest,
     process: Process,
     context: &Context,
+    mut pending_upload: Option<Shared<BoxFuture<'_, Result<(), String>>>>,
+    progress: &parking_lot::Mutex<ExecutionProgress>,
   ) -> Result<FallibleProcessResultWithPlatform, String> {
     const MAX_RETRIES: u32 = 5;
     const MAX_BACKOFF_DURATION: Duration = Duration::from_secs(10);
@@ -577,6 +994,7 @@ impl CommandRunner {
     let start_time = Instant::now();
     let mut current_operation_name: Option<String> = None;
     let mut num_retries = 0;
+    let mut num_capacity_waits = 0;
 
     loop {
       // If we are currently retrying a request, then delay using an exponential backoff.
@@ -592,6 +1010,22 @@ impl CommandRunner {
         tokio::time::sleep(sleep_time).await;
       }
 
+      if let Some(rpc_limiter) = &self.rpc_limiter {
+        let waited = rpc_limiter.acquire(1).await;
+        if waited > Duration::default() {
+          context.workunit_store.increment_counter(
+            Metric::RemoteExecutionRateLimitedMs,
+            waited.as_millis() as u64,
+          );
+        }
+      }
+
+      let rpc_start_time = Instant::now();
+      let rpc_time_metric = if current_operation_name.is_none() {
+        ObservationMetric::RemoteExecutionRPCExecuteTime
+      } else {
+        ObservationMetric::RemoteExecutionRPCWaitExecutionTime
+      };
       let rpc_result = match current_operation_name {
         None => {
           // The request has not been submitted yet. Submit the request using the REv2
@@ -604,7 +1038,11 @@ impl CommandRunner {
             .workunit_store
             .increment_counter(Metric::RemoteExecutionRPCExecute, 1);
           let mut client = self.execution_client.as_ref().clone();
-          let request = apply_headers(Request::new(execute_request.clone()), &context.build_id);
+          let request = apply_headers(
+            Request::new(execute_request.clone()),
+            &context,
+            &process.tags,
+          );
           client.execute(request).await
         }
 
@@ -622,7 +1060,11 @@ impl CommandRunner {
             name: operation_name.to_owned(),
           };
           let mut client = self.execution_client.as_ref().clone();
-          let request = apply_headers(Request::new(wait_execution_request), &context.build_id);
+          let request = apply_headers(
+            Request::new(wait_execution_request),
+            &context,
+            &process.tags,
+          );
           client.wait_execution(request).await
         }
       };
@@ -635,8 +1077,11 @@ impl CommandRunner {
           // or status to interpret.
           let operation_stream = operation_stream_response.into_inner();
           let stream_outcome = self
-            .wait_on_operation_stream(operation_stream, &context)
+            .wait_on_operation_stream(operation_stream, &context, progress)
             .await;
+          context
+            .workunit_store
+            .record_observation_since(rpc_time_metric, rpc_start_time);
 
           match stream_outcome {
             StreamOutcome::Complete(status) => {
@@ -672,10 +1117,14 @@ impl CommandRunner {
           }
         }
         Err(status) => {
+          // Preserve any details (such as a suggested retry delay) which accompany the error.
+          let details = StatusProto::decode(status.details())
+            .map(|status_proto| status_proto.details)
+            .unwrap_or_default();
           let status_proto = StatusProto {
             code: status.code() as i32,
             message: status.message().to_owned(),
-            ..StatusProto::default()
+            details,
           };
           OperationOrStatus::Status(status_proto)
         }
@@ -708,26 +1157,66 @@ impl CommandRunner {
               num_retries += 1;
             }
           }
-          ExecutionError::MissingDigests(missing_digests) => {
-            trace!(
-              "Server reported missing digests; trying to upload: {:?}",
-              missing_digests,
+          ExecutionError::ResourceExhausted(e, retry_delay) => {
+            // The server is out of capacity: rather than counting this against our retries (and
+            // hammering the server), throttle the number of concurrent executions and back off
+            // until it has capacity again. The overall deadline still applies.
+            context
+              .workunit_store
+              .increment_counter(Metric::RemoteExecutionResourceExhausted, 1);
+            self.throttle.resource_exhausted();
+            num_capacity_waits += 1;
+
+            let multiplier = thread_rng().gen_range(1..2_u32.pow(num_capacity_waits.min(16)) + 1);
+            let sleep_time = self.retry_interval_duration * multiplier;
+            let sleep_time = sleep_time.min(MAX_BACKOFF_DURATION);
+            // Never retry sooner than the server asked us to.
+            let sleep_time = match retry_delay {
+              Some(retry_delay) => {
+                self.throttle.pause_for(retry_delay);
+                sleep_time.max(retry_delay)
+              }
+              None => sleep_time,
+            };
+            debug!(
+              "server is out of capacity ({}): delaying {:?} before retry",
+              e, sleep_time
             );
+            tokio::time::sleep(sleep_time).await;
+          }
+          ExecutionError::MissingDigests(missing_digests) => {
+            if let Some(pending_upload) = pending_upload.take() {
+              trace!(
+                "Server reported missing digests; waiting for inputs to finish uploading: {:?}",
+                missing_digests,
+              );
+              context
+                .workunit_store
+                .increment_counter(Metric::RemoteExecutionPipelinedUploadWaits, 1);
+              pending_upload.await?;
+            } else {
+              trace!(
+                "Server reported missing digests; trying to upload: {:?}",
+                missing_digests,
+              );
 
-            let _ = self
-              .store
-              .ensure_remote_has_recursive(missing_digests)
-              .await?;
+              let _ = self
+                .store
+                .ensure_remote_has_recursive(missing_digests)
+                .await?;
+            }
           }
           ExecutionError::Timeout => {
             context
               .workunit_store
               .increment_counter(Metric::RemoteExecutionTimeouts, 1);
+            let diagnosis = progress.lock().diagnosis();
             return populate_fallible_execution_result_for_timeout(
               &self.store,
               &process.description,
               process.timeout,
               start_time.elapsed(),
+              &diagnosis,
               self.platform,
             )
             .await;
@@ -752,8 +1241,47 @@ impl crate::CommandRunner for CommandRunner {
 
     // Construct the REv2 ExecuteRequest and related data for this execution request.
     let request = self.extract_compatible_request(&request).unwrap();
+    if crate::placeholders::contains_placeholders(&request.env) {
+      return Err(format!(
+        "Placeholders in the env of a process are not supported by remote execution: {}",
+        request.description
+      ));
+    }
+    if !request.leased_port_variables.is_empty() || request.invocation_id_variable.is_some() {
+      return Err(format!(
+        "Leased ports and invocation id variables are not supported by remote execution: {}",
+        request.description
+      ));
+    }
+    if request.allocate_tty {
+      return Err(format!(
+        "Allocating a TTY is not supported by remote execution: {}",
+        request.description
+      ));
+    }
+    if !request.resource_limits.is_empty() {
+      return Err(format!(
+        "Resource limits are not supported by remote execution: {}",
+        request.description
+      ));
+    }
+    if !OutputDeclaration::for_process(&request).is_supported_by(capabilities) {
+      return Err(format!(
+        "Output paths require a remote execution server which supports v2.1 of the Remote \
+         Execution API, but the server reported {:?}: {}",
+        capabilities.high_api_version, request.description
+      ));
+    }
     let store = self.store.clone();
-    let (action, command, execute_request) = make_execute_request(&request, self.metadata.clone())?;
+    let (action, command, mut execute_request) =
+      make_execute_request(&request, self.metadata.clone())?;
+    // NB: The ExecutionPolicy is not a part of the Action, and so does not affect cache keys.
+    let execution_priority = context.priority.execution_priority();
+    if execution_priority != 0 {
+      execute_request.execution_policy = Some(remexec::ExecutionPolicy {
+        priority: execution_priority,
+      });
+    }
     let build_id = context.build_id.clone();
 
     debug!("Remote execution: {}", request.description);
@@ -795,7 +1323,9 @@ impl crate::CommandRunner for CommandRunner {
         &self.metadata,
         self.platform,
         &context,
+        &request.tags,
         self.action_cache_client.clone(),
+        &self.action_cache_throttle,
         self.store.clone(),
         false,
       ),
@@ -811,7 +1341,7 @@ impl crate::CommandRunner for CommandRunner {
     }
 
     // Upload the action (and related data, i.e. the embedded command and input files).
-    with_workunit(
+    let upload = with_workunit(
       context.workunit_store.clone(),
       "ensure_action_uploaded".to_owned(),
       WorkunitMetadata {
@@ -821,14 +1351,41 @@ impl crate::CommandRunner for CommandRunner {
       },
       ensure_action_uploaded(&store, command_digest, action_digest, request.input_files),
       |_, md| md,
-    )
-    .await?;
+    );
+    // If uploads are pipelined, the upload runs concurrently with the execution request below
+    // rather than before it: when most inputs are already present, the server can begin executing
+    // without waiting for the upload to complete.
+    let pending_upload = if self.pipeline_uploads {
+      Some(upload.boxed().shared())
+    } else {
+      upload.await?;
+      None
+    };
+
+    // Wait until the server has capacity for another execution, if we have been throttled.
+    let _permit = self.acquire_execution_permit(&context).await;
 
     // Submit the execution request to the RE server for execution.
     context
       .workunit_store
       .increment_counter(Metric::RemoteExecutionRequests, 1);
-    let result_fut = self.run_execute_request(execute_request, request, &context);
+    let progress = parking_lot::Mutex::new(ExecutionProgress::new());
+    let execute_fut = self.run_execute_request(
+      execute_request,
+      request,
+      &context,
+      pending_upload.clone(),
+      &progress,
+    );
+    let result_fut = async move {
+      if let Some(pending_upload) = pending_upload {
+        // NB: The result of the upload is observed by the execution request if the server reports
+        // missing digests: otherwise, the inputs were present.
+        future::join(pending_upload, execute_fut).await.1
+      } else {
+        execute_fut.await
+      }
+    };
     let timeout_fut = tokio::time::timeout(deadline_duration, result_fut);
     let response = with_workunit(
       context.workunit_store.clone(),
@@ -842,8 +1399,9 @@ impl crate::CommandRunner for CommandRunner {
         if result.is_err() {
           metadata.level = Level::Error;
           metadata.desc = Some(format!(
-            "remote execution timed out after {:?}",
-            deadline_duration
+            "remote execution timed out after {:?}. {}",
+            deadline_duration,
+            progress.lock().diagnosis()
           ));
         }
         metadata
@@ -855,6 +1413,7 @@ impl crate::CommandRunner for CommandRunner {
     match response {
       Ok(result) => {
         if result.is_ok() {
+          self.throttle.succeeded();
           context
             .workunit_store
             .increment_counter(Metric::RemoteExecutionSuccess, 1);
@@ -875,8 +1434,9 @@ impl crate::CommandRunner for CommandRunner {
           .workunit_store
           .increment_counter(Metric::RemoteExecutionTimeouts, 1);
         Err(format!(
-          "remote execution timed out after {:?}",
-          deadline_duration
+          "remote execution timed out after {:?}. {}",
+          deadline_duration,
+          progress.lock().diagnosis()
         ))
       }
     }
@@ -893,6 +1453,44 @@ impl crate::CommandRunner for CommandRunner {
   }
 }
 
+///
+/// Stands in for the remote execution CommandRunner in offline mode: rather than waiting for the
+/// network to time out, fails all processes immediately with an error that explains why.
+///
+pub struct OfflineCommandRunner {
+  platform: Platform,
+}
+
+impl OfflineCommandRunner {
+  pub fn new(platform: Platform) -> OfflineCommandRunner {
+    OfflineCommandRunner { platform }
+  }
+}
+
+#[async_trait]
+impl crate::CommandRunner for OfflineCommandRunner {
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    _context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    Err(format!(
+      "Cannot remotely execute {} because Pants is running in offline mode (`--offline`). \
+      Disable `--remote-execution` to run processes locally.",
+      req.user_facing_name()
+    ))
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    for compatible_constraint in vec![None, self.platform.into()].iter() {
+      if let Some(compatible_req) = req.0.get(compatible_constraint) {
+        return Some(compatible_req.clone());
+      }
+    }
+    None
+  }
+}
+
 fn maybe_add_workunit(
   result_cached: bool,
   name: &str,
@@ -914,18 +1512,80 @@ fn maybe_add_workunit(
   }
 }
 
+///
+/// The REAPI has no notion of a pipeline of processes, so a Process which pipes from another
+/// process is executed remotely under a shell with `pipefail` set, which matches the semantics of
+/// local execution.
+///
+fn make_pipeline_arguments(
+  source_argv: &[String],
+  sink_argv: &[String],
+) -> Result<Vec<String>, String> {
+  let quote = |argv: &[String]| -> Result<String, String> {
+    argv
+      .iter()
+      .enumerate()
+      .map(|(i, arg)| {
+        // Relative binaries are resolved relative to the working directory locally, so we do the
+        // same here, rather than allowing the shell to consult the PATH.
+        let arg = if i == 0 && !arg.contains('/') {
+          format!("./{}", arg)
+        } else {
+          arg.to_owned()
+        };
+        String::from_utf8(bash::escape(&arg)).map_err(|e| format!("{:?}", e))
+      })
+      .collect::<Result<Vec<_>, _>>()
+      .map(|args| args.join(" "))
+  };
+  Ok(vec![
+    "/bin/bash".to_owned(),
+    "-o".to_owned(),
+    "pipefail".to_owned(),
+    "-c".to_owned(),
+    format!("{} | {}", quote(source_argv)?, quote(sink_argv)?),
+  ])
+}
+
+///
+/// Creates the REAPI protos for the given Process, declaring its outputs as appropriate for the
+/// Process itself (see `OutputDeclaration::for_process`).
+///
 pub fn make_execute_request(
   req: &Process,
   metadata: ProcessMetadata,
 ) -> Result<(remexec::Action, remexec::Command, remexec::ExecuteRequest), String> {
+  make_execute_request_with_output_declaration(req, metadata, OutputDeclaration::for_process(req))
+}
+
+///
+/// The digest of the Action which would be executed for the given Process.
+///
+pub fn action_digest(req: &Process, metadata: ProcessMetadata) -> Result<Digest, String> {
+  let (_, _, execute_request) = make_execute_request(req, metadata)?;
+  require_digest(execute_request.action_digest.as_ref())
+}
+
+pub fn make_execute_request_with_output_declaration(
+  req: &Process,
+  metadata: ProcessMetadata,
+  output_declaration: OutputDeclaration,
+) -> Result<(remexec::Action, remexec::Command, remexec::ExecuteRequest), String> {
+  let arguments = match req.pipe_from_argv {
+    Some(ref pipe_from_argv) => make_pipeline_arguments(pipe_from_argv, &req.argv)?,
+    None => req.argv.clone(),
+  };
   let mut command = remexec::Command {
-    arguments: req.argv.clone(),
+    arguments,
     ..remexec::Command::default()
   };
   for (name, value) in &req.env {
     if name == CACHE_KEY_GEN_VERSION_ENV_VAR_NAME
       || name == CACHE_KEY_TARGET_PLATFORM_ENV_VAR_NAME
       || name == CACHE_KEY_SALT_ENV_VAR_NAME
+      || name == CACHE_KEY_VERSION_PROBES_ENV_VAR_NAME
+      || name == CACHE_KEY_TTY_ENV_VAR_NAME
+      || name == CACHE_KEY_RESOURCE_LIMITS_ENV_VAR_NAME
     {
       return Err(format!(
         "Cannot set env var with name {} as that is reserved for internal use by pants",
@@ -977,15 +1637,48 @@ pub fn make_execute_request(
       });
   }
 
+  if let Some(version_probe_fingerprint) = req.version_probe_fingerprint {
+    command
+      .environment_variables
+      .push(remexec::command::EnvironmentVariable {
+        name: CACHE_KEY_VERSION_PROBES_ENV_VAR_NAME.to_string(),
+        value: version_probe_fingerprint.to_hex(),
+      });
+  }
+
+  if !req.platform_requirements.is_empty() {
+    command
+      .environment_variables
+      .push(remexec::command::EnvironmentVariable {
+        name: CACHE_KEY_PLATFORM_REQUIREMENTS_ENV_VAR_NAME.to_string(),
+        value: req.platform_requirements.cache_key_value(),
+      });
+  }
+
+  if req.allocate_tty {
+    command
+      .environment_variables
+      .push(remexec::command::EnvironmentVariable {
+        name: CACHE_KEY_TTY_ENV_VAR_NAME.to_string(),
+        value: "1".to_owned(),
+      });
+  }
+
+  if !req.resource_limits.is_empty() {
+    command
+      .environment_variables
+      .push(remexec::command::EnvironmentVariable {
+        name: CACHE_KEY_RESOURCE_LIMITS_ENV_VAR_NAME.to_string(),
+        value: req.resource_limits.cache_key_value(),
+      });
+  }
+
   {
     command
       .environment_variables
       .push(remexec::command::EnvironmentVariable {
         name: CACHE_KEY_TARGET_PLATFORM_ENV_VAR_NAME.to_string(),
-        value: match req.platform_constraint {
-          Some(plat) => plat.into(),
-          None => "none".to_string(),
-        },
+        value: req.output_platform.cache_key_value(req.platform_constraint),
       });
   }
 
@@ -999,7 +1692,6 @@ pub fn make_execute_request(
     })
     .collect::<Result<Vec<String>, String>>()?;
   output_files.sort();
-  command.output_files = output_files;
 
   let mut output_directories = req
     .output_directories
@@ -1011,6 +1703,34 @@ pub fn make_execute_request(
     })
     .collect::<Result<Vec<String>, String>>()?;
   output_directories.sort();
+
+  match output_declaration {
+    OutputDeclaration::Separate if !req.output_paths.is_empty() => {
+      return Err(format!(
+        "Output paths cannot be declared without using v2.1 of the Remote Execution API: {}",
+        req.description
+      ));
+    }
+    OutputDeclaration::Separate => (),
+    OutputDeclaration::Unified => {
+      let mut output_paths = req
+        .output_paths
+        .iter()
+        .map(|p| {
+          p.to_str()
+            .map(str::to_owned)
+            .ok_or_else(|| format!("Non-UTF8 output path: {:?}", p))
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+      output_paths.extend(output_files.iter().cloned());
+      output_paths.extend(output_directories.iter().cloned());
+      output_paths.sort();
+      output_paths.dedup();
+      command.output_paths = output_paths;
+    }
+  }
+
+  command.output_files = output_files;
   command.output_directories = output_directories;
 
   if let Some(working_directory) = &req.working_directory {
@@ -1030,6 +1750,35 @@ pub fn make_execute_request(
     platform_properties.push(("JDK_SYMLINK".to_owned(), ".jdk".to_owned()));
   }
 
+  if let Some(ref container_image) = req.container_image {
+    // The image of the process takes precedence over any image which was configured for all
+    // processes.
+    platform_properties.retain(|(name, _)| name != CONTAINER_IMAGE_PLATFORM_PROPERTY_NAME);
+    platform_properties.push((
+      CONTAINER_IMAGE_PLATFORM_PROPERTY_NAME.to_owned(),
+      container_image.clone(),
+    ));
+  }
+
+  if let Some(ref remote_worker_key) = req.remote_worker_key {
+    // Servers which support persistent workers (such as Buildbarn) use this property to route
+    // actions to a warm worker with the same key. Like all platform properties, it contributes to
+    // the cache key of the action.
+    platform_properties.push((
+      PERSISTENT_WORKER_KEY_PLATFORM_PROPERTY_NAME.to_owned(),
+      remote_worker_key.clone(),
+    ));
+  }
+
+  if let Some(ref remote_affinity_key) = req.remote_affinity_key {
+    // Unlike the worker key, affinity is a hint: a scheduler may run the action on any compatible
+    // worker, but prefers the one which last ran an action with the same key.
+    platform_properties.push((
+      AFFINITY_KEY_PLATFORM_PROPERTY_NAME.to_owned(),
+      remote_affinity_key.clone(),
+    ));
+  }
+
   // Extract `Platform` proto from the `Command` to avoid a partial move of `Command`.
   let mut command_platform = command.platform.take().unwrap_or_default();
 
@@ -1092,6 +1841,7 @@ pub async fn populate_fallible_execution_result_for_timeout(
   description: &str,
   timeout: Option<Duration>,
   elapsed: Duration,
+  diagnosis: &str,
   platform: Platform,
 ) -> Result<FallibleProcessResultWithPlatform, String> {
   let timeout_msg = if let Some(timeout) = timeout {
@@ -1099,7 +1849,10 @@ pub async fn populate_fallible_execution_result_for_timeout(
   } else {
     format!("server timeout after {:?}", elapsed)
   };
-  let stdout = Bytes::from(format!("Exceeded {} for {}", timeout_msg, description));
+  let stdout = Bytes::from(format!(
+    "Exceeded {} for {}\n\n{}",
+    timeout_msg, description, diagnosis
+  ));
   let stdout_digest = store.store_file_bytes(stdout, true).await?;
 
   Ok(FallibleProcessResultWithPlatform {
@@ -1342,21 +2095,33 @@ pub fn extract_output_files(
 }
 
 /// Apply REAPI request metadata header to a `tonic::Request`.
-fn apply_headers<T>(mut request: Request<T>, build_id: &str) -> Request<T> {
-  let reapi_request_metadata = remexec::RequestMetadata {
+///
+/// The invocation id of the Session (if any) identifies the tool invocation, while the build id
+/// correlates it with the rest of the run. The tags of the process (if any) which the request is
+/// made on behalf of are also applied, in order to attribute its cost.
+fn apply_headers<T>(mut request: Request<T>, context: &Context, tags: &ProcessTags) -> Request<T> {
+  let tool_invocation_id = if context.invocation_id.is_empty() {
+    context.build_id.clone()
+  } else {
+    context.invocation_id.clone()
+  };
+  let mut reapi_request_metadata = remexec::RequestMetadata {
     tool_details: Some(remexec::ToolDetails {
       tool_name: "pants".into(),
       ..remexec::ToolDetails::default()
     }),
-    tool_invocation_id: build_id.to_string(),
+    tool_invocation_id,
+    correlated_invocations_id: context.build_id.clone(),
     ..remexec::RequestMetadata::default()
   };
+  tags.apply_to_request_metadata(&mut reapi_request_metadata);
 
   let md = request.metadata_mut();
   md.insert_bin(
     "google.devtools.remoteexecution.v1test.requestmetadata-bin",
     BinaryMetadataValue::try_from_bytes(&reapi_request_metadata.to_bytes()).unwrap(),
   );
+  tags.apply_to_headers(md);
 
   request
 }
@@ -1367,12 +2132,17 @@ fn apply_headers<T>(mut request: Request<T>, build_id: &str) -> Request<T> {
 /// by checking the Action Cache (e.g., BuildBarn). Thus, this client must check the cache
 /// explicitly in order to avoid duplicating already-cached work. This behavior matches
 /// the Bazel RE client.
+///
+/// Retryable errors are retried (up to `ACTION_CACHE_RPC_ATTEMPTS` times), honoring any retry delay
+/// that the server suggests via the given throttle.
 pub async fn check_action_cache(
   action_digest: Digest,
   metadata: &ProcessMetadata,
   platform: Platform,
   context: &Context,
+  tags: &ProcessTags,
   action_cache_client: Arc<ActionCacheClient<Channel>>,
+  action_cache_throttle: &Arc<RetryThrottle>,
   store: Store,
   eager_fetch: bool,
 ) -> Result<Option<FallibleProcessResultWithPlatform>, String> {
@@ -1390,9 +2160,17 @@ pub async fn check_action_cache(
     ..remexec::GetActionResultRequest::default()
   };
 
-  let mut client = action_cache_client.as_ref().clone();
-  let request = apply_headers(Request::new(request), &context.build_id);
-  let action_result_response = client.get_action_result(request).await;
+  let start_time = Instant::now();
+  let action_result_response = retry_call(action_cache_throttle, ACTION_CACHE_RPC_ATTEMPTS, || {
+    let mut client = action_cache_client.as_ref().clone();
+    let request = apply_headers(Request::new(request.clone()), &context, tags);
+    async move { client.get_action_result(request).await }
+  })
+  .await;
+  context.workunit_store.record_observation_since(
+    ObservationMetric::RemoteCacheGetActionResultTime,
+    start_time,
+  );
 
   match action_result_response {
     Ok(action_result) => {
@@ -1476,6 +2254,40 @@ pub fn format_error(error: &StatusProto) -> String {
   format!("{}: {}", error_code, error.message)
 }
 
+///
+/// A RemoteEndpoint which is warmed up using a `GetCapabilities` request (which all REAPI servers
+/// support cheaply) on the given client.
+///
+pub(crate) fn capabilities_endpoint(
+  name: &str,
+  address: &str,
+  client: CapabilitiesClient<Channel>,
+  instance_name: Option<String>,
+) -> RemoteEndpoint {
+  RemoteEndpoint::new(
+    name,
+    address,
+    Arc::new(move || {
+      let mut client = client.clone();
+      let request = apply_headers(
+        Request::new(remexec::GetCapabilitiesRequest {
+          instance_name: instance_name.clone().unwrap_or_default(),
+        }),
+        &Context::default(),
+        &ProcessTags::default(),
+      );
+      async move {
+        client
+          .get_capabilities(request)
+          .await
+          .map(|_| ())
+          .map_err(rpcerror_to_string)
+      }
+      .boxed()
+    }),
+  )
+}
+
 pub(crate) fn rpcerror_to_string(status: Status) -> String {
   format!("{:?}: {:?}", status.code(), status.message(),)
 }
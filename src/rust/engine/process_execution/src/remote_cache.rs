@@ -1,5 +1,6 @@
 use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::ffi::OsString;
+use std::fmt;
 use std::path::Component;
 use std::sync::Arc;
 use std::time::Instant;
@@ -10,15 +11,23 @@ use bazel_protos::require_digest;
 use fs::RelativePath;
 use futures::FutureExt;
 use grpc_util::headers_to_interceptor_fn;
+use grpc_util::retry::{retry_call, RetryThrottle};
 use hashing::Digest;
 use parking_lot::Mutex;
+use prost::Message;
 use remexec::action_cache_client::ActionCacheClient;
-use remexec::{ActionResult, Command, FileNode, Tree};
-use store::Store;
+use remexec::capabilities_client::CapabilitiesClient;
+use remexec::{Action, ActionResult, Command, FileNode, Tree};
+use store::{with_request_priority, RemoteEndpoint, RequestPriority, Store};
+use task_executor::TailTasks;
 use tonic::transport::Channel;
-use workunit_store::{with_workunit, Level, Metric, ObservationMetric, WorkunitMetadata};
+use tonic::{Code, Interceptor};
+use workunit_store::{
+  with_workunit, Level, Metric, ObservationMetric, UserMetadataItem, WorkunitMetadata,
+};
 
-use crate::remote::make_execute_request;
+use crate::cache_backfill::{BackfillEntry, CacheBackfill};
+use crate::remote::{capabilities_endpoint, make_execute_request};
 use crate::{
   Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, Platform, Process,
   ProcessMetadata,
@@ -32,6 +41,65 @@ use crate::{
 /// reasonable increment.
 const LOG_ERRORS_INCREMENT: usize = 5;
 
+///
+/// The category of a failure to write to the remote cache, which is attached to the workunit of
+/// the write so that persistent failures (such as a lack of permission to write) can be told apart
+/// from transient ones.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CacheWriteErrorCategory {
+  /// The Action, Command, or inputs of the process could not be uploaded to the remote store.
+  UploadInputs,
+  /// The outputs of the process could not be captured or uploaded to the remote store.
+  UploadOutputs,
+  /// The action cache refused the write, because the client is not authorized to write.
+  PermissionDenied,
+  /// The action cache was unavailable, or did not respond in time.
+  Unavailable,
+  /// The action cache rejected the write for some other reason.
+  Rejected,
+}
+
+impl CacheWriteErrorCategory {
+  fn for_status(status: &tonic::Status) -> CacheWriteErrorCategory {
+    match status.code() {
+      Code::PermissionDenied | Code::Unauthenticated => CacheWriteErrorCategory::PermissionDenied,
+      Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted | Code::Aborted => {
+        CacheWriteErrorCategory::Unavailable
+      }
+      _ => CacheWriteErrorCategory::Rejected,
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      CacheWriteErrorCategory::UploadInputs => "upload_inputs",
+      CacheWriteErrorCategory::UploadOutputs => "upload_outputs",
+      CacheWriteErrorCategory::PermissionDenied => "permission_denied",
+      CacheWriteErrorCategory::Unavailable => "unavailable",
+      CacheWriteErrorCategory::Rejected => "rejected",
+    }
+  }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CacheWriteError {
+  pub category: CacheWriteErrorCategory,
+  pub message: String,
+}
+
+impl CacheWriteError {
+  fn new(category: CacheWriteErrorCategory) -> impl FnOnce(String) -> CacheWriteError {
+    move |message| CacheWriteError { category, message }
+  }
+}
+
+impl fmt::Display for CacheWriteError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} ({})", self.message, self.category.as_str())
+  }
+}
+
 /// This `CommandRunner` implementation caches results remotely using the Action Cache service
 /// of the Remote Execution API.
 ///
@@ -40,6 +108,13 @@ const LOG_ERRORS_INCREMENT: usize = 5;
 /// then the remote cache, and then execution (local or remote) as necessary if neither cache
 /// has a hit. On the way back out of the stack, the result will be stored remotely and
 /// then locally.
+///
+/// If cache writes are disabled but a `CacheBackfill` is configured, results are instead recorded
+/// in its journal, and uploaded in the background.
+///
+/// Failed writes are logged, and reported as `remote_cache_write` workunits with the category of
+/// the failure. If writes are strict, they are instead completed before the result is returned,
+/// and a failed write fails the process.
 #[derive(Clone)]
 pub struct CommandRunner {
   underlying: Arc<dyn crate::CommandRunner>,
@@ -47,13 +122,18 @@ pub struct CommandRunner {
   executor: task_executor::Executor,
   store: Store,
   action_cache_client: Arc<ActionCacheClient<Channel>>,
+  action_cache_throttle: Arc<RetryThrottle>,
   headers: BTreeMap<String, String>,
   platform: Platform,
   cache_read: bool,
   cache_write: bool,
+  strict_writes: bool,
   eager_fetch: bool,
   read_errors_counter: Arc<Mutex<BTreeMap<String, usize>>>,
   write_errors_counter: Arc<Mutex<BTreeMap<String, usize>>>,
+  endpoint: RemoteEndpoint,
+  backfill: Option<Arc<CacheBackfill>>,
+  tail_tasks: TailTasks,
 }
 
 impl CommandRunner {
@@ -78,11 +158,26 @@ impl CommandRunner {
 
     let endpoint = grpc_util::create_endpoint(&action_cache_address, tls_client_config.as_ref())?;
     let channel = tonic::transport::Channel::balance_list(vec![endpoint].into_iter());
-    let action_cache_client = Arc::new(if headers.is_empty() {
-      ActionCacheClient::new(channel)
+    let interceptor = if headers.is_empty() {
+      None
     } else {
-      ActionCacheClient::with_interceptor(channel, headers_to_interceptor_fn(&headers)?)
+      Some(Interceptor::new(headers_to_interceptor_fn(&headers)?))
+    };
+    let action_cache_client = Arc::new(match interceptor.as_ref() {
+      Some(interceptor) => {
+        ActionCacheClient::with_interceptor(channel.clone(), interceptor.clone())
+      }
+      None => ActionCacheClient::new(channel.clone()),
     });
+    let endpoint = capabilities_endpoint(
+      "action_cache",
+      action_cache_address,
+      match interceptor {
+        Some(interceptor) => CapabilitiesClient::with_interceptor(channel, interceptor),
+        None => CapabilitiesClient::new(channel),
+      },
+      metadata.instance_name.clone(),
+    );
 
     Ok(CommandRunner {
       underlying,
@@ -90,16 +185,59 @@ impl CommandRunner {
       executor,
       store,
       action_cache_client,
+      action_cache_throttle: RetryThrottle::new(),
       headers,
       platform,
       cache_read,
       cache_write,
+      strict_writes: false,
       eager_fetch,
       read_errors_counter: Arc::new(Mutex::new(BTreeMap::new())),
       write_errors_counter: Arc::new(Mutex::new(BTreeMap::new())),
+      endpoint,
+      backfill: None,
+      tail_tasks: TailTasks::new(),
     })
   }
 
+  ///
+  /// Backfills the remote cache with the results of processes which ran locally, rather than
+  /// writing them as they complete: see `CacheBackfill`.
+  ///
+  /// Any entries which were recorded by previous runs begin to be backfilled immediately.
+  ///
+  pub fn with_backfill(mut self, backfill: CacheBackfill) -> Self {
+    self.backfill = Some(Arc::new(backfill));
+    self.ensure_backfilling();
+    self
+  }
+
+  ///
+  /// Completes writes to the remote cache before returning results, and fails processes whose
+  /// results could not be written, rather than only logging failures. This is useful for jobs
+  /// whose purpose is to seed the cache, where broken writes should not go unnoticed.
+  ///
+  pub fn with_strict_writes(mut self, strict_writes: bool) -> Self {
+    self.strict_writes = strict_writes;
+    self
+  }
+
+  ///
+  /// Spawns writes to the remote cache as the given TailTasks, so that they may be awaited before
+  /// the process exits.
+  ///
+  pub fn with_tail_tasks(mut self, tail_tasks: TailTasks) -> Self {
+    self.tail_tasks = tail_tasks;
+    self
+  }
+
+  ///
+  /// The endpoint of the action cache, which may be warmed up before it is first used.
+  ///
+  pub fn endpoint(&self) -> RemoteEndpoint {
+    self.endpoint.clone()
+  }
+
   /// Create a REAPI `Tree` protobuf for an output directory by traversing down from a Pants
   /// merged final output directory to find the specific path to extract. (REAPI requires
   /// output directories to be stored as `Tree` protos that contain all of the `Directory`
@@ -278,7 +416,27 @@ impl CommandRunner {
     digests.insert(result.stdout_digest);
     digests.insert(result.stderr_digest);
 
-    for output_directory in &command.output_directories {
+    // Output paths may be either files or directories, depending on what the process created.
+    let mut output_files = command.output_files.clone();
+    let mut output_directories = command.output_directories.clone();
+    for output_path in &command.output_paths {
+      if output_files.contains(output_path) || output_directories.contains(output_path) {
+        continue;
+      }
+      let file_node = Self::extract_output_file(
+        result.output_directory,
+        RelativePath::new(output_path)?,
+        store,
+      )
+      .await?;
+      if file_node.is_some() {
+        output_files.push(output_path.clone());
+      } else {
+        output_directories.push(output_path.clone());
+      }
+    }
+
+    for output_directory in &output_directories {
       let tree = match Self::make_tree_for_output_directory(
         result.output_directory,
         RelativePath::new(output_directory).unwrap(),
@@ -301,7 +459,7 @@ impl CommandRunner {
         });
     }
 
-    for output_file in &command.output_files {
+    for output_file in &output_files {
       let file_node = match Self::extract_output_file(
         result.output_directory,
         RelativePath::new(output_file).unwrap(),
@@ -340,7 +498,7 @@ impl CommandRunner {
     command: &Command,
     action_digest: Digest,
     command_digest: Digest,
-  ) -> Result<(), String> {
+  ) -> Result<(), CacheWriteError> {
     // Upload the action (and related data, i.e. the embedded command and input files).
     // Assumption: The Action and related data has already been stored locally.
     with_workunit(
@@ -359,19 +517,39 @@ impl CommandRunner {
       ),
       |_, md| md,
     )
-    .await?;
+    .await
+    .map_err(CacheWriteError::new(CacheWriteErrorCategory::UploadInputs))?;
+
+    self
+      .write_action_result(metadata, command, result, action_digest)
+      .await
+  }
 
+  ///
+  /// Uploads the outputs of a result, and then the result itself, to the remote Action Cache.
+  ///
+  /// Assumption: The Action and related data has already been uploaded.
+  ///
+  async fn write_action_result(
+    &self,
+    metadata: &ProcessMetadata,
+    command: &Command,
+    result: &FallibleProcessResultWithPlatform,
+    action_digest: Digest,
+  ) -> Result<(), CacheWriteError> {
     // Create an ActionResult from the process result.
     let (action_result, digests_for_action_result) = self
       .make_action_result(command, result, &self.store)
-      .await?;
+      .await
+      .map_err(CacheWriteError::new(CacheWriteErrorCategory::UploadOutputs))?;
 
     // Ensure that all digests referenced by directly and indirectly by the ActionResult
     // have been uploaded to the remote cache.
     self
       .store
       .ensure_remote_has_recursive(digests_for_action_result)
-      .await?;
+      .await
+      .map_err(CacheWriteError::new(CacheWriteErrorCategory::UploadOutputs))?;
 
     let update_action_cache_request = remexec::UpdateActionResultRequest {
       instance_name: metadata
@@ -384,14 +562,121 @@ impl CommandRunner {
       ..remexec::UpdateActionResultRequest::default()
     };
 
-    let mut client = self.action_cache_client.as_ref().clone();
-    client
-      .update_action_result(update_action_cache_request)
-      .await
-      .map_err(crate::remote::rpcerror_to_string)?;
+    retry_call(
+      &self.action_cache_throttle,
+      crate::remote::ACTION_CACHE_RPC_ATTEMPTS,
+      || {
+        let mut client = self.action_cache_client.as_ref().clone();
+        let request = update_action_cache_request.clone();
+        async move { client.update_action_result(request).await }
+      },
+    )
+    .await
+    .map_err(|status| CacheWriteError {
+      category: CacheWriteErrorCategory::for_status(&status),
+      message: crate::remote::rpcerror_to_string(status),
+    })?;
 
     Ok(())
   }
+
+  ///
+  /// Spawns a task to drain the backfill journal, unless one is already running.
+  ///
+  fn ensure_backfilling(&self) {
+    let backfill = match self.backfill {
+      Some(ref backfill) if backfill.start_draining() => backfill.clone(),
+      _ => return,
+    };
+    let command_runner = self.clone();
+    let _join = self.executor.spawn(async move {
+      // The reads of a backfill should never delay reads which the user is waiting on.
+      let backfilled = with_request_priority(
+        RequestPriority::Background,
+        command_runner.drain_backfill(&backfill),
+      )
+      .await;
+      // NB: An entry which is recorded after the final scan of the journal but before this point
+      // will be backfilled when the next entry is recorded (or by the next run).
+      backfill.finish_draining();
+      match backfilled {
+        Ok(0) => (),
+        Ok(count) => log::debug!("Backfilled {} entries to the remote cache.", count),
+        Err(e) => log::debug!("Failed to backfill the remote cache: {}", e),
+      }
+    });
+  }
+
+  ///
+  /// Uploads the entries of the backfill journal (including entries which are recorded while it is
+  /// draining), one at a time, and returns the number which were uploaded. Entries which fail to
+  /// upload are retried by later drains, until they expire.
+  ///
+  async fn drain_backfill(&self, backfill: &CacheBackfill) -> Result<usize, String> {
+    let mut attempted = HashSet::new();
+    let mut backfilled = 0;
+    loop {
+      let entries = backfill.pending(&attempted).await?;
+      if entries.is_empty() {
+        return Ok(backfilled);
+      }
+      for entry in entries {
+        let action_fingerprint = entry.action_digest.hash;
+        attempted.insert(action_fingerprint);
+        match self.backfill_entry(entry).await {
+          Ok(()) => {
+            backfill.remove(action_fingerprint).await?;
+            backfilled += 1;
+          }
+          Err(e) => log::debug!(
+            "Failed to backfill {:?} to the remote cache: {}",
+            action_fingerprint,
+            e
+          ),
+        }
+        tokio::time::sleep(backfill.interval()).await;
+      }
+    }
+  }
+
+  async fn backfill_entry(&self, entry: BackfillEntry) -> Result<(), String> {
+    let action: Action = self.load_proto(entry.action_digest).await?;
+    let command_digest = require_digest(action.command_digest.as_ref())?;
+    let input_root_digest = require_digest(action.input_root_digest.as_ref())?;
+    let command: Command = self.load_proto(command_digest).await?;
+    let result = crate::remote::populate_fallible_execution_result(
+      self.store.clone(),
+      &entry.action_result,
+      entry.platform,
+      true,
+    )
+    .await?;
+
+    crate::remote::ensure_action_uploaded(
+      &self.store,
+      command_digest,
+      entry.action_digest,
+      input_root_digest,
+    )
+    .await?;
+    self
+      .write_action_result(&self.metadata, &command, &result, entry.action_digest)
+      .await
+      .map_err(|e| e.to_string())
+  }
+
+  async fn load_proto<P: Message + Default + Send + 'static>(
+    &self,
+    digest: Digest,
+  ) -> Result<P, String> {
+    self
+      .store
+      .load_file_bytes_with(digest, |bytes| P::decode(bytes))
+      .await?
+      .ok_or_else(|| format!("{:?} was not present in the local store", digest))?
+      .0
+      .map_err(|e| format!("Invalid protobuf for {:?}: {:?}", digest, e))
+  }
 }
 
 #[async_trait]
@@ -436,14 +721,21 @@ impl crate::CommandRunner for CommandRunner {
             desc: Some(format!("check action cache for {:?}", action_digest)),
             ..WorkunitMetadata::default()
           },
-          crate::remote::check_action_cache(
-            action_digest,
-            &self.metadata,
-            self.platform,
-            &context,
-            self.action_cache_client.clone(),
-            self.store.clone(),
-            self.eager_fetch,
+          // The lookup is speculative (since the process is also running locally), so its reads
+          // should not delay reads which the user is waiting on.
+          with_request_priority(
+            RequestPriority::Background,
+            crate::remote::check_action_cache(
+              action_digest,
+              &self.metadata,
+              self.platform,
+              &context,
+              &request.tags,
+              self.action_cache_client.clone(),
+              &self.action_cache_throttle,
+              self.store.clone(),
+              self.eager_fetch,
+            ),
           ),
           |_, md| md,
         )
@@ -513,11 +805,14 @@ impl crate::CommandRunner for CommandRunner {
       local_execution_future.await?
     };
 
-    if result.exit_code == 0 && self.cache_write {
+    // NB: The result of a process which ran as part of a batch is not cached: see
+    // `ProcessResultMetadata::batched`.
+    let cacheable = result.exit_code == 0 && !result.metadata.batched;
+    if cacheable && self.cache_write {
+      let description = request.description.clone();
       let command_runner = self.clone();
       let result = result.clone();
       let context2 = context.clone();
-      // NB: We use `TaskExecutor::spawn` instead of `tokio::spawn` to ensure logging still works.
       let cache_write_future = async move {
         context2
           .workunit_store
@@ -536,10 +831,10 @@ impl crate::CommandRunner for CommandRunner {
         context2
           .workunit_store
           .increment_counter(Metric::RemoteCacheWriteFinished, 1);
-        if let Err(err) = write_result {
+        if let Err(ref err) = write_result {
           let err_count = {
             let mut errors_counter = command_runner.write_errors_counter.lock();
-            let count = errors_counter.entry(err.clone()).or_insert(0);
+            let count = errors_counter.entry(err.message.clone()).or_insert(0);
             *count += 1;
             *count
           };
@@ -556,10 +851,11 @@ impl crate::CommandRunner for CommandRunner {
             .workunit_store
             .increment_counter(Metric::RemoteCacheWriteErrors, 1);
         };
+        write_result
       }
       .boxed();
 
-      let _write_join = self.executor.spawn(with_workunit(
+      let cache_write_future = with_workunit(
         context.workunit_store,
         "remote_cache_write".to_owned(),
         WorkunitMetadata {
@@ -567,8 +863,55 @@ impl crate::CommandRunner for CommandRunner {
           ..WorkunitMetadata::default()
         },
         cache_write_future,
-        |_, md| md,
-      ));
+        |write_result, md| match write_result {
+          Ok(()) => md,
+          // A failed write is reported at a visible level, with the category of the failure.
+          Err(err) => {
+            let mut user_metadata = md.user_metadata;
+            user_metadata.push((
+              "remote_cache_write_error_category".to_owned(),
+              UserMetadataItem::ImmediateString(err.category.as_str().to_owned()),
+            ));
+            user_metadata.push((
+              "remote_cache_write_error".to_owned(),
+              UserMetadataItem::ImmediateString(err.message.clone()),
+            ));
+            WorkunitMetadata {
+              level: Level::Warn,
+              desc: Some(format!("Failed to write to remote cache: {}", err)),
+              user_metadata,
+              ..md
+            }
+          }
+        },
+      );
+
+      if self.strict_writes {
+        cache_write_future.await.map_err(|err| {
+          format!(
+            "Failed to write the result of `{}` to the remote cache, and \
+             `--remote-cache-write-strict` is set: {}",
+            description, err
+          )
+        })?;
+      } else {
+        // NB: We use `TaskExecutor::spawn` instead of `tokio::spawn` to ensure logging still works.
+        self.tail_tasks.spawn_on(
+          "remote cache write",
+          &self.executor,
+          cache_write_future.map(|_| ()),
+        );
+      }
+    } else if cacheable {
+      if let Some(ref backfill) = self.backfill {
+        match backfill.record(action_digest, &result).await {
+          Ok(()) => self.ensure_backfilling(),
+          Err(err) => log::warn!(
+            "Failed to record result to backfill to the remote cache: {}",
+            err
+          ),
+        }
+      }
     }
 
     Ok(result)
@@ -12,12 +12,14 @@ use hashing::{Digest, EMPTY_DIGEST};
 use maplit::hashset;
 use mock::{StubActionCache, StubCAS};
 use remexec::ActionResult;
+use sharded_lmdb::{ShardedLmdb, DEFAULT_LEASE_TIME};
 use store::Store;
 use tempfile::TempDir;
 use testutil::data::{TestData, TestDirectory, TestTree};
 use tokio::time::sleep;
 use workunit_store::WorkunitStore;
 
+use crate::cache_backfill::CacheBackfill;
 use crate::remote::{ensure_action_stored_locally, make_execute_request};
 use crate::{
   CommandRunner as CommandRunnerTrait, Context, FallibleProcessResultWithPlatform,
@@ -91,8 +93,12 @@ impl StoreSetup {
         None,
         BTreeMap::new(),
         10 * 1024 * 1024,
+        None,
         Duration::from_secs(1),
         1,
+        None,
+        None,
+        None,
       )
       .unwrap();
     StoreSetup {
@@ -352,6 +358,47 @@ async fn cache_write_not_for_failures() {
   assert!(action_cache.action_map.lock().is_empty());
 }
 
+/// Failed cache writes are only logged, unless writes are strict, in which case they fail the
+/// process.
+#[tokio::test]
+async fn cache_write_strict() {
+  WorkunitStore::setup_for_tests();
+  let store_setup = StoreSetup::new();
+  let action_cache = StubActionCache::new().unwrap();
+  action_cache.always_errors.store(true, Ordering::SeqCst);
+  let (process, _action_digest) = create_process(&store_setup.store).await;
+
+  let run_process = |strict_writes: bool| {
+    let (local_runner, _) = create_local_runner(0, 0);
+    let cache_runner = crate::remote_cache::CommandRunner::new(
+      Arc::new(*local_runner),
+      ProcessMetadata::default(),
+      store_setup.executor.clone(),
+      store_setup.store.clone(),
+      &action_cache.address(),
+      None,
+      BTreeMap::default(),
+      Platform::current().unwrap(),
+      true,
+      true,
+      false,
+    )
+    .unwrap()
+    .with_strict_writes(strict_writes);
+    let process = process.clone();
+    async move { cache_runner.run(process.into(), Context::default()).await }
+  };
+
+  assert_eq!(run_process(false).await.unwrap().exit_code, 0);
+  let err = run_process(true).await.unwrap_err();
+  assert!(
+    err.contains("`--remote-cache-write-strict` is set") && err.contains("(unavailable)"),
+    "Bad error message: {}",
+    err
+  );
+  assert!(action_cache.action_map.lock().is_empty());
+}
+
 /// Cache writes should be async and not block the CommandRunner from returning.
 #[tokio::test]
 async fn cache_write_does_not_block() {
@@ -388,6 +435,72 @@ async fn cache_write_does_not_block() {
   );
 }
 
+#[tokio::test]
+async fn cache_backfill() {
+  WorkunitStore::setup_for_tests();
+  let store_setup = StoreSetup::new();
+  let (local_runner, local_runner_call_counter) = create_local_runner(0, 100);
+  let action_cache = StubActionCache::new().unwrap();
+  let journal_dir = TempDir::new().unwrap();
+  let journal = ShardedLmdb::new(
+    journal_dir.path().to_owned(),
+    10_000_000,
+    store_setup.executor.clone(),
+    DEFAULT_LEASE_TIME,
+    1,
+  )
+  .unwrap();
+  let cache_runner = crate::remote_cache::CommandRunner::new(
+    local_runner.into(),
+    ProcessMetadata::default(),
+    store_setup.executor.clone(),
+    store_setup.store.clone(),
+    &action_cache.address(),
+    None,
+    BTreeMap::default(),
+    Platform::current().unwrap(),
+    true,
+    // Results are not written as they complete, but are instead backfilled.
+    false,
+    false,
+  )
+  .unwrap()
+  .with_backfill(CacheBackfill::new(
+    journal.clone(),
+    store_setup.store.clone(),
+    Duration::from_millis(10),
+  ));
+  let (process, action_digest) = create_process(&store_setup.store).await;
+
+  let local_result = cache_runner
+    .run(process.clone().into(), Context::default())
+    .await
+    .unwrap();
+  assert_eq!(local_result.exit_code, 0);
+  assert_eq!(local_runner_call_counter.load(Ordering::SeqCst), 1);
+
+  // Wait for the journal to drain.
+  sleep(Duration::from_secs(1)).await;
+  assert_eq!(
+    action_cache
+      .action_map
+      .lock()
+      .get(&action_digest.hash)
+      .unwrap()
+      .exit_code,
+    0
+  );
+  let pending = CacheBackfill::new(
+    journal,
+    store_setup.store.clone(),
+    Duration::from_millis(10),
+  )
+  .pending(&HashSet::new())
+  .await
+  .unwrap();
+  assert!(pending.is_empty());
+}
+
 #[tokio::test]
 async fn make_tree_from_directory() {
   let store_dir = TempDir::new().unwrap();
@@ -1,6 +1,7 @@
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::convert::TryInto;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
 use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
@@ -12,6 +13,7 @@ use maplit::{btreemap, hashset};
 use mock::execution_server::{ExpectedAPICall, MockOperation};
 use prost::Message;
 use remexec::ExecutedActionMetadata;
+use shell_quote::bash;
 use spectral::prelude::*;
 use spectral::{assert_that, string::StrAssertions};
 use store::Store;
@@ -20,10 +22,11 @@ use testutil::data::{TestData, TestDirectory, TestTree};
 use testutil::{owned_string_vec, relative_paths};
 use workunit_store::{WorkunitState, WorkunitStore};
 
-use crate::remote::{digest, CommandRunner, ExecutionError, OperationOrStatus};
+use crate::remote::{digest, CommandRunner, ExecutionError, ExecutionThrottle, OperationOrStatus};
 use crate::{
-  CommandRunner as CommandRunnerTrait, Context, FallibleProcessResultWithPlatform,
-  MultiPlatformProcess, Platform, Process, ProcessCacheScope, ProcessMetadata,
+  CommandRunner as CommandRunnerTrait, Context, DescriptionFields,
+  FallibleProcessResultWithPlatform, MultiPlatformProcess, OutputPlatform, Platform,
+  PlatformRequirements, Process, ProcessCacheScope, ProcessMetadata, ProcessTags, ResourceLimits,
 };
 use std::any::type_name;
 use std::io::Cursor;
@@ -67,23 +70,45 @@ async fn make_execute_request() {
   let input_directory = TestDirectory::containing_roland();
   let req = Process {
     argv: owned_string_vec(&["/bin/echo", "yo"]),
+    pipe_from_argv: None,
     env: vec![("SOME".to_owned(), "value".to_owned())]
       .into_iter()
       .collect(),
     working_directory: None,
+    create_working_directory: true,
     input_files: input_directory.digest(),
     // Intentionally poorly sorted:
     output_files: relative_paths(&["path/to/file", "other/file"]).collect(),
     output_directories: relative_paths(&["directory/name"]).collect(),
+    output_paths: BTreeSet::new(),
     timeout: None,
+    resource_limits: ResourceLimits::default(),
     description: "some description".to_owned(),
+    description_fields: DescriptionFields::default(),
+    tags: ProcessTags::default(),
     level: log::Level::Info,
     append_only_caches: BTreeMap::new(),
     jdk_home: None,
     platform_constraint: None,
+    platform_requirements: PlatformRequirements::default(),
+    output_platform: OutputPlatform::Execution,
     is_nailgunnable: false,
+    allocate_tty: false,
+    container_image: None,
+    remote_worker_key: None,
+    remote_affinity_key: None,
     execution_slot_variable: None,
+    leased_port_variables: vec![],
+    invocation_id_variable: None,
     cache_scope: ProcessCacheScope::Always,
+    discovered_inputs_depfile: None,
+    source_output_files: BTreeSet::new(),
+    version_probes: vec![],
+    version_probe_fingerprint: None,
+    output_scanners: vec![],
+    max_output_bytes: None,
+    batch_argv_prefix_len: None,
+    response_file: None,
   };
 
   let want_command = remexec::Command {
@@ -139,28 +164,317 @@ async fn make_execute_request() {
   );
 }
 
+#[tokio::test]
+async fn make_execute_request_with_pipe_from_argv() {
+  let req = Process::new(owned_string_vec(&["/usr/bin/wc", "-l"]))
+    .pipe_from_argv(owned_string_vec(&["generator", "--out", "some file"]));
+
+  let (_action, command, _execute_request) =
+    crate::remote::make_execute_request(&req, ProcessMetadata::default()).unwrap();
+
+  let quote = |arg: &str| String::from_utf8(bash::escape(arg)).unwrap();
+  assert_eq!(
+    command.arguments,
+    vec![
+      "/bin/bash".to_owned(),
+      "-o".to_owned(),
+      "pipefail".to_owned(),
+      "-c".to_owned(),
+      format!(
+        "{} {} {} | {} {}",
+        quote("./generator"),
+        quote("--out"),
+        quote("some file"),
+        quote("/usr/bin/wc"),
+        quote("-l"),
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn make_execute_request_with_version_probe_fingerprint() {
+  let req = Process::new(owned_string_vec(&["/usr/bin/cc", "main.c"]));
+  let mut probed_req = req.clone();
+  probed_req.version_probes = vec![owned_string_vec(&["/usr/bin/cc", "--version"])];
+  probed_req.version_probe_fingerprint = Some(TestData::roland().fingerprint());
+
+  let (_action, command, execute_request) =
+    crate::remote::make_execute_request(&probed_req, ProcessMetadata::default()).unwrap();
+  assert!(command
+    .environment_variables
+    .contains(&remexec::command::EnvironmentVariable {
+      name: crate::remote::CACHE_KEY_VERSION_PROBES_ENV_VAR_NAME.to_owned(),
+      value: TestData::roland().fingerprint().to_hex(),
+    }));
+
+  // The probe output contributes to the cache key.
+  let (_action, _command, unprobed_execute_request) =
+    crate::remote::make_execute_request(&req, ProcessMetadata::default()).unwrap();
+  assert_ne!(
+    execute_request.action_digest,
+    unprobed_execute_request.action_digest
+  );
+}
+
+#[tokio::test]
+async fn make_execute_request_with_allocate_tty() {
+  let req = Process::new(owned_string_vec(&["/usr/bin/pytest"]));
+  let mut tty_req = req.clone();
+  tty_req.allocate_tty = true;
+
+  let (_action, command, execute_request) =
+    crate::remote::make_execute_request(&tty_req, ProcessMetadata::default()).unwrap();
+  assert!(command
+    .environment_variables
+    .iter()
+    .any(|env| env.name == crate::remote::CACHE_KEY_TTY_ENV_VAR_NAME));
+
+  // Merged output differs from separate output, so a TTY contributes to the cache key.
+  let (_action, _command, no_tty_execute_request) =
+    crate::remote::make_execute_request(&req, ProcessMetadata::default()).unwrap();
+  assert_ne!(
+    execute_request.action_digest,
+    no_tty_execute_request.action_digest
+  );
+}
+
+#[tokio::test]
+async fn make_execute_request_with_resource_limits() {
+  let mut req = Process::new(owned_string_vec(&["/usr/bin/pytest"]));
+  req.resource_limits.cpu_seconds = Some(60);
+
+  let (_action, command, _execute_request) =
+    crate::remote::make_execute_request(&req, ProcessMetadata::default()).unwrap();
+  assert!(command
+    .environment_variables
+    .contains(&remexec::command::EnvironmentVariable {
+      name: crate::remote::CACHE_KEY_RESOURCE_LIMITS_ENV_VAR_NAME.to_owned(),
+      value: req.resource_limits.cache_key_value(),
+    }));
+}
+
+#[tokio::test]
+async fn make_execute_request_with_container_image() {
+  let mut req = Process::new(owned_string_vec(&["/usr/bin/python3", "main.py"]));
+  req.container_image = Some("docker://python:3.9-slim".to_owned());
+  let metadata = ProcessMetadata {
+    platform_properties: vec![
+      (
+        crate::remote::CONTAINER_IMAGE_PLATFORM_PROPERTY_NAME.to_owned(),
+        "docker://ubuntu:20.04".to_owned(),
+      ),
+      ("OSFamily".to_owned(), "linux".to_owned()),
+    ],
+    ..ProcessMetadata::default()
+  };
+
+  // The image of the process replaces the configured image.
+  let (_action, command, _execute_request) =
+    crate::remote::make_execute_request(&req, metadata).unwrap();
+  assert_eq!(
+    command.platform,
+    Some(remexec::Platform {
+      properties: vec![
+        remexec::platform::Property {
+          name: "OSFamily".to_owned(),
+          value: "linux".to_owned(),
+        },
+        remexec::platform::Property {
+          name: crate::remote::CONTAINER_IMAGE_PLATFORM_PROPERTY_NAME.to_owned(),
+          value: "docker://python:3.9-slim".to_owned(),
+        },
+      ],
+    })
+  );
+}
+
+#[tokio::test]
+async fn make_execute_request_with_remote_affinity_key() {
+  let mut req = Process::new(owned_string_vec(&["/usr/bin/javac", "Main.java"]));
+  req.remote_worker_key = Some("javac-11".to_owned());
+  req.remote_affinity_key = Some("src/java/example:lib".to_owned());
+
+  let (_action, command, _execute_request) =
+    crate::remote::make_execute_request(&req, ProcessMetadata::default()).unwrap();
+  assert_eq!(
+    command.platform,
+    Some(remexec::Platform {
+      properties: vec![
+        remexec::platform::Property {
+          name: crate::remote::AFFINITY_KEY_PLATFORM_PROPERTY_NAME.to_owned(),
+          value: "src/java/example:lib".to_owned(),
+        },
+        remexec::platform::Property {
+          name: crate::remote::PERSISTENT_WORKER_KEY_PLATFORM_PROPERTY_NAME.to_owned(),
+          value: "javac-11".to_owned(),
+        },
+      ],
+    })
+  );
+}
+
+#[tokio::test]
+async fn make_execute_request_with_remote_worker_key() {
+  let mut req = Process::new(owned_string_vec(&["/usr/bin/javac", "Main.java"]));
+  req.remote_worker_key = Some("javac-11".to_owned());
+
+  let (_action, command, execute_request) =
+    crate::remote::make_execute_request(&req, ProcessMetadata::default()).unwrap();
+  assert_eq!(
+    command.platform,
+    Some(remexec::Platform {
+      properties: vec![remexec::platform::Property {
+        name: crate::remote::PERSISTENT_WORKER_KEY_PLATFORM_PROPERTY_NAME.to_owned(),
+        value: "javac-11".to_owned(),
+      }],
+    })
+  );
+
+  // The worker key contributes to the cache key, so that results are only reused by processes
+  // which have affinity for the same worker.
+  let mut other_req = req.clone();
+  other_req.remote_worker_key = Some("javac-17".to_owned());
+  let (_action, _command, other_execute_request) =
+    crate::remote::make_execute_request(&other_req, ProcessMetadata::default()).unwrap();
+  assert_ne!(
+    execute_request.action_digest,
+    other_execute_request.action_digest
+  );
+}
+
+#[tokio::test]
+async fn make_execute_request_with_output_platform() {
+  let target_platform = |req: &Process| {
+    let (_action, command, _execute_request) =
+      crate::remote::make_execute_request(req, ProcessMetadata::default()).unwrap();
+    command
+      .environment_variables
+      .into_iter()
+      .find(|env| env.name == crate::remote::CACHE_KEY_TARGET_PLATFORM_ENV_VAR_NAME)
+      .unwrap()
+      .value
+  };
+
+  let mut req = Process::new(owned_string_vec(&["/usr/bin/javac", "Main.java"]));
+  assert_eq!(target_platform(&req), "none");
+  req.platform_constraint = Some(Platform::Darwin);
+  assert_eq!(target_platform(&req), "darwin");
+
+  // Platform independent outputs are shared by processes with any constraint.
+  req.output_platform = OutputPlatform::Independent;
+  assert_eq!(target_platform(&req), "any");
+  let mut linux_req = req.clone();
+  linux_req.platform_constraint = Some(Platform::Linux);
+  assert_eq!(
+    crate::remote::make_execute_request(&req, ProcessMetadata::default())
+      .unwrap()
+      .2
+      .action_digest,
+    crate::remote::make_execute_request(&linux_req, ProcessMetadata::default())
+      .unwrap()
+      .2
+      .action_digest,
+  );
+
+  // A cross compiled output is keyed by its target, rather than by where it runs.
+  req.output_platform = OutputPlatform::Target(Platform::Linux);
+  assert_eq!(target_platform(&req), "linux");
+}
+
+#[tokio::test]
+async fn make_execute_request_with_output_paths() {
+  let req = Process::new(owned_string_vec(&["/bin/echo", "yo"]))
+    .output_files(relative_paths(&["path/to/file"]).collect())
+    .output_directories(relative_paths(&["directory/name"]).collect())
+    .output_paths(relative_paths(&["either/file/or/dir", "path/to/file"]).collect());
+
+  let (_action, command, _execute_request) =
+    crate::remote::make_execute_request(&req, ProcessMetadata::default()).unwrap();
+  assert_eq!(
+    command.output_paths,
+    owned_string_vec(&["directory/name", "either/file/or/dir", "path/to/file"])
+  );
+  // The pre-v2.1 fields are retained for the outputs which were declared by type.
+  assert_eq!(command.output_files, owned_string_vec(&["path/to/file"]));
+  assert_eq!(
+    command.output_directories,
+    owned_string_vec(&["directory/name"])
+  );
+
+  // Output paths cannot be represented by the pre-v2.1 fields.
+  let err = crate::remote::make_execute_request_with_output_declaration(
+    &req,
+    ProcessMetadata::default(),
+    crate::remote::OutputDeclaration::Separate,
+  )
+  .unwrap_err();
+  assert!(err.contains("v2.1"), "Unexpected error: {}", err);
+
+  // And so they require a server which supports v2.1, while other processes do not.
+  let capabilities = remexec::ServerCapabilities {
+    high_api_version: Some(bazel_protos::gen::build::bazel::semver::SemVer {
+      major: 2,
+      minor: 1,
+      ..bazel_protos::gen::build::bazel::semver::SemVer::default()
+    }),
+    ..remexec::ServerCapabilities::default()
+  };
+  let pre_v2_1_capabilities = remexec::ServerCapabilities::default();
+  let unified = crate::remote::OutputDeclaration::for_process(&req);
+  assert_eq!(unified, crate::remote::OutputDeclaration::Unified);
+  assert!(unified.is_supported_by(&capabilities));
+  assert!(!unified.is_supported_by(&pre_v2_1_capabilities));
+  let separate =
+    crate::remote::OutputDeclaration::for_process(&Process::new(owned_string_vec(&["/bin/echo"])));
+  assert_eq!(separate, crate::remote::OutputDeclaration::Separate);
+  assert!(separate.is_supported_by(&pre_v2_1_capabilities));
+}
+
 #[tokio::test]
 async fn make_execute_request_with_instance_name() {
   let input_directory = TestDirectory::containing_roland();
   let req = Process {
     argv: owned_string_vec(&["/bin/echo", "yo"]),
+    pipe_from_argv: None,
     env: vec![("SOME".to_owned(), "value".to_owned())]
       .into_iter()
       .collect(),
     working_directory: None,
+    create_working_directory: true,
     input_files: input_directory.digest(),
     // Intentionally poorly sorted:
     output_files: relative_paths(&["path/to/file", "other/file"]).collect(),
     output_directories: relative_paths(&["directory/name"]).collect(),
+    output_paths: BTreeSet::new(),
     timeout: None,
+    resource_limits: ResourceLimits::default(),
     description: "some description".to_owned(),
+    description_fields: DescriptionFields::default(),
+    tags: ProcessTags::default(),
     level: log::Level::Info,
     append_only_caches: BTreeMap::new(),
     jdk_home: None,
     platform_constraint: None,
+    platform_requirements: PlatformRequirements::default(),
+    output_platform: OutputPlatform::Execution,
     is_nailgunnable: false,
+    allocate_tty: false,
+    container_image: None,
+    remote_worker_key: None,
+    remote_affinity_key: None,
     execution_slot_variable: None,
+    leased_port_variables: vec![],
+    invocation_id_variable: None,
     cache_scope: ProcessCacheScope::Always,
+    discovered_inputs_depfile: None,
+    source_output_files: BTreeSet::new(),
+    version_probes: vec![],
+    version_probe_fingerprint: None,
+    output_scanners: vec![],
+    max_output_bytes: None,
+    batch_argv_prefix_len: None,
+    response_file: None,
   };
 
   let want_command = remexec::Command {
@@ -234,23 +548,45 @@ async fn make_execute_request_with_cache_key_gen_version() {
   let input_directory = TestDirectory::containing_roland();
   let req = Process {
     argv: owned_string_vec(&["/bin/echo", "yo"]),
+    pipe_from_argv: None,
     env: vec![("SOME".to_owned(), "value".to_owned())]
       .into_iter()
       .collect(),
     working_directory: None,
+    create_working_directory: true,
     input_files: input_directory.digest(),
     // Intentionally poorly sorted:
     output_files: relative_paths(&["path/to/file", "other/file"]).collect(),
     output_directories: relative_paths(&["directory/name"]).collect(),
+    output_paths: BTreeSet::new(),
     timeout: None,
+    resource_limits: ResourceLimits::default(),
     description: "some description".to_owned(),
+    description_fields: DescriptionFields::default(),
+    tags: ProcessTags::default(),
     level: log::Level::Info,
     append_only_caches: BTreeMap::new(),
     jdk_home: None,
     platform_constraint: None,
+    platform_requirements: PlatformRequirements::default(),
+    output_platform: OutputPlatform::Execution,
     is_nailgunnable: false,
+    allocate_tty: false,
+    container_image: None,
+    remote_worker_key: None,
+    remote_affinity_key: None,
     execution_slot_variable: None,
+    leased_port_variables: vec![],
+    invocation_id_variable: None,
     cache_scope: ProcessCacheScope::Always,
+    discovered_inputs_depfile: None,
+    source_output_files: BTreeSet::new(),
+    version_probes: vec![],
+    version_probe_fingerprint: None,
+    output_scanners: vec![],
+    max_output_bytes: None,
+    batch_argv_prefix_len: None,
+    response_file: None,
   };
 
   let mut want_command = remexec::Command {
@@ -471,23 +807,45 @@ async fn make_execute_request_with_timeout() {
   let input_directory = TestDirectory::containing_roland();
   let req = Process {
     argv: owned_string_vec(&["/bin/echo", "yo"]),
+    pipe_from_argv: None,
     env: vec![("SOME".to_owned(), "value".to_owned())]
       .into_iter()
       .collect(),
     working_directory: None,
+    create_working_directory: true,
     input_files: input_directory.digest(),
     // Intentionally poorly sorted:
     output_files: relative_paths(&["path/to/file", "other/file"]).collect(),
     output_directories: relative_paths(&["directory/name"]).collect(),
+    output_paths: BTreeSet::new(),
     timeout: one_second(),
+    resource_limits: ResourceLimits::default(),
     description: "some description".to_owned(),
+    description_fields: DescriptionFields::default(),
+    tags: ProcessTags::default(),
     level: log::Level::Info,
     append_only_caches: BTreeMap::new(),
     jdk_home: None,
     platform_constraint: None,
+    platform_requirements: PlatformRequirements::default(),
+    output_platform: OutputPlatform::Execution,
     is_nailgunnable: false,
+    allocate_tty: false,
+    container_image: None,
+    remote_worker_key: None,
+    remote_affinity_key: None,
     execution_slot_variable: None,
+    leased_port_variables: vec![],
+    invocation_id_variable: None,
     cache_scope: ProcessCacheScope::Always,
+    discovered_inputs_depfile: None,
+    source_output_files: BTreeSet::new(),
+    version_probes: vec![],
+    version_probe_fingerprint: None,
+    output_scanners: vec![],
+    max_output_bytes: None,
+    batch_argv_prefix_len: None,
+    response_file: None,
   };
 
   let want_command = remexec::Command {
@@ -815,6 +1173,97 @@ async fn server_sending_triggering_timeout_with_deadline_exceeded() {
   assert!(result.stdout().contains("user timeout"));
 }
 
+#[tokio::test]
+async fn timeout_diagnosis_while_queued() {
+  WorkunitStore::setup_for_tests();
+
+  let execute_request = echo_foo_request();
+  let op_name = "gimme-foo".to_string();
+
+  let mock_server = {
+    let (action, _, execute_request) = crate::remote::make_execute_request(
+      &execute_request.clone().try_into().unwrap(),
+      ProcessMetadata::default(),
+    )
+    .unwrap();
+
+    let action_digest = digest(&action).unwrap();
+
+    mock::execution_server::TestServer::new(
+      mock::execution_server::MockExecution::new(vec![
+        ExpectedAPICall::GetActionResult {
+          action_digest,
+          response: Err(Status::not_found("".to_owned())),
+        },
+        ExpectedAPICall::Execute {
+          execute_request,
+          stream_responses: Ok(vec![
+            make_incomplete_operation_at_stage(&op_name, remexec::execution_stage::Value::Queued),
+            make_deadline_exceeded_operation(&op_name, None),
+          ]),
+        },
+      ]),
+      None,
+    )
+  };
+
+  let result = run_command_remote(mock_server.address(), execute_request)
+    .await
+    .expect("Should succeed, but with a failed process.");
+  assert_contains(result.stdout(), "user timeout");
+  assert_contains(result.stdout(), "never began executing");
+  assert_contains(result.stdout(), "short of capacity");
+}
+
+#[tokio::test]
+async fn timeout_diagnosis_while_executing() {
+  WorkunitStore::setup_for_tests();
+
+  let execute_request = echo_foo_request();
+  let op_name = "gimme-foo".to_string();
+
+  let mock_server = {
+    let (action, _, execute_request) = crate::remote::make_execute_request(
+      &execute_request.clone().try_into().unwrap(),
+      ProcessMetadata::default(),
+    )
+    .unwrap();
+
+    let action_digest = digest(&action).unwrap();
+
+    let mut deadline_exceeded = make_deadline_exceeded_operation(&op_name, Some("worker-7"));
+    deadline_exceeded.duration = Some(Duration::from_millis(500));
+
+    mock::execution_server::TestServer::new(
+      mock::execution_server::MockExecution::new(vec![
+        ExpectedAPICall::GetActionResult {
+          action_digest,
+          response: Err(Status::not_found("".to_owned())),
+        },
+        ExpectedAPICall::Execute {
+          execute_request,
+          stream_responses: Ok(vec![
+            make_incomplete_operation_at_stage(&op_name, remexec::execution_stage::Value::Queued),
+            make_incomplete_operation_at_stage(
+              &op_name,
+              remexec::execution_stage::Value::Executing,
+            ),
+            deadline_exceeded,
+          ]),
+        },
+      ]),
+      None,
+    )
+  };
+
+  let result = run_command_remote(mock_server.address(), execute_request)
+    .await
+    .expect("Should succeed, but with a failed process.");
+  assert_contains(result.stdout(), "user timeout");
+  assert_contains(result.stdout(), "on worker `worker-7`");
+  assert_contains(result.stdout(), "consider raising its timeout");
+}
+
 #[tokio::test]
 async fn sends_headers() {
   WorkunitStore::setup_for_tests();
@@ -864,8 +1313,12 @@ async fn sends_headers() {
       None,
       BTreeMap::new(),
       10 * 1024 * 1024,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap();
 
@@ -882,11 +1335,14 @@ async fn sends_headers() {
     Platform::Linux,
     OVERALL_DEADLINE_SECS,
     RETRY_INTERVAL,
+    None,
+    false,
   )
   .unwrap();
   let context = Context {
     workunit_store: WorkunitStore::new(false),
     build_id: String::from("marmosets"),
+    invocation_id: String::from("01F8MECHZX3TBDSZ7XRADM79XV"),
   };
   command_runner
     .run(execute_request, context)
@@ -909,7 +1365,8 @@ async fn sends_headers() {
         .expect("Failed to parse metadata proto");
 
       assert_eq!(proto.tool_details.map(|x| x.tool_name).unwrap(), "pants");
-      assert_eq!(proto.tool_invocation_id, "marmosets");
+      assert_eq!(proto.tool_invocation_id, "01F8MECHZX3TBDSZ7XRADM79XV");
+      assert_eq!(proto.correlated_invocations_id, "marmosets");
     }
 
     assert_eq!(headers.get("cat").unwrap().to_str().unwrap(), "roland");
@@ -1059,8 +1516,12 @@ async fn ensure_inline_stdio_is_stored() {
       None,
       BTreeMap::new(),
       10 * 1024 * 1024,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap();
 
@@ -1074,6 +1535,8 @@ async fn ensure_inline_stdio_is_stored() {
     Platform::Linux,
     OVERALL_DEADLINE_SECS,
     RETRY_INTERVAL,
+    None,
+    false,
   )
   .unwrap();
 
@@ -1436,8 +1899,125 @@ async fn execute_missing_file_uploads_if_known() {
       None,
       BTreeMap::new(),
       10 * 1024 * 1024,
+      None,
+      Duration::from_secs(1),
+      1,
+      None,
+      None,
+      None,
+    )
+    .unwrap();
+  store
+    .store_file_bytes(roland.bytes(), false)
+    .await
+    .expect("Saving file bytes to store");
+  store
+    .record_directory(&TestDirectory::containing_roland().directory(), false)
+    .await
+    .expect("Saving directory bytes to store");
+  let command_runner = CommandRunner::new(
+    &mock_server.address(),
+    &mock_server.address(),
+    ProcessMetadata::default(),
+    None,
+    BTreeMap::new(),
+    store.clone(),
+    Platform::Linux,
+    OVERALL_DEADLINE_SECS,
+    RETRY_INTERVAL,
+    None,
+    false,
+  )
+  .unwrap();
+
+  let result = run_cmd_runner(cat_roland_request(), command_runner, store)
+    .await
+    .unwrap();
+
+  assert_eq!(result.stdout_bytes, roland.bytes());
+  assert_eq!(result.stderr_bytes, "".as_bytes());
+  assert_eq!(result.original.exit_code, 0);
+  assert_eq!(result.original.platform, Platform::Linux);
+
+  {
+    let blobs = cas.blobs.lock();
+    assert_eq!(blobs.get(&roland.fingerprint()), Some(&roland.bytes()));
+  }
+}
+
+#[tokio::test]
+async fn execute_missing_file_waits_for_pipelined_upload() {
+  WorkunitStore::setup_for_tests();
+  let runtime = task_executor::Executor::new();
+
+  let roland = TestData::roland();
+
+  let mock_server = {
+    let op_name = "cat".to_owned();
+
+    let (action, _, execute_request) = crate::remote::make_execute_request(
+      &cat_roland_request().try_into().unwrap(),
+      ProcessMetadata::default(),
+    )
+    .unwrap();
+
+    let action_digest = digest(&action).unwrap();
+
+    mock::execution_server::TestServer::new(
+      mock::execution_server::MockExecution::new(vec![
+        ExpectedAPICall::GetActionResult {
+          action_digest,
+          response: Err(Status::not_found("".to_owned())),
+        },
+        ExpectedAPICall::Execute {
+          execute_request,
+          stream_responses: Ok(vec![
+            make_incomplete_operation(&op_name),
+            make_precondition_failure_operation(vec![missing_preconditionfailure_violation(
+              &roland.digest(),
+            )]),
+          ]),
+        },
+        ExpectedAPICall::Execute {
+          execute_request: crate::remote::make_execute_request(
+            &cat_roland_request().try_into().unwrap(),
+            ProcessMetadata::default(),
+          )
+          .unwrap()
+          .2,
+          stream_responses: Ok(vec![
+            make_incomplete_operation(&op_name),
+            make_successful_operation(
+              "cat2",
+              StdoutType::Raw(roland.string()),
+              StderrType::Raw("".to_owned()),
+              0,
+            ),
+          ]),
+        },
+      ]),
+      None,
+    )
+  };
+
+  let store_dir = TempDir::new().unwrap();
+  let cas = mock::StubCAS::builder()
+    .directory(&TestDirectory::containing_roland())
+    .build();
+  let store = Store::local_only(runtime.clone(), store_dir)
+    .unwrap()
+    .into_with_remote(
+      &cas.address(),
+      None,
+      None,
+      BTreeMap::new(),
+      10 * 1024 * 1024,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap();
   store
@@ -1458,9 +2038,13 @@ async fn execute_missing_file_uploads_if_known() {
     Platform::Linux,
     OVERALL_DEADLINE_SECS,
     RETRY_INTERVAL,
+    None,
+    true,
   )
   .unwrap();
 
+  // The upload of inputs runs concurrently with the first Execute request: when the server reports
+  // that an input is missing, the retry should wait for the upload to complete.
   let result = run_cmd_runner(cat_roland_request(), command_runner, store)
     .await
     .unwrap();
@@ -1511,8 +2095,12 @@ async fn execute_missing_file_errors_if_unknown() {
       None,
       BTreeMap::new(),
       10 * 1024 * 1024,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap();
 
@@ -1526,6 +2114,8 @@ async fn execute_missing_file_errors_if_unknown() {
     Platform::Linux,
     OVERALL_DEADLINE_SECS,
     RETRY_INTERVAL,
+    None,
+    false,
   )
   .unwrap();
 
@@ -1714,6 +2304,136 @@ async fn extract_execute_response_other_status() {
   };
 }
 
+#[tokio::test]
+async fn extract_execute_response_resource_exhausted() {
+  let operation = Operation {
+    name: "cat".to_owned(),
+    done: true,
+    result: Some(
+      bazel_protos::gen::google::longrunning::operation::Result::Response(make_any_proto(
+        &remexec::ExecuteResponse {
+          status: Some(bazel_protos::gen::google::rpc::Status {
+            code: Code::ResourceExhausted as i32,
+            message: "no workers available".to_owned(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        },
+        "bazel_protos::gen::",
+      )),
+    ),
+    ..Default::default()
+  };
+
+  assert_eq!(
+    extract_execute_response(operation, Platform::Linux).await,
+    Err(ExecutionError::ResourceExhausted(
+      "no workers available".to_owned(),
+      None
+    ))
+  );
+}
+
+#[tokio::test]
+async fn extract_execute_response_honors_retry_info() {
+  let operation = Operation {
+    name: "cat".to_owned(),
+    done: true,
+    result: Some(
+      bazel_protos::gen::google::longrunning::operation::Result::Response(make_any_proto(
+        &remexec::ExecuteResponse {
+          status: Some(bazel_protos::gen::google::rpc::Status {
+            code: Code::Unavailable as i32,
+            message: "slow down".to_owned(),
+            details: vec![make_any_proto(
+              &bazel_protos::gen::google::rpc::RetryInfo {
+                retry_delay: Some(Duration::from_secs(3).into()),
+              },
+              "bazel_protos::gen::",
+            )],
+          }),
+          ..Default::default()
+        },
+        "bazel_protos::gen::",
+      )),
+    ),
+    ..Default::default()
+  };
+
+  // A server which suggests a retry delay is asking us to back off, rather than to retry
+  // immediately.
+  assert_eq!(
+    extract_execute_response(operation, Platform::Linux).await,
+    Err(ExecutionError::ResourceExhausted(
+      "slow down".to_owned(),
+      Some(Duration::from_secs(3))
+    ))
+  );
+}
+
+#[test]
+fn execution_throttle_backs_off_and_recovers() {
+  let throttle = Arc::new(ExecutionThrottle::default());
+  let mut permits: Vec<_> = (0..8)
+    .map(|_| ExecutionThrottle::try_acquire(&throttle).unwrap())
+    .collect();
+  assert_eq!(throttle.limit(), None);
+
+  // Being out of capacity halves the limit each time, but never below one.
+  throttle.resource_exhausted();
+  assert_eq!(throttle.limit(), Some(4));
+  throttle.resource_exhausted();
+  throttle.resource_exhausted();
+  throttle.resource_exhausted();
+  assert_eq!(throttle.limit(), Some(1));
+
+  // No further executions may start until we are back under the limit.
+  permits.truncate(1);
+  assert_eq!(throttle.in_flight(), 1);
+  assert!(ExecutionThrottle::try_acquire(&throttle).is_none());
+
+  // Each success raises the limit by one, until the original concurrency is restored.
+  throttle.succeeded();
+  assert_eq!(throttle.limit(), Some(2));
+  assert!(ExecutionThrottle::try_acquire(&throttle).is_some());
+  for _ in 0..5 {
+    throttle.succeeded();
+  }
+  assert_eq!(throttle.limit(), Some(7));
+  throttle.succeeded();
+  assert_eq!(throttle.limit(), None);
+}
+
+#[test]
+fn execution_throttle_pauses_for_retry_delay() {
+  let throttle = Arc::new(ExecutionThrottle::default());
+  throttle.pause_for(Duration::from_secs(60));
+  assert!(ExecutionThrottle::try_acquire(&throttle).is_none());
+
+  let throttle = Arc::new(ExecutionThrottle::default());
+  throttle.pause_for(Duration::from_millis(0));
+  assert!(ExecutionThrottle::try_acquire(&throttle).is_some());
+}
+
+#[tokio::test]
+async fn execution_throttle_acquire_waits_for_release() {
+  let throttle = Arc::new(ExecutionThrottle::default());
+  let permit = ExecutionThrottle::try_acquire(&throttle).unwrap();
+  throttle.resource_exhausted();
+  assert_eq!(throttle.limit(), Some(1));
+
+  let waiting = tokio::spawn(ExecutionThrottle::acquire(throttle.clone()));
+  tokio::time::sleep(Duration::from_millis(50)).await;
+  assert_eq!(throttle.in_flight(), 1);
+
+  std::mem::drop(permit);
+  let _permit = tokio::time::timeout(Duration::from_secs(5), waiting)
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(throttle.in_flight(), 1);
+}
+
 #[tokio::test]
 async fn remote_workunits_are_stored() {
   let mut workunit_store = WorkunitStore::setup_for_tests();
@@ -2005,6 +2725,17 @@ async fn extract_output_files_from_response_no_prefix() {
   )
 }
 
+#[tokio::test]
+async fn offline_runner_fails_fast() {
+  let runner = crate::remote::OfflineCommandRunner::new(Platform::Linux);
+  let request = echo_foo_request();
+  assert!(runner.extract_compatible_request(&request).is_some());
+
+  let err = runner.run(request, Context::default()).await.unwrap_err();
+  assert_that(&err).contains("Cannot remotely execute echo a foo");
+  assert_that(&err).contains("`--offline`");
+}
+
 pub fn echo_foo_request() -> MultiPlatformProcess {
   let mut req = Process::new(owned_string_vec(&["/bin/echo", "-n", "foo"]));
   req.timeout = Some(Duration::from_millis(5000));
@@ -2021,6 +2752,54 @@ pub(crate) fn make_incomplete_operation(operation_name: &str) -> MockOperation {
   MockOperation::new(op)
 }
 
+fn make_incomplete_operation_at_stage(
+  operation_name: &str,
+  stage: remexec::execution_stage::Value,
+) -> MockOperation {
+  let op = Operation {
+    name: operation_name.to_string(),
+    done: false,
+    metadata: Some(make_any_proto(
+      &remexec::ExecuteOperationMetadata {
+        stage: stage as i32,
+        ..Default::default()
+      },
+      "bazel_protos::gen::",
+    )),
+    ..Default::default()
+  };
+  MockOperation::new(op)
+}
+
+fn make_deadline_exceeded_operation(operation_name: &str, worker: Option<&str>) -> MockOperation {
+  let result = worker.map(|worker| remexec::ActionResult {
+    execution_metadata: Some(ExecutedActionMetadata {
+      worker: worker.to_owned(),
+      ..Default::default()
+    }),
+    ..Default::default()
+  });
+  let op = Operation {
+    name: operation_name.to_string(),
+    done: true,
+    result: Some(
+      bazel_protos::gen::google::longrunning::operation::Result::Response(make_any_proto(
+        &remexec::ExecuteResponse {
+          status: Some(bazel_protos::gen::google::rpc::Status {
+            code: Code::DeadlineExceeded as i32,
+            ..Default::default()
+          }),
+          result,
+          ..Default::default()
+        },
+        "bazel_protos::gen::",
+      )),
+    ),
+    ..Default::default()
+  };
+  MockOperation::new(op)
+}
+
 pub(crate) fn make_retryable_operation_failure() -> MockOperation {
   let status = bazel_protos::gen::google::rpc::Status {
     code: Code::Aborted as i32,
@@ -2225,6 +3004,8 @@ fn create_command_runner(
     platform,
     OVERALL_DEADLINE_SECS,
     RETRY_INTERVAL,
+    None,
+    false,
   )
   .expect("Failed to make command runner");
   (command_runner, store)
@@ -2272,8 +3053,12 @@ pub(crate) fn make_store(
       None,
       BTreeMap::new(),
       10 * 1024 * 1024,
+      None,
       Duration::from_secs(1),
       1,
+      None,
+      None,
+      None,
     )
     .unwrap()
 }
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str;
+
+use bytes::Bytes;
+use fs::RelativePath;
+use futures::future::{self, FutureExt};
+use hashing::Digest;
+use shell_quote::bash;
+use store::Store;
+
+use crate::describe::describe_action;
+use crate::{NamedCaches, Platform, Process, ProcessMetadata, Redactor};
+
+/// The name of the archive file in the digest created by `store_repro_bundle`.
+pub const REPRO_BUNDLE_FILE_NAME: &str = "repro.tar";
+
+/// The directory within the archive which all of its entries are placed under.
+const BUNDLE_ROOT: &str = "repro";
+
+///
+/// Creates a "repro bundle" for the given Process: a tar archive which contains its input files
+/// (under `repro/sandbox`) and a `repro/__run.sh` script which runs the process in them, along with
+/// its environment, the platform that it was described on, its expected outputs, and the REAPI
+/// Action which would be used to execute it. Users can attach a bundle to a bug report so that a
+/// failing process can be reproduced byte-for-byte on another machine.
+///
+/// The archive is deterministic: entries are sorted, and have fixed owners and mtimes. Configured
+/// secrets are redacted from every generated file (but not from the input files).
+///
+/// NB: The content of named caches is not included in the bundle.
+///
+pub async fn repro_bundle(
+  store: &Store,
+  process: &Process,
+  metadata: ProcessMetadata,
+  named_caches: &NamedCaches,
+  redactor: &Redactor,
+) -> Result<Bytes, String> {
+  let description = describe_action(process, metadata, named_caches)?;
+
+  let mut directories = store
+    .walk(process.input_files, |_, path_so_far, _, _| {
+      future::ok(path_so_far.clone()).boxed()
+    })
+    .await?;
+  directories.sort();
+  let files = store.contents_for_directory(process.input_files).await?;
+
+  let mut platform = String::new();
+  let mut expected_outputs = String::new();
+  render_platform(&mut platform, process)
+    .and_then(|()| render_expected_outputs(&mut expected_outputs, process))
+    .map_err(|e| format!("Failed to render repro bundle: {}", e))?;
+
+  let redact = |content: &str| {
+    redactor
+      .redact(&process.env, content.as_bytes())
+      .into_owned()
+  };
+  let root = PathBuf::from(BUNDLE_ROOT);
+  let sandbox = root.join("sandbox");
+
+  let mut builder = tar::Builder::new(Vec::new());
+  append_directory(&mut builder, &root)?;
+  for directory in directories {
+    append_directory(&mut builder, &sandbox.join(directory))?;
+  }
+  for file in files {
+    append_file(
+      &mut builder,
+      &sandbox.join(&file.path),
+      &file.content,
+      file.is_executable,
+    )?;
+  }
+  append_file(
+    &mut builder,
+    &root.join("__run.sh"),
+    &redact(&run_script(process)?),
+    true,
+  )?;
+  append_file(
+    &mut builder,
+    &root.join("env.txt"),
+    &redact(&render_env(&process.env)),
+    false,
+  )?;
+  append_file(
+    &mut builder,
+    &root.join("platform.txt"),
+    &redact(&platform),
+    false,
+  )?;
+  append_file(
+    &mut builder,
+    &root.join("expected_outputs.txt"),
+    expected_outputs.as_bytes(),
+    false,
+  )?;
+  append_file(
+    &mut builder,
+    &root.join("action.txt"),
+    &redact(&description.rendered),
+    false,
+  )?;
+
+  builder
+    .into_inner()
+    .map(Bytes::from)
+    .map_err(|e| format!("Failed to create repro bundle: {}", e))
+}
+
+///
+/// Creates a repro bundle for the given Process (see `repro_bundle`), and stores it in a Directory
+/// which contains only the archive, returning the digest of the Directory.
+///
+pub async fn store_repro_bundle(
+  store: &Store,
+  process: &Process,
+  metadata: ProcessMetadata,
+  named_caches: &NamedCaches,
+  redactor: &Redactor,
+) -> Result<Digest, String> {
+  let bundle = repro_bundle(store, process, metadata, named_caches, redactor).await?;
+  let file_digest = store.store_file_bytes(bundle, true).await?;
+  let snapshot = store
+    .snapshot_of_one_file(
+      RelativePath::new(REPRO_BUNDLE_FILE_NAME)?,
+      file_digest,
+      false,
+    )
+    .await?;
+  Ok(snapshot.digest)
+}
+
+fn append_directory(builder: &mut tar::Builder<Vec<u8>>, path: &Path) -> Result<(), String> {
+  let mut header = header(tar::EntryType::Directory, 0o755, 0);
+  builder
+    .append_data(&mut header, path, std::io::empty())
+    .map_err(|e| format!("Failed to add {} to repro bundle: {}", path.display(), e))
+}
+
+fn append_file(
+  builder: &mut tar::Builder<Vec<u8>>,
+  path: &Path,
+  content: &[u8],
+  is_executable: bool,
+) -> Result<(), String> {
+  let mode = if is_executable { 0o755 } else { 0o644 };
+  let mut header = header(tar::EntryType::Regular, mode, content.len() as u64);
+  builder
+    .append_data(&mut header, path, content)
+    .map_err(|e| format!("Failed to add {} to repro bundle: {}", path.display(), e))
+}
+
+fn header(entry_type: tar::EntryType, mode: u32, size: u64) -> tar::Header {
+  // NB: A new GNU header has zeroed owners and mtime, which keeps the archive deterministic.
+  let mut header = tar::Header::new_gnu();
+  header.set_entry_type(entry_type);
+  header.set_mode(mode);
+  header.set_size(size);
+  header
+}
+
+fn quote(value: &str) -> Result<String, String> {
+  str::from_utf8(&bash::escape(value))
+    .map(|s| s.to_owned())
+    .map_err(|e| format!("{:?}", e))
+}
+
+///
+/// Renders a script which runs the process from the sandbox directory next to the script, with
+/// exactly the environment of the process.
+///
+fn run_script(process: &Process) -> Result<String, String> {
+  let mut command_prefix = vec!["env".to_owned(), "-i".to_owned()];
+  for (key, value) in &process.env {
+    command_prefix.push(quote(&format!("{}={}", key, value))?);
+  }
+  let command_line = |argv: &[String]| -> Result<String, String> {
+    let mut command_line = command_prefix.clone();
+    for (i, arg) in argv.iter().enumerate() {
+      // NB: Unless the process has a PATH, a bare argv[0] is relative to the working directory,
+      // rather than being looked up on the PATH of the shell.
+      if i == 0 && !arg.contains('/') && !process.env.contains_key("PATH") {
+        command_line.push(quote(&format!("./{}", arg))?);
+      } else {
+        command_line.push(quote(arg)?);
+      }
+    }
+    Ok(command_line.join(" "))
+  };
+
+  let mut script = "#!/bin/bash
+# This script should execute the same process as pants did internally, in the sandbox which was
+# extracted alongside it.
+set -o pipefail
+cd \"$(dirname \"$0\")/sandbox\" || exit 1
+"
+  .to_owned();
+  if let Some(ref working_directory) = process.working_directory {
+    let working_directory = quote(&working_directory.to_string_lossy())?;
+    if process.create_working_directory {
+      script.push_str(&format!("mkdir -p {}\n", working_directory));
+    }
+    script.push_str(&format!("cd {} || exit 1\n", working_directory));
+  }
+  match process.pipe_from_argv {
+    Some(ref pipe_from_argv) => script.push_str(&format!(
+      "{} | {}\n",
+      command_line(pipe_from_argv)?,
+      command_line(&process.argv)?
+    )),
+    None => script.push_str(&format!("{}\n", command_line(&process.argv)?)),
+  }
+  Ok(script)
+}
+
+fn render_env(env: &BTreeMap<String, String>) -> String {
+  env
+    .iter()
+    .map(|(key, value)| format!("{}={}\n", key, value))
+    .collect()
+}
+
+fn render_platform(out: &mut String, process: &Process) -> std::fmt::Result {
+  match process.platform_constraint {
+    Some(platform) => writeln!(out, "platform constraint: {}", String::from(platform))?,
+    None => writeln!(out, "platform constraint: none")?,
+  }
+  match Platform::current() {
+    Ok(platform) => writeln!(out, "platform: {}", String::from(platform))?,
+    Err(e) => writeln!(out, "platform: unknown ({})", e)?,
+  }
+  if let Ok(info) = uname::uname() {
+    writeln!(
+      out,
+      "uname: {} {} {} {}",
+      info.sysname, info.release, info.version, info.machine
+    )?;
+  }
+  Ok(())
+}
+
+fn render_expected_outputs(out: &mut String, process: &Process) -> std::fmt::Result {
+  for path in &process.output_files {
+    writeln!(out, "file: {}", path.display())?;
+  }
+  for path in &process.output_directories {
+    writeln!(out, "directory: {}", path.display())?;
+  }
+  for path in &process.output_paths {
+    writeln!(out, "path: {}", path.display())?;
+  }
+  Ok(())
+}
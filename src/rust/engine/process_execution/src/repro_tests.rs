@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::PathBuf;
+
+use fs::RelativePath;
+use maplit::btreemap;
+use store::Store;
+use tempfile::TempDir;
+use testutil::data::{TestData, TestDirectory};
+use testutil::path::find_bash;
+use testutil::relative_paths;
+
+use crate::repro::{repro_bundle, store_repro_bundle, REPRO_BUNDLE_FILE_NAME};
+use crate::{NamedCaches, Process, ProcessMetadata, Redactor};
+
+async fn prepare_store(dir: &TempDir) -> Store {
+  let store = Store::local_only(task_executor::Executor::new(), dir.path()).unwrap();
+  store
+    .store_file_bytes(TestData::roland().bytes(), false)
+    .await
+    .unwrap();
+  store
+    .record_directory(&TestDirectory::containing_roland().directory(), false)
+    .await
+    .unwrap();
+  store
+    .record_directory(&TestDirectory::nested().directory(), false)
+    .await
+    .unwrap();
+  store
+}
+
+fn process() -> Process {
+  let mut process = Process::new(vec![
+    find_bash(),
+    "-c".to_owned(),
+    "mkdir -p ../out && cp roland ../out/roland".to_owned(),
+  ])
+  .env(btreemap! {
+    "SECRET".to_owned() => "hunter2".to_owned(),
+  })
+  .output_files(relative_paths(&["out/roland"]).collect());
+  process.input_files = TestDirectory::nested().digest();
+  process.working_directory = Some(RelativePath::new("cats").unwrap());
+  process
+}
+
+fn unpack(bundle: &[u8]) -> BTreeMap<PathBuf, Vec<u8>> {
+  let mut archive = tar::Archive::new(bundle);
+  archive
+    .entries()
+    .unwrap()
+    .map(|entry| {
+      let mut entry = entry.unwrap();
+      let path = entry.path().unwrap().into_owned();
+      let mut content = Vec::new();
+      entry.read_to_end(&mut content).unwrap();
+      (path, content)
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn bundle_contents() {
+  let store_dir = TempDir::new().unwrap();
+  let store = prepare_store(&store_dir).await;
+  let named_caches = NamedCaches::new(PathBuf::from("/named_caches"));
+  let redactor = Redactor::new(vec!["SECRET".to_owned()], &[]).unwrap();
+
+  let bundle = repro_bundle(
+    &store,
+    &process(),
+    ProcessMetadata::default(),
+    &named_caches,
+    &redactor,
+  )
+  .await
+  .unwrap();
+
+  // Bundles are deterministic.
+  assert_eq!(
+    bundle,
+    repro_bundle(
+      &store,
+      &process(),
+      ProcessMetadata::default(),
+      &named_caches,
+      &redactor,
+    )
+    .await
+    .unwrap()
+  );
+
+  let entries = unpack(&bundle);
+  assert_eq!(
+    entries.keys().cloned().collect::<Vec<_>>(),
+    vec![
+      PathBuf::from("repro"),
+      PathBuf::from("repro/__run.sh"),
+      PathBuf::from("repro/action.txt"),
+      PathBuf::from("repro/env.txt"),
+      PathBuf::from("repro/expected_outputs.txt"),
+      PathBuf::from("repro/platform.txt"),
+      PathBuf::from("repro/sandbox"),
+      PathBuf::from("repro/sandbox/cats"),
+      PathBuf::from("repro/sandbox/cats/roland"),
+    ]
+  );
+  assert_eq!(
+    entries[&PathBuf::from("repro/sandbox/cats/roland")],
+    TestData::roland().bytes().to_vec()
+  );
+  assert_eq!(
+    entries[&PathBuf::from("repro/expected_outputs.txt")],
+    b"file: out/roland\n".to_vec()
+  );
+  // Secrets are redacted from the generated files.
+  assert_eq!(
+    entries[&PathBuf::from("repro/env.txt")],
+    b"SECRET=<redacted>\n".to_vec()
+  );
+  let script = String::from_utf8(entries[&PathBuf::from("repro/__run.sh")].clone()).unwrap();
+  assert!(script.contains("cd cats"));
+  assert!(!script.contains("hunter2"));
+}
+
+#[tokio::test]
+async fn bundle_reproduces_process() {
+  let store_dir = TempDir::new().unwrap();
+  let store = prepare_store(&store_dir).await;
+
+  let digest = store_repro_bundle(
+    &store,
+    &process(),
+    ProcessMetadata::default(),
+    &NamedCaches::new(PathBuf::from("/named_caches")),
+    &Redactor::default(),
+  )
+  .await
+  .unwrap();
+  let contents = store.contents_for_directory(digest).await.unwrap();
+  assert_eq!(contents.len(), 1);
+  assert_eq!(contents[0].path, PathBuf::from(REPRO_BUNDLE_FILE_NAME));
+
+  // Unpacking the bundle and running its script runs the process in its sandbox.
+  let unpack_dir = TempDir::new().unwrap();
+  tar::Archive::new(&contents[0].content[..])
+    .unpack(unpack_dir.path())
+    .unwrap();
+  let status = std::process::Command::new(find_bash())
+    .arg(unpack_dir.path().join("repro/__run.sh"))
+    .status()
+    .unwrap();
+  assert!(status.success());
+  assert_eq!(
+    std::fs::read(unpack_dir.path().join("repro/sandbox/out/roland")).unwrap(),
+    TestData::roland().bytes().to_vec()
+  );
+}
@@ -0,0 +1,467 @@
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use tokio::process::Child;
+
+use crate::local::exit_code;
+
+///
+/// How often the memory usage of a process with a memory limit is sampled.
+///
+#[cfg(target_os = "linux")]
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+///
+/// Limits on the resources which a process may use when it runs locally.
+///
+/// Limits on CPU time and open files are enforced by the OS via `setrlimit`: a process which
+/// exceeds its CPU time is sent `SIGXCPU` (and then `SIGKILL`), and a process which exceeds its
+/// open files fails to open more of them (with `EMFILE`).
+///
+/// The limit on processes applies to the process and to its descendants (including their
+/// threads), and is enforced by a cgroup which the process runs in: a process which exceeds it
+/// fails to fork more (with `EAGAIN`). `RLIMIT_NPROC` is not used, because it counts every process
+/// of the user, rather than the descendants of the process. Process limits are only supported on
+/// Linux, and require that the `pids` controller of cgroup v2 is available to the children of the
+/// cgroup that Pants runs in: see `PidsCgroup`.
+///
+/// The limit on memory applies to the total resident set size of the process and of its
+/// descendants, which is sampled while it runs: a process which exceeds it is killed (along with
+/// its process group). Memory limits are only supported on Linux.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ResourceLimits {
+  pub max_rss_bytes: Option<u64>,
+  pub cpu_seconds: Option<u64>,
+  pub nofile: Option<u64>,
+  pub nproc: Option<u64>,
+}
+
+impl ResourceLimits {
+  pub fn is_empty(&self) -> bool {
+    self.max_rss_bytes.is_none()
+      && self.cpu_seconds.is_none()
+      && self.nofile.is_none()
+      && self.nproc.is_none()
+  }
+
+  ///
+  /// A stable rendering of these limits, which is used in cache keys.
+  ///
+  pub fn cache_key_value(&self) -> String {
+    let render = |limit: Option<u64>| limit.map(|l| l.to_string()).unwrap_or_default();
+    format!(
+      "max_rss_bytes={};cpu_seconds={};nofile={};nproc={}",
+      render(self.max_rss_bytes),
+      render(self.cpu_seconds),
+      render(self.nofile),
+      render(self.nproc),
+    )
+  }
+
+  ///
+  /// Applies these limits to the calling process. Called in a forked child before it execs, and
+  /// so must be async-signal-safe.
+  ///
+  pub(crate) fn apply(&self) -> io::Result<()> {
+    if let Some(cpu_seconds) = self.cpu_seconds {
+      // NB: The hard limit is one second beyond the soft limit, so that the process is first sent
+      // SIGXCPU (which identifies the reason that it was killed), and only then SIGKILL.
+      set_rlimit(libc::RLIMIT_CPU, cpu_seconds, cpu_seconds.saturating_add(1))?;
+    }
+    if let Some(nofile) = self.nofile {
+      set_rlimit(libc::RLIMIT_NOFILE, nofile, nofile)?;
+    }
+    // A process which exceeds its memory limit is killed along with its process group, so it must
+    // lead one (unless it already does, because it leads a new session).
+    if self.max_rss_bytes.is_some()
+      && unsafe { libc::getpgrp() != libc::getpid() }
+      && unsafe { libc::setpgid(0, 0) } != 0
+    {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(())
+  }
+
+  ///
+  /// The reason that a process which was subject to these limits exited with the given code after
+  /// using the given CPU time (if it is known), if it was because it exceeded one of them.
+  ///
+  /// A process which handles or ignores the `SIGXCPU` that it is sent at its CPU time limit is sent
+  /// `SIGKILL` once it reaches its hard limit one CPU second later. But `SIGKILL` might also have
+  /// been sent by something else (the OOM killer, for example), so it is only attributed to the
+  /// CPU time limit if the process had used that much CPU time.
+  ///
+  pub(crate) fn exit_reason(
+    &self,
+    exit_code: i32,
+    cpu_time: Option<Duration>,
+  ) -> Option<ExitReason> {
+    let cpu_seconds = self.cpu_seconds?;
+    // NB: The CPU time of a process is reported in clock ticks, rounded down.
+    let reached_hard_limit = cpu_time.map_or(false, |cpu_time| {
+      cpu_time + CPU_TIME_PRECISION >= Duration::from_secs(cpu_seconds.saturating_add(1))
+    });
+    if exit_code == -libc::SIGXCPU || (exit_code == -libc::SIGKILL && reached_hard_limit) {
+      Some(ExitReason::CpuTimeLimitExceeded)
+    } else {
+      None
+    }
+  }
+}
+
+///
+/// The precision with which the CPU time of a process is known: a clock tick, which is 10ms on
+/// every Linux platform that Pants supports.
+///
+const CPU_TIME_PRECISION: Duration = Duration::from_millis(10);
+
+fn set_rlimit(resource: RlimitResource, soft: u64, hard: u64) -> io::Result<()> {
+  let rlimit = libc::rlimit {
+    rlim_cur: soft as libc::rlim_t,
+    rlim_max: hard as libc::rlim_t,
+  };
+  if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(())
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type RlimitResource = libc::c_int;
+
+///
+/// The reason that a process exited, when it was stopped because it exceeded one of its
+/// `ResourceLimits` (rather than exiting of its own accord).
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ExitReason {
+  MemoryLimitExceeded,
+  CpuTimeLimitExceeded,
+}
+
+impl ExitReason {
+  ///
+  /// A message describing this reason for the given limits, which is appended to the stderr of the
+  /// process.
+  ///
+  pub fn describe(&self, limits: &ResourceLimits) -> String {
+    match self {
+      ExitReason::MemoryLimitExceeded => format!(
+        "Exceeded the memory limit of {} bytes for local process execution.",
+        limits.max_rss_bytes.unwrap_or_default()
+      ),
+      ExitReason::CpuTimeLimitExceeded => format!(
+        "Exceeded the CPU time limit of {} seconds for local process execution.",
+        limits.cpu_seconds.unwrap_or_default()
+      ),
+    }
+  }
+}
+
+impl fmt::Display for ExitReason {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      ExitReason::MemoryLimitExceeded => "memory_limit_exceeded",
+      ExitReason::CpuTimeLimitExceeded => "cpu_time_limit_exceeded",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+///
+/// Waits for the given child, which is subject to the given limits, to exit. If it has a memory
+/// limit, the child is killed (along with the rest of its process group) if it exceeds it. If the
+/// child was stopped because it exceeded one of its limits, the reason is returned.
+///
+pub(crate) async fn wait_within_limits(
+  child: &mut Child,
+  limits: ResourceLimits,
+) -> io::Result<(ExitStatus, Option<ExitReason>)> {
+  #[cfg(target_os = "linux")]
+  {
+    use futures::future::{self, Either, FutureExt};
+
+    if let (Some(max_rss_bytes), Some(pid)) = (limits.max_rss_bytes, child.id()) {
+      let pid = pid as libc::pid_t;
+      let exceeded = exceeds_memory_limit(pid, max_rss_bytes).boxed();
+      let wait = wait_with_cpu_time(child, limits.cpu_seconds.is_some()).boxed();
+      return match future::select(wait, exceeded).await {
+        Either::Left((exit, _)) => {
+          let (exit_status, cpu_time) = exit?;
+          let exit_reason = limits.exit_reason(exit_code(exit_status), cpu_time);
+          Ok((exit_status, exit_reason))
+        }
+        Either::Right(((), wait)) => {
+          // NB: The child leads its process group, and has not been reaped yet, so its process
+          // group id cannot have been reused.
+          unsafe { libc::killpg(pid, libc::SIGKILL) };
+          let (exit_status, _) = wait.await?;
+          Ok((exit_status, Some(ExitReason::MemoryLimitExceeded)))
+        }
+      };
+    }
+  }
+  let (exit_status, cpu_time) = wait_with_cpu_time(child, limits.cpu_seconds.is_some()).await?;
+  let exit_reason = limits.exit_reason(exit_code(exit_status), cpu_time);
+  Ok((exit_status, exit_reason))
+}
+
+///
+/// Waits for the given child to exit, and (if requested, and where it is supported) returns the
+/// CPU time that it used.
+///
+/// The CPU time is read from `/proc/<pid>/stat` once the child has exited, but before it is reaped
+/// (and so while its pid cannot have been reused), which requires waiting for it on a blocking
+/// thread with `WNOWAIT`.
+///
+async fn wait_with_cpu_time(
+  child: &mut Child,
+  cpu_time: bool,
+) -> io::Result<(ExitStatus, Option<Duration>)> {
+  #[cfg(target_os = "linux")]
+  let cpu_time = match (cpu_time, child.id()) {
+    (true, Some(pid)) => tokio::task::spawn_blocking(move || {
+      let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+      let waited = unsafe {
+        libc::waitid(
+          libc::P_PID,
+          pid as libc::id_t,
+          &mut info,
+          libc::WEXITED | libc::WNOWAIT,
+        )
+      };
+      if waited != 0 {
+        return None;
+      }
+      let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+      if ticks_per_second <= 0 {
+        return None;
+      }
+      let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+      let cpu_ticks = parse_stat(&stat)?.cpu_ticks;
+      Some(Duration::from_secs_f64(
+        cpu_ticks as f64 / ticks_per_second as f64,
+      ))
+    })
+    .await
+    .ok()
+    .flatten(),
+    _ => None,
+  };
+  #[cfg(not(target_os = "linux"))]
+  let cpu_time = {
+    let _ = cpu_time;
+    None
+  };
+  Ok((child.wait().await?, cpu_time))
+}
+
+///
+/// A cgroup (v2) which limits the number of processes (and threads) of the processes in it, and of
+/// their descendants, via the `pids` controller. Removed when dropped, after killing anything
+/// which remains in it.
+///
+/// The cgroup is created as a child of the cgroup that Pants runs in, which must make the `pids`
+/// controller available to its children: for example, because it was delegated to the user that
+/// runs Pants, or because Pants runs in the root cgroup of a container.
+///
+#[cfg(target_os = "linux")]
+pub(crate) struct PidsCgroup {
+  path: std::path::PathBuf,
+  procs: std::fs::File,
+}
+
+#[cfg(target_os = "linux")]
+impl PidsCgroup {
+  ///
+  /// Creates a cgroup which allows at most the given number of processes.
+  ///
+  pub(crate) fn create(max_pids: u64) -> Result<PidsCgroup, String> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let parent = Self::current_cgroup()?;
+    let unavailable = |e: &dyn fmt::Display| {
+      format!(
+        "Limiting the number of processes of a process requires the `pids` controller of cgroup v2 \
+         to be available to the children of the cgroup that Pants runs in ({}): {}",
+        parent.display(),
+        e
+      )
+    };
+    let subtree_control = parent.join("cgroup.subtree_control");
+    let enabled = std::fs::read_to_string(&subtree_control)
+      .map_err(|e| unavailable(&e))?
+      .split_whitespace()
+      .any(|controller| controller == "pids");
+    if !enabled {
+      std::fs::write(&subtree_control, "+pids").map_err(|e| unavailable(&e))?;
+    }
+
+    let path = parent.join(format!(
+      "pants-process-{}-{}",
+      std::process::id(),
+      COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir(&path).map_err(|e| unavailable(&e))?;
+    let opened = std::fs::write(path.join("pids.max"), max_pids.to_string()).and_then(|()| {
+      std::fs::OpenOptions::new()
+        .write(true)
+        .open(path.join("cgroup.procs"))
+    });
+    match opened {
+      Ok(procs) => Ok(PidsCgroup { path, procs }),
+      Err(e) => {
+        let _ = std::fs::remove_dir(&path);
+        Err(unavailable(&e))
+      }
+    }
+  }
+
+  ///
+  /// The directory of the (v2) cgroup of this process.
+  ///
+  fn current_cgroup() -> Result<std::path::PathBuf, String> {
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup")
+      .map_err(|e| format!("Failed to read the cgroup of Pants: {}", e))?;
+    // The v2 hierarchy is the one with id 0 (and no controllers) in `cgroups(7)`.
+    let path = cgroups
+      .lines()
+      .find_map(|line| line.strip_prefix("0::"))
+      .ok_or_else(|| {
+        "Limiting the number of processes of a process requires cgroup v2, but Pants does not \
+         run in a cgroup v2 hierarchy."
+          .to_owned()
+      })?;
+    Ok(std::path::Path::new("/sys/fs/cgroup").join(path.trim_start_matches('/')))
+  }
+
+  ///
+  /// Moves the calling process into this cgroup. Called in a forked child before it execs, and so
+  /// must be async-signal-safe.
+  ///
+  pub(crate) fn enter(procs: std::os::unix::io::RawFd) -> io::Result<()> {
+    // NB: Writing `0` to `cgroup.procs` moves the writing process.
+    if unsafe { libc::write(procs, b"0".as_ptr() as *const libc::c_void, 1) } != 1 {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(())
+  }
+
+  ///
+  /// The `cgroup.procs` file of this cgroup, to pass to `PidsCgroup::enter`.
+  ///
+  pub(crate) fn procs(&self) -> std::os::unix::io::RawFd {
+    use std::os::unix::io::AsRawFd;
+    self.procs.as_raw_fd()
+  }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for PidsCgroup {
+  fn drop(&mut self) {
+    // Descendants which outlived the process (by daemonizing, for example) would prevent the cgroup
+    // from being removed. NB: `cgroup.kill` requires Linux 5.14.
+    let _ = std::fs::write(self.path.join("cgroup.kill"), "1");
+    if let Err(e) = std::fs::remove_dir(&self.path) {
+      log::debug!("Failed to remove cgroup {}: {}", self.path.display(), e);
+    }
+  }
+}
+
+///
+/// Completes once the total resident set size of the given process and its descendants exceeds the
+/// given limit.
+///
+#[cfg(target_os = "linux")]
+async fn exceeds_memory_limit(pid: libc::pid_t, max_rss_bytes: u64) {
+  loop {
+    tokio::time::sleep(MEMORY_SAMPLE_INTERVAL).await;
+    let rss_bytes = tokio::task::spawn_blocking(move || process_tree_rss_bytes(pid))
+      .await
+      .unwrap_or(0);
+    if rss_bytes > max_rss_bytes {
+      return;
+    }
+  }
+}
+
+///
+/// Samples the total resident set size of the given process and of its descendants, in bytes.
+///
+/// The descendants are found by following the `/proc/<pid>/task/<tid>/children` of each process
+/// (which requires a kernel built with `CONFIG_PROC_CHILDREN`, as most distributions' are), so
+/// that a sample costs time proportional to the size of the process tree, rather than to the
+/// number of processes on the machine. Descendants which have been orphaned (by daemonizing, for
+/// example) are no longer counted, but are still killed with the process group if it exceeds its
+/// limit.
+///
+#[cfg(target_os = "linux")]
+pub(crate) fn process_tree_rss_bytes(pid: libc::pid_t) -> u64 {
+  let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+  let mut rss_pages = 0;
+  let mut pending = vec![pid];
+  while let Some(pid) = pending.pop() {
+    // NB: Each process may have exited since it was listed.
+    let proc_dir = std::path::PathBuf::from(format!("/proc/{}", pid));
+    if let Some(stat) = std::fs::read_to_string(proc_dir.join("stat"))
+      .ok()
+      .as_deref()
+      .and_then(parse_stat)
+    {
+      rss_pages += stat.rss_pages;
+    }
+    let tasks = match std::fs::read_dir(proc_dir.join("task")) {
+      Ok(tasks) => tasks,
+      Err(_) => continue,
+    };
+    for task in tasks.flatten() {
+      if let Ok(children) = std::fs::read_to_string(task.path().join("children")) {
+        pending.extend(
+          children
+            .split_whitespace()
+            .filter_map(|c| c.parse::<libc::pid_t>().ok()),
+        );
+      }
+    }
+  }
+  rss_pages * page_size
+}
+
+///
+/// The fields of a `/proc/<pid>/stat` file which are used to enforce resource limits.
+///
+#[cfg(target_os = "linux")]
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct ProcStat {
+  pub pgrp: libc::pid_t,
+  /// The CPU time (user and system) of the process, in clock ticks.
+  pub cpu_ticks: u64,
+  /// The resident set size of the process, in pages.
+  pub rss_pages: u64,
+}
+
+///
+/// Parses the content of a `/proc/<pid>/stat` file, as described in `proc(5)`.
+///
+#[cfg(target_os = "linux")]
+pub(crate) fn parse_stat(stat: &str) -> Option<ProcStat> {
+  // NB: The command name (the second field) is parenthesized and may itself contain spaces and
+  // parentheses, so the remaining fields are those after the last closing parenthesis: the first of
+  // them is the third field (the state of the process).
+  let fields = stat[stat.rfind(')')? + 1..]
+    .split_whitespace()
+    .collect::<Vec<_>>();
+  let field = |number: usize| fields.get(number - 3)?.parse::<u64>().ok();
+  Some(ProcStat {
+    pgrp: fields.get(5 - 3)?.parse().ok()?,
+    cpu_ticks: field(14)? + field(15)?,
+    rss_pages: field(24)?,
+  })
+}
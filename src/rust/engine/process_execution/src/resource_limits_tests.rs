@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use crate::resource_limits::{ExitReason, ResourceLimits};
+
+#[test]
+fn cache_key_value() {
+  assert_eq!(
+    ResourceLimits {
+      max_rss_bytes: Some(1 << 30),
+      cpu_seconds: Some(60),
+      ..ResourceLimits::default()
+    }
+    .cache_key_value(),
+    "max_rss_bytes=1073741824;cpu_seconds=60;nofile=;nproc="
+  );
+  assert!(ResourceLimits::default().is_empty());
+}
+
+#[test]
+fn exit_reason() {
+  let limits = ResourceLimits {
+    cpu_seconds: Some(1),
+    ..ResourceLimits::default()
+  };
+  let cpu_time = Some(Duration::from_secs(2));
+  assert_eq!(
+    limits.exit_reason(-libc::SIGXCPU, None),
+    Some(ExitReason::CpuTimeLimitExceeded)
+  );
+  // A process which ignored SIGXCPU is killed at the hard limit...
+  assert_eq!(
+    limits.exit_reason(-libc::SIGKILL, cpu_time),
+    Some(ExitReason::CpuTimeLimitExceeded)
+  );
+  // ...(which is only known to within a clock tick)...
+  assert_eq!(
+    limits.exit_reason(-libc::SIGKILL, Some(Duration::from_millis(1995))),
+    Some(ExitReason::CpuTimeLimitExceeded)
+  );
+  // ...but it cannot have reached it if it did not use that much CPU time, or if its CPU time is
+  // not known.
+  assert_eq!(
+    limits.exit_reason(-libc::SIGKILL, Some(Duration::from_millis(1500))),
+    None
+  );
+  assert_eq!(limits.exit_reason(-libc::SIGKILL, None), None);
+  assert_eq!(limits.exit_reason(1, cpu_time), None);
+  // Without a CPU time limit, SIGXCPU was not sent by the OS on our behalf.
+  assert_eq!(
+    ResourceLimits::default().exit_reason(-libc::SIGXCPU, cpu_time),
+    None
+  );
+  assert_eq!(
+    ResourceLimits::default().exit_reason(-libc::SIGKILL, cpu_time),
+    None
+  );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn parse_stat() {
+  // The command name may contain spaces and parentheses.
+  let stat = "4242 (my (weird) cmd) S 1 4240 4240 0 -1 4194560 114 0 0 0 7 3 0 0 20 0 1 0 \
+              3456 8609792 321 18446744073709551615";
+  assert_eq!(
+    crate::resource_limits::parse_stat(stat),
+    Some(crate::resource_limits::ProcStat {
+      pgrp: 4240,
+      cpu_ticks: 10,
+      rss_pages: 321,
+    })
+  );
+  assert_eq!(
+    crate::resource_limits::parse_stat("4242 (truncated) S 1"),
+    None
+  );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn process_tree_rss_bytes() {
+  // The tree of the current process includes at least its own memory.
+  let pid = unsafe { libc::getpid() };
+  assert!(crate::resource_limits::process_tree_rss_bytes(pid) > 0);
+  // And a process which does not exist uses none.
+  assert_eq!(crate::resource_limits::process_tree_rss_bytes(-1), 0);
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The name of the response file which is created in the sandbox of a process whose arguments are
+/// spilled into one.
+pub const RESPONSE_FILE_NAME: &str = "__args";
+
+/// POSIX recommends leaving this much of `ARG_MAX` unused, since some implementations count
+/// additional overhead against it.
+const ARG_MAX_HEADROOM: usize = 2048;
+
+/// The value of `ARG_MAX` to assume if it cannot be determined.
+const DEFAULT_ARG_MAX: usize = 128 * 1024;
+
+/// Linux additionally limits the length of each individual argument (or env var).
+const MAX_ARG_STRLEN: usize = 32 * 4096;
+
+///
+/// How the arguments in a response file are separated and quoted.
+///
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ResponseFileQuoting {
+  /// One argument per line. Arguments which contain whitespace, quotes or backslashes are
+  /// surrounded by double quotes, and any quotes or backslashes within them are escaped with a
+  /// backslash, which is understood by (for example) `gcc`, `clang` and `javac`.
+  Quoted,
+  /// One argument per line, without any quoting: arguments may not contain newlines.
+  Lines,
+}
+
+///
+/// Declares how a tool accepts arguments from a response file (also known as an "argfile"), which
+/// is used when a Process runs locally with arguments (and env) that exceed the limits of the OS.
+/// Rather than failing with `E2BIG`, the arguments after the first `argv_prefix_len` are moved
+/// into a response file in the sandbox, which is passed to the tool as a single argument: its
+/// absolute path with the given `prefix` (such as `@`).
+///
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ResponseFileSpec {
+  pub argv_prefix_len: usize,
+  pub prefix: String,
+  pub quoting: ResponseFileQuoting,
+}
+
+impl ResponseFileSpec {
+  pub fn new(
+    argv_prefix_len: usize,
+    prefix: String,
+    quoting: &str,
+  ) -> Result<ResponseFileSpec, String> {
+    if argv_prefix_len == 0 {
+      return Err("A response file requires an argv prefix of at least 1 argument.".to_owned());
+    }
+    let quoting = match quoting {
+      "quoted" => ResponseFileQuoting::Quoted,
+      "lines" => ResponseFileQuoting::Lines,
+      other => return Err(format!("Unknown response file quoting: {}", other)),
+    };
+    Ok(ResponseFileSpec {
+      argv_prefix_len,
+      prefix,
+      quoting,
+    })
+  }
+
+  ///
+  /// Renders the given arguments as the content of a response file.
+  ///
+  pub fn render(&self, args: &[String]) -> Result<String, String> {
+    let mut content = String::new();
+    for arg in args {
+      match self.quoting {
+        ResponseFileQuoting::Quoted => content.push_str(&quote(arg)),
+        ResponseFileQuoting::Lines if arg.contains('\n') => {
+          return Err(format!(
+            "The argument {:?} contains a newline, and so cannot be written to a response file \
+             with one argument per line.",
+            arg
+          ))
+        }
+        ResponseFileQuoting::Lines => content.push_str(arg),
+      }
+      content.push('\n');
+    }
+    Ok(content)
+  }
+
+  ///
+  /// Moves the arguments of the given argv after its prefix into a response file at the given
+  /// path, and returns the new argv and the content of the response file.
+  ///
+  pub fn spill(&self, argv: &[String], path: &Path) -> Result<(Vec<String>, String), String> {
+    let prefix_len = std::cmp::min(self.argv_prefix_len, argv.len());
+    let content = self.render(&argv[prefix_len..])?;
+    let mut spilled_argv = argv[..prefix_len].to_vec();
+    spilled_argv.push(format!("{}{}", self.prefix, path.display()));
+    Ok((spilled_argv, content))
+  }
+}
+
+fn quote(arg: &str) -> String {
+  let needs_quotes = arg.is_empty()
+    || arg
+      .chars()
+      .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\');
+  if !needs_quotes {
+    return arg.to_owned();
+  }
+  let mut quoted = String::with_capacity(arg.len() + 2);
+  quoted.push('"');
+  for c in arg.chars() {
+    if c == '"' || c == '\\' {
+      quoted.push('\\');
+    }
+    quoted.push(c);
+  }
+  quoted.push('"');
+  quoted
+}
+
+///
+/// The number of bytes which the OS allows for the arguments and environment of a new process.
+///
+pub fn arg_max() -> usize {
+  let arg_max = unsafe { libc::sysconf(libc::_SC_ARG_MAX) };
+  if arg_max > 0 {
+    arg_max as usize
+  } else {
+    DEFAULT_ARG_MAX
+  }
+}
+
+///
+/// True if executing a process with the given argv and env would exceed the given `ARG_MAX` (or
+/// the maximum length of an individual argument), and so fail with `E2BIG`.
+///
+pub fn exceeds_arg_max(argv: &[String], env: &BTreeMap<String, String>, arg_max: usize) -> bool {
+  // Each string is null terminated, and has a pointer to it in the argv or envp array.
+  let overhead = 1 + std::mem::size_of::<usize>();
+  let argv_bytes = argv.iter().map(|arg| arg.len() + overhead);
+  // Each env var is rendered as `KEY=VALUE`.
+  let env_bytes = env
+    .iter()
+    .map(|(key, value)| key.len() + 1 + value.len() + overhead);
+  let string_lengths = argv_bytes.chain(env_bytes).collect::<Vec<_>>();
+  let total_bytes: usize = string_lengths.iter().sum();
+  total_bytes + ARG_MAX_HEADROOM > arg_max
+    || (cfg!(target_os = "linux")
+      && string_lengths
+        .iter()
+        .any(|length| length - overhead + 1 > MAX_ARG_STRLEN))
+}
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use maplit::btreemap;
+use testutil::owned_string_vec;
+
+use crate::response_files::{exceeds_arg_max, ResponseFileQuoting, ResponseFileSpec};
+
+fn spec(quoting: &str) -> ResponseFileSpec {
+  ResponseFileSpec::new(1, "@".to_owned(), quoting).unwrap()
+}
+
+#[test]
+fn quoted() {
+  let content = spec("quoted")
+    .render(&owned_string_vec(&[
+      "-Ifoo",
+      "with space",
+      "",
+      "say \"hi\"",
+      "C:\\dir",
+    ]))
+    .unwrap();
+  assert_eq!(
+    content,
+    "-Ifoo\n\"with space\"\n\"\"\n\"say \\\"hi\\\"\"\n\"C:\\\\dir\"\n"
+  );
+}
+
+#[test]
+fn lines() {
+  let spec = spec("lines");
+  assert_eq!(spec.quoting, ResponseFileQuoting::Lines);
+  assert_eq!(
+    spec
+      .render(&owned_string_vec(&["-Ifoo", "with space"]))
+      .unwrap(),
+    "-Ifoo\nwith space\n"
+  );
+  assert!(spec
+    .render(&owned_string_vec(&["multi\nline"]))
+    .unwrap_err()
+    .contains("contains a newline"));
+}
+
+#[test]
+fn invalid_spec() {
+  assert!(ResponseFileSpec::new(0, "@".to_owned(), "quoted").is_err());
+  assert!(ResponseFileSpec::new(1, "@".to_owned(), "yaml").is_err());
+}
+
+#[test]
+fn spill() {
+  let spec = ResponseFileSpec::new(2, "--argfile=".to_owned(), "lines").unwrap();
+  let (argv, content) = spec
+    .spill(
+      &owned_string_vec(&["/bin/javac", "-g", "A.java", "B.java"]),
+      Path::new("/sandbox/__args"),
+    )
+    .unwrap();
+  assert_eq!(
+    argv,
+    owned_string_vec(&["/bin/javac", "-g", "--argfile=/sandbox/__args"])
+  );
+  assert_eq!(content, "A.java\nB.java\n");
+}
+
+#[test]
+fn arg_max_accounts_for_argv_and_env() {
+  let argv = owned_string_vec(&["/bin/echo", "hello"]);
+  let env = btreemap! {"KEY".to_owned() => "value".to_owned()};
+  assert!(!exceeds_arg_max(&argv, &env, 64 * 1024));
+  assert!(!exceeds_arg_max(&argv, &BTreeMap::new(), 2100));
+  assert!(exceeds_arg_max(&argv, &env, 2048));
+
+  let large_env = btreemap! {"KEY".to_owned() => "v".repeat(64 * 1024)};
+  assert!(exceeds_arg_max(&argv, &large_env, 64 * 1024));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn long_individual_arguments_exceed_arg_max() {
+  let argv = vec!["/bin/echo".to_owned(), "a".repeat(200 * 1024)];
+  assert!(exceeds_arg_max(&argv, &BTreeMap::new(), 64 * 1024 * 1024));
+}
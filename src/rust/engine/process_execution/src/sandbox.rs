@@ -0,0 +1,253 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use fs::{GlobExpansionConjunction, GlobMatching, PathGlobs, RelativePath, StrictGlobMatching};
+use futures::TryFutureExt;
+use hashing::Digest;
+use log::warn;
+use store::{DirectoryMaterializeMetadata, OneOffStoreFileByDigest, Snapshot, Store};
+use task_executor::Executor;
+
+///
+/// Creates, populates, captures the outputs of, and destroys the sandboxes in which local
+/// processes run. The default implementation (`TempdirSandboxProvider`) creates a temporary
+/// directory for each process, but embedders may supply alternatives (backed by overlayfs,
+/// filesystem clones, or microVMs, for example) via `local::CommandRunner::with_sandbox_provider`.
+///
+/// A sandbox is identified by the path at which its process runs, which must remain valid until
+/// the sandbox is destroyed. Sandboxes which are preserved for debugging are never destroyed.
+///
+#[async_trait]
+pub trait SandboxProvider: Send + Sync {
+  ///
+  /// Creates a new, empty sandbox (below the given base directory, if that is meaningful for this
+  /// provider), and returns its path.
+  ///
+  async fn create(&self, workdir_base: &Path) -> Result<PathBuf, String>;
+
+  ///
+  /// Materializes the given digest of inputs into the sandbox at the given path.
+  ///
+  async fn materialize(
+    &self,
+    store: &Store,
+    sandbox: &Path,
+    input_digest: Digest,
+  ) -> Result<DirectoryMaterializeMetadata, String>;
+
+  ///
+  /// Captures the given outputs of a completed process from the sandbox at the given path. Output
+  /// directories are captured recursively, and outputs which do not exist are skipped.
+  ///
+  async fn capture(
+    &self,
+    store: &Store,
+    sandbox: &Path,
+    output_files: BTreeSet<RelativePath>,
+    output_directories: BTreeSet<RelativePath>,
+  ) -> Result<Snapshot, String>;
+
+  ///
+  /// Destroys the sandbox at the given path. This is called at most once per sandbox, in the
+  /// background.
+  ///
+  async fn destroy(&self, sandbox: PathBuf) -> Result<(), String>;
+}
+
+///
+/// The prefix of the names of the directories which are created by the `TempdirSandboxProvider`.
+///
+pub const TEMPDIR_SANDBOX_PREFIX: &str = "process-execution";
+
+///
+/// The default SandboxProvider, which creates each sandbox as a temporary directory below the
+/// base directory, and materializes inputs into it by copying (or hardlinking) them from the Store.
+///
+pub struct TempdirSandboxProvider {
+  executor: Executor,
+}
+
+impl TempdirSandboxProvider {
+  pub fn new(executor: Executor) -> TempdirSandboxProvider {
+    TempdirSandboxProvider { executor }
+  }
+}
+
+#[async_trait]
+impl SandboxProvider for TempdirSandboxProvider {
+  async fn create(&self, workdir_base: &Path) -> Result<PathBuf, String> {
+    let workdir = tempfile::Builder::new()
+      .prefix(TEMPDIR_SANDBOX_PREFIX)
+      .tempdir_in(workdir_base)
+      .map_err(|err| {
+        format!(
+          "Error making tempdir for local process execution: {:?}",
+          err
+        )
+      })?;
+    // Consume the `TempDir` without deleting the directory: it is deleted by `destroy`.
+    Ok(workdir.into_path())
+  }
+
+  async fn materialize(
+    &self,
+    store: &Store,
+    sandbox: &Path,
+    input_digest: Digest,
+  ) -> Result<DirectoryMaterializeMetadata, String> {
+    store
+      .materialize_directory(sandbox.to_owned(), input_digest)
+      .await
+  }
+
+  async fn capture(
+    &self,
+    store: &Store,
+    sandbox: &Path,
+    output_files: BTreeSet<RelativePath>,
+    output_directories: BTreeSet<RelativePath>,
+  ) -> Result<Snapshot, String> {
+    // Use no ignore patterns, because we are looking for explicitly listed paths.
+    let posix_fs = Arc::new(
+      fs::PosixFS::new(
+        sandbox,
+        fs::GitignoreStyleExcludes::empty(),
+        self.executor.clone(),
+      )
+      .map_err(|err| {
+        format!(
+          "Error making posix_fs to fetch local process execution output files: {}",
+          err
+        )
+      })?,
+    );
+    construct_output_snapshot(store.clone(), posix_fs, output_files, output_directories).await
+  }
+
+  async fn destroy(&self, sandbox: PathBuf) -> Result<(), String> {
+    self
+      .executor
+      .spawn_blocking(move || {
+        std::fs::remove_dir_all(&sandbox)
+          .map_err(|err| format!("Error removing sandbox {:?}: {:?}", sandbox, err))
+      })
+      .await
+  }
+}
+
+///
+/// Captures the given outputs from the given filesystem as a Snapshot.
+///
+pub async fn construct_output_snapshot(
+  store: Store,
+  posix_fs: Arc<fs::PosixFS>,
+  output_file_paths: BTreeSet<RelativePath>,
+  output_dir_paths: BTreeSet<RelativePath>,
+) -> Result<Snapshot, String> {
+  let output_paths = output_dir_paths
+    .into_iter()
+    .flat_map(|p| {
+      let mut dir_glob = PathBuf::from(p).into_os_string();
+      let dir = dir_glob.clone();
+      dir_glob.push("/**");
+      vec![dir, dir_glob]
+    })
+    .chain(
+      output_file_paths
+        .into_iter()
+        .map(|p| PathBuf::from(p).into_os_string()),
+    )
+    .map(|s| {
+      s.into_string()
+        .map_err(|e| format!("Error stringifying output paths: {:?}", e))
+    })
+    .collect::<Result<Vec<String>, String>>()?;
+
+  // TODO: should we error when globs fail?
+  let output_globs = PathGlobs::new(
+    output_paths,
+    StrictGlobMatching::Ignore,
+    GlobExpansionConjunction::AllMatch,
+  )
+  .parse()?;
+
+  let path_stats = posix_fs
+    .expand_globs(output_globs, None)
+    .map_err(|err| format!("Error expanding output globs: {}", err))
+    .await?;
+  Snapshot::from_path_stats(
+    store.clone(),
+    OneOffStoreFileByDigest::new(store, posix_fs),
+    path_stats,
+  )
+  .await
+}
+
+///
+/// A sandbox which was created by a SandboxProvider, and which is destroyed in the background when
+/// it is dropped, unless it has been preserved.
+///
+pub struct Sandbox {
+  path: PathBuf,
+  provider: Arc<dyn SandboxProvider>,
+  executor: Executor,
+  preserved: bool,
+}
+
+impl Sandbox {
+  pub async fn create(
+    provider: Arc<dyn SandboxProvider>,
+    executor: Executor,
+    workdir_base: &Path,
+  ) -> Result<Sandbox, String> {
+    let path = provider.create(workdir_base).await?;
+    Ok(Sandbox {
+      path,
+      provider,
+      executor,
+      preserved: false,
+    })
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  ///
+  /// Preserves the sandbox (rather than destroying it when it is dropped), and returns its path.
+  ///
+  pub fn preserve(mut self) -> PathBuf {
+    self.preserved = true;
+    self.path.clone()
+  }
+
+  ///
+  /// Destroys the sandbox in the background, and only then drops the given value (such as the
+  /// slot of the sandbox).
+  ///
+  pub fn destroy_then_drop<T: Send + 'static>(mut self, value: T) {
+    self.spawn_destroy(value);
+  }
+
+  fn spawn_destroy<T: Send + 'static>(&mut self, value: T) {
+    self.preserved = true;
+    let provider = self.provider.clone();
+    let path = self.path.clone();
+    let _background_cleanup = self.executor.spawn(async move {
+      if let Err(e) = provider.destroy(path).await {
+        warn!("{}", e);
+      }
+      std::mem::drop(value);
+    });
+  }
+}
+
+impl Drop for Sandbox {
+  fn drop(&mut self) {
+    if !self.preserved {
+      self.spawn_destroy(());
+    }
+  }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::warn;
+use walkdir::WalkDir;
+
+use crate::local::RUN_SCRIPT_NAME;
+use crate::sandbox::TEMPDIR_SANDBOX_PREFIX;
+
+///
+/// A retention policy for the sandboxes of local processes which were preserved for debugging (see
+/// `KeepSandboxes`), which would otherwise accumulate until the disk fills.
+///
+/// Preserved sandboxes are identified as the directories below the sandbox root which were created
+/// by the `TempdirSandboxProvider`, and which contain a `__run.sh` script. Because the sandbox root
+/// may be shared (it defaults to the system temp dir), only the sandboxes which were marked as
+/// preserved by the same workspace (see `mark_preserved_sandbox`) are considered. The most recently
+/// preserved sandboxes are retained, up to each of the (optional) limits.
+///
+#[derive(Clone, Debug, Default)]
+pub struct SandboxRetention {
+  /// The maximum number of preserved sandboxes to retain.
+  pub max_count: Option<usize>,
+  /// The maximum age of a preserved sandbox.
+  pub max_age: Option<Duration>,
+  /// The maximum total size of the retained preserved sandboxes.
+  pub max_bytes: Option<u64>,
+}
+
+///
+/// A summary of the preserved sandboxes which were pruned by `SandboxRetention::prune`.
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PrunedSandboxes {
+  pub pruned_count: usize,
+  pub pruned_bytes: u64,
+  pub retained_count: usize,
+  pub retained_bytes: u64,
+}
+
+impl fmt::Display for PrunedSandboxes {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "pruned {} preserved sandboxes ({} bytes), and retained {} ({} bytes)",
+      self.pruned_count, self.pruned_bytes, self.retained_count, self.retained_bytes
+    )
+  }
+}
+
+///
+/// The name of the file in a preserved sandbox which records the workspace that preserved it.
+///
+pub const WORKSPACE_MARKER_NAME: &str = "__workspace";
+
+///
+/// Records that the given preserved sandbox belongs to the given workspace, so that it is only ever
+/// pruned by that workspace.
+///
+pub fn mark_preserved_sandbox(sandbox: &Path, workspace: &Path) -> Result<(), String> {
+  std::fs::write(
+    sandbox.join(WORKSPACE_MARKER_NAME),
+    workspace.to_string_lossy().as_bytes(),
+  )
+  .map_err(|e| {
+    format!(
+      "Failed to mark preserved sandbox {:?} as belonging to {:?}: {}",
+      sandbox, workspace, e
+    )
+  })
+}
+
+struct PreservedSandbox {
+  path: PathBuf,
+  preserved_at: SystemTime,
+  bytes: u64,
+}
+
+impl SandboxRetention {
+  pub fn is_unbounded(&self) -> bool {
+    self.max_count.is_none() && self.max_age.is_none() && self.max_bytes.is_none()
+  }
+
+  ///
+  /// Deletes the preserved sandboxes of the given workspace below the given root which exceed this
+  /// policy, oldest first. Sandboxes which fail to be deleted are logged and skipped.
+  ///
+  /// NB: This performs blocking IO.
+  ///
+  pub fn prune(
+    &self,
+    sandbox_root: &Path,
+    workspace: &Path,
+    now: SystemTime,
+  ) -> Result<PrunedSandboxes, String> {
+    let mut summary = PrunedSandboxes::default();
+    if self.is_unbounded() {
+      return Ok(summary);
+    }
+
+    let mut sandboxes = Self::preserved_sandboxes(sandbox_root, workspace)?;
+    // Most recently preserved first.
+    sandboxes.sort_by(|a, b| b.preserved_at.cmp(&a.preserved_at));
+
+    let mut exceeded = false;
+    for sandbox in sandboxes {
+      let too_old = self.max_age.map_or(false, |max_age| {
+        now
+          .duration_since(sandbox.preserved_at)
+          .map_or(false, |age| age > max_age)
+      });
+      let too_many = self
+        .max_count
+        .map_or(false, |max_count| summary.retained_count >= max_count);
+      let too_large = self.max_bytes.map_or(false, |max_bytes| {
+        summary.retained_bytes + sandbox.bytes > max_bytes
+      });
+      // Once a sandbox has been pruned, all older sandboxes are pruned as well, so that the
+      // retained sandboxes are always the most recent.
+      exceeded = exceeded || too_old || too_many || too_large;
+
+      if exceeded {
+        // The sandbox might be removed concurrently by another run, or might not be removable by
+        // this user: neither should prevent the rest from being pruned.
+        match std::fs::remove_dir_all(&sandbox.path) {
+          Ok(()) => {
+            summary.pruned_count += 1;
+            summary.pruned_bytes += sandbox.bytes;
+          }
+          Err(e) => warn!(
+            "Failed to remove preserved sandbox {:?}: {}",
+            sandbox.path, e
+          ),
+        }
+      } else {
+        summary.retained_count += 1;
+        summary.retained_bytes += sandbox.bytes;
+      }
+    }
+    Ok(summary)
+  }
+
+  fn preserved_sandboxes(
+    sandbox_root: &Path,
+    workspace: &Path,
+  ) -> Result<Vec<PreservedSandbox>, String> {
+    let entries = match std::fs::read_dir(sandbox_root) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+      Err(e) => {
+        return Err(format!(
+          "Failed to list sandboxes in {:?}: {}",
+          sandbox_root, e
+        ))
+      }
+    };
+
+    let mut sandboxes = Vec::new();
+    for entry in entries {
+      let entry =
+        entry.map_err(|e| format!("Failed to list sandboxes in {:?}: {}", sandbox_root, e))?;
+      let is_sandbox = entry
+        .file_name()
+        .to_str()
+        .map_or(false, |name| name.starts_with(TEMPDIR_SANDBOX_PREFIX));
+      if !is_sandbox || !entry.file_type().map_or(false, |t| t.is_dir()) {
+        continue;
+      }
+      let path = entry.path();
+      // Sandboxes which were preserved by other workspaces (or which were never marked) are not
+      // ours to prune.
+      let is_ours = std::fs::read(path.join(WORKSPACE_MARKER_NAME)).map_or(false, |marker| {
+        marker == workspace.to_string_lossy().as_bytes()
+      });
+      if !is_ours {
+        continue;
+      }
+      // Sandboxes which do not (yet) contain a run script are in use by running processes. The
+      // script is written once the sandbox is preserved, so its mtime is when that happened.
+      let preserved_at = match std::fs::metadata(path.join(RUN_SCRIPT_NAME)) {
+        Ok(metadata) => metadata
+          .modified()
+          .map_err(|e| format!("Failed to stat preserved sandbox {:?}: {}", path, e))?,
+        Err(_) => continue,
+      };
+      // NB: Symlinks (such as those to named caches) are not followed.
+      let bytes = WalkDir::new(&path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum::<u64>();
+      sandboxes.push(PreservedSandbox {
+        path,
+        preserved_at,
+        bytes,
+      });
+    }
+    Ok(sandboxes)
+  }
+}
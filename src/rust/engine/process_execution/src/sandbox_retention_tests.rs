@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tempfile::TempDir;
+
+use crate::local::RUN_SCRIPT_NAME;
+use crate::sandbox_retention::{mark_preserved_sandbox, PrunedSandboxes, SandboxRetention};
+
+const WORKSPACE: &str = "/workspace";
+
+///
+/// Creates a sandbox containing a file of the given size which was preserved by the given
+/// workspace, and returns its path.
+///
+fn preserved_sandbox_of(root: &Path, workspace: &str, name: &str, bytes: usize) -> PathBuf {
+  let path = root.join(format!("process-execution{}", name));
+  std::fs::create_dir(&path).unwrap();
+  std::fs::write(path.join("output"), vec![0_u8; bytes]).unwrap();
+  std::fs::write(path.join(RUN_SCRIPT_NAME), b"").unwrap();
+  mark_preserved_sandbox(&path, Path::new(workspace)).unwrap();
+  path
+}
+
+fn preserved_sandbox(root: &Path, name: &str, bytes: usize) -> PathBuf {
+  preserved_sandbox_of(root, WORKSPACE, name, bytes)
+}
+
+fn retention(
+  max_count: Option<usize>,
+  max_age: Option<Duration>,
+  max_bytes: Option<u64>,
+) -> SandboxRetention {
+  SandboxRetention {
+    max_count,
+    max_age,
+    max_bytes,
+  }
+}
+
+#[test]
+fn prunes_oldest_beyond_max_count() {
+  let root = TempDir::new().unwrap();
+  let oldest = preserved_sandbox(root.path(), "a", 10);
+  std::thread::sleep(Duration::from_millis(20));
+  let newest = preserved_sandbox(root.path(), "b", 10);
+  // Sandboxes without run scripts are in use, and other directories are not sandboxes.
+  let live = root.path().join("process-executionc");
+  std::fs::create_dir(&live).unwrap();
+  let unrelated = root.path().join("named_caches");
+  std::fs::create_dir(&unrelated).unwrap();
+
+  let summary = retention(Some(1), None, None)
+    .prune(root.path(), Path::new(WORKSPACE), SystemTime::now())
+    .unwrap();
+
+  assert_eq!(
+    summary,
+    PrunedSandboxes {
+      pruned_count: 1,
+      pruned_bytes: 10,
+      retained_count: 1,
+      retained_bytes: 10,
+    }
+  );
+  assert!(!oldest.exists());
+  assert!(newest.exists());
+  assert!(live.exists());
+  assert!(unrelated.exists());
+}
+
+#[test]
+fn prunes_beyond_max_age_and_max_bytes() {
+  let root = TempDir::new().unwrap();
+  let oldest = preserved_sandbox(root.path(), "a", 10);
+  std::thread::sleep(Duration::from_millis(20));
+  let middle = preserved_sandbox(root.path(), "b", 10);
+  std::thread::sleep(Duration::from_millis(20));
+  let newest = preserved_sandbox(root.path(), "c", 10);
+
+  // Only the oldest is older than the max age, as of the moment at which the middle sandbox
+  // reaches it.
+  let max_age = Duration::from_secs(3600);
+  let middle_preserved_at = std::fs::metadata(middle.join(RUN_SCRIPT_NAME))
+    .unwrap()
+    .modified()
+    .unwrap();
+  let summary = retention(None, Some(max_age), None)
+    .prune(
+      root.path(),
+      Path::new(WORKSPACE),
+      middle_preserved_at + max_age,
+    )
+    .unwrap();
+  assert_eq!(summary.pruned_count, 1);
+  assert!(!oldest.exists());
+
+  // Only one sandbox fits within the max bytes.
+  let summary = retention(None, None, Some(15))
+    .prune(root.path(), Path::new(WORKSPACE), SystemTime::now())
+    .unwrap();
+  assert_eq!(summary.pruned_count, 1);
+  assert_eq!(summary.retained_bytes, 10);
+  assert!(!middle.exists());
+  assert!(newest.exists());
+}
+
+#[test]
+fn unbounded_prunes_nothing() {
+  let root = TempDir::new().unwrap();
+  let sandbox = preserved_sandbox(root.path(), "a", 10);
+
+  let summary = SandboxRetention::default()
+    .prune(root.path(), Path::new(WORKSPACE), SystemTime::now())
+    .unwrap();
+
+  assert_eq!(summary, PrunedSandboxes::default());
+  assert!(sandbox.exists());
+}
+
+#[test]
+fn prunes_only_the_sandboxes_of_the_workspace() {
+  let root = TempDir::new().unwrap();
+  let other = preserved_sandbox_of(root.path(), "/other-workspace", "a", 10);
+  // A sandbox preserved without a marker (e.g. by an older version) belongs to no workspace.
+  let unmarked = root.path().join("process-executionb");
+  std::fs::create_dir(&unmarked).unwrap();
+  std::fs::write(unmarked.join(RUN_SCRIPT_NAME), b"").unwrap();
+  let ours = preserved_sandbox(root.path(), "c", 10);
+
+  let summary = retention(Some(0), None, None)
+    .prune(root.path(), Path::new(WORKSPACE), SystemTime::now())
+    .unwrap();
+
+  assert_eq!(summary.pruned_count, 1);
+  assert_eq!(summary.retained_count, 0);
+  assert!(other.exists());
+  assert!(unmarked.exists());
+  assert!(!ours.exists());
+}
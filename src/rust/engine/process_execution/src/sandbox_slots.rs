@@ -0,0 +1,94 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use walkdir::WalkDir;
+
+///
+/// Bounds the number of local sandboxes which exist at once, and tracks their total size on disk.
+///
+/// A sandbox holds a slot from before its inputs are materialized until it has been deleted
+/// (rather than only while its process runs), so when sandboxes are bounded, new sandboxes wait
+/// for completed sandboxes to be cleaned up. Sandboxes which are preserved for debugging release
+/// their slot as soon as their process completes.
+///
+#[derive(Clone)]
+pub struct SandboxSlots {
+  semaphore: Option<Arc<Semaphore>>,
+  live_bytes: Arc<Mutex<u64>>,
+}
+
+impl SandboxSlots {
+  pub fn new(max_sandboxes: Option<usize>) -> SandboxSlots {
+    SandboxSlots {
+      semaphore: max_sandboxes.map(|max_sandboxes| Arc::new(Semaphore::new(max_sandboxes))),
+      live_bytes: Arc::new(Mutex::new(0)),
+    }
+  }
+
+  ///
+  /// Waits for a slot for a new sandbox, if sandboxes are bounded.
+  ///
+  pub async fn acquire(&self) -> Result<SandboxSlot, String> {
+    let permit = match self.semaphore {
+      Some(ref semaphore) => Some(
+        semaphore
+          .clone()
+          .acquire_owned()
+          .await
+          .map_err(|e| format!("Failed to acquire a sandbox slot: {}", e))?,
+      ),
+      None => None,
+    };
+    Ok(SandboxSlot {
+      _permit: permit,
+      bytes: 0,
+      live_bytes: self.live_bytes.clone(),
+    })
+  }
+
+  ///
+  /// The total size of the sandboxes which currently hold slots, as last measured.
+  ///
+  pub fn live_bytes(&self) -> u64 {
+    *self.live_bytes.lock()
+  }
+}
+
+///
+/// A slot for one sandbox, which is released when it is dropped.
+///
+pub struct SandboxSlot {
+  _permit: Option<OwnedSemaphorePermit>,
+  bytes: u64,
+  live_bytes: Arc<Mutex<u64>>,
+}
+
+impl SandboxSlot {
+  ///
+  /// Measures the size on disk of the sandbox at the given path (without following symlinks, such
+  /// as those to named caches), and returns the total size of all live sandboxes.
+  ///
+  /// NB: This walks the sandbox, and so should be called on a blocking thread.
+  ///
+  pub fn measure(&mut self, sandbox: &Path) -> u64 {
+    let bytes = WalkDir::new(sandbox)
+      .into_iter()
+      .filter_map(|entry| entry.ok())
+      .filter_map(|entry| entry.metadata().ok())
+      .filter(|metadata| metadata.is_file())
+      .map(|metadata| metadata.len())
+      .sum::<u64>();
+    let mut live_bytes = self.live_bytes.lock();
+    *live_bytes = *live_bytes - self.bytes + bytes;
+    self.bytes = bytes;
+    *live_bytes
+  }
+}
+
+impl Drop for SandboxSlot {
+  fn drop(&mut self) {
+    *self.live_bytes.lock() -= self.bytes;
+  }
+}
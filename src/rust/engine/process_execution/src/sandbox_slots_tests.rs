@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+use crate::sandbox_slots::SandboxSlots;
+
+#[tokio::test]
+async fn bounds_live_sandboxes() {
+  let slots = SandboxSlots::new(Some(1));
+  let slot = slots.acquire().await.unwrap();
+
+  // A second sandbox waits until the first has released its slot.
+  let waiting = tokio::time::timeout(Duration::from_millis(100), slots.acquire()).await;
+  assert!(waiting.is_err());
+  std::mem::drop(slot);
+  tokio::time::timeout(Duration::from_millis(100), slots.acquire())
+    .await
+    .unwrap()
+    .unwrap();
+}
+
+#[tokio::test]
+async fn measures_live_bytes() {
+  let slots = SandboxSlots::new(None);
+  let first_dir = TempDir::new().unwrap();
+  std::fs::write(first_dir.path().join("a"), vec![0_u8; 10]).unwrap();
+  std::fs::create_dir(first_dir.path().join("sub")).unwrap();
+  std::fs::write(first_dir.path().join("sub/b"), vec![0_u8; 5]).unwrap();
+  let second_dir = TempDir::new().unwrap();
+  std::fs::write(second_dir.path().join("c"), vec![0_u8; 7]).unwrap();
+
+  let mut first = slots.acquire().await.unwrap();
+  let mut second = slots.acquire().await.unwrap();
+  assert_eq!(first.measure(first_dir.path()), 15);
+  assert_eq!(second.measure(second_dir.path()), 22);
+
+  // Re-measuring replaces the previous measurement.
+  std::fs::write(second_dir.path().join("d"), vec![0_u8; 3]).unwrap();
+  assert_eq!(second.measure(second_dir.path()), 25);
+
+  std::mem::drop(first);
+  assert_eq!(slots.live_bytes(), 10);
+  std::mem::drop(second);
+  assert_eq!(slots.live_bytes(), 0);
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use fs::RelativePath;
+use hashing::Digest;
+use parking_lot::Mutex;
+use store::{DirectoryMaterializeMetadata, Snapshot, Store};
+use tempfile::TempDir;
+use testutil::{owned_string_vec, relative_paths};
+use workunit_store::WorkunitStore;
+
+use crate::local::KeepSandboxes;
+use crate::sandbox::{SandboxProvider, TempdirSandboxProvider};
+use crate::{CommandRunner as CommandRunnerTrait, Context, NamedCaches, Process};
+
+///
+/// A SandboxProvider which records the operations that it is asked to perform, and delegates
+/// them to a TempdirSandboxProvider.
+///
+struct RecordingSandboxProvider {
+  underlying: TempdirSandboxProvider,
+  operations: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl SandboxProvider for RecordingSandboxProvider {
+  async fn create(&self, workdir_base: &Path) -> Result<PathBuf, String> {
+    self.operations.lock().push("create".to_owned());
+    self.underlying.create(workdir_base).await
+  }
+
+  async fn materialize(
+    &self,
+    store: &Store,
+    sandbox: &Path,
+    input_digest: Digest,
+  ) -> Result<DirectoryMaterializeMetadata, String> {
+    self.operations.lock().push("materialize".to_owned());
+    self
+      .underlying
+      .materialize(store, sandbox, input_digest)
+      .await
+  }
+
+  async fn capture(
+    &self,
+    store: &Store,
+    sandbox: &Path,
+    output_files: BTreeSet<RelativePath>,
+    output_directories: BTreeSet<RelativePath>,
+  ) -> Result<Snapshot, String> {
+    self.operations.lock().push("capture".to_owned());
+    self
+      .underlying
+      .capture(store, sandbox, output_files, output_directories)
+      .await
+  }
+
+  async fn destroy(&self, sandbox: PathBuf) -> Result<(), String> {
+    let result = self.underlying.destroy(sandbox).await;
+    self.operations.lock().push("destroy".to_owned());
+    result
+  }
+}
+
+async fn run_with_provider(
+  process: Process,
+  keep_sandboxes: KeepSandboxes,
+) -> (Arc<RecordingSandboxProvider>, TempDir) {
+  let store_dir = TempDir::new().unwrap();
+  let named_cache_dir = TempDir::new().unwrap();
+  let work_dir = TempDir::new().unwrap();
+  let executor = task_executor::Executor::new();
+  let store = Store::local_only(executor.clone(), store_dir.path()).unwrap();
+  let provider = Arc::new(RecordingSandboxProvider {
+    underlying: TempdirSandboxProvider::new(executor.clone()),
+    operations: Mutex::default(),
+  });
+  let runner = crate::local::CommandRunner::new(
+    store,
+    executor,
+    work_dir.path().to_owned(),
+    NamedCaches::new(named_cache_dir.path().to_owned()),
+    keep_sandboxes,
+    crate::Redactor::default(),
+  )
+  .with_sandbox_provider(provider.clone());
+
+  let result = runner
+    .run(process.into(), Context::default())
+    .await
+    .unwrap();
+  assert_eq!(result.exit_code, 0);
+  (provider, work_dir)
+}
+
+///
+/// Waits for the background destruction of sandboxes to complete.
+///
+async fn wait_for_operations(provider: &RecordingSandboxProvider, count: usize) -> Vec<String> {
+  for _ in 0..50 {
+    let operations = provider.operations.lock().clone();
+    if operations.len() >= count {
+      return operations;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+  }
+  provider.operations.lock().clone()
+}
+
+#[tokio::test]
+async fn provider_manages_sandbox_lifecycle() {
+  WorkunitStore::setup_for_tests();
+
+  let process = Process::new(owned_string_vec(&[
+    "/bin/bash",
+    "-c",
+    "echo hello > out.txt",
+  ]))
+  .output_files(relative_paths(&["out.txt"]).collect());
+  let (provider, work_dir) = run_with_provider(process, KeepSandboxes::Never).await;
+
+  assert_eq!(
+    wait_for_operations(&provider, 4).await,
+    owned_string_vec(&["create", "materialize", "capture", "destroy"])
+  );
+  assert_eq!(testutil::file::list_dir(work_dir.path()).len(), 0);
+}
+
+#[tokio::test]
+async fn preserved_sandboxes_are_not_destroyed() {
+  WorkunitStore::setup_for_tests();
+
+  let process = Process::new(owned_string_vec(&["/bin/echo", "-n", "foo"]));
+  let (provider, work_dir) = run_with_provider(process, KeepSandboxes::Always).await;
+
+  // A process without outputs does not need its outputs to be captured.
+  tokio::time::sleep(Duration::from_millis(200)).await;
+  assert_eq!(
+    provider.operations.lock().clone(),
+    owned_string_vec(&["create", "materialize"])
+  );
+  assert_eq!(testutil::file::list_dir(work_dir.path()).len(), 1);
+}
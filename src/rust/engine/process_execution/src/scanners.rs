@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// The maximum number of lines which a `Lines` scanner will collect from the output of a Process.
+pub const MAX_COLLECTED_LINES: usize = 100;
+
+///
+/// A structured finding extracted from the output of a Process by an `OutputScanner`.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Finding {
+  Lines(Vec<String>),
+  Count(u64),
+  Counts(BTreeMap<String, u64>),
+}
+
+///
+/// Extracts a structured finding from the output of a Process, so that it can be attached to the
+/// workunit for the Process (and summarized by a UI) without shipping the full output to Python.
+///
+/// New kinds of scanners are added by implementing this trait and adding a corresponding variant
+/// to `OutputScannerSpec`.
+///
+pub trait OutputScanner: Send + Sync {
+  fn scan(&self, lines: &mut dyn Iterator<Item = &str>) -> Finding;
+}
+
+///
+/// The declaration of an `OutputScanner` by a Process, which is identified in the workunit
+/// metadata of the Process by its name.
+///
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum OutputScannerSpec {
+  /// Collects (up to `MAX_COLLECTED_LINES`) lines which match the given pattern.
+  Lines { name: String, pattern: String },
+  /// Counts the lines which match the given pattern.
+  Count { name: String, pattern: String },
+  /// Parses compiler-style diagnostics, and counts the errors and warnings.
+  Diagnostics { name: String },
+}
+
+impl OutputScannerSpec {
+  pub fn new(kind: &str, name: String, pattern: String) -> Result<OutputScannerSpec, String> {
+    let spec = match kind {
+      "lines" => OutputScannerSpec::Lines { name, pattern },
+      "count" => OutputScannerSpec::Count { name, pattern },
+      "diagnostics" => OutputScannerSpec::Diagnostics { name },
+      other => return Err(format!("Unknown output scanner kind: {}", other)),
+    };
+    // Validate the spec eagerly, so that an invalid pattern fails before the Process runs.
+    spec.scanner()?;
+    Ok(spec)
+  }
+
+  pub fn name(&self) -> &str {
+    match self {
+      OutputScannerSpec::Lines { name, .. }
+      | OutputScannerSpec::Count { name, .. }
+      | OutputScannerSpec::Diagnostics { name } => name,
+    }
+  }
+
+  pub fn scanner(&self) -> Result<Box<dyn OutputScanner>, String> {
+    let compile = |pattern: &str| {
+      Regex::new(pattern).map_err(|e| {
+        format!(
+          "Invalid pattern for output scanner `{}`: {}",
+          self.name(),
+          e
+        )
+      })
+    };
+    Ok(match self {
+      OutputScannerSpec::Lines { pattern, .. } => Box::new(LinesScanner(compile(pattern)?)),
+      OutputScannerSpec::Count { pattern, .. } => Box::new(CountScanner(compile(pattern)?)),
+      OutputScannerSpec::Diagnostics { .. } => Box::new(DiagnosticsScanner),
+    })
+  }
+}
+
+///
+/// Runs the given scanners over the lines of the stdout and then the stderr of a Process, and
+/// returns their findings by name.
+///
+pub fn scan(
+  specs: &[OutputScannerSpec],
+  stdout: &[u8],
+  stderr: &[u8],
+) -> Result<Vec<(String, Finding)>, String> {
+  let stdout = String::from_utf8_lossy(stdout);
+  let stderr = String::from_utf8_lossy(stderr);
+  specs
+    .iter()
+    .map(|spec| {
+      let scanner = spec.scanner()?;
+      let mut lines = stdout.lines().chain(stderr.lines());
+      Ok((spec.name().to_owned(), scanner.scan(&mut lines)))
+    })
+    .collect()
+}
+
+struct LinesScanner(Regex);
+
+impl OutputScanner for LinesScanner {
+  fn scan(&self, lines: &mut dyn Iterator<Item = &str>) -> Finding {
+    Finding::Lines(
+      lines
+        .filter(|line| self.0.is_match(line))
+        .take(MAX_COLLECTED_LINES)
+        .map(|line| line.to_owned())
+        .collect(),
+    )
+  }
+}
+
+struct CountScanner(Regex);
+
+impl OutputScanner for CountScanner {
+  fn scan(&self, lines: &mut dyn Iterator<Item = &str>) -> Finding {
+    Finding::Count(lines.filter(|line| self.0.is_match(line)).count() as u64)
+  }
+}
+
+lazy_static! {
+  // Matches diagnostics of the form emitted by gcc, clang, rustc, javac and mypy, among others:
+  // either prefixed by a location (`path:line[:column]: error: ...`) or not (`error[E0308]: ...`).
+  static ref DIAGNOSTIC_RE: Regex =
+    Regex::new(r"^(?:\S+:\d+(?::\d+)?:\s*)?(?:fatal )?(error|warning)(?:\[[^\]]*\])?:").unwrap();
+}
+
+///
+/// Counts the errors and warnings emitted by compilers and similar tools.
+///
+struct DiagnosticsScanner;
+
+impl OutputScanner for DiagnosticsScanner {
+  fn scan(&self, lines: &mut dyn Iterator<Item = &str>) -> Finding {
+    let mut counts = BTreeMap::new();
+    counts.insert("errors".to_owned(), 0);
+    counts.insert("warnings".to_owned(), 0);
+    for line in lines {
+      if let Some(captures) = DIAGNOSTIC_RE.captures(line) {
+        let key = match &captures[1] {
+          "error" => "errors",
+          _ => "warnings",
+        };
+        *counts.get_mut(key).unwrap() += 1;
+      }
+    }
+    Finding::Counts(counts)
+  }
+}
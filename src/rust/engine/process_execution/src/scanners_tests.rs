@@ -0,0 +1,77 @@
+use maplit::btreemap;
+
+use crate::scanners::{scan, Finding, OutputScannerSpec, MAX_COLLECTED_LINES};
+
+fn spec(kind: &str, name: &str, pattern: &str) -> OutputScannerSpec {
+  OutputScannerSpec::new(kind, name.to_owned(), pattern.to_owned()).unwrap()
+}
+
+#[test]
+fn lines_and_counts() {
+  let findings = scan(
+    &[
+      spec("lines", "failures", "^FAILED "),
+      spec("count", "passes", "^PASSED "),
+    ],
+    b"PASSED test_a\nFAILED test_b\nPASSED test_c\n",
+    b"FAILED test_d\n",
+  )
+  .unwrap();
+  assert_eq!(
+    findings,
+    vec![
+      (
+        "failures".to_owned(),
+        Finding::Lines(vec!["FAILED test_b".to_owned(), "FAILED test_d".to_owned()])
+      ),
+      ("passes".to_owned(), Finding::Count(2)),
+    ]
+  );
+}
+
+#[test]
+fn lines_are_limited() {
+  let stdout = "error\n".repeat(MAX_COLLECTED_LINES * 2);
+  let findings = scan(&[spec("lines", "errors", "error")], stdout.as_bytes(), b"").unwrap();
+  match &findings[0].1 {
+    Finding::Lines(lines) => assert_eq!(lines.len(), MAX_COLLECTED_LINES),
+    other => panic!("Unexpected finding: {:?}", other),
+  }
+}
+
+#[test]
+fn diagnostics() {
+  let stderr = "\
+src/main.c:3:5: error: use of undeclared identifier 'x'
+src/main.c:7: warning: unused variable 'y'
+error[E0308]: mismatched types
+warning: unused import
+note: this is not a diagnostic that is counted
+an error: in the middle of a line
+";
+  let findings = scan(&[spec("diagnostics", "diags", "")], b"", stderr.as_bytes()).unwrap();
+  assert_eq!(
+    findings,
+    vec![(
+      "diags".to_owned(),
+      Finding::Counts(btreemap! {
+        "errors".to_owned() => 2,
+        "warnings".to_owned() => 2,
+      })
+    )]
+  );
+}
+
+#[test]
+fn invalid_specs() {
+  let err = OutputScannerSpec::new("lines", "bad".to_owned(), "(".to_owned()).unwrap_err();
+  assert!(
+    err.starts_with("Invalid pattern for output scanner `bad`"),
+    "Unexpected error: {}",
+    err
+  );
+  assert_eq!(
+    OutputScannerSpec::new("unknown", "bad".to_owned(), "".to_owned()),
+    Err("Unknown output scanner kind: unknown".to_owned())
+  );
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::future::{self, Either, FutureExt};
+use log::debug;
+use parking_lot::Mutex;
+use tokio::sync::Semaphore;
+use workunit_store::Metric;
+
+use crate::{Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, Process};
+
+type ProcessResult = Result<FallibleProcessResultWithPlatform, String>;
+
+///
+/// Where speculative attempts to run processes are run.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SpeculationTarget {
+  Local,
+  Remote,
+}
+
+impl TryFrom<String> for SpeculationTarget {
+  type Error = String;
+  fn try_from(variant_candidate: String) -> Result<Self, Self::Error> {
+    match variant_candidate.as_ref() {
+      "local" => Ok(SpeculationTarget::Local),
+      "remote" => Ok(SpeculationTarget::Remote),
+      other => Err(format!("Unknown value for speculation: {:?}", other)),
+    }
+  }
+}
+
+///
+/// When and how often to speculatively re-execute processes: see `CommandRunner`.
+///
+#[derive(Clone, Debug)]
+pub struct SpeculationOptions {
+  /// A process is speculatively re-executed once it has run for this multiple of its historical
+  /// runtime.
+  pub runtime_multiplier: f64,
+  /// A process is never speculatively re-executed before it has run for this long, which avoids
+  /// duplicating short processes whose runtimes are dominated by noise.
+  pub min_delay: Duration,
+  /// The maximum number of speculative attempts which may run at once. Processes which would be
+  /// speculatively re-executed while this many attempts are running are not.
+  pub max_concurrent: usize,
+}
+
+///
+/// A CommandRunner wrapper which mitigates stragglers (such as processes which were scheduled on
+/// an occasional slow remote worker) by speculatively re-executing them.
+///
+/// The runtime of each kind of process (as identified by its description) is recorded. When a
+/// process with a recorded runtime runs for longer than a multiple of that runtime, a second
+/// attempt is started with the `speculative` runner (which might run it remotely again, or
+/// locally), and the first attempt to succeed is used. The other attempt is then cancelled by
+/// dropping it. If either attempt fails to run at all, the result of the other is used.
+///
+/// NB: Some remote execution servers merge identical concurrent executions, in which case a
+/// speculative remote attempt will not be scheduled separately from the original attempt.
+///
+pub struct CommandRunner {
+  underlying: Arc<dyn crate::CommandRunner>,
+  speculative: Arc<dyn crate::CommandRunner>,
+  options: SpeculationOptions,
+  // The exponentially weighted moving average of the runtimes of processes, by description.
+  runtimes: Mutex<HashMap<String, Duration>>,
+  speculation_permits: Semaphore,
+}
+
+impl CommandRunner {
+  pub fn new(
+    underlying: Arc<dyn crate::CommandRunner>,
+    speculative: Arc<dyn crate::CommandRunner>,
+    options: SpeculationOptions,
+  ) -> CommandRunner {
+    let speculation_permits = Semaphore::new(options.max_concurrent);
+    CommandRunner {
+      underlying,
+      speculative,
+      options,
+      runtimes: Mutex::default(),
+      speculation_permits,
+    }
+  }
+
+  ///
+  /// How long to let the given process run before speculatively re-executing it, if at all.
+  ///
+  fn speculation_delay(&self, process: &Process) -> Option<Duration> {
+    let runtime = *self.runtimes.lock().get(&process.description)?;
+    Some(std::cmp::max(
+      self.options.min_delay,
+      runtime.mul_f64(self.options.runtime_multiplier),
+    ))
+  }
+
+  fn record_runtime(&self, process: &Process, runtime: Duration) {
+    let mut runtimes = self.runtimes.lock();
+    let average = runtimes
+      .entry(process.description.clone())
+      .or_insert(runtime);
+    *average = (*average * 3 + runtime) / 4;
+  }
+
+  ///
+  /// Runs the given attempt, and returns its result along with how long it took.
+  ///
+  async fn timed(
+    runner: &Arc<dyn crate::CommandRunner>,
+    req: MultiPlatformProcess,
+    context: Context,
+  ) -> (ProcessResult, Duration) {
+    let start = Instant::now();
+    let result = runner.run(req, context).await;
+    (result, start.elapsed())
+  }
+}
+
+#[async_trait]
+impl crate::CommandRunner for CommandRunner {
+  async fn run(&self, req: MultiPlatformProcess, context: Context) -> ProcessResult {
+    let process = match self.extract_compatible_request(&req) {
+      Some(process) => process,
+      None => return self.underlying.run(req, context).await,
+    };
+
+    let original = Self::timed(&self.underlying, req.clone(), context.clone()).boxed();
+    let (result, runtime) = if let Some(delay) = self.speculation_delay(&process) {
+      match future::select(original, tokio::time::sleep(delay).boxed()).await {
+        Either::Left((completed, _)) => completed,
+        Either::Right((_, original)) => {
+          if let Ok(_permit) = self.speculation_permits.try_acquire() {
+            debug!(
+              "Speculatively re-executing `{}`, which has run for {:?}.",
+              process.description, delay
+            );
+            context
+              .workunit_store
+              .increment_counter(Metric::RemoteExecutionSpeculationStarted, 1);
+            let speculative = Self::timed(&self.speculative, req, context.clone()).boxed();
+            match future::select(original, speculative).await {
+              Either::Left(((Ok(result), runtime), _)) => {
+                context
+                  .workunit_store
+                  .increment_counter(Metric::RemoteExecutionSpeculationOriginalCompletedFirst, 1);
+                (Ok(result), runtime)
+              }
+              Either::Right(((Ok(result), runtime), _)) => {
+                context.workunit_store.increment_counter(
+                  Metric::RemoteExecutionSpeculationSpeculativeCompletedFirst,
+                  1,
+                );
+                (Ok(result), runtime)
+              }
+              // One of the attempts failed to run: use the other.
+              Either::Left(((Err(e), _), other)) | Either::Right(((Err(e), _), other)) => {
+                debug!(
+                  "An attempt to run `{}` failed while speculating: {}",
+                  process.description, e
+                );
+                other.await
+              }
+            }
+          } else {
+            original.await
+          }
+        }
+      }
+    } else {
+      original.await
+    };
+
+    if result.is_ok() {
+      self.record_runtime(&process, runtime);
+    }
+    result
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    self.underlying.extract_compatible_request(req)
+  }
+}
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use hashing::EMPTY_DIGEST;
+use parking_lot::Mutex;
+use testutil::owned_string_vec;
+use workunit_store::WorkunitStore;
+
+use crate::speculation::{CommandRunner, SpeculationOptions};
+use crate::{
+  CommandRunner as CommandRunnerTrait, Context, FallibleProcessResultWithPlatform,
+  MultiPlatformProcess, Platform, Process, ProcessResultMetadata,
+};
+
+/// A mock runner whose processes take the given durations (in order) to exit with the given code,
+/// or to fail.
+struct MockCommandRunner {
+  durations: Mutex<VecDeque<Duration>>,
+  result: Result<i32, String>,
+  calls: AtomicUsize,
+}
+
+impl MockCommandRunner {
+  fn new(durations_millis: &[u64], result: Result<i32, String>) -> Arc<MockCommandRunner> {
+    Arc::new(MockCommandRunner {
+      durations: Mutex::new(
+        durations_millis
+          .iter()
+          .map(|millis| Duration::from_millis(*millis))
+          .collect(),
+      ),
+      result,
+      calls: AtomicUsize::new(0),
+    })
+  }
+}
+
+#[async_trait]
+impl CommandRunnerTrait for MockCommandRunner {
+  async fn run(
+    &self,
+    _req: MultiPlatformProcess,
+    _context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    self.calls.fetch_add(1, Ordering::SeqCst);
+    let duration = self.durations.lock().pop_front().unwrap();
+    tokio::time::sleep(duration).await;
+    let exit_code = self.result.clone()?;
+    Ok(FallibleProcessResultWithPlatform {
+      stdout_digest: EMPTY_DIGEST,
+      stderr_digest: EMPTY_DIGEST,
+      exit_code,
+      output_directory: EMPTY_DIGEST,
+      platform: Platform::Linux,
+      metadata: ProcessResultMetadata::default(),
+    })
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    req.0.get(&None).cloned()
+  }
+}
+
+fn process() -> MultiPlatformProcess {
+  let mut process = Process::new(owned_string_vec(&["/bin/true"]));
+  process.description = "Running true".to_owned();
+  process.into()
+}
+
+fn speculation_runner(
+  underlying: Arc<MockCommandRunner>,
+  speculative: Arc<MockCommandRunner>,
+  max_concurrent: usize,
+) -> CommandRunner {
+  CommandRunner::new(
+    underlying,
+    speculative,
+    SpeculationOptions {
+      runtime_multiplier: 2.0,
+      min_delay: Duration::from_millis(50),
+      max_concurrent,
+    },
+  )
+}
+
+#[tokio::test]
+async fn stragglers_are_speculatively_reexecuted() {
+  WorkunitStore::setup_for_tests();
+  let underlying = MockCommandRunner::new(&[10, 10_000], Ok(0));
+  let speculative = MockCommandRunner::new(&[10], Ok(7));
+  let runner = speculation_runner(underlying.clone(), speculative.clone(), 1);
+
+  // Without a historical runtime, the process is not speculatively re-executed.
+  let result = runner.run(process(), Context::default()).await.unwrap();
+  assert_eq!(result.exit_code, 0);
+  assert_eq!(speculative.calls.load(Ordering::SeqCst), 0);
+
+  // But once it has one, the speculative attempt completes first.
+  let start = Instant::now();
+  let result = runner.run(process(), Context::default()).await.unwrap();
+  assert_eq!(result.exit_code, 7);
+  assert_eq!(speculative.calls.load(Ordering::SeqCst), 1);
+  assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn speculation_is_bounded() {
+  WorkunitStore::setup_for_tests();
+  let underlying = MockCommandRunner::new(&[10, 200], Ok(0));
+  let speculative = MockCommandRunner::new(&[10], Ok(7));
+  let runner = speculation_runner(underlying.clone(), speculative.clone(), 0);
+
+  runner.run(process(), Context::default()).await.unwrap();
+  let result = runner.run(process(), Context::default()).await.unwrap();
+  assert_eq!(result.exit_code, 0);
+  assert_eq!(speculative.calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn failed_speculative_attempts_are_ignored() {
+  WorkunitStore::setup_for_tests();
+  let underlying = MockCommandRunner::new(&[10, 200], Ok(0));
+  let speculative = MockCommandRunner::new(&[10], Err("Worker lost.".to_owned()));
+  let runner = speculation_runner(underlying.clone(), speculative.clone(), 1);
+
+  runner.run(process(), Context::default()).await.unwrap();
+  let result = runner.run(process(), Context::default()).await.unwrap();
+  assert_eq!(result.exit_code, 0);
+  assert_eq!(speculative.calls.load(Ordering::SeqCst), 1);
+}
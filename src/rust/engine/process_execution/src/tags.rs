@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
+use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue, MetadataMap};
+use workunit_store::UserMetadataItem;
+
+///
+/// The tag which identifies the target that a Process was run for, which is reported to remote
+/// servers as the `target_id` of the REAPI `RequestMetadata`.
+///
+pub const TARGET_TAG: &str = "target";
+
+///
+/// Free-form tags (such as the goal, target address, or shard) which are attached to a Process in
+/// order to attribute its cost, for example the cost of remote execution by team or by target.
+///
+/// Tags are not part of the cache key of a Process: they are attached to its workunit as metadata,
+/// and are sent to remote servers along with the requests made on its behalf, both in the REAPI
+/// `RequestMetadata` (see `TARGET_TAG`) and as `x-pants-tag-<name>` headers.
+///
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ProcessTags(BTreeMap<String, String>);
+
+impl ProcessTags {
+  pub fn new(tags: BTreeMap<String, String>) -> ProcessTags {
+    ProcessTags(tags)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self.0.get(name).map(|value| value.as_str())
+  }
+
+  ///
+  /// The tags, as workunit metadata.
+  ///
+  pub fn user_metadata(&self) -> Vec<(String, UserMetadataItem)> {
+    self
+      .0
+      .iter()
+      .map(|(name, value)| {
+        (
+          format!("tag.{}", name),
+          UserMetadataItem::ImmediateString(value.clone()),
+        )
+      })
+      .collect()
+  }
+
+  ///
+  /// Records the tags which have a meaning in the REAPI in the given RequestMetadata.
+  ///
+  pub fn apply_to_request_metadata(&self, request_metadata: &mut remexec::RequestMetadata) {
+    if let Some(target) = self.get(TARGET_TAG) {
+      request_metadata.target_id = target.to_owned();
+    }
+  }
+
+  ///
+  /// Adds a header for each tag to the given gRPC metadata. Tags which cannot be represented as
+  /// headers (because their names or values are not valid ASCII metadata) are skipped.
+  ///
+  pub fn apply_to_headers(&self, metadata: &mut MetadataMap) {
+    for (name, value) in &self.0 {
+      let key = AsciiMetadataKey::from_str(&format!("x-pants-tag-{}", name));
+      let value = AsciiMetadataValue::from_str(value);
+      if let (Ok(key), Ok(value)) = (key, value) {
+        metadata.insert(key, value);
+      }
+    }
+  }
+}
@@ -0,0 +1,56 @@
+use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
+use maplit::btreemap;
+use tonic::metadata::MetadataMap;
+use workunit_store::UserMetadataItem;
+
+use crate::tags::ProcessTags;
+
+fn tags() -> ProcessTags {
+  ProcessTags::new(btreemap! {
+    "goal".to_owned() => "test".to_owned(),
+    "target".to_owned() => "src/python/foo:tests".to_owned(),
+  })
+}
+
+#[test]
+fn user_metadata() {
+  assert_eq!(
+    tags().user_metadata(),
+    vec![
+      (
+        "tag.goal".to_owned(),
+        UserMetadataItem::ImmediateString("test".to_owned())
+      ),
+      (
+        "tag.target".to_owned(),
+        UserMetadataItem::ImmediateString("src/python/foo:tests".to_owned())
+      ),
+    ]
+  );
+  assert_eq!(ProcessTags::default().user_metadata(), vec![]);
+}
+
+#[test]
+fn request_metadata() {
+  let mut request_metadata = remexec::RequestMetadata::default();
+  tags().apply_to_request_metadata(&mut request_metadata);
+  assert_eq!(request_metadata.target_id, "src/python/foo:tests");
+
+  let mut request_metadata = remexec::RequestMetadata::default();
+  ProcessTags::default().apply_to_request_metadata(&mut request_metadata);
+  assert_eq!(request_metadata.target_id, "");
+}
+
+#[test]
+fn headers() {
+  let mut metadata = MetadataMap::new();
+  ProcessTags::new(btreemap! {
+    "shard".to_owned() => "3".to_owned(),
+    "Not A Header".to_owned() => "skipped".to_owned(),
+    "owner".to_owned() => "not\nascii".to_owned(),
+  })
+  .apply_to_headers(&mut metadata);
+
+  assert_eq!(metadata.len(), 1);
+  assert_eq!(metadata.get("x-pants-tag-shard").unwrap(), "3");
+}
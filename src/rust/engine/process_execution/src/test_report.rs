@@ -0,0 +1,283 @@
+use std::io::BufRead;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer};
+use serde_json::json;
+
+///
+/// Merges JUnit XML reports (as emitted by e.g. `pytest --junitxml`, or the JUnit and ScalaTest
+/// runners) into a single report, with one `<testsuites>` root element which contains every
+/// `<testsuite>` of the inputs.
+///
+/// The content of each suite is preserved, but the totals of the root element are recomputed from
+/// the test cases of the suites. Suites are emitted in sorted order so that the output is
+/// deterministic regardless of the order of the inputs.
+///
+pub fn merge_junit_xml(reports: &[&[u8]]) -> Result<Vec<u8>, String> {
+  let suites = parse_reports(reports)?;
+  let totals = Totals::of(&suites);
+
+  let mut output = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_vec();
+  output.extend_from_slice(
+    format!(
+      "<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+      totals.tests, totals.failures, totals.errors, totals.skipped, totals.time
+    )
+    .as_bytes(),
+  );
+  for suite in &suites {
+    output.extend_from_slice(&suite.xml);
+    output.push(b'\n');
+  }
+  output.extend_from_slice(b"</testsuites>\n");
+  Ok(output)
+}
+
+///
+/// Merges JUnit XML reports (see `merge_junit_xml`) into a single JSON report, which is easier to
+/// consume than XML for many CI systems. The report has the totals of the run, and an entry for
+/// each suite with its totals and the outcome of each of its test cases.
+///
+pub fn merge_junit_xml_to_json(reports: &[&[u8]]) -> Result<Vec<u8>, String> {
+  let suites = parse_reports(reports)?;
+  let totals = Totals::of(&suites);
+  let report = json!({
+    "tests": totals.tests,
+    "failures": totals.failures,
+    "errors": totals.errors,
+    "skipped": totals.skipped,
+    "time": totals.time,
+    "testsuites": suites.iter().map(|suite| {
+      let totals = Totals::of(std::slice::from_ref(suite));
+      json!({
+        "name": suite.name,
+        "tests": totals.tests,
+        "failures": totals.failures,
+        "errors": totals.errors,
+        "skipped": totals.skipped,
+        "time": totals.time,
+        "testcases": suite.test_cases.iter().map(|test_case| json!({
+          "name": test_case.name,
+          "classname": test_case.classname,
+          "time": test_case.time,
+          "outcome": test_case.outcome.as_str(),
+          "message": test_case.message,
+        })).collect::<Vec<_>>(),
+      })
+    }).collect::<Vec<_>>(),
+  });
+  let mut output = serde_json::to_vec_pretty(&report)
+    .map_err(|e| format!("Failed to render JSON test report: {}", e))?;
+  output.push(b'\n');
+  Ok(output)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Outcome {
+  Passed,
+  Failed,
+  Errored,
+  Skipped,
+}
+
+impl Outcome {
+  fn as_str(self) -> &'static str {
+    match self {
+      Outcome::Passed => "passed",
+      Outcome::Failed => "failed",
+      Outcome::Errored => "error",
+      Outcome::Skipped => "skipped",
+    }
+  }
+}
+
+struct TestCase {
+  name: String,
+  classname: String,
+  time: f64,
+  outcome: Outcome,
+  message: Option<String>,
+}
+
+struct TestSuite {
+  name: String,
+  time: Option<f64>,
+  test_cases: Vec<TestCase>,
+  // The serialized `<testsuite>` element.
+  xml: Vec<u8>,
+}
+
+struct Totals {
+  tests: usize,
+  failures: usize,
+  errors: usize,
+  skipped: usize,
+  time: f64,
+}
+
+impl Totals {
+  fn of(suites: &[TestSuite]) -> Totals {
+    let test_cases = || suites.iter().flat_map(|suite| suite.test_cases.iter());
+    let count = |outcome| {
+      test_cases()
+        .filter(|test_case| test_case.outcome == outcome)
+        .count()
+    };
+    Totals {
+      tests: test_cases().count(),
+      failures: count(Outcome::Failed),
+      errors: count(Outcome::Errored),
+      skipped: count(Outcome::Skipped),
+      time: suites
+        .iter()
+        .map(|suite| {
+          suite.time.unwrap_or_else(|| {
+            suite
+              .test_cases
+              .iter()
+              .map(|test_case| test_case.time)
+              .sum()
+          })
+        })
+        .sum(),
+    }
+  }
+}
+
+fn parse_reports(reports: &[&[u8]]) -> Result<Vec<TestSuite>, String> {
+  let mut suites = Vec::new();
+  for (report_index, report) in reports.iter().enumerate() {
+    parse_junit_xml(report, &mut suites)
+      .map_err(|e| format!("Failed to parse JUnit XML report {}: {}", report_index, e))?;
+  }
+  suites.sort_by(|a, b| (&a.name, &a.xml).cmp(&(&b.name, &b.xml)));
+  Ok(suites)
+}
+
+///
+/// Parses the top-level `<testsuite>` elements of a report, which may either be the root element,
+/// or the children of a `<testsuites>` root element.
+///
+fn parse_junit_xml(report: &[u8], suites: &mut Vec<TestSuite>) -> Result<(), String> {
+  let mut reader = Reader::from_reader(report);
+  let mut buf = Vec::new();
+  // The suite which is being parsed, its serialized content so far, and the depth of the current
+  // element within it.
+  let mut current: Option<(TestSuite, Writer<Vec<u8>>, usize)> = None;
+  loop {
+    let event = reader
+      .read_event(&mut buf)
+      .map_err(|e| format!("at position {}: {}", reader.buffer_position(), e))?;
+    if let Event::Eof = event {
+      break;
+    }
+    let finished = match current {
+      None => match event {
+        Event::Start(ref element) | Event::Empty(ref element) if element.name() == b"testsuite" => {
+          let mut suite = TestSuite {
+            name: attribute(&reader, element, b"name")?.unwrap_or_default(),
+            time: attribute(&reader, element, b"time")?.and_then(|time| parse_time(&time)),
+            test_cases: Vec::new(),
+            xml: Vec::new(),
+          };
+          let mut writer = Writer::new(Vec::new());
+          writer.write_event(&event).map_err(|e| e.to_string())?;
+          if let Event::Empty(_) = event {
+            suite.xml = writer.into_inner();
+            suites.push(suite);
+          } else {
+            current = Some((suite, writer, 0));
+          }
+          false
+        }
+        // Declarations, comments, and the `<testsuites>` root element.
+        _ => false,
+      },
+      Some((ref mut suite, ref mut writer, ref mut depth)) => {
+        writer.write_event(&event).map_err(|e| e.to_string())?;
+        match event {
+          Event::Start(ref element) => {
+            *depth += 1;
+            parse_element(&reader, suite, element)?;
+            false
+          }
+          Event::Empty(ref element) => {
+            parse_element(&reader, suite, element)?;
+            false
+          }
+          Event::End(_) if *depth == 0 => true,
+          Event::End(_) => {
+            *depth -= 1;
+            false
+          }
+          _ => false,
+        }
+      }
+    };
+    if finished {
+      let (mut suite, writer, _) = current.take().unwrap();
+      suite.xml = writer.into_inner();
+      suites.push(suite);
+    }
+    buf.clear();
+  }
+  if current.is_some() {
+    return Err("a <testsuite> element was not closed".to_owned());
+  }
+  Ok(())
+}
+
+///
+/// Records the test cases within a suite, and their outcomes.
+///
+fn parse_element<B: BufRead>(
+  reader: &Reader<B>,
+  suite: &mut TestSuite,
+  element: &BytesStart,
+) -> Result<(), String> {
+  let outcome = match element.name() {
+    b"testcase" => {
+      suite.test_cases.push(TestCase {
+        name: attribute(reader, element, b"name")?.unwrap_or_default(),
+        classname: attribute(reader, element, b"classname")?.unwrap_or_default(),
+        time: attribute(reader, element, b"time")?
+          .and_then(|time| parse_time(&time))
+          .unwrap_or(0.0),
+        outcome: Outcome::Passed,
+        message: None,
+      });
+      return Ok(());
+    }
+    b"failure" => Outcome::Failed,
+    b"error" => Outcome::Errored,
+    b"skipped" => Outcome::Skipped,
+    _ => return Ok(()),
+  };
+  if let Some(test_case) = suite.test_cases.last_mut() {
+    test_case.outcome = outcome;
+    test_case.message = attribute(reader, element, b"message")?;
+  }
+  Ok(())
+}
+
+fn attribute<B: BufRead>(
+  reader: &Reader<B>,
+  element: &BytesStart,
+  name: &[u8],
+) -> Result<Option<String>, String> {
+  for attribute in element.attributes() {
+    let attribute = attribute.map_err(|e| e.to_string())?;
+    if attribute.key == name {
+      return attribute
+        .unescape_and_decode_value(reader)
+        .map(Some)
+        .map_err(|e| e.to_string());
+    }
+  }
+  Ok(None)
+}
+
+fn parse_time(time: &str) -> Option<f64> {
+  // Some runners format large durations with thousands separators.
+  time.replace(',', "").trim().parse().ok()
+}
@@ -0,0 +1,89 @@
+use crate::test_report::{merge_junit_xml, merge_junit_xml_to_json};
+
+const PYTEST_REPORT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<testsuites><testsuite name="pytest" errors="0" failures="1" skipped="1" tests="3" time="1.5"><testcase classname="app.test_app" name="test_ok" time="0.5"/><testcase classname="app.test_app" name="test_bad" time="0.75"><failure message="assert 1 == 2">details &amp; more</failure></testcase><testcase classname="app.test_app" name="test_skip" time="0.25"><skipped message="not today"/></testcase></testsuite></testsuites>
+"#;
+
+const JUNIT_REPORT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="com.example.ExampleTest" tests="1" time="2.0">
+  <testcase name="works" classname="com.example.ExampleTest" time="2.0">
+    <error message="boom"/>
+  </testcase>
+  <system-out><![CDATA[output]]></system-out>
+</testsuite>
+"#;
+
+#[test]
+fn merge_xml() {
+  let merged = merge_junit_xml(&[JUNIT_REPORT.as_bytes(), PYTEST_REPORT.as_bytes()]).unwrap();
+  let merged = String::from_utf8(merged).unwrap();
+
+  assert!(merged.starts_with(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+     <testsuites tests=\"4\" failures=\"1\" errors=\"1\" skipped=\"1\" time=\"3.500\">\n\
+     <testsuite name=\"com.example.ExampleTest\""
+  ));
+  assert!(merged.ends_with("</testsuite>\n</testsuites>\n"));
+  // The content of each suite is preserved.
+  assert!(merged.contains("details &amp; more"));
+  assert!(merged.contains("<![CDATA[output]]>"));
+  assert_eq!(merged.matches("<testsuite ").count(), 2);
+
+  // The output is independent of the order of the inputs.
+  assert_eq!(
+    merged.into_bytes(),
+    merge_junit_xml(&[PYTEST_REPORT.as_bytes(), JUNIT_REPORT.as_bytes()]).unwrap()
+  );
+}
+
+#[test]
+fn merge_empty() {
+  assert_eq!(
+    String::from_utf8(merge_junit_xml(&[]).unwrap()).unwrap(),
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+     <testsuites tests=\"0\" failures=\"0\" errors=\"0\" skipped=\"0\" time=\"0.000\">\n\
+     </testsuites>\n"
+  );
+}
+
+#[test]
+fn merge_json() {
+  let merged =
+    merge_junit_xml_to_json(&[PYTEST_REPORT.as_bytes(), JUNIT_REPORT.as_bytes()]).unwrap();
+  let report: serde_json::Value = serde_json::from_slice(&merged).unwrap();
+
+  assert_eq!(report["tests"], 4);
+  assert_eq!(report["failures"], 1);
+  assert_eq!(report["errors"], 1);
+  assert_eq!(report["skipped"], 1);
+  assert_eq!(report["time"], 3.5);
+
+  let suites = report["testsuites"].as_array().unwrap();
+  assert_eq!(suites.len(), 2);
+  assert_eq!(suites[0]["name"], "com.example.ExampleTest");
+  assert_eq!(suites[0]["errors"], 1);
+  assert_eq!(suites[1]["name"], "pytest");
+  assert_eq!(suites[1]["tests"], 3);
+
+  let test_cases = suites[1]["testcases"].as_array().unwrap();
+  assert_eq!(test_cases[0]["name"], "test_ok");
+  assert_eq!(test_cases[0]["outcome"], "passed");
+  assert_eq!(test_cases[0]["message"], serde_json::Value::Null);
+  assert_eq!(test_cases[1]["outcome"], "failed");
+  assert_eq!(test_cases[1]["message"], "assert 1 == 2");
+  assert_eq!(test_cases[2]["outcome"], "skipped");
+  assert_eq!(test_cases[2]["classname"], "app.test_app");
+}
+
+#[test]
+fn merge_invalid() {
+  let err = merge_junit_xml(&[
+    PYTEST_REPORT.as_bytes(),
+    &b"<testsuite name=\"x\"><testcase></testsuite>"[..],
+  ])
+  .unwrap_err();
+  assert!(err.starts_with("Failed to parse JUnit XML report 1:"));
+
+  let err = merge_junit_xml(&[&b"<testsuite name=\"x\">"[..]]).unwrap_err();
+  assert!(err.contains("was not closed"));
+}
@@ -0,0 +1,264 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use hashing::{Digest, EMPTY_DIGEST};
+use parking_lot::Mutex;
+use regex::Regex;
+use store::Store;
+
+use crate::{
+  CommandRunner, Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, Platform,
+  Process, ProcessResultMetadata,
+};
+
+///
+/// Matches the Processes that a scripted FakeResponse applies to. A matcher with no constraints
+/// matches every Process.
+///
+#[derive(Clone, Debug, Default)]
+pub struct ProcessMatcher {
+  argv_prefix: Vec<String>,
+  description: Option<Regex>,
+}
+
+impl ProcessMatcher {
+  pub fn any() -> ProcessMatcher {
+    ProcessMatcher::default()
+  }
+
+  ///
+  /// Only match Processes whose argv starts with the given arguments.
+  ///
+  pub fn argv_prefix(mut self, argv_prefix: Vec<String>) -> ProcessMatcher {
+    self.argv_prefix = argv_prefix;
+    self
+  }
+
+  ///
+  /// Only match Processes whose description matches (i.e. contains a match of) the given regex.
+  ///
+  pub fn description(mut self, pattern: &str) -> Result<ProcessMatcher, String> {
+    let regex = Regex::new(pattern)
+      .map_err(|e| format!("Invalid description pattern {:?}: {}", pattern, e))?;
+    self.description = Some(regex);
+    Ok(self)
+  }
+
+  pub fn matches(&self, process: &Process) -> bool {
+    process.argv.starts_with(&self.argv_prefix)
+      && self
+        .description
+        .as_ref()
+        .map(|regex| regex.is_match(&process.description))
+        .unwrap_or(true)
+  }
+}
+
+#[derive(Clone, Debug)]
+enum FakeOutcome {
+  Completed {
+    exit_code: i32,
+    stdout: Bytes,
+    stderr: Bytes,
+    output_directory: Digest,
+  },
+  Failed(String),
+}
+
+///
+/// The canned result of a Process which is run by a FakeCommandRunner, and how long it takes to
+/// produce it.
+///
+#[derive(Clone, Debug)]
+pub struct FakeResponse {
+  outcome: FakeOutcome,
+  latency: Duration,
+}
+
+impl FakeResponse {
+  ///
+  /// A Process which exits with the given code, without output (unless it is added).
+  ///
+  pub fn exit(exit_code: i32) -> FakeResponse {
+    FakeResponse {
+      outcome: FakeOutcome::Completed {
+        exit_code,
+        stdout: Bytes::new(),
+        stderr: Bytes::new(),
+        output_directory: EMPTY_DIGEST,
+      },
+      latency: Duration::from_millis(0),
+    }
+  }
+
+  pub fn success() -> FakeResponse {
+    FakeResponse::exit(0)
+  }
+
+  ///
+  /// A Process which fails to run at all (rather than running and exiting unsuccessfully), as if
+  /// the CommandRunner had hit an error: for example, while talking to a remote server.
+  ///
+  pub fn failure(message: String) -> FakeResponse {
+    FakeResponse {
+      outcome: FakeOutcome::Failed(message),
+      latency: Duration::from_millis(0),
+    }
+  }
+
+  pub fn stdout(mut self, bytes: Bytes) -> FakeResponse {
+    if let FakeOutcome::Completed { ref mut stdout, .. } = self.outcome {
+      *stdout = bytes;
+    }
+    self
+  }
+
+  pub fn stderr(mut self, bytes: Bytes) -> FakeResponse {
+    if let FakeOutcome::Completed { ref mut stderr, .. } = self.outcome {
+      *stderr = bytes;
+    }
+    self
+  }
+
+  ///
+  /// The digest of the outputs of the Process, which must already be present in the Store.
+  ///
+  pub fn output_directory(mut self, digest: Digest) -> FakeResponse {
+    if let FakeOutcome::Completed {
+      ref mut output_directory,
+      ..
+    } = self.outcome
+    {
+      *output_directory = digest;
+    }
+    self
+  }
+
+  pub fn latency(mut self, latency: Duration) -> FakeResponse {
+    self.latency = latency;
+    self
+  }
+}
+
+#[derive(Default)]
+struct FakeProcessesState {
+  responses: Vec<(ProcessMatcher, FakeResponse)>,
+  calls: Vec<Process>,
+}
+
+///
+/// The script of a FakeCommandRunner: the responses to the Processes that it runs, and a record
+/// of the Processes that it has run. Clones share the same script, so a script may continue to be
+/// edited (and its calls inspected) after a FakeCommandRunner has been created for it.
+///
+#[derive(Clone, Default)]
+pub struct FakeProcesses {
+  state: Arc<Mutex<FakeProcessesState>>,
+}
+
+impl FakeProcesses {
+  pub fn new() -> FakeProcesses {
+    FakeProcesses::default()
+  }
+
+  ///
+  /// Responds to Processes which match the given matcher with the given response. When more than
+  /// one matcher matches a Process, the response which was added first is used.
+  ///
+  pub fn respond(&self, matcher: ProcessMatcher, response: FakeResponse) {
+    self.state.lock().responses.push((matcher, response));
+  }
+
+  ///
+  /// The Processes which have been run so far, in the order that they started running.
+  ///
+  pub fn calls(&self) -> Vec<Process> {
+    self.state.lock().calls.clone()
+  }
+
+  fn record(&self, process: &Process) -> Option<FakeResponse> {
+    let mut state = self.state.lock();
+    state.calls.push(process.clone());
+    state
+      .responses
+      .iter()
+      .find(|(matcher, _)| matcher.matches(process))
+      .map(|(_, response)| response.clone())
+  }
+}
+
+///
+/// A CommandRunner which does not run any binaries, and which instead responds to Processes with
+/// the results that its FakeProcesses script dictates. Processes which the script does not have a
+/// response for fail. This allows the rules which run Processes to be unit tested.
+///
+pub struct FakeCommandRunner {
+  store: Store,
+  processes: FakeProcesses,
+  platform: Platform,
+}
+
+impl FakeCommandRunner {
+  pub fn new(store: Store, processes: FakeProcesses) -> FakeCommandRunner {
+    FakeCommandRunner {
+      store,
+      processes,
+      platform: Platform::current().unwrap(),
+    }
+  }
+}
+
+#[async_trait]
+impl CommandRunner for FakeCommandRunner {
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    _context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    let process = self
+      .extract_compatible_request(&req)
+      .ok_or_else(|| "No compatible Process was requested.".to_owned())?;
+    let response = self.processes.record(&process).ok_or_else(|| {
+      format!(
+        "The FakeCommandRunner has no response for the Process {:?} ({}).",
+        process.argv, process.description
+      )
+    })?;
+
+    if response.latency > Duration::from_millis(0) {
+      tokio::time::sleep(response.latency).await;
+    }
+
+    match response.outcome {
+      FakeOutcome::Completed {
+        exit_code,
+        stdout,
+        stderr,
+        output_directory,
+      } => {
+        let stdout_digest = self.store.store_file_bytes(stdout, true).await?;
+        let stderr_digest = self.store.store_file_bytes(stderr, true).await?;
+        Ok(FallibleProcessResultWithPlatform {
+          stdout_digest,
+          stderr_digest,
+          exit_code,
+          output_directory,
+          platform: self.platform,
+          metadata: ProcessResultMetadata::default(),
+        })
+      }
+      FakeOutcome::Failed(message) => Err(message),
+    }
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    for compatible_constraint in vec![None, self.platform.into()].iter() {
+      if let Some(compatible_req) = req.0.get(compatible_constraint) {
+        return Some(compatible_req.clone());
+      }
+    }
+    None
+  }
+}
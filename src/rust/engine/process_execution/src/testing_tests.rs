@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use store::Store;
+use tempfile::TempDir;
+use testutil::owned_string_vec;
+use workunit_store::WorkunitStore;
+
+use crate::testing::{FakeCommandRunner, FakeProcesses, FakeResponse, ProcessMatcher};
+use crate::{CommandRunner as CommandRunnerTrait, Context, Process};
+
+fn process(argv: &[&str], description: &str) -> Process {
+  let mut process = Process::new(owned_string_vec(argv));
+  process.description = description.to_owned();
+  process
+}
+
+fn runner() -> (FakeCommandRunner, FakeProcesses, Store, TempDir) {
+  let store_dir = TempDir::new().unwrap();
+  let executor = task_executor::Executor::new();
+  let store = Store::local_only(executor, store_dir.path()).unwrap();
+  let processes = FakeProcesses::new();
+  let runner = FakeCommandRunner::new(store.clone(), processes.clone());
+  (runner, processes, store, store_dir)
+}
+
+#[tokio::test]
+async fn responds_with_the_first_matching_response() {
+  WorkunitStore::setup_for_tests();
+  let (runner, processes, store, _store_dir) = runner();
+  processes.respond(
+    ProcessMatcher::any()
+      .argv_prefix(owned_string_vec(&["/usr/bin/python", "-m", "pytest"]))
+      .description("^Run tests")
+      .unwrap(),
+    FakeResponse::exit(1).stdout(Bytes::from("1 failed")),
+  );
+  processes.respond(ProcessMatcher::any(), FakeResponse::success());
+
+  let result = runner
+    .run(
+      process(
+        &["/usr/bin/python", "-m", "pytest", "foo_test.py"],
+        "Run tests for foo",
+      )
+      .into(),
+      Context::default(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(result.exit_code, 1);
+  assert_eq!(
+    store
+      .load_file_bytes_with(result.stdout_digest, |bytes| Bytes::copy_from_slice(bytes))
+      .await
+      .unwrap()
+      .unwrap()
+      .0,
+    Bytes::from("1 failed")
+  );
+
+  let result = runner
+    .run(
+      process(&["/usr/bin/python", "-m", "pytest"], "Lint foo").into(),
+      Context::default(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(result.exit_code, 0);
+
+  assert_eq!(
+    processes
+      .calls()
+      .into_iter()
+      .map(|p| p.description)
+      .collect::<Vec<_>>(),
+    owned_string_vec(&["Run tests for foo", "Lint foo"])
+  );
+}
+
+#[tokio::test]
+async fn unscripted_processes_and_failures_are_errors() {
+  WorkunitStore::setup_for_tests();
+  let (runner, processes, _store, _store_dir) = runner();
+  processes.respond(
+    ProcessMatcher::any().argv_prefix(owned_string_vec(&["/bin/flaky"])),
+    FakeResponse::failure("Connection reset.".to_owned()).latency(Duration::from_millis(10)),
+  );
+
+  let error = runner
+    .run(process(&["/bin/flaky"], "Flaky").into(), Context::default())
+    .await
+    .unwrap_err();
+  assert_eq!(error, "Connection reset.");
+
+  let error = runner
+    .run(process(&["/bin/other"], "Other").into(), Context::default())
+    .await
+    .unwrap_err();
+  assert!(error.contains("has no response"), "{}", error);
+  assert_eq!(processes.calls().len(), 2);
+}
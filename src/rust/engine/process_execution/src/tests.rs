@@ -2,11 +2,19 @@
 // Licensed under the Apache License, Version 2.0 (see LICENSE).
 
 use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
 use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 use std::time::Duration;
 
-use crate::{Process, ProcessResultMetadata};
+use crate::{
+  BoundedCommandRunner, CommandRunner, Context, FallibleProcessResultWithPlatform,
+  MultiPlatformProcess, Platform, Process, ProcessResultMetadata, EXECUTION_SLOT_ENV_VAR_NAME,
+};
+use async_trait::async_trait;
 use bazel_protos::gen::build::bazel::remote::execution::v2 as remexec;
+use hashing::EMPTY_DIGEST;
+use parking_lot::Mutex;
 use prost_types::Timestamp;
 use remexec::ExecutedActionMetadata;
 
@@ -106,3 +114,66 @@ fn process_result_metadata_time_saved_from_cache() {
     None
   );
 }
+
+/// A mock runner which records the env of each Process that it runs.
+#[derive(Clone, Default)]
+struct EnvRecordingCommandRunner {
+  envs: Arc<Mutex<Vec<BTreeMap<String, String>>>>,
+}
+
+#[async_trait]
+impl CommandRunner for EnvRecordingCommandRunner {
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    _context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    let process = self.extract_compatible_request(&req).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    self.envs.lock().push(process.env);
+    Ok(FallibleProcessResultWithPlatform {
+      stdout_digest: EMPTY_DIGEST,
+      stderr_digest: EMPTY_DIGEST,
+      exit_code: 0,
+      output_directory: EMPTY_DIGEST,
+      platform: Platform::Linux,
+      metadata: ProcessResultMetadata::default(),
+    })
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    req.0.get(&None).cloned()
+  }
+}
+
+#[tokio::test]
+async fn bounded_runner_exposes_distinct_execution_slots() {
+  let underlying = EnvRecordingCommandRunner::default();
+  let runner = BoundedCommandRunner::new(
+    Box::new(underlying.clone()),
+    2,
+    Some(EXECUTION_SLOT_ENV_VAR_NAME.to_owned()),
+  );
+
+  let mut requested_slot_var = Process::new(vec!["/bin/true".to_owned()]);
+  requested_slot_var.execution_slot_variable = Some("SLOT".to_owned());
+  let (first, second) = futures::join!(
+    runner.run(requested_slot_var.into(), Context::default()),
+    runner.run(
+      Process::new(vec!["/bin/false".to_owned()]).into(),
+      Context::default()
+    ),
+  );
+  first.unwrap();
+  second.unwrap();
+
+  let envs = underlying.envs.lock();
+  let mut slots = envs
+    .iter()
+    .map(|env| env[EXECUTION_SLOT_ENV_VAR_NAME].clone())
+    .collect::<Vec<_>>();
+  slots.sort();
+  assert_eq!(slots, vec!["1".to_owned(), "2".to_owned()]);
+  let requested = envs.iter().find(|env| env.contains_key("SLOT")).unwrap();
+  assert_eq!(requested["SLOT"], requested[EXECUTION_SLOT_ENV_VAR_NAME]);
+}
@@ -0,0 +1,78 @@
+use std::borrow::Cow;
+
+use regex::bytes::{NoExpand, Regex};
+
+///
+/// A replacement of all of the occurrences of a pattern in the content of a file, which is
+/// equivalent to `sed -e 's/pattern/replacement/g'`.
+///
+/// Patterns are matched against bytes rather than text, so that files which are not valid UTF-8
+/// can still be rewritten.
+///
+#[derive(Clone, Debug)]
+pub struct TextRewrite {
+  pattern: Regex,
+  replacement: Vec<u8>,
+  literal: bool,
+}
+
+impl TextRewrite {
+  ///
+  /// A rewrite of the matches of the given regex, which may refer to capture groups of the regex in
+  /// its replacement as `$1` or `${name}`.
+  ///
+  pub fn regex(pattern: &str, replacement: &str) -> Result<TextRewrite, String> {
+    let pattern =
+      Regex::new(pattern).map_err(|e| format!("Invalid pattern `{}`: {}", pattern, e))?;
+    Ok(TextRewrite {
+      pattern,
+      replacement: replacement.as_bytes().to_vec(),
+      literal: false,
+    })
+  }
+
+  ///
+  /// A rewrite of the occurrences of the given literal string, such as a path prefix.
+  ///
+  pub fn literal(pattern: &str, replacement: &str) -> Result<TextRewrite, String> {
+    if pattern.is_empty() {
+      return Err("A literal pattern may not be empty.".to_owned());
+    }
+    let pattern = Regex::new(&regex::escape(pattern))
+      .map_err(|e| format!("Invalid pattern `{}`: {}", pattern, e))?;
+    Ok(TextRewrite {
+      pattern,
+      replacement: replacement.as_bytes().to_vec(),
+      literal: true,
+    })
+  }
+
+  fn apply<'c>(&self, content: &'c [u8]) -> Cow<'c, [u8]> {
+    if self.literal {
+      self
+        .pattern
+        .replace_all(content, NoExpand(&self.replacement))
+    } else {
+      self
+        .pattern
+        .replace_all(content, self.replacement.as_slice())
+    }
+  }
+}
+
+///
+/// Applies the given rewrites to the content of a file, in order (so that each rewrite applies to
+/// the output of the previous one). Returns None if no rewrite matched, so that unchanged files
+/// do not need to be stored again.
+///
+pub fn rewrite(rewrites: &[TextRewrite], content: &[u8]) -> Option<Vec<u8>> {
+  let mut rewritten: Option<Vec<u8>> = None;
+  for rewrite in rewrites {
+    let output = match rewrite.apply(rewritten.as_deref().unwrap_or(content)) {
+      Cow::Owned(output) => output,
+      Cow::Borrowed(_) => continue,
+    };
+    rewritten = Some(output);
+  }
+  rewritten
+}
@@ -0,0 +1,53 @@
+use crate::text_rewrite::{rewrite, TextRewrite};
+
+fn rewritten(rewrites: &[TextRewrite], content: &str) -> Option<String> {
+  rewrite(rewrites, content.as_bytes()).map(|output| String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn literal() {
+  let rewrites = vec![TextRewrite::literal("/tmp/sandbox.1/", "").unwrap()];
+  assert_eq!(
+    rewritten(
+      &rewrites,
+      "SF:/tmp/sandbox.1/src/a.py\nSF:/tmp/sandbox.1/src/b.py\n"
+    ),
+    Some("SF:src/a.py\nSF:src/b.py\n".to_owned())
+  );
+  // Neither the pattern nor the replacement of a literal rewrite are interpreted.
+  let rewrites = vec![TextRewrite::literal("a.py", "$1").unwrap()];
+  assert_eq!(
+    rewritten(&rewrites, "a.py aXpy"),
+    Some("$1 aXpy".to_owned())
+  );
+  assert!(TextRewrite::literal("", "x").is_err());
+}
+
+#[test]
+fn regex() {
+  let rewrites = vec![TextRewrite::regex(r"import (\w+)_pb2", "import ${1}_pb2_grpc").unwrap()];
+  assert_eq!(
+    rewritten(&rewrites, "import foo_pb2\nimport bar\n"),
+    Some("import foo_pb2_grpc\nimport bar\n".to_owned())
+  );
+  assert!(TextRewrite::regex("(unclosed", "").is_err());
+}
+
+#[test]
+fn applied_in_order() {
+  let rewrites = vec![
+    TextRewrite::literal("a", "b").unwrap(),
+    TextRewrite::literal("b", "c").unwrap(),
+  ];
+  assert_eq!(rewritten(&rewrites, "ab"), Some("cc".to_owned()));
+}
+
+#[test]
+fn unchanged() {
+  let rewrites = vec![
+    TextRewrite::literal("/tmp/sandbox.1/", "").unwrap(),
+    TextRewrite::regex("^#!.*python$", "#!/usr/bin/env python3").unwrap(),
+  ];
+  assert_eq!(rewritten(&rewrites, "print('hello')\n"), None);
+  assert_eq!(rewritten(&[], "print('hello')\n"), None);
+}
@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+
+use bazel_protos::require_digest;
+use fs::RelativePath;
+use hashing::{Digest, EMPTY_DIGEST};
+use store::{SnapshotOps, Store};
+
+use crate::Process;
+
+///
+/// Validates that the `working_directory` of the given Process is a directory in its
+/// `input_files`.
+///
+/// If the working directory is missing and the Process has `create_working_directory` set, it is
+/// added to the `input_files` of the returned Process (as an empty directory). Otherwise, an error
+/// naming the first missing component of the path is returned, rather than the obscure failure
+/// which would otherwise occur when the process is spawned.
+///
+/// This is applied once, before a Process is run by any CommandRunner, so that the caches (which
+/// compute their keys from the `input_files`) and runners all see the same inputs.
+///
+pub async fn prepare_working_directory(store: &Store, mut req: Process) -> Result<Process, String> {
+  let working_directory = match &req.working_directory {
+    Some(working_directory) if !working_directory.as_os_str().is_empty() => {
+      working_directory.clone()
+    }
+    _ => return Ok(req),
+  };
+
+  let missing = match find_missing_directory(store, req.input_files, &working_directory).await? {
+    Some(missing) => missing,
+    None => return Ok(req),
+  };
+
+  if !req.create_working_directory {
+    return Err(format!(
+      "The working_directory `{}` of process `{}` is not present in its input digest {:?}: \
+       `{}` does not exist.",
+      working_directory.display(),
+      req.description,
+      req.input_files,
+      missing.display(),
+    ));
+  }
+
+  let working_directory_digest = store
+    .add_prefix(EMPTY_DIGEST, working_directory)
+    .await
+    .map_err(|e| format!("Failed to create working_directory: {:?}", e))?;
+  req.input_files = store
+    .merge(vec![req.input_files, working_directory_digest])
+    .await
+    .map_err(|e| format!("Failed to create working_directory: {:?}", e))?;
+  Ok(req)
+}
+
+///
+/// Returns the first prefix of the given path which is not a directory in the given digest, or
+/// None if the entire path is present. Fails if a prefix of the path is a file.
+///
+async fn find_missing_directory(
+  store: &Store,
+  digest: Digest,
+  path: &RelativePath,
+) -> Result<Option<PathBuf>, String> {
+  let mut current_digest = digest;
+  let mut current_path = PathBuf::new();
+  for component in path.components() {
+    let name = component.as_os_str().to_string_lossy();
+    current_path.push(component);
+    let directory = store
+      .load_directory(current_digest)
+      .await?
+      .map(|(directory, _)| directory)
+      .ok_or_else(|| format!("Input digest {:?} was not found.", current_digest))?;
+
+    if directory.files.iter().any(|file| file.name == name) {
+      return Err(format!(
+        "The working_directory `{}` is not a directory: `{}` is a file.",
+        path.display(),
+        current_path.display(),
+      ));
+    }
+    match directory.directories.iter().find(|dir| dir.name == name) {
+      Some(directory_node) => {
+        current_digest = require_digest(directory_node.digest.as_ref())?;
+      }
+      None => return Ok(Some(current_path)),
+    }
+  }
+  Ok(None)
+}
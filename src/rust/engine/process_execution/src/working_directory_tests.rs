@@ -0,0 +1,90 @@
+use fs::RelativePath;
+use hashing::EMPTY_DIGEST;
+use store::{SnapshotOps, Store};
+use tempfile::TempDir;
+use testutil::data::{TestData, TestDirectory};
+use testutil::owned_string_vec;
+
+use crate::working_directory::prepare_working_directory;
+use crate::Process;
+
+async fn prepare_store() -> (Store, TempDir) {
+  let store_dir = TempDir::new().unwrap();
+  let store = Store::local_only(task_executor::Executor::new(), store_dir.path()).unwrap();
+  store
+    .store_file_bytes(TestData::roland().bytes(), false)
+    .await
+    .unwrap();
+  store
+    .record_directory(&TestDirectory::containing_roland().directory(), false)
+    .await
+    .unwrap();
+  store
+    .record_directory(&TestDirectory::nested().directory(), false)
+    .await
+    .unwrap();
+  (store, store_dir)
+}
+
+fn process_in(working_directory: &str, create_working_directory: bool) -> Process {
+  let mut process = Process::new(owned_string_vec(&["/bin/ls"]));
+  process.description = "ls".to_owned();
+  process.input_files = TestDirectory::nested().digest();
+  process.working_directory = Some(RelativePath::new(working_directory).unwrap());
+  process.create_working_directory = create_working_directory;
+  process
+}
+
+#[tokio::test]
+async fn existing_working_directory() {
+  let (store, _store_dir) = prepare_store().await;
+  let process = process_in("cats", false);
+  assert_eq!(
+    prepare_working_directory(&store, process.clone()).await,
+    Ok(process)
+  );
+}
+
+#[tokio::test]
+async fn missing_working_directory_is_created() {
+  let (store, _store_dir) = prepare_store().await;
+  let process = prepare_working_directory(&store, process_in("cats/kittens", true))
+    .await
+    .unwrap();
+
+  let kittens = store
+    .add_prefix(EMPTY_DIGEST, RelativePath::new("cats/kittens").unwrap())
+    .await
+    .unwrap();
+  let expected = store
+    .merge(vec![TestDirectory::nested().digest(), kittens])
+    .await
+    .unwrap();
+  assert_eq!(process.input_files, expected);
+}
+
+#[tokio::test]
+async fn missing_working_directory_is_an_error() {
+  let (store, _store_dir) = prepare_store().await;
+  let err = prepare_working_directory(&store, process_in("dogs/puppies", false))
+    .await
+    .unwrap_err();
+  assert!(
+    err.contains("The working_directory `dogs/puppies` of process `ls`")
+      && err.contains("`dogs` does not exist"),
+    "Unexpected error: {}",
+    err
+  );
+}
+
+#[tokio::test]
+async fn working_directory_which_is_a_file() {
+  let (store, _store_dir) = prepare_store().await;
+  let err = prepare_working_directory(&store, process_in("cats/roland/x", true))
+    .await
+    .unwrap_err();
+  assert_eq!(
+    err,
+    "The working_directory `cats/roland/x` is not a directory: `cats/roland` is a file."
+  );
+}
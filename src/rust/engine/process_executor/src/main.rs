@@ -29,6 +29,7 @@
 #![type_length_limit = "1257309"]
 
 use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
 use std::iter::{FromIterator, Iterator};
 use std::path::PathBuf;
 use std::process::exit;
@@ -39,7 +40,10 @@ use bazel_protos::gen::buildbarn::cas::UncachedActionResult;
 use bazel_protos::require_digest;
 use fs::RelativePath;
 use hashing::{Digest, Fingerprint};
-use process_execution::{Context, NamedCaches, Platform, ProcessCacheScope, ProcessMetadata};
+use process_execution::{
+  Context, DescriptionFields, NamedCaches, OutputChunk, OutputPlatform, Platform,
+  PlatformRequirements, ProcessCacheScope, ProcessMetadata, ProcessTags, ResourceLimits,
+};
 use prost::Message;
 use store::{Store, StoreWrapper};
 use structopt::StructOpt;
@@ -73,6 +77,14 @@ struct CommandSpec {
   #[structopt(long)]
   jdk: Option<PathBuf>,
 
+  /// The key of a remote persistent worker which may be reused to run the process.
+  #[structopt(long)]
+  remote_worker_key: Option<String>,
+
+  /// A key which routes the process to the remote worker which last ran a process with that key.
+  #[structopt(long)]
+  remote_affinity_key: Option<String>,
+
   /// Path to file that is considered to be output.
   #[structopt(long)]
   output_file_path: Vec<PathBuf>,
@@ -81,6 +93,10 @@ struct CommandSpec {
   #[structopt(long)]
   output_directory_path: Vec<PathBuf>,
 
+  /// Path to a file or directory that is considered to be output.
+  #[structopt(long)]
+  output_path: Vec<PathBuf>,
+
   /// Path to execute the binary at relative to its input digest root.
   #[structopt(long)]
   working_directory: Option<PathBuf>,
@@ -180,6 +196,11 @@ struct Opt {
   /// Extra header to pass on remote execution request.
   #[structopt(long)]
   header: Vec<String>,
+
+  /// Run the process this many times (without consulting or populating caches), and report any
+  /// outputs which differed between runs. Exits non-zero if any outputs differed.
+  #[structopt(long)]
+  check_determinism: Option<usize>,
 }
 
 /// A binary which takes args of format:
@@ -232,9 +253,13 @@ async fn main() {
         root_ca_certs,
         headers,
         args.upload_chunk_bytes,
+        None,
         Duration::from_secs(30),
         // TODO: Take a command line arg.
         3,
+        None,
+        None,
+        None,
       )
     }
     (None, None) => Ok(local_only_store),
@@ -254,6 +279,10 @@ async fn main() {
       .collect();
   }
 
+  let request = process_execution::working_directory::prepare_working_directory(&store, request)
+    .await
+    .expect("Invalid working_directory");
+
   let runner: Box<dyn process_execution::CommandRunner> = match args.server {
     Some(address) => {
       let root_ca_certs = if let Some(path) = args.execution_root_ca_cert_file {
@@ -283,6 +312,8 @@ async fn main() {
             Platform::Linux,
             Duration::from_secs(args.overall_deadline_secs),
             Duration::from_millis(100),
+            None,
+            false,
           )
           .expect("Failed to make command runner"),
         )
@@ -299,14 +330,47 @@ async fn main() {
           .named_cache_path
           .unwrap_or_else(NamedCaches::default_path),
       ),
-      true,
+      process_execution::local::KeepSandboxes::Never,
+      process_execution::Redactor::default(),
     )) as Box<dyn process_execution::CommandRunner>,
   };
 
+  if let Some(runs) = args.check_determinism {
+    let report = process_execution::determinism::check_determinism(
+      runner.as_ref(),
+      &store,
+      request.into(),
+      Context::default(),
+      runs,
+    )
+    .await
+    .expect("Error checking determinism");
+    println!("{}", process_execution::determinism::render_report(&report));
+    exit(if report.is_deterministic() { 0 } else { 1 });
+  }
+
+  // Forward the output of the process while it runs. If nothing was streamed (because the result
+  // was cached, or the process ran remotely), the complete output of the result is printed below.
+  let (output_sender, mut output_receiver) = tokio::sync::mpsc::unbounded_channel();
+  let output_forwarder = tokio::spawn(async move {
+    let mut streamed = false;
+    while let Some(chunk) = output_receiver.recv().await {
+      streamed = true;
+      let _ = match chunk {
+        OutputChunk::Stdout(bytes) => write_and_flush(&mut std::io::stdout(), &bytes),
+        OutputChunk::Stderr(bytes) => write_and_flush(&mut std::io::stderr(), &bytes),
+      };
+    }
+    streamed
+  });
+
   let result = runner
-    .run(request.into(), Context::default())
+    .run_streaming(request.into(), Context::default(), output_sender)
     .await
     .expect("Error executing");
+  let streamed = output_forwarder
+    .await
+    .expect("Failed to forward the output of the process");
 
   if let Some(output) = args.materialize_output_to {
     store
@@ -329,11 +393,18 @@ async fn main() {
     .unwrap()
     .0;
 
-  print!("{}", String::from_utf8(stdout).unwrap());
-  eprint!("{}", String::from_utf8(stderr).unwrap());
+  if !streamed {
+    print!("{}", String::from_utf8(stdout).unwrap());
+    eprint!("{}", String::from_utf8(stderr).unwrap());
+  }
   exit(result.exit_code);
 }
 
+fn write_and_flush(destination: &mut dyn Write, bytes: &[u8]) -> std::io::Result<()> {
+  destination.write_all(bytes)?;
+  destination.flush()
+}
+
 async fn make_request(
   store: &Store,
   args: &Opt,
@@ -380,6 +451,12 @@ fn make_request_from_flat_args(
     .iter()
     .map(RelativePath::new)
     .collect::<Result<BTreeSet<_>, _>>()?;
+  let output_paths = args
+    .command
+    .output_path
+    .iter()
+    .map(RelativePath::new)
+    .collect::<Result<BTreeSet<_>, _>>()?;
 
   let working_directory = args
     .command
@@ -393,20 +470,42 @@ fn make_request_from_flat_args(
 
   let process = process_execution::Process {
     argv: args.command.argv.clone(),
+    pipe_from_argv: None,
     env: collection_from_keyvalues(args.command.env.iter()),
     working_directory,
+    create_working_directory: true,
     input_files: input_root_digest,
     output_files,
     output_directories,
+    output_paths,
     timeout: Some(Duration::new(15 * 60, 0)),
+    resource_limits: ResourceLimits::default(),
     description: "process_executor".to_string(),
+    description_fields: DescriptionFields::default(),
+    tags: ProcessTags::default(),
     level: log::Level::Info,
     append_only_caches: BTreeMap::new(),
     jdk_home: args.command.jdk.clone(),
     platform_constraint: None,
+    platform_requirements: PlatformRequirements::default(),
+    output_platform: OutputPlatform::Execution,
     is_nailgunnable: args.use_nailgun,
+    allocate_tty: false,
+    container_image: None,
+    remote_worker_key: args.command.remote_worker_key.clone(),
+    remote_affinity_key: args.command.remote_affinity_key.clone(),
     execution_slot_variable: None,
+    leased_port_variables: vec![],
+    invocation_id_variable: None,
     cache_scope: ProcessCacheScope::Always,
+    discovered_inputs_depfile: None,
+    source_output_files: BTreeSet::new(),
+    version_probes: vec![],
+    version_probe_fingerprint: None,
+    output_scanners: vec![],
+    max_output_bytes: None,
+    batch_argv_prefix_len: None,
+    response_file: None,
   };
 
   let metadata = ProcessMetadata {
@@ -466,12 +565,14 @@ async fn extract_request_from_action_digest(
 
   let process = process_execution::Process {
     argv: command.arguments,
+    pipe_from_argv: None,
     env: command
       .environment_variables
       .iter()
       .map(|env| (env.name.clone(), env.value.clone()))
       .collect(),
     working_directory,
+    create_working_directory: true,
     input_files,
     output_files: command
       .output_files
@@ -483,17 +584,41 @@ async fn extract_request_from_action_digest(
       .iter()
       .map(RelativePath::new)
       .collect::<Result<_, _>>()?,
+    output_paths: command
+      .output_paths
+      .iter()
+      .map(RelativePath::new)
+      .collect::<Result<_, _>>()?,
+    resource_limits: ResourceLimits::default(),
     timeout: action.timeout.map(|timeout| {
       std::time::Duration::from_nanos(timeout.nanos as u64 + timeout.seconds as u64 * 1000000000)
     }),
     execution_slot_variable: None,
+    leased_port_variables: vec![],
+    invocation_id_variable: None,
     description: "".to_string(),
+    description_fields: DescriptionFields::default(),
+    tags: ProcessTags::default(),
     level: log::Level::Error,
     append_only_caches: BTreeMap::new(),
     jdk_home: None,
     platform_constraint: None,
+    platform_requirements: PlatformRequirements::default(),
+    output_platform: OutputPlatform::Execution,
     is_nailgunnable: false,
+    allocate_tty: false,
+    container_image: None,
+    remote_worker_key: None,
+    remote_affinity_key: None,
     cache_scope: ProcessCacheScope::Always,
+    discovered_inputs_depfile: None,
+    source_output_files: BTreeSet::new(),
+    version_probes: vec![],
+    version_probe_fingerprint: None,
+    output_scanners: vec![],
+    max_output_bytes: None,
+    batch_argv_prefix_len: None,
+    response_file: None,
   };
 
   let metadata = ProcessMetadata {
@@ -30,7 +30,7 @@
 use bytes::Bytes;
 use hashing::{Fingerprint, FINGERPRINT_SIZE};
 use lmdb::{
-  self, Database, DatabaseFlags, Environment, EnvironmentCopyFlags, EnvironmentFlags,
+  self, Cursor, Database, DatabaseFlags, Environment, EnvironmentCopyFlags, EnvironmentFlags,
   RwTransaction, Transaction, WriteFlags,
 };
 use log::trace;
@@ -39,8 +39,14 @@ use std::fmt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{self, Duration};
+use task_executor::IoPriority;
 use tempfile::TempDir;
 
+mod readers;
+pub use crate::readers::{
+  begin_ro_txn, clear_stale_readers, describe_readers, max_readers, readers, Reader,
+};
+
 ///
 /// The lease time is relatively short, because we in general would like things to be
 /// garbage collectible. Leases are set on creation, and extended by pantsd for things that
@@ -109,6 +115,7 @@ pub struct ShardedLmdb {
   lmdbs: HashMap<u8, (PathBuf, Arc<Environment>, Database, Database)>,
   root_path: PathBuf,
   max_size_per_shard: usize,
+  max_readers: u32,
   executor: task_executor::Executor,
   lease_time: Duration,
   shard_count: u8,
@@ -144,6 +151,8 @@ impl ShardedLmdb {
     }
 
     let max_size_per_shard = max_size / (shard_count as usize);
+    // Size the reader tables for the number of threads which might read concurrently.
+    let max_readers = max_readers(executor.max_threads());
     // We select which shard to use by masking to select only the relevant number of high order bits
     // from the high order byte of each stored key.
     let shard_fingerprint_mask = {
@@ -162,7 +171,7 @@ impl ShardedLmdb {
     let mut lmdbs = HashMap::new();
 
     for (env, dir, fingerprint_prefix) in
-      ShardedLmdb::envs(&root_path, max_size_per_shard, shard_count)?
+      ShardedLmdb::envs(&root_path, max_size_per_shard, max_readers, shard_count)?
     {
       let content_database = env
         .create_db(Some("content-versioned"), DatabaseFlags::empty())
@@ -192,6 +201,7 @@ impl ShardedLmdb {
       lmdbs,
       root_path,
       max_size_per_shard,
+      max_readers,
       executor,
       lease_time,
       shard_count,
@@ -212,6 +222,7 @@ impl ShardedLmdb {
   fn envs(
     root_path: &Path,
     max_size_per_shard: usize,
+    max_readers: u32,
     shard_count: u8,
   ) -> Result<Vec<(Environment, PathBuf, u8)>, String> {
     let shard_shift = Self::shard_shift(shard_count);
@@ -223,7 +234,7 @@ impl ShardedLmdb {
         .map_err(|err| format!("Error making directory for store at {:?}: {:?}", dir, err))?;
       let fingerprint_prefix = b.rotate_left(shard_shift as u32);
       envs.push((
-        ShardedLmdb::make_env(&dir, max_size_per_shard)?,
+        ShardedLmdb::make_env(&dir, max_size_per_shard, max_readers)?,
         dir,
         fingerprint_prefix,
       ));
@@ -231,7 +242,11 @@ impl ShardedLmdb {
     Ok(envs)
   }
 
-  fn make_env(dir: &Path, max_size_per_shard: usize) -> Result<Environment, String> {
+  fn make_env(
+    dir: &Path,
+    max_size_per_shard: usize,
+    max_readers: u32,
+  ) -> Result<Environment, String> {
     Environment::new()
       // NO_SYNC
       // =======
@@ -267,6 +282,9 @@ impl ShardedLmdb {
       // 2 DBs; one for file contents, one for leases.
       .set_max_dbs(2)
       .set_map_size(max_size_per_shard)
+      // NB: The reader table is stored in the lock file of the environment, and so this only takes
+      // effect when no other process has the environment open.
+      .set_max_readers(max_readers)
       .open(dir)
       .map_err(|e| format!("Error making env for store at {:?}: {}", dir, e))
   }
@@ -292,6 +310,29 @@ impl ShardedLmdb {
       .collect()
   }
 
+  ///
+  /// Flushes the buffers of all shards to disk. Because the environments are opened with NO_SYNC,
+  /// recent writes might otherwise be lost if the machine crashes after this process exits.
+  ///
+  pub async fn sync(&self) -> Result<(), String> {
+    let envs = self
+      .lmdbs
+      .values()
+      .map(|(dir, env, _, _)| (dir.clone(), env.clone()))
+      .collect::<Vec<_>>();
+    self
+      .executor
+      .spawn_blocking(move || {
+        for (dir, env) in envs {
+          env
+            .sync(true)
+            .map_err(|e| format!("Error syncing store at {:?}: {}", dir, e))?;
+        }
+        Ok(())
+      })
+      .await
+  }
+
   pub async fn remove(&self, fingerprint: Fingerprint) -> Result<bool, String> {
     let store = self.clone();
     self
@@ -325,9 +366,7 @@ impl ShardedLmdb {
       .spawn_blocking(move || {
         let fingerprint = effective_key.get_fingerprint();
         let (env, db, _) = store.get(&fingerprint);
-        let txn = env
-          .begin_ro_txn()
-          .map_err(|err| format!("Failed to begin read transaction: {:?}", err))?;
+        let txn = begin_ro_txn(&env)?;
         match txn.get(db, &effective_key) {
           Ok(_) => Ok(true),
           Err(lmdb::Error::NotFound) => Ok(false),
@@ -378,11 +417,69 @@ impl ShardedLmdb {
       .await
   }
 
-  pub async fn lease(&self, fingerprint: Fingerprint) -> Result<(), lmdb::Error> {
+  ///
+  /// Stores a batch of values, using a single write transaction per shard (rather than one per
+  /// value, as `store_bytes` does).
+  ///
+  pub async fn store_bytes_batch(
+    &self,
+    items: Vec<(Fingerprint, Bytes)>,
+    initial_lease: bool,
+  ) -> Result<(), String> {
     let store = self.clone();
     self
       .executor
       .spawn_blocking(move || {
+        let mut items_by_shard: HashMap<u8, Vec<(Fingerprint, Bytes)>> = HashMap::new();
+        for (fingerprint, bytes) in items {
+          items_by_shard
+            .entry(fingerprint.0[0] & store.shard_fingerprint_mask)
+            .or_default()
+            .push((fingerprint, bytes));
+        }
+
+        let lease_until_secs_since_epoch = store.lease_until_secs_since_epoch();
+        for (shard, items) in items_by_shard {
+          let (_, env, db, lease_database) = store.get_raw(shard);
+          let put_res = env.begin_rw_txn().and_then(|mut txn| {
+            for (fingerprint, bytes) in &items {
+              let effective_key =
+                VersionedFingerprint::new(*fingerprint, ShardedLmdb::SCHEMA_VERSION);
+              match txn.put(*db, &effective_key, bytes, WriteFlags::NO_OVERWRITE) {
+                Ok(()) | Err(lmdb::Error::KeyExist) => (),
+                Err(err) => return Err(err),
+              }
+              if initial_lease {
+                store.lease_inner(
+                  *lease_database,
+                  &effective_key,
+                  lease_until_secs_since_epoch,
+                  &mut txn,
+                )?;
+              }
+            }
+            txn.commit()
+          });
+          put_res.map_err(|err| format!("Error storing batch of {} keys: {}", items.len(), err))?;
+        }
+        Ok(())
+      })
+      .await
+  }
+
+  ///
+  /// Extends the lease of the given entry, with the given IO priority: lease extension is usually
+  /// background maintenance.
+  ///
+  pub async fn lease(
+    &self,
+    fingerprint: Fingerprint,
+    io_priority: IoPriority,
+  ) -> Result<(), lmdb::Error> {
+    let store = self.clone();
+    self
+      .executor
+      .spawn_blocking_with_io_priority(io_priority, move || {
         let until_secs_since_epoch: u64 = store.lease_until_secs_since_epoch();
         let (env, _, lease_database) = store.get(&fingerprint);
         env.begin_rw_txn().and_then(|mut txn| {
@@ -434,10 +531,7 @@ impl ShardedLmdb {
       .executor
       .spawn_blocking(move || {
         let (env, db, _) = store.get(&fingerprint);
-        let ro_txn = env
-          .begin_ro_txn()
-          .map_err(|err| format!("Failed to begin read transaction: {}", err));
-        ro_txn.and_then(|txn| match txn.get(db, &effective_key) {
+        begin_ro_txn(&env).and_then(|txn| match txn.get(db, &effective_key) {
           Ok(bytes) => f(bytes).map(Some),
           Err(lmdb::Error::NotFound) => Ok(None),
           Err(err) => Err(format!(
@@ -450,11 +544,122 @@ impl ShardedLmdb {
       .await
   }
 
+  ///
+  /// Loads every value in the store, decoding each with the given function. This reads every shard,
+  /// and so should only be used for small stores, such as indexes.
+  ///
+  pub async fn load_all_with<
+    T: Send + 'static,
+    F: Fn(&[u8]) -> Result<T, String> + Send + Sync + 'static,
+  >(
+    &self,
+    f: F,
+  ) -> Result<Vec<(Fingerprint, T)>, String> {
+    let store = self.clone();
+    self
+      .executor
+      .spawn_blocking(move || {
+        let mut values = Vec::new();
+        for (env, db, _) in store.all_lmdbs() {
+          let txn = begin_ro_txn(&env)?;
+          let mut cursor = txn
+            .open_ro_cursor(db)
+            .map_err(|err| format!("Failed to open lmdb read cursor: {}", err))?;
+          for (key, bytes) in cursor.iter() {
+            let fingerprint = VersionedFingerprint::from_bytes_unsafe(key).get_fingerprint();
+            values.push((fingerprint, f(bytes)?));
+          }
+        }
+        Ok(values)
+      })
+      .await
+  }
+
+  ///
+  /// As `load_all_with`, but additionally returns the time until which each value is leased (which
+  /// is the UNIX_EPOCH for values which have never been leased).
+  ///
+  pub async fn load_all_with_leases<
+    T: Send + 'static,
+    F: Fn(&[u8]) -> Result<T, String> + Send + Sync + 'static,
+  >(
+    &self,
+    f: F,
+  ) -> Result<Vec<(Fingerprint, T, time::SystemTime)>, String> {
+    let store = self.clone();
+    self
+      .executor
+      .spawn_blocking(move || {
+        let mut values = Vec::new();
+        for (env, db, lease_db) in store.all_lmdbs() {
+          let txn = begin_ro_txn(&env)?;
+          let mut cursor = txn
+            .open_ro_cursor(db)
+            .map_err(|err| format!("Failed to open lmdb read cursor: {}", err))?;
+          for (key, bytes) in cursor.iter() {
+            let fingerprint = VersionedFingerprint::from_bytes_unsafe(key).get_fingerprint();
+            let leased_until_secs = match txn.get(lease_db, &key) {
+              Ok(lease_bytes) => {
+                let mut array = [0_u8; 8];
+                array.copy_from_slice(lease_bytes);
+                u64::from_le_bytes(array)
+              }
+              Err(lmdb::Error::NotFound) => 0,
+              Err(err) => return Err(format!("Failed to read lease: {}", err)),
+            };
+            let leased_until = time::UNIX_EPOCH + Duration::from_secs(leased_until_secs);
+            values.push((fingerprint, f(bytes)?, leased_until));
+          }
+        }
+        Ok(values)
+      })
+      .await
+  }
+
+  ///
+  /// Finds the fingerprints of all values in the store whose hex representation starts with the
+  /// given (lowercase) prefix. Keys are sorted, so this seeks to the prefix in each shard rather
+  /// than scanning it.
+  ///
+  pub async fn find_by_prefix(&self, hex_prefix: String) -> Result<Vec<Fingerprint>, String> {
+    // The smallest fingerprint with the prefix is the prefix padded with zeros.
+    let padded = format!("{:0<width$}", hex_prefix, width = FINGERPRINT_SIZE * 2);
+    let start = Fingerprint::from_hex_string(&padded)
+      .map_err(|e| format!("Invalid fingerprint prefix {:?}: {}", hex_prefix, e))?;
+    let start_key = VersionedFingerprint::new(start, 0);
+    let store = self.clone();
+    self
+      .executor
+      .spawn_blocking(move || {
+        let mut fingerprints = Vec::new();
+        for (env, db, _) in store.all_lmdbs() {
+          let txn = begin_ro_txn(&env)?;
+          let mut cursor = txn
+            .open_ro_cursor(db)
+            .map_err(|err| format!("Failed to open lmdb read cursor: {}", err))?;
+          for (key, _) in cursor.iter_from(start_key.as_ref()) {
+            let fingerprint = VersionedFingerprint::from_bytes_unsafe(key).get_fingerprint();
+            if !fingerprint.to_hex().starts_with(&hex_prefix) {
+              break;
+            }
+            fingerprints.push(fingerprint);
+          }
+        }
+        fingerprints.sort();
+        fingerprints.dedup();
+        Ok(fingerprints)
+      })
+      .await
+  }
+
   #[allow(clippy::useless_conversion)] // False positive: https://github.com/rust-lang/rust-clippy/issues/3913
   pub fn compact(&self) -> Result<(), String> {
-    for (env, old_dir, _) in
-      ShardedLmdb::envs(&self.root_path, self.max_size_per_shard, self.shard_count)?
-    {
+    for (env, old_dir, _) in ShardedLmdb::envs(
+      &self.root_path,
+      self.max_size_per_shard,
+      self.max_readers,
+      self.shard_count,
+    )? {
       let new_dir = TempDir::new_in(old_dir.parent().unwrap()).expect("TODO");
       env
         .copy(new_dir.path(), EnvironmentCopyFlags::COMPACT)
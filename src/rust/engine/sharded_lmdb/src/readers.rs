@@ -0,0 +1,192 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::thread;
+use std::time::Duration;
+
+use lmdb::{self, Environment, RoTransaction};
+use log::{debug, warn};
+
+/// The size of the reader table of an environment which LMDB uses by default.
+const DEFAULT_MAX_READERS: u32 = 126;
+
+/// The reader table of an environment is shared by every process which has it open (for example:
+/// pantsd and a concurrent run which does not use it, or concurrent CI jobs on one machine), so
+/// it is sized to allow for this many processes.
+const MAX_CONCURRENT_PROCESSES: u32 = 4;
+
+/// The number of times to retry beginning a read transaction while the reader table is full.
+const READERS_FULL_RETRIES: u32 = 8;
+
+const READERS_FULL_INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+
+///
+/// The size of the reader table for an environment which will be read by at most the given number
+/// of threads per process.
+///
+/// Since `NO_TLS` is used, a thread only holds a reader slot for the duration of a transaction,
+/// and a thread never has more than one read transaction open at once.
+///
+pub fn max_readers(max_threads: Option<usize>) -> u32 {
+  match max_threads {
+    Some(max_threads) => std::cmp::max(
+      DEFAULT_MAX_READERS,
+      max_threads as u32 * MAX_CONCURRENT_PROCESSES,
+    ),
+    None => DEFAULT_MAX_READERS,
+  }
+}
+
+///
+/// Begins a read transaction, retrying (with backoff) if the reader table of the environment is
+/// full. Before the first retry, the slots of any readers which belonged to processes which have
+/// exited are reclaimed.
+///
+/// NB: This blocks while backing off, and so should only be called on blocking threads.
+///
+pub fn begin_ro_txn(env: &Environment) -> Result<RoTransaction<'_>, String> {
+  let mut attempt = 0;
+  let mut backoff = READERS_FULL_INITIAL_BACKOFF;
+  loop {
+    match env.begin_ro_txn() {
+      Err(lmdb::Error::ReadersFull) if attempt < READERS_FULL_RETRIES => {
+        if attempt == 0 {
+          match clear_stale_readers(env) {
+            Ok(0) => thread::sleep(backoff),
+            Ok(cleared) => debug!("Cleared {} stale LMDB readers.", cleared),
+            Err(e) => warn!("{}", e),
+          }
+        } else {
+          thread::sleep(backoff);
+          backoff *= 2;
+        }
+        attempt += 1;
+      }
+      Err(lmdb::Error::ReadersFull) => {
+        return Err(format!(
+          "Failed to begin read transaction after {} retries, because the LMDB reader table is \
+           full. {}",
+          READERS_FULL_RETRIES,
+          describe_readers(env)
+        ))
+      }
+      res => return res.map_err(|err| format!("Failed to begin read transaction: {}", err)),
+    }
+  }
+}
+
+///
+/// Reclaims the reader slots of processes which have exited without releasing them (for example,
+/// because they crashed), and returns the number which were reclaimed.
+///
+pub fn clear_stale_readers(env: &Environment) -> Result<usize, String> {
+  let mut dead: c_int = 0;
+  let rc = unsafe { lmdb_sys::mdb_reader_check(env.env(), &mut dead) };
+  if rc == 0 {
+    Ok(dead as usize)
+  } else {
+    Err(format!(
+      "Failed to check for stale LMDB readers: {}",
+      lmdb::Error::from_err_code(rc)
+    ))
+  }
+}
+
+///
+/// A slot of the reader table of an environment.
+///
+#[derive(Debug, Eq, PartialEq)]
+pub struct Reader {
+  pub pid: i32,
+  pub thread: String,
+  /// The id of the transaction which the reader has open, if any.
+  pub txn_id: Option<u64>,
+  /// False if the process which owns the slot has exited, in which case the slot is stale.
+  pub live: bool,
+}
+
+///
+/// Lists the slots of the reader table of an environment.
+///
+pub fn readers(env: &Environment) -> Result<Vec<Reader>, String> {
+  unsafe extern "C" fn collect_line(msg: *const c_char, ctx: *mut c_void) -> c_int {
+    let lines = &mut *(ctx as *mut Vec<String>);
+    lines.push(CStr::from_ptr(msg).to_string_lossy().into_owned());
+    0
+  }
+
+  let mut lines: Vec<String> = Vec::new();
+  let rc = unsafe {
+    lmdb_sys::mdb_reader_list(
+      env.env(),
+      Some(collect_line),
+      &mut lines as *mut Vec<String> as *mut c_void,
+    )
+  };
+  if rc < 0 {
+    return Err(format!(
+      "Failed to list LMDB readers: {}",
+      lmdb::Error::from_err_code(rc)
+    ));
+  }
+
+  // Each line is either a header, a placeholder for an empty table, or `<pid> <thread> <txnid>`,
+  // where the txnid is `-` for a reader without an open transaction.
+  Ok(
+    lines
+      .iter()
+      .flat_map(|line| line.lines())
+      .filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        let pid = fields.next()?.parse::<i32>().ok()?;
+        let thread = fields.next()?.to_owned();
+        let txn_id = fields.next().and_then(|txn_id| txn_id.parse().ok());
+        Some(Reader {
+          pid,
+          thread,
+          txn_id,
+          live: process_is_running(pid),
+        })
+      })
+      .collect(),
+  )
+}
+
+///
+/// A human readable description of the reader table of an environment, which highlights any stale
+/// readers.
+///
+pub fn describe_readers(env: &Environment) -> String {
+  let readers = match readers(env) {
+    Ok(readers) => readers,
+    Err(e) => return e,
+  };
+  let stale = readers
+    .iter()
+    .filter(|reader| !reader.live)
+    .map(|reader| reader.pid.to_string())
+    .collect::<Vec<_>>();
+  let mut description = format!("There are {} readers", readers.len());
+  if !stale.is_empty() {
+    description.push_str(&format!(
+      ", of which {} belong to processes which are no longer running (pids: {})",
+      stale.len(),
+      stale.join(", ")
+    ));
+  }
+  description.push_str(
+    ". If other processes are reading from the store, consider reducing their parallelism.",
+  );
+  description
+}
+
+fn process_is_running(pid: i32) -> bool {
+  // Signal 0 only checks whether the process exists. EPERM indicates that it exists, but belongs
+  // to another user.
+  if unsafe { libc::kill(pid, 0) } == 0 {
+    return true;
+  }
+  std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
@@ -1,9 +1,17 @@
 use std::collections::HashMap;
+use std::time::SystemTime;
 
+use bytes::Bytes;
+use hashing::Digest;
 use task_executor::Executor;
 use tempfile::TempDir;
 
-use crate::{ShardedLmdb, DEFAULT_LEASE_TIME};
+use lmdb::Transaction;
+
+use crate::{
+  begin_ro_txn, clear_stale_readers, describe_readers, max_readers, readers, ShardedLmdb,
+  DEFAULT_LEASE_TIME,
+};
 
 fn new_store(shard_count: u8) -> (ShardedLmdb, TempDir) {
   let tempdir = TempDir::new().unwrap();
@@ -38,3 +46,126 @@ async fn shard_counts() {
     }
   }
 }
+
+#[tokio::test]
+async fn store_bytes_batch() {
+  let (s, _tempdir) = new_store(4);
+  let items = (0u8..8)
+    .map(|i| {
+      let bytes = Bytes::from(vec![i; 10]);
+      (Digest::of_bytes(&bytes).hash, bytes)
+    })
+    .collect::<Vec<_>>();
+
+  s.store_bytes_batch(items.clone(), true).await.unwrap();
+  // Storing existing values again is not an error.
+  s.store_bytes_batch(items.clone(), false).await.unwrap();
+
+  for (fingerprint, bytes) in items {
+    assert_eq!(
+      s.load_bytes_with(fingerprint, |b| Ok(Bytes::copy_from_slice(b)))
+        .await,
+      Ok(Some(bytes))
+    );
+  }
+}
+
+#[tokio::test]
+async fn load_all_with() {
+  let (s, _tempdir) = new_store(4);
+  let mut items = (0u8..8)
+    .map(|i| {
+      let bytes = Bytes::from(vec![i; 10]);
+      (Digest::of_bytes(&bytes).hash, bytes)
+    })
+    .collect::<Vec<_>>();
+  s.store_bytes_batch(items.clone(), false).await.unwrap();
+
+  let mut loaded = s
+    .load_all_with(|b| Ok(Bytes::copy_from_slice(b)))
+    .await
+    .unwrap();
+  loaded.sort();
+  items.sort();
+  assert_eq!(loaded, items);
+}
+
+#[tokio::test]
+async fn load_all_with_leases() {
+  let (s, _tempdir) = new_store(4);
+  let leased = Bytes::from_static(b"leased");
+  let unleased = Bytes::from_static(b"unleased");
+  s.store_bytes(Digest::of_bytes(&leased).hash, leased.clone(), true)
+    .await
+    .unwrap();
+  s.store_bytes(Digest::of_bytes(&unleased).hash, unleased.clone(), false)
+    .await
+    .unwrap();
+
+  let mut loaded = s
+    .load_all_with_leases(|b| Ok(Bytes::copy_from_slice(b)))
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|(_, bytes, leased_until)| (bytes, leased_until > SystemTime::now()))
+    .collect::<Vec<_>>();
+  loaded.sort();
+  assert_eq!(loaded, vec![(leased, true), (unleased, false)]);
+}
+
+#[tokio::test]
+async fn find_by_prefix() {
+  let (s, _tempdir) = new_store(4);
+  let items = (0u8..8)
+    .map(|i| {
+      let bytes = Bytes::from(vec![i; 10]);
+      (Digest::of_bytes(&bytes).hash, bytes)
+    })
+    .collect::<Vec<_>>();
+  s.store_bytes_batch(items.clone(), false).await.unwrap();
+
+  let fingerprint = items[3].0;
+  let hex = fingerprint.to_hex();
+  assert_eq!(
+    s.find_by_prefix(hex[..12].to_owned()).await.unwrap(),
+    vec![fingerprint]
+  );
+  assert_eq!(
+    s.find_by_prefix(hex[..11].to_owned()).await.unwrap(),
+    vec![fingerprint]
+  );
+  assert_eq!(s.find_by_prefix(String::new()).await.unwrap().len(), 8);
+  assert!(s
+    .find_by_prefix("ffffffffffff".to_owned())
+    .await
+    .unwrap()
+    .is_empty());
+}
+
+#[test]
+fn max_readers_scale_with_threads() {
+  assert_eq!(max_readers(None), 126);
+  assert_eq!(max_readers(Some(8)), 126);
+  assert_eq!(max_readers(Some(64)), 256);
+}
+
+#[tokio::test]
+async fn list_readers() {
+  let (s, _tempdir) = new_store(1);
+  let (env, db, _) = s.all_lmdbs().pop().unwrap();
+
+  let txn = begin_ro_txn(&env).unwrap();
+  assert_eq!(txn.get(db, &[0_u8; 33]), Err(lmdb::Error::NotFound));
+  let current = readers(&env)
+    .unwrap()
+    .into_iter()
+    .filter(|reader| reader.pid == std::process::id() as i32)
+    .collect::<Vec<_>>();
+  assert_eq!(current.len(), 1);
+  assert!(current[0].live);
+  assert!(current[0].txn_id.is_some());
+  assert!(describe_readers(&env).starts_with("There are 1 readers."));
+
+  // None of the readers belong to processes which have exited.
+  assert_eq!(clear_stale_readers(&env), Ok(0));
+}
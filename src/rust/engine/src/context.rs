@@ -2,16 +2,19 @@
 // Licensed under the Apache License, Version 2.0 (see LICENSE).
 
 use std::collections::{BTreeMap, HashSet};
-use std::convert::{Into, TryInto};
+use std::convert::{Into, TryFrom, TryInto};
 use std::future::Future;
 use std::io::Read;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use crate::core::Failure;
+use crate::download_checksums::DownloadChecksums;
+use crate::input_prefetcher::{InputPrefetcher, PrefetchingCommandRunner};
 use crate::intrinsics::Intrinsics;
+use crate::local_binaries::LocalBinaries;
 use crate::nodes::{NodeKey, WrappedNode};
 use crate::session::{Session, Sessions};
 use crate::tasks::{Rule, Tasks};
@@ -19,16 +22,28 @@ use crate::types::Types;
 
 use fs::{safe_create_dir_all_ioerror, GitignoreStyleExcludes, PosixFS};
 use graph::{self, EntryId, Graph, InvalidationResult, NodeContext};
-use log::info;
+use hashing::Digest;
+use log::{debug, info, warn};
 use parking_lot::Mutex;
+use process_execution::access_audit::FileAccessAuditor;
+use process_execution::cache_backfill::CacheBackfill;
+use process_execution::local::KeepSandboxes;
+use process_execution::ports::PortLeases;
+use process_execution::sandbox_retention::SandboxRetention;
+use process_execution::sandbox_slots::SandboxSlots;
+use process_execution::speculation::{SpeculationOptions, SpeculationTarget};
+use process_execution::testing::{FakeCommandRunner, FakeProcesses};
 use process_execution::{
-  self, BoundedCommandRunner, CommandRunner, NamedCaches, Platform, ProcessMetadata,
+  self, BoundedCommandRunner, CommandRunner, NamedCaches, Platform, ProcessMetadata, Redactor,
 };
 use regex::Regex;
 use rule_graph::RuleGraph;
 use sharded_lmdb::ShardedLmdb;
-use store::{self, Store};
-use task_executor::Executor;
+use store::{
+  self, DigestFunction, EndpointStatus, GcRoots, HttpMirrors, RemoteEndpoint, RootLifetime,
+  ShrinkBehavior, Store,
+};
+use task_executor::{Executor, IoPriority, TailTasks};
 use uuid::Uuid;
 use watch::{Invalidatable, InvalidationWatcher};
 
@@ -50,6 +65,9 @@ const PEM_RE_STR: &str =
 ///
 pub struct Core {
   pub graph: Arc<InvalidatableGraph>,
+  // A unique id for the Graph, which distinguishes the records of runs against different Graphs:
+  // see `RunGraph`.
+  pub graph_id: String,
   pub tasks: Tasks,
   pub rule_graph: RuleGraph<Rule>,
   pub types: Types,
@@ -57,16 +75,47 @@ pub struct Core {
   pub executor: Executor,
   store: Store,
   pub command_runner: Box<dyn process_execution::CommandRunner>,
+  pub process_execution_metadata: ProcessMetadata,
+  pub named_caches: NamedCaches,
   pub http_client: reqwest::Client,
   pub vfs: PosixFS,
-  pub watcher: Arc<InvalidationWatcher>,
+  // If None, the filesystem is not watched, and callers must instead explicitly invalidate any
+  // paths which have changed between Sessions.
+  pub watcher: Option<Arc<InvalidationWatcher>>,
   pub build_root: PathBuf,
+  // The directory of the local store: see `doctor::run_checks`.
+  pub local_store_dir: PathBuf,
   pub local_parallelism: usize,
+  // If true, Pants is running in offline mode, and should not attempt network access.
+  pub offline: bool,
   pub sessions: Sessions,
+  pub redactor: Redactor,
+  // The endpoints of the remote services that are in use, which are warmed up eagerly: see
+  // `Core::warm_up_remote_endpoints`.
+  pub remote_endpoints: Vec<RemoteEndpoint>,
+  // The digests of the downloads which were not pinned to an expected digest.
+  pub download_checksums: DownloadChecksums,
+  // The results of testing the binaries which were discovered on the local machine.
+  pub local_binaries: LocalBinaries,
+  // Background work (such as cache writes) which should complete before the process exits: see
+  // `Core::shutdown`.
+  pub tail_tasks: TailTasks,
+  // Local caches other than the Store, which are flushed to disk by `Core::shutdown`.
+  local_caches: Vec<ShardedLmdb>,
+  // The local cache of process results (if enabled), whose outputs are roots during garbage
+  // collection of the Store: see `Core::garbage_collect_store`.
+  process_cache: Option<ShardedLmdb>,
+  // The directory below which local sandboxes are created, and the retention policy for those
+  // which are preserved: see `Core::prune_preserved_sandboxes`.
+  local_execution_root_dir: PathBuf,
+  preserved_sandbox_retention: SandboxRetention,
 }
 
 #[derive(Clone, Debug)]
 pub struct RemotingOptions {
+  // If true, no network access is attempted: remote execution and downloads fail fast, and remote
+  // caches and stores are skipped.
+  pub offline: bool,
   pub execution_enable: bool,
   pub store_address: Option<String>,
   pub execution_address: Option<String>,
@@ -75,22 +124,140 @@ pub struct RemotingOptions {
   pub root_ca_certs_path: Option<PathBuf>,
   pub store_headers: BTreeMap<String, String>,
   pub store_chunk_bytes: usize,
+  pub store_chunk_bytes_bounds: Option<(usize, usize)>,
   pub store_chunk_upload_timeout: Duration,
   pub store_rpc_retries: usize,
+  pub store_rpc_qps: Option<usize>,
+  pub store_bandwidth_bytes_per_sec: Option<usize>,
+  pub store_read_concurrency: Option<usize>,
+  pub store_max_inflight_bytes: Option<usize>,
+  // If set, a file containing a hex-encoded AES-256 key, which is used to encrypt blobs before they
+  // are uploaded to the remote store.
+  pub store_encryption_key_path: Option<PathBuf>,
+  // The base URLs of read-only HTTP mirrors of the CAS: see `store::HttpMirrors`.
+  pub store_mirrors: Vec<String>,
+  // The digest function which the remote store uses to address blobs.
+  pub store_digest_function: DigestFunction,
   pub cache_eager_fetch: bool,
   pub execution_extra_platform_properties: Vec<(String, String)>,
   pub execution_headers: BTreeMap<String, String>,
   pub execution_overall_deadline: Duration,
+  pub execution_rpc_qps: Option<usize>,
+  pub execution_pipeline_uploads: bool,
+  // If set, a key (such as a repository or branch name) which partitions remote action cache
+  // entries, according to the `cache_partition_strategy`.
+  pub cache_partition: Option<String>,
+  pub cache_partition_strategy: CachePartitionStrategy,
+}
+
+///
+/// How the `RemotingOptions::cache_partition` is applied to remote requests.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CachePartitionStrategy {
+  /// Mix the partition into the cache keys of actions, so that only action cache entries are
+  /// partitioned, and blobs in the CAS are shared between partitions.
+  ActionSalt,
+  /// Append the partition to the remote instance name, which partitions all remote state
+  /// (including the CAS).
+  InstanceName,
+}
+
+impl TryFrom<String> for CachePartitionStrategy {
+  type Error = String;
+  fn try_from(variant_candidate: String) -> Result<Self, Self::Error> {
+    match variant_candidate.as_ref() {
+      "action_salt" => Ok(CachePartitionStrategy::ActionSalt),
+      "instance_name" => Ok(CachePartitionStrategy::InstanceName),
+      other => Err(format!(
+        "Unknown value for cache_partition_strategy: {:?}",
+        other
+      )),
+    }
+  }
+}
+
+impl RemotingOptions {
+  ///
+  /// The instance name to use for remote requests, including the cache partition if it is applied
+  /// to the instance name.
+  ///
+  pub fn effective_instance_name(&self) -> Option<String> {
+    match (&self.cache_partition, self.cache_partition_strategy) {
+      (Some(partition), CachePartitionStrategy::InstanceName) => match &self.instance_name {
+        Some(instance_name) if !instance_name.is_empty() => {
+          Some(format!("{}/{}", instance_name, partition))
+        }
+        _ => Some(partition.clone()),
+      },
+      _ => self.instance_name.clone(),
+    }
+  }
+
+  ///
+  /// The cache namespace to mix into the keys of remotely cached actions, including the cache
+  /// partition if it is applied to the action salt.
+  ///
+  pub fn remote_cache_namespace(&self) -> Option<String> {
+    match (&self.cache_partition, self.cache_partition_strategy) {
+      (Some(partition), CachePartitionStrategy::ActionSalt) => {
+        match &self.execution_process_cache_namespace {
+          Some(namespace) => Some(format!("{}/{}", namespace, partition)),
+          None => Some(partition.clone()),
+        }
+      }
+      _ => self.execution_process_cache_namespace.clone(),
+    }
+  }
 }
 
 #[derive(Clone, Debug)]
 pub struct ExecutionStrategyOptions {
   pub local_parallelism: usize,
   pub remote_parallelism: usize,
-  pub local_cleanup: bool,
+  pub keep_sandboxes: KeepSandboxes,
+  // Limits on the sandboxes which are preserved below the local execution root, which are pruned
+  // at startup and after each run: see `process_execution::sandbox_retention`.
+  pub preserved_sandbox_retention: SandboxRetention,
   pub local_cache: bool,
   pub remote_cache_read: bool,
   pub remote_cache_write: bool,
+  // Whether failures to write to the remote cache fail processes, rather than being logged.
+  pub remote_cache_write_strict: bool,
+  // If set, the results of processes which run locally are backfilled to the remote cache in the
+  // background (with this delay between uploads) rather than written as they complete: see
+  // `process_execution::cache_backfill`.
+  pub remote_cache_backfill_interval: Option<Duration>,
+  // The names of environment variables, and patterns, whose values should be redacted from the
+  // outputs of processes.
+  pub redact_env_vars: Vec<String>,
+  pub redact_patterns: Vec<String>,
+  // Quotas for the total size of the outputs of each process (unless overridden by the process),
+  // and of all processes in a run: see `process_execution::quota`.
+  pub max_process_output_bytes: Option<usize>,
+  pub max_run_output_bytes: Option<usize>,
+  // Whether to report the accesses of local processes to undeclared paths outside of their
+  // sandboxes: see `process_execution::access_audit`.
+  pub audit_file_accesses: bool,
+  // Whether to fail local processes which write to paths outside of their sandboxes (which requires
+  // auditing their file accesses).
+  pub fail_on_external_writes: bool,
+  // The maximum number of local sandboxes which may exist at once (including those which are
+  // waiting to be cleaned up): see `process_execution::sandbox_slots`.
+  pub local_max_sandboxes: Option<usize>,
+  // The maximum number of compatible local processes which may run as a batch, and how long to wait
+  // for them to be requested: see `process_execution::batching`.
+  pub local_batch_size: usize,
+  pub local_batch_window: Duration,
+  // Properties of the local machine which processes may require in order to run locally: see
+  // `process_execution::PlatformRequirements`.
+  pub local_platform_properties: BTreeMap<String, String>,
+  // The `docker` CLI which runs processes which set a `container_image` locally: see
+  // `process_execution::docker`.
+  pub docker_binary: PathBuf,
+  // If set, remote executions which run for much longer than they historically have are
+  // speculatively re-executed in the given location: see `process_execution::speculation`.
+  pub speculation: Option<(SpeculationTarget, SpeculationOptions)>,
 }
 
 #[derive(Clone, Debug)]
@@ -101,6 +268,15 @@ pub struct LocalStoreOptions {
   pub directories_max_size_bytes: usize,
   pub lease_time: Duration,
   pub shard_count: u8,
+  pub materialize_umask: u32,
+  pub materialize_digest_xattrs: bool,
+  pub hashing_parallelism: usize,
+  // Whether to run background maintenance of the store (lease extension and garbage collection)
+  // in the idle IO scheduling class.
+  pub maintenance_idle_io: bool,
+  // URLs whose trust-on-first-use digests should be replaced by their next download: see
+  // `DownloadChecksums`.
+  pub download_repin: Vec<String>,
 }
 
 impl From<&LocalStoreOptions> for store::LocalOptions {
@@ -110,6 +286,14 @@ impl From<&LocalStoreOptions> for store::LocalOptions {
       directories_max_size_bytes: lso.directories_max_size_bytes,
       lease_time: lso.lease_time,
       shard_count: lso.shard_count,
+      materialize_umask: lso.materialize_umask,
+      materialize_digest_xattrs: lso.materialize_digest_xattrs,
+      hashing_parallelism: Some(lso.hashing_parallelism),
+      maintenance_io_priority: if lso.maintenance_idle_io {
+        IoPriority::Idle
+      } else {
+        IoPriority::Default
+      },
     }
   }
 }
@@ -128,21 +312,54 @@ impl Core {
       local_store_options.store_dir.clone(),
       local_store_options.into(),
     )?;
-    if enable_remote {
+    let store = if enable_remote {
       let remote_store_address = remote_store_address
         .as_ref()
         .ok_or("Remote store required, but none configured")?;
-      local_only.into_with_remote(
+      let store = local_only.into_with_remote(
         remote_store_address,
-        remoting_opts.instance_name.clone(),
+        remoting_opts.effective_instance_name(),
         root_ca_certs.clone(),
         remoting_opts.store_headers.clone(),
         remoting_opts.store_chunk_bytes,
+        remoting_opts.store_chunk_bytes_bounds,
         remoting_opts.store_chunk_upload_timeout,
         remoting_opts.store_rpc_retries,
-      )
+        remoting_opts.store_rpc_qps,
+        remoting_opts.store_bandwidth_bytes_per_sec,
+        remoting_opts.store_read_concurrency,
+      )?;
+      let store = if let Some(ref path) = remoting_opts.store_encryption_key_path {
+        let key = std::fs::read_to_string(path).map_err(|err| {
+          format!(
+            "Error reading remote store encryption key file {:?}: {}",
+            path, err
+          )
+        })?;
+        let key = hex::decode(key.trim()).map_err(|err| {
+          format!(
+            "The remote store encryption key in {:?} must be hex-encoded: {}",
+            path, err
+          )
+        })?;
+        store.with_remote_encryption(&key)?
+      } else {
+        store
+      };
+      let store = if let Some(max_bytes) = remoting_opts.store_max_inflight_bytes {
+        store.with_remote_max_inflight_bytes(max_bytes)?
+      } else {
+        store
+      };
+      store.with_remote_digest_function(remoting_opts.store_digest_function)
     } else {
       Ok(local_only)
+    }?;
+    if remoting_opts.store_mirrors.is_empty() || remoting_opts.offline {
+      Ok(store)
+    } else {
+      let mirrors = HttpMirrors::new(remoting_opts.store_mirrors.clone())?;
+      Ok(store.with_digest_resolver(Arc::new(mirrors)))
     }
   }
 
@@ -150,6 +367,7 @@ impl Core {
     full_store: &Store,
     remote_store_address: &Option<String>,
     executor: &Executor,
+    build_root: &Path,
     local_execution_root_dir: &Path,
     named_caches_dir: &Path,
     local_store_options: &LocalStoreOptions,
@@ -157,9 +375,27 @@ impl Core {
     root_ca_certs: &Option<Vec<u8>>,
     exec_strategy_opts: &ExecutionStrategyOptions,
     remoting_opts: &RemotingOptions,
-  ) -> Result<Box<dyn CommandRunner>, String> {
-    let remote_caching_used =
-      exec_strategy_opts.remote_cache_read || exec_strategy_opts.remote_cache_write;
+    redactor: &Redactor,
+    tail_tasks: &TailTasks,
+  ) -> Result<
+    (
+      Box<dyn CommandRunner>,
+      Vec<RemoteEndpoint>,
+      Vec<ShardedLmdb>,
+      Option<ShardedLmdb>,
+    ),
+    String,
+  > {
+    let remote_caching_used = (exec_strategy_opts.remote_cache_read
+      || exec_strategy_opts.remote_cache_write
+      || exec_strategy_opts.remote_cache_backfill_interval.is_some())
+      && !remoting_opts.offline;
+
+    // Remote runners additionally partition the keys of the actions that they cache, if configured.
+    let remote_process_execution_metadata = ProcessMetadata {
+      cache_key_gen_version: remoting_opts.remote_cache_namespace(),
+      ..process_execution_metadata.clone()
+    };
 
     // If remote caching is used with eager_fetch, we do not want to use the remote store
     // with the local command runner. This reduces the surface area of where the remote store is
@@ -169,42 +405,150 @@ impl Core {
     } else {
       full_store.clone()
     };
-    let local_command_runner = Box::new(BoundedCommandRunner::new(
-      Box::new(process_execution::local::CommandRunner::new(
-        store_for_local_runner,
+    // Output quotas are enforced directly around the runners which execute processes, so that
+    // outputs which exceed them are never cached.
+    let with_output_quotas = |command_runner: Box<dyn CommandRunner>| -> Box<dyn CommandRunner> {
+      if exec_strategy_opts.max_process_output_bytes.is_none()
+        && exec_strategy_opts.max_run_output_bytes.is_none()
+      {
+        return command_runner;
+      }
+      Box::new(process_execution::quota::CommandRunner::new(
+        command_runner.into(),
+        full_store.clone(),
+        exec_strategy_opts.max_process_output_bytes,
+        exec_strategy_opts.max_run_output_bytes,
+      ))
+    };
+
+    let file_access_auditor =
+      if exec_strategy_opts.audit_file_accesses || exec_strategy_opts.fail_on_external_writes {
+        Some(FileAccessAuditor::new(
+          vec![named_caches_dir.to_path_buf()],
+          exec_strategy_opts.audit_file_accesses,
+          exec_strategy_opts.fail_on_external_writes,
+        )?)
+      } else {
+        None
+      };
+    // Processes which set a `container_image` run in a container rather than directly, but share
+    // the execution slots, sandbox slots and leasable ports of local processes.
+    let sandbox_slots = SandboxSlots::new(exec_strategy_opts.local_max_sandboxes);
+    let port_leases = PortLeases::default();
+    let container_command_runner = Box::new(
+      process_execution::docker::CommandRunner::new(
+        Box::new(
+          process_execution::local::CommandRunner::new(
+            store_for_local_runner.clone(),
+            executor.clone(),
+            local_execution_root_dir.to_path_buf(),
+            NamedCaches::new(named_caches_dir.to_path_buf()),
+            exec_strategy_opts.keep_sandboxes,
+            redactor.clone(),
+          )
+          .with_file_access_auditor(file_access_auditor)
+          .with_sandbox_slots(sandbox_slots.clone())
+          .with_port_leases(port_leases.clone())
+          .with_platform_properties(exec_strategy_opts.local_platform_properties.clone())
+          .with_workspace(build_root.to_path_buf()),
+        ),
+        store_for_local_runner.clone(),
         executor.clone(),
         local_execution_root_dir.to_path_buf(),
         NamedCaches::new(named_caches_dir.to_path_buf()),
-        exec_strategy_opts.local_cleanup,
-      )),
+        exec_strategy_opts.keep_sandboxes,
+        redactor.clone(),
+      )
+      .with_docker_binary(exec_strategy_opts.docker_binary.clone())
+      .with_sandbox_slots(sandbox_slots)
+      .with_port_leases(port_leases)
+      .with_workspace(build_root.to_path_buf()),
+    );
+    let bounded_local_command_runner: Box<dyn CommandRunner> = Box::new(BoundedCommandRunner::new(
+      container_command_runner,
       exec_strategy_opts.local_parallelism,
+      Some(process_execution::EXECUTION_SLOT_ENV_VAR_NAME.to_owned()),
     ));
+    // Process inputs can only be missing locally if the remote store is visible to the local runner
+    // (i.e. if remote cache hits are fetched lazily), and only need to be local if processes run
+    // locally. If so, the inputs of processes which missed the caches are prefetched while they
+    // wait for an execution slot.
+    let bounded_local_command_runner: Box<dyn CommandRunner> =
+      if store_for_local_runner.remote_endpoint().is_some() && !remoting_opts.execution_enable {
+        Box::new(PrefetchingCommandRunner::new(
+          bounded_local_command_runner,
+          InputPrefetcher::new(store_for_local_runner.clone(), executor.clone()),
+        ))
+      } else {
+        bounded_local_command_runner
+      };
+    // Batches of processes are formed beneath the caches (so that only processes which miss are
+    // batched), and each batch occupies a single execution slot.
+    let local_command_runner =
+      with_output_quotas(Box::new(process_execution::batching::CommandRunner::new(
+        bounded_local_command_runner.into(),
+        store_for_local_runner,
+        executor.clone(),
+        exec_strategy_opts.local_batch_size,
+        exec_strategy_opts.local_batch_window,
+      )));
 
     // Possibly either add the remote execution runner or the remote cache runner.
     // `global_options.py` already validates that both are not set at the same time.
+    let mut remote_endpoints = Vec::new();
+    let mut local_caches = Vec::new();
     let maybe_remote_enabled_command_runner: Box<dyn CommandRunner> =
-      if remoting_opts.execution_enable {
-        Box::new(BoundedCommandRunner::new(
-          Box::new(process_execution::remote::CommandRunner::new(
-            // We unwrap because global_options.py will have already validated these are defined.
-            remoting_opts.execution_address.as_ref().unwrap(),
-            remoting_opts.store_address.as_ref().unwrap(),
-            process_execution_metadata.clone(),
-            root_ca_certs.clone(),
-            remoting_opts.execution_headers.clone(),
-            full_store.clone(),
-            // TODO if we ever want to configure the remote platform to be something else we
-            // need to take an option all the way down here and into the remote::CommandRunner struct.
-            Platform::Linux,
-            remoting_opts.execution_overall_deadline,
-            Duration::from_millis(100),
-          )?),
-          exec_strategy_opts.remote_parallelism,
+      if remoting_opts.execution_enable && remoting_opts.offline {
+        // Fail fast rather than waiting for the network to time out.
+        Box::new(process_execution::remote::OfflineCommandRunner::new(
+          Platform::Linux,
         ))
+      } else if remoting_opts.execution_enable {
+        let remote_command_runner = process_execution::remote::CommandRunner::new(
+          // We unwrap because global_options.py will have already validated these are defined.
+          remoting_opts.execution_address.as_ref().unwrap(),
+          remoting_opts.store_address.as_ref().unwrap(),
+          remote_process_execution_metadata.clone(),
+          root_ca_certs.clone(),
+          remoting_opts.execution_headers.clone(),
+          full_store.clone(),
+          // TODO if we ever want to configure the remote platform to be something else we
+          // need to take an option all the way down here and into the remote::CommandRunner struct.
+          Platform::Linux,
+          remoting_opts.execution_overall_deadline,
+          Duration::from_millis(100),
+          remoting_opts.execution_rpc_qps,
+          remoting_opts.execution_pipeline_uploads,
+        )?;
+        remote_endpoints.extend(remote_command_runner.endpoints());
+        let remote_command_runner: Box<dyn CommandRunner> = Box::new(remote_command_runner);
+        // Speculation happens beneath the bound on remote parallelism, so that the runtimes which
+        // determine when a process is speculatively re-executed do not include time spent waiting
+        // for a permit. A speculative attempt runs within the permit of the original attempt.
+        let remote_command_runner: Box<dyn CommandRunner> =
+          if let Some((target, speculation_options)) = &exec_strategy_opts.speculation {
+            let remote_command_runner: Arc<dyn CommandRunner> = remote_command_runner.into();
+            let speculative_command_runner = match target {
+              SpeculationTarget::Local => local_command_runner.into(),
+              SpeculationTarget::Remote => remote_command_runner.clone(),
+            };
+            Box::new(process_execution::speculation::CommandRunner::new(
+              remote_command_runner,
+              speculative_command_runner,
+              speculation_options.clone(),
+            ))
+          } else {
+            remote_command_runner
+          };
+        with_output_quotas(Box::new(BoundedCommandRunner::new(
+          remote_command_runner,
+          exec_strategy_opts.remote_parallelism,
+          None,
+        )))
       } else if remote_caching_used {
-        Box::new(process_execution::remote_cache::CommandRunner::new(
+        let mut remote_cache_command_runner = process_execution::remote_cache::CommandRunner::new(
           local_command_runner.into(),
-          process_execution_metadata.clone(),
+          remote_process_execution_metadata.clone(),
           executor.clone(),
           full_store.clone(),
           remote_store_address.as_ref().unwrap(),
@@ -214,12 +558,38 @@ impl Core {
           exec_strategy_opts.remote_cache_read,
           exec_strategy_opts.remote_cache_write,
           remoting_opts.cache_eager_fetch,
-        )?)
+        )?
+        .with_tail_tasks(tail_tasks.clone())
+        .with_strict_writes(exec_strategy_opts.remote_cache_write_strict);
+        if let Some(interval) = exec_strategy_opts.remote_cache_backfill_interval {
+          let journal = ShardedLmdb::new(
+            local_store_options.store_dir.join("cache_backfill"),
+            local_store_options.process_cache_max_size_bytes,
+            executor.clone(),
+            local_store_options.lease_time,
+            local_store_options.shard_count,
+          )
+          .map_err(|err| format!("Could not initialize journal for cache backfill: {:?}", err))?;
+          local_caches.push(journal.clone());
+          remote_cache_command_runner = remote_cache_command_runner
+            .with_backfill(CacheBackfill::new(journal, full_store.clone(), interval));
+        }
+        remote_endpoints.push(remote_cache_command_runner.endpoint());
+        Box::new(remote_cache_command_runner)
       } else {
         local_command_runner
       };
 
+    // Merge concurrent requests which would run the same action (for example, because they differ
+    // only in variants for other platforms), so that it executes and is cached once.
+    let merging_command_runner: Box<dyn CommandRunner> =
+      Box::new(process_execution::merging::CommandRunner::new(
+        maybe_remote_enabled_command_runner.into(),
+        process_execution_metadata.clone(),
+      ));
+
     // Possibly use the local cache runner, regardless of remote execution/caching.
+    let mut process_cache = None;
     let maybe_local_cached_command_runner = if exec_strategy_opts.local_cache {
       let process_execution_store = ShardedLmdb::new(
         local_store_options.store_dir.join("processes"),
@@ -229,17 +599,24 @@ impl Core {
         local_store_options.shard_count,
       )
       .map_err(|err| format!("Could not initialize store for process cache: {:?}", err))?;
+      local_caches.push(process_execution_store.clone());
+      process_cache = Some(process_execution_store.clone());
       Box::new(process_execution::cache::CommandRunner::new(
-        maybe_remote_enabled_command_runner.into(),
+        merging_command_runner.into(),
         process_execution_store,
         full_store.clone(),
         process_execution_metadata.clone(),
       ))
     } else {
-      maybe_remote_enabled_command_runner
+      merging_command_runner
     };
 
-    Ok(maybe_local_cached_command_runner)
+    Ok((
+      maybe_local_cached_command_runner,
+      remote_endpoints,
+      local_caches,
+      process_cache,
+    ))
   }
 
   fn load_certificates(
@@ -283,12 +660,14 @@ impl Core {
     build_root: PathBuf,
     ignore_patterns: Vec<String>,
     use_gitignore: bool,
+    watch_filesystem: bool,
     local_execution_root_dir: PathBuf,
     named_caches_dir: PathBuf,
     ca_certs_path: Option<PathBuf>,
     local_store_options: LocalStoreOptions,
     remoting_opts: RemotingOptions,
     exec_strategy_opts: ExecutionStrategyOptions,
+    fake_processes: Option<FakeProcesses>,
   ) -> Result<Core, String> {
     // We re-use these certs for both the execution and store service; they're generally tied together.
     let root_ca_certs = if let Some(ref path) = remoting_opts.root_ca_certs_path {
@@ -300,9 +679,12 @@ impl Core {
       None
     };
 
-    let need_remote_store = remoting_opts.execution_enable
-      || exec_strategy_opts.remote_cache_read
-      || exec_strategy_opts.remote_cache_write;
+    // In offline mode, the remote store is skipped: blobs which are missing locally are reported as
+    // missing, rather than waiting for the network to time out.
+    let need_remote_store = !remoting_opts.offline
+      && (remoting_opts.execution_enable
+        || exec_strategy_opts.remote_cache_read
+        || exec_strategy_opts.remote_cache_write);
 
     safe_create_dir_all_ioerror(&local_store_options.store_dir).map_err(|e| {
       format!(
@@ -322,6 +704,7 @@ impl Core {
 
     let store = if (exec_strategy_opts.remote_cache_read || exec_strategy_opts.remote_cache_write)
       && remoting_opts.cache_eager_fetch
+      && !remoting_opts.offline
     {
       // In remote cache mode with eager fetching, the only interaction with the remote CAS
       // should be through the remote cache code paths. Thus, the store seen by the rest of the
@@ -333,24 +716,47 @@ impl Core {
     };
 
     let process_execution_metadata = ProcessMetadata {
-      instance_name: remoting_opts.instance_name.clone(),
+      instance_name: remoting_opts.effective_instance_name(),
       cache_key_gen_version: remoting_opts.execution_process_cache_namespace.clone(),
       platform_properties: remoting_opts.execution_extra_platform_properties.clone(),
     };
 
-    let command_runner = Self::make_command_runner(
-      &full_store,
-      &remoting_opts.store_address,
-      &executor,
-      &local_execution_root_dir,
-      &named_caches_dir,
-      &local_store_options,
-      &process_execution_metadata,
-      &root_ca_certs,
-      &exec_strategy_opts,
-      &remoting_opts,
+    let redactor = Redactor::new(
+      exec_strategy_opts.redact_env_vars.clone(),
+      &exec_strategy_opts.redact_patterns,
     )?;
 
+    let tail_tasks = TailTasks::new();
+    let (command_runner, runner_endpoints, local_caches, process_cache) =
+      if let Some(fake_processes) = fake_processes {
+        // Processes are not run at all: see `process_execution::testing`.
+        let fake_command_runner: Box<dyn CommandRunner> =
+          Box::new(FakeCommandRunner::new(store.clone(), fake_processes));
+        (fake_command_runner, Vec::new(), Vec::new(), None)
+      } else {
+        Self::make_command_runner(
+          &full_store,
+          &remoting_opts.store_address,
+          &executor,
+          &build_root,
+          &local_execution_root_dir,
+          &named_caches_dir,
+          &local_store_options,
+          &process_execution_metadata,
+          &root_ca_certs,
+          &exec_strategy_opts,
+          &remoting_opts,
+          &redactor,
+          &tail_tasks,
+        )?
+      };
+
+    let remote_endpoints = full_store
+      .remote_endpoint()
+      .into_iter()
+      .chain(runner_endpoints)
+      .collect();
+
     let graph = Arc::new(InvalidatableGraph(Graph::new()));
 
     // These certs are for downloads, not to be confused with the ones used for remoting.
@@ -380,13 +786,20 @@ impl Core {
       GitignoreStyleExcludes::create_with_gitignore_file(ignore_patterns, gitignore_file)
         .map_err(|e| format!("Could not parse build ignore patterns: {:?}", e))?;
 
-    let watcher = InvalidationWatcher::new(executor.clone(), build_root.clone(), ignorer.clone())?;
-    watcher.start(&graph);
+    let watcher = if watch_filesystem {
+      let watcher =
+        InvalidationWatcher::new(executor.clone(), build_root.clone(), ignorer.clone())?;
+      watcher.start(&graph);
+      Some(watcher)
+    } else {
+      None
+    };
 
     let sessions = Sessions::new(&executor)?;
 
-    Ok(Core {
+    let core = Core {
       graph,
+      graph_id: uuid::Uuid::new_v4().to_string(),
       tasks,
       rule_graph,
       types,
@@ -394,6 +807,8 @@ impl Core {
       executor: executor.clone(),
       store,
       command_runner,
+      process_execution_metadata,
+      named_caches: NamedCaches::new(named_caches_dir),
       http_client,
       // TODO: Errors in initialization should definitely be exposed as python
       // exceptions, rather than as panics.
@@ -401,14 +816,158 @@ impl Core {
         .map_err(|e| format!("Could not initialize VFS: {:?}", e))?,
       build_root,
       watcher,
+      local_store_dir: local_store_options.store_dir.clone(),
       local_parallelism: exec_strategy_opts.local_parallelism,
+      offline: remoting_opts.offline,
       sessions,
-    })
+      redactor,
+      remote_endpoints,
+      download_checksums: DownloadChecksums::new(
+        local_store_options.store_dir.join("download_checksums"),
+        local_store_options.download_repin.clone(),
+      ),
+      local_binaries: LocalBinaries::default(),
+      tail_tasks,
+      local_caches,
+      process_cache,
+      local_execution_root_dir,
+      preserved_sandbox_retention: exec_strategy_opts.preserved_sandbox_retention,
+    };
+    // Sandboxes which were preserved by previous runs are pruned at startup.
+    core.prune_preserved_sandboxes();
+    Ok(core)
   }
 
   pub fn store(&self) -> Store {
     self.store.clone()
   }
+
+  ///
+  /// Prunes the sandboxes which this workspace preserved below the local execution root down to the
+  /// configured retention policy in the background, and logs a summary of what was pruned.
+  ///
+  pub fn prune_preserved_sandboxes(&self) {
+    if self.preserved_sandbox_retention.is_unbounded() {
+      return;
+    }
+    let retention = self.preserved_sandbox_retention.clone();
+    let root = self.local_execution_root_dir.clone();
+    let workspace = self.build_root.clone();
+    let executor = self.executor.clone();
+    self
+      .tail_tasks
+      .spawn_on("prune preserved sandboxes", &self.executor, async move {
+        let pruned = {
+          let root = root.clone();
+          executor
+            .spawn_blocking(move || retention.prune(&root, &workspace, SystemTime::now()))
+            .await
+        };
+        match pruned {
+          Ok(summary) if summary.pruned_count > 0 => {
+            info!("Preserved sandboxes in {:?}: {}.", root, summary)
+          }
+          Ok(summary) => debug!("Preserved sandboxes in {:?}: {}.", root, summary),
+          Err(e) => warn!("Failed to prune preserved sandboxes in {:?}: {}", root, e),
+        }
+      });
+  }
+
+  ///
+  /// Waits (up to the given timeout) for background work which would otherwise be lost when the
+  /// process exits, such as writes to caches, and then flushes the local caches to disk. Progress
+  /// is logged. Returns the names of the background tasks which did not complete in time.
+  ///
+  /// NB: The Graph itself is held only in memory, and so there is nothing to persist for it.
+  ///
+  pub async fn shutdown(&self, timeout: Duration) -> Vec<String> {
+    let pending = self.tail_tasks.pending().len();
+    if pending > 0 {
+      info!(
+        "Waiting up to {:?} for {} background tasks to complete...",
+        timeout, pending
+      );
+    }
+    let abandoned = self.tail_tasks.wait(timeout).await;
+    if !abandoned.is_empty() {
+      warn!(
+        "{} background tasks did not complete before shutdown, and their work will be lost.",
+        abandoned.len()
+      );
+    }
+
+    debug!("Flushing local caches to disk...");
+    let synced = async {
+      self.store.sync().await?;
+      for local_cache in &self.local_caches {
+        local_cache.sync().await?;
+      }
+      Ok::<(), String>(())
+    }
+    .await;
+    if let Err(e) = synced {
+      warn!("Failed to flush local caches to disk: {}", e);
+    }
+    abandoned
+  }
+
+  ///
+  /// Garbage collects the local Store down to (approximately) the given size. Blobs which are
+  /// referenced by the given values retained in the Graph are never collected, and blobs which are
+  /// referenced by cached process results are retained until those results expire.
+  ///
+  pub async fn garbage_collect_store(
+    &self,
+    target_size_bytes: usize,
+    graph_digests: HashSet<Digest>,
+  ) -> Result<(), String> {
+    let mut roots = if let Some(process_cache) = &self.process_cache {
+      process_execution::cache::gc_roots(process_cache).await?
+    } else {
+      GcRoots::new()
+    };
+    roots.extend(
+      graph_digests
+        .into_iter()
+        .map(|digest| (digest, RootLifetime::Live)),
+    );
+    debug!("Garbage collecting the store with {} roots.", roots.len());
+    self
+      .store
+      .garbage_collect_with_roots(target_size_bytes, ShrinkBehavior::Fast, roots)
+      .await
+  }
+
+  ///
+  /// Eagerly resolves and connects to the remote endpoints in use (in the background), so that the
+  /// first processes to use them do not pay for connection setup. If `only_unhealthy` is set, only
+  /// endpoints whose last warm-up failed (for example: because the network has changed since) are
+  /// warmed up.
+  ///
+  pub fn warm_up_remote_endpoints(&self, only_unhealthy: bool) {
+    for endpoint in &self.remote_endpoints {
+      if only_unhealthy && !matches!(endpoint.status(), EndpointStatus::Unhealthy { .. }) {
+        continue;
+      }
+      let endpoint = endpoint.clone();
+      let _join = self.executor.spawn(async move {
+        match endpoint.warm_up().await {
+          EndpointStatus::Unhealthy { error } => warn!(
+            "Failed to connect to the remote {} at {}: {}",
+            endpoint.name(),
+            endpoint.address(),
+            error
+          ),
+          status => debug!(
+            "Warmed up the remote {} at {}: {:?}",
+            endpoint.name(),
+            endpoint.address(),
+            status
+          ),
+        }
+      });
+    }
+  }
 }
 
 pub struct InvalidatableGraph(Graph<NodeKey>);
@@ -0,0 +1,104 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use store::DigestFunction;
+
+use crate::context::{CachePartitionStrategy, RemotingOptions};
+
+fn remoting_options(
+  instance_name: Option<&str>,
+  cache_namespace: Option<&str>,
+  cache_partition: Option<&str>,
+  cache_partition_strategy: CachePartitionStrategy,
+) -> RemotingOptions {
+  RemotingOptions {
+    offline: false,
+    execution_enable: false,
+    store_address: None,
+    execution_address: None,
+    execution_process_cache_namespace: cache_namespace.map(|s| s.to_owned()),
+    instance_name: instance_name.map(|s| s.to_owned()),
+    root_ca_certs_path: None,
+    store_headers: BTreeMap::new(),
+    store_chunk_bytes: 1024 * 1024,
+    store_chunk_bytes_bounds: None,
+    store_chunk_upload_timeout: Duration::from_secs(30),
+    store_rpc_retries: 1,
+    store_rpc_qps: None,
+    store_bandwidth_bytes_per_sec: None,
+    store_read_concurrency: None,
+    store_max_inflight_bytes: None,
+    store_encryption_key_path: None,
+    store_mirrors: vec![],
+    store_digest_function: DigestFunction::Sha256,
+    cache_eager_fetch: true,
+    execution_extra_platform_properties: vec![],
+    execution_headers: BTreeMap::new(),
+    execution_overall_deadline: Duration::from_secs(60 * 60),
+    execution_rpc_qps: None,
+    execution_pipeline_uploads: false,
+    cache_partition: cache_partition.map(|s| s.to_owned()),
+    cache_partition_strategy,
+  }
+}
+
+#[test]
+fn effective_instance_name() {
+  use CachePartitionStrategy::{ActionSalt, InstanceName};
+  let cases = vec![
+    // (instance_name, cache_partition, strategy, expected)
+    (None, None, InstanceName, None),
+    (Some("main"), None, InstanceName, Some("main")),
+    (None, Some("branch"), InstanceName, Some("branch")),
+    (Some(""), Some("branch"), InstanceName, Some("branch")),
+    (
+      Some("main"),
+      Some("branch"),
+      InstanceName,
+      Some("main/branch"),
+    ),
+    // A partition which is applied to the action salt does not affect the instance name.
+    (None, Some("branch"), ActionSalt, None),
+    (Some("main"), Some("branch"), ActionSalt, Some("main")),
+  ];
+  for (instance_name, cache_partition, strategy, expected) in cases {
+    let options = remoting_options(instance_name, None, cache_partition, strategy);
+    assert_eq!(
+      options.effective_instance_name().as_deref(),
+      expected,
+      "instance_name={:?}, cache_partition={:?}, strategy={:?}",
+      instance_name,
+      cache_partition,
+      strategy,
+    );
+  }
+}
+
+#[test]
+fn remote_cache_namespace() {
+  use CachePartitionStrategy::{ActionSalt, InstanceName};
+  let cases = vec![
+    // (cache_namespace, cache_partition, strategy, expected)
+    (None, None, ActionSalt, None),
+    (Some("v2"), None, ActionSalt, Some("v2")),
+    (None, Some("branch"), ActionSalt, Some("branch")),
+    (Some("v2"), Some("branch"), ActionSalt, Some("v2/branch")),
+    // A partition which is applied to the instance name does not affect the namespace.
+    (None, Some("branch"), InstanceName, None),
+    (Some("v2"), Some("branch"), InstanceName, Some("v2")),
+  ];
+  for (cache_namespace, cache_partition, strategy, expected) in cases {
+    let options = remoting_options(Some("main"), cache_namespace, cache_partition, strategy);
+    assert_eq!(
+      options.remote_cache_namespace().as_deref(),
+      expected,
+      "cache_namespace={:?}, cache_partition={:?}, strategy={:?}",
+      cache_namespace,
+      cache_partition,
+      strategy,
+    );
+  }
+}
@@ -0,0 +1,356 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::ffi::CString;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::future;
+use store::EndpointStatus;
+
+use crate::context::Core;
+
+/// Less free space than this in the local store directory is reported as a warning.
+const MIN_FREE_STORE_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+
+/// A lower soft limit on open files than this is reported as a warning.
+const MIN_OPEN_FILES: u64 = 10_000;
+
+/// A lower limit on inotify watches than this is reported as a warning.
+const MIN_INOTIFY_WATCHES: u64 = 65_536;
+
+/// A larger difference than this between the local clock and a remote endpoint's is reported as a
+/// warning.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(30);
+
+///
+/// The outcome of a single environment check.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CheckStatus {
+  Ok,
+  /// The check passed, but found something which is likely to cause problems.
+  Warning,
+  Failed,
+  /// The check does not apply to this configuration (for example: no remote is in use).
+  Skipped,
+}
+
+impl CheckStatus {
+  pub fn name(&self) -> &'static str {
+    match self {
+      CheckStatus::Ok => "ok",
+      CheckStatus::Warning => "warning",
+      CheckStatus::Failed => "failed",
+      CheckStatus::Skipped => "skipped",
+    }
+  }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Check {
+  pub name: String,
+  pub status: CheckStatus,
+  pub detail: String,
+}
+
+impl Check {
+  fn new(name: &str, status: CheckStatus, detail: impl Into<String>) -> Check {
+    Check {
+      name: name.to_owned(),
+      status,
+      detail: detail.into(),
+    }
+  }
+}
+
+///
+/// Runs a battery of checks of the environment that the engine is running in: the local store,
+/// the filesystem watcher, resource limits, the remote endpoints which are in use, and the clock.
+///
+/// The checks are intended to support triage of problems which are caused by the environment
+/// rather than by a build, and so they report problems rather than failing.
+///
+pub async fn run_checks(core: &Core) -> Vec<Check> {
+  let store_dir = core.local_store_dir.clone();
+  let mut checks = core
+    .executor
+    .spawn_blocking(move || check_store(&store_dir))
+    .await;
+  checks.push(check_watcher(core).await);
+  checks.push(check_open_files());
+  checks.extend(check_remote_endpoints(core).await);
+  checks.push(check_clock_skew(core).await);
+  checks
+}
+
+///
+/// Confirms that the local store directory is writable, and that it has enough free space.
+///
+/// NB: This method blocks, and should be called on a blocking thread.
+///
+fn check_store(store_dir: &Path) -> Vec<Check> {
+  let writable = tempfile::NamedTempFile::new_in(store_dir).and_then(|mut file| {
+    file
+      .write_all(b"doctor")
+      .and_then(|()| file.as_file().sync_all())
+  });
+  let writable = match writable {
+    Ok(()) => Check::new(
+      "store_writable",
+      CheckStatus::Ok,
+      format!("{} is writable.", store_dir.display()),
+    ),
+    Err(e) => Check::new(
+      "store_writable",
+      CheckStatus::Failed,
+      format!("Failed to write to {}: {}", store_dir.display(), e),
+    ),
+  };
+
+  let free_space = match free_bytes(store_dir) {
+    Ok(free) if free < MIN_FREE_STORE_BYTES => Check::new(
+      "store_free_space",
+      CheckStatus::Warning,
+      format!(
+        "Only {} bytes are free in {}: the store may fail to grow.",
+        free,
+        store_dir.display()
+      ),
+    ),
+    Ok(free) => Check::new(
+      "store_free_space",
+      CheckStatus::Ok,
+      format!("{} bytes are free in {}.", free, store_dir.display()),
+    ),
+    Err(e) => Check::new("store_free_space", CheckStatus::Failed, e),
+  };
+
+  vec![writable, free_space]
+}
+
+fn free_bytes(path: &Path) -> Result<u64, String> {
+  let c_path = CString::new(path.as_os_str().as_bytes())
+    .map_err(|e| format!("Invalid path {}: {}", path.display(), e))?;
+  let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+    return Err(format!(
+      "Failed to determine the free space in {}: {}",
+      path.display(),
+      std::io::Error::last_os_error()
+    ));
+  }
+  #[allow(clippy::useless_conversion)]
+  Ok(u64::from(stat.f_bavail) * u64::from(stat.f_frsize))
+}
+
+///
+/// Confirms that the filesystem watcher (if any) is alive, and on Linux, that the limit on inotify
+/// watches is large enough for a typical repository.
+///
+async fn check_watcher(core: &Core) -> Check {
+  let watcher = match core.watcher {
+    Some(ref watcher) => watcher,
+    None => {
+      return Check::new(
+        "watcher",
+        CheckStatus::Skipped,
+        "Filesystem watching is disabled.",
+      )
+    }
+  };
+  if let Err(e) = watcher.is_valid().await {
+    return Check::new("watcher", CheckStatus::Failed, e);
+  }
+  if cfg!(target_os = "linux") {
+    let limit = std::fs::read_to_string("/proc/sys/fs/inotify/max_user_watches")
+      .ok()
+      .and_then(|limit| limit.trim().parse::<u64>().ok());
+    if let Some(limit) = limit {
+      if limit < MIN_INOTIFY_WATCHES {
+        return Check::new(
+          "watcher",
+          CheckStatus::Warning,
+          format!(
+            "The watcher is running, but `max_user_watches` is {}, which may be lower than the \
+             number of files and directories in the repository.",
+            limit
+          ),
+        );
+      }
+    }
+  }
+  Check::new("watcher", CheckStatus::Ok, "The watcher is running.")
+}
+
+///
+/// Confirms that the soft limit on open files is high enough for the local store and sandboxes.
+///
+fn check_open_files() -> Check {
+  let mut limit = libc::rlimit {
+    rlim_cur: 0,
+    rlim_max: 0,
+  };
+  if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+    return Check::new(
+      "open_files_limit",
+      CheckStatus::Failed,
+      format!(
+        "Failed to read the limit on open files: {}",
+        std::io::Error::last_os_error()
+      ),
+    );
+  }
+  if limit.rlim_cur == libc::RLIM_INFINITY {
+    return Check::new(
+      "open_files_limit",
+      CheckStatus::Ok,
+      "The limit on open files is unlimited.",
+    );
+  }
+  #[allow(clippy::useless_conversion)]
+  let (soft, hard) = (u64::from(limit.rlim_cur), u64::from(limit.rlim_max));
+  let status = if soft < MIN_OPEN_FILES {
+    CheckStatus::Warning
+  } else {
+    CheckStatus::Ok
+  };
+  Check::new(
+    "open_files_limit",
+    status,
+    format!(
+      "The limit on open files is {} (with a hard limit of {}).",
+      soft, hard
+    ),
+  )
+}
+
+///
+/// Probes each of the remote endpoints which are in use, which confirms both that they are
+/// reachable, and that they accept our credentials.
+///
+async fn check_remote_endpoints(core: &Core) -> Vec<Check> {
+  if core.offline {
+    return vec![Check::new(
+      "remote",
+      CheckStatus::Skipped,
+      "Pants is running in offline mode.",
+    )];
+  }
+  if core.remote_endpoints.is_empty() {
+    return vec![Check::new(
+      "remote",
+      CheckStatus::Skipped,
+      "No remote endpoints are in use.",
+    )];
+  }
+  let statuses = future::join_all(
+    core
+      .remote_endpoints
+      .iter()
+      .map(|endpoint| endpoint.warm_up()),
+  )
+  .await;
+  core
+    .remote_endpoints
+    .iter()
+    .zip(statuses)
+    .map(|(endpoint, status)| {
+      let name = format!("remote_{}", endpoint.name());
+      match status {
+        EndpointStatus::Healthy { latency } => Check::new(
+          &name,
+          CheckStatus::Ok,
+          format!(
+            "Reached {} in {}ms.",
+            endpoint.address(),
+            latency.as_millis()
+          ),
+        ),
+        EndpointStatus::Unhealthy { error } => Check::new(
+          &name,
+          CheckStatus::Failed,
+          format!("Failed to reach {}: {}", endpoint.address(), error),
+        ),
+        EndpointStatus::Unknown => Check::new(
+          &name,
+          CheckStatus::Failed,
+          format!("{} was not probed.", endpoint.address()),
+        ),
+      }
+    })
+    .collect()
+}
+
+///
+/// Compares the local clock to the `Date` reported by the first remote endpoint which is in use.
+/// Skew confuses remote caches (which expire entries by time) and authentication (which checks
+/// the validity periods of tokens and certificates).
+///
+async fn check_clock_skew(core: &Core) -> Check {
+  let endpoint = match core.remote_endpoints.first() {
+    Some(endpoint) if !core.offline => endpoint,
+    _ => {
+      return Check::new(
+        "clock_skew",
+        CheckStatus::Skipped,
+        "No remote endpoints are in use to compare the clock to.",
+      )
+    }
+  };
+  let start = Instant::now();
+  let response = match core.http_client.head(endpoint.address()).send().await {
+    Ok(response) => response,
+    Err(e) => {
+      return Check::new(
+        "clock_skew",
+        CheckStatus::Skipped,
+        format!(
+          "Could not fetch the time from {}: {}",
+          endpoint.address(),
+          e
+        ),
+      )
+    }
+  };
+  // The remote time was (roughly) sampled halfway through the request.
+  let local_secs = SystemTime::now()
+    .checked_sub(start.elapsed() / 2)
+    .unwrap_or_else(SystemTime::now)
+    .duration_since(UNIX_EPOCH)
+    .expect("Surely you're not before the unix epoch?")
+    .as_secs() as i64;
+  let remote_secs = response
+    .headers()
+    .get(reqwest::header::DATE)
+    .and_then(|date| date.to_str().ok())
+    .and_then(|date| time::strptime(date, "%a, %d %b %Y %H:%M:%S GMT").ok())
+    .map(|tm| tm.to_timespec().sec);
+  let remote_secs = match remote_secs {
+    Some(remote_secs) => remote_secs,
+    None => {
+      return Check::new(
+        "clock_skew",
+        CheckStatus::Skipped,
+        format!("{} did not report a valid Date.", endpoint.address()),
+      )
+    }
+  };
+  let skew = local_secs - remote_secs;
+  let status = if skew.abs() as u64 > MAX_CLOCK_SKEW.as_secs() {
+    CheckStatus::Warning
+  } else {
+    CheckStatus::Ok
+  };
+  Check::new(
+    "clock_skew",
+    status,
+    format!(
+      "The local clock differs from the clock of {} by {}s.",
+      endpoint.address(),
+      skew
+    ),
+  )
+}
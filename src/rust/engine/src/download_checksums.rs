@@ -0,0 +1,127 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use hashing::{Digest, Fingerprint};
+use parking_lot::Mutex;
+
+///
+/// A persistent, trust-on-first-use database of the digests of downloaded files which were not
+/// pinned to an expected digest.
+///
+/// The first download of such a URL records the digest of its content, and later downloads of the
+/// URL (in this or any other run which uses the same local store) must match it. A URL which is
+/// explicitly re-pinned has its recorded digest replaced by the digest of its next download.
+///
+/// The database is a text file with one `{fingerprint} {size_bytes} {url}` entry per line, which
+/// is re-read before each lookup and replaced atomically on each update, so that it may be shared
+/// by concurrent runs.
+///
+pub struct DownloadChecksums {
+  path: PathBuf,
+  repin: HashSet<String>,
+  // Serializes updates within this process.
+  lock: Mutex<()>,
+}
+
+impl DownloadChecksums {
+  pub fn new(path: PathBuf, repin: Vec<String>) -> DownloadChecksums {
+    DownloadChecksums {
+      path,
+      repin: repin.into_iter().collect(),
+      lock: Mutex::new(()),
+    }
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  ///
+  /// Returns the digest that the given URL must match, if one was recorded, and the URL is not
+  /// being re-pinned.
+  ///
+  pub fn expected_digest(&self, url: &str) -> Result<Option<Digest>, String> {
+    if self.repin.contains(url) {
+      return Ok(None);
+    }
+    Ok(self.load()?.get(url).copied())
+  }
+
+  ///
+  /// Records the digest of a download of the given URL, replacing any previous entry.
+  ///
+  pub fn record(&self, url: &str, digest: Digest) -> Result<(), String> {
+    let _lock = self.lock.lock();
+    let mut checksums = self.load()?;
+    if checksums.get(url) == Some(&digest) {
+      return Ok(());
+    }
+    checksums.insert(url.to_owned(), digest);
+
+    let dir = self
+      .path
+      .parent()
+      .ok_or_else(|| format!("{} has no parent directory.", self.path.display()))?;
+    let mut tmp = tempfile::NamedTempFile::new_in(dir).map_err(|e| {
+      format!(
+        "Failed to create temporary file in {}: {}",
+        dir.display(),
+        e
+      )
+    })?;
+    for (url, digest) in &checksums {
+      writeln!(tmp, "{} {} {}", digest.hash, digest.size_bytes, url)
+        .map_err(|e| format!("Failed to write download checksums: {}", e))?;
+    }
+    tmp.persist(&self.path).map_err(|e| {
+      format!(
+        "Failed to persist download checksums to {}: {}",
+        self.path.display(),
+        e
+      )
+    })?;
+    Ok(())
+  }
+
+  fn load(&self) -> Result<BTreeMap<String, Digest>, String> {
+    let content = match std::fs::read_to_string(&self.path) {
+      Ok(content) => content,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+      Err(e) => {
+        return Err(format!(
+          "Failed to read download checksums from {}: {}",
+          self.path.display(),
+          e
+        ))
+      }
+    };
+    content
+      .lines()
+      .filter(|line| !line.trim().is_empty())
+      .map(|line| {
+        let invalid = || {
+          format!(
+            "Invalid entry in download checksums {}: {:?}",
+            self.path.display(),
+            line
+          )
+        };
+        let mut parts = line.splitn(3, ' ');
+        let fingerprint = parts
+          .next()
+          .and_then(|fingerprint| Fingerprint::from_hex_string(fingerprint).ok())
+          .ok_or_else(invalid)?;
+        let size_bytes = parts
+          .next()
+          .and_then(|size_bytes| size_bytes.parse::<usize>().ok())
+          .ok_or_else(invalid)?;
+        let url = parts.next().ok_or_else(invalid)?;
+        Ok((url.to_owned(), Digest::new(fingerprint, size_bytes)))
+      })
+      .collect()
+  }
+}
@@ -43,7 +43,7 @@
 /// how we expose ourselves back to Python.
 use std::any::Any;
 use std::cell::RefCell;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fs::File;
 use std::io;
 use std::os::unix::ffi::OsStrExt;
@@ -58,6 +58,7 @@ use cpython::{
   PyErr, PyInt, PyList, PyObject, PyResult as CPyResult, PyString, PyTuple, PyType, Python,
   PythonObject, ToPyObject,
 };
+use fs::{FileContent, RelativePath};
 use futures::future::FutureExt;
 use futures::future::{self, TryFutureExt};
 use futures::Future;
@@ -65,18 +66,24 @@ use hashing::Digest;
 use log::{self, debug, error, warn, Log};
 use logging::logger::PANTS_LOGGER;
 use logging::{Logger, PythonLogLevel};
+use process_execution::local::KeepSandboxes;
+use process_execution::sandbox_retention::SandboxRetention;
+use process_execution::speculation::{SpeculationOptions, SpeculationTarget};
+use process_execution::SessionPriority;
 use regex::Regex;
 use rule_graph::{self, RuleGraph};
 use std::collections::hash_map::HashMap;
+use store::DigestFunction;
 use task_executor::Executor;
 use workunit_store::{
-  ArtifactOutput, Metric, ObservationMetric, UserMetadataItem, Workunit, WorkunitState,
+  ArtifactOutput, Metric, ObservationMetric, OverflowPolicy, StreamingQueueBounds,
+  UserMetadataItem, Workunit, WorkunitState,
 };
 
 use crate::{
-  externs, nodes, Core, ExecutionRequest, ExecutionStrategyOptions, ExecutionTermination, Failure,
-  Function, Intrinsics, LocalStoreOptions, Params, RemotingOptions, Rule, Scheduler, Session,
-  Tasks, Types, Value,
+  doctor, externs, nodes, CachePartitionStrategy, Core, ExecutionRequest, ExecutionStrategyOptions,
+  ExecutionTermination, Failure, Function, Intrinsics, LocalStoreOptions, NotificationOptions,
+  Params, RemotingOptions, Rule, RunGraph, Scheduler, Session, Tasks, Types, Value,
 };
 
 mod testutil;
@@ -112,9 +119,12 @@ py_module_initializer!(native_engine, |py, m| {
         b: bool,
         c: bool,
         d: bool,
-        e: PyDict,
-        f: Vec<String>,
-        g: String
+        e: bool,
+        f: PyDict,
+        g: Vec<String>,
+        h: Vec<String>,
+        i: Vec<String>,
+        j: String
       )
     ),
   )?;
@@ -153,6 +163,11 @@ py_module_initializer!(native_engine, |py, m| {
     "set_per_run_log_path",
     py_fn!(py, set_per_run_log_path(a: Option<String>)),
   )?;
+  m.add(
+    py,
+    "set_display_full_digests",
+    py_fn!(py, set_display_full_digests(a: bool)),
+  )?;
 
   m.add(
     py,
@@ -187,6 +202,29 @@ py_module_initializer!(native_engine, |py, m| {
       capture_snapshots(a: PyScheduler, b: PySession, c: PyObject)
     ),
   )?;
+  m.add(
+    py,
+    "snapshot_of_file_contents",
+    py_fn!(
+      py,
+      snapshot_of_file_contents(
+        a: PyScheduler,
+        b: PySession,
+        c: Vec<(String, PyBytes, bool)>,
+        d: Vec<String>
+      )
+    ),
+  )?;
+  m.add(
+    py,
+    "print_action",
+    py_fn!(py, print_action(a: PyScheduler, b: PyObject)),
+  )?;
+  m.add(
+    py,
+    "export_repro_bundle",
+    py_fn!(py, export_repro_bundle(a: PyScheduler, b: PyObject)),
+  )?;
   m.add(
     py,
     "run_local_interactive_process",
@@ -207,6 +245,11 @@ py_module_initializer!(native_engine, |py, m| {
     py_fn!(py, graph_invalidate_all_paths(a: PyScheduler)),
   )?;
   m.add(py, "graph_len", py_fn!(py, graph_len(a: PyScheduler)))?;
+  m.add(
+    py,
+    "graph_per_session_nodes",
+    py_fn!(py, graph_per_session_nodes(a: PyScheduler, b: PySession)),
+  )?;
   m.add(
     py,
     "graph_visualize",
@@ -236,7 +279,10 @@ py_module_initializer!(native_engine, |py, m| {
   m.add(
     py,
     "garbage_collect_store",
-    py_fn!(py, garbage_collect_store(a: PyScheduler, b: usize)),
+    py_fn!(
+      py,
+      garbage_collect_store(a: PyScheduler, b: PySession, c: usize)
+    ),
   )?;
   m.add(
     py,
@@ -295,6 +341,11 @@ py_module_initializer!(native_engine, |py, m| {
     "session_new_run_id",
     py_fn!(py, session_new_run_id(a: PySession)),
   )?;
+  m.add(
+    py,
+    "session_get_invocation_id",
+    py_fn!(py, session_get_invocation_id(a: PySession)),
+  )?;
   m.add(
     py,
     "session_poll_workunits",
@@ -303,6 +354,27 @@ py_module_initializer!(native_engine, |py, m| {
       session_poll_workunits(a: PyScheduler, b: PySession, c: u64)
     ),
   )?;
+  m.add(
+    py,
+    "session_set_streaming_workunits_queue_bounds",
+    py_fn!(
+      py,
+      session_set_streaming_workunits_queue_bounds(
+        a: PyScheduler,
+        b: PySession,
+        c: Option<u64>,
+        d: String
+      )
+    ),
+  )?;
+  m.add(
+    py,
+    "session_record_streaming_workunit_callback_time",
+    py_fn!(
+      py,
+      session_record_streaming_workunit_callback_time(a: PyScheduler, b: PySession, c: u64)
+    ),
+  )?;
   m.add(
     py,
     "session_get_observation_histograms",
@@ -319,12 +391,34 @@ py_module_initializer!(native_engine, |py, m| {
       session_record_test_observation(a: PyScheduler, b: PySession, c: u64)
     ),
   )?;
+  m.add(
+    py,
+    "session_get_planned_processes",
+    py_fn!(
+      py,
+      session_get_planned_processes(a: PyScheduler, b: PySession)
+    ),
+  )?;
   m.add(
     py,
     "session_isolated_shallow_clone",
     py_fn!(py, session_isolated_shallow_clone(a: PySession)),
   )?;
 
+  m.add(
+    py,
+    "session_write_run_graph",
+    py_fn!(
+      py,
+      session_write_run_graph(a: PyScheduler, b: PySession, c: String)
+    ),
+  )?;
+  m.add(
+    py,
+    "explain_run_graph_divergence",
+    py_fn!(py, explain_run_graph_divergence(a: String, b: String)),
+  )?;
+
   m.add(py, "all_counter_names", py_fn!(py, all_counter_names()))?;
 
   m.add(
@@ -340,7 +434,8 @@ py_module_initializer!(native_engine, |py, m| {
         e: bool,
         f: String,
         g: String,
-        h: u64
+        h: u64,
+        i: Option<f64>
       )
     ),
   )?;
@@ -369,11 +464,47 @@ py_module_initializer!(native_engine, |py, m| {
       scheduler_execute(a: PyScheduler, b: PySession, c: PyExecutionRequest)
     ),
   )?;
+  m.add(
+    py,
+    "scheduler_warm_up",
+    py_fn!(
+      py,
+      scheduler_warm_up(a: PyScheduler, b: PySession, c: PyExecutionRequest)
+    ),
+  )?;
+  m.add(
+    py,
+    "scheduler_warm_up_remote_endpoints",
+    py_fn!(
+      py,
+      scheduler_warm_up_remote_endpoints(a: PyScheduler, b: bool)
+    ),
+  )?;
+  m.add(
+    py,
+    "scheduler_remote_endpoint_health",
+    py_fn!(py, scheduler_remote_endpoint_health(a: PyScheduler)),
+  )?;
+  m.add(
+    py,
+    "scheduler_shutdown",
+    py_fn!(py, scheduler_shutdown(a: PyScheduler, b: u64)),
+  )?;
+  m.add(
+    py,
+    "engine_doctor",
+    py_fn!(py, engine_doctor(a: PyScheduler)),
+  )?;
   m.add(
     py,
     "scheduler_metrics",
     py_fn!(py, scheduler_metrics(a: PyScheduler, b: PySession)),
   )?;
+  m.add(
+    py,
+    "scheduler_live_node_counts",
+    py_fn!(py, scheduler_live_node_counts(a: PyScheduler, b: PySession)),
+  )?;
   m.add(
     py,
     "scheduler_create",
@@ -389,9 +520,11 @@ py_module_initializer!(native_engine, |py, m| {
         ca_certs_path: Option<String>,
         ignore_patterns: Vec<String>,
         use_gitignore: bool,
+        watch_filesystem: bool,
         remoting_options: PyRemotingOptions,
         local_store_options: PyLocalStoreOptions,
-        exec_strategy_opts: PyExecutionStrategyOptions
+        exec_strategy_opts: PyExecutionStrategyOptions,
+        fake_processes: Option<self::testutil::PyFakeProcesses>
       )
     ),
   )?;
@@ -408,6 +541,18 @@ py_module_initializer!(native_engine, |py, m| {
     py_fn!(py, ensure_remote_has_recursive(a: PyScheduler, b: PyList)),
   )?;
 
+  m.add(
+    py,
+    "expand_digest",
+    py_fn!(py, expand_digest(a: PyScheduler, b: String)),
+  )?;
+
+  m.add(
+    py,
+    "tree_size",
+    py_fn!(py, tree_size(a: PyScheduler, b: PyObject)),
+  )?;
+
   m.add_class::<PyExecutionRequest>(py)?;
   m.add_class::<PyExecutionStrategyOptions>(py)?;
   m.add_class::<PyExecutor>(py)?;
@@ -433,6 +578,7 @@ py_module_initializer!(native_engine, |py, m| {
 
   m.add_class::<self::testutil::PyStubCAS>(py)?;
   m.add_class::<self::testutil::PyStubCASBuilder>(py)?;
+  m.add_class::<self::testutil::PyFakeProcesses>(py)?;
 
   Ok(())
 });
@@ -460,7 +606,15 @@ py_class!(class PyTypes |py| {
       remove_prefix: PyType,
       create_digest: PyType,
       digest_subset: PyType,
+      parse_depfiles: PyType,
+      merge_coverage_data: PyType,
+      merge_test_reports: PyType,
+      rewrite_digest: PyType,
       download_file: PyType,
+      blob_file: PyType,
+      local_binary_path_request: PyType,
+      binary_path: PyType,
+      binary_paths: PyType,
       platform: PyType,
       multi_platform_process: PyType,
       process_result: PyType,
@@ -484,7 +638,15 @@ py_class!(class PyTypes |py| {
         remove_prefix: externs::type_for(remove_prefix),
         create_digest: externs::type_for(create_digest),
         digest_subset: externs::type_for(digest_subset),
+        parse_depfiles: externs::type_for(parse_depfiles),
+        merge_coverage_data: externs::type_for(merge_coverage_data),
+        merge_test_reports: externs::type_for(merge_test_reports),
+        rewrite_digest: externs::type_for(rewrite_digest),
         download_file: externs::type_for(download_file),
+        blob_file: externs::type_for(blob_file),
+        local_binary_path_request: externs::type_for(local_binary_path_request),
+        binary_path: externs::type_for(binary_path),
+        binary_paths: externs::type_for(binary_paths),
         platform: externs::type_for(platform),
         multi_platform_process: externs::type_for(multi_platform_process),
         process_result: externs::type_for(process_result),
@@ -525,19 +687,75 @@ py_class!(class PyExecutionStrategyOptions |py| {
     _cls,
     local_parallelism: u64,
     remote_parallelism: u64,
-    local_cleanup: bool,
+    keep_sandboxes: String,
+    keep_sandboxes_max_count: Option<u64>,
+    keep_sandboxes_max_age_secs: Option<u64>,
+    keep_sandboxes_max_bytes: Option<u64>,
     local_cache: bool,
     remote_cache_read: bool,
-    remote_cache_write: bool
+    remote_cache_write: bool,
+    remote_cache_write_strict: bool,
+    remote_cache_backfill: bool,
+    remote_cache_backfill_interval_millis: u64,
+    redact_env_vars: Vec<String>,
+    redact_patterns: Vec<String>,
+    max_process_output_bytes: Option<u64>,
+    max_run_output_bytes: Option<u64>,
+    audit_file_accesses: bool,
+    fail_on_external_writes: bool,
+    local_max_sandboxes: Option<u64>,
+    local_batch_size: u64,
+    local_batch_window_millis: u64,
+    local_platform_properties: Vec<(String, String)>,
+    docker_binary: String,
+    speculation: String,
+    speculation_runtime_multiplier: f64,
+    speculation_min_delay_millis: u64,
+    speculation_max_concurrent: u64
   ) -> CPyResult<Self> {
+    let speculation = if speculation == "none" {
+      None
+    } else {
+      let target = SpeculationTarget::try_from(speculation)
+        .map_err(|e| PyErr::new::<exc::ValueError, _>(py, (e,)))?;
+      Some((target, SpeculationOptions {
+        runtime_multiplier: speculation_runtime_multiplier,
+        min_delay: Duration::from_millis(speculation_min_delay_millis),
+        max_concurrent: speculation_max_concurrent as usize,
+      }))
+    };
     Self::create_instance(py,
       ExecutionStrategyOptions {
         local_parallelism: local_parallelism as usize,
         remote_parallelism: remote_parallelism as usize,
-        local_cleanup,
+        keep_sandboxes: KeepSandboxes::try_from(keep_sandboxes)
+          .map_err(|e| PyErr::new::<exc::ValueError, _>(py, (e,)))?,
+        preserved_sandbox_retention: SandboxRetention {
+          max_count: keep_sandboxes_max_count.map(|c| c as usize),
+          max_age: keep_sandboxes_max_age_secs.map(Duration::from_secs),
+          max_bytes: keep_sandboxes_max_bytes,
+        },
         local_cache,
         remote_cache_read,
         remote_cache_write,
+        remote_cache_write_strict,
+        remote_cache_backfill_interval: if remote_cache_backfill {
+          Some(Duration::from_millis(remote_cache_backfill_interval_millis))
+        } else {
+          None
+        },
+        redact_env_vars,
+        redact_patterns,
+        max_process_output_bytes: max_process_output_bytes.map(|b| b as usize),
+        max_run_output_bytes: max_run_output_bytes.map(|b| b as usize),
+        audit_file_accesses,
+        fail_on_external_writes,
+        local_max_sandboxes: local_max_sandboxes.map(|m| m as usize),
+        local_batch_size: local_batch_size as usize,
+        local_batch_window: Duration::from_millis(local_batch_window_millis),
+        local_platform_properties: local_platform_properties.into_iter().collect(),
+        docker_binary: PathBuf::from(docker_binary),
+        speculation,
       }
     )
   }
@@ -549,6 +767,7 @@ py_class!(class PyRemotingOptions |py| {
 
   def __new__(
     _cls,
+    offline: bool,
     execution_enable: bool,
     store_address: Option<String>,
     execution_address: Option<String>,
@@ -557,15 +776,29 @@ py_class!(class PyRemotingOptions |py| {
     root_ca_certs_path: Option<String>,
     store_headers: Vec<(String, String)>,
     store_chunk_bytes: u64,
+    store_chunk_bytes_min: u64,
+    store_chunk_bytes_max: u64,
     store_chunk_upload_timeout: u64,
     store_rpc_retries: u64,
+    store_rpc_qps: Option<u64>,
+    store_bandwidth_bytes_per_sec: Option<u64>,
+    store_read_concurrency: Option<u64>,
+    store_max_inflight_bytes: Option<u64>,
+    store_encryption_key_path: Option<String>,
+    store_mirrors: Vec<String>,
+    store_digest_function: String,
     cache_eager_fetch: bool,
     execution_extra_platform_properties: Vec<(String, String)>,
     execution_headers: Vec<(String, String)>,
-    execution_overall_deadline_secs: u64
+    execution_overall_deadline_secs: u64,
+    execution_rpc_qps: Option<u64>,
+    execution_pipeline_uploads: bool,
+    cache_partition: Option<String>,
+    cache_partition_strategy: String
   ) -> CPyResult<Self> {
     Self::create_instance(py,
       RemotingOptions {
+        offline,
         execution_enable,
         store_address,
         execution_address,
@@ -574,12 +807,26 @@ py_class!(class PyRemotingOptions |py| {
         root_ca_certs_path: root_ca_certs_path.map(PathBuf::from),
         store_headers: store_headers.into_iter().collect(),
         store_chunk_bytes: store_chunk_bytes as usize,
+        store_chunk_bytes_bounds: Some((store_chunk_bytes_min as usize, store_chunk_bytes_max as usize)),
         store_chunk_upload_timeout: Duration::from_secs(store_chunk_upload_timeout),
         store_rpc_retries: store_rpc_retries as usize,
+        store_rpc_qps: store_rpc_qps.map(|qps| qps as usize),
+        store_bandwidth_bytes_per_sec: store_bandwidth_bytes_per_sec.map(|rate| rate as usize),
+        store_read_concurrency: store_read_concurrency.map(|c| c as usize),
+        store_max_inflight_bytes: store_max_inflight_bytes.map(|b| b as usize),
+        store_encryption_key_path: store_encryption_key_path.map(PathBuf::from),
+        store_mirrors,
+        store_digest_function: DigestFunction::try_from(store_digest_function)
+          .map_err(|e| PyErr::new::<exc::ValueError, _>(py, (e,)))?,
         cache_eager_fetch,
         execution_extra_platform_properties,
         execution_headers: execution_headers.into_iter().collect(),
         execution_overall_deadline: Duration::from_secs(execution_overall_deadline_secs),
+        execution_rpc_qps: execution_rpc_qps.map(|qps| qps as usize),
+        execution_pipeline_uploads,
+        cache_partition,
+        cache_partition_strategy: CachePartitionStrategy::try_from(cache_partition_strategy)
+          .map_err(|e| PyErr::new::<exc::ValueError, _>(py, (e,)))?,
       }
     )
   }
@@ -596,11 +843,20 @@ py_class!(class PyLocalStoreOptions |py| {
     directories_max_size_bytes: usize,
     lease_time_millis: u64,
     shard_count: u8,
+    materialize_umask: u32,
+    materialize_digest_xattrs: bool,
+    hashing_parallelism: usize,
+    maintenance_idle_io: bool,
+    download_repin: Vec<String>,
   ) -> CPyResult<Self> {
     if shard_count.count_ones() != 1 {
         let err_string = format!("The local store shard count must be a power of two: got {}", shard_count);
         return Err(PyErr::new::<exc::ValueError, _>(py, (err_string,)));
     }
+    if hashing_parallelism == 0 {
+        let err_string = "The local store hashing parallelism must be at least 1.".to_owned();
+        return Err(PyErr::new::<exc::ValueError, _>(py, (err_string,)));
+    }
     Self::create_instance(py,
       LocalStoreOptions {
         store_dir: PathBuf::from(store_dir),
@@ -609,6 +865,11 @@ py_class!(class PyLocalStoreOptions |py| {
         directories_max_size_bytes,
         lease_time: Duration::from_millis(lease_time_millis),
         shard_count,
+        materialize_umask,
+        materialize_digest_xattrs,
+        hashing_parallelism,
+        maintenance_idle_io,
+        download_repin,
       }
     )
   }
@@ -622,13 +883,32 @@ py_class!(class PySession |py| {
           build_id: String,
           session_values: PyObject,
           cancellation_latch: PySessionCancellationLatch,
+          notification_threshold_millis: Option<u64>,
+          notification_command: Vec<String>,
+          dry_run: bool,
+          priority: String,
     ) -> CPyResult<Self> {
+      let priority = SessionPriority::try_from(priority)
+        .map_err(|e| PyErr::new::<exc::ValueError, _>(py, (e,)))?;
+      let notification_options = notification_threshold_millis.map(|threshold_millis| {
+        NotificationOptions {
+          command: if notification_command.is_empty() {
+            None
+          } else {
+            Some(notification_command)
+          },
+          threshold: Duration::from_millis(threshold_millis),
+        }
+      });
       Self::create_instance(py, Session::new(
           scheduler.scheduler(py),
           should_render_ui,
           build_id,
           session_values.into(),
           cancellation_latch.cancelled(py).clone(),
+          notification_options,
+          dry_run,
+          priority,
         )
       )
     }
@@ -888,6 +1168,9 @@ fn nailgun_server_await_shutdown(py: Python, nailgun_server_ptr: PyNailgunServer
 /// The given Tasks struct will be cloned, so no additional mutation of the reference will
 /// affect the created Scheduler.
 ///
+/// If `fake_processes` are given, Processes are never run, and instead receive the responses that
+/// were scripted for them (for tests).
+///
 fn scheduler_create(
   py: Python,
   executor_ptr: PyExecutor,
@@ -899,9 +1182,11 @@ fn scheduler_create(
   ca_certs_path_buf: Option<String>,
   ignore_patterns: Vec<String>,
   use_gitignore: bool,
+  watch_filesystem: bool,
   remoting_options: PyRemotingOptions,
   local_store_options: PyLocalStoreOptions,
   exec_strategy_opts: PyExecutionStrategyOptions,
+  fake_processes: Option<self::testutil::PyFakeProcesses>,
 ) -> CPyResult<PyScheduler> {
   match fs::increase_limits() {
     Ok(msg) => debug!("{}", msg),
@@ -929,12 +1214,14 @@ fn scheduler_create(
         PathBuf::from(build_root_buf),
         ignore_patterns,
         use_gitignore,
+        watch_filesystem,
         PathBuf::from(local_execution_root_dir_buf),
         PathBuf::from(named_caches_dir_buf),
         ca_certs_path_buf.map(PathBuf::from),
         local_store_options.options(py).clone(),
         remoting_options.options(py).clone(),
         exec_strategy_opts.options(py).clone(),
+        fake_processes.map(|fake_processes| fake_processes.processes(py).clone()),
       )
     })
   });
@@ -964,6 +1251,10 @@ async fn workunit_to_py_value(
       externs::store_utf8("level"),
       externs::store_utf8(&workunit.metadata.level.to_string()),
     ),
+    (
+      externs::store_utf8("invocation_id"),
+      externs::store_utf8(session.invocation_id()),
+    ),
   ];
   if let Some(parent_id) = workunit.parent_id {
     dict_entries.push((
@@ -1017,6 +1308,30 @@ async fn workunit_to_py_value(
     ));
   }
 
+  if let Some(progress) = workunit.metadata.progress {
+    dict_entries.push((
+      externs::store_utf8("progress"),
+      externs::store_dict(vec![
+        (
+          externs::store_utf8("done_files"),
+          externs::store_u64(progress.done_files),
+        ),
+        (
+          externs::store_utf8("total_files"),
+          externs::store_u64(progress.total_files),
+        ),
+        (
+          externs::store_utf8("done_bytes"),
+          externs::store_u64(progress.done_bytes),
+        ),
+        (
+          externs::store_utf8("total_bytes"),
+          externs::store_u64(progress.total_bytes),
+        ),
+      ])?,
+    ));
+  }
+
   let mut artifact_entries = Vec::new();
 
   for (artifact_name, digest) in workunit.metadata.artifacts.iter() {
@@ -1053,6 +1368,12 @@ async fn workunit_to_py_value(
           externs::store_i64(*n),
         ));
       }
+      UserMetadataItem::ImmediateString(s) => {
+        user_metadata_entries.push((
+          externs::store_utf8(user_metadata_key.as_str()),
+          externs::store_utf8(s),
+        ));
+      }
       UserMetadataItem::PyValue(py_val_handle) => {
         match session.with_metadata_map(|map| map.get(py_val_handle).cloned()) {
           None => log::warn!(
@@ -1109,6 +1430,29 @@ async fn workunit_to_py_value(
     ));
   }
 
+  if !workunit.logs.is_empty() {
+    let log_values = workunit
+      .logs
+      .iter()
+      .map(|log| {
+        externs::store_dict(vec![
+          (
+            externs::store_utf8("level"),
+            externs::store_utf8(&log.level.to_string()),
+          ),
+          (
+            externs::store_utf8("message"),
+            externs::store_utf8(&log.message),
+          ),
+        ])
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    dict_entries.push((
+      externs::store_utf8("logs"),
+      externs::store_tuple(log_values),
+    ));
+  }
+
   externs::store_dict(dict_entries)
 }
 
@@ -1167,6 +1511,43 @@ fn session_poll_workunits(
   })
 }
 
+fn session_set_streaming_workunits_queue_bounds(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  session_ptr: PySession,
+  capacity: Option<u64>,
+  overflow_policy: String,
+) -> CPyResult<PyObject> {
+  let overflow_policy = OverflowPolicy::try_from(overflow_policy)
+    .map_err(|e| PyErr::new::<exc::ValueError, _>(py, (e,)))?;
+  with_scheduler(py, scheduler_ptr, |_scheduler| {
+    with_session(py, session_ptr, |session| {
+      let bounds = capacity.map(|capacity| StreamingQueueBounds {
+        capacity: capacity as usize,
+        overflow_policy,
+      });
+      session.workunit_store().set_streaming_queue_bounds(bounds);
+      Ok(py.None())
+    })
+  })
+}
+
+fn session_record_streaming_workunit_callback_time(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  session_ptr: PySession,
+  micros: u64,
+) -> CPyResult<PyObject> {
+  with_scheduler(py, scheduler_ptr, |_scheduler| {
+    with_session(py, session_ptr, |session| {
+      session
+        .workunit_store()
+        .record_observation(ObservationMetric::StreamingWorkunitCallbackTime, micros);
+      Ok(py.None())
+    })
+  })
+}
+
 fn scheduler_metrics(
   py: Python,
   scheduler_ptr: PyScheduler,
@@ -1184,6 +1565,24 @@ fn scheduler_metrics(
   })
 }
 
+fn scheduler_live_node_counts(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  session_ptr: PySession,
+) -> CPyResult<Vec<(String, String, u64)>> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    with_session(py, session_ptr, |session| {
+      let counts = py.allow_threads(|| scheduler.live_node_counts(session));
+      Ok(
+        counts
+          .into_iter()
+          .map(|(node_type, state, count)| (node_type, state.name().to_owned(), count as u64))
+          .collect(),
+      )
+    })
+  })
+}
+
 fn all_counter_names(_: Python) -> CPyResult<Vec<String>> {
   Ok(Metric::all_metrics())
 }
@@ -1219,6 +1618,104 @@ fn scheduler_execute(
   })
 }
 
+fn scheduler_warm_up(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  session_ptr: PySession,
+  execution_request_ptr: PyExecutionRequest,
+) -> PyUnitResult {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    with_execution_request(py, execution_request_ptr, |execution_request| {
+      with_session(py, session_ptr, |session| {
+        scheduler.warm_up(execution_request, session);
+        Ok(None)
+      })
+    })
+  })
+}
+
+fn scheduler_warm_up_remote_endpoints(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  only_unhealthy: bool,
+) -> PyUnitResult {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    scheduler.core.warm_up_remote_endpoints(only_unhealthy);
+    Ok(None)
+  })
+}
+
+fn scheduler_remote_endpoint_health(py: Python, scheduler_ptr: PyScheduler) -> CPyResult<PyObject> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    let endpoints = scheduler
+      .core
+      .remote_endpoints
+      .iter()
+      .map(|endpoint| {
+        let status = endpoint.status();
+        let (latency, error) = match &status {
+          store::EndpointStatus::Healthy { latency } => (
+            externs::store_u64(latency.as_millis() as u64),
+            Value::from(externs::none()),
+          ),
+          store::EndpointStatus::Unhealthy { error } => {
+            (Value::from(externs::none()), externs::store_utf8(error))
+          }
+          store::EndpointStatus::Unknown => {
+            (Value::from(externs::none()), Value::from(externs::none()))
+          }
+        };
+        externs::store_tuple(vec![
+          externs::store_utf8(endpoint.name()),
+          externs::store_utf8(endpoint.address()),
+          externs::store_utf8(status.name()),
+          latency,
+          error,
+        ])
+      })
+      .collect();
+    Ok(externs::store_tuple(endpoints).into())
+  })
+}
+
+fn scheduler_shutdown(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  timeout_millis: u64,
+) -> CPyResult<PyObject> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    let core = scheduler.core.clone();
+    let abandoned = py.allow_threads(|| {
+      core
+        .executor
+        .block_on(core.shutdown(Duration::from_millis(timeout_millis)))
+    });
+    let abandoned = abandoned
+      .iter()
+      .map(|name| externs::store_utf8(name))
+      .collect();
+    Ok(externs::store_tuple(abandoned).into())
+  })
+}
+
+fn engine_doctor(py: Python, scheduler_ptr: PyScheduler) -> CPyResult<PyObject> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    let core = scheduler.core.clone();
+    let checks = py.allow_threads(|| core.executor.block_on(doctor::run_checks(&core)));
+    let checks = checks
+      .into_iter()
+      .map(|check| {
+        externs::store_tuple(vec![
+          externs::store_utf8(&check.name),
+          externs::store_utf8(check.status.name()),
+          externs::store_utf8(&check.detail),
+        ])
+      })
+      .collect();
+    Ok(externs::store_tuple(checks).into())
+  })
+}
+
 fn execution_add_root_select(
   py: Python,
   scheduler_ptr: PyScheduler,
@@ -1251,10 +1748,24 @@ fn tasks_task_begin(
   name: String,
   desc: String,
   level: u64,
+  timeout_seconds: Option<f64>,
 ) -> PyUnitResult {
   let py_level: PythonLogLevel = level
     .try_into()
     .map_err(|e| PyErr::new::<exc::Exception, _>(py, (format!("{}", e),)))?;
+  let timeout = match timeout_seconds {
+    Some(seconds) if seconds.is_finite() && seconds > 0.0 => Some(Duration::from_secs_f64(seconds)),
+    Some(seconds) => {
+      return Err(PyErr::new::<exc::ValueError, _>(
+        py,
+        (format!(
+          "The timeout for rule `{}` must be a positive number of seconds: got {}.",
+          name, seconds
+        ),),
+      ))
+    }
+    None => None,
+  };
   with_tasks(py, tasks_ptr, |tasks| {
     let func = Function(externs::key_for(func.into())?);
     let output_type = externs::type_for(output_type);
@@ -1266,6 +1777,7 @@ fn tasks_task_begin(
       name,
       if desc.is_empty() { None } else { Some(desc) },
       py_level.into(),
+      timeout,
     );
     Ok(None)
   })
@@ -1338,6 +1850,24 @@ fn graph_len(py: Python, scheduler_ptr: PyScheduler) -> CPyResult<u64> {
   })
 }
 
+fn graph_per_session_nodes(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  session_ptr: PySession,
+) -> CPyResult<Vec<(String, String)>> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    with_session(py, session_ptr, |session| {
+      let nodes = py.allow_threads(|| scheduler.per_session_nodes(session));
+      Ok(
+        nodes
+          .into_iter()
+          .map(|(node, reason)| (node, reason.to_owned()))
+          .collect(),
+      )
+    })
+  })
+}
+
 fn graph_visualize(
   py: Python,
   scheduler_ptr: PyScheduler,
@@ -1365,6 +1895,12 @@ fn session_new_run_id(py: Python, session_ptr: PySession) -> PyUnitResult {
   })
 }
 
+fn session_get_invocation_id(py: Python, session_ptr: PySession) -> CPyResult<String> {
+  with_session(py, session_ptr, |session| {
+    Ok(session.invocation_id().to_owned())
+  })
+}
+
 fn session_get_observation_histograms(
   py: Python,
   scheduler_ptr: PyScheduler,
@@ -1373,6 +1909,8 @@ fn session_get_observation_histograms(
   // Encoding version to return to callers. This should be bumped when the encoded histograms
   // are encoded in a backwards-incompatible manner.
   const OBSERVATIONS_VERSION: u64 = 0;
+  // The percentiles which are precomputed for callers which cannot decode the histograms.
+  const PERCENTILES: [f64; 6] = [25.0, 50.0, 75.0, 90.0, 95.0, 99.0];
 
   with_scheduler(py, scheduler_ptr, |_scheduler| {
     with_session(py, session_ptr, |session| {
@@ -1390,6 +1928,22 @@ fn session_get_observation_histograms(
         )?;
       }
 
+      let percentiles = PyDict::new(py);
+      for (metric, values) in session
+        .workunit_store()
+        .observation_percentiles(&PERCENTILES)
+      {
+        let values_dict = PyDict::new(py);
+        for (percentile, value) in values {
+          values_dict.set_item(
+            py,
+            percentile.into_py_object(py).into_object(),
+            value.into_py_object(py).into_object(),
+          )?;
+        }
+        percentiles.set_item(py, PyString::new(py, &metric), values_dict.into_object())?;
+      }
+
       let result = PyDict::new(py);
       result.set_item(
         py,
@@ -1401,6 +1955,11 @@ fn session_get_observation_histograms(
         PyString::new(py, "histograms"),
         encoded_observations.into_object(),
       )?;
+      result.set_item(
+        py,
+        PyString::new(py, "percentiles"),
+        percentiles.into_object(),
+      )?;
 
       Ok(result)
     })
@@ -1423,12 +1982,66 @@ fn session_record_test_observation(
   })
 }
 
+fn session_get_planned_processes(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  session_ptr: PySession,
+) -> CPyResult<PyObject> {
+  with_scheduler(py, scheduler_ptr, |_scheduler| {
+    with_session(py, session_ptr, |session| {
+      let planned_processes = session
+        .planned_processes()
+        .into_iter()
+        .map(|planned_process| {
+          Ok(externs::store_tuple(vec![
+            externs::store_utf8(&planned_process.description),
+            nodes::Snapshot::store_directory_digest(&planned_process.action_digest)?,
+            nodes::Snapshot::store_directory_digest(&planned_process.input_digest)?,
+            externs::store_u64(planned_process.input_bytes as u64),
+          ]))
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))?;
+      Ok(externs::store_tuple(planned_processes).into())
+    })
+  })
+}
+
 fn session_isolated_shallow_clone(py: Python, session_ptr: PySession) -> CPyResult<PySession> {
   with_session(py, session_ptr, |session| {
     PySession::create_instance(py, session.isolated_shallow_clone())
   })
 }
 
+fn session_write_run_graph(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  session_ptr: PySession,
+  path: String,
+) -> PyUnitResult {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    with_session(py, session_ptr, |session| {
+      py.allow_threads(|| RunGraph::capture(&scheduler.core, session).write(Path::new(&path)))
+        .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))?;
+      Ok(None)
+    })
+  })
+}
+
+fn explain_run_graph_divergence(
+  py: Python,
+  before_path: String,
+  after_path: String,
+) -> CPyResult<String> {
+  py.allow_threads(|| {
+    let before = RunGraph::read(Path::new(&before_path))?;
+    let after = RunGraph::read(Path::new(&after_path))?;
+    serde_json::to_string(&crate::explain_divergence(&before, &after))
+      .map_err(|e| format!("Failed to encode divergence: {}", e))
+  })
+  .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))
+}
+
 fn validate_reachability(py: Python, scheduler_ptr: PyScheduler) -> PyUnitResult {
   with_scheduler(py, scheduler_ptr, |scheduler| {
     scheduler
@@ -1549,17 +2162,24 @@ fn maybe_set_panic_handler(_: Python) -> PyUnitResult {
 fn garbage_collect_store(
   py: Python,
   scheduler_ptr: PyScheduler,
+  session_ptr: PySession,
   target_size_bytes: usize,
 ) -> PyUnitResult {
   with_scheduler(py, scheduler_ptr, |scheduler| {
-    py.allow_threads(|| {
-      scheduler
-        .core
-        .store()
-        .garbage_collect(target_size_bytes, store::ShrinkBehavior::Fast)
+    with_session(py, session_ptr, |session| {
+      // NB: See the note on with_scheduler re: allow_threads.
+      py.allow_threads(|| {
+        // The digests which are retained in the graph are live roots.
+        let digests = scheduler.all_digests(session);
+        scheduler.core.executor.block_on(
+          scheduler
+            .core
+            .garbage_collect_store(target_size_bytes, digests),
+        )
+      })
+      .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))
+      .map(|()| None)
     })
-    .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))
-    .map(|()| None)
   })
 }
 
@@ -1704,8 +2324,79 @@ fn ensure_remote_has_recursive(
   })
 }
 
+///
+/// Expands a (possibly abbreviated) Digest as displayed in errors, workunits and logs into a full
+/// Digest, using the local store.
+///
+fn expand_digest(py: Python, scheduler_ptr: PyScheduler, displayed: String) -> CPyResult<PyObject> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    let core = &scheduler.core;
+    py.allow_threads(|| {
+      core.executor.block_on(async move {
+        let digest = core.store().expand_digest(&displayed).await?;
+        nodes::Snapshot::store_directory_digest(&digest)
+      })
+    })
+    .map(|value| value.into())
+    .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))
+  })
+}
+
 /// This functions assumes that the Digest in question represents the contents of a single File rather than a Directory,
 /// and will fail on Digests representing a Directory.
+///
+/// Stores the given in-memory files and empty directories in bulk, and returns a Snapshot of them.
+/// Unlike requesting a `CreateDigest`, this takes raw tuples rather than Python objects, so that
+/// creating a digest from many thousands of generated files does not need an attribute lookup per
+/// field of each file.
+///
+fn snapshot_of_file_contents(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  session_ptr: PySession,
+  file_contents: Vec<(String, PyBytes, bool)>,
+  empty_directories: Vec<String>,
+) -> CPyResult<PyObject> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    with_session(py, session_ptr, |session| {
+      // TODO: A parent_id should be an explicit argument.
+      session.workunit_store().init_thread_state(None);
+      let core = scheduler.core.clone();
+
+      let relative_path = |path: String| {
+        RelativePath::new(PathBuf::from(&path))
+          .map_err(|e| PyErr::new::<exc::ValueError, _>(py, (format!("{}: {}", path, e),)))
+      };
+      let files = file_contents
+        .into_iter()
+        .map(|(path, content, is_executable)| {
+          Ok(FileContent {
+            path: relative_path(path)?.into(),
+            content: bytes::Bytes::copy_from_slice(content.data(py)),
+            is_executable,
+          })
+        })
+        .collect::<CPyResult<Vec<_>>>()?;
+      let empty_directories = empty_directories
+        .into_iter()
+        .map(relative_path)
+        .collect::<CPyResult<Vec<_>>>()?;
+
+      py.allow_threads(|| {
+        core.executor.block_on(async move {
+          let snapshot = core
+            .store()
+            .snapshot_of_file_contents(files, empty_directories)
+            .await?;
+          nodes::Snapshot::store_snapshot(snapshot)
+        })
+      })
+      .map(|value| value.into())
+      .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))
+    })
+  })
+}
+
 fn single_file_digests_to_bytes(
   py: Python,
   scheduler_ptr: PyScheduler,
@@ -1748,6 +2439,89 @@ fn single_file_digests_to_bytes(
   })
 }
 
+fn tree_size(py: Python, scheduler_ptr: PyScheduler, py_digest: PyObject) -> CPyResult<PyDict> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    let core = &scheduler.core;
+    let digest = nodes::lift_directory_digest(&py_digest)
+      .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))?;
+    let size = py
+      .allow_threads(|| core.executor.block_on(core.store().tree_size(digest)))
+      .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))?;
+
+    let result = PyDict::new(py);
+    result.set_item(py, "file_count", size.file_count)?;
+    result.set_item(py, "file_bytes", size.file_bytes)?;
+    result.set_item(py, "directory_count", size.directory_count)?;
+    Ok(result)
+  })
+}
+
+fn print_action(py: Python, scheduler_ptr: PyScheduler, process: PyObject) -> CPyResult<PyDict> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    let core = &scheduler.core;
+    let value: Value = process.into();
+    let process = nodes::MultiPlatformExecuteProcess::lift_process(&value, None)
+      .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))?;
+    // The working directory is prepared as it would be before the process ran, so that the
+    // rendered Action has the same digest.
+    let process = py
+      .allow_threads(|| {
+        core.executor.block_on(
+          process_execution::working_directory::prepare_working_directory(&core.store(), process),
+        )
+      })
+      .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))?;
+    let (description, action_digest, command_digest) =
+      process_execution::describe::describe_action(
+        &process,
+        core.process_execution_metadata.clone(),
+        &core.named_caches,
+      )
+      .and_then(|description| {
+        let action_digest = nodes::Snapshot::store_directory_digest(&description.action_digest)?;
+        let command_digest = nodes::Snapshot::store_directory_digest(&description.command_digest)?;
+        Ok((description, action_digest, command_digest))
+      })
+      .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))?;
+
+    let result = PyDict::new(py);
+    result.set_item(py, "action_digest", &action_digest)?;
+    result.set_item(py, "action", PyBytes::new(py, &description.action))?;
+    result.set_item(py, "command_digest", &command_digest)?;
+    result.set_item(py, "command", PyBytes::new(py, &description.command))?;
+    result.set_item(py, "rendered", description.rendered)?;
+    Ok(result)
+  })
+}
+
+fn export_repro_bundle(
+  py: Python,
+  scheduler_ptr: PyScheduler,
+  process: PyObject,
+) -> CPyResult<PyObject> {
+  with_scheduler(py, scheduler_ptr, |scheduler| {
+    let core = &scheduler.core;
+    let value: Value = process.into();
+    let process = nodes::MultiPlatformExecuteProcess::lift_process(&value, None)
+      .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))?;
+    py.allow_threads(|| {
+      core.executor.block_on(async move {
+        let digest = process_execution::repro::store_repro_bundle(
+          &core.store(),
+          &process,
+          core.process_execution_metadata.clone(),
+          &core.named_caches,
+          &core.redactor,
+        )
+        .await?;
+        nodes::Snapshot::store_directory_digest(&digest)
+      })
+    })
+    .map(|value| value.into())
+    .map_err(|e| PyErr::new::<exc::Exception, _>(py, (e,)))
+  })
+}
+
 fn run_local_interactive_process(
   py: Python,
   scheduler_ptr: PyScheduler,
@@ -1810,10 +2584,13 @@ fn write_digest(
       destination.push(path_prefix);
 
       block_in_place_and_wait(py, || {
-        scheduler
-          .core
-          .store()
-          .materialize_directory(destination.clone(), lifted_digest)
+        store::with_request_priority(
+          store::RequestPriority::Interactive,
+          scheduler
+            .core
+            .store()
+            .materialize_directory_transactionally(destination.clone(), lifted_digest),
+        )
       })
       .map_err(|e| PyErr::new::<exc::ValueError, _>(py, (e,)))?;
       Ok(None)
@@ -1842,8 +2619,11 @@ fn stdio_initialize(
   show_rust_3rdparty_logs: bool,
   use_color: bool,
   show_target: bool,
+  log_by_workunit: bool,
   log_levels_by_target: PyDict,
   message_regex_filters: Vec<String>,
+  redact_env_vars: Vec<String>,
+  redact_patterns: Vec<String>,
   log_file: String,
 ) -> CPyResult<PyTuple> {
   let log_levels_by_target = log_levels_by_target
@@ -1863,13 +2643,27 @@ fn stdio_initialize(
       })
     })
     .collect::<Result<Vec<Regex>, _>>()?;
+  let message_redactions = redact_env_vars
+    .iter()
+    .filter_map(|name| std::env::var(name).ok())
+    .filter(|value| !value.is_empty())
+    .map(|value| regex::escape(&value))
+    .chain(redact_patterns.into_iter())
+    .map(|re| {
+      Regex::new(&re).map_err(|e| {
+        PyErr::new::<exc::Exception, _>(py, (format!("Failed to parse redaction pattern: {}", e),))
+      })
+    })
+    .collect::<Result<Vec<Regex>, _>>()?;
   Logger::init(
     level,
     show_rust_3rdparty_logs,
     use_color,
     show_target,
+    log_by_workunit,
     log_levels_by_target,
     message_regex_filters,
+    message_redactions,
     PathBuf::from(log_file),
   )
   .map_err(|s| {
@@ -1920,6 +2714,13 @@ fn set_per_run_log_path(py: Python, log_path: Option<String>) -> PyUnitResult {
   })
 }
 
+fn set_display_full_digests(py: Python, full: bool) -> PyUnitResult {
+  py.allow_threads(|| {
+    hashing::set_display_full_digests(full);
+    Ok(None)
+  })
+}
+
 fn write_log(py: Python, msg: String, level: u64, target: String) -> PyUnitResult {
   py.allow_threads(|| {
     Logger::log_from_python(&msg, level, &target).expect("Error logging message");
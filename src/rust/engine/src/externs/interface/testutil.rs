@@ -1,8 +1,12 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use super::PyExecutor;
-use cpython::{exc, py_class, PyErr, PyObject, PyResult, PyString};
+use crate::externs::fs::PyDigest;
+use bytes::Bytes;
+use cpython::{exc, py_class, PyBytes, PyErr, PyObject, PyResult, PyString};
 use parking_lot::Mutex;
+use process_execution::testing::{FakeProcesses, FakeResponse, ProcessMatcher};
 use testutil_mock::{StubCAS, StubCASBuilder};
 
 py_class!(pub class PyStubCASBuilder |py| {
@@ -46,3 +50,56 @@ py_class!(pub class PyStubCAS |py| {
     Ok(PyString::new(py, &address))
   }
 });
+
+py_class!(pub class PyFakeProcesses |py| {
+  data processes: FakeProcesses;
+
+  def __new__(_cls) -> PyResult<Self> {
+    Self::create_instance(py, FakeProcesses::new())
+  }
+
+  def respond(
+    &self,
+    argv_prefix: Vec<String>,
+    description_regex: Option<String>,
+    exit_code: i32,
+    stdout: PyBytes,
+    stderr: PyBytes,
+    output_digest: Option<PyDigest>,
+    latency_millis: u64,
+    error: Option<String>
+  ) -> PyResult<PyObject> {
+    let mut matcher = ProcessMatcher::any().argv_prefix(argv_prefix);
+    if let Some(pattern) = description_regex {
+      matcher = matcher
+        .description(&pattern)
+        .map_err(|e| PyErr::new::<exc::ValueError, _>(py, (e,)))?;
+    }
+    let response = if let Some(error) = error {
+      FakeResponse::failure(error)
+    } else {
+      let mut response = FakeResponse::exit(exit_code)
+        .stdout(Bytes::copy_from_slice(stdout.data(py)))
+        .stderr(Bytes::copy_from_slice(stderr.data(py)));
+      if let Some(output_digest) = output_digest {
+        response = response.output_directory(*output_digest.digest(py));
+      }
+      response
+    };
+    self
+      .processes(py)
+      .respond(matcher, response.latency(Duration::from_millis(latency_millis)));
+    Ok(py.None())
+  }
+
+  def calls(&self) -> PyResult<Vec<(Vec<String>, String)>> {
+    Ok(
+      self
+        .processes(py)
+        .calls()
+        .into_iter()
+        .map(|process| (process.argv, process.description))
+        .collect(),
+    )
+  }
+});
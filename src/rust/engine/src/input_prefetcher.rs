@@ -0,0 +1,114 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future::{self, BoxFuture, FutureExt};
+use hashing::{Digest, EMPTY_DIGEST};
+use parking_lot::Mutex;
+use process_execution::{
+  CommandRunner, Context, FallibleProcessResultWithPlatform, MultiPlatformProcess, Process,
+};
+use store::Store;
+use task_executor::Executor;
+
+///
+/// Fetches the inputs of processes which will run locally from the remote store into the local
+/// store in the background.
+///
+/// A prefetch is started once a process has missed the caches and is about to wait for an
+/// execution slot (see `PrefetchingCommandRunner`), so that the transfer overlaps with that wait,
+/// rather than delaying the process once it is able to run. The inputs of processes which hit the
+/// caches are never fetched.
+///
+/// Prefetches have background priority, so that they do not delay reads which are needed
+/// immediately. Failures are ignored, since the inputs will be fetched again (and any failure
+/// reported) when the process runs.
+///
+#[derive(Clone)]
+pub struct InputPrefetcher {
+  store: Store,
+  executor: Executor,
+  // The input digests which are currently being prefetched, so that a digest which is requested
+  // again before its prefetch completes is not fetched twice.
+  in_flight: Arc<Mutex<HashSet<Digest>>>,
+}
+
+impl InputPrefetcher {
+  pub fn new(store: Store, executor: Executor) -> InputPrefetcher {
+    InputPrefetcher {
+      store,
+      executor,
+      in_flight: Arc::default(),
+    }
+  }
+
+  ///
+  /// Begins prefetching the given input digest, unless it is already being prefetched. The returned
+  /// future (which needn't be awaited) completes when the prefetch has.
+  ///
+  pub fn prefetch(&self, input_files: Digest) -> BoxFuture<'static, ()> {
+    if input_files == EMPTY_DIGEST || !self.in_flight.lock().insert(input_files) {
+      return future::ready(()).boxed();
+    }
+
+    let store = self.store.clone();
+    let in_flight = self.in_flight.clone();
+    self
+      .executor
+      .spawn(store::with_request_priority(
+        store::RequestPriority::Background,
+        async move {
+          if let Err(e) = store
+            .ensure_local_has_recursive_directory(input_files)
+            .await
+          {
+            log::debug!("Failed to prefetch inputs {:?}: {}", input_files, e);
+          }
+          in_flight.lock().remove(&input_files);
+        },
+      ))
+      .boxed()
+  }
+}
+
+///
+/// A CommandRunner wrapper which begins prefetching the inputs of each process before running it
+/// with the underlying runner, which should wait for an execution slot before materializing them.
+///
+pub struct PrefetchingCommandRunner {
+  underlying: Box<dyn CommandRunner>,
+  input_prefetcher: InputPrefetcher,
+}
+
+impl PrefetchingCommandRunner {
+  pub fn new(
+    underlying: Box<dyn CommandRunner>,
+    input_prefetcher: InputPrefetcher,
+  ) -> PrefetchingCommandRunner {
+    PrefetchingCommandRunner {
+      underlying,
+      input_prefetcher,
+    }
+  }
+}
+
+#[async_trait]
+impl CommandRunner for PrefetchingCommandRunner {
+  async fn run(
+    &self,
+    req: MultiPlatformProcess,
+    context: Context,
+  ) -> Result<FallibleProcessResultWithPlatform, String> {
+    if let Some(process) = self.underlying.extract_compatible_request(&req) {
+      let _ = self.input_prefetcher.prefetch(process.input_files);
+    }
+    self.underlying.run(req, context).await
+  }
+
+  fn extract_compatible_request(&self, req: &MultiPlatformProcess) -> Option<Process> {
+    self.underlying.extract_compatible_request(req)
+  }
+}
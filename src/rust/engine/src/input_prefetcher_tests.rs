@@ -0,0 +1,82 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use futures::future;
+use mock::StubCAS;
+use store::Store;
+use tempfile::TempDir;
+use testutil::data::{TestData, TestDirectory};
+use workunit_store::WorkunitStore;
+
+use crate::input_prefetcher::InputPrefetcher;
+
+fn new_store(dir: &Path, executor: task_executor::Executor, cas_address: &str) -> Store {
+  Store::local_only(executor, dir)
+    .unwrap()
+    .into_with_remote(
+      cas_address,
+      None,
+      None,
+      BTreeMap::new(),
+      1024 * 1024,
+      None,
+      Duration::from_secs(1),
+      1,
+      None,
+      None,
+      None,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn prefetches_inputs_into_the_local_store() {
+  WorkunitStore::setup_for_tests();
+  let cas = StubCAS::builder()
+    .file(&TestData::roland())
+    .directory(&TestDirectory::containing_roland())
+    .build();
+  let input_files = TestDirectory::containing_roland().digest();
+  let executor = task_executor::Executor::new();
+
+  // Fetch the inputs once, to find how many reads that takes.
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path(), executor.clone(), &cas.address());
+  InputPrefetcher::new(store, executor.clone())
+    .prefetch(input_files)
+    .await;
+  let reads_per_prefetch = cas.read_request_count();
+  assert!(reads_per_prefetch > 0);
+
+  // Inputs which are requested again while they are being prefetched are only fetched once.
+  let dir = TempDir::new().unwrap();
+  let store = new_store(dir.path(), executor.clone(), &cas.address());
+  let input_prefetcher = InputPrefetcher::new(store.clone(), executor);
+  future::join(
+    input_prefetcher.prefetch(input_files),
+    input_prefetcher.prefetch(input_files),
+  )
+  .await;
+  assert_eq!(cas.read_request_count(), 2 * reads_per_prefetch);
+
+  // And once they have been prefetched, the inputs are available locally before a process which
+  // consumes them is scheduled.
+  let local_store = store.into_local_only();
+  assert_eq!(
+    local_store
+      .load_file_bytes_with(TestData::roland().digest(), |bytes| bytes.to_vec())
+      .await
+      .unwrap()
+      .map(|(bytes, _)| bytes),
+    Some(TestData::roland().bytes().to_vec())
+  );
+  local_store
+    .load_directory(input_files)
+    .await
+    .unwrap()
+    .expect("The prefetched directory should be available locally.");
+}
@@ -3,18 +3,24 @@ use crate::core::{throw, Value};
 use crate::externs;
 use crate::nodes::MultiPlatformExecuteProcess;
 use crate::nodes::{
-  lift_directory_digest, DownloadedFile, NodeResult, Paths, SessionValues, Snapshot,
+  lift_directory_digest, lift_file_digest, DownloadedFile, LocalBinary, NodeResult, Paths,
+  ProcessResult, SessionValues, Snapshot,
 };
 use crate::tasks::Intrinsic;
 use crate::types::Types;
 use crate::Failure;
 
-use fs::RelativePath;
+use fs::{FileContent, RelativePath};
 use futures::future::{self, BoxFuture, FutureExt, TryFutureExt};
+use hashing::Digest;
 use indexmap::IndexMap;
+use process_execution::output_encoding::detect_output_encoding;
+use process_execution::text_rewrite::{self, TextRewrite};
+use process_execution::{coverage, depfile, test_report};
 use store::{SnapshotOps, SubsetParams};
 
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 type IntrinsicFn =
   Box<dyn Fn(Context, Vec<Value>) -> BoxFuture<'static, NodeResult<Value>> + Send + Sync>;
@@ -54,6 +60,20 @@ impl Intrinsics {
       },
       Box::new(download_file_to_digest),
     );
+    intrinsics.insert(
+      Intrinsic {
+        product: types.blob_file,
+        inputs: vec![types.file_digest],
+      },
+      Box::new(file_digest_to_blob_file),
+    );
+    intrinsics.insert(
+      Intrinsic {
+        product: types.binary_paths,
+        inputs: vec![types.local_binary_path_request],
+      },
+      Box::new(local_binary_path_request_to_binary_paths),
+    );
     intrinsics.insert(
       Intrinsic {
         product: types.snapshot,
@@ -103,6 +123,34 @@ impl Intrinsics {
       },
       Box::new(digest_subset_to_digest),
     );
+    intrinsics.insert(
+      Intrinsic {
+        product: types.paths,
+        inputs: vec![types.parse_depfiles],
+      },
+      Box::new(parse_depfiles_to_paths),
+    );
+    intrinsics.insert(
+      Intrinsic {
+        product: types.directory_digest,
+        inputs: vec![types.merge_coverage_data],
+      },
+      Box::new(merge_coverage_data_to_digest),
+    );
+    intrinsics.insert(
+      Intrinsic {
+        product: types.directory_digest,
+        inputs: vec![types.merge_test_reports],
+      },
+      Box::new(merge_test_reports_to_digest),
+    );
+    intrinsics.insert(
+      Intrinsic {
+        product: types.directory_digest,
+        inputs: vec![types.rewrite_digest],
+      },
+      Box::new(rewrite_digest_to_digest),
+    );
     intrinsics.insert(
       Intrinsic {
         product: types.session_values,
@@ -146,7 +194,17 @@ fn multi_platform_process_request_to_process_result(
         str
       ))
     })?;
-    let result = context.get(process_request).await?.0;
+    // NB: The processes of a MultiPlatformProcess share a description, and so (in practice) their
+    // declared output encoding.
+    let declared_output_encoding = externs::getattr::<Vec<Value>>(process_val, "processes")
+      .map_err(|e| throw(&e))?
+      .iter()
+      .map(|process| externs::getattr::<Option<String>>(process, "output_encoding"))
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|e| throw(&e))?
+      .into_iter()
+      .find_map(|encoding| encoding);
+    let ProcessResult(result, _, source_digests) = context.get(process_request).await?;
 
     let maybe_stdout = context
       .core
@@ -180,6 +238,15 @@ fn multi_platform_process_request_to_process_result(
         ))
       })?;
 
+    let stdout_encoding =
+      detect_output_encoding(&stdout_bytes, declared_output_encoding.as_deref());
+    let stderr_encoding =
+      detect_output_encoding(&stderr_bytes, declared_output_encoding.as_deref());
+    let store_encoding = |encoding: Option<String>| match encoding {
+      Some(encoding) => externs::store_utf8(&encoding),
+      None => Value::from(externs::none()),
+    };
+
     let platform_name: String = result.platform.into();
     Ok(externs::unsafe_call(
       context.core.types.process_result,
@@ -194,6 +261,28 @@ fn multi_platform_process_request_to_process_result(
           context.core.types.platform,
           &[externs::store_utf8(&platform_name)],
         ),
+        externs::store_tuple(
+          source_digests
+            .iter()
+            .map(|(path, digest)| {
+              Ok(externs::store_tuple(vec![
+                externs::store_utf8(path.to_str().unwrap()),
+                Snapshot::store_directory_digest(digest)?,
+              ]))
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|s| throw(&s))?,
+        ),
+        match result.metadata.platform_details {
+          Some(ref platform_details) => externs::store_utf8(&platform_details.to_string()),
+          None => Value::from(externs::none()),
+        },
+        store_encoding(stdout_encoding),
+        store_encoding(stderr_encoding),
+        match result.metadata.exit_reason {
+          Some(exit_reason) => externs::store_utf8(&exit_reason.to_string()),
+          None => Value::from(externs::none()),
+        },
       ],
     ))
   }
@@ -306,6 +395,90 @@ fn download_file_to_digest(
   .boxed()
 }
 
+fn local_binary_path_request_to_binary_paths(
+  context: Context,
+  mut args: Vec<Value>,
+) -> BoxFuture<'static, NodeResult<Value>> {
+  async move {
+    let request = args.pop().unwrap();
+    let binary_name = externs::getattr_as_string(&request, "binary_name");
+    let search_path: Vec<String> =
+      externs::getattr(&request, "search_path").map_err(|e| throw(&e))?;
+    let test_args: Option<Vec<String>> =
+      externs::getattr(&request, "test_args").map_err(|e| throw(&e))?;
+    let fingerprint_stdout: bool =
+      externs::getattr(&request, "fingerprint_stdout").map_err(|e| throw(&e))?;
+
+    // Like `which -a`, each (absolute) entry of the search path is a candidate, in order.
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    for directory in search_path.iter().map(Path::new) {
+      let candidate = directory.join(&binary_name);
+      if directory.is_absolute() && !candidates.contains(&candidate) {
+        candidates.push(candidate);
+      }
+    }
+    let discovered = future::try_join_all(candidates.iter().map(|path| {
+      context.get(LocalBinary {
+        path: path.clone(),
+        test_args: test_args.clone(),
+      })
+    }))
+    .await?;
+
+    let binary_paths = candidates
+      .into_iter()
+      .zip(discovered)
+      .filter_map(|(path, binary)| {
+        let binary = binary?;
+        // See `BinaryPath.fingerprinted`: an untested binary has the fingerprint of empty content.
+        let fingerprint = if fingerprint_stdout {
+          Digest::of_bytes(&binary.stdout).hash.to_hex()
+        } else {
+          String::from_utf8_lossy(&binary.stdout).into_owned()
+        };
+        Some(externs::unsafe_call(
+          context.core.types.binary_path,
+          &[
+            externs::store_utf8(&path.to_string_lossy()),
+            externs::store_utf8(&fingerprint),
+          ],
+        ))
+      })
+      .collect();
+    Ok(externs::unsafe_call(
+      context.core.types.binary_paths,
+      &[
+        externs::store_utf8(&binary_name),
+        externs::store_tuple(binary_paths),
+      ],
+    ))
+  }
+  .boxed()
+}
+
+fn file_digest_to_blob_file(
+  context: Context,
+  mut args: Vec<Value>,
+) -> BoxFuture<'static, NodeResult<Value>> {
+  async move {
+    let py_file_digest = args.pop().unwrap();
+    let digest = lift_file_digest(&context.core.types, &py_file_digest).map_err(|e| throw(&e))?;
+    let path = context
+      .session
+      .blob_file(digest)
+      .await
+      .map_err(|e| throw(&e))?;
+    let path = path
+      .to_str()
+      .ok_or_else(|| throw(&format!("Could not decode path `{:?}` as UTF8.", path)))?;
+    Ok(externs::unsafe_call(
+      context.core.types.blob_file,
+      &[externs::store_utf8(path), py_file_digest],
+    ))
+  }
+  .boxed()
+}
+
 fn path_globs_to_digest(
   context: Context,
   mut args: Vec<Value>,
@@ -339,51 +512,48 @@ fn create_digest_to_digest(
   context: Context,
   args: Vec<Value>,
 ) -> BoxFuture<'static, NodeResult<Value>> {
-  let file_contents_and_directories = externs::collect_iterable(&args[0]).unwrap();
-  let digests: Vec<_> = file_contents_and_directories
-    .into_iter()
-    .map(|file_content_or_directory| {
-      let path = externs::getattr_as_string(&file_content_or_directory, "path");
-      let store = context.core.store();
-      async move {
-        let path = RelativePath::new(PathBuf::from(path))
-          .map_err(|e| format!("The `path` must be relative: {:?}", e))?;
-
-        if externs::hasattr(&file_content_or_directory, "content") {
-          let bytes = bytes::Bytes::from(
-            externs::getattr::<Vec<u8>>(&file_content_or_directory, "content").unwrap(),
-          );
-          let is_executable: bool =
-            externs::getattr(&file_content_or_directory, "is_executable").unwrap();
-
-          let digest = store.store_file_bytes(bytes, true).await?;
-          let snapshot = store
-            .snapshot_of_one_file(path, digest, is_executable)
-            .await?;
-          let res: Result<_, String> = Ok(snapshot.digest);
-          res
-        } else {
-          store
-            .create_empty_dir(path)
-            .await
-            .map_err(|e| format!("{:?}", e))
-        }
-      }
-    })
-    .collect();
-
+  // Lift all of the entries while we hold the GIL, and then store (and hash) them in bulk.
+  let file_contents_and_directories =
+    lift_file_contents_and_directories(externs::collect_iterable(&args[0]).unwrap());
   let store = context.core.store();
   async move {
-    let digests = future::try_join_all(digests).await.map_err(|e| throw(&e))?;
-    let digest = store
-      .merge(digests)
+    let (files, directories) = file_contents_and_directories.map_err(|e| throw(&e))?;
+    let snapshot = store
+      .snapshot_of_file_contents(files, directories)
       .await
-      .map_err(|e| throw(&format!("{:?}", e)))?;
-    Snapshot::store_directory_digest(&digest).map_err(|s| throw(&s))
+      .map_err(|e| throw(&e))?;
+    Snapshot::store_directory_digest(&snapshot.digest).map_err(|s| throw(&s))
   }
   .boxed()
 }
 
+fn lift_file_contents_and_directories(
+  file_contents_and_directories: Vec<Value>,
+) -> Result<(Vec<FileContent>, Vec<RelativePath>), String> {
+  let mut files = Vec::new();
+  let mut directories = Vec::new();
+  for file_content_or_directory in file_contents_and_directories {
+    let path = externs::getattr_as_string(&file_content_or_directory, "path");
+    let path = RelativePath::new(PathBuf::from(path))
+      .map_err(|e| format!("The `path` must be relative: {:?}", e))?;
+    if externs::hasattr(&file_content_or_directory, "content") {
+      let content = bytes::Bytes::from(
+        externs::getattr::<Vec<u8>>(&file_content_or_directory, "content").unwrap(),
+      );
+      let is_executable: bool =
+        externs::getattr(&file_content_or_directory, "is_executable").unwrap();
+      files.push(FileContent {
+        path: path.into(),
+        content,
+        is_executable,
+      });
+    } else {
+      directories.push(path);
+    }
+  }
+  Ok((files, directories))
+}
+
 fn digest_subset_to_digest(
   context: Context,
   args: Vec<Value>,
@@ -405,6 +575,252 @@ fn digest_subset_to_digest(
   .boxed()
 }
 
+fn parse_depfiles_to_paths(
+  context: Context,
+  args: Vec<Value>,
+) -> BoxFuture<'static, NodeResult<Value>> {
+  let core = context.core.clone();
+  let store = context.core.store();
+
+  async move {
+    let digest = lift_directory_digest(&externs::getattr(&args[0], "digest").unwrap())
+      .map_err(|e| throw(&e))?;
+    let format =
+      externs::getattr_as_string(&externs::getattr(&args[0], "format").unwrap(), "value");
+    let parse: fn(&[u8]) -> Result<Vec<String>, String> = match format.as_ref() {
+      "make" => |content| depfile::parse_depfile(&String::from_utf8_lossy(content)),
+      "ninja_deps" => depfile::parse_ninja_deps_log,
+      other => return Err(throw(&format!("Unknown depfile format: {}", other))),
+    };
+    let depfiles = store
+      .contents_for_directory(digest)
+      .await
+      .map_err(|e| throw(&e))?;
+
+    // Parse all of the depfiles (which might be numerous) off of the io pool.
+    let paths = core
+      .executor
+      .spawn_blocking(move || {
+        let mut seen = HashSet::new();
+        let mut paths = Vec::new();
+        for depfile in depfiles {
+          let dependencies = parse(&depfile.content)
+            .map_err(|e| format!("Failed to parse {}: {}", depfile.path.display(), e))?;
+          paths.extend(
+            dependencies
+              .into_iter()
+              .filter(|dependency| seen.insert(dependency.clone())),
+          );
+        }
+        let res: Result<_, String> = Ok(paths);
+        res
+      })
+      .await
+      .map_err(|e| throw(&e))?;
+
+    Ok(externs::unsafe_call(
+      core.types.paths,
+      &[
+        externs::store_tuple(paths.iter().map(|p| externs::store_utf8(p)).collect()),
+        externs::store_tuple(vec![]),
+      ],
+    ))
+  }
+  .boxed()
+}
+
+fn merge_coverage_data_to_digest(
+  context: Context,
+  args: Vec<Value>,
+) -> BoxFuture<'static, NodeResult<Value>> {
+  let core = context.core.clone();
+  let store = context.core.store();
+
+  async move {
+    let digests: Vec<hashing::Digest> = externs::getattr::<Vec<Value>>(&args[0], "digests")
+      .unwrap()
+      .into_iter()
+      .map(|val: Value| lift_directory_digest(&val))
+      .collect::<Result<_, _>>()
+      .map_err(|e| throw(&e))?;
+    let format =
+      externs::getattr_as_string(&externs::getattr(&args[0], "format").unwrap(), "value");
+    let merge: fn(&[&[u8]]) -> Result<Vec<u8>, String> = match format.as_ref() {
+      "lcov" => coverage::merge_lcov,
+      "coverage_py" => coverage::merge_coverage_py,
+      other => return Err(throw(&format!("Unknown coverage data format: {}", other))),
+    };
+    let output_path = externs::getattr_as_string(&args[0], "output_path");
+    let output_path = RelativePath::new(PathBuf::from(output_path))
+      .map_err(|e| throw(&format!("The `output_path` must be relative: {:?}", e)))?;
+
+    // NB: Data files are collected per-digest (rather than by merging the digests) because shards
+    // commonly write data files at identical paths.
+    let data_files = future::try_join_all(
+      digests
+        .into_iter()
+        .map(|digest| store.contents_for_directory(digest)),
+    )
+    .await
+    .map_err(|e| throw(&e))?
+    .into_iter()
+    .flatten()
+    .map(|file_content| file_content.content)
+    .collect::<Vec<_>>();
+
+    // Merge the data files (which might be numerous) off of the io pool.
+    let merged = core
+      .executor
+      .spawn_blocking(move || {
+        let data_files = data_files.iter().map(|c| c.as_ref()).collect::<Vec<_>>();
+        merge(&data_files)
+      })
+      .await
+      .map_err(|e| throw(&e))?;
+
+    let digest = store
+      .store_file_bytes(bytes::Bytes::from(merged), true)
+      .await
+      .map_err(|e| throw(&e))?;
+    let snapshot = store
+      .snapshot_of_one_file(output_path, digest, false)
+      .await
+      .map_err(|e| throw(&e))?;
+    Snapshot::store_directory_digest(&snapshot.digest).map_err(|s| throw(&s))
+  }
+  .boxed()
+}
+
+fn merge_test_reports_to_digest(
+  context: Context,
+  args: Vec<Value>,
+) -> BoxFuture<'static, NodeResult<Value>> {
+  let core = context.core.clone();
+  let store = context.core.store();
+
+  async move {
+    let digests: Vec<hashing::Digest> = externs::getattr::<Vec<Value>>(&args[0], "digests")
+      .unwrap()
+      .into_iter()
+      .map(|val: Value| lift_directory_digest(&val))
+      .collect::<Result<_, _>>()
+      .map_err(|e| throw(&e))?;
+    let format =
+      externs::getattr_as_string(&externs::getattr(&args[0], "format").unwrap(), "value");
+    let merge: fn(&[&[u8]]) -> Result<Vec<u8>, String> = match format.as_ref() {
+      "junit_xml" => test_report::merge_junit_xml,
+      "json" => test_report::merge_junit_xml_to_json,
+      other => return Err(throw(&format!("Unknown test report format: {}", other))),
+    };
+    let output_path = externs::getattr_as_string(&args[0], "output_path");
+    let output_path = RelativePath::new(PathBuf::from(output_path))
+      .map_err(|e| throw(&format!("The `output_path` must be relative: {:?}", e)))?;
+
+    // NB: As with coverage data, reports are collected per-digest, because test runners commonly
+    // write reports at identical paths.
+    let reports = future::try_join_all(
+      digests
+        .into_iter()
+        .map(|digest| store.contents_for_directory(digest)),
+    )
+    .await
+    .map_err(|e| throw(&e))?
+    .into_iter()
+    .flatten()
+    .filter(|file_content| file_content.path.extension() == Some("xml".as_ref()))
+    .map(|file_content| file_content.content)
+    .collect::<Vec<_>>();
+
+    let merged = core
+      .executor
+      .spawn_blocking(move || {
+        let reports = reports.iter().map(|c| c.as_ref()).collect::<Vec<_>>();
+        merge(&reports)
+      })
+      .await
+      .map_err(|e| throw(&e))?;
+
+    let digest = store
+      .store_file_bytes(bytes::Bytes::from(merged), true)
+      .await
+      .map_err(|e| throw(&e))?;
+    let snapshot = store
+      .snapshot_of_one_file(output_path, digest, false)
+      .await
+      .map_err(|e| throw(&e))?;
+    Snapshot::store_directory_digest(&snapshot.digest).map_err(|s| throw(&s))
+  }
+  .boxed()
+}
+
+fn rewrite_digest_to_digest(
+  context: Context,
+  args: Vec<Value>,
+) -> BoxFuture<'static, NodeResult<Value>> {
+  let core = context.core.clone();
+  let store = context.core.store();
+
+  async move {
+    let original_digest = lift_directory_digest(&externs::getattr(&args[0], "digest").unwrap())
+      .map_err(|e| throw(&e))?;
+    let globs = externs::getattr(&args[0], "globs").unwrap();
+    let path_globs = Snapshot::lift_prepared_path_globs(&globs).map_err(|e| throw(&e))?;
+    let rewrites = externs::getattr::<Vec<Value>>(&args[0], "replacements")
+      .unwrap()
+      .into_iter()
+      .map(|replacement| {
+        let pattern = externs::getattr_as_string(&replacement, "pattern");
+        let replacement_text = externs::getattr_as_string(&replacement, "replacement");
+        let literal: bool = externs::getattr(&replacement, "literal").unwrap();
+        if literal {
+          TextRewrite::literal(&pattern, &replacement_text)
+        } else {
+          TextRewrite::regex(&pattern, &replacement_text)
+        }
+      })
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|e| throw(&e))?;
+
+    let matching_digest = store
+      .subset(original_digest, SubsetParams { globs: path_globs })
+      .await
+      .map_err(|e| throw(&format!("{:?}", e)))?;
+    let files = store
+      .contents_for_directory(matching_digest)
+      .await
+      .map_err(|e| throw(&e))?;
+
+    // Rewrite the files (which might be large) off of the io pool.
+    let rewritten = core
+      .executor
+      .spawn_blocking(move || {
+        files
+          .into_iter()
+          .filter_map(|file| {
+            text_rewrite::rewrite(&rewrites, &file.content)
+              .map(|content| (file.path, bytes::Bytes::from(content)))
+          })
+          .collect::<Vec<_>>()
+      })
+      .await;
+
+    let (paths, contents): (Vec<_>, Vec<_>) = rewritten.into_iter().unzip();
+    let digests = store
+      .store_file_bytes_batch(contents, true)
+      .await
+      .map_err(|e| throw(&e))?;
+    let digest = store
+      .replace_files(
+        original_digest,
+        paths.into_iter().zip(digests).collect::<HashMap<_, _>>(),
+      )
+      .await
+      .map_err(|e| throw(&e))?;
+    Snapshot::store_directory_digest(&digest).map_err(|s| throw(&s))
+  }
+  .boxed()
+}
+
 fn session_values(context: Context, _args: Vec<Value>) -> BoxFuture<'static, NodeResult<Value>> {
   async move { context.get(SessionValues).await }.boxed()
 }
@@ -33,20 +33,36 @@
 #![type_length_limit = "43757804"]
 
 mod context;
+#[cfg(test)]
+mod context_tests;
 mod core;
+mod doctor;
+mod download_checksums;
 mod externs;
+mod input_prefetcher;
+#[cfg(test)]
+mod input_prefetcher_tests;
 mod interning;
 mod intrinsics;
+mod local_binaries;
 mod nodes;
+mod notifications;
+#[cfg(test)]
+mod notifications_tests;
+mod run_graph;
 mod scheduler;
 mod selectors;
 mod session;
 mod tasks;
 mod types;
 
-pub use crate::context::{Core, ExecutionStrategyOptions, LocalStoreOptions, RemotingOptions};
+pub use crate::context::{
+  CachePartitionStrategy, Core, ExecutionStrategyOptions, LocalStoreOptions, RemotingOptions,
+};
 pub use crate::core::{Failure, Function, Key, Params, TypeId, Value};
 pub use crate::intrinsics::Intrinsics;
+pub use crate::notifications::NotificationOptions;
+pub use crate::run_graph::{explain_divergence, RunGraph};
 pub use crate::scheduler::{ExecutionRequest, ExecutionTermination, Scheduler};
 pub use crate::session::Session;
 pub use crate::tasks::{Rule, Tasks};
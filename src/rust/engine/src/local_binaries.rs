@@ -0,0 +1,143 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use hashing::Digest;
+use log::debug;
+use parking_lot::Mutex;
+
+///
+/// A binary which was discovered on the local machine, along with the stdout of its test (which is
+/// empty if it was not tested).
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiscoveredBinary {
+  pub stdout: Bytes,
+}
+
+struct TestedBinary {
+  modified: SystemTime,
+  len: u64,
+  digest: Digest,
+  // None if the test failed.
+  stdout: Option<Bytes>,
+}
+
+impl TestedBinary {
+  fn discovered(&self) -> Option<DiscoveredBinary> {
+    self
+      .stdout
+      .clone()
+      .map(|stdout| DiscoveredBinary { stdout })
+  }
+}
+
+///
+/// A cache of the results of testing local binaries (usually by running them with `--version`).
+///
+/// The graph memoizes discovery for as long as the directory containing a binary is unchanged, but
+/// a change to any entry in the directory (or a restart of the graph) will cause the binaries in it
+/// to be re-discovered. This cache avoids re-running a test unless the binary itself changed: an
+/// entry is reused without reading the binary if its mtime and length are unchanged, and is
+/// otherwise reused if the digest of its content is unchanged.
+///
+#[derive(Default)]
+pub struct LocalBinaries {
+  tested: Mutex<HashMap<(PathBuf, Vec<String>), TestedBinary>>,
+}
+
+impl LocalBinaries {
+  ///
+  /// Returns the given path if it is a file, after running it with the given test args (if any)
+  /// and confirming that the test exits successfully.
+  ///
+  /// NB: This method blocks, and should be called on a blocking thread.
+  ///
+  pub fn discover(
+    &self,
+    path: &Path,
+    test_args: Option<&[String]>,
+  ) -> Result<Option<DiscoveredBinary>, String> {
+    let metadata = match std::fs::metadata(path) {
+      Ok(metadata) => metadata,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(format!("Failed to stat {}: {}", path.display(), e)),
+    };
+    // NB: Any file is a candidate: a file which is not executable will fail its test, if any.
+    if !metadata.is_file() {
+      return Ok(None);
+    }
+    let test_args = match test_args {
+      Some(test_args) => test_args,
+      None => {
+        return Ok(Some(DiscoveredBinary {
+          stdout: Bytes::new(),
+        }))
+      }
+    };
+    let modified = metadata
+      .modified()
+      .map_err(|e| format!("Failed to get the mtime of {}: {}", path.display(), e))?;
+    let len = metadata.len();
+
+    let key = (path.to_owned(), test_args.to_vec());
+    if let Some(entry) = self.tested.lock().get(&key) {
+      if entry.modified == modified && entry.len == len {
+        return Ok(entry.discovered());
+      }
+    }
+    // NB: The lock is not held while hashing and testing: concurrent discoveries of the same
+    // binary are deduplicated by the graph.
+    let content =
+      std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let digest = Digest::of_bytes(&content);
+    if let Some(entry) = self.tested.lock().get_mut(&key) {
+      if entry.digest == digest {
+        entry.modified = modified;
+        entry.len = len;
+        return Ok(entry.discovered());
+      }
+    }
+
+    let entry = TestedBinary {
+      modified,
+      len,
+      digest,
+      stdout: test(path, test_args),
+    };
+    let discovered = entry.discovered();
+    self.tested.lock().insert(key, entry);
+    Ok(discovered)
+  }
+}
+
+///
+/// Runs the given binary with the given args and an empty environment, returning its stdout if it
+/// could be executed and exited successfully.
+///
+fn test(path: &Path, args: &[String]) -> Option<Bytes> {
+  let output = Command::new(path)
+    .args(args)
+    .env_clear()
+    .current_dir(std::env::temp_dir())
+    .stdin(Stdio::null())
+    .stderr(Stdio::null())
+    .output();
+  match output {
+    Ok(output) if output.status.success() => Some(Bytes::from(output.stdout)),
+    Ok(output) => {
+      debug!("Test of {} failed: {}", path.display(), output.status);
+      None
+    }
+    // The binary could not be executed at all (because it is not executable, for example).
+    Err(e) => {
+      debug!("Failed to test {}: {}", path.display(), e);
+      None
+    }
+  }
+}
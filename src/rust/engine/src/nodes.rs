@@ -1,7 +1,7 @@
 // Copyright 2018 Pants project contributors (see CONTRIBUTORS.md).
 // Licensed under the Apache License, Version 2.0 (see LICENSE).
 
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::fmt::Display;
 use std::io::Write;
@@ -19,7 +19,9 @@ use crate::context::{Context, Core};
 use crate::core::{display_sorted_in_parens, throw, Failure, Key, Params, TypeId, Value};
 use crate::externs;
 use crate::externs::engine_aware::{self, EngineAwareInformation};
+use crate::local_binaries::DiscoveredBinary;
 use crate::selectors;
+use crate::session::PlannedProcess;
 use crate::tasks::{self, Rule};
 use crate::Types;
 use bytes::BufMut;
@@ -28,22 +30,31 @@ use fs::{
   self, Dir, DirectoryListing, File, FileContent, GlobExpansionConjunction, GlobMatching, Link,
   PathGlobs, PathStat, PreparedPathGlobs, RelativePath, StrictGlobMatching, VFS,
 };
+use process_execution::platform_details::LibcFlavor;
+use process_execution::scanners::{self, Finding};
 use process_execution::{
-  self, CacheDest, CacheName, MultiPlatformProcess, Platform, Process, ProcessCacheScope,
+  self, CacheDest, CacheName, DescriptionFields, FallibleProcessResultWithPlatform,
+  MultiPlatformProcess, OutputPlatform, OutputScannerSpec, Platform, PlatformRequirements, Process,
+  ProcessCacheScope, ProcessResultMetadata, ProcessTags, ResourceLimits, ResponseFileSpec,
 };
 
 use bytes::Bytes;
 use graph::{Entry, Node, NodeError, NodeVisualizer};
-use hashing::Digest;
+use hashing::{Digest, Fingerprint, EMPTY_DIGEST};
 use reqwest::Error;
 use std::pin::Pin;
-use store::{self, StoreFileByDigest};
+use store::{self, SnapshotOps, StoreFileByDigest};
 use workunit_store::{
   with_workunit, ArtifactOutput, Level, UserMetadataItem, UserMetadataPyValue, WorkunitMetadata,
 };
 
 pub type NodeResult<T> = Result<T, Failure>;
 
+///
+/// The exit code of the results which are synthesized for Processes in a dry-run Session.
+///
+pub const DRY_RUN_EXIT_CODE: i32 = 125;
+
 #[async_trait]
 impl VFS<Failure> for Context {
   async fn read_link(&self, link: &Link) -> Result<PathBuf, Failure> {
@@ -235,7 +246,10 @@ pub struct MultiPlatformExecuteProcess {
 }
 
 impl MultiPlatformExecuteProcess {
-  fn lift_process(value: &Value, platform_constraint: Option<Platform>) -> Result<Process, String> {
+  pub fn lift_process(
+    value: &Value,
+    platform_constraint: Option<Platform>,
+  ) -> Result<Process, String> {
     let env = externs::getattr_from_frozendict(&value, "env");
 
     let working_directory = {
@@ -251,13 +265,41 @@ impl MultiPlatformExecuteProcess {
     let digest =
       lift_directory_digest(&py_digest).map_err(|err| format!("Error parsing digest {}", err))?;
 
-    let output_files = externs::getattr::<Vec<String>>(&value, "output_files")
+    let discovered_inputs_depfile = {
+      let val = externs::getattr_as_string(&value, "discovered_inputs_depfile");
+      if val.is_empty() {
+        None
+      } else {
+        Some(RelativePath::new(val.as_str())?)
+      }
+    };
+
+    let mut output_files: BTreeSet<RelativePath> =
+      externs::getattr::<Vec<String>>(&value, "output_files")
+        .unwrap()
+        .into_iter()
+        .map(RelativePath::new)
+        .collect::<Result<_, _>>()?;
+    // The depfile is always captured, since the engine consumes it after execution.
+    if let Some(ref depfile) = discovered_inputs_depfile {
+      output_files.insert(depfile.clone());
+    }
+
+    let source_output_files: BTreeSet<RelativePath> =
+      externs::getattr::<Vec<String>>(&value, "source_output_files")
+        .unwrap()
+        .into_iter()
+        .map(RelativePath::new)
+        .collect::<Result<_, _>>()?;
+    output_files.extend(source_output_files.iter().cloned());
+
+    let output_directories = externs::getattr::<Vec<String>>(&value, "output_directories")
       .unwrap()
       .into_iter()
       .map(RelativePath::new)
       .collect::<Result<_, _>>()?;
 
-    let output_directories = externs::getattr::<Vec<String>>(&value, "output_directories")
+    let output_paths = externs::getattr::<Vec<String>>(&value, "output_paths")
       .unwrap()
       .into_iter()
       .map(RelativePath::new)
@@ -271,6 +313,12 @@ impl MultiPlatformExecuteProcess {
       Some(Duration::from_millis((timeout_in_seconds * 1000.0) as u64))
     };
 
+    let description_fields = DescriptionFields {
+      tool: externs::getattr(&value, "tool").unwrap(),
+      verb: externs::getattr(&value, "verb").unwrap(),
+      target_count: externs::getattr(&value, "target_count").unwrap(),
+    };
+    // NB: If any structured fields are set, the description has already been rendered from them.
     let description = externs::getattr_as_string(&value, "description");
     let py_level: PyObject = externs::getattr(&value, "level").unwrap();
     let level = externs::val_to_log_level(&py_level)?;
@@ -291,6 +339,33 @@ impl MultiPlatformExecuteProcess {
 
     let is_nailgunnable: bool = externs::getattr(&value, "is_nailgunnable").unwrap();
 
+    let remote_worker_key = {
+      let s = externs::getattr_as_string(&value, "remote_worker_key");
+      if s.is_empty() {
+        None
+      } else {
+        Some(s)
+      }
+    };
+
+    let container_image = {
+      let s = externs::getattr_as_string(&value, "container_image");
+      if s.is_empty() {
+        None
+      } else {
+        Some(s)
+      }
+    };
+
+    let remote_affinity_key = {
+      let s = externs::getattr_as_string(&value, "remote_affinity_key");
+      if s.is_empty() {
+        None
+      } else {
+        Some(s)
+      }
+    };
+
     let execution_slot_variable = {
       let s = externs::getattr_as_string(&value, "execution_slot_variable");
       if s.is_empty() {
@@ -300,29 +375,432 @@ impl MultiPlatformExecuteProcess {
       }
     };
 
+    let leased_port_variables: Vec<String> =
+      externs::getattr(&value, "leased_port_variables").unwrap();
+
+    let invocation_id_variable = {
+      let s = externs::getattr_as_string(&value, "invocation_id_variable");
+      if s.is_empty() {
+        None
+      } else {
+        Some(s)
+      }
+    };
+
     let cache_scope =
       externs::getattr_as_string(&externs::getattr(&value, "cache_scope").unwrap(), "name")
         .try_into()?;
 
+    let output_platform = {
+      let platform_independent: bool = externs::getattr(&value, "platform_independent").unwrap();
+      let target_platform = externs::getattr_as_string(&value, "target_platform");
+      if platform_independent {
+        OutputPlatform::Independent
+      } else if target_platform.is_empty() {
+        OutputPlatform::Execution
+      } else {
+        OutputPlatform::Target(Platform::try_from(target_platform)?)
+      }
+    };
+
+    let pipe_from_argv = {
+      let argv: Vec<String> = externs::getattr(&value, "pipe_from_argv").unwrap();
+      if argv.is_empty() {
+        None
+      } else {
+        Some(argv)
+      }
+    };
+
+    let version_probes: Vec<Vec<String>> = externs::getattr(&value, "version_probes").unwrap();
+
+    let output_scanners = externs::getattr::<Vec<Value>>(&value, "output_scanners")
+      .unwrap()
+      .into_iter()
+      .map(|scanner| {
+        OutputScannerSpec::new(
+          &externs::getattr_as_string(&externs::getattr(&scanner, "kind").unwrap(), "value"),
+          externs::getattr_as_string(&scanner, "name"),
+          externs::getattr_as_string(&scanner, "pattern"),
+        )
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let platform_requirements = {
+      let optional_string = |field: &str| {
+        let s = externs::getattr_as_string(&value, field);
+        if s.is_empty() {
+          None
+        } else {
+          Some(s)
+        }
+      };
+      PlatformRequirements {
+        libc: optional_string("libc")
+          .map(|flavor| LibcFlavor::new(&flavor))
+          .transpose()?,
+        min_libc_version: optional_string("min_libc_version"),
+        min_macos_version: optional_string("min_macos_version"),
+        properties: externs::getattr_from_frozendict(&value, "platform_properties"),
+      }
+    };
+
+    let response_file =
+      match externs::check_for_python_none(externs::getattr(&value, "response_file").unwrap()) {
+        Some(response_file) => Some(ResponseFileSpec::new(
+          externs::getattr(&response_file, "argv_prefix_len").unwrap(),
+          externs::getattr_as_string(&response_file, "prefix"),
+          &externs::getattr_as_string(
+            &externs::getattr(&response_file, "quoting").unwrap(),
+            "value",
+          ),
+        )?),
+        None => None,
+      };
+
+    let resource_limits = {
+      let limits: PyObject = externs::getattr(&value, "resource_limits").unwrap();
+      ResourceLimits {
+        max_rss_bytes: externs::getattr(&limits, "max_rss_bytes").unwrap(),
+        cpu_seconds: externs::getattr(&limits, "cpu_seconds").unwrap(),
+        nofile: externs::getattr(&limits, "nofile").unwrap(),
+        nproc: externs::getattr(&limits, "nproc").unwrap(),
+      }
+    };
+
     Ok(process_execution::Process {
       argv: externs::getattr(&value, "argv").unwrap(),
+      pipe_from_argv,
       env,
       working_directory,
+      create_working_directory: externs::getattr(&value, "create_working_directory").unwrap(),
       input_files: digest,
       output_files,
       output_directories,
+      output_paths,
       timeout,
+      resource_limits,
       description,
+      description_fields,
+      tags: ProcessTags::new(externs::getattr_from_frozendict(&value, "tags")),
       level,
       append_only_caches,
       jdk_home,
       platform_constraint,
+      platform_requirements,
+      output_platform,
       is_nailgunnable,
+      allocate_tty: externs::getattr(&value, "allocate_tty").unwrap(),
+      container_image,
+      remote_worker_key,
+      remote_affinity_key,
       execution_slot_variable,
+      leased_port_variables,
+      invocation_id_variable,
       cache_scope,
+      discovered_inputs_depfile,
+      source_output_files,
+      version_probes,
+      version_probe_fingerprint: None,
+      output_scanners,
+      max_output_bytes: externs::getattr(&value, "max_output_bytes").unwrap(),
+      batch_argv_prefix_len: externs::getattr(&value, "batch_argv_prefix_len").unwrap(),
+      response_file,
     })
   }
 
+  ///
+  /// Records the given Process as planned by the (dry-run) Session, and synthesizes a failed result
+  /// which describes it, rather than running it.
+  ///
+  async fn dry_run(context: &Context, process: &Process) -> NodeResult<ProcessResult> {
+    let store = context.core.store();
+    let action_digest = process_execution::remote::action_digest(
+      process,
+      context.core.process_execution_metadata.clone(),
+    )
+    .map_err(|e| throw(&e))?;
+    let input_bytes: usize = store
+      .expand_digests(
+        std::iter::once(&process.input_files),
+        store::LocalMissingBehavior::Fetch,
+      )
+      .await
+      .map_err(|e| throw(&e))?
+      .into_iter()
+      .filter(|(_, entry_type)| *entry_type == store::EntryType::File)
+      .map(|(digest, _)| digest.size_bytes)
+      .sum();
+
+    context.session.record_planned_process(PlannedProcess {
+      description: process.description.clone(),
+      action_digest,
+      input_digest: process.input_files,
+      input_bytes,
+    });
+
+    let stdout_digest = store
+      .store_file_bytes(Bytes::new(), true)
+      .await
+      .map_err(|e| throw(&e))?;
+    let stderr = format!(
+      "Dry run: `{}` was not run.\n  action digest: {:?}\n  input digest: {:?} ({} bytes of \
+       files)\n",
+      process.description, action_digest, process.input_files, input_bytes,
+    );
+    let stderr_digest = store
+      .store_file_bytes(Bytes::from(stderr), true)
+      .await
+      .map_err(|e| throw(&e))?;
+    let platform = match process.platform_constraint {
+      Some(platform) => platform,
+      None => Platform::current().map_err(|e| throw(&e))?,
+    };
+    let mut metadata = ProcessResultMetadata::new(None);
+    metadata.dry_run = true;
+    Ok(ProcessResult(
+      FallibleProcessResultWithPlatform {
+        stdout_digest,
+        stderr_digest,
+        exit_code: DRY_RUN_EXIT_CODE,
+        output_directory: EMPTY_DIGEST,
+        platform,
+        metadata,
+      },
+      vec![],
+      vec![],
+    ))
+  }
+
+  ///
+  /// Runs the version probes of the given Process (each of which is memoized for the Session), and
+  /// returns a fingerprint of their outputs.
+  ///
+  async fn run_version_probes(context: &Context, process: &Process) -> NodeResult<Fingerprint> {
+    let probes = process.version_probes.iter().map(|argv| {
+      let probe = Process {
+        argv: argv.clone(),
+        pipe_from_argv: None,
+        env: process.env.clone(),
+        working_directory: None,
+        create_working_directory: true,
+        input_files: EMPTY_DIGEST,
+        output_files: BTreeSet::new(),
+        output_directories: BTreeSet::new(),
+        output_paths: BTreeSet::new(),
+        timeout: Some(Duration::from_secs(60)),
+        resource_limits: ResourceLimits::default(),
+        execution_slot_variable: None,
+        leased_port_variables: vec![],
+        invocation_id_variable: None,
+        description: format!("Probe tool version with `{}`", argv.join(" ")),
+        description_fields: DescriptionFields::default(),
+        tags: ProcessTags::default(),
+        level: Level::Debug,
+        append_only_caches: BTreeMap::new(),
+        jdk_home: None,
+        platform_constraint: process.platform_constraint,
+        platform_requirements: process.platform_requirements.clone(),
+        output_platform: process.output_platform,
+        is_nailgunnable: false,
+        allocate_tty: false,
+        container_image: None,
+        remote_worker_key: None,
+        remote_affinity_key: None,
+        // Probes are never cached persistently, so that a tool which is upgraded between runs is
+        // noticed.
+        cache_scope: ProcessCacheScope::Never,
+        discovered_inputs_depfile: None,
+        source_output_files: BTreeSet::new(),
+        version_probes: vec![],
+        version_probe_fingerprint: None,
+        output_scanners: vec![],
+        max_output_bytes: None,
+        batch_argv_prefix_len: None,
+        response_file: None,
+      };
+      context.get(MultiPlatformExecuteProcess {
+        cache_scope: ProcessCacheScope::Never,
+        process: MultiPlatformProcess(
+          vec![(process.platform_constraint, probe)]
+            .into_iter()
+            .collect(),
+        ),
+      })
+    });
+    let results = future::try_join_all(probes).await?;
+
+    let mut probe_outputs = String::new();
+    for (argv, ProcessResult(result, ..)) in process.version_probes.iter().zip(results) {
+      probe_outputs.push_str(&format!(
+        "{:?}\0{}\0{}\n",
+        argv, result.exit_code, result.stdout_digest.hash
+      ));
+    }
+    Ok(Digest::of_bytes(probe_outputs.as_bytes()).hash)
+  }
+
+  ///
+  /// Runs the given output scanners over the stdout and stderr of the given result.
+  ///
+  async fn scan_outputs(
+    context: &Context,
+    result: &process_execution::FallibleProcessResultWithPlatform,
+    output_scanners: Vec<OutputScannerSpec>,
+  ) -> NodeResult<Vec<(String, Finding)>> {
+    if output_scanners.is_empty() {
+      return Ok(vec![]);
+    }
+    let store = context.core.store();
+    let load = |digest: Digest| {
+      let store = store.clone();
+      async move {
+        store
+          .load_file_bytes_with(digest, Bytes::copy_from_slice)
+          .await?
+          .map(|(bytes, _)| bytes)
+          .ok_or_else(|| format!("Bytes for Digest {:?} not found in store", digest))
+      }
+    };
+    let (stdout, stderr) = future::try_join(load(result.stdout_digest), load(result.stderr_digest))
+      .await
+      .map_err(|e| throw(&e))?;
+
+    // Scan the outputs (which might be large) off of the io pool.
+    context
+      .core
+      .executor
+      .spawn_blocking(move || scanners::scan(&output_scanners, &stdout, &stderr))
+      .await
+      .map_err(|e| throw(&e))
+  }
+
+  ///
+  /// Computes a digest for each of the given source output files of a process, containing only
+  /// that file. Files which the process did not create (possibly because it failed) are omitted.
+  ///
+  async fn digest_source_outputs(
+    context: &Context,
+    output_directory: Digest,
+    source_output_files: BTreeSet<RelativePath>,
+  ) -> NodeResult<Vec<(RelativePath, Digest)>> {
+    let store = context.core.store();
+    let digests = source_output_files.into_iter().map(|path| {
+      let store = store.clone();
+      async move {
+        let globs = PathGlobs::new(
+          vec![path.to_str().unwrap().to_owned()],
+          StrictGlobMatching::Ignore,
+          GlobExpansionConjunction::AllMatch,
+        )
+        .parse()?;
+        let digest = store
+          .subset(output_directory, store::SubsetParams { globs })
+          .await
+          .map_err(|e| format!("{:?}", e))?;
+        Ok::<_, String>((path, digest))
+      }
+    });
+    let digests = future::try_join_all(digests).await.map_err(|e| throw(&e))?;
+    Ok(
+      digests
+        .into_iter()
+        .filter(|(_, digest)| *digest != EMPTY_DIGEST)
+        .collect(),
+    )
+  }
+
+  ///
+  /// Parses the given depfile from the outputs of a process, and snapshots the discovered inputs
+  /// which are within the build root but are not already inputs of the process, which records them
+  /// as dependencies of this Node: it will then be invalidated if any of them change. Relative
+  /// paths in the depfile are relative to the working directory of the process.
+  ///
+  /// Returns the digest of the snapshot, which is empty if no new inputs were discovered.
+  ///
+  async fn discover_inputs(
+    context: &Context,
+    process: &Process,
+    output_directory: Digest,
+    depfile: &RelativePath,
+  ) -> NodeResult<Digest> {
+    let store = context.core.store();
+    let depfile_globs = PathGlobs::new(
+      vec![depfile.to_str().unwrap().to_owned()],
+      StrictGlobMatching::Ignore,
+      GlobExpansionConjunction::AllMatch,
+    )
+    .parse()
+    .map_err(|e| throw(&e))?;
+    let depfile_digest = store
+      .subset(
+        output_directory,
+        store::SubsetParams {
+          globs: depfile_globs,
+        },
+      )
+      .await
+      .map_err(|e| throw(&format!("{:?}", e)))?;
+    let content = match store
+      .contents_for_directory(depfile_digest)
+      .await
+      .map_err(|e| throw(&e))?
+      .pop()
+    {
+      Some(file_content) => file_content.content,
+      None => {
+        // The process did not emit a depfile (possibly because it failed).
+        return Ok(EMPTY_DIGEST);
+      }
+    };
+    let discovered_inputs =
+      process_execution::depfile::parse_depfile(&String::from_utf8_lossy(&content))
+        .map_err(|e| throw(&format!("Failed to parse {}: {}", depfile.display(), e)))?;
+
+    let existing_inputs = store::Snapshot::from_digest(store, process.input_files)
+      .await
+      .map_err(|e| throw(&e))?
+      .path_stats
+      .into_iter()
+      .map(|path_stat| path_stat.path().to_owned())
+      .collect::<HashSet<_>>();
+    let working_directory = process
+      .working_directory
+      .as_ref()
+      .map(|working_directory| working_directory.as_path())
+      .unwrap_or_else(|| Path::new(""));
+    let discovered_globs = discovered_inputs
+      .into_iter()
+      .filter_map(
+        |path| match RelativePath::new(working_directory.join(&path)) {
+          Ok(path) => Some(path),
+          Err(_) => {
+            log::debug!(
+              "Ignoring discovered input outside of the build root: {}",
+              path
+            );
+            None
+          }
+        },
+      )
+      .filter(|path| !existing_inputs.contains(path.as_path()))
+      .map(|path| path.to_str().unwrap().to_owned())
+      .collect::<Vec<_>>();
+    if discovered_globs.is_empty() {
+      return Ok(EMPTY_DIGEST);
+    }
+    // NB: The dependency is recorded even if a file cannot be read (because it has been deleted,
+    // for example), so unmatched inputs are ignored here: any inputs that the process actually
+    // requires will have been validated by its execution.
+    context
+      .get(Snapshot::from_path_globs(PathGlobs::new(
+        discovered_globs,
+        StrictGlobMatching::Ignore,
+        GlobExpansionConjunction::AnyMatch,
+      )))
+      .await
+  }
+
   pub fn lift(value: &Value) -> Result<MultiPlatformExecuteProcess, String> {
     let raw_constraints = externs::getattr::<Vec<Option<String>>>(&value, "platform_constraints")?;
     let constraints = raw_constraints
@@ -370,27 +848,87 @@ impl WrappedNode for MultiPlatformExecuteProcess {
   type Item = ProcessResult;
 
   async fn run_wrapped_node(self, context: Context) -> NodeResult<ProcessResult> {
-    let request = self.process;
+    let mut request = self.process;
 
-    if context
+    if let Some(compatible_request) = context
       .core
       .command_runner
       .extract_compatible_request(&request)
-      .is_some()
     {
       let command_runner = &context.core.command_runner;
 
+      // The working directory is validated (and possibly created) once, before the process reaches
+      // any runner, so that every cache and runner sees the same inputs.
+      let compatible_request = process_execution::working_directory::prepare_working_directory(
+        &context.core.store(),
+        compatible_request,
+      )
+      .await
+      .map_err(|e| throw(&e))?;
+      request.0.insert(
+        compatible_request.platform_constraint,
+        compatible_request.clone(),
+      );
+
+      if context.session.is_dry_run() {
+        return Self::dry_run(&context, &compatible_request).await;
+      }
+
       let execution_context = process_execution::Context::new(
         context.session.workunit_store(),
         context.session.build_id().to_string(),
+        context.session.invocation_id().to_owned(),
+        context.session.priority(),
+        context.session.captured_output_bytes(),
       );
 
-      let res = command_runner
-        .run(request, execution_context)
+      if !compatible_request.version_probes.is_empty() {
+        let fingerprint = Self::run_version_probes(&context, &compatible_request).await?;
+        if let Some(process) = request.0.get_mut(&compatible_request.platform_constraint) {
+          process.version_probe_fingerprint = Some(fingerprint);
+        }
+      }
+
+      let mut res = command_runner
+        .run(request.clone(), execution_context.clone())
         .await
         .map_err(|e| throw(&e))?;
 
-      Ok(ProcessResult(res))
+      // The inputs that a process discovers are only known once it has run, and so are not part of
+      // its cache key. To avoid reusing a result which was computed from different versions of
+      // them, the process is run again with them added to its inputs, until it discovers no more.
+      if let Some(depfile) = &compatible_request.discovered_inputs_depfile {
+        let mut process = request.0[&compatible_request.platform_constraint].clone();
+        loop {
+          let discovered_inputs =
+            Self::discover_inputs(&context, &process, res.output_directory, depfile).await?;
+          if discovered_inputs == EMPTY_DIGEST {
+            break;
+          }
+          process.input_files = context
+            .core
+            .store()
+            .merge(vec![process.input_files, discovered_inputs])
+            .await
+            .map_err(|e| throw(&format!("{:?}", e)))?;
+          request
+            .0
+            .insert(process.platform_constraint, process.clone());
+          res = command_runner
+            .run(request.clone(), execution_context.clone())
+            .await
+            .map_err(|e| throw(&e))?;
+        }
+      }
+
+      let output_scanners = compatible_request.output_scanners;
+      let source_output_files = compatible_request.source_output_files;
+
+      let findings = Self::scan_outputs(&context, &res, output_scanners).await?;
+      let source_digests =
+        Self::digest_source_outputs(&context, res.output_directory, source_output_files).await?;
+
+      Ok(ProcessResult(res, findings, source_digests))
     } else {
       Err(throw(&format!(
         "No compatible platform found for request: {:?}",
@@ -400,8 +938,56 @@ impl WrappedNode for MultiPlatformExecuteProcess {
   }
 }
 
+///
+/// The result of a Process, along with the findings of its output scanners, and the digests of
+/// each of its source output files.
+///
+/// Consumers which depend on the digest of an individual source output (rather than on the whole
+/// output directory) are memoized on it in the same way as consumers of a source file: when the
+/// process re-runs, they are only invalidated if that file changed.
+///
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct ProcessResult(pub process_execution::FallibleProcessResultWithPlatform);
+pub struct ProcessResult(
+  pub process_execution::FallibleProcessResultWithPlatform,
+  pub Vec<(String, Finding)>,
+  pub Vec<(RelativePath, Digest)>,
+);
+
+impl ProcessResult {
+  ///
+  /// Converts the findings of the output scanners of the Process (and the location of its sandbox,
+  /// if it was preserved) into workunit metadata.
+  ///
+  fn user_metadata(&self) -> Vec<(String, Value)> {
+    let preserved_sandbox = self.0.metadata.preserved_sandbox.as_ref().map(|path| {
+      (
+        "preserved_sandbox".to_owned(),
+        externs::store_utf8(&path.to_string_lossy()),
+      )
+    });
+    self
+      .1
+      .iter()
+      .map(|(name, finding)| {
+        let value = match finding {
+          Finding::Lines(lines) => {
+            externs::store_tuple(lines.iter().map(|line| externs::store_utf8(line)).collect())
+          }
+          Finding::Count(count) => externs::store_u64(*count),
+          Finding::Counts(counts) => externs::store_dict(
+            counts
+              .iter()
+              .map(|(key, count)| (externs::store_utf8(key), externs::store_u64(*count)))
+              .collect(),
+          )
+          .unwrap(),
+        };
+        (name.clone(), value)
+      })
+      .chain(preserved_sandbox)
+      .collect()
+  }
+}
 
 ///
 /// A Node that represents reading the destination of a symlink (non-recursively).
@@ -445,6 +1031,15 @@ impl WrappedNode for DigestFile {
   type Item = hashing::Digest;
 
   async fn run_wrapped_node(self, context: Context) -> NodeResult<hashing::Digest> {
+    if let Some(digest) = context
+      .core
+      .store()
+      .digest_of_tagged_file(context.core.vfs.file_path(&self.0))
+      .map_err(|e| throw(&e))
+      .await?
+    {
+      return Ok(digest);
+    }
     let content = context
       .core
       .vfs
@@ -793,7 +1388,7 @@ impl DownloadedFile {
     &self,
     core: Arc<Core>,
     url: Url,
-    digest: hashing::Digest,
+    expected_digest: Option<hashing::Digest>,
   ) -> Result<store::Snapshot, String> {
     let file_name = url
       .path_segments()
@@ -806,11 +1401,50 @@ impl DownloadedFile {
         &url, &file_name, e
       )
     })?;
+    let digest = match expected_digest {
+      Some(digest) => {
+        DownloadedFile::load_or_download_digest(&core, url, file_name, digest, None).await?
+      }
+      None => {
+        // The download was not pinned to a digest: trust the digest of its first download.
+        let checksums = &core.download_checksums;
+        match checksums.expected_digest(url.as_str())? {
+          Some(digest) => {
+            let repin_hint = format!(
+              "The content of {} has changed since it was first downloaded, and its digest no \
+               longer matches the one recorded in {}. If the change is expected, re-pin it with \
+               `--download-repin={}`.",
+              url,
+              checksums.path().display(),
+              url
+            );
+            DownloadedFile::load_or_download_digest(&core, url, file_name, digest, Some(repin_hint))
+              .await?
+          }
+          None => {
+            let digest =
+              DownloadedFile::download(core.clone(), url.clone(), file_name, None, None).await?;
+            checksums.record(url.as_str(), digest)?;
+            digest
+          }
+        }
+      }
+    };
+    core.store().snapshot_of_one_file(path, digest, true).await
+  }
+
+  async fn load_or_download_digest(
+    core: &Arc<Core>,
+    url: Url,
+    file_name: String,
+    digest: hashing::Digest,
+    mismatch_hint: Option<String>,
+  ) -> Result<hashing::Digest, String> {
     let maybe_bytes = core.store().load_file_bytes_with(digest, |_| ()).await?;
     if maybe_bytes.is_none() {
-      DownloadedFile::download(core.clone(), url, file_name, digest).await?;
+      DownloadedFile::download(core.clone(), url, file_name, Some(digest), mismatch_hint).await?;
     }
-    core.store().snapshot_of_one_file(path, digest, true).await
+    Ok(digest)
   }
 
   async fn start_download(
@@ -821,6 +1455,13 @@ impl DownloadedFile {
     if url.scheme() == "file" {
       return Ok(Box::new(FileDownload::start(url.path(), file_name).await?));
     }
+    if core.offline {
+      return Err(format!(
+        "Cannot download {} because Pants is running in offline mode (`--offline`), and the \
+        file was not previously downloaded.",
+        url
+      ));
+    }
     Ok(Box::new(NetDownload::start(&core, url, file_name).await?))
   }
 
@@ -828,8 +1469,9 @@ impl DownloadedFile {
     core: Arc<Core>,
     url: Url,
     file_name: String,
-    expected_digest: hashing::Digest,
-  ) -> Result<(), String> {
+    expected_digest: Option<hashing::Digest>,
+    mismatch_hint: Option<String>,
+  ) -> Result<hashing::Digest, String> {
     let mut response_stream = DownloadedFile::start_download(&core, url, file_name).await?;
 
     let (actual_digest, bytes) = {
@@ -859,32 +1501,46 @@ impl DownloadedFile {
         }
       }
 
+      let size_limit = expected_digest.map_or(usize::MAX, |digest| digest.size_bytes);
       let mut hasher = hashing::WriterHasher::new(SizeLimiter {
-        writer: bytes::BytesMut::with_capacity(expected_digest.size_bytes).writer(),
+        writer: bytes::BytesMut::with_capacity(
+          expected_digest.map_or(0, |digest| digest.size_bytes),
+        )
+        .writer(),
         written: 0,
-        size_limit: expected_digest.size_bytes,
+        size_limit,
       });
 
       while let Some(next_chunk) = response_stream.next().await {
         let chunk =
           next_chunk.map_err(|err| format!("Error reading URL fetch response: {}", err))?;
-        hasher
-          .write_all(&chunk)
-          .map_err(|err| format!("Error hashing/capturing URL fetch response: {}", err))?;
+        hasher.write_all(&chunk).map_err(|err| {
+          let err = format!("Error hashing/capturing URL fetch response: {}", err);
+          match &mismatch_hint {
+            Some(hint) => format!("{}\n\n{}", err, hint),
+            None => err,
+          }
+        })?;
       }
       let (digest, bytewriter) = hasher.finish();
       (digest, bytewriter.writer.into_inner().freeze())
     };
 
-    if expected_digest != actual_digest {
-      return Err(format!(
-        "Wrong digest for downloaded file: want {:?} got {:?}",
-        expected_digest, actual_digest
-      ));
+    if let Some(expected_digest) = expected_digest {
+      if expected_digest != actual_digest {
+        let err = format!(
+          "Wrong digest for downloaded file: want {:?} got {:?}",
+          expected_digest, actual_digest
+        );
+        return Err(match mismatch_hint {
+          Some(hint) => format!("{}\n\n{}", err, hint),
+          None => err,
+        });
+      }
     }
 
     let _ = core.store().store_file_bytes(bytes, true).await?;
-    Ok(())
+    Ok(actual_digest)
   }
 }
 
@@ -899,9 +1555,12 @@ impl WrappedNode for DownloadedFile {
     let url = Url::parse(&url_str)
       .map_err(|err| throw(&format!("Error parsing URL {}: {}", url_str, err)))?;
 
-    let py_digest: Value = externs::getattr(&value, "expected_digest").unwrap();
-    let expected_digest =
-      lift_file_digest(&context.core.types, &py_digest).map_err(|s| throw(&s))?;
+    let py_digest: PyObject = externs::getattr(&value, "expected_digest").unwrap();
+    let expected_digest = if py_digest == externs::none() {
+      None
+    } else {
+      Some(lift_file_digest(&context.core.types, &py_digest).map_err(|s| throw(&s))?)
+    };
 
     let snapshot = self
       .load_or_download(context.core, url, expected_digest)
@@ -917,6 +1576,53 @@ impl From<DownloadedFile> for NodeKey {
   }
 }
 
+///
+/// A candidate path for a binary on the local machine, which is discovered (and optionally tested)
+/// using `LocalBinaries`.
+///
+/// The `fs_subject` of the node is the directory containing the candidate, so that any change to
+/// an entry of the directory invalidates it.
+///
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct LocalBinary {
+  pub path: PathBuf,
+  pub test_args: Option<Vec<String>>,
+}
+
+#[async_trait]
+impl WrappedNode for LocalBinary {
+  type Item = Option<DiscoveredBinary>;
+
+  async fn run_wrapped_node(self, context: Context) -> NodeResult<Option<DiscoveredBinary>> {
+    let core = context.core;
+    // NB: Unlike other nodes with an `fs_subject`, the watch is installed here, because the
+    // directory containing the candidate need not exist.
+    if let (Some(watcher), Some(directory)) = (&core.watcher, self.path.parent()) {
+      if directory.is_dir() {
+        watcher
+          .watch(directory.to_owned())
+          .await
+          .map_err(|e| throw(&e))?;
+      }
+    }
+    let executor = core.executor.clone();
+    executor
+      .spawn_blocking(move || {
+        core
+          .local_binaries
+          .discover(&self.path, self.test_args.as_deref())
+      })
+      .await
+      .map_err(|e| throw(&e))
+  }
+}
+
+impl From<LocalBinary> for NodeKey {
+  fn from(n: LocalBinary) -> Self {
+    NodeKey::LocalBinary(n)
+  }
+}
+
 #[derive(Clone, Eq, Hash, PartialEq)]
 pub struct Task {
   params: Params,
@@ -1065,13 +1771,30 @@ impl WrappedNode for Task {
     let entry = self.entry;
     let product = self.product;
     let can_modify_workunit = self.task.can_modify_workunit;
+    let timeout = self.task.timeout;
+    let name = self.task.display_info.name;
 
     let result_val =
       externs::call_function(&externs::val_for(&func.0), &deps).map_err(Failure::from_py_err)?;
     let mut result_val: Value = result_val.into();
     let mut result_type = externs::get_type_for(&result_val);
     if result_type == context.core.types.coroutine {
-      result_val = Self::generate(context.clone(), params, entry, result_val).await?;
+      let generate = Self::generate(context.clone(), params, entry, result_val);
+      // NB: The timeout can only interrupt a coroutine while it is awaiting a `Get`: a rule which
+      // blocks synchronously will not be interrupted until it next yields.
+      result_val = if let Some(timeout) = timeout {
+        tokio::time::timeout(timeout, generate)
+          .await
+          .map_err(|_| {
+            throw(&format!(
+              "Rule `{}` timed out after {:.1} seconds.",
+              name,
+              timeout.as_secs_f64()
+            ))
+          })??
+      } else {
+        generate.await?
+      };
       result_type = externs::get_type_for(&result_val);
     }
 
@@ -1143,6 +1866,7 @@ impl NodeVisualizer<NodeKey> for Visualizer {
 pub enum NodeKey {
   DigestFile(DigestFile),
   DownloadedFile(DownloadedFile),
+  LocalBinary(LocalBinary),
   MultiPlatformExecuteProcess(Box<MultiPlatformExecuteProcess>),
   ReadLink(ReadLink),
   Scandir(Scandir),
@@ -1158,6 +1882,7 @@ impl NodeKey {
     match self {
       &NodeKey::MultiPlatformExecuteProcess(..) => "ProcessResult".to_string(),
       &NodeKey::DownloadedFile(..) => "DownloadedFile".to_string(),
+      &NodeKey::LocalBinary(..) => "LocalBinary".to_string(),
       &NodeKey::Select(ref s) => format!("{}", s.product),
       &NodeKey::SessionValues(_) => "SessionValues".to_string(),
       &NodeKey::Task(ref s) => format!("{}", s.product),
@@ -1169,11 +1894,32 @@ impl NodeKey {
     }
   }
 
+  ///
+  /// The type of this Node for the purposes of introspection: the name of the @rule for Tasks, and
+  /// otherwise the kind of Node.
+  ///
+  pub fn node_type(&self) -> String {
+    match self {
+      NodeKey::Task(ref task) => task.task.display_info.name.clone(),
+      NodeKey::MultiPlatformExecuteProcess(..) => "process".to_string(),
+      NodeKey::Snapshot(..) => "snapshot".to_string(),
+      NodeKey::Paths(..) => "paths".to_string(),
+      NodeKey::DigestFile(..) => "digest_file".to_string(),
+      NodeKey::DownloadedFile(..) => "downloaded_file".to_string(),
+      NodeKey::LocalBinary(..) => "local_binary".to_string(),
+      NodeKey::ReadLink(..) => "read_link".to_string(),
+      NodeKey::Scandir(..) => "scandir".to_string(),
+      NodeKey::Select(..) => "select".to_string(),
+      NodeKey::SessionValues(..) => "session_values".to_string(),
+    }
+  }
+
   pub fn fs_subject(&self) -> Option<&Path> {
     match self {
       &NodeKey::DigestFile(ref s) => Some(s.0.path.as_path()),
       &NodeKey::ReadLink(ref s) => Some((s.0).0.as_path()),
       &NodeKey::Scandir(ref s) => Some((s.0).0.as_path()),
+      &NodeKey::LocalBinary(ref s) => s.path.parent(),
 
       // Not FS operations:
       // Explicitly listed so that if people add new NodeKeys they need to consider whether their
@@ -1192,7 +1938,7 @@ impl NodeKey {
   fn workunit_level(&self) -> Level {
     match self {
       NodeKey::Task(ref task) => task.task.display_info.level,
-      NodeKey::DownloadedFile(..) => Level::Debug,
+      NodeKey::DownloadedFile(..) | NodeKey::LocalBinary(..) => Level::Debug,
       _ => Level::Trace,
     }
   }
@@ -1209,6 +1955,7 @@ impl NodeKey {
       NodeKey::Paths(..) => "paths".to_string(),
       NodeKey::DigestFile(..) => "digest_file".to_string(),
       NodeKey::DownloadedFile(..) => "downloaded_file".to_string(),
+      NodeKey::LocalBinary(..) => "local_binary".to_string(),
       NodeKey::ReadLink(..) => "read_link".to_string(),
       NodeKey::Scandir(..) => "scandir".to_string(),
       NodeKey::Select(..) => "select".to_string(),
@@ -1233,6 +1980,7 @@ impl NodeKey {
         Some(format!("Fingerprinting: {}", path.display()))
       }
       NodeKey::DownloadedFile(ref d) => Some(format!("Downloading: {}", d.0)),
+      NodeKey::LocalBinary(ref b) => Some(format!("Testing binary: {}", b.path.display())),
       NodeKey::ReadLink(ReadLink(Link(path))) => Some(format!("Reading link: {}", path.display())),
       NodeKey::Scandir(Scandir(Dir(path))) => {
         Some(format!("Reading directory: {}", path.display()))
@@ -1253,7 +2001,20 @@ impl Node for NodeKey {
   async fn run(self, context: Context) -> Result<NodeOutput, Failure> {
     let workunit_store_handle = workunit_store::expect_workunit_store_handle();
 
-    let user_facing_name = self.user_facing_name();
+    let user_facing_name = match (&self, self.user_facing_name()) {
+      // NB: The descriptions of processes frequently embed their arguments, which may be secret.
+      (NodeKey::MultiPlatformExecuteProcess(ref mp_epr), Some(name)) => {
+        let env = mp_epr
+          .process
+          .0
+          .values()
+          .next()
+          .map(|process| process.env.clone())
+          .unwrap_or_default();
+        Some(context.core.redactor.redact_str(&env, &name).into_owned())
+      }
+      (_, name) => name,
+    };
     let workunit_name = self.workunit_name();
     let failure_name = match &self {
       NodeKey::Task(ref task) => {
@@ -1304,6 +2065,7 @@ impl Node for NodeKey {
       stderr: None,
       artifacts: Vec::new(),
       user_metadata: Vec::new(),
+      progress: None,
     };
     let metadata2 = metadata.clone();
 
@@ -1313,16 +2075,17 @@ impl Node for NodeKey {
       // executing the node logic. But in case of failure, we wait to see if the Node itself
       // fails, and prefer that error message if so (because we have little control over the
       // error messages of the watch API).
-      let maybe_watch = if let Some(path) = self.fs_subject() {
-        let abs_path = context.core.build_root.join(path);
-        context
-          .core
-          .watcher
-          .watch(abs_path)
-          .map_err(|e| Context::mk_error(&e))
-          .await
-      } else {
-        Ok(())
+      let maybe_watch = match (self.fs_subject(), &context.core.watcher) {
+        // NB: LocalBinary nodes install their own watch: see `LocalBinary`.
+        (Some(_), Some(_)) if matches!(self, NodeKey::LocalBinary(..)) => Ok(()),
+        (Some(path), Some(watcher)) => {
+          let abs_path = context.core.build_root.join(path);
+          watcher
+            .watch(abs_path)
+            .map_err(|e| Context::mk_error(&e))
+            .await
+        }
+        _ => Ok(()),
       };
 
       let mut level = metadata.level;
@@ -1334,9 +2097,17 @@ impl Node for NodeKey {
       let mut result = match self {
         NodeKey::DigestFile(n) => n.run_wrapped_node(context).map_ok(NodeOutput::Digest).await,
         NodeKey::DownloadedFile(n) => n.run_wrapped_node(context).map_ok(NodeOutput::Digest).await,
+        NodeKey::LocalBinary(n) => {
+          n.run_wrapped_node(context)
+            .map_ok(NodeOutput::LocalBinary)
+            .await
+        }
         NodeKey::MultiPlatformExecuteProcess(n) => {
           n.run_wrapped_node(context)
-            .map_ok(|r| NodeOutput::ProcessResult(Box::new(r)))
+            .map_ok(|r| {
+              user_metadata = r.user_metadata();
+              NodeOutput::ProcessResult(Box::new(r))
+            })
             .await
         }
         NodeKey::ReadLink(n) => {
@@ -1413,24 +2184,77 @@ impl Node for NodeKey {
   }
 
   fn cacheable(&self) -> bool {
-    match self {
-      &NodeKey::Task(ref s) => s.task.cacheable,
-      &NodeKey::SessionValues(_) => false,
-      _ => true,
-    }
+    self.sharing() == Sharing::AcrossSessions
   }
 
   fn cacheable_item(&self, output: &NodeOutput) -> bool {
+    self.sharing_of_item(output) == Sharing::AcrossSessions
+  }
+}
+
+///
+/// Whether the result of a Node may be shared by all Sessions, or must instead be recomputed in
+/// each Session which requests it.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Sharing {
+  AcrossSessions,
+  /// The result is only valid within the Session which computed it, for the given reason.
+  PerSession(&'static str),
+}
+
+impl NodeKey {
+  ///
+  /// The sharing of this Node's result, based solely on the type and properties of the Node.
+  ///
+  /// NB: Any Node which must be recomputed per-Session should be annotated here (with a reason),
+  /// rather than by special-casing it elsewhere in the engine.
+  ///
+  pub fn sharing(&self) -> Sharing {
     match self {
-      NodeKey::MultiPlatformExecuteProcess(ref mp) => match output {
-        NodeOutput::ProcessResult(ref process_result) => match mp.cache_scope {
-          ProcessCacheScope::Always | ProcessCacheScope::PerRestart => true,
-          ProcessCacheScope::Successful => process_result.0.exit_code == 0,
-          ProcessCacheScope::Never => false,
-        },
-        _ => true,
-      },
-      _ => true,
+      NodeKey::Task(ref s) if !s.task.cacheable => {
+        Sharing::PerSession("the @rule is marked uncacheable, usually because it has side effects")
+      }
+      NodeKey::SessionValues(_) => Sharing::PerSession("it reads values provided by each Session"),
+      NodeKey::LocalBinary(_) if cfg!(target_os = "macos") => Sharing::PerSession(
+        "directories outside of the build root are not watched on macOS, so the binary may change",
+      ),
+      NodeKey::Task(_)
+      | NodeKey::DigestFile(_)
+      | NodeKey::DownloadedFile(_)
+      | NodeKey::LocalBinary(_)
+      | NodeKey::MultiPlatformExecuteProcess(_)
+      | NodeKey::ReadLink(_)
+      | NodeKey::Scandir(_)
+      | NodeKey::Select(_)
+      | NodeKey::Snapshot(_)
+      | NodeKey::Paths(_) => Sharing::AcrossSessions,
+    }
+  }
+
+  ///
+  /// The sharing of the given result of this Node, which may be narrower than `sharing`.
+  ///
+  pub fn sharing_of_item(&self, output: &NodeOutput) -> Sharing {
+    match (self, output) {
+      (NodeKey::MultiPlatformExecuteProcess(_), NodeOutput::ProcessResult(ref result))
+        if result.0.metadata.dry_run =>
+      {
+        Sharing::PerSession("the process was not run, because the Session was a dry run")
+      }
+      (NodeKey::MultiPlatformExecuteProcess(ref mp), NodeOutput::ProcessResult(ref result)) => {
+        match mp.cache_scope {
+          ProcessCacheScope::Always | ProcessCacheScope::PerRestart => Sharing::AcrossSessions,
+          ProcessCacheScope::Successful if result.0.exit_code == 0 => Sharing::AcrossSessions,
+          ProcessCacheScope::Successful => {
+            Sharing::PerSession("the process failed, and only successful results may be reused")
+          }
+          ProcessCacheScope::Never => {
+            Sharing::PerSession("the process has a cache scope of `Never`")
+          }
+        }
+      }
+      _ => self.sharing(),
     }
   }
 }
@@ -1440,6 +2264,7 @@ impl Display for NodeKey {
     match self {
       &NodeKey::DigestFile(ref s) => write!(f, "DigestFile({})", s.0.path.display()),
       &NodeKey::DownloadedFile(ref s) => write!(f, "DownloadedFile({})", s.0),
+      &NodeKey::LocalBinary(ref s) => write!(f, "LocalBinary({})", s.path.display()),
       &NodeKey::MultiPlatformExecuteProcess(ref s) => {
         write!(f, "Process({})", s.process.user_facing_name())
       }
@@ -1483,6 +2308,7 @@ pub enum NodeOutput {
   Digest(hashing::Digest),
   DirectoryListing(Arc<DirectoryListing>),
   LinkDest(LinkDest),
+  LocalBinary(Option<DiscoveredBinary>),
   ProcessResult(Box<ProcessResult>),
   // Allow clippy::rc_buffer due to non-trivial issues that would arise in using the
   // suggested Arc<[PathStat]> type. See https://github.com/rust-lang/rust-clippy/issues/6170
@@ -1496,10 +2322,13 @@ impl NodeOutput {
     match self {
       NodeOutput::Digest(d) => vec![*d],
       NodeOutput::ProcessResult(p) => {
-        vec![p.0.stdout_digest, p.0.stderr_digest, p.0.output_directory]
+        let mut digests = vec![p.0.stdout_digest, p.0.stderr_digest, p.0.output_directory];
+        digests.extend(p.2.iter().map(|(_, digest)| *digest));
+        digests
       }
       NodeOutput::DirectoryListing(_)
       | NodeOutput::LinkDest(_)
+      | NodeOutput::LocalBinary(_)
       | NodeOutput::Paths(_)
       | NodeOutput::Value(_) => vec![],
     }
@@ -1578,6 +2407,17 @@ impl TryFrom<NodeOutput> for LinkDest {
   }
 }
 
+impl TryFrom<NodeOutput> for Option<DiscoveredBinary> {
+  type Error = ();
+
+  fn try_from(nr: NodeOutput) -> Result<Self, ()> {
+    match nr {
+      NodeOutput::LocalBinary(v) => Ok(v),
+      _ => Err(()),
+    }
+  }
+}
+
 impl TryFrom<NodeOutput> for Arc<DirectoryListing> {
   type Error = ();
 
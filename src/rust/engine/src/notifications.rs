@@ -0,0 +1,161 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::collections::HashSet;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use parking_lot::Mutex;
+use task_executor::Executor;
+use workunit_store::{format_workunit_duration, WorkunitStore};
+
+// The interval at which running workunits are checked against the notification threshold.
+const STRAGGLER_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+const NOTIFICATION_TITLE: &str = "Pants";
+
+///
+/// Options for the desktop notifications sent for a Session.
+///
+#[derive(Clone, Debug)]
+pub struct NotificationOptions {
+  // A command to run to send a notification, to which the title and message of the notification
+  // are appended as arguments. If None, a native notification is sent (where supported).
+  pub command: Option<Vec<String>>,
+  // The minimum duration of an execution or of a running workunit before a notification is sent.
+  pub threshold: Duration,
+}
+
+///
+/// Sends notifications when a long running execution completes, or when work within an execution
+/// has been running for longer than a threshold: useful when a user has switched away from their
+/// terminal during a long build.
+///
+/// Notifications are sent in the background, and failures to send them are only logged at debug
+/// level.
+///
+pub struct Notifier {
+  options: NotificationOptions,
+  // The descriptions of workunits which have already been notified for, in order to notify only
+  // once for each.
+  notified: Mutex<HashSet<String>>,
+  next_straggler_check: Mutex<Instant>,
+}
+
+impl Notifier {
+  pub fn new(options: NotificationOptions) -> Notifier {
+    Notifier {
+      options,
+      notified: Mutex::new(HashSet::new()),
+      next_straggler_check: Mutex::new(Instant::now()),
+    }
+  }
+
+  ///
+  /// Notifies for an execution which has completed, if it ran for longer than the threshold.
+  ///
+  pub fn maybe_notify_completed(&self, executor: &Executor, elapsed: Duration, succeeded: bool) {
+    if elapsed < self.options.threshold {
+      return;
+    }
+    let outcome = if succeeded { "succeeded" } else { "failed" };
+    self.notify(
+      executor,
+      format!(
+        "Run {} after {}",
+        outcome,
+        format_workunit_duration(elapsed).trim_end()
+      ),
+    );
+  }
+
+  ///
+  /// Notifies for any work which has been running for longer than the threshold (at most once for
+  /// each description of work). Checks are rate limited, so this may be called frequently.
+  ///
+  pub fn maybe_notify_stragglers(&self, executor: &Executor, workunit_store: &WorkunitStore) {
+    {
+      let mut next_straggler_check = self.next_straggler_check.lock();
+      let now = Instant::now();
+      if *next_straggler_check > now {
+        return;
+      }
+      *next_straggler_check = now + STRAGGLER_CHECK_INTERVAL;
+    }
+
+    let stragglers = workunit_store.straggling_workunits(self.options.threshold);
+    let mut notified = self.notified.lock();
+    for (duration, desc) in stragglers {
+      if notified.insert(desc.clone()) {
+        self.notify(
+          executor,
+          format!(
+            "Still running after {}: {}",
+            format_workunit_duration(duration).trim_end(),
+            desc
+          ),
+        );
+      }
+    }
+  }
+
+  fn notify(&self, executor: &Executor, message: String) {
+    let argv = match &self.options.command {
+      Some(command) if !command.is_empty() => {
+        let mut argv = command.clone();
+        argv.push(NOTIFICATION_TITLE.to_owned());
+        argv.push(message);
+        argv
+      }
+      _ => match native_notification_argv(&message) {
+        Some(argv) => argv,
+        None => {
+          debug!(
+            "Native notifications are not supported on this platform: {}",
+            message
+          );
+          return;
+        }
+      },
+    };
+
+    // NB: The spawned task is detached: we don't wait for notifications to be sent.
+    let _ = executor.spawn_blocking(move || {
+      let result = Command::new(&argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+      match result {
+        Ok(status) if status.success() => (),
+        Ok(status) => debug!("Notification command {:?} failed: {}", argv, status),
+        Err(e) => debug!("Failed to run notification command {:?}: {}", argv, e),
+      }
+    });
+  }
+}
+
+pub(crate) fn native_notification_argv(message: &str) -> Option<Vec<String>> {
+  if cfg!(target_os = "macos") {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    Some(vec![
+      "osascript".to_owned(),
+      "-e".to_owned(),
+      format!(
+        "display notification \"{}\" with title \"{}\"",
+        escape(message),
+        escape(NOTIFICATION_TITLE)
+      ),
+    ])
+  } else if cfg!(target_os = "linux") {
+    Some(vec![
+      "notify-send".to_owned(),
+      NOTIFICATION_TITLE.to_owned(),
+      message.to_owned(),
+    ])
+  } else {
+    None
+  }
+}
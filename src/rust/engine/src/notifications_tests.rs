@@ -0,0 +1,118 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tempfile::TempDir;
+use testutil::path::find_bash;
+use workunit_store::{with_workunit, WorkunitMetadata, WorkunitStore};
+
+use crate::notifications::{NotificationOptions, Notifier};
+
+///
+/// Creates a notification command which appends its arguments (one invocation per line) to the
+/// returned file.
+///
+fn notification_command(dir: &Path) -> (Vec<String>, PathBuf) {
+  let log = dir.join("notifications.log");
+  let command = dir.join("notify");
+  std::fs::write(
+    &command,
+    format!("#!{}\necho \"$*\" >> {}\n", find_bash(), log.display()),
+  )
+  .unwrap();
+  std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755)).unwrap();
+  (vec![command.display().to_string()], log)
+}
+
+fn notifier(dir: &Path, threshold: Duration) -> (Notifier, PathBuf) {
+  let (command, log) = notification_command(dir);
+  let notifier = Notifier::new(NotificationOptions {
+    command: Some(command),
+    threshold,
+  });
+  (notifier, log)
+}
+
+///
+/// Waits for the given number of notifications to have been sent, and returns them.
+///
+async fn notifications(log: &Path, count: usize) -> Vec<String> {
+  for _ in 0..100 {
+    let lines = std::fs::read_to_string(log)
+      .map(|content| content.lines().map(|l| l.to_owned()).collect::<Vec<_>>())
+      .unwrap_or_default();
+    if lines.len() >= count {
+      return lines;
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+  }
+  panic!("Timed out waiting for {} notifications.", count);
+}
+
+#[tokio::test]
+async fn notifies_for_completed_runs_above_the_threshold() {
+  let dir = TempDir::new().unwrap();
+  let executor = task_executor::Executor::new();
+  let (notifier, log) = notifier(dir.path(), Duration::from_secs(10));
+
+  notifier.maybe_notify_completed(&executor, Duration::from_secs(1), true);
+  notifier.maybe_notify_completed(&executor, Duration::from_secs(12), false);
+
+  assert_eq!(
+    notifications(&log, 1).await,
+    vec!["Pants Run failed after 12.00s".to_owned()]
+  );
+}
+
+#[tokio::test]
+async fn notifies_for_stragglers_once() {
+  let workunit_store = WorkunitStore::setup_for_tests();
+  let dir = TempDir::new().unwrap();
+  let executor = task_executor::Executor::new();
+  let (notifier, log) = notifier(dir.path(), Duration::from_secs(0));
+
+  with_workunit(
+    workunit_store.clone(),
+    "straggler".to_owned(),
+    WorkunitMetadata {
+      desc: Some("Running a straggler".to_owned()),
+      ..WorkunitMetadata::default()
+    },
+    async {
+      notifier.maybe_notify_stragglers(&executor, &workunit_store);
+      notifier.maybe_notify_stragglers(&executor, &workunit_store);
+    },
+    |_, metadata| metadata,
+  )
+  .await;
+
+  let notifications = notifications(&log, 1).await;
+  assert_eq!(notifications.len(), 1);
+  assert!(notifications[0].starts_with("Pants Still running after "));
+  assert!(notifications[0].ends_with(": Running a straggler"));
+}
+
+#[test]
+fn native_notifications() {
+  let argv = crate::notifications::native_notification_argv("Run \"succeeded\"");
+  if cfg!(target_os = "macos") {
+    assert_eq!(
+      argv.unwrap()[2],
+      "display notification \"Run \\\"succeeded\\\"\" with title \"Pants\""
+    );
+  } else if cfg!(target_os = "linux") {
+    assert_eq!(
+      argv.unwrap(),
+      vec![
+        "notify-send".to_owned(),
+        "Pants".to_owned(),
+        "Run \"succeeded\"".to_owned()
+      ]
+    );
+  } else {
+    assert_eq!(argv, None);
+  }
+}
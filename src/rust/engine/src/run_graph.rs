@@ -0,0 +1,215 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use graph::Node;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::context::Core;
+use crate::session::Session;
+
+///
+/// A lightweight record of the Nodes which were reachable from the roots of a run, which can be
+/// persisted, and later compared to the record of another run to explain why Nodes were dirtied
+/// between them.
+///
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunGraph {
+  /// The id of the Graph that the run used: the versions of Nodes are only comparable between runs
+  /// which used the same Graph.
+  graph_id: String,
+  /// The indexes of the roots of the run in `nodes`.
+  roots: Vec<usize>,
+  nodes: Vec<RunGraphNode>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RunGraphNode {
+  key: String,
+  /// The file or directory that the Node reads, if any.
+  path: Option<String>,
+  cacheable: bool,
+  run_token: u32,
+  generation: u32,
+  dependencies: Vec<usize>,
+}
+
+impl RunGraph {
+  pub fn capture(core: &Core, session: &Session) -> RunGraph {
+    let roots = session.roots_nodes();
+    let entries = core.graph.run_graph(&roots);
+    let root_keys = roots.into_iter().collect::<HashSet<_>>();
+    let roots = entries
+      .iter()
+      .enumerate()
+      .filter(|(_, entry)| root_keys.contains(&entry.node))
+      .map(|(index, _)| index)
+      .collect();
+    let nodes = entries
+      .into_iter()
+      .map(|entry| RunGraphNode {
+        key: entry.node.to_string(),
+        path: entry
+          .node
+          .fs_subject()
+          .map(|path| path.to_string_lossy().into_owned()),
+        cacheable: entry.node.cacheable(),
+        run_token: entry.run_token,
+        generation: entry.generation,
+        dependencies: entry.dependencies,
+      })
+      .collect();
+    RunGraph {
+      graph_id: core.graph_id.clone(),
+      roots,
+      nodes,
+    }
+  }
+
+  pub fn write(&self, path: &Path) -> Result<(), String> {
+    let content =
+      serde_json::to_vec(self).map_err(|e| format!("Failed to encode run graph: {}", e))?;
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent)
+        .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(path, content)
+      .map_err(|e| format!("Failed to write run graph to {}: {}", path.display(), e))
+  }
+
+  pub fn read(path: &Path) -> Result<RunGraph, String> {
+    let content = std::fs::read(path)
+      .map_err(|e| format!("Failed to read run graph from {}: {}", path.display(), e))?;
+    serde_json::from_slice(&content)
+      .map_err(|e| format!("Failed to decode run graph from {}: {}", path.display(), e))
+  }
+}
+
+///
+/// Why a Node changed between two runs, independent of its dependencies.
+///
+#[derive(Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RootCause {
+  /// The Graph was recreated between the runs (for example: because bootstrap options changed),
+  /// and so every Node ran from scratch.
+  GraphRestarted,
+  /// A file or directory that the Node reads changed.
+  ChangedPath { key: String, path: String },
+  /// The Node did not exist in the first run: for example, because it has different parameters,
+  /// such as changed options or arguments.
+  NewNode { key: String },
+  /// The Node is not cacheable between runs, and so always runs.
+  Uncacheable { key: String },
+  /// The Node changed without any of its dependencies having changed (for example: because it has
+  /// dependencies which are not recorded, or because it failed).
+  Unknown { key: String },
+}
+
+///
+/// The differences between two runs: see `explain_divergence`.
+///
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Divergence {
+  /// The Nodes of the second run which were dirtied (or new) since the first run, and so were
+  /// either re-run or checked for changes to their dependencies.
+  pub dirtied: Vec<String>,
+  /// The subset of the dirtied Nodes whose outputs changed.
+  pub changed: Vec<String>,
+  pub root_causes: Vec<RootCause>,
+  /// For each root of the second run which changed, a chain of changed Nodes from the root to
+  /// (the Node of) one of its root causes.
+  pub chains: Vec<Vec<String>>,
+}
+
+///
+/// Explains why the Nodes of the `after` run were dirtied relative to the `before` run, by
+/// finding the Nodes whose outputs changed without any of their dependencies having changed.
+///
+pub fn explain_divergence(before: &RunGraph, after: &RunGraph) -> Divergence {
+  let restarted = before.graph_id != after.graph_id;
+  let before_versions = before
+    .nodes
+    .iter()
+    .map(|node| (node.key.as_str(), (node.run_token, node.generation)))
+    .collect::<HashMap<_, _>>();
+
+  // If the Graph was restarted, the versions are not comparable, and every Node is treated as new.
+  let previous = |node: &RunGraphNode| {
+    if restarted {
+      None
+    } else {
+      before_versions.get(node.key.as_str()).cloned()
+    }
+  };
+  let dirtied = after
+    .nodes
+    .iter()
+    .map(|node| match previous(node) {
+      Some((run_token, _)) => run_token != node.run_token,
+      None => true,
+    })
+    .collect::<Vec<_>>();
+  let changed = after
+    .nodes
+    .iter()
+    .map(|node| match previous(node) {
+      Some((_, generation)) => generation != node.generation,
+      None => true,
+    })
+    .collect::<Vec<_>>();
+  let changed_dependency = |index: usize| {
+    after.nodes[index]
+      .dependencies
+      .iter()
+      .cloned()
+      .find(|&dep| changed[dep])
+  };
+
+  let mut divergence = Divergence::default();
+  for (index, node) in after.nodes.iter().enumerate() {
+    if dirtied[index] {
+      divergence.dirtied.push(node.key.clone());
+    }
+    if !changed[index] {
+      continue;
+    }
+    divergence.changed.push(node.key.clone());
+    if restarted || changed_dependency(index).is_some() {
+      continue;
+    }
+    let key = node.key.clone();
+    divergence.root_causes.push(if previous(node).is_none() {
+      RootCause::NewNode { key }
+    } else if let Some(ref path) = node.path {
+      RootCause::ChangedPath {
+        key,
+        path: path.clone(),
+      }
+    } else if !node.cacheable {
+      RootCause::Uncacheable { key }
+    } else {
+      RootCause::Unknown { key }
+    });
+  }
+  if restarted {
+    divergence.root_causes.push(RootCause::GraphRestarted);
+  }
+
+  for &root in &after.roots {
+    if !changed[root] {
+      continue;
+    }
+    let mut chain = vec![after.nodes[root].key.clone()];
+    let mut current = root;
+    // NB: Dependencies are acyclic, so this terminates.
+    while let Some(dep) = changed_dependency(current) {
+      chain.push(after.nodes[dep].key.clone());
+      current = dep;
+    }
+    divergence.chains.push(chain);
+  }
+  divergence
+}
@@ -11,16 +11,17 @@ use std::time::{Duration, Instant};
 
 use crate::context::{Context, Core};
 use crate::core::{Failure, Params, TypeId, Value};
-use crate::nodes::{Select, Visualizer};
+use crate::nodes::{Select, Sharing, Visualizer};
 use crate::session::{ObservedValueResult, Root, Session};
 
 use futures::{future, FutureExt, TryFutureExt};
-use graph::LastObserved;
+use graph::{LastObserved, NodeState};
 use hashing::{Digest, EMPTY_DIGEST};
 use log::{debug, warn};
 use stdio::TryCloneAsFile;
 use tempfile::TempDir;
 use tokio::process;
+use tokio::sync::watch;
 use tokio::time;
 use ui::ConsoleUI;
 use watch::Invalidatable;
@@ -63,12 +64,14 @@ pub struct ExecutionRequest {
 ///
 pub struct Scheduler {
   pub core: Arc<Core>,
+  interactive_executions: Arc<InteractiveExecutions>,
 }
 
 impl Scheduler {
   pub fn new(core: Core) -> Scheduler {
     Scheduler {
       core: Arc::new(core),
+      interactive_executions: Arc::new(InteractiveExecutions::new()),
     }
   }
 
@@ -135,9 +138,34 @@ impl Scheduler {
       session.preceding_graph_size() as i64,
     );
     m.insert("resulting_graph_size", self.core.graph.len() as i64);
+    let streaming_queue_stats = session.workunit_store().streaming_queue_stats();
+    m.insert(
+      "streaming_workunits_dropped",
+      streaming_queue_stats.dropped as i64,
+    );
+    m.insert(
+      "streaming_workunits_spilled",
+      streaming_queue_stats.spilled as i64,
+    );
     m
   }
 
+  ///
+  /// Returns the number of Nodes in the Graph, by their type (see `NodeKey::node_type`) and state.
+  ///
+  pub fn live_node_counts(&self, session: &Session) -> Vec<(String, NodeState, usize)> {
+    let context = Context::new(self.core.clone(), session.clone());
+    let mut counts = self
+      .core
+      .graph
+      .node_counts(&context, |node| node.node_type())
+      .into_iter()
+      .map(|((node_type, state), count)| (node_type, state, count))
+      .collect::<Vec<_>>();
+    counts.sort();
+    counts
+  }
+
   ///
   /// Return unit if the Scheduler is still valid, or an error string if something has invalidated
   /// the Scheduler, indicating that it should re-initialize. See InvalidationWatcher.
@@ -145,8 +173,11 @@ impl Scheduler {
   pub fn is_valid(&self) -> Result<(), String> {
     let core = self.core.clone();
     self.core.executor.block_on(async move {
-      // Confirm that our InvalidationWatcher is still alive.
-      core.watcher.is_valid().await
+      // Confirm that our InvalidationWatcher (if any) is still alive.
+      match core.watcher {
+        Some(ref watcher) => watcher.is_valid().await,
+        None => Ok(()),
+      }
     })
   }
 
@@ -163,6 +194,22 @@ impl Scheduler {
     digests
   }
 
+  ///
+  /// Return the Nodes currently in memory in this Scheduler whose results may only be used by the
+  /// given Session, along with the reason that they may not be shared with other Sessions.
+  ///
+  pub fn per_session_nodes(&self, session: &Session) -> Vec<(String, &'static str)> {
+    let context = Context::new(self.core.clone(), session.clone());
+    let mut nodes = Vec::new();
+    self.core.graph.visit_live(&context, |n, v| {
+      if let Sharing::PerSession(reason) = n.sharing_of_item(&v) {
+        nodes.push((n.to_string(), reason));
+      }
+    });
+    nodes.sort();
+    nodes
+  }
+
   pub async fn run_local_interactive_process(
     &self,
     session: &Session,
@@ -188,11 +235,14 @@ impl Scheduler {
           None => unreachable!(),
         };
 
-        self
-          .core
-          .store()
-          .materialize_directory(destination, input_digest)
-          .await?;
+        store::with_request_priority(
+          store::RequestPriority::Interactive,
+          self
+            .core
+            .store()
+            .materialize_directory(destination, input_digest),
+        )
+        .await?;
       }
     }
 
@@ -358,10 +408,14 @@ impl Scheduler {
       request.poll
     );
 
+    // Preempt any warm-up work for the duration of this execution.
+    let _interactive_execution = self.interactive_executions.enter();
+
     let interval = ConsoleUI::render_interval();
     let deadline = request.timeout.map(|timeout| Instant::now() + timeout);
 
     // Spawn and wait for all roots to complete.
+    let start = Instant::now();
     session.maybe_display_initialize(&self.core.executor);
     let mut execution_task = self.execute_helper(request, session).boxed();
 
@@ -382,6 +436,7 @@ impl Scheduler {
             } else {
               // Just a receive timeout. render and continue.
               session.maybe_display_render();
+              session.maybe_notify_stragglers();
             }
             refresh_delay = time::sleep(Self::refresh_delay(interval, deadline)).boxed();
           }
@@ -392,10 +447,64 @@ impl Scheduler {
         }
       };
       session.maybe_display_teardown().await;
+      if let Ok(results) = &result {
+        // NB: Interrupted and timed out executions are not notified for: in the former case the
+        // user is present, and in the latter the caller will poll again.
+        session.maybe_notify_completed(start.elapsed(), results.iter().all(|r| r.is_ok()));
+      }
       result
     })
   }
 
+  ///
+  /// Computes the roots in the given request in the background, but only while no interactive
+  /// executions are running.
+  ///
+  /// Warm-up work is preempted as soon as an interactive execution begins: whichever Nodes had
+  /// already completed remain in the Graph (and so are available to the interactive execution),
+  /// and the remainder are retried once the Scheduler is idle again. The results of warm-up roots
+  /// are not recorded in the Session: they should be requested via `execute` as usual.
+  ///
+  pub fn warm_up(&self, request: &ExecutionRequest, session: &Session) {
+    let context = Context::new(self.core.clone(), session.clone());
+    let roots = request.roots.clone();
+    let interactive_executions = self.interactive_executions.clone();
+    let session = session.clone();
+    debug!("Scheduling {} warm-up roots.", roots.len());
+
+    let _join = self.core.executor.spawn(async move {
+      loop {
+        tokio::select! {
+          _ = session.cancelled() => return,
+          _ = interactive_executions.idle() => {}
+        }
+
+        let warm_up = future::join_all(
+          roots
+            .iter()
+            .map(|root| context.core.graph.create(root.clone().into(), &context))
+            .collect::<Vec<_>>(),
+        );
+        tokio::select! {
+          _ = session.cancelled() => {
+            debug!("Warm-up of {} roots was cancelled.", roots.len());
+            return;
+          }
+          _ = interactive_executions.busy() => {
+            debug!(
+              "Preempting warm-up of {} roots for an interactive execution.",
+              roots.len()
+            );
+          }
+          _ = warm_up => {
+            debug!("Completed warm-up of {} roots.", roots.len());
+            return;
+          }
+        }
+      }
+    });
+  }
+
   fn refresh_delay(refresh_interval: Duration, deadline: Option<Instant>) -> Duration {
     deadline
       .and_then(|deadline| deadline.checked_duration_since(Instant::now()))
@@ -404,6 +513,68 @@ impl Scheduler {
   }
 }
 
+///
+/// Tracks the number of interactive executions which are running, in order to run warm-up work
+/// only while there are none.
+///
+struct InteractiveExecutions {
+  sender: watch::Sender<usize>,
+  // NB: Held so that the channel remains open even while there are no warm-ups waiting on it.
+  receiver: watch::Receiver<usize>,
+  count: parking_lot::Mutex<usize>,
+}
+
+impl InteractiveExecutions {
+  fn new() -> InteractiveExecutions {
+    let (sender, receiver) = watch::channel(0);
+    InteractiveExecutions {
+      sender,
+      receiver,
+      count: parking_lot::Mutex::new(0),
+    }
+  }
+
+  fn enter(&self) -> InteractiveExecution<'_> {
+    self.update(|count| count + 1);
+    InteractiveExecution(self)
+  }
+
+  fn update(&self, f: impl FnOnce(usize) -> usize) {
+    let mut count = self.count.lock();
+    *count = f(*count);
+    let _ = self.sender.send(*count);
+  }
+
+  /// Waits until the predicate holds for the current number of interactive executions.
+  async fn wait_for(&self, predicate: impl Fn(usize) -> bool) {
+    let mut receiver = self.receiver.clone();
+    loop {
+      if predicate(*receiver.borrow()) {
+        return;
+      }
+      if receiver.changed().await.is_err() {
+        return;
+      }
+    }
+  }
+
+  async fn idle(&self) {
+    self.wait_for(|count| count == 0).await
+  }
+
+  async fn busy(&self) {
+    self.wait_for(|count| count > 0).await
+  }
+}
+
+struct InteractiveExecution<'a>(&'a InteractiveExecutions);
+
+impl<'a> Drop for InteractiveExecution<'a> {
+  fn drop(&mut self) {
+    self.0.update(|count| count - 1);
+  }
+}
+
 impl Drop for Scheduler {
   fn drop(&mut self) {
     // Because Nodes may hold references to the Core in their closure, this is intended to
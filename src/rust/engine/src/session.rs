@@ -3,21 +3,29 @@
 
 use std::collections::HashMap;
 use std::future::Future;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, Weak};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::context::Core;
 use crate::core::{Failure, Value};
 use crate::nodes::{NodeKey, Select};
+use crate::notifications::{NotificationOptions, Notifier};
 use crate::scheduler::Scheduler;
 
 use async_latch::AsyncLatch;
 use futures::future::{AbortHandle, Abortable};
 use futures::FutureExt;
 use graph::LastObserved;
+use hashing::Digest;
 use log::warn;
 use parking_lot::{Mutex, RwLock};
+use process_execution::SessionPriority;
 use task_executor::Executor;
+use tempfile::TempDir;
 use tokio::signal::unix::{signal, SignalKind};
 use ui::ConsoleUI;
 use uuid::Uuid;
@@ -81,6 +89,9 @@ struct SessionState {
   workunit_store: WorkunitStore,
   // The unique id for this Session: used for metrics gathering purposes.
   build_id: String,
+  // A ULID generated for this Session, which is exposed to processes, workunits and remote
+  // servers so that their artifacts, logs and traces can be correlated.
+  invocation_id: String,
   // Per-Session values that have been set for this session.
   session_values: Mutex<Value>,
   // An id used to control the visibility of uncacheable rules. Generally this is identical for an
@@ -92,6 +103,37 @@ struct SessionState {
   // Session/build_id would be stable.
   run_id: Mutex<Uuid>,
   workunit_metadata_map: RwLock<HashMap<UserMetadataPyValue, Value>>,
+  // If notifications are enabled for this Session, the Notifier which sends them.
+  notifier: Option<Notifier>,
+  // If this Session is a dry run, the Processes which it would have executed.
+  planned_processes: Option<Mutex<Vec<PlannedProcess>>>,
+  // The priority that the client declared for this Session.
+  priority: SessionPriority,
+  // The total size of the outputs of processes captured during this Session: see
+  // `process_execution::quota`.
+  captured_output_bytes: Arc<AtomicUsize>,
+  // Temporary files containing store blobs, which are deleted when the Session ends: see
+  // `Session::blob_file`.
+  blob_files: Mutex<BlobFiles>,
+}
+
+#[derive(Default)]
+struct BlobFiles {
+  // Lazily created on the first request for a blob file.
+  dir: Option<TempDir>,
+  paths: HashMap<Digest, PathBuf>,
+}
+
+///
+/// A Process which a dry-run Session would have executed.
+///
+#[derive(Clone, Debug)]
+pub struct PlannedProcess {
+  pub description: String,
+  pub action_digest: Digest,
+  pub input_digest: Digest,
+  // The total size of the unique files in the input digest.
+  pub input_bytes: usize,
 }
 
 ///
@@ -138,6 +180,9 @@ impl Session {
     build_id: String,
     session_values: Value,
     cancelled: AsyncLatch,
+    notification_options: Option<NotificationOptions>,
+    dry_run: bool,
+    priority: SessionPriority,
   ) -> Session {
     let workunit_store = WorkunitStore::new(!should_render_ui);
     let display = Mutex::new(SessionDisplay::new(
@@ -148,6 +193,8 @@ impl Session {
 
     let handle = Arc::new(SessionHandle { cancelled, display });
     scheduler.core.sessions.add(&handle);
+    // Small blobs created during the Session are staged in memory until it ends: see `Drop`.
+    scheduler.core.store().begin_staging();
     Session {
       handle,
       state: Arc::new(SessionState {
@@ -156,9 +203,19 @@ impl Session {
         roots: Mutex::new(HashMap::new()),
         workunit_store,
         build_id,
+        invocation_id: ulid(),
         session_values: Mutex::new(session_values),
         run_id: Mutex::new(Uuid::new_v4()),
         workunit_metadata_map: RwLock::new(HashMap::new()),
+        notifier: notification_options.map(Notifier::new),
+        planned_processes: if dry_run {
+          Some(Mutex::new(Vec::new()))
+        } else {
+          None
+        },
+        priority,
+        captured_output_bytes: Arc::default(),
+        blob_files: Mutex::default(),
       }),
     }
   }
@@ -249,11 +306,123 @@ impl Session {
     &self.state.build_id
   }
 
+  pub fn invocation_id(&self) -> &str {
+    &self.state.invocation_id
+  }
+
   pub fn run_id(&self) -> Uuid {
     let run_id = self.state.run_id.lock();
     *run_id
   }
 
+  ///
+  /// Whether this Session is a dry run, in which Processes are recorded (see `planned_processes`)
+  /// rather than executed.
+  ///
+  pub fn is_dry_run(&self) -> bool {
+    self.state.planned_processes.is_some()
+  }
+
+  ///
+  /// The priority that the client declared for this Session, which is used to order the processes
+  /// of concurrent Sessions.
+  ///
+  pub fn priority(&self) -> SessionPriority {
+    self.state.priority
+  }
+
+  pub fn captured_output_bytes(&self) -> Arc<AtomicUsize> {
+    self.state.captured_output_bytes.clone()
+  }
+
+  ///
+  /// Returns the path of a read-only temporary file containing the given file blob, which remains
+  /// valid until this Session ends. The file is materialized on the first request for it, so that
+  /// large blobs can be memory-mapped by Python rather than being copied into Python objects.
+  ///
+  pub async fn blob_file(&self, digest: Digest) -> Result<PathBuf, String> {
+    let path = {
+      let mut blob_files = self.state.blob_files.lock();
+      if let Some(path) = blob_files.paths.get(&digest) {
+        return Ok(path.clone());
+      }
+      if blob_files.dir.is_none() {
+        let dir = tempfile::Builder::new()
+          .prefix("pants-blobs-")
+          .tempdir()
+          .map_err(|e| format!("Failed to create a directory for blob files: {}", e))?;
+        blob_files.dir = Some(dir);
+      }
+      blob_files
+        .dir
+        .as_ref()
+        .unwrap()
+        .path()
+        .join(format!("{}-{}", digest.hash, digest.size_bytes))
+    };
+
+    // Write to a temporary file and then rename it into place, so that concurrent requests for the
+    // same blob never observe a partial file.
+    let destination = path.clone();
+    let written = self
+      .state
+      .core
+      .store()
+      .load_file_bytes_with(digest, move |bytes| {
+        let dir = destination.parent().unwrap();
+        let mut tmp = tempfile::NamedTempFile::new_in(dir).map_err(|e| e.to_string())?;
+        tmp.write_all(bytes).map_err(|e| e.to_string())?;
+        tmp
+          .as_file()
+          .set_permissions(std::fs::Permissions::from_mode(0o444))
+          .map_err(|e| e.to_string())?;
+        tmp
+          .persist(&destination)
+          .map(|_| ())
+          .map_err(|e| e.to_string())
+      })
+      .await?;
+    match written {
+      Some((Ok(()), _)) => (),
+      Some((Err(e), _)) => {
+        return Err(format!("Failed to write blob file for {:?}: {}", digest, e))
+      }
+      None => {
+        return Err(format!(
+          "Blob for {:?} was not present in the store.",
+          digest
+        ))
+      }
+    }
+
+    self
+      .state
+      .blob_files
+      .lock()
+      .paths
+      .insert(digest, path.clone());
+    Ok(path)
+  }
+
+  pub fn record_planned_process(&self, planned_process: PlannedProcess) {
+    if let Some(planned_processes) = &self.state.planned_processes {
+      planned_processes.lock().push(planned_process);
+    }
+  }
+
+  ///
+  /// The Processes which this (dry-run) Session would have executed, in the order in which they
+  /// were requested.
+  ///
+  pub fn planned_processes(&self) -> Vec<PlannedProcess> {
+    self
+      .state
+      .planned_processes
+      .as_ref()
+      .map(|planned_processes| planned_processes.lock().clone())
+      .unwrap_or_default()
+  }
+
   pub fn new_run_id(&self) {
     let mut run_id = self.state.run_id.lock();
     *run_id = Uuid::new_v4();
@@ -298,6 +467,25 @@ impl Session {
     }
   }
 
+  ///
+  /// If notifications are enabled, notifies for any work which has been running for longer than
+  /// the notification threshold.
+  ///
+  pub fn maybe_notify_stragglers(&self) {
+    if let Some(notifier) = &self.state.notifier {
+      notifier.maybe_notify_stragglers(&self.state.core.executor, &self.state.workunit_store);
+    }
+  }
+
+  ///
+  /// If notifications are enabled, notifies that an execution on this Session has completed.
+  ///
+  pub fn maybe_notify_completed(&self, elapsed: Duration, succeeded: bool) {
+    if let Some(notifier) = &self.state.notifier {
+      notifier.maybe_notify_completed(&self.state.core.executor, elapsed, succeeded);
+    }
+  }
+
   pub fn maybe_display_render(&self) {
     match *self.handle.display.lock() {
       SessionDisplay::ConsoleUI(ref mut ui) => ui.render(),
@@ -375,3 +563,40 @@ impl Drop for Sessions {
     self.signal_task_abort_handle.abort();
   }
 }
+
+impl Drop for SessionState {
+  fn drop(&mut self) {
+    // Commit blobs which were staged during the Session, since memoized values may continue to
+    // reference them in later Sessions.
+    let store = self.core.store();
+    self
+      .core
+      .tail_tasks
+      .spawn_on("commit staged blobs", &self.core.executor, async move {
+        if let Err(e) = store.end_staging().await {
+          warn!("Failed to commit staged blobs to the local store: {}", e);
+        }
+      });
+    // Sandboxes which were preserved during the Session are pruned once it has completed.
+    self.core.prune_preserved_sandboxes();
+  }
+}
+
+///
+/// Generates a ULID (see https://github.com/ulid/spec): a 48 bit timestamp in milliseconds
+/// followed by 80 random bits, encoded in Crockford's base32 so that IDs sort by creation time.
+///
+fn ulid() -> String {
+  const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+  let millis = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis())
+    .unwrap_or(0)
+    & ((1 << 48) - 1);
+  let random = rand::random::<u128>() & ((1 << 80) - 1);
+  let value = (millis << 80) | random;
+  (0..26)
+    .rev()
+    .map(|i| ALPHABET[((value >> (i * 5)) & 0x1f) as usize] as char)
+    .collect()
+}
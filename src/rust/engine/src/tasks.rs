@@ -2,6 +2,7 @@
 // Licensed under the Apache License, Version 2.0 (see LICENSE).
 
 use std::fmt;
+use std::time::Duration;
 
 use crate::core::{Function, TypeId};
 use crate::intrinsics::Intrinsics;
@@ -151,6 +152,9 @@ pub struct Task {
   pub func: Function,
   pub cacheable: bool,
   pub display_info: DisplayInfo,
+  // A wall-clock timeout for the execution of the rule's function, excluding the computation of
+  // its selected inputs.
+  pub timeout: Option<Duration>,
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -222,6 +226,7 @@ impl Tasks {
     name: String,
     desc: Option<String>,
     level: Level,
+    timeout: Option<Duration>,
   ) {
     assert!(
       self.preparing.is_none(),
@@ -236,6 +241,7 @@ impl Tasks {
       gets: Vec::new(),
       func,
       display_info: DisplayInfo { name, desc, level },
+      timeout,
     });
   }
 
@@ -16,7 +16,15 @@ pub struct Types {
   pub remove_prefix: TypeId,
   pub create_digest: TypeId,
   pub digest_subset: TypeId,
+  pub parse_depfiles: TypeId,
+  pub merge_coverage_data: TypeId,
+  pub merge_test_reports: TypeId,
+  pub rewrite_digest: TypeId,
   pub download_file: TypeId,
+  pub blob_file: TypeId,
+  pub local_binary_path_request: TypeId,
+  pub binary_path: TypeId,
+  pub binary_paths: TypeId,
   pub platform: TypeId,
   pub multi_platform_process: TypeId,
   pub process_result: TypeId,
@@ -123,7 +123,12 @@ impl Drop for Console {
 enum InnerDestination {
   Logging,
   Console(Console),
-  Exclusive { stderr_handler: StdioHandler },
+  // NB: The stdout of the Console is kept (but not owned) while access is Exclusive, so that the
+  // terminal that it is attached to can still be queried: see `terminal_as_raw_fd`.
+  Exclusive {
+    stderr_handler: StdioHandler,
+    stdout_fd: RawFd,
+  },
 }
 
 impl fmt::Debug for InnerDestination {
@@ -131,9 +136,10 @@ impl fmt::Debug for InnerDestination {
     match self {
       Self::Logging => f.debug_struct("Logging").finish(),
       Self::Console(c) => f.debug_struct("Console").field("console", c).finish(),
-      Self::Exclusive { .. } => f
+      Self::Exclusive { stdout_fd, .. } => f
         .debug_struct("Exclusive")
         .field("stderr_handler", &"<elided>")
+        .field("stdout_fd", stdout_fd)
         .finish(),
     }
   }
@@ -168,15 +174,21 @@ impl Destination {
     String,
   > {
     let mut destination = self.0.lock();
-    if !matches!(*destination, InnerDestination::Console(..)) {
-      return Err(format!(
-        "Cannot start Exclusive access on Destination {:?}",
-        destination
-      ));
-    }
+    let stdout_fd = match &*destination {
+      InnerDestination::Console(console) => console.stdout_as_raw_fd(),
+      _ => {
+        return Err(format!(
+          "Cannot start Exclusive access on Destination {:?}",
+          destination
+        ))
+      }
+    };
     let console = std::mem::replace(
       &mut *destination,
-      InnerDestination::Exclusive { stderr_handler },
+      InnerDestination::Exclusive {
+        stderr_handler,
+        stdout_fd,
+      },
     );
     match console {
       InnerDestination::Console(console) => Ok(term::TermDestination::new(console, self.clone())),
@@ -263,10 +275,10 @@ impl Destination {
       InnerDestination::Console(ref mut console) => {
         console.write_stderr(content).map_err(|e| e.to_string())
       }
-      InnerDestination::Exclusive { ref stderr_handler } => {
-        stderr_handler(&String::from_utf8_lossy(content))
-          .map_err(|()| "Exclusive handler failed.".to_owned())
-      }
+      InnerDestination::Exclusive {
+        ref stderr_handler, ..
+      } => stderr_handler(&String::from_utf8_lossy(content))
+        .map_err(|()| "Exclusive handler failed.".to_owned()),
       InnerDestination::Logging => {
         Err("There is no 'real' stdio destination available.".to_owned())
       }
@@ -289,7 +301,9 @@ impl Destination {
         // If writing to the stdout handle fails, fall through to mutate self to drop it.
         res.map_err(|e| e.to_string())
       }
-      InnerDestination::Exclusive { ref stderr_handler } => {
+      InnerDestination::Exclusive {
+        ref stderr_handler, ..
+      } => {
         // Write to the Exclusive handler.
         let res = stderr_handler(&String::from_utf8_lossy(content));
         if res.is_ok() {
@@ -351,6 +365,21 @@ impl Destination {
     }
   }
 
+  ///
+  /// Like `stdout_as_raw_fd`, but also returns the stdout of the Console while a UI or process has
+  /// exclusive access to it. The result may only be used to query the terminal that stdout is
+  /// attached to (for example, for its size), and never to read or write it.
+  ///
+  pub fn terminal_as_raw_fd(&self) -> Result<RawFd, String> {
+    match &*self.0.lock() {
+      InnerDestination::Console(console) => Ok(console.stdout_as_raw_fd()),
+      InnerDestination::Exclusive { stdout_fd, .. } => Ok(*stdout_fd),
+      InnerDestination::Logging => {
+        Err("No associated file descriptor for the Logging destination".to_owned())
+      }
+    }
+  }
+
   ///
   /// If stdout is backed by a real file, returns it as a RawFd. All usage of `RawFd` is unsafe,
   /// but this method is additionally unsafe because the real file might have been closed by the
@@ -0,0 +1,108 @@
+///
+/// The IO scheduling priority with which blocking work runs: see
+/// `Executor::spawn_blocking_with_io_priority`.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IoPriority {
+  /// The IO priority of the process.
+  Default,
+  /// The idle IO scheduling class (`IOPRIO_CLASS_IDLE`), which only gets disk time when no other
+  /// process has asked for it for a while. This is appropriate for maintenance work (such as
+  /// garbage collection) which should never compete with a build for disk bandwidth.
+  ///
+  /// Only supported on Linux: elsewhere, this is equivalent to `Default`.
+  Idle,
+}
+
+impl Default for IoPriority {
+  fn default() -> Self {
+    IoPriority::Default
+  }
+}
+
+///
+/// Runs the given function with the IO priority of the calling thread set to the given priority,
+/// and then restores the previous priority (since the threads of the blocking pool are reused).
+///
+/// Failing to set the priority (because it is not supported, for example) is not an error.
+///
+pub(crate) fn with_io_priority<F: FnOnce() -> R, R>(io_priority: IoPriority, f: F) -> R {
+  match io_priority {
+    IoPriority::Default => f(),
+    IoPriority::Idle => {
+      let previous = sys::set_idle();
+      let result = f();
+      if let Some(previous) = previous {
+        sys::restore(previous);
+      }
+      result
+    }
+  }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) mod sys {
+  use log::debug;
+
+  const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+  const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+  pub(crate) const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+  ///
+  /// The raw IO priority of the calling thread, as described in `ioprio_get(2)`.
+  ///
+  pub(crate) fn get() -> Option<libc::c_int> {
+    // NB: A `who` of 0 refers to the calling thread.
+    let res = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+    if res < 0 {
+      debug!(
+        "Failed to get the IO priority of the current thread: {}",
+        std::io::Error::last_os_error()
+      );
+      return None;
+    }
+    Some(res as libc::c_int)
+  }
+
+  fn set(io_priority: libc::c_int) -> bool {
+    let res = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, io_priority) };
+    if res < 0 {
+      debug!(
+        "Failed to set the IO priority of the current thread to {}: {}",
+        io_priority,
+        std::io::Error::last_os_error()
+      );
+    }
+    res >= 0
+  }
+
+  ///
+  /// Moves the calling thread into the idle class, and returns its previous priority if that
+  /// succeeded.
+  ///
+  pub(crate) fn set_idle() -> Option<libc::c_int> {
+    let previous = get()?;
+    if set(IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT) {
+      Some(previous)
+    } else {
+      None
+    }
+  }
+
+  pub(crate) fn restore(previous: libc::c_int) {
+    set(previous);
+  }
+
+  pub(crate) fn class_of(io_priority: libc::c_int) -> libc::c_int {
+    io_priority >> IOPRIO_CLASS_SHIFT
+  }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) mod sys {
+  pub(crate) fn set_idle() -> Option<i32> {
+    None
+  }
+
+  pub(crate) fn restore(_previous: i32) {}
+}
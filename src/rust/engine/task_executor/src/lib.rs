@@ -35,14 +35,20 @@ use futures::future::FutureExt;
 use lazy_static::lazy_static;
 use tokio::runtime::{Builder, Handle, Runtime};
 
+pub use crate::io_priority::IoPriority;
+pub use crate::tail_tasks::TailTasks;
+
+mod io_priority;
+mod tail_tasks;
+
 lazy_static! {
-    // Lazily initialized in Executor::global.
-    static ref GLOBAL_EXECUTOR: ArcSwapOption<Runtime> = ArcSwapOption::from_pointee(None);
+    // Lazily initialized in Executor::global, along with its maximum number of threads.
+    static ref GLOBAL_EXECUTOR: ArcSwapOption<(Runtime, usize)> = ArcSwapOption::from_pointee(None);
 }
 
 #[derive(Debug, Clone)]
 pub struct Executor {
-  runtime: Option<Arc<Runtime>>,
+  runtime: Option<Arc<(Runtime, usize)>>,
   handle: Handle,
 }
 
@@ -76,7 +82,7 @@ impl Executor {
     if let Some(ref runtime) = *global {
       return Ok(Executor {
         runtime: Some(runtime.clone()),
-        handle: runtime.handle().clone(),
+        handle: runtime.0.handle().clone(),
       });
     }
 
@@ -88,10 +94,18 @@ impl Executor {
       .map_err(|e| format!("Failed to start the runtime: {}", e))?;
 
     // Attempt to swap, then recurse to retry.
-    GLOBAL_EXECUTOR.compare_and_swap(global, Some(Arc::new(runtime)));
+    GLOBAL_EXECUTOR.compare_and_swap(global, Some(Arc::new((runtime, max_threads))));
     Self::global(num_worker_threads, max_threads)
   }
 
+  ///
+  /// The maximum number of threads (including blocking threads) that this Executor will use, if it
+  /// was configured: see `Executor::global`. Executors for existing Runtimes are unbounded.
+  ///
+  pub fn max_threads(&self) -> Option<usize> {
+    self.runtime.as_ref().map(|runtime| runtime.1)
+  }
+
   ///
   /// Enter the runtime context associated with this Executor. This should be used in situations
   /// where threads not started by the runtime need access to it via task-local variables.
@@ -168,6 +182,19 @@ impl Executor {
       .map(|r| r.expect("Background task exited unsafely."))
   }
 
+  ///
+  /// As `spawn_blocking`, but runs the given function with the given IO priority. This should be
+  /// used for background maintenance work which would otherwise compete with foreground work for
+  /// disk bandwidth.
+  ///
+  pub fn spawn_blocking_with_io_priority<F: FnOnce() -> R + Send + 'static, R: Send + 'static>(
+    &self,
+    io_priority: IoPriority,
+    f: F,
+  ) -> impl Future<Output = R> {
+    self.spawn_blocking(move || io_priority::with_io_priority(io_priority, f))
+  }
+
   ///
   /// Copy our (thread-local or task-local) stdio destination and current workunit parent into
   /// the task. The former ensures that when a pantsd thread kicks off a future, any stdio done
@@ -187,3 +214,6 @@ impl Executor {
     })
   }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use crate::Executor;
+
+/// How often the progress of `TailTasks::wait` is logged.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+///
+/// Background tasks which should complete before the process exits, such as writes to caches
+/// which were deferred in order not to delay the runs which triggered them: see `TailTasks::wait`.
+///
+#[derive(Clone, Default)]
+pub struct TailTasks {
+  inner: Arc<TailTasksInner>,
+}
+
+#[derive(Default)]
+struct TailTasksInner {
+  state: Mutex<TailTasksState>,
+  completed: Notify,
+}
+
+#[derive(Default)]
+struct TailTasksState {
+  next_id: u64,
+  // The names of the tasks which have not completed, by id.
+  pending: BTreeMap<u64, String>,
+  completed: usize,
+}
+
+impl TailTasks {
+  pub fn new() -> TailTasks {
+    TailTasks::default()
+  }
+
+  ///
+  /// Spawns the given Future on the given Executor as a task with the given name, which `wait`
+  /// will wait for.
+  ///
+  pub fn spawn_on<F>(&self, name: &str, executor: &Executor, future: F)
+  where
+    F: Future<Output = ()> + Send + 'static,
+  {
+    let id = {
+      let mut state = self.inner.state.lock();
+      let id = state.next_id;
+      state.next_id += 1;
+      state.pending.insert(id, name.to_owned());
+      id
+    };
+    // NB: The guard marks the task completed even if it panics.
+    let guard = TailTaskGuard {
+      inner: self.inner.clone(),
+      id,
+    };
+    let _join = executor.spawn(async move {
+      future.await;
+      std::mem::drop(guard);
+    });
+  }
+
+  ///
+  /// The names of the tasks which have not yet completed.
+  ///
+  pub fn pending(&self) -> Vec<String> {
+    self.inner.state.lock().pending.values().cloned().collect()
+  }
+
+  ///
+  /// The number of tasks which have completed.
+  ///
+  pub fn completed(&self) -> usize {
+    self.inner.state.lock().completed
+  }
+
+  ///
+  /// Waits up to the given timeout for all tasks (including any which are spawned while waiting)
+  /// to complete, periodically logging which are still running. Returns the names of the tasks
+  /// which did not complete in time, which continue to run in the background.
+  ///
+  pub async fn wait(&self, timeout: Duration) -> Vec<String> {
+    let deadline = Instant::now() + timeout;
+    let mut next_report = Instant::now() + PROGRESS_INTERVAL;
+    loop {
+      let pending = self.pending();
+      let now = Instant::now();
+      if pending.is_empty() || now >= deadline {
+        return pending;
+      }
+      if now >= next_report {
+        log::info!(
+          "Waiting for {} background tasks to complete ({} completed so far): {}",
+          pending.len(),
+          self.completed(),
+          summarize(&pending)
+        );
+        next_report = now + PROGRESS_INTERVAL;
+      }
+      let wake_at = std::cmp::min(deadline, next_report);
+      let _ = tokio::time::timeout(wake_at - now, self.inner.completed.notified()).await;
+    }
+  }
+}
+
+///
+/// Renders the given task names with their counts, such as `remote cache write (x3)`.
+///
+fn summarize(names: &[String]) -> String {
+  let mut counts = BTreeMap::new();
+  for name in names {
+    *counts.entry(name.as_str()).or_insert(0) += 1;
+  }
+  counts
+    .into_iter()
+    .map(|(name, count)| {
+      if count == 1 {
+        name.to_owned()
+      } else {
+        format!("{} (x{})", name, count)
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+struct TailTaskGuard {
+  inner: Arc<TailTasksInner>,
+  id: u64,
+}
+
+impl Drop for TailTaskGuard {
+  fn drop(&mut self) {
+    {
+      let mut state = self.inner.state.lock();
+      state.pending.remove(&self.id);
+      state.completed += 1;
+    }
+    self.inner.completed.notify_one();
+  }
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+use crate::{Executor, IoPriority, TailTasks};
+
+#[tokio::test]
+async fn tail_tasks_are_awaited() {
+  let executor = Executor::new();
+  let tail_tasks = TailTasks::new();
+  let (sender, receiver) = oneshot::channel::<()>();
+  tail_tasks.spawn_on("slow write", &executor, async move {
+    let _ = receiver.await;
+  });
+  tail_tasks.spawn_on("fast write", &executor, async {});
+
+  // The slow task does not complete until it is released.
+  let abandoned = tail_tasks.wait(Duration::from_millis(100)).await;
+  assert_eq!(abandoned, vec!["slow write".to_owned()]);
+  assert_eq!(tail_tasks.completed(), 1);
+
+  sender.send(()).unwrap();
+  let abandoned = tail_tasks.wait(Duration::from_secs(10)).await;
+  assert!(abandoned.is_empty());
+  assert_eq!(tail_tasks.completed(), 2);
+}
+
+#[tokio::test]
+async fn tail_tasks_complete_when_they_panic() {
+  let executor = Executor::new();
+  let tail_tasks = TailTasks::new();
+  tail_tasks.spawn_on("doomed write", &executor, async {
+    panic!("Failed to write.");
+  });
+  assert!(tail_tasks.wait(Duration::from_secs(10)).await.is_empty());
+}
+
+#[tokio::test]
+async fn spawn_blocking_with_io_priority() {
+  let executor = Executor::new();
+  let result = executor
+    .spawn_blocking_with_io_priority(IoPriority::Idle, || 42)
+    .await;
+  assert_eq!(result, 42);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn idle_io_priority_is_restored() {
+  use crate::io_priority::{sys, with_io_priority};
+
+  let before = sys::get().unwrap();
+  let during = with_io_priority(IoPriority::Idle, || sys::get().unwrap());
+  assert_eq!(sys::get().unwrap(), before);
+  // NB: Setting the idle class may not be permitted in all environments.
+  assert!(during == before || sys::class_of(during) == sys::IOPRIO_CLASS_IDLE);
+}
@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::convert::TryInto;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
@@ -13,16 +12,18 @@ use bazel_protos::gen::google::bytestream::{
 use bytes::{Bytes, BytesMut};
 use futures::stream::StreamExt;
 use futures::{FutureExt, Stream};
-use hashing::{Digest, Fingerprint};
+use hashing::Fingerprint;
 use parking_lot::Mutex;
+use remexec::action_cache_server::{ActionCache, ActionCacheServer};
 use remexec::capabilities_server::{Capabilities, CapabilitiesServer};
 use remexec::content_addressable_storage_server::{
   ContentAddressableStorage, ContentAddressableStorageServer,
 };
 use remexec::{
-  BatchReadBlobsRequest, BatchReadBlobsResponse, BatchUpdateBlobsRequest, BatchUpdateBlobsResponse,
-  CacheCapabilities, ExecutionCapabilities, FindMissingBlobsRequest, FindMissingBlobsResponse,
-  GetCapabilitiesRequest, GetTreeRequest, GetTreeResponse, ServerCapabilities,
+  ActionResult, BatchReadBlobsRequest, BatchReadBlobsResponse, BatchUpdateBlobsRequest,
+  BatchUpdateBlobsResponse, CacheCapabilities, ExecutionCapabilities, FindMissingBlobsRequest,
+  FindMissingBlobsResponse, GetActionResultRequest, GetCapabilitiesRequest, GetTreeRequest,
+  GetTreeResponse, ServerCapabilities, UpdateActionResultRequest,
 };
 use std::pin::Pin;
 use testutil::data::{TestData, TestDirectory, TestTree};
@@ -34,10 +35,14 @@ use tonic::{Request, Response, Status};
 /// Implements the ContentAddressableStorage gRPC API, answering read requests with either known
 /// content, NotFound for valid but unknown content, or InvalidArguments for bad arguments.
 ///
+/// Also implements a minimal ActionCache, for clients which store metadata in the action cache of
+/// the same server as their blobs.
+///
 pub struct StubCAS {
   read_request_count: Arc<Mutex<usize>>,
   pub write_message_sizes: Arc<Mutex<Vec<usize>>>,
   pub blobs: Arc<Mutex<HashMap<Fingerprint, Bytes>>>,
+  pub action_results: Arc<Mutex<HashMap<Fingerprint, ActionResult>>>,
   local_addr: SocketAddr,
   shutdown_sender: Option<tokio::sync::oneshot::Sender<()>>,
 }
@@ -169,10 +174,12 @@ impl StubCAS {
     let read_request_count = Arc::new(Mutex::new(0));
     let write_message_sizes = Arc::new(Mutex::new(Vec::new()));
     let blobs = Arc::new(Mutex::new(blobs));
+    let action_results = Arc::new(Mutex::new(HashMap::new()));
     let responder = StubCASResponder {
       chunk_size_bytes,
       instance_name,
       blobs: blobs.clone(),
+      action_results: action_results.clone(),
       always_errors,
       read_request_count: read_request_count.clone(),
       write_message_sizes: write_message_sizes.clone(),
@@ -193,6 +200,7 @@ impl StubCAS {
       let router = server
         .add_service(ByteStreamServer::new(responder.clone()))
         .add_service(ContentAddressableStorageServer::new(responder.clone()))
+        .add_service(ActionCacheServer::new(responder.clone()))
         .add_service(CapabilitiesServer::new(responder));
 
       router
@@ -205,6 +213,7 @@ impl StubCAS {
       read_request_count,
       write_message_sizes,
       blobs,
+      action_results,
       local_addr,
       shutdown_sender: Some(shutdown_sender),
     }
@@ -235,6 +244,7 @@ pub struct StubCASResponder {
   chunk_size_bytes: usize,
   instance_name: Option<String>,
   blobs: Arc<Mutex<HashMap<Fingerprint, Bytes>>>,
+  action_results: Arc<Mutex<HashMap<Fingerprint, ActionResult>>>,
   always_errors: bool,
   required_auth_header: Option<String>,
   pub read_request_count: Arc<Mutex<usize>>,
@@ -278,6 +288,18 @@ macro_rules! check_instance_name {
   };
 }
 
+///
+/// The key of the blob with the given hex-encoded hash. SHA-1 hashes (as used by some legacy CAS
+/// deployments) are zero-padded to the size of a SHA-256 Fingerprint.
+///
+fn blob_key(hash: &str) -> Result<Fingerprint, String> {
+  if hash.len() == 40 {
+    Fingerprint::from_hex_string(&format!("{}{}", hash, "0".repeat(24)))
+  } else {
+    Fingerprint::from_hex_string(hash)
+  }
+}
+
 impl StubCASResponder {
   fn instance_name(&self) -> String {
     self.instance_name.clone().unwrap_or_default()
@@ -296,7 +318,7 @@ impl StubCASResponder {
       )));
     }
     let digest = parts[2];
-    let fingerprint = Fingerprint::from_hex_string(digest)
+    let fingerprint = blob_key(digest)
       .map_err(|e| Status::invalid_argument(format!("Bad digest {}: {}", digest, e)))?;
     if self.always_errors {
       return Err(Status::internal(
@@ -415,7 +437,7 @@ impl ByteStream for StubCASResponder {
             resource_name
           )));
         }
-        let fingerprint = match Fingerprint::from_hex_string(parts[4]) {
+        let fingerprint = match blob_key(parts[4]) {
           Ok(f) => f,
           Err(err) => {
             return Err(Status::invalid_argument(format!(
@@ -489,10 +511,9 @@ impl ContentAddressableStorage for StubCASResponder {
     let blobs = self.blobs.lock();
     let mut response = FindMissingBlobsResponse::default();
     for digest in request.blob_digests {
-      let hashing_digest_result: Result<Digest, String> = digest.try_into();
-      let hashing_digest = hashing_digest_result.expect("Bad digest");
-      if !blobs.contains_key(&hashing_digest.hash) {
-        response.missing_blob_digests.push(hashing_digest.into())
+      let fingerprint = blob_key(&digest.hash).expect("Bad digest");
+      if !blobs.contains_key(&fingerprint) {
+        response.missing_blob_digests.push(digest)
       }
     }
     Ok(Response::new(response))
@@ -522,6 +543,70 @@ impl ContentAddressableStorage for StubCASResponder {
   }
 }
 
+#[tonic::async_trait]
+impl ActionCache for StubCASResponder {
+  async fn get_action_result(
+    &self,
+    request: Request<GetActionResultRequest>,
+  ) -> Result<Response<ActionResult>, Status> {
+    check_auth!(self, request);
+
+    if self.always_errors {
+      return Err(Status::internal(
+        "StubCAS is configured to always fail".to_owned(),
+      ));
+    }
+
+    let request = request.into_inner();
+
+    check_instance_name!(self, request);
+
+    let action_digest = request
+      .action_digest
+      .ok_or_else(|| Status::invalid_argument("Must provide action_digest".to_owned()))?;
+    let fingerprint = blob_key(&action_digest.hash)
+      .map_err(|e| Status::invalid_argument(format!("Bad digest: {}", e)))?;
+    match self.action_results.lock().get(&fingerprint) {
+      Some(action_result) => Ok(Response::new(action_result.clone())),
+      None => Err(Status::not_found(format!(
+        "Did not find action {}",
+        fingerprint
+      ))),
+    }
+  }
+
+  async fn update_action_result(
+    &self,
+    request: Request<UpdateActionResultRequest>,
+  ) -> Result<Response<ActionResult>, Status> {
+    check_auth!(self, request);
+
+    if self.always_errors {
+      return Err(Status::internal(
+        "StubCAS is configured to always fail".to_owned(),
+      ));
+    }
+
+    let request = request.into_inner();
+
+    check_instance_name!(self, request);
+
+    let action_digest = request
+      .action_digest
+      .ok_or_else(|| Status::invalid_argument("Must provide action_digest".to_owned()))?;
+    let fingerprint = blob_key(&action_digest.hash)
+      .map_err(|e| Status::invalid_argument(format!("Bad digest: {}", e)))?;
+    let action_result = request
+      .action_result
+      .ok_or_else(|| Status::invalid_argument("Must provide action_result".to_owned()))?;
+    self
+      .action_results
+      .lock()
+      .insert(fingerprint, action_result.clone());
+    Ok(Response::new(action_result))
+  }
+}
+
 #[tonic::async_trait]
 impl Capabilities for StubCASResponder {
   async fn get_capabilities(
@@ -34,7 +34,7 @@ use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Weak};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::{self, Receiver, RecvTimeoutError, TryRecvError};
 use fs::GitignoreStyleExcludes;
@@ -68,6 +68,12 @@ type WatcherTaskInputs = (
   Receiver<notify::Result<notify::Event>>,
 );
 
+///
+/// The period for which filesystem events are batched before the paths that they affect are
+/// invalidated.
+///
+const INVALIDATION_BATCH_WINDOW: Duration = Duration::from_millis(50);
+
 pub struct InvalidationWatcher(Mutex<Inner>);
 
 impl InvalidationWatcher {
@@ -150,6 +156,11 @@ impl InvalidationWatcher {
     watch_receiver: Receiver<notify::Result<notify::Event>>,
   ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
+      // Events are accumulated into batches, so that a storm of events (such as those caused by a
+      // VCS checkout) results in a few large invalidations rather than very many small ones.
+      let mut pending_paths: HashSet<PathBuf> = HashSet::new();
+      let mut pending_all = false;
+      let mut batch_deadline: Option<Instant> = None;
       let exit_msg = loop {
         let event_res = watch_receiver.recv_timeout(Duration::from_millis(10));
         let invalidatable = if let Some(g) = invalidatable.upgrade() {
@@ -179,10 +190,15 @@ impl InvalidationWatcher {
                 // only glob (one that ends in `/` ) was supposed to ignore a directory path, but didn't because we claimed it was a file. That
                 // directory path will be used to invalidate nodes, but won't invalidate anything because its path is somewhere
                 // out of our purview.
-                if ignorer.is_ignored_or_child_of_ignored_path(
-                  &path_relative_to_build_root,
-                  /* is_dir */ false,
-                ) {
+                //
+                // Paths outside of the build root (such as the directories containing local
+                // binaries) are not subject to the ignorer.
+                if path_relative_to_build_root.is_relative()
+                  && ignorer.is_ignored_or_child_of_ignored_path(
+                    &path_relative_to_build_root,
+                    /* is_dir */ false,
+                  )
+                {
                   trace!("notify ignoring {:?}", path_relative_to_build_root);
                   None
                 } else {
@@ -201,26 +217,46 @@ impl InvalidationWatcher {
 
             // Only invalidate stuff if we have paths that weren't filtered out by gitignore.
             if flag == Some(Flag::Rescan) {
-              debug!("notify queue overflowed: invalidating all paths");
-              invalidatable.invalidate_all("notify");
+              debug!("notify queue overflowed: will invalidate all paths");
+              pending_all = true;
             } else if !paths.is_empty() {
-              debug!("notify invalidating {:?} because of {:?}", paths, ev.kind);
-              invalidatable.invalidate(&paths, "notify");
+              debug!(
+                "notify will invalidate {:?} because of {:?}",
+                paths, ev.kind
+              );
+              pending_paths.extend(paths);
+            }
+            if batch_deadline.is_none() && (pending_all || !pending_paths.is_empty()) {
+              batch_deadline = Some(Instant::now() + INVALIDATION_BATCH_WINDOW);
             }
           }
           Ok(Err(err)) => {
             if let notify::ErrorKind::PathNotFound = err.kind {
               warn!("Path(s) did not exist: {:?}", err.paths);
-              continue;
             } else {
               break format!("Watch error: {}", err);
             }
           }
-          Err(RecvTimeoutError::Timeout) => continue,
+          Err(RecvTimeoutError::Timeout) => {}
           Err(RecvTimeoutError::Disconnected) => {
             break "The watch provider exited.".to_owned();
           }
         };
+
+        // If the current batch has been open for long enough, invalidate it.
+        if batch_deadline.map_or(false, |deadline| deadline <= Instant::now()) {
+          batch_deadline = None;
+          if pending_all {
+            debug!("notify invalidating all paths");
+            pending_all = false;
+            pending_paths.clear();
+            invalidatable.invalidate_all("notify");
+          } else {
+            let paths = std::mem::take(&mut pending_paths);
+            debug!("notify invalidating {} paths", paths.len());
+            invalidatable.invalidate(&paths, "notify");
+          }
+        }
       };
 
       // Log and send the exit code.
@@ -138,6 +138,42 @@ async fn liveness_watch_error() {
   join_handle.join().unwrap();
 }
 
+#[tokio::test]
+async fn batch_watch_events() {
+  let (tempdir, file_path) = setup_fs();
+  let build_root = tempdir.path().to_path_buf();
+
+  let invalidatable = Arc::new(TestInvalidatable::default());
+  let ignorer = GitignoreStyleExcludes::empty();
+  let _watcher = setup_watch(ignorer.clone(), build_root.clone(), file_path.clone()).await;
+  let (liveness_sender, _liveness_receiver) = crossbeam_channel::unbounded();
+  let (event_sender, event_receiver) = crossbeam_channel::unbounded();
+  let _join_handle = InvalidationWatcher::start_background_thread(
+    Arc::downgrade(&invalidatable),
+    ignorer,
+    build_root.clone(),
+    liveness_sender,
+    event_receiver,
+  );
+
+  // Send events for a few files in quick succession: they should be invalidated together.
+  let file_names = vec!["a", "b", "c"];
+  for file_name in &file_names {
+    event_sender
+      .send(Ok(
+        notify::Event::new(notify::EventKind::Any).add_path(build_root.join(file_name)),
+      ))
+      .unwrap();
+  }
+  sleep(Duration::from_millis(500));
+
+  let calls = invalidatable.calls.lock();
+  assert_eq!(1, calls.len());
+  for file_name in file_names {
+    assert!(calls[0].contains(Path::new(file_name)));
+  }
+}
+
 #[derive(Default)]
 struct TestInvalidatable {
   pub calls: Mutex<Vec<HashSet<PathBuf>>>,
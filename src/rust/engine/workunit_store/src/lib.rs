@@ -33,7 +33,7 @@ use std::collections::{BinaryHeap, HashMap};
 use std::future::Future;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use bytes::{BufMut, Bytes, BytesMut};
 use concrete_time::TimeSpan;
@@ -43,13 +43,19 @@ pub use log::Level;
 pub use metrics::{Metric, ObservationMetric};
 use parking_lot::Mutex;
 use petgraph::graph::{DiGraph, NodeIndex};
+pub use progress::{Progress, ProgressReporter};
 use rand::thread_rng;
 use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use streaming::StreamingQueue;
+pub use streaming::{OverflowPolicy, StreamingQueueBounds, StreamingQueueStats};
 use tokio::task_local;
 
 mod metrics;
+mod progress;
+mod streaming;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct SpanId(u64);
 
 impl SpanId {
@@ -67,7 +73,7 @@ impl std::fmt::Display for SpanId {
 
 type WorkunitGraph = DiGraph<SpanId, (), u32>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Workunit {
   pub name: String,
   pub span_id: SpanId,
@@ -75,6 +81,7 @@ pub struct Workunit {
   pub state: WorkunitState,
   pub metadata: WorkunitMetadata,
   pub counters: HashMap<Metric, u64>,
+  pub logs: Vec<WorkunitLog>,
 }
 
 impl Workunit {
@@ -120,19 +127,19 @@ impl Workunit {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum WorkunitState {
   Started { start_time: SystemTime },
   Completed { time_span: TimeSpan },
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ArtifactOutput {
   FileDigest(hashing::Digest),
   Snapshot(hashing::Digest),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WorkunitMetadata {
   pub desc: Option<String>,
   pub message: Option<String>,
@@ -142,6 +149,8 @@ pub struct WorkunitMetadata {
   pub stderr: Option<hashing::Digest>,
   pub artifacts: Vec<(String, ArtifactOutput)>,
   pub user_metadata: Vec<(String, UserMetadataItem)>,
+  /// The progress of a long running workunit, if it reports any: see `ProgressReporter`.
+  pub progress: Option<Progress>,
 }
 
 impl Default for WorkunitMetadata {
@@ -155,18 +164,34 @@ impl Default for WorkunitMetadata {
       stderr: None,
       artifacts: Vec::new(),
       user_metadata: Vec::new(),
+      progress: None,
     }
   }
 }
 
+///
+/// A log record which was emitted while a workunit was running, and which was attached to it
+/// (rather than being written to the global log stream): see `WorkunitStore::record_log`.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkunitLog {
+  pub level: Level,
+  pub message: String,
+}
+
+/// The maximum number of log records which are attached to a single workunit. Records beyond
+/// this limit are not attached, and so are written to the global log stream instead.
+pub const MAX_LOGS_PER_WORKUNIT: usize = 1000;
+
 /// Abstract id for passing user metadata items around
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum UserMetadataItem {
   PyValue(UserMetadataPyValue),
   ImmediateId(i64),
+  ImmediateString(String),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct UserMetadataPyValue(uuid::Uuid);
 
 impl UserMetadataPyValue {
@@ -175,6 +200,7 @@ impl UserMetadataPyValue {
   }
 }
 
+#[derive(Serialize, Deserialize)]
 enum StoreMsg {
   Started(Workunit),
   Completed(
@@ -182,8 +208,10 @@ enum StoreMsg {
     Option<WorkunitMetadata>,
     SystemTime,
     HashMap<Metric, u64>,
+    Vec<WorkunitLog>,
   ),
   Canceled(SpanId),
+  Progress(SpanId, Progress),
 }
 
 #[derive(Clone)]
@@ -192,22 +220,20 @@ pub struct WorkunitStore {
   streaming_workunit_data: StreamingWorkunitData,
   heavy_hitters_data: HeavyHittersData,
   metrics_data: MetricsData,
+  logs_data: LogsData,
   observation_data: ObservationsData,
 }
 
 #[derive(Clone)]
 struct StreamingWorkunitData {
-  msg_rx: Arc<Mutex<Receiver<StoreMsg>>>,
-  msg_tx: Arc<Mutex<Sender<StoreMsg>>>,
+  queue: Arc<StreamingQueue>,
   workunit_records: Arc<Mutex<HashMap<SpanId, Workunit>>>,
 }
 
 impl StreamingWorkunitData {
   fn new() -> StreamingWorkunitData {
-    let (msg_tx, msg_rx) = channel();
     StreamingWorkunitData {
-      msg_tx: Arc::new(Mutex::new(msg_tx)),
-      msg_rx: Arc::new(Mutex::new(msg_rx)),
+      queue: Arc::new(StreamingQueue::default()),
       workunit_records: Arc::new(Mutex::new(HashMap::new())),
     }
   }
@@ -221,17 +247,16 @@ impl StreamingWorkunitData {
     let (started_workunits, completed_workunits) = {
       let mut started_messages = vec![];
       let mut completed_messages = vec![];
+      let mut progress_messages = vec![];
 
-      {
-        let receiver = self.msg_rx.lock();
-        while let Ok(msg) = receiver.try_recv() {
-          match msg {
-            StoreMsg::Started(started) => started_messages.push(started),
-            StoreMsg::Completed(span, metadata, time, new_counters) => {
-              completed_messages.push((span, metadata, time, new_counters))
-            }
-            StoreMsg::Canceled(..) => (),
+      for msg in self.queue.drain() {
+        match msg {
+          StoreMsg::Started(started) => started_messages.push(started),
+          StoreMsg::Completed(span, metadata, time, new_counters, logs) => {
+            completed_messages.push((span, metadata, time, new_counters, logs))
           }
+          StoreMsg::Canceled(..) => (),
+          StoreMsg::Progress(span, progress) => progress_messages.push((span, progress)),
         }
       }
 
@@ -248,8 +273,25 @@ impl StreamingWorkunitData {
         }
       }
 
+      // A running workunit which reported progress is emitted again as started (once per poll),
+      // with its latest progress.
+      let progressed = progress_messages.into_iter().collect::<HashMap<_, _>>();
+      started_workunits.retain(|workunit| !progressed.contains_key(&workunit.span_id));
+      for (span_id, progress) in progressed {
+        if let Some(workunit) = workunit_records.get_mut(&span_id) {
+          workunit.metadata.progress = Some(progress);
+          let running = matches!(workunit.state, WorkunitState::Started { .. });
+          if running && should_emit(workunit) {
+            let mut workunit = workunit.clone();
+            workunit.parent_id =
+              first_matched_parent(&workunit_records, workunit.parent_id, should_emit);
+            started_workunits.push(workunit);
+          }
+        }
+      }
+
       let mut completed_workunits: Vec<Workunit> = vec![];
-      for (span_id, new_metadata, end_time, new_counters) in completed_messages.into_iter() {
+      for (span_id, new_metadata, end_time, new_counters, logs) in completed_messages.into_iter() {
         match workunit_records.entry(span_id) {
           Entry::Vacant(_) => {
             log::warn!("No previously-started workunit found for id: {}", span_id);
@@ -272,6 +314,7 @@ impl StreamingWorkunitData {
               workunit.metadata = metadata;
             }
             workunit.counters = new_counters;
+            workunit.logs = logs;
             workunit_records.insert(span_id, workunit.clone());
 
             if should_emit(&workunit) {
@@ -364,7 +407,7 @@ impl HeavyHittersData {
     while let Ok(msg) = receiver.try_recv() {
       match msg {
         StoreMsg::Started(started) => Self::add_started_workunit_to_store(started, &mut inner),
-        StoreMsg::Completed(span_id, new_metadata, time, new_counters) => {
+        StoreMsg::Completed(span_id, new_metadata, time, new_counters, _logs) => {
           Self::add_completed_workunit_to_store(
             span_id,
             new_metadata,
@@ -377,8 +420,32 @@ impl HeavyHittersData {
           inner.workunit_records.remove(&span_id);
           inner.span_id_to_graph.remove(&span_id);
         }
+        StoreMsg::Progress(span_id, progress) => {
+          if let Some(workunit) = inner.workunit_records.get_mut(&span_id) {
+            workunit.metadata.progress = Some(progress);
+          }
+        }
+      }
+    }
+  }
+
+  ///
+  /// Renders the description of the given visible workunit, along with the progress of the first
+  /// workunit between the given leaf and it (inclusive) which reported any.
+  ///
+  fn label(inner: &HeavyHittersInnerStore, leaf: SpanId, visible: &Workunit) -> Option<String> {
+    let desc = visible.metadata.desc.as_ref()?;
+    let mut span_id = Some(leaf);
+    while let Some(workunit) = span_id.and_then(|span_id| inner.workunit_records.get(&span_id)) {
+      if let Some(progress) = workunit.metadata.progress {
+        return Some(format!("{} ({})", desc, progress));
+      }
+      if workunit.span_id == visible.span_id {
+        break;
       }
+      span_id = workunit.parent_id;
     }
+    Some(desc.clone())
   }
 
   fn heavy_hitters(&self, k: usize) -> HashMap<String, Option<Duration>> {
@@ -405,16 +472,18 @@ impl HeavyHittersData {
 
     // Output the visible parents of the longest running leaves.
     let mut res = HashMap::new();
-    while let Some((_dur, span_id)) = queue.pop() {
+    while let Some((_dur, leaf_span_id)) = queue.pop() {
       // If the leaf is visible or has a visible parent, emit it.
-      if let Some(span_id) =
-        first_matched_parent(&inner.workunit_records, Some(span_id), Self::is_visible)
-      {
+      if let Some(span_id) = first_matched_parent(
+        &inner.workunit_records,
+        Some(leaf_span_id),
+        Self::is_visible,
+      ) {
         let workunit = inner.workunit_records.get(&span_id).unwrap();
-        if let Some(effective_name) = workunit.metadata.desc.as_ref() {
+        if let Some(effective_name) = Self::label(&inner, leaf_span_id, workunit) {
           let maybe_duration = Self::duration_for(now, &workunit);
 
-          res.insert(effective_name.to_string(), maybe_duration);
+          res.insert(effective_name, maybe_duration);
           if res.len() >= k {
             break;
           }
@@ -424,7 +493,7 @@ impl HeavyHittersData {
     res
   }
 
-  fn render_straggling_workunits(&self, duration_threshold: Duration) -> Option<String> {
+  fn straggling_workunits(&self, duration_threshold: Duration) -> Vec<(Duration, String)> {
     self.refresh_store();
     let now = SystemTime::now();
     let inner = self.inner.lock();
@@ -442,20 +511,24 @@ impl HeavyHittersData {
             Self::is_visible,
           )
           .and_then(|span_id| inner.workunit_records.get(&span_id))
-          .and_then(|wu| wu.metadata.desc.as_ref())
+          .and_then(|wu| Self::label(&inner, workunit.span_id, wu))
           .map(|desc| (duration, desc))
         }
         _ => None,
       })
       .collect::<Vec<_>>();
 
-    if matching_visible_parents.is_empty() {
-      return None;
-    }
-
     // NB: We sort before stringifying the Duration to get Duration ordering.
     matching_visible_parents.sort();
     matching_visible_parents.dedup();
+    matching_visible_parents
+  }
+
+  fn render_straggling_workunits(&self, duration_threshold: Duration) -> Option<String> {
+    let matching_visible_parents = self.straggling_workunits(duration_threshold);
+    if matching_visible_parents.is_empty() {
+      return None;
+    }
 
     Some(format!(
       "Long running tasks:\n  {}",
@@ -516,6 +589,7 @@ impl WorkunitStore {
       streaming_workunit_data: StreamingWorkunitData::new(),
       heavy_hitters_data: HeavyHittersData::new(),
       metrics_data: MetricsData::default(),
+      logs_data: LogsData::default(),
       observation_data: ObservationsData::default(),
     }
   }
@@ -536,6 +610,14 @@ impl WorkunitStore {
     }
   }
 
+  ///
+  /// Find the visible parents of leaf workunits which have been running for at least the given
+  /// threshold, sorted by duration.
+  ///
+  pub fn straggling_workunits(&self, threshold: Duration) -> Vec<(Duration, String)> {
+    self.heavy_hitters_data.straggling_workunits(threshold)
+  }
+
   ///
   /// Find the longest running leaf workunits, and render their first visible parents.
   ///
@@ -559,7 +641,9 @@ impl WorkunitStore {
       },
       metadata,
       counters: HashMap::new(),
+      logs: Vec::new(),
     };
+    self.logs_data.logs.lock().insert(span_id, Vec::new());
 
     self
       .heavy_hitters_data
@@ -569,10 +653,8 @@ impl WorkunitStore {
       .unwrap();
     self
       .streaming_workunit_data
-      .msg_tx
-      .lock()
-      .send(StoreMsg::Started(started.clone()))
-      .unwrap();
+      .queue
+      .push(StoreMsg::Started(started.clone()));
 
     if self.log_starting_workunits {
       started.log_workunit_state(false)
@@ -586,6 +668,7 @@ impl WorkunitStore {
   }
 
   fn cancel_workunit(&self, workunit: &Workunit) {
+    self.logs_data.logs.lock().remove(&workunit.span_id);
     workunit.log_workunit_state(true);
     self
       .heavy_hitters_data
@@ -607,15 +690,20 @@ impl WorkunitStore {
       }
     };
     workunit.counters = workunit_counters.clone();
+    let workunit_logs = self
+      .logs_data
+      .logs
+      .lock()
+      .remove(&span_id)
+      .unwrap_or_default();
 
-    let tx = self.streaming_workunit_data.msg_tx.lock();
-    tx.send(StoreMsg::Completed(
+    self.streaming_workunit_data.queue.push(StoreMsg::Completed(
       span_id,
       new_metadata.clone(),
       end_time,
       workunit_counters.clone(),
-    ))
-    .unwrap();
+      workunit_logs,
+    ));
 
     self
       .heavy_hitters_data
@@ -626,6 +714,7 @@ impl WorkunitStore {
         new_metadata,
         end_time,
         workunit_counters,
+        Vec::new(),
       ))
       .unwrap();
 
@@ -642,6 +731,22 @@ impl WorkunitStore {
     workunit.log_workunit_state(false);
   }
 
+  ///
+  /// Records the latest progress of a running workunit: see `ProgressReporter`.
+  ///
+  pub fn update_progress(&self, span_id: SpanId, progress: Progress) {
+    self
+      .heavy_hitters_data
+      .msg_tx
+      .lock()
+      .send(StoreMsg::Progress(span_id, progress))
+      .unwrap();
+    self
+      .streaming_workunit_data
+      .queue
+      .push(StoreMsg::Progress(span_id, progress));
+  }
+
   pub fn add_completed_workunit(
     &self,
     name: String,
@@ -659,6 +764,7 @@ impl WorkunitStore {
       state: WorkunitState::Started { start_time },
       metadata,
       counters: HashMap::new(),
+      logs: Vec::new(),
     };
 
     self
@@ -669,10 +775,8 @@ impl WorkunitStore {
       .unwrap();
     self
       .streaming_workunit_data
-      .msg_tx
-      .lock()
-      .send(StoreMsg::Started(workunit.clone()))
-      .unwrap();
+      .queue
+      .push(StoreMsg::Started(workunit.clone()));
 
     self.complete_workunit_impl(workunit, end_time);
   }
@@ -686,6 +790,18 @@ impl WorkunitStore {
       .with_latest_workunits(max_verbosity, f)
   }
 
+  ///
+  /// Bounds the queue of workunits which are waiting to be polled by streaming workunit handlers,
+  /// or (if None) removes its bound.
+  ///
+  pub fn set_streaming_queue_bounds(&self, bounds: Option<StreamingQueueBounds>) {
+    self.streaming_workunit_data.queue.set_bounds(bounds)
+  }
+
+  pub fn streaming_queue_stats(&self) -> StreamingQueueStats {
+    self.streaming_workunit_data.queue.stats()
+  }
+
   pub fn increment_counter(&self, counter_name: Metric, change: u64) {
     let store_handle = expect_workunit_store_handle();
     if let Some(span_id) = store_handle.parent_id {
@@ -706,6 +822,23 @@ impl WorkunitStore {
     }
   }
 
+  ///
+  /// Attaches a log record to the given running workunit, to be reported along with it when it
+  /// completes. Returns false (without attaching the record) if the workunit is not running, or
+  /// already has the maximum number of records attached, in which case the caller should log the
+  /// record normally.
+  ///
+  pub fn record_log(&self, span_id: SpanId, level: Level, message: String) -> bool {
+    let mut logs = self.logs_data.logs.lock();
+    match logs.get_mut(&span_id) {
+      Some(workunit_logs) if workunit_logs.len() < MAX_LOGS_PER_WORKUNIT => {
+        workunit_logs.push(WorkunitLog { level, message });
+        true
+      }
+      _ => false,
+    }
+  }
+
   ///
   /// Records an observation of a time-like metric into a histogram.
   ///
@@ -723,6 +856,32 @@ impl WorkunitStore {
       });
   }
 
+  ///
+  /// Records the time (in microseconds) elapsed since the given start time into a histogram.
+  ///
+  pub fn record_observation_since(&self, metric: ObservationMetric, start: Instant) {
+    let elapsed = start.elapsed().as_micros();
+    self.record_observation(metric, elapsed.min(u128::from(u64::MAX)) as u64);
+  }
+
+  ///
+  /// Returns the values at the given percentiles (in the range 0.0 to 100.0) of each recorded
+  /// histogram, for consumers which cannot decode the encoded histograms.
+  ///
+  pub fn observation_percentiles(&self, percentiles: &[f64]) -> HashMap<String, Vec<(f64, u64)>> {
+    let histograms_by_metric = self.observation_data.observations.lock();
+    histograms_by_metric
+      .iter()
+      .map(|(metric, histogram)| {
+        let values = percentiles
+          .iter()
+          .map(|percentile| (*percentile, histogram.value_at_percentile(*percentile)))
+          .collect();
+        (metric.as_ref().to_owned(), values)
+      })
+      .collect()
+  }
+
   ///
   /// Return all observations in binary encoded format.
   ///
@@ -874,6 +1033,20 @@ impl Default for MetricsData {
   }
 }
 
+#[derive(Clone)]
+struct LogsData {
+  /// The log records attached to each running workunit.
+  logs: Arc<Mutex<HashMap<SpanId, Vec<WorkunitLog>>>>,
+}
+
+impl Default for LogsData {
+  fn default() -> LogsData {
+    LogsData {
+      logs: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+}
+
 #[derive(Clone)]
 struct ObservationsData {
   /// Histograms for supported observation metrics.
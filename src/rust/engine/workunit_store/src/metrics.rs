@@ -40,6 +40,8 @@ use strum::IntoEnumIterator;
   strum_macros::AsRefStr,
   strum_macros::EnumIter,
   strum_macros::ToString,
+  serde_derive::Serialize,
+  serde_derive::Deserialize,
 )]
 #[strum(serialize_all = "snake_case")]
 pub enum Metric {
@@ -52,6 +54,9 @@ pub enum Metric {
   /// processes directly.
   LocalCacheTotalTimeSavedMs,
   LocalExecutionRequests,
+  /// The number of files which were not re-written while materializing into the workspace, because
+  /// their existing content already matched.
+  LocalMaterializeUnchangedFiles,
   RemoteCacheRequests,
   RemoteCacheRequestsCached,
   RemoteCacheRequestsUncached,
@@ -65,13 +70,32 @@ pub enum Metric {
   /// processes directly.
   RemoteCacheTotalTimeSavedMs,
   RemoteExecutionErrors,
+  /// The number of times that the server reported missing inputs while they were still being
+  /// uploaded concurrently with execution, causing the execution to wait for the upload.
+  RemoteExecutionPipelinedUploadWaits,
+  /// The total time (in milliseconds) that execution requests were delayed by the configured
+  /// rate limit.
+  RemoteExecutionRateLimitedMs,
   RemoteExecutionRequests,
+  /// The number of times that the server reported that it was out of capacity, causing us to
+  /// throttle the number of concurrent executions.
+  RemoteExecutionResourceExhausted,
   RemoteExecutionRPCErrors,
   RemoteExecutionRPCExecute,
   RemoteExecutionRPCRetries,
   RemoteExecutionRPCWaitExecution,
+  /// The number of remote executions which ran for long enough relative to their historical runtime
+  /// that a second (speculative) attempt was started: see `process_execution::speculation`.
+  RemoteExecutionSpeculationStarted,
+  RemoteExecutionSpeculationOriginalCompletedFirst,
+  RemoteExecutionSpeculationSpeculativeCompletedFirst,
   RemoteExecutionSuccess,
   RemoteExecutionTimeouts,
+  RemoteStoreBytesDownloaded,
+  RemoteStoreBytesUploaded,
+  /// The total time (in milliseconds) that remote store requests were delayed by the configured
+  /// rate and bandwidth limits.
+  RemoteStoreRateLimitedMs,
 }
 
 impl Metric {
@@ -93,4 +117,41 @@ pub enum ObservationMetric {
   /// The time saved (in milliseconds) thanks to a remote cache hit instead of running the process
   /// directly.
   RemoteCacheTimeSavedMs,
+  /// The latency (in microseconds) of GetActionResult RPCs against the remote cache.
+  RemoteCacheGetActionResultTime,
+  /// The latency (in microseconds) of FindMissingBlobs RPCs against the remote store.
+  RemoteStoreFindMissingBlobsTime,
+  /// The latency (in microseconds) of ByteStream Read RPCs against the remote store.
+  RemoteStoreReadBlobTime,
+  /// The latency (in microseconds) of ByteStream Write RPCs against the remote store.
+  RemoteStoreWriteBlobTime,
+  /// The time (in microseconds) that reads from the remote store spent queued behind other reads,
+  /// when `--remote-store-read-concurrency` is set.
+  RemoteStoreReadQueueTime,
+  /// The time (in microseconds) that transfers to or from the remote store spent waiting for other
+  /// transfers to complete, when `--remote-store-max-inflight-bytes` is set.
+  RemoteStoreInflightBytesQueueTime,
+  /// The latency (in microseconds) of Execute RPCs, from submission until the operation stream
+  /// completes or disconnects.
+  RemoteExecutionRPCExecuteTime,
+  /// The latency (in microseconds) of WaitExecution RPCs, from reconnection until the operation
+  /// stream completes or disconnects.
+  RemoteExecutionRPCWaitExecutionTime,
+  /// The time (in microseconds) spent materializing the inputs of a local process into its sandbox.
+  LocalSandboxMaterializeInputsTime,
+  /// The time (in microseconds) spent creating the output directories, named cache symlinks and
+  /// JDK symlink in the sandbox of a local process.
+  LocalSandboxCreateOutputDirsTime,
+  /// The time (in microseconds) that local processes spent executing.
+  LocalSandboxExecuteTime,
+  /// The time (in microseconds) spent capturing the outputs of a local process from its sandbox.
+  LocalSandboxCaptureOutputsTime,
+  /// The time (in microseconds) spent storing the stdout and stderr of a local process.
+  LocalSandboxStoreResultsTime,
+  /// The total size (in bytes) of the local sandboxes which existed (including those waiting to be
+  /// cleaned up) as each local process completed. The maximum is the peak disk usage of sandboxes.
+  LocalSandboxesLiveBytes,
+  /// The time (in microseconds) that each streaming workunit handler callback took to process a
+  /// batch of workunits.
+  StreamingWorkunitCallbackTime,
 }
@@ -0,0 +1,135 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{get_workunit_store_handle, SpanId, WorkunitStore};
+
+/// Progress is only reported for operations which have been running for at least this long, so
+/// that short operations do not generate updates.
+const PROGRESS_THRESHOLD: Duration = Duration::from_secs(2);
+
+/// The minimum interval between reports of progress for an operation.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+///
+/// The progress of a long running operation on many files, such as an upload. The totals may grow
+/// while the operation runs, for operations which discover their work incrementally (such as
+/// walking a directory).
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+  pub done_files: u64,
+  pub total_files: u64,
+  pub done_bytes: u64,
+  pub total_bytes: u64,
+}
+
+impl fmt::Display for Progress {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}/{} files, {}/{}",
+      self.done_files,
+      self.total_files,
+      format_bytes(self.done_bytes),
+      format_bytes(self.total_bytes)
+    )
+  }
+}
+
+fn format_bytes(bytes: u64) -> String {
+  const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+  if bytes < 1024 {
+    return format!("{}B", bytes);
+  }
+  let mut value = bytes as f64 / 1024.0;
+  let mut unit = 0;
+  while value >= 1024.0 && unit < UNITS.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+  format!("{:.1}{}", value, UNITS[unit])
+}
+
+struct ProgressState {
+  progress: Progress,
+  last_reported: Option<Instant>,
+}
+
+///
+/// Tracks the progress of an operation, and (once it has been running for longer than a
+/// threshold) periodically reports it as an update to the workunit of the task which created
+/// the reporter, so that it is rendered by the dynamic UI and sent to streaming workunit handlers.
+///
+pub struct ProgressReporter {
+  target: Option<(WorkunitStore, SpanId)>,
+  start: Instant,
+  state: Mutex<ProgressState>,
+}
+
+impl ProgressReporter {
+  ///
+  /// Creates a reporter for the current workunit. If there is no current workunit, progress is
+  /// tracked, but not reported.
+  ///
+  pub fn for_current_workunit() -> ProgressReporter {
+    let target = get_workunit_store_handle()
+      .and_then(|handle| handle.parent_id.map(|span_id| (handle.store, span_id)));
+    ProgressReporter {
+      target,
+      start: Instant::now(),
+      state: Mutex::new(ProgressState {
+        progress: Progress::default(),
+        last_reported: None,
+      }),
+    }
+  }
+
+  ///
+  /// Adds the given work to the totals of the operation.
+  ///
+  pub fn add_total(&self, files: u64, bytes: u64) {
+    self.update(|progress| {
+      progress.total_files += files;
+      progress.total_bytes += bytes;
+    })
+  }
+
+  ///
+  /// Records that the given work has been completed.
+  ///
+  pub fn advance(&self, files: u64, bytes: u64) {
+    self.update(|progress| {
+      progress.done_files += files;
+      progress.done_bytes += bytes;
+    })
+  }
+
+  pub fn progress(&self) -> Progress {
+    self.state.lock().progress
+  }
+
+  fn update(&self, f: impl FnOnce(&mut Progress)) {
+    let mut state = self.state.lock();
+    f(&mut state.progress);
+
+    let now = Instant::now();
+    if now.duration_since(self.start) < PROGRESS_THRESHOLD {
+      return;
+    }
+    if let Some(last_reported) = state.last_reported {
+      if now.duration_since(last_reported) < PROGRESS_INTERVAL {
+        return;
+      }
+    }
+    state.last_reported = Some(now);
+    if let Some((ref store, span_id)) = self.target {
+      store.update_progress(span_id, state.progress);
+    }
+  }
+}
@@ -0,0 +1,231 @@
+// Copyright 2021 Pants project contributors (see CONTRIBUTORS.md).
+// Licensed under the Apache License, Version 2.0 (see LICENSE).
+
+use std::collections::{HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+
+use log::warn;
+use parking_lot::{Condvar, Mutex};
+
+use crate::{SpanId, StoreMsg};
+
+///
+/// What to do with a workunit message when the queue of messages for streaming workunit handlers
+/// is full, because the handlers have fallen behind the engine.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+  /// Block the producer of the message (i.e. the engine) until the handlers catch up.
+  Block,
+  /// Drop the message (along with the completion of a dropped workunit), and count it.
+  Drop,
+  /// Write the message to a temporary file, from which it is read once the handlers catch up.
+  Spill,
+}
+
+impl TryFrom<String> for OverflowPolicy {
+  type Error = String;
+  fn try_from(variant_candidate: String) -> Result<Self, Self::Error> {
+    match variant_candidate.as_ref() {
+      "block" => Ok(OverflowPolicy::Block),
+      "drop" => Ok(OverflowPolicy::Drop),
+      "spill" => Ok(OverflowPolicy::Spill),
+      other => Err(format!(
+        "Unknown value for streaming workunits overflow policy: {:?}",
+        other
+      )),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamingQueueBounds {
+  /// The maximum number of messages to hold in memory.
+  pub capacity: usize,
+  pub overflow_policy: OverflowPolicy,
+}
+
+///
+/// The number of messages which have overflowed the queue, by policy.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamingQueueStats {
+  pub dropped: u64,
+  pub spilled: u64,
+}
+
+///
+/// A queue of messages between the WorkunitStore and the streaming workunit handlers, which poll
+/// it periodically.
+///
+/// The queue is unbounded until bounds are set, which should only happen while a handler is
+/// polling it: otherwise, the `Block` policy would block the engine forever.
+///
+#[derive(Default)]
+pub(crate) struct StreamingQueue {
+  state: Mutex<QueueState>,
+  // Notified when the queue is drained, or its bounds change.
+  drained: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+  bounds: Option<StreamingQueueBounds>,
+  messages: VecDeque<StoreMsg>,
+  // Messages which were spilled to disk: these are always newer than the messages in memory.
+  spill: Option<Spill>,
+  // The spans whose Started messages were dropped, and whose Completed messages should be too.
+  dropped_spans: HashSet<SpanId>,
+  stats: StreamingQueueStats,
+}
+
+impl QueueState {
+  ///
+  /// If the queue is full, returns the policy to apply to a new message.
+  ///
+  fn overflow_policy(&self) -> Option<OverflowPolicy> {
+    let bounds = self.bounds?;
+    let spilling = self.spill.as_ref().map_or(false, |spill| spill.count > 0);
+    if spilling || self.messages.len() >= bounds.capacity {
+      Some(bounds.overflow_policy)
+    } else {
+      None
+    }
+  }
+
+  fn spill(&mut self, msg: &StoreMsg) -> Result<(), String> {
+    if self.spill.is_none() {
+      self.spill = Some(Spill::new()?);
+    }
+    self.spill.as_mut().unwrap().write(msg)
+  }
+}
+
+impl StreamingQueue {
+  pub(crate) fn set_bounds(&self, bounds: Option<StreamingQueueBounds>) {
+    self.state.lock().bounds = bounds;
+    self.drained.notify_all();
+  }
+
+  pub(crate) fn stats(&self) -> StreamingQueueStats {
+    self.state.lock().stats
+  }
+
+  pub(crate) fn push(&self, msg: StoreMsg) {
+    let mut state = self.state.lock();
+    if let StoreMsg::Completed(span_id, ..) = &msg {
+      if state.dropped_spans.remove(span_id) {
+        state.stats.dropped += 1;
+        return;
+      }
+    }
+
+    // Progress is superseded by later progress, so it is never worth waiting or spilling for.
+    if matches!(msg, StoreMsg::Progress(..)) && state.overflow_policy().is_some() {
+      return;
+    }
+
+    match state.overflow_policy() {
+      None => state.messages.push_back(msg),
+      Some(OverflowPolicy::Block) => {
+        while state.overflow_policy().is_some() {
+          self.drained.wait(&mut state);
+        }
+        state.messages.push_back(msg);
+      }
+      Some(OverflowPolicy::Drop) => {
+        if state.stats.dropped == 0 {
+          warn!(
+            "Streaming workunit handlers have fallen behind: dropping workunits until they catch up."
+          );
+        }
+        if let StoreMsg::Started(workunit) = &msg {
+          state.dropped_spans.insert(workunit.span_id);
+        }
+        state.stats.dropped += 1;
+      }
+      Some(OverflowPolicy::Spill) => match state.spill(&msg) {
+        Ok(()) => state.stats.spilled += 1,
+        Err(e) => {
+          // Rather than losing the message, exceed the bound.
+          warn!("Failed to spill workunit to disk: {}", e);
+          state.messages.push_back(msg);
+        }
+      },
+    }
+  }
+
+  ///
+  /// Removes and returns all queued messages, in the order that they were pushed.
+  ///
+  pub(crate) fn drain(&self) -> Vec<StoreMsg> {
+    let mut state = self.state.lock();
+    let mut messages = state.messages.drain(..).collect::<Vec<_>>();
+    if let Some(ref mut spill) = state.spill {
+      match spill.read_all() {
+        Ok(spilled) => messages.extend(spilled),
+        Err(e) => warn!("Failed to read spilled workunits from disk: {}", e),
+      }
+    }
+    self.drained.notify_all();
+    messages
+  }
+}
+
+///
+/// A temporary file of newline-delimited JSON messages.
+///
+struct Spill {
+  writer: BufWriter<File>,
+  count: usize,
+}
+
+impl Spill {
+  fn new() -> Result<Spill, String> {
+    let file =
+      tempfile::tempfile().map_err(|e| format!("Failed to create temporary file: {}", e))?;
+    Ok(Spill {
+      writer: BufWriter::new(file),
+      count: 0,
+    })
+  }
+
+  fn write(&mut self, msg: &StoreMsg) -> Result<(), String> {
+    serde_json::to_writer(&mut self.writer, msg).map_err(|e| e.to_string())?;
+    self.writer.write_all(b"\n").map_err(|e| e.to_string())?;
+    self.count += 1;
+    Ok(())
+  }
+
+  ///
+  /// Reads all of the messages in the file, and then truncates it (even if reading failed, so that
+  /// a corrupt file cannot wedge the queue).
+  ///
+  fn read_all(&mut self) -> Result<Vec<StoreMsg>, String> {
+    if self.count == 0 {
+      return Ok(vec![]);
+    }
+    let messages = self.read_messages();
+    self.count = 0;
+    let file = self.writer.get_mut();
+    file
+      .set_len(0)
+      .and_then(|()| file.seek(SeekFrom::Start(0)))
+      .map_err(|e| e.to_string())?;
+    messages
+  }
+
+  fn read_messages(&mut self) -> Result<Vec<StoreMsg>, String> {
+    self.writer.flush().map_err(|e| e.to_string())?;
+    let file = self.writer.get_mut();
+    file.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+    let mut messages = Vec::with_capacity(self.count);
+    for line in BufReader::new(&*file).lines() {
+      let line = line.map_err(|e| e.to_string())?;
+      messages.push(serde_json::from_str(&line).map_err(|e| e.to_string())?);
+    }
+    Ok(messages)
+  }
+}
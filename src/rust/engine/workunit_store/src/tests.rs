@@ -1,4 +1,10 @@
-use crate::SpanId;
+use std::time::{Instant, SystemTime};
+
+use crate::{
+  Level, ObservationMetric, OverflowPolicy, Progress, SpanId, StreamingQueueBounds,
+  StreamingQueueStats, Workunit, WorkunitLog, WorkunitMetadata, WorkunitStore,
+  MAX_LOGS_PER_WORKUNIT,
+};
 
 #[test]
 fn workunit_span_id_has_16_digits_len_hex_format() {
@@ -22,3 +28,207 @@ fn hex_16_digit_string_actually_uses_input_number() {
     "0123456789abcdef"
   );
 }
+
+#[test]
+fn observation_percentiles() {
+  let store = WorkunitStore::new(false);
+  for value in 1..=100 {
+    store.record_observation(ObservationMetric::TestObservation, value);
+  }
+  store.record_observation_since(ObservationMetric::RemoteStoreReadBlobTime, Instant::now());
+
+  let percentiles = store.observation_percentiles(&[50.0, 99.0]);
+  assert_eq!(
+    percentiles.get("test_observation"),
+    Some(&vec![(50.0, 50), (99.0, 99)])
+  );
+  assert_eq!(percentiles["remote_store_read_blob_time"].len(), 2);
+  assert_eq!(percentiles.len(), 2);
+}
+
+fn add_workunit(store: &WorkunitStore, name: &str) {
+  let now = SystemTime::now();
+  store.add_completed_workunit(
+    name.to_owned(),
+    now,
+    now,
+    None,
+    WorkunitMetadata {
+      desc: Some(format!("{} desc", name)),
+      ..WorkunitMetadata::default()
+    },
+  );
+}
+
+fn poll_names(store: &mut WorkunitStore) -> (Vec<String>, Vec<String>) {
+  store.with_latest_workunits(Level::Trace, |started, completed| {
+    let names = |workunits: &[Workunit]| -> Vec<String> {
+      workunits.iter().map(|w| w.name.clone()).collect()
+    };
+    (names(started), names(completed))
+  })
+}
+
+#[test]
+fn streaming_queue_spill() {
+  let mut store = WorkunitStore::new(false);
+  store.set_streaming_queue_bounds(Some(StreamingQueueBounds {
+    capacity: 1,
+    overflow_policy: OverflowPolicy::Spill,
+  }));
+  for name in &["a", "b", "c"] {
+    add_workunit(&store, name);
+  }
+
+  // Every message after the first was spilled, and all of them are read back in order.
+  let expected = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+  assert_eq!(poll_names(&mut store), (expected.clone(), expected));
+  assert_eq!(
+    store.streaming_queue_stats(),
+    StreamingQueueStats {
+      dropped: 0,
+      spilled: 5
+    }
+  );
+  store.with_latest_workunits(Level::Trace, |started, completed| {
+    assert!(started.is_empty());
+    assert!(completed.is_empty());
+  });
+
+  // The spill file is reused once it has been drained.
+  add_workunit(&store, "d");
+  store.with_latest_workunits(Level::Trace, |_, completed| {
+    assert_eq!(completed[0].metadata.desc, Some("d desc".to_owned()));
+  });
+}
+
+#[test]
+fn streaming_queue_drop() {
+  let mut store = WorkunitStore::new(false);
+  store.set_streaming_queue_bounds(Some(StreamingQueueBounds {
+    capacity: 1,
+    overflow_policy: OverflowPolicy::Drop,
+  }));
+  // The Started message of `a` fits, but its Completed message is dropped. Both messages of `b`
+  // are dropped.
+  add_workunit(&store, "a");
+  add_workunit(&store, "b");
+  assert_eq!(poll_names(&mut store), (vec!["a".to_owned()], vec![]));
+  assert_eq!(
+    store.streaming_queue_stats(),
+    StreamingQueueStats {
+      dropped: 3,
+      spilled: 0
+    }
+  );
+
+  // Once the queue is unbounded, nothing is dropped.
+  store.set_streaming_queue_bounds(None);
+  add_workunit(&store, "c");
+  add_workunit(&store, "d");
+  assert_eq!(
+    poll_names(&mut store).1,
+    vec!["c".to_owned(), "d".to_owned()]
+  );
+}
+
+#[test]
+fn streaming_queue_block() {
+  let mut store = WorkunitStore::new(false);
+  store.set_streaming_queue_bounds(Some(StreamingQueueBounds {
+    capacity: 1,
+    overflow_policy: OverflowPolicy::Block,
+  }));
+  let producer = {
+    let store = store.clone();
+    std::thread::spawn(move || add_workunit(&store, "a"))
+  };
+
+  // The producer blocks on the Completed message until the queue is drained.
+  let mut completed = vec![];
+  while completed.is_empty() {
+    completed = poll_names(&mut store).1;
+    std::thread::sleep(std::time::Duration::from_millis(10));
+  }
+  producer.join().unwrap();
+  assert_eq!(completed, vec!["a".to_owned()]);
+  assert_eq!(
+    store.streaming_queue_stats(),
+    StreamingQueueStats::default()
+  );
+}
+
+#[test]
+fn progress_updates() {
+  let mut store = WorkunitStore::new(false);
+  let span_id = SpanId::new();
+  store.start_workunit(
+    span_id,
+    "upload".to_owned(),
+    None,
+    WorkunitMetadata {
+      desc: Some("Uploading".to_owned()),
+      ..WorkunitMetadata::default()
+    },
+  );
+  assert_eq!(poll_names(&mut store), (vec!["upload".to_owned()], vec![]));
+
+  let progress = Progress {
+    done_files: 1,
+    total_files: 4,
+    done_bytes: 1024,
+    total_bytes: 4096,
+  };
+  store.update_progress(span_id, progress);
+  assert_eq!(
+    store.heavy_hitters(1).keys().cloned().collect::<Vec<_>>(),
+    vec!["Uploading (1/4 files, 1.0KiB/4.0KiB)".to_owned()]
+  );
+
+  // The running workunit is emitted again, with its progress.
+  let progresses = store.with_latest_workunits(Level::Trace, |started, completed| {
+    assert!(completed.is_empty());
+    started
+      .iter()
+      .map(|workunit| workunit.metadata.progress)
+      .collect::<Vec<_>>()
+  });
+  assert_eq!(progresses, vec![Some(progress)]);
+  assert_eq!(poll_names(&mut store), (vec![], vec![]));
+}
+
+#[test]
+fn logs_are_attached_to_running_workunits() {
+  let mut store = WorkunitStore::new(false);
+  let span_id = SpanId::new();
+  let workunit = store.start_workunit(
+    span_id,
+    "compile".to_owned(),
+    None,
+    WorkunitMetadata::default(),
+  );
+  assert!(store.record_log(span_id, Level::Warn, "careful".to_owned()));
+  for _ in 1..MAX_LOGS_PER_WORKUNIT {
+    assert!(store.record_log(span_id, Level::Debug, "chatty".to_owned()));
+  }
+  // Records beyond the limit are not attached.
+  assert!(!store.record_log(span_id, Level::Debug, "chatty".to_owned()));
+  // Nor are records for workunits which are not running.
+  assert!(!store.record_log(SpanId::new(), Level::Info, "orphan".to_owned()));
+
+  store.complete_workunit(workunit);
+  assert!(!store.record_log(span_id, Level::Info, "late".to_owned()));
+
+  let logs = store.with_latest_workunits(Level::Trace, |_, completed| {
+    assert_eq!(completed.len(), 1);
+    completed[0].logs.clone()
+  });
+  assert_eq!(logs.len(), MAX_LOGS_PER_WORKUNIT);
+  assert_eq!(
+    logs[0],
+    WorkunitLog {
+      level: Level::Warn,
+      message: "careful".to_owned(),
+    }
+  );
+}